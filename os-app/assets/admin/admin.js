@@ -0,0 +1,254 @@
+// OpenCraw admin dashboard. Polls /api/v1/os/dashboard and /readyz; approve/deny go
+// through the approvals API. The control API token, when the server requires one, is
+// kept in this browser's localStorage.
+"use strict";
+
+const TOKEN_KEY = "opencraw.api_token";
+const REFRESH_MS = 5000;
+
+const $ = (id) => document.getElementById(id);
+
+// Build an element; string children become text, never HTML.
+function el(tag, attrs, ...children) {
+  const node = document.createElement(tag);
+  for (const [key, value] of Object.entries(attrs || {})) {
+    if (key.startsWith("on")) node.addEventListener(key.slice(2), value);
+    else node.setAttribute(key, value);
+  }
+  for (const child of children.flat()) {
+    if (child === null || child === undefined || child === false) continue;
+    node.append(child instanceof Node ? child : String(child));
+  }
+  return node;
+}
+
+class Unauthorized extends Error {}
+
+async function api(path, options = {}) {
+  const headers = { "content-type": "application/json" };
+  const token = localStorage.getItem(TOKEN_KEY);
+  if (token) headers.authorization = `Bearer ${token}`;
+  const resp = await fetch(path, { ...options, headers });
+  if (resp.status === 401) throw new Unauthorized();
+  return resp.json();
+}
+
+function ago(iso) {
+  if (!iso) return "never";
+  const s = Math.max(0, Math.round((Date.now() - new Date(iso).getTime()) / 1000));
+  if (s < 60) return `${s}s ago`;
+  if (s < 3600) return `${Math.round(s / 60)}m ago`;
+  if (s < 86400) return `${Math.round(s / 3600)}h ago`;
+  return `${Math.round(s / 86400)}d ago`;
+}
+
+function tokens(usage) {
+  return (usage?.prompt_tokens || 0) + (usage?.completion_tokens || 0);
+}
+
+function renderApprovals(batches) {
+  const root = $("approvals");
+  if (!batches.length) {
+    root.replaceChildren(el("p", { class: "muted" }, "Nothing waiting."));
+    return;
+  }
+  root.replaceChildren(
+    ...batches.flatMap((batch) =>
+      batch.actions.map((action) => {
+        const origin = batch.origin
+          ? `${batch.origin.channel_id} · ${batch.origin.sender_id}`
+          : "background work";
+        const result = el("span", { class: "muted" });
+        const decide = (decision) => async (event) => {
+          const buttons = event.target.parentElement.querySelectorAll("button");
+          buttons.forEach((b) => (b.disabled = true));
+          try {
+            const body = await api(`/api/v1/os/approvals/${action.action_id}/decision`, {
+              method: "POST",
+              body: JSON.stringify({ decision }),
+            });
+            result.textContent = body.status === "ok" ? `${decision}d` : body.error || body.status;
+            if (body.status === "ok") setTimeout(refresh, 500);
+            else buttons.forEach((b) => (b.disabled = false));
+          } catch (e) {
+            handleError(e);
+          }
+        };
+        return el(
+          "div",
+          { class: "card" },
+          el("h3", {}, action.tool, " ", el("span", { class: "pill" }, action.risk_level)),
+          el("div", { class: "muted" }, `${origin} · ${ago(batch.created_at)}`,
+            batch.skill ? ` · skill ${batch.skill.name}` : ""),
+          el("pre", {}, JSON.stringify(action.arguments, null, 2)),
+          el(
+            "div",
+            {},
+            el("button", { class: "approve", onclick: decide("approve") }, "Approve"),
+            " ",
+            el("button", { class: "deny", onclick: decide("deny") }, "Deny"),
+            " ",
+            result,
+          ),
+        );
+      }),
+    ),
+  );
+}
+
+function renderSessions(sessions) {
+  const root = $("sessions");
+  if (!sessions.length) {
+    root.replaceChildren(el("p", { class: "muted" }, "No live sessions."));
+    return;
+  }
+  root.replaceChildren(
+    el(
+      "table",
+      {},
+      el("tr", {}, ["Channel", "Sender", "Persona", "Messages", "Tokens", "Last active", "Recent"]
+        .map((h) => el("th", {}, h))),
+      sessions.map((s) =>
+        el(
+          "tr",
+          {},
+          el("td", {}, s.channel_id),
+          el("td", {}, s.sender_id),
+          el("td", {}, s.persona || "default"),
+          el("td", {}, s.messages),
+          el("td", {}, tokens(s.usage).toLocaleString()),
+          el("td", {}, ago(s.last_active)),
+          el(
+            "td",
+            {},
+            s.recent.map((m) =>
+              el("div", { class: "message" }, el("span", { class: "role" }, m.role), m.content),
+            ),
+          ),
+        ),
+      ),
+    ),
+  );
+}
+
+function renderUsage(sessions) {
+  const byChannel = new Map();
+  for (const s of sessions) {
+    const sum = byChannel.get(s.channel_id) || { prompt: 0, completion: 0 };
+    sum.prompt += s.usage?.prompt_tokens || 0;
+    sum.completion += s.usage?.completion_tokens || 0;
+    byChannel.set(s.channel_id, sum);
+  }
+  const rows = [...byChannel.entries()].sort((a, b) =>
+    b[1].prompt + b[1].completion - (a[1].prompt + a[1].completion));
+  const max = Math.max(1, ...rows.map(([, u]) => u.prompt + u.completion));
+  $("usage").replaceChildren(
+    el("div", { class: "legend muted" }, "Live sessions:",
+      el("span", { class: "prompt" }, "prompt"), el("span", { class: "completion" }, "completion")),
+    rows.length ? "" : el("p", { class: "muted" }, "No usage yet."),
+    rows.map(([channel, u]) =>
+      el(
+        "div",
+        { class: "bar-row" },
+        el("span", {}, channel),
+        el(
+          "div",
+          { class: "bar" },
+          el("div", { class: "prompt", style: `width:${(100 * u.prompt) / max}%` }),
+          el("div", { class: "completion", style: `width:${(100 * u.completion) / max}%` }),
+        ),
+        el("span", { class: "muted" }, (u.prompt + u.completion).toLocaleString()),
+      ),
+    ),
+  );
+}
+
+function renderActivity(metrics) {
+  const llm = metrics.llm;
+  $("activity").replaceChildren(
+    el(
+      "dl",
+      {},
+      el("dt", {}, "LLM calls (1h)"),
+      el("dd", {}, `${llm.calls} calls, ${llm.errors} errors (${(llm.error_rate * 100).toFixed(1)}%)`),
+      el("dt", {}, "Queues"),
+      el("dd", {}, `${metrics.queues.inbound} inbound, ${metrics.queues.outbound} outbound`),
+      el("dt", {}, "Suggestions waiting"),
+      el("dd", {}, metrics.pending_approvals),
+      Object.entries(metrics.channels).flatMap(([channel, a]) => [
+        el("dt", {}, channel),
+        el("dd", {}, `in ${ago(a.last_inbound)}, out ${ago(a.last_outbound)}`),
+      ]),
+    ),
+  );
+}
+
+function renderConfig(config) {
+  const list = (items) => (items.length ? items.join(", ") : "none");
+  const a = config.approvals;
+  const rows = [
+    ["Version", config.version],
+    ["Model", config.model],
+    ["Channels", list(config.channels)],
+    ["Personas", list(config.personas)],
+    ["Tools", list(config.tools)],
+    ["Approvals", `shell ${a.shell}, browser ${a.browser}, file writes ${a.filesystem_write}, ${a.rules} rule(s)`],
+    ["Senders", config.allow_all_senders ? "anyone" : "allowed users only"],
+    ["Admin alerts", config.admin || "off"],
+    ["Webhooks", `${config.webhooks} endpoint(s)`],
+    ["API token", config.api_token ? "required" : "not set (API is open)"],
+    ["Data dir", config.data_dir],
+  ];
+  $("config").replaceChildren(...rows.flatMap(([k, v]) => [el("dt", {}, k), el("dd", {}, v)]));
+}
+
+async function renderHealth() {
+  const pill = $("health");
+  try {
+    const body = await (await fetch("/readyz")).json();
+    const ready = body.readiness?.ready;
+    pill.textContent = ready ? "ready" : "not ready";
+    pill.className = `pill ${ready ? "ok" : "bad"}`;
+  } catch {
+    pill.textContent = "unreachable";
+    pill.className = "pill bad";
+  }
+}
+
+function handleError(e) {
+  if (e instanceof Unauthorized) {
+    $("dashboard").hidden = true;
+    $("login").hidden = false;
+    $("login-error").textContent = localStorage.getItem(TOKEN_KEY) ? "That token was rejected." : "";
+    return;
+  }
+  $("updated").textContent = `refresh failed: ${e.message}`;
+}
+
+async function refresh() {
+  renderHealth();
+  try {
+    const data = await api("/api/v1/os/dashboard");
+    $("login").hidden = true;
+    $("dashboard").hidden = false;
+    renderApprovals(data.approvals);
+    renderSessions(data.sessions);
+    renderUsage(data.sessions);
+    renderActivity(data.metrics);
+    renderConfig(data.config);
+    $("updated").textContent = `updated ${new Date().toLocaleTimeString()}`;
+  } catch (e) {
+    handleError(e);
+  }
+}
+
+$("login").addEventListener("submit", (event) => {
+  event.preventDefault();
+  localStorage.setItem(TOKEN_KEY, $("token").value.trim());
+  refresh();
+});
+
+refresh();
+setInterval(() => {
+  if ($("login").hidden) refresh();
+}, REFRESH_MS);
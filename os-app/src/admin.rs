@@ -0,0 +1,86 @@
+//! Operational alerts for the admin conversation (`[admin] deliver_to`).
+//!
+//! Integrity problems, stopped adapters and approvals waiting in other conversations reach
+//! it through their own `notify_*` targets, which default to it. This covers what has no
+//! target of its own: the LLM cooling down after repeated failures, and runs hitting their
+//! budget. Each kind of alert goes out at most once per `REPEAT_AFTER`, so a flapping
+//! provider doesn't flood the conversation.
+
+use crate::config::AdminConfig;
+use crate::outbox::Outbox;
+use dashmap::DashMap;
+use os_channels::OutboundMessage;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const REPEAT_AFTER: Duration = Duration::from_secs(15 * 60);
+
+pub struct AdminAlerts {
+    target: Option<(String, String)>,
+    outbox: Arc<Outbox>,
+    last_sent: DashMap<String, Instant>,
+}
+
+impl AdminAlerts {
+    pub fn new(cfg: &AdminConfig, outbox: Arc<Outbox>) -> Self {
+        Self {
+            target: cfg
+                .delivery_target()
+                .map(|(channel, recipient)| (channel.to_string(), recipient.to_string())),
+            outbox,
+            last_sent: DashMap::new(),
+        }
+    }
+
+    /// Queue `text`, unless an alert with the same `key` went out recently. Returns
+    /// whether it was queued.
+    pub fn alert(&self, key: &str, text: String) -> bool {
+        let Some((channel_id, recipient)) = self.target.as_ref() else {
+            return false;
+        };
+        let now = Instant::now();
+        if self
+            .last_sent
+            .get(key)
+            .is_some_and(|at| now.duration_since(*at) < REPEAT_AFTER)
+        {
+            return false;
+        }
+        self.last_sent.insert(key.to_string(), now);
+
+        tracing::warn!(%key, %text, "admin alert");
+        self.outbox.enqueue(
+            channel_id,
+            recipient,
+            OutboundMessage {
+                content: format!("OpenCraw: {text}"),
+                reply_to_message_id: None,
+                attachments: vec![],
+            },
+        );
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn alerts_of_one_kind_are_rate_limited() {
+        let outbox = Arc::new(Outbox::new(HashMap::new()));
+        let admin = AdminAlerts::new(
+            &AdminConfig {
+                deliver_to: "ntfy:ops".to_string(),
+            },
+            outbox.clone(),
+        );
+        assert!(admin.alert("llm", "the LLM is failing".to_string()));
+        assert!(!admin.alert("llm", "the LLM is failing".to_string()));
+        assert!(admin.alert("budget", "a run hit its budget".to_string()));
+
+        let off = AdminAlerts::new(&AdminConfig::default(), outbox);
+        assert!(!off.alert("llm", "the LLM is failing".to_string()));
+    }
+}
@@ -0,0 +1,256 @@
+//! Tool calls waiting for a human.
+//!
+//! Each waiting call is listed here, in a batch of its own or, when one model turn has
+//! several, together: those are all proposed before any runs and announced in a single
+//! `approval.pending` webhook carrying a `batch_id` and the list of actions.
+//! `GET /api/v1/os/approvals` lists open batches. `POST /api/v1/os/approvals/{action_id}/decision`
+//! decides one action, and `POST /api/v1/os/approvals/batches/{batch_id}` a whole batch,
+//! all at once with `{"decision": "approve"}` or one by one with
+//! `{"decisions": {"<action_id>": "deny"}}`.
+//!
+//! Calls made while a `/skill` runs carry that skill's declared manifest, so whoever
+//! reviews them can compare what it asked for with what it is doing.
+//!
+//! With `[approvals] notify_channel` set, each new batch is also pushed there (ntfy,
+//! Pushover, or any chat channel), for approving from a phone.
+
+use crate::outbox::Outbox;
+use crate::skills::SkillSummary;
+use crate::tasks::{ConversationOrigin, CONVERSATION};
+use chrono::{DateTime, Duration, Utc};
+use horizons_core::core_agents::models::RiskLevel;
+use os_channels::OutboundMessage;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// Matches the proposals' TTL; batches left behind by a cancelled run go after this.
+const BATCH_MAX_AGE: Duration = Duration::hours(1);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingAction {
+    pub action_id: Uuid,
+    pub tool: String,
+    pub risk_level: RiskLevel,
+    pub arguments: serde_json::Value,
+    pub reviewers: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ApprovalBatch {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    /// The conversation the calls came from; absent for background work.
+    pub origin: Option<ConversationOrigin>,
+    pub actions: Vec<PendingAction>,
+    /// The skill whose run made the calls.
+    pub skill: Option<SkillSummary>,
+}
+
+impl ApprovalBatch {
+    pub fn new(actions: Vec<PendingAction>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            created_at: Utc::now(),
+            origin: CONVERSATION.try_with(|o| o.clone()).ok(),
+            actions,
+            skill: None,
+        }
+    }
+
+    pub fn with_skill(mut self, skill: Option<SkillSummary>) -> Self {
+        self.skill = skill;
+        self
+    }
+
+    fn notification(&self) -> String {
+        let mut out = match self.actions.len() {
+            1 => "Approval needed".to_string(),
+            n => format!("{n} approvals needed"),
+        };
+        if let Some(origin) = self.origin.as_ref() {
+            out.push_str(&format!(" ({}:{})", origin.channel_id, origin.sender_id));
+        }
+        out.push(':');
+        if let Some(skill) = self.skill.as_ref() {
+            out.push_str(&format!(
+                "\nSkill {} {} declared\n{}",
+                skill.name,
+                skill.version,
+                skill.requires.describe()
+            ));
+        }
+        for action in &self.actions {
+            let mut args = action.arguments.to_string();
+            if args.chars().count() > 200 {
+                args = args.chars().take(200).collect::<String>() + "…";
+            }
+            out.push_str(&format!(
+                "\n- {} [{:?}] {args}\n  id {}",
+                action.tool, action.risk_level, action.action_id
+            ));
+        }
+        if self.actions.len() > 1 {
+            out.push_str(&format!("\nBatch {}", self.id));
+        }
+        out
+    }
+}
+
+struct Notify {
+    outbox: Arc<Outbox>,
+    channel_id: String,
+    recipient: String,
+}
+
+#[derive(Default)]
+pub struct ApprovalBatches {
+    open: Mutex<HashMap<Uuid, ApprovalBatch>>,
+    notify: Option<Notify>,
+}
+
+impl ApprovalBatches {
+    /// Push each new batch to `recipient` on `channel_id`.
+    pub fn with_notify(mut self, outbox: Arc<Outbox>, channel_id: &str, recipient: &str) -> Self {
+        self.notify = Some(Notify {
+            outbox,
+            channel_id: channel_id.to_string(),
+            recipient: recipient.to_string(),
+        });
+        self
+    }
+
+    pub fn insert(&self, batch: ApprovalBatch) {
+        if let Some(notify) = self.notify.as_ref() {
+            notify.outbox.enqueue(
+                &notify.channel_id,
+                &notify.recipient,
+                OutboundMessage {
+                    content: batch.notification(),
+                    reply_to_message_id: None,
+                    attachments: vec![],
+                },
+            );
+        }
+        if let Ok(mut open) = self.open.lock() {
+            let cutoff = Utc::now() - BATCH_MAX_AGE;
+            open.retain(|_, b| b.created_at > cutoff);
+            open.insert(batch.id, batch);
+        }
+    }
+
+    /// Oldest first.
+    pub fn list(&self) -> Vec<ApprovalBatch> {
+        let cutoff = Utc::now() - BATCH_MAX_AGE;
+        let mut batches: Vec<ApprovalBatch> = self
+            .open
+            .lock()
+            .map(|open| {
+                open.values()
+                    .filter(|b| b.created_at > cutoff)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+        batches.sort_by_key(|b| b.created_at);
+        batches
+    }
+
+    pub fn get(&self, id: Uuid) -> Option<ApprovalBatch> {
+        self.open.lock().ok()?.get(&id).cloned()
+    }
+
+    /// Whether `action_id` is waiting in any open batch.
+    pub fn contains_action(&self, action_id: Uuid) -> bool {
+        self.open.lock().is_ok_and(|open| {
+            open.values()
+                .any(|b| b.actions.iter().any(|a| a.action_id == action_id))
+        })
+    }
+
+    /// The run has finished waiting on the batch.
+    pub fn close(&self, id: Uuid) {
+        if let Ok(mut open) = self.open.lock() {
+            open.remove(&id);
+        }
+    }
+
+    /// Close and return the conversation's open batches, for a run that was cancelled
+    /// while waiting on them.
+    pub fn close_for(&self, channel_id: &str, sender_id: &str) -> Vec<ApprovalBatch> {
+        let Ok(mut open) = self.open.lock() else {
+            return Vec::new();
+        };
+        let ids: Vec<Uuid> = open
+            .values()
+            .filter(|b| {
+                b.origin
+                    .as_ref()
+                    .is_some_and(|o| o.channel_id == channel_id && o.sender_id == sender_id)
+            })
+            .map(|b| b.id)
+            .collect();
+        ids.iter().filter_map(|id| open.remove(id)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batches_are_listed_until_closed_or_stale() {
+        let batches = ApprovalBatches::default();
+        let action = |tool: &str| PendingAction {
+            action_id: Uuid::new_v4(),
+            tool: tool.to_string(),
+            risk_level: RiskLevel::High,
+            arguments: serde_json::json!({}),
+            reviewers: vec![],
+        };
+        let batch = ApprovalBatch::new(vec![action("shell.execute"), action("browser")]);
+        let id = batch.id;
+        batches.insert(batch);
+
+        let mut stale = ApprovalBatch::new(vec![action("shell.execute")]);
+        stale.created_at -= Duration::hours(2);
+        batches.insert(stale);
+
+        let listed = batches.list();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].actions.len(), 2);
+        assert!(batches.get(id).is_some());
+        assert!(batches.contains_action(listed[0].actions[1].action_id));
+        assert!(listed[0]
+            .notification()
+            .starts_with("2 approvals needed:\n- shell.execute [High] {}"));
+
+        batches.close(id);
+        assert!(batches.list().is_empty());
+        assert!(batches.get(id).is_none());
+    }
+
+    #[test]
+    fn cancelled_conversations_close_their_batches() {
+        let batches = ApprovalBatches::default();
+        let origin = |sender: &str| ConversationOrigin {
+            channel_id: "telegram".to_string(),
+            sender_id: sender.to_string(),
+            recipient: sender.to_string(),
+        };
+        let mine = CONVERSATION.sync_scope(origin("42"), || ApprovalBatch::new(vec![]));
+        let theirs = CONVERSATION.sync_scope(origin("7"), || ApprovalBatch::new(vec![]));
+        let (mine_id, theirs_id) = (mine.id, theirs.id);
+        batches.insert(mine);
+        batches.insert(theirs);
+        batches.insert(ApprovalBatch::new(vec![]));
+
+        let closed = batches.close_for("telegram", "42");
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].id, mine_id);
+        assert!(batches.get(mine_id).is_none());
+        assert!(batches.get(theirs_id).is_some());
+        assert_eq!(batches.list().len(), 2);
+    }
+}
@@ -0,0 +1,249 @@
+//! Which approval a tool call needs.
+//!
+//! `[[security.rules]]` are checked in order and the first one that matches decides. After
+//! them come the built-in rules, which express `shell_approval`, `browser_approval`,
+//! `filesystem_write_approval` and the risk-level fallback, so a config without rules
+//! behaves as before.
+
+use crate::config::{expand_home, ApprovalMode, ApprovalRule, OpenShellConfig, RuleRisk};
+use horizons_core::core_agents::models::RiskLevel;
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::{Component, Path};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Approval {
+    pub mode: ApprovalMode,
+    /// Extra people to ask, from the matching rule; sent with human approval requests.
+    pub reviewers: Vec<String>,
+}
+
+pub fn decide(
+    cfg: &OpenShellConfig,
+    tool: &str,
+    risk: RiskLevel,
+    arguments: &serde_json::Value,
+) -> Approval {
+    let builtin = builtin_rules(cfg);
+    let rule = cfg
+        .security
+        .rules
+        .iter()
+        .chain(builtin.iter())
+        .find(|rule| matches(rule, tool, risk, arguments))
+        .expect("the built-in rules end with a catch-all");
+    Approval {
+        mode: rule.approval,
+        reviewers: rule.reviewers.clone(),
+    }
+}
+
+/// The behavior from before rules existed, as rules.
+fn builtin_rules(cfg: &OpenShellConfig) -> Vec<ApprovalRule> {
+    let rule =
+        |tool: Option<&str>, action: Option<&str>, risk: &[RuleRisk], approval| ApprovalRule {
+            tool: tool.map(str::to_string),
+            action: action.map(str::to_string),
+            risk: risk.to_vec(),
+            args: HashMap::new(),
+            path_prefix: None,
+            approval,
+            reviewers: Vec::new(),
+        };
+    let security = &cfg.security;
+    vec![
+        rule(Some("shell.execute"), None, &[], security.shell_approval),
+        rule(Some("browser"), None, &[], security.browser_approval),
+        rule(
+            Some("filesystem"),
+            Some("write_file"),
+            &[],
+            security.filesystem_write_approval,
+        ),
+        rule(Some("filesystem"), None, &[], ApprovalMode::Auto),
+        rule(None, None, &[RuleRisk::Low], ApprovalMode::Auto),
+        rule(None, None, &[RuleRisk::Medium], ApprovalMode::Ai),
+        rule(None, None, &[], ApprovalMode::Human),
+    ]
+}
+
+fn matches(
+    rule: &ApprovalRule,
+    tool: &str,
+    risk: RiskLevel,
+    arguments: &serde_json::Value,
+) -> bool {
+    if rule.tool.as_deref().is_some_and(|t| t != tool) {
+        return false;
+    }
+    if let Some(action) = rule.action.as_deref() {
+        if arguments.get("action").and_then(|v| v.as_str()) != Some(action) {
+            return false;
+        }
+    }
+    if !rule.risk.is_empty() && !rule.risk.iter().any(|r| risk_level(*r) == risk) {
+        return false;
+    }
+    for (name, pattern) in &rule.args {
+        let Some(value) = argument_text(arguments, name) else {
+            return false;
+        };
+        // Patterns were checked when the config loaded.
+        if !Regex::new(pattern).is_ok_and(|re| re.is_match(&value)) {
+            return false;
+        }
+    }
+    if let Some(prefix) = rule.path_prefix.as_deref() {
+        let path = arguments.get("path").and_then(|v| v.as_str());
+        if !path.is_some_and(|p| under_prefix(p, prefix)) {
+            return false;
+        }
+    }
+    true
+}
+
+fn risk_level(risk: RuleRisk) -> RiskLevel {
+    match risk {
+        RuleRisk::Low => RiskLevel::Low,
+        RuleRisk::Medium => RiskLevel::Medium,
+        RuleRisk::High => RiskLevel::High,
+        RuleRisk::Critical => RiskLevel::Critical,
+    }
+}
+
+fn argument_text(arguments: &serde_json::Value, name: &str) -> Option<String> {
+    match arguments.get(name)? {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Array(items) => Some(
+            items
+                .iter()
+                .map(|v| {
+                    v.as_str()
+                        .map(str::to_string)
+                        .unwrap_or_else(|| v.to_string())
+                })
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        serde_json::Value::Null => None,
+        other => Some(other.to_string()),
+    }
+}
+
+/// Compared by path components. A path with `..` never matches, since it could climb out
+/// of the prefix.
+fn under_prefix(path: &str, prefix: &str) -> bool {
+    let (Ok(path), Ok(prefix)) = (expand_home(path), expand_home(prefix)) else {
+        return false;
+    };
+    let absolute = |p: &Path| match p.is_absolute() {
+        true => Some(p.to_path_buf()),
+        false => std::env::current_dir().ok().map(|cwd| cwd.join(p)),
+    };
+    let (Some(path), Some(prefix)) = (absolute(&path), absolute(&prefix)) else {
+        return false;
+    };
+    !path.components().any(|c| c == Component::ParentDir) && path.starts_with(prefix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[test]
+    fn first_matching_rule_wins_and_builtins_keep_old_behavior() {
+        let mut cfg: OpenShellConfig =
+            toml::from_str(include_str!("../../config.example.toml")).unwrap();
+        let mode = |cfg: &OpenShellConfig, tool, risk, args: serde_json::Value| {
+            decide(cfg, tool, risk, &args).mode
+        };
+        let security = cfg.security.clone();
+
+        assert_eq!(
+            mode(&cfg, "shell.execute", RiskLevel::High, json!({})),
+            security.shell_approval
+        );
+        assert_eq!(
+            mode(
+                &cfg,
+                "filesystem",
+                RiskLevel::Low,
+                json!({ "action": "read_file" })
+            ),
+            ApprovalMode::Auto
+        );
+        assert_eq!(
+            mode(&cfg, "todoist", RiskLevel::Medium, json!({})),
+            ApprovalMode::Ai
+        );
+        assert_eq!(
+            mode(&cfg, "anything", RiskLevel::Critical, json!({})),
+            ApprovalMode::Human
+        );
+
+        #[derive(Deserialize)]
+        struct Rules {
+            rules: Vec<ApprovalRule>,
+        }
+        cfg.security.rules = toml::from_str::<Rules>(
+            r#"
+            [[rules]]
+            tool = "shell.execute"
+            args = { command = '^(ls|pwd|git status)$' }
+            approval = "auto"
+
+            [[rules]]
+            tool = "filesystem"
+            action = "write_file"
+            path_prefix = "/tmp/scratch"
+            approval = "auto"
+
+            [[rules]]
+            risk = ["critical"]
+            approval = "human"
+            reviewers = ["telegram:12345"]
+            "#,
+        )
+        .unwrap()
+        .rules;
+
+        let shell = |command: &str| json!({ "command": command });
+        assert_eq!(
+            mode(&cfg, "shell.execute", RiskLevel::High, shell("git status")),
+            ApprovalMode::Auto
+        );
+        for command in ["rm -rf ~", "git status; rm -rf ~"] {
+            assert_eq!(
+                mode(&cfg, "shell.execute", RiskLevel::High, shell(command)),
+                security.shell_approval,
+                "{command}"
+            );
+        }
+        let write = |path: &str| json!({ "action": "write_file", "path": path });
+        assert_eq!(
+            mode(
+                &cfg,
+                "filesystem",
+                RiskLevel::Medium,
+                write("/tmp/scratch/a.txt")
+            ),
+            ApprovalMode::Auto
+        );
+        for path in ["/tmp/scratch/../../etc/hosts", "/tmp/scratchpad"] {
+            assert_eq!(
+                mode(&cfg, "filesystem", RiskLevel::Medium, write(path)),
+                security.filesystem_write_approval,
+                "{path}"
+            );
+        }
+        assert_eq!(
+            decide(&cfg, "browser", RiskLevel::Critical, &json!({})),
+            Approval {
+                mode: ApprovalMode::Human,
+                reviewers: vec!["telegram:12345".to_string()],
+            }
+        );
+    }
+}
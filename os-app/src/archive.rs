@@ -0,0 +1,872 @@
+//! Conversation archive with semantic search.
+//!
+//! Every user/assistant turn is appended to `data/conversations.db`, or to Postgres with
+//! `[archive] database_url` set, which replicas sharing a database need. When an embedder
+//! is configured, each message is embedded in the background and searches rank by cosine
+//! similarity; otherwise (or when the embedding call fails) search falls back to keyword
+//! matching. With `[encryption]` on, message text is sealed before it's stored and
+//! keyword search scans decrypted recent messages instead of querying the column.
+//!
+//! The `conversation_search` tool only searches the asking sender's own conversations
+//! (and those of senders linked to the same person in `identities.people`); owners listed
+//! in `inbound.priority.owners` search everyone's.
+
+use crate::config::{IdentitiesConfig, OpenShellConfig};
+use crate::encryption::{open_value, DataCipher};
+use crate::identities;
+use crate::sqlite::SqlitePool;
+use crate::tasks::CONVERSATION;
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use horizons_core::core_agents::models::RiskLevel;
+use os_tools::{until_cancelled, CancellationToken, Tool, ToolError, ToolSpec};
+use rusqlite::{params, OptionalExtension};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use sqlx::Row;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Most recent embedded messages considered per semantic search.
+const SEMANTIC_SCAN_MAX: usize = 20_000;
+/// Most recent matches per term in a plaintext keyword search.
+const KEYWORD_MATCHES_MAX: usize = 500;
+const SNIPPET_CHARS: usize = 280;
+
+const POSTGRES_SCHEMA: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS conversation_messages (
+        id BIGSERIAL PRIMARY KEY,
+        session_id TEXT NOT NULL,
+        channel_id TEXT NOT NULL,
+        sender_id TEXT NOT NULL,
+        role TEXT NOT NULL,
+        content TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        embedding BYTEA
+    )",
+    "CREATE INDEX IF NOT EXISTS conversation_messages_session
+        ON conversation_messages(session_id)",
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub session_id: String,
+    pub channel_id: String,
+    pub sender_id: String,
+    pub role: String,
+    pub snippet: String,
+    pub created_at: DateTime<Utc>,
+    pub score: f32,
+}
+
+/// Whose archived messages a search covers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchScope {
+    Everyone,
+    /// These `channel:sender` ids.
+    Senders(Vec<String>),
+}
+
+impl SearchScope {
+    /// What `channel_id:sender_id` may search: everything for an owner, otherwise their
+    /// own conversations and those of the senders linked to them.
+    pub fn for_sender(
+        owners: &[String],
+        people: &IdentitiesConfig,
+        channel_id: &str,
+        sender_id: &str,
+    ) -> Self {
+        let me = format!("{channel_id}:{sender_id}");
+        if owners.contains(&me) {
+            return Self::Everyone;
+        }
+        let mut senders = vec![me];
+        if let Some((_, others)) = identities::linked_senders(people, channel_id, sender_id) {
+            senders.extend(others.into_iter().map(|(c, s)| format!("{c}:{s}")));
+        }
+        Self::Senders(senders)
+    }
+
+    /// The scope of the conversation the current run belongs to; `None` outside one.
+    pub fn current(owners: &[String], people: &IdentitiesConfig) -> Option<Self> {
+        CONVERSATION
+            .try_with(|o| Self::for_sender(owners, people, &o.channel_id, &o.sender_id))
+            .ok()
+    }
+
+    /// A condition on `conversation_messages` for this scope, with its placeholders
+    /// numbered from `first`, and the values to bind to them.
+    fn condition(&self, first: usize) -> Option<(String, Vec<String>)> {
+        match self {
+            Self::Everyone => None,
+            Self::Senders(senders) if senders.is_empty() => Some(("1 = 0".to_string(), vec![])),
+            Self::Senders(senders) => {
+                let placeholders: Vec<String> = (first..first + senders.len())
+                    .map(|n| format!("${n}"))
+                    .collect();
+                Some((
+                    format!(
+                        "(channel_id || ':' || sender_id) IN ({})",
+                        placeholders.join(", ")
+                    ),
+                    senders.clone(),
+                ))
+            }
+        }
+    }
+}
+
+/// Metadata for one archived message, without its content.
+#[derive(Debug, Clone, Serialize)]
+pub struct TurnSummary {
+    pub session_id: String,
+    pub channel_id: String,
+    pub role: String,
+    pub chars: usize,
+    pub created_at: String,
+}
+
+/// One archived message as stored; `content` may be sealed.
+struct StoredMessage {
+    id: i64,
+    session_id: String,
+    channel_id: String,
+    sender_id: String,
+    role: String,
+    content: String,
+    created_at: String,
+    embedding: Option<Vec<u8>>,
+}
+
+const COLUMNS: &str = "id, session_id, channel_id, sender_id, role, content, created_at, embedding";
+
+impl StoredMessage {
+    fn from_sqlite(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            session_id: row.get(1)?,
+            channel_id: row.get(2)?,
+            sender_id: row.get(3)?,
+            role: row.get(4)?,
+            content: row.get(5)?,
+            created_at: row.get(6)?,
+            embedding: row.get(7)?,
+        })
+    }
+
+    fn from_postgres(row: &sqlx::postgres::PgRow) -> sqlx::Result<Self> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            session_id: row.try_get("session_id")?,
+            channel_id: row.try_get("channel_id")?,
+            sender_id: row.try_get("sender_id")?,
+            role: row.try_get("role")?,
+            content: row.try_get("content")?,
+            created_at: row.try_get("created_at")?,
+            embedding: row.try_get("embedding")?,
+        })
+    }
+}
+
+/// Statements are written with Postgres `$n` placeholders; SQLite gets them as `?n`.
+enum Store {
+    Sqlite(Arc<SqlitePool>),
+    Postgres(PgPool),
+}
+
+impl Store {
+    async fn insert(
+        &self,
+        session_id: &str,
+        channel_id: &str,
+        sender_id: &str,
+        role: &str,
+        content: &str,
+        created_at: &str,
+    ) -> Result<i64> {
+        let sql = "INSERT INTO conversation_messages
+                   (session_id, channel_id, sender_id, role, content, created_at)
+                   VALUES ($1, $2, $3, $4, $5, $6)";
+        match self {
+            Self::Sqlite(pool) => {
+                let conn = pool.write()?;
+                conn.execute(
+                    &sql.replace('$', "?"),
+                    params![session_id, channel_id, sender_id, role, content, created_at],
+                )?;
+                Ok(conn.last_insert_rowid())
+            }
+            Self::Postgres(pool) => Ok(sqlx::query(&format!("{sql} RETURNING id"))
+                .bind(session_id)
+                .bind(channel_id)
+                .bind(sender_id)
+                .bind(role)
+                .bind(content)
+                .bind(created_at)
+                .fetch_one(pool)
+                .await?
+                .try_get("id")?),
+        }
+    }
+
+    async fn set_embedding(&self, id: i64, embedding: Vec<u8>) -> Result<()> {
+        let sql = "UPDATE conversation_messages SET embedding = $1 WHERE id = $2";
+        match self {
+            Self::Sqlite(pool) => {
+                pool.write()?
+                    .execute(&sql.replace('$', "?"), params![embedding, id])?;
+            }
+            Self::Postgres(pool) => {
+                sqlx::query(sql)
+                    .bind(embedding)
+                    .bind(id)
+                    .execute(pool)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn newest_sealed(&self) -> Result<Option<String>> {
+        let sql = "SELECT content FROM conversation_messages
+                    WHERE content LIKE 'enc:v1:%'
+                    ORDER BY id DESC
+                    LIMIT 1";
+        match self {
+            Self::Sqlite(pool) => Ok(pool
+                .read()?
+                .query_row(sql, [], |row| row.get(0))
+                .optional()?),
+            Self::Postgres(pool) => Ok(sqlx::query_scalar::<_, String>(sql)
+                .fetch_optional(pool)
+                .await?),
+        }
+    }
+
+    /// Newest first.
+    async fn newest(
+        &self,
+        limit: usize,
+        embedded_only: bool,
+        scope: &SearchScope,
+    ) -> Result<Vec<StoredMessage>> {
+        let mut conditions = Vec::new();
+        if embedded_only {
+            conditions.push("embedding IS NOT NULL".to_string());
+        }
+        let mut binds = Vec::new();
+        if let Some((condition, senders)) = scope.condition(2) {
+            conditions.push(condition);
+            binds = senders;
+        }
+        let filter = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+        let sql = format!(
+            "SELECT {COLUMNS} FROM conversation_messages {filter} ORDER BY id DESC LIMIT $1"
+        );
+        self.fetch(&sql, limit, binds).await
+    }
+
+    /// Newest first; `term` is matched case-insensitively against plaintext content.
+    async fn containing(
+        &self,
+        term: &str,
+        limit: usize,
+        scope: &SearchScope,
+    ) -> Result<Vec<StoredMessage>> {
+        let position = match self {
+            Self::Sqlite(_) => "instr(lower(content), $2)",
+            Self::Postgres(_) => "strpos(lower(content), $2)",
+        };
+        let mut binds = vec![term.to_string()];
+        let mut filter = String::new();
+        if let Some((condition, senders)) = scope.condition(3) {
+            filter = format!("AND {condition}");
+            binds.extend(senders);
+        }
+        let sql = format!(
+            "SELECT {COLUMNS} FROM conversation_messages
+              WHERE {position} > 0 {filter} ORDER BY id DESC LIMIT $1"
+        );
+        self.fetch(&sql, limit, binds).await
+    }
+
+    /// Runs `sql` with `limit` as `$1` and `binds` as `$2` onwards.
+    async fn fetch(
+        &self,
+        sql: &str,
+        limit: usize,
+        binds: Vec<String>,
+    ) -> Result<Vec<StoredMessage>> {
+        match self {
+            Self::Sqlite(pool) => {
+                let conn = pool.read()?;
+                let mut stmt = conn.prepare(&sql.replace('$', "?"))?;
+                let values = std::iter::once(rusqlite::types::Value::Integer(limit as i64))
+                    .chain(binds.into_iter().map(rusqlite::types::Value::Text));
+                let rows = stmt
+                    .query_map(
+                        rusqlite::params_from_iter(values),
+                        StoredMessage::from_sqlite,
+                    )?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                Ok(rows)
+            }
+            Self::Postgres(pool) => {
+                let mut query = sqlx::query(sql).bind(limit as i64);
+                for bind in binds {
+                    query = query.bind(bind);
+                }
+                let rows = query.fetch_all(pool).await?;
+                Ok(rows
+                    .iter()
+                    .map(StoredMessage::from_postgres)
+                    .collect::<sqlx::Result<Vec<_>>>()?)
+            }
+        }
+    }
+}
+
+pub struct ConversationArchive {
+    store: Store,
+    embedder: Option<os_llm::LlmClient>,
+    cipher: Option<Arc<DataCipher>>,
+}
+
+impl ConversationArchive {
+    /// The archive `[archive]` points at: Postgres with `database_url`, otherwise
+    /// `conversations.db` in `data_dir`. Sealed messages are checked against `cipher`.
+    pub async fn from_config(
+        cfg: &OpenShellConfig,
+        data_dir: &Path,
+        embedder: Option<os_llm::LlmClient>,
+        cipher: Option<Arc<DataCipher>>,
+    ) -> Result<Self> {
+        let archive = match cfg.archive.database_url.as_deref() {
+            Some(url) => Self::connect(url, embedder).await?,
+            None => Self::open(&data_dir.join("conversations.db"), embedder)?,
+        };
+        archive.with_cipher(cipher).await
+    }
+
+    pub fn open(path: &Path, embedder: Option<os_llm::LlmClient>) -> Result<Self> {
+        let pool = SqlitePool::open(
+            path,
+            "CREATE TABLE IF NOT EXISTS conversation_messages (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                session_id TEXT NOT NULL,
+                channel_id TEXT NOT NULL,
+                sender_id TEXT NOT NULL,
+                role TEXT NOT NULL,
+                content TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                embedding BLOB
+            );
+            CREATE INDEX IF NOT EXISTS conversation_messages_session
+                ON conversation_messages(session_id);",
+        )?;
+        Ok(Self {
+            store: Store::Sqlite(Arc::new(pool)),
+            embedder,
+            cipher: None,
+        })
+    }
+
+    /// Archive to Postgres at `url`, creating the table if needed.
+    pub async fn connect(url: &str, embedder: Option<os_llm::LlmClient>) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .connect(url)
+            .await
+            .map_err(|e| anyhow::anyhow!("connect to archive.database_url: {e}"))?;
+        for statement in POSTGRES_SCHEMA {
+            sqlx::query(statement).execute(&pool).await?;
+        }
+        Ok(Self {
+            store: Store::Postgres(pool),
+            embedder,
+            cipher: None,
+        })
+    }
+
+    /// The SQLite connections, when the archive is not in Postgres.
+    pub fn sqlite_pool(&self) -> Option<Arc<SqlitePool>> {
+        match &self.store {
+            Store::Sqlite(pool) => Some(pool.clone()),
+            Store::Postgres(_) => None,
+        }
+    }
+
+    /// Seal new messages with `cipher` (or store them as plaintext with `None`), after
+    /// checking that the newest sealed message opens with it, so a wrong or missing key
+    /// fails at startup rather than on the first search.
+    pub async fn with_cipher(mut self, cipher: Option<Arc<DataCipher>>) -> Result<Self> {
+        if let Some(sealed) = self.store.newest_sealed().await? {
+            open_value(cipher.as_deref(), sealed)
+                .map_err(|e| anyhow::anyhow!("conversation archive: {e}"))?;
+        }
+        self.cipher = cipher;
+        Ok(self)
+    }
+
+    /// Append one turn (user message + assistant reply). Embeddings are computed off the
+    /// request path.
+    pub async fn record_turn(
+        self: &Arc<Self>,
+        session_id: Uuid,
+        channel_id: &str,
+        sender_id: &str,
+        user_message: &str,
+        assistant_message: &str,
+    ) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        let session_id = session_id.to_string();
+        let mut ids = Vec::with_capacity(2);
+        for (role, content) in [("user", user_message), ("assistant", assistant_message)] {
+            if content.trim().is_empty() {
+                continue;
+            }
+            let stored = match self.cipher.as_deref() {
+                Some(cipher) => cipher.seal(content)?,
+                None => content.to_string(),
+            };
+            let id = self
+                .store
+                .insert(&session_id, channel_id, sender_id, role, &stored, &now)
+                .await?;
+            ids.push((id, content.to_string()));
+        }
+
+        if self.embedder.is_some() && !ids.is_empty() {
+            let this = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = this.embed_rows(ids).await {
+                    tracing::warn!(%e, "conversation archive embedding failed");
+                }
+            });
+        }
+        Ok(())
+    }
+
+    async fn embed_rows(&self, rows: Vec<(i64, String)>) -> Result<()> {
+        let Some(embedder) = self.embedder.as_ref() else {
+            return Ok(());
+        };
+        let inputs: Vec<String> = rows.iter().map(|(_, c)| c.clone()).collect();
+        let vectors = embedder.embed(&inputs).await?;
+        for ((id, _), v) in rows.iter().zip(vectors) {
+            self.store.set_embedding(*id, encode_vector(&v)).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn recent_turns(&self, limit: usize) -> Result<Vec<TurnSummary>> {
+        self.store
+            .newest(limit, false, &SearchScope::Everyone)
+            .await?
+            .into_iter()
+            .map(|m| {
+                Ok(TurnSummary {
+                    chars: self.content(&m)?.chars().count(),
+                    session_id: m.session_id,
+                    channel_id: m.channel_id,
+                    role: m.role,
+                    created_at: m.created_at,
+                })
+            })
+            .collect()
+    }
+
+    #[tracing::instrument(level = "info", skip_all)]
+    pub async fn search(
+        &self,
+        query: &str,
+        limit: usize,
+        scope: &SearchScope,
+    ) -> Result<Vec<SearchHit>> {
+        let query = query.trim();
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+        if let Some(embedder) = self.embedder.as_ref() {
+            match embedder.embed(&[query.to_string()]).await {
+                Ok(mut v) if !v.is_empty() => {
+                    let hits = self.semantic_search(&v.remove(0), limit, scope).await?;
+                    if !hits.is_empty() {
+                        return Ok(hits);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!(%e, "query embedding failed; using keyword search"),
+            }
+        }
+        self.keyword_search(query, limit, scope).await
+    }
+
+    async fn semantic_search(
+        &self,
+        query: &[f32],
+        limit: usize,
+        scope: &SearchScope,
+    ) -> Result<Vec<SearchHit>> {
+        let mut hits = Vec::new();
+        for m in self.store.newest(SEMANTIC_SCAN_MAX, true, scope).await? {
+            let score = cosine(
+                query,
+                &decode_vector(m.embedding.as_deref().unwrap_or_default()),
+            );
+            hits.push(to_hit(&m, &self.content(&m)?, score));
+        }
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits.truncate(limit);
+        Ok(hits)
+    }
+
+    async fn keyword_search(
+        &self,
+        query: &str,
+        limit: usize,
+        scope: &SearchScope,
+    ) -> Result<Vec<SearchHit>> {
+        let terms: Vec<String> = query
+            .split_whitespace()
+            .filter(|t| t.len() > 2)
+            .map(|t| t.to_lowercase())
+            .collect();
+        let terms = if terms.is_empty() {
+            vec![query.to_lowercase()]
+        } else {
+            terms
+        };
+
+        let mut by_id: HashMap<i64, SearchHit> = HashMap::new();
+        if self.cipher.is_some() {
+            for m in self.store.newest(SEMANTIC_SCAN_MAX, false, scope).await? {
+                let content = self.content(&m)?;
+                let lower = content.to_lowercase();
+                let matched = terms.iter().filter(|t| lower.contains(t.as_str())).count();
+                if matched > 0 {
+                    let score = matched as f32 / terms.len() as f32;
+                    by_id.insert(m.id, to_hit(&m, &content, score));
+                }
+            }
+        } else {
+            for term in &terms {
+                for m in self
+                    .store
+                    .containing(term, KEYWORD_MATCHES_MAX, scope)
+                    .await?
+                {
+                    by_id
+                        .entry(m.id)
+                        .or_insert_with(|| to_hit(&m, &m.content, 0.0))
+                        .score += 1.0 / terms.len() as f32;
+                }
+            }
+        }
+        let mut hits: Vec<SearchHit> = by_id.into_values().collect();
+        hits.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then(b.created_at.cmp(&a.created_at))
+        });
+        hits.truncate(limit);
+        Ok(hits)
+    }
+
+    /// Message text, decrypted when sealed.
+    fn content(&self, message: &StoredMessage) -> Result<String> {
+        open_value(self.cipher.as_deref(), message.content.clone())
+    }
+}
+
+fn to_hit(message: &StoredMessage, content: &str, score: f32) -> SearchHit {
+    SearchHit {
+        session_id: message.session_id.clone(),
+        channel_id: message.channel_id.clone(),
+        sender_id: message.sender_id.clone(),
+        role: message.role.clone(),
+        snippet: snippet(content),
+        created_at: DateTime::parse_from_rfc3339(&message.created_at)
+            .map(|t| t.with_timezone(&Utc))
+            .unwrap_or_else(|_| Utc::now()),
+        score,
+    }
+}
+
+fn snippet(content: &str) -> String {
+    if content.chars().count() <= SNIPPET_CHARS {
+        return content.to_string();
+    }
+    let mut out: String = content.chars().take(SNIPPET_CHARS).collect();
+    out.push('…');
+    out
+}
+
+fn encode_vector(v: &[f32]) -> Vec<u8> {
+    v.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+fn decode_vector(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+        .collect()
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let na = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let nb = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if na == 0.0 || nb == 0.0 {
+        return 0.0;
+    }
+    dot / (na * nb)
+}
+
+/// Lets the assistant look up earlier conversations.
+pub struct ConversationSearchTool {
+    archive: Arc<ConversationArchive>,
+    owners: Vec<String>,
+    people: IdentitiesConfig,
+}
+
+impl ConversationSearchTool {
+    pub fn new(archive: Arc<ConversationArchive>) -> Self {
+        Self {
+            archive,
+            owners: Vec::new(),
+            people: IdentitiesConfig::default(),
+        }
+    }
+
+    /// `owners` (`channel:sender`) search everyone's conversations; everyone else their
+    /// own and those linked to them in `people`.
+    pub fn with_access(mut self, owners: Vec<String>, people: IdentitiesConfig) -> Self {
+        self.owners = owners;
+        self.people = people;
+        self
+    }
+
+    /// The current conversation's scope; searches outside a conversation are refused.
+    fn scope(&self) -> os_tools::Result<SearchScope> {
+        SearchScope::current(&self.owners, &self.people).ok_or_else(|| {
+            ToolError::Unauthorized(
+                "conversation search only runs inside a conversation".to_string(),
+            )
+        })
+    }
+}
+
+#[async_trait]
+impl Tool for ConversationSearchTool {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "conversation_search".to_string(),
+            description: "Search your past conversations with this person across all channels. Returns message snippets with channel, sender and timestamp.".to_string(),
+            parameters_schema: json!({
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string", "description": "What to look for, in natural language." },
+                    "limit": { "type": "integer", "minimum": 1, "maximum": 50 }
+                },
+                "required": ["query"]
+            }),
+            risk_level: RiskLevel::Low,
+        }
+    }
+
+    async fn execute(
+        &self,
+        arguments: serde_json::Value,
+        cancel: &CancellationToken,
+    ) -> os_tools::Result<serde_json::Value> {
+        let query = arguments
+            .get("query")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| ToolError::InvalidArguments("missing key: query".to_string()))?;
+        let limit = arguments
+            .get("limit")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(10)
+            .clamp(1, 50) as usize;
+        let scope = self.scope()?;
+        // Semantic search waits on the embeddings API.
+        let hits = until_cancelled(cancel, async {
+            self.archive
+                .search(query, limit, &scope)
+                .await
+                .map_err(|e| ToolError::ExecutionFailed(e.to_string()))
+        })
+        .await?;
+        Ok(json!({ "results": hits }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+
+    #[tokio::test]
+    async fn keyword_search_finds_archived_turns() {
+        let path = std::env::temp_dir().join(format!("opencraw-archive-{}.db", Uuid::new_v4()));
+        let archive = Arc::new(ConversationArchive::open(&path, None).unwrap());
+        archive
+            .record_turn(
+                Uuid::new_v4(),
+                "telegram",
+                "42",
+                "what should we do about the billing migration?",
+                "Let's move billing to the new ledger next sprint.",
+            )
+            .await
+            .unwrap();
+        archive
+            .record_turn(Uuid::new_v4(), "webchat", "me", "hello", "hi there")
+            .await
+            .unwrap();
+
+        let hits = archive
+            .search("billing migration", 5, &SearchScope::Everyone)
+            .await
+            .unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].role, "user");
+        assert_eq!(hits[0].channel_id, "telegram");
+        assert!(archive
+            .search("nothing-matches", 5, &SearchScope::Everyone)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn sealed_messages_are_searchable_and_need_the_key() {
+        let path = std::env::temp_dir().join(format!("opencraw-archive-{}.db", Uuid::new_v4()));
+        let cipher =
+            Arc::new(DataCipher::from_key("AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=").unwrap());
+        let archive = Arc::new(
+            ConversationArchive::open(&path, None)
+                .unwrap()
+                .with_cipher(Some(cipher.clone()))
+                .await
+                .unwrap(),
+        );
+        archive
+            .record_turn(
+                Uuid::new_v4(),
+                "telegram",
+                "42",
+                "Passport renewal?",
+                "Due in May.",
+            )
+            .await
+            .unwrap();
+
+        let raw: String = Connection::open(&path)
+            .unwrap()
+            .query_row(
+                "SELECT content FROM conversation_messages LIMIT 1",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert!(DataCipher::is_sealed(&raw));
+        let scope = SearchScope::Senders(vec!["telegram:42".to_string()]);
+        let hits = archive.search("passport", 5, &scope).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].snippet, "Passport renewal?");
+        assert_eq!(archive.recent_turns(5).await.unwrap()[0].chars, 11);
+
+        assert!(ConversationArchive::open(&path, None)
+            .unwrap()
+            .with_cipher(None)
+            .await
+            .is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn senders_only_search_their_own_conversations() {
+        let path = std::env::temp_dir().join(format!("opencraw-archive-{}.db", Uuid::new_v4()));
+        let archive = Arc::new(ConversationArchive::open(&path, None).unwrap());
+        for (channel, sender) in [
+            ("telegram", "alice"),
+            ("imessage", "+1555"),
+            ("telegram", "bob"),
+        ] {
+            archive
+                .record_turn(
+                    Uuid::new_v4(),
+                    channel,
+                    sender,
+                    &format!("my salary is secret, {sender}"),
+                    "Noted.",
+                )
+                .await
+                .unwrap();
+        }
+        let people = IdentitiesConfig {
+            people: HashMap::from([(
+                "alice".to_string(),
+                vec!["telegram:alice".to_string(), "imessage:+1555".to_string()],
+            )]),
+            ..IdentitiesConfig::default()
+        };
+        let tool = ConversationSearchTool::new(archive)
+            .with_access(vec!["webchat:owner".to_string()], people);
+        let search = |sender: &'static str| {
+            let (channel, sender) = sender.split_once(':').unwrap();
+            let origin = crate::tasks::ConversationOrigin {
+                channel_id: channel.to_string(),
+                sender_id: sender.to_string(),
+                recipient: sender.to_string(),
+            };
+            CONVERSATION.scope(origin, async {
+                let found = tool
+                    .execute(json!({ "query": "salary" }), &CancellationToken::new())
+                    .await
+                    .unwrap();
+                let mut senders: Vec<String> = found["results"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|hit| hit["sender_id"].as_str().unwrap().to_string())
+                    .collect();
+                senders.sort();
+                senders
+            })
+        };
+
+        assert_eq!(search("telegram:bob").await, ["bob"]);
+        assert_eq!(search("telegram:alice").await, ["+1555", "alice"]);
+        assert_eq!(search("webchat:owner").await, ["+1555", "alice", "bob"]);
+        // Outside a conversation there is no one to scope the search to.
+        assert!(tool
+            .execute(json!({ "query": "salary" }), &CancellationToken::new())
+            .await
+            .is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn vectors_round_trip_and_compare() {
+        let v = vec![0.5f32, -1.0, 2.0];
+        assert_eq!(decode_vector(&encode_vector(&v)), v);
+        assert!((cosine(&v, &v) - 1.0).abs() < 1e-6);
+        assert_eq!(cosine(&v, &[1.0]), 0.0);
+    }
+}
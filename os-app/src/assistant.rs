@@ -2,8 +2,31 @@
 //!
 //! See: specifications/openshell/implementation_v0_1_0.md
 
-use crate::config::{ApprovalMode, OpenShellConfig};
+use crate::admin::AdminAlerts;
+use crate::approval_batches::{ApprovalBatch, ApprovalBatches, PendingAction};
+use crate::approval_rules::{self, Approval};
+use crate::audit::AuditLog;
+use crate::capabilities::{self, ChannelCapabilities};
+use crate::config::{ApprovalMode, InjectionAction, OpenShellConfig, PersonaConfig};
+use crate::context_window::ContextWindow;
+use crate::continuations::SCHEDULE_FOLLOWUP_TOOL;
+use crate::facts::{self, FactExtractor, FactStore};
+use crate::feedback::Feedback;
+use crate::grants::{GrantOffers, SessionGrants};
+use crate::injection::{self, InjectionGuard};
+use crate::metrics::Metrics;
+use crate::pii::{self, PiiMasker};
+use crate::progress::{self, ProgressEvent};
 use crate::session::Session;
+use crate::skill_wasm::{SkillHost, WasmRuntime};
+use crate::skills::{SkillManifest, SkillPackage, SkillStore, SkillSummary};
+use crate::tasks::{CONVERSATION, DELEGATE_TASK_TOOL, RUN_CANCEL};
+use crate::template::{self, Escape, Vars};
+use crate::tool_limits::ToolLimiter;
+use crate::tool_results::ToolResultSummarizer;
+use crate::tool_selection;
+use crate::trace::{self, ApprovalOutcome, RunTrace, ToolOutcome, TraceStep};
+use crate::webhooks::{self, Webhooks};
 use anyhow::Result;
 use horizons_core::core_agents::models::{
     ActionProposal, ActionStatus, ReviewMode, ReviewPolicy, RiskLevel,
@@ -16,17 +39,45 @@ use horizons_core::memory::traits::{
 };
 use horizons_core::models::{AgentIdentity, OrgId, ProjectDbHandle, ProjectId};
 use horizons_core::onboard::traits::{ProjectDb, ProjectDbParam, ProjectDbValue};
-use os_channels::{InboundMessage, InboundMessageKind};
+use os_channels::ChannelAdapter;
 use os_llm::{ChatMessage, Role, ToolCall};
-use os_tools::{to_llm_tool_def, Tool};
+use os_tools::{to_llm_tool_def, CancellationToken, Tool, ToolError};
 use serde_json::json;
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Instant;
+use tracing::Instrument as _;
 use uuid::Uuid;
 
+/// Bounds for one `run`: interactive turns are short; delegated tasks get more room.
+#[derive(Debug, Clone, Copy)]
+pub struct RunBudget {
+    pub tool_loops_max: usize,
+    pub tokens_max: Option<u64>,
+    /// Whether `delegate_task` and `schedule_followup` are offered (they aren't inside a
+    /// delegated task, which has no conversation of its own to come back to).
+    pub allow_delegation: bool,
+    /// Off when the message comes from someone untrusted, e.g. a suggest-mode draft.
+    pub allow_tools: bool,
+}
+
+impl RunBudget {
+    pub fn interactive() -> Self {
+        Self {
+            tool_loops_max: 4,
+            tokens_max: None,
+            allow_delegation: true,
+            allow_tools: true,
+        }
+    }
+}
+
 pub struct AssistantAgent {
     cfg: OpenShellConfig,
     llm: Option<os_llm::LlmClient>,
+    /// Clients for personas that override `general.model`, `reasoning`, `sampling` or
+    /// `routing`.
+    persona_llms: HashMap<String, os_llm::LlmClient>,
     tools: Vec<Arc<dyn Tool>>,
     memory: Option<Arc<dyn HorizonsMemory>>,
     project_db: Arc<dyn ProjectDb>,
@@ -35,6 +86,21 @@ pub struct AssistantAgent {
     project_id: ProjectId,
     project_db_handle: ProjectDbHandle,
     evaluation: Option<Arc<EvaluationEngine>>,
+    tool_limits: Arc<ToolLimiter>,
+    webhooks: Option<Arc<Webhooks>>,
+    admin: Option<Arc<AdminAlerts>>,
+    audit: Option<Arc<AuditLog>>,
+    grant_offers: Option<Arc<GrantOffers>>,
+    approval_batches: Option<Arc<ApprovalBatches>>,
+    metrics: Option<Arc<Metrics>>,
+    channels: HashMap<String, ChannelCapabilities>,
+    pii: Option<PiiMasker>,
+    injection: Option<InjectionGuard>,
+    tool_results: Option<Arc<ToolResultSummarizer>>,
+    skills: Option<Arc<SkillStore>>,
+    wasm: Option<Arc<WasmRuntime>>,
+    facts: Option<Arc<FactStore>>,
+    fact_extractor: Option<Arc<FactExtractor>>,
 }
 
 impl AssistantAgent {
@@ -51,9 +117,66 @@ impl AssistantAgent {
         project_db_handle: ProjectDbHandle,
         evaluation: Option<Arc<EvaluationEngine>>,
     ) -> Self {
+        let tool_limits = Arc::new(ToolLimiter::new(&cfg.tools));
+        let persona_llms = cfg
+            .personas
+            .iter()
+            .filter_map(|(name, p)| {
+                if p.model.is_none()
+                    && p.reasoning.is_none()
+                    && p.sampling.is_none()
+                    && p.routing.is_none()
+                {
+                    return None;
+                }
+                let model = p.model.as_deref().unwrap_or(&cfg.general.model);
+                let Some(llm) = cfg.llm_client(model) else {
+                    tracing::warn!(
+                        persona = %name,
+                        %model,
+                        "no api key for persona model; using default model"
+                    );
+                    return None;
+                };
+                let reasoning = p
+                    .reasoning
+                    .clone()
+                    .unwrap_or_else(|| cfg.general.reasoning.clone());
+                let sampling = p
+                    .sampling
+                    .clone()
+                    .unwrap_or_else(|| cfg.general.sampling.clone());
+                let routing = p
+                    .routing
+                    .clone()
+                    .unwrap_or_else(|| cfg.general.routing.clone());
+                Some((
+                    name.clone(),
+                    llm.with_reasoning(reasoning)
+                        .with_sampling(sampling)
+                        .with_routing(routing),
+                ))
+            })
+            .collect();
+        // Patterns were checked when the config loaded.
+        let pii = match PiiMasker::new(&cfg.pii) {
+            Ok(masker) => cfg.pii.enabled.then_some(masker),
+            Err(e) => {
+                tracing::warn!(%e, "pii masking disabled: invalid config");
+                None
+            }
+        };
+        let injection = match InjectionGuard::new(&cfg.injection) {
+            Ok(guard) => cfg.injection.enabled.then_some(guard),
+            Err(e) => {
+                tracing::warn!(%e, "injection guard disabled: invalid config");
+                None
+            }
+        };
         Self {
             cfg,
             llm,
+            persona_llms,
             tools,
             memory,
             project_db,
@@ -62,28 +185,105 @@ impl AssistantAgent {
             project_id,
             project_db_handle,
             evaluation,
+            tool_limits,
+            webhooks: None,
+            admin: None,
+            audit: None,
+            grant_offers: None,
+            approval_batches: None,
+            metrics: None,
+            channels: HashMap::new(),
+            pii,
+            injection,
+            tool_results: None,
+            skills: None,
+            wasm: None,
+            facts: None,
+            fact_extractor: None,
         }
     }
 
-    pub async fn on_reaction(&self, inbound: &InboundMessage) -> Result<()> {
-        if inbound.kind != InboundMessageKind::Reaction {
-            return Ok(());
-        }
+    pub fn with_webhooks(mut self, webhooks: Arc<Webhooks>) -> Self {
+        self.webhooks = Some(webhooks);
+        self
+    }
+
+    /// Alert the admin conversation when the LLM keeps failing or a run hits its budget.
+    pub fn with_admin(mut self, admin: Arc<AdminAlerts>) -> Self {
+        self.admin = Some(admin);
+        self
+    }
+
+    pub fn with_audit(mut self, audit: Arc<AuditLog>) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    /// Lets approvers grant repeats of a call they approve; see `grants`.
+    pub fn with_grant_offers(mut self, offers: Arc<GrantOffers>) -> Self {
+        self.grant_offers = Some(offers);
+        self
+    }
+
+    /// Where calls waiting for a human are listed for `/api/v1/os/approvals`.
+    pub fn with_approval_batches(mut self, batches: Arc<ApprovalBatches>) -> Self {
+        self.approval_batches = Some(batches);
+        self
+    }
+
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Summarize tool outputs over their configured length.
+    pub fn with_tool_results(mut self, summarizer: Arc<ToolResultSummarizer>) -> Self {
+        self.tool_results = Some(summarizer);
+        self
+    }
+
+    /// Installed skills, for `/skill`, and the runtime for those that ship a module.
+    pub fn with_skills(mut self, skills: Arc<SkillStore>, wasm: Arc<WasmRuntime>) -> Self {
+        self.skills = Some(skills);
+        self.wasm = Some(wasm);
+        self
+    }
+
+    /// List known facts about the user in the system prompt, and learn new ones after each
+    /// turn with `extractor`.
+    pub fn with_facts(
+        mut self,
+        store: Arc<FactStore>,
+        extractor: Option<Arc<FactExtractor>>,
+    ) -> Self {
+        self.facts = Some(store);
+        self.fact_extractor = extractor;
+        self
+    }
+
+    /// Describe these channels' features in the system prompt.
+    pub fn with_channels(mut self, channels: &HashMap<String, Arc<dyn ChannelAdapter>>) -> Self {
+        self.channels = channels
+            .iter()
+            .map(|(id, adapter)| (id.clone(), ChannelCapabilities::of(adapter.as_ref())))
+            .collect();
+        self
+    }
+
+    /// Score a reaction as a verification case: 👍 passes, 👎 fails.
+    pub async fn on_reaction(&self, feedback: &Feedback) -> Result<()> {
         let Some(eval) = self.evaluation.as_ref() else {
             return Ok(());
         };
-
-        // Minimal v0.1.0 wiring: map 👍 to pass, 👎 to fail.
-        let (output, expected) = match inbound.content.as_str() {
-            "👍" | "❤️" | "✅" => ("positive".to_string(), Some("positive".to_string())),
-            "👎" | "❌" => ("negative".to_string(), Some("positive".to_string())),
-            _ => return Ok(()),
+        let output = if feedback.positive {
+            "positive"
+        } else {
+            "negative"
         };
-
         let case = VerificationCase::new(
-            format!("reaction:{}:{}", inbound.channel_id, inbound.sender_id),
-            output,
-            expected,
+            format!("reaction:{}:{}", feedback.channel_id, feedback.sender_id),
+            output.to_string(),
+            Some("positive".to_string()),
         );
         let identity = AgentIdentity::System {
             name: "openshell.feedback".to_string(),
@@ -100,6 +300,42 @@ impl AssistantAgent {
         Ok(())
     }
 
+    /// Deny the tool calls the conversation's run was waiting on, after `/cancel`, so they
+    /// don't linger in the approvals list or get approved after the fact. Returns how
+    /// many there were.
+    pub async fn withdraw_approvals(&self, channel_id: &str, sender_id: &str) -> usize {
+        let Some(batches) = self.approval_batches.as_ref() else {
+            return 0;
+        };
+        let identity = AgentIdentity::System {
+            name: "openshell".to_string(),
+        };
+        let mut withdrawn = 0;
+        for batch in batches.close_for(channel_id, sender_id) {
+            for action in batch.actions {
+                if let Some(offers) = self.grant_offers.as_ref() {
+                    offers.close(action.action_id);
+                }
+                let denied = self
+                    .core_agents
+                    .deny(
+                        self.org_id,
+                        self.project_id,
+                        &self.project_db_handle,
+                        action.action_id,
+                        &identity,
+                        "run cancelled with /cancel",
+                    )
+                    .await;
+                match denied {
+                    Ok(()) => withdrawn += 1,
+                    Err(e) => tracing::warn!(%e, %action.action_id, "failed to withdraw approval"),
+                }
+            }
+        }
+        withdrawn
+    }
+
     #[tracing::instrument(level = "info", skip_all)]
     pub async fn run(
         &self,
@@ -108,53 +344,252 @@ impl AssistantAgent {
         session: &mut Session,
         user_message: &str,
     ) -> Result<String> {
+        self.run_with_budget(
+            channel_id,
+            sender_id,
+            session,
+            user_message,
+            RunBudget::interactive(),
+        )
+        .await
+    }
+
+    #[tracing::instrument(level = "info", skip_all)]
+    pub async fn run_with_budget(
+        &self,
+        channel_id: &str,
+        sender_id: &str,
+        session: &mut Session,
+        user_message: &str,
+        budget: RunBudget,
+    ) -> Result<String> {
+        session.last_trace = Some(RunTrace::new());
         session.history.push(ChatMessage {
             role: Role::User,
             content: user_message.to_string(),
             tool_calls: vec![],
             tool_call_id: None,
+            reasoning: vec![],
         });
+        let linked_context = session.linked_context.take();
+        let skill = match session.skill.take() {
+            Some(name) => Some(self.load_skill(&name, channel_id).await?),
+            None => None,
+        };
+
+        let persona_name = session
+            .run_persona
+            .take()
+            .or_else(|| session.persona.clone())
+            .or_else(|| {
+                self.cfg
+                    .default_persona_for_channel(channel_id)
+                    .map(|s| s.to_string())
+            });
+        let persona: Option<&PersonaConfig> = persona_name
+            .as_deref()
+            .and_then(|name| self.cfg.personas.get(name));
+        let llm = persona_name
+            .as_deref()
+            .and_then(|name| self.persona_llms.get(name))
+            .or(self.llm.as_ref());
+        let system_prompt = persona
+            .and_then(|p| p.system_prompt.clone())
+            .unwrap_or_else(|| self.cfg.general.system_prompt.clone());
+        let allowed_tools = persona.and_then(|p| p.tools.as_ref());
+        let tools: Vec<Arc<dyn Tool>> = self
+            .tools
+            .iter()
+            .filter(|_| budget.allow_tools)
+            .filter(|t| allowed_tools.is_none_or(|allow| allow.contains(&t.spec().name)))
+            .filter(|t| {
+                skill
+                    .as_ref()
+                    .is_none_or(|s| s.requires.offers_tool(&t.spec().name))
+            })
+            .filter(|t| {
+                budget.allow_delegation
+                    || ![DELEGATE_TASK_TOOL, SCHEDULE_FOLLOWUP_TOOL]
+                        .contains(&t.spec().name.as_str())
+            })
+            .cloned()
+            .collect();
 
-        let Some(llm) = self.llm.as_ref() else {
+        // Runs outside the gateway and task queue (drafts, tests) can't be cancelled.
+        let cancel = RUN_CANCEL.try_with(|c| c.clone()).unwrap_or_default();
+        if let Some(skill) = skill.as_ref().filter(|s| s.module.is_some()) {
+            let reply = tokio::select! {
+                reply = self.run_wasm_skill(
+                    skill,
+                    channel_id,
+                    sender_id,
+                    tools,
+                    user_message,
+                    &cancel,
+                ) => reply?,
+                _ = cancel.cancelled() => return Err(anyhow::anyhow!("run cancelled")),
+            };
+            session.history.push(ChatMessage {
+                role: Role::Assistant,
+                content: reply.clone(),
+                tool_calls: vec![],
+                tool_call_id: None,
+                reasoning: vec![],
+            });
+            return Ok(reply);
+        }
+
+        let Some(llm) = llm else {
             let reply = format!("echo: {user_message}");
             session.history.push(ChatMessage {
                 role: Role::Assistant,
                 content: reply.clone(),
                 tool_calls: vec![],
                 tool_call_id: None,
+                reasoning: vec![],
             });
             return Ok(reply);
         };
 
-        let tool_defs: Vec<os_llm::ToolDefinition> = self
-            .tools
-            .iter()
-            .map(|t| to_llm_tool_def(t.as_ref()))
-            .collect();
+        let manifest = capabilities::manifest(
+            &self.cfg,
+            &tools,
+            &budget,
+            channel_id,
+            self.channels.get(channel_id),
+        );
+        let all_tool_defs: Vec<os_llm::ToolDefinition> =
+            tools.iter().map(|t| to_llm_tool_def(t.as_ref())).collect();
+        // A pruned set for this turn, until the model asks for everything.
+        let mut pruned_tool_defs: Option<Vec<os_llm::ToolDefinition>> =
+            tool_selection::select(&tools, &self.cfg.tools, &session.history, user_message).map(
+                |selected| {
+                    let mut defs: Vec<os_llm::ToolDefinition> = selected
+                        .iter()
+                        .map(|t| to_llm_tool_def(t.as_ref()))
+                        .collect();
+                    defs.push(tool_selection::request_all_tools_def());
+                    defs
+                },
+            );
+
+        let window = ContextWindow::for_model(&self.cfg.context, llm.model());
 
         let mut tool_loops = 0usize;
-        let tool_loops_max = 4usize;
+        let tool_loops_max = budget.tool_loops_max;
+        let tokens_start = session.usage_totals.prompt_tokens as u64
+            + session.usage_totals.completion_tokens as u64;
+
+        let offers_untrusted = self
+            .injection
+            .as_ref()
+            .is_some_and(|g| tools.iter().any(|t| g.is_untrusted(&t.spec().name)));
+        // Set once untrusted output looked like instructions; holds the matched phrase.
+        let mut tainted: Option<String> = None;
 
         loop {
             tool_loops += 1;
             if tool_loops > tool_loops_max {
+                self.emit_budget_exceeded(
+                    channel_id,
+                    sender_id,
+                    "tool_loops",
+                    tool_loops_max as u64,
+                );
                 return Ok("Tool loop limit reached.".to_string());
             }
+            if let Some(tokens_max) = budget.tokens_max {
+                let used = session.usage_totals.prompt_tokens as u64
+                    + session.usage_totals.completion_tokens as u64
+                    - tokens_start;
+                if used > tokens_max {
+                    self.emit_budget_exceeded(channel_id, sender_id, "tokens", tokens_max);
+                    return Ok("Token budget exhausted.".to_string());
+                }
+            }
 
             let mut messages = Vec::new();
+            let mut system = self
+                .build_system_prompt(&system_prompt, channel_id, sender_id, user_message)
+                .await;
+            system.push_str("\n\n");
+            system.push_str(&manifest);
+            if let Some(skill) = skill.as_ref() {
+                system.push_str(&format!(
+                    "\n\nThis turn runs skill {} {}. Follow its instructions:\n{}",
+                    skill.name, skill.version, skill.instructions
+                ));
+            }
+            if let Some(context) = linked_context.as_deref() {
+                system.push_str("\n\n");
+                system.push_str(context);
+            }
+            if self.pii.is_some() {
+                system.push_str("\n\n");
+                system.push_str(pii::PROMPT_NOTE);
+            }
+            if offers_untrusted {
+                system.push_str("\n\n");
+                system.push_str(injection::PROMPT_NOTE);
+            }
+            let tool_defs = pruned_tool_defs.as_deref().unwrap_or(&all_tool_defs);
+            let fixed = window.count_text(&system) + window.count_tools(tool_defs);
+            let start = window.history_start(&session.history, fixed);
+            if start > 0 {
+                tracing::debug!(
+                    dropped = start,
+                    "older history left out to fit the context window"
+                );
+            }
             messages.push(ChatMessage {
                 role: Role::System,
-                content: self
-                    .build_system_prompt(channel_id, sender_id, user_message)
-                    .await,
+                content: system,
                 tool_calls: vec![],
                 tool_call_id: None,
+                reasoning: vec![],
             });
-            messages.extend(session.history.clone());
+            messages.extend(session.history[start..].iter().cloned());
+            if let Some(masker) = self.pii.as_ref() {
+                messages = masker.mask_messages(&messages, &mut session.pii);
+            }
 
-            let response = llm.chat(&messages, &tool_defs).await?;
+            progress::emit(ProgressEvent::Thinking);
+            let asked = Instant::now();
+            let response = tokio::select! {
+                response = llm.chat(&messages, tool_defs) => response,
+                _ = cancel.cancelled() => return Err(anyhow::anyhow!("run cancelled")),
+            };
+            session.trace(TraceStep::Model {
+                model: llm.model().to_string(),
+                duration_ms: trace::elapsed_ms(asked),
+                prompt_tokens: response
+                    .as_ref()
+                    .map_or(0, |r| r.usage.prompt_tokens as u64),
+                completion_tokens: response
+                    .as_ref()
+                    .map_or(0, |r| r.usage.completion_tokens as u64),
+                tool_calls: response.as_ref().map_or(0, |r| r.message.tool_calls.len()),
+                error: response.as_ref().err().map(ToString::to_string),
+            });
+            if let Some(metrics) = self.metrics.as_ref() {
+                metrics.llm_call(response.as_ref().err().map(ToString::to_string));
+                let health = metrics.llm_health(chrono::Utc::now());
+                if let Some(admin) = self.admin.as_ref().filter(|_| !health.reachable) {
+                    admin.alert(
+                        "llm",
+                        format!(
+                            "the LLM failed {} times in a row; last error: {}",
+                            health.consecutive_failures,
+                            health.last_error.unwrap_or_default()
+                        ),
+                    );
+                }
+            }
+            let mut response = response?;
+            session.pii.unmask_message(&mut response.message);
             session.usage_totals.prompt_tokens += response.usage.prompt_tokens;
             session.usage_totals.completion_tokens += response.usage.completion_tokens;
+            session.usage_totals.reasoning_tokens += response.usage.reasoning_tokens;
 
             if response.message.tool_calls.is_empty() {
                 let content = response.message.content.clone();
@@ -163,6 +598,7 @@ impl AssistantAgent {
                     content: content.clone(),
                     tool_calls: vec![],
                     tool_call_id: None,
+                    reasoning: vec![],
                 });
                 session.last_assistant_message_id = Some(Uuid::new_v4().to_string());
 
@@ -170,15 +606,80 @@ impl AssistantAgent {
                     self.append_memory(mem, channel_id, sender_id, user_message, &content)
                         .await;
                 }
+                // Only the user's own conversations: not delegated tasks or drafts for
+                // third parties, and not with PII masking, which the extra call would bypass.
+                if let Some(extractor) = self
+                    .fact_extractor
+                    .as_ref()
+                    .filter(|_| budget.allow_tools && budget.allow_delegation && self.pii.is_none())
+                {
+                    extractor.observe(
+                        facts::subject_for(&self.cfg.identities, channel_id, sender_id),
+                        user_message,
+                        &content,
+                    );
+                }
+                if let Some(hooks) = self.webhooks.as_ref() {
+                    hooks.emit(
+                        webhooks::RUN_COMPLETED,
+                        json!({
+                            "channel_id": channel_id,
+                            "sender_id": sender_id,
+                            "tool_loops": tool_loops,
+                            "tokens": session.usage_totals.prompt_tokens as u64
+                                + session.usage_totals.completion_tokens as u64
+                                - tokens_start,
+                        }),
+                    );
+                }
 
+                if session.show_thinking {
+                    return Ok(with_reasoning(&response.message.reasoning, content));
+                }
                 return Ok(content);
             }
 
             session.history.push(response.message.clone());
 
+            let refusing = tainted.is_some()
+                && self
+                    .injection
+                    .as_ref()
+                    .is_some_and(|g| g.action() == InjectionAction::Refuse);
+            // Nothing runs in simulation, so there's nothing to ask approval for.
+            let batch = if refusing || self.cfg.simulation.enabled {
+                None
+            } else {
+                tokio::select! {
+                    batch = self.propose_batch(
+                        &response.message.tool_calls,
+                        &tools,
+                        tainted.is_some(),
+                        &session.grants,
+                        skill.as_ref(),
+                    ) => batch?,
+                    _ = cancel.cancelled() => return Err(anyhow::anyhow!("run cancelled")),
+                }
+            };
+            let (batch_id, mut proposed) = match batch {
+                Some((batch, proposed)) => (Some(batch.id), proposed),
+                None => (None, HashMap::new()),
+            };
+
             for tool_call in response.message.tool_calls {
-                let tool = self
-                    .tools
+                if tool_call.name == tool_selection::REQUEST_ALL_TOOLS {
+                    pruned_tool_defs = None;
+                    session.history.push(ChatMessage {
+                        role: Role::Tool,
+                        content: json!({ "status": "all tools are now available" }).to_string(),
+                        tool_calls: vec![],
+                        tool_call_id: Some(tool_call.id.clone()),
+                        reasoning: vec![],
+                    });
+                    continue;
+                }
+                // Only the persona's tools are callable, whatever the model asks for.
+                let tool = tools
                     .iter()
                     .find(|t| t.spec().name == tool_call.name)
                     .cloned();
@@ -188,42 +689,211 @@ impl AssistantAgent {
                         content: json!({ "error": "unknown tool" }).to_string(),
                         tool_calls: vec![],
                         tool_call_id: Some(tool_call.id.clone()),
+                        reasoning: vec![],
                     });
                     continue;
                 };
 
                 let args: serde_json::Value =
                     serde_json::from_str(&tool_call.arguments).unwrap_or_else(|_| json!({}));
+                if let Some(reason) = skill
+                    .as_ref()
+                    .and_then(|s| s.requires.check_call(&tool_call.name, &args))
+                {
+                    if let Some(audit) = self.audit.as_ref() {
+                        audit.policy_violation(&tool_call.name, &reason).await;
+                    }
+                    session.history.push(ChatMessage {
+                        role: Role::Tool,
+                        content: json!({ "error": format!("tool call refused: {reason}") })
+                            .to_string(),
+                        tool_calls: vec![],
+                        tool_call_id: Some(tool_call.id.clone()),
+                        reasoning: vec![],
+                    });
+                    continue;
+                }
                 let risk = effective_risk_level(tool.as_ref(), &args);
-                let approved = self.gate_tool_call(&tool_call, risk, &args).await?;
-                if !approved {
+                let escalate = match (self.injection.as_ref(), tainted.as_deref()) {
+                    (Some(guard), Some(phrase)) if guard.action() == InjectionAction::Refuse => {
+                        session.history.push(ChatMessage {
+                            role: Role::Tool,
+                            content: json!({
+                                "error": "tool call refused: earlier tool output contained instructions",
+                                "matched": phrase,
+                            })
+                            .to_string(),
+                            tool_calls: vec![],
+                            tool_call_id: Some(tool_call.id.clone()),
+                            reasoning: vec![],
+                        });
+                        continue;
+                    }
+                    (_, tainted) => tainted.is_some(),
+                };
+                if self.cfg.simulation.enabled && risk != RiskLevel::Low {
+                    tracing::info!(
+                        tool = %tool_call.name,
+                        arguments = %args,
+                        "simulation: tool call not executed"
+                    );
+                    session.history.push(ChatMessage {
+                        role: Role::Tool,
+                        content: json!({
+                            "simulated": true,
+                            "status": "not executed: the assistant is in simulation mode",
+                            "tool": tool_call.name,
+                            "risk_level": risk,
+                            "arguments": args,
+                        })
+                        .to_string(),
+                        tool_calls: vec![],
+                        tool_call_id: Some(tool_call.id.clone()),
+                        reasoning: vec![],
+                    });
+                    continue;
+                }
+                let asked = Instant::now();
+                let approval = tokio::select! {
+                    approval = self.gate_tool_call(
+                        &tool_call,
+                        risk,
+                        &args,
+                        escalate,
+                        &mut session.grants,
+                        proposed.remove(&tool_call.id),
+                        skill.as_ref(),
+                    ) => approval?,
+                    _ = cancel.cancelled() => return Err(anyhow::anyhow!("run cancelled")),
+                };
+                let approval_ms = trace::elapsed_ms(asked);
+                if approval == ApprovalOutcome::Denied {
+                    session.trace(TraceStep::Tool {
+                        tool: tool_call.name.clone(),
+                        approval,
+                        approval_ms,
+                        duration_ms: 0,
+                        outcome: ToolOutcome::Denied,
+                    });
+                    self.emit_tool_denied(channel_id, sender_id, &tool_call.name, "not approved");
                     session.history.push(ChatMessage {
                         role: Role::Tool,
                         content: json!({ "error": "tool call denied" }).to_string(),
                         tool_calls: vec![],
                         tool_call_id: Some(tool_call.id.clone()),
+                        reasoning: vec![],
                     });
                     continue;
                 }
 
-                let tool_out = tool.execute(args).await?;
+                progress::emit(ProgressEvent::ToolStarted {
+                    tool: tool_call.name.clone(),
+                    detail: progress::call_detail(&args),
+                });
+                let started = Instant::now();
+                let tool_out = {
+                    let _permit = self.tool_limits.acquire(&tool_call.name).await;
+                    execute_tool(tool.as_ref(), args, &cancel)
+                        .instrument(tracing::info_span!("tool", tool = %tool_call.name))
+                        .await
+                };
+                session.trace(TraceStep::Tool {
+                    tool: tool_call.name.clone(),
+                    approval,
+                    approval_ms,
+                    duration_ms: trace::elapsed_ms(started),
+                    outcome: match &tool_out {
+                        Ok(_) => ToolOutcome::Ok,
+                        Err(ToolError::Unauthorized(_)) => ToolOutcome::Refused,
+                        Err(_) => ToolOutcome::Failed,
+                    },
+                });
+                progress::emit(ProgressEvent::ToolFinished {
+                    tool: tool_call.name.clone(),
+                    ok: tool_out.is_ok(),
+                });
+                // A policy refusal is the model's to work around, not a failed run.
+                let tool_out = match tool_out {
+                    Ok(v) => v,
+                    Err(ToolError::Unauthorized(detail)) => {
+                        if let Some(audit) = self.audit.as_ref() {
+                            audit.policy_violation(&tool_call.name, &detail).await;
+                        }
+                        self.emit_tool_denied(channel_id, sender_id, &tool_call.name, &detail);
+                        json!({ "error": detail })
+                    }
+                    Err(e) => return Err(e.into()),
+                };
+                let mut content = tool_out.to_string();
+                let guard = self
+                    .injection
+                    .as_ref()
+                    .filter(|g| g.is_untrusted(&tool_call.name));
+                // Scanned whole, before a summary could reword it.
+                if let Some(phrase) = guard.and_then(|g| g.scan(&content)) {
+                    if let Some(audit) = self.audit.as_ref() {
+                        audit.injection_suspected(&tool_call.name, &phrase).await;
+                    }
+                    tainted.get_or_insert(phrase);
+                }
+                if let Some(summarizer) = self.tool_results.as_ref() {
+                    let agent_id = format!("os.assistant.{channel_id}.{sender_id}");
+                    content = tokio::select! {
+                        content = summarizer.shape(
+                            &tool_call.name,
+                            &agent_id,
+                            content,
+                            self.pii.as_ref().map(|masker| (masker, &mut session.pii)),
+                        ) => content,
+                        _ = cancel.cancelled() => return Err(anyhow::anyhow!("run cancelled")),
+                    };
+                }
+                if let Some(guard) = guard {
+                    content = guard.wrap(&tool_call.name, &content);
+                }
                 session.history.push(ChatMessage {
                     role: Role::Tool,
-                    content: tool_out.to_string(),
+                    content,
                     tool_calls: vec![],
                     tool_call_id: Some(tool_call.id.clone()),
+                    reasoning: vec![],
                 });
             }
+            if let (Some(id), Some(batches)) = (batch_id, self.approval_batches.as_ref()) {
+                batches.close(id);
+            }
         }
     }
 
     async fn build_system_prompt(
         &self,
+        base_prompt: &str,
         channel_id: &str,
         sender_id: &str,
         user_message: &str,
     ) -> String {
-        let mut system = self.cfg.general.system_prompt.clone();
+        let mut system = if base_prompt.contains("{{") {
+            let vars = Vars::new()
+                .with_clock(chrono::Local::now())
+                .set("channel", channel_id);
+            // Checked when the config loads, so this only fails on a size limit.
+            template::render(base_prompt, &vars, Escape::Prompt).unwrap_or_else(|e| {
+                tracing::warn!(%e, "system prompt template failed; using it as written");
+                base_prompt.to_string()
+            })
+        } else {
+            base_prompt.to_string()
+        };
+        if let Some(known) = self.facts.as_ref().and_then(|store| {
+            store.prompt_section(&facts::subject_for(
+                &self.cfg.identities,
+                channel_id,
+                sender_id,
+            ))
+        }) {
+            system.push_str("\n\n");
+            system.push_str(&known);
+        }
         let Some(mem) = self.memory.as_ref() else {
             return system;
         };
@@ -282,14 +952,156 @@ impl AssistantAgent {
         let _ = mem.append_item(self.org_id, item).await;
     }
 
+    fn emit_tool_denied(&self, channel_id: &str, sender_id: &str, tool: &str, reason: &str) {
+        if let Some(hooks) = self.webhooks.as_ref() {
+            hooks.emit(
+                webhooks::TOOL_DENIED,
+                json!({
+                    "channel_id": channel_id,
+                    "sender_id": sender_id,
+                    "tool": tool,
+                    "reason": reason,
+                }),
+            );
+        }
+    }
+
+    fn emit_budget_exceeded(&self, channel_id: &str, sender_id: &str, limit: &str, max: u64) {
+        if let Some(admin) = self.admin.as_ref() {
+            admin.alert(
+                &format!("budget:{channel_id}:{sender_id}"),
+                format!("a run for {channel_id}:{sender_id} stopped at its {limit} limit ({max})"),
+            );
+        }
+        if let Some(hooks) = self.webhooks.as_ref() {
+            hooks.emit(
+                webhooks::BUDGET_EXCEEDED,
+                json!({
+                    "channel_id": channel_id,
+                    "sender_id": sender_id,
+                    "limit": limit,
+                    "max": max,
+                }),
+            );
+        }
+    }
+
+    /// `proposed` is the action id when the call was already proposed with its turn.
+    #[allow(clippy::too_many_arguments)]
     async fn gate_tool_call(
         &self,
         tool_call: &ToolCall,
         risk: RiskLevel,
         arguments: &serde_json::Value,
-    ) -> Result<bool> {
-        let approval_mode = approval_mode_for_tool(&self.cfg, &tool_call.name, risk, arguments);
-        let review_mode = match approval_mode {
+        escalate: bool,
+        grants: &mut SessionGrants,
+        proposed: Option<Uuid>,
+        skill: Option<&SkillPackage>,
+    ) -> Result<ApprovalOutcome> {
+        let mut single_batch = None;
+        let action_id = match proposed {
+            Some(action_id) => action_id,
+            None => {
+                let Some(approval) =
+                    self.approval_for(tool_call, risk, arguments, escalate, grants)
+                else {
+                    return Ok(ApprovalOutcome::NotNeeded);
+                };
+                let skill = skill.map(SkillSummary::from);
+                let Some(action_id) = self
+                    .propose(tool_call, risk, arguments, &approval, skill.as_ref())
+                    .await?
+                else {
+                    return Ok(ApprovalOutcome::NotNeeded);
+                };
+                if approval.mode == ApprovalMode::Human {
+                    let batch = ApprovalBatch::new(vec![PendingAction {
+                        action_id,
+                        tool: tool_call.name.clone(),
+                        risk_level: risk,
+                        arguments: arguments.clone(),
+                        reviewers: approval.reviewers.clone(),
+                    }])
+                    .with_skill(skill.clone());
+                    if let Some(hooks) = self.webhooks.as_ref() {
+                        hooks.emit(
+                            webhooks::APPROVAL_PENDING,
+                            json!({
+                                "action_id": action_id,
+                                "batch_id": batch.id,
+                                "tool": tool_call.name,
+                                "risk_level": risk,
+                                "reviewers": approval.reviewers,
+                                "skill": skill,
+                            }),
+                        );
+                    }
+                    single_batch = Some(batch.id);
+                    if let Some(batches) = self.approval_batches.as_ref() {
+                        batches.insert(batch);
+                    }
+                }
+                action_id
+            }
+        };
+
+        let status = wait_for_action_status(
+            &*self.project_db,
+            self.org_id,
+            &self.project_db_handle,
+            action_id,
+            std::time::Duration::from_secs(60),
+        )
+        .await;
+        if let (Some(id), Some(batches)) = (single_batch, self.approval_batches.as_ref()) {
+            batches.close(id);
+        }
+        let scope = self
+            .grant_offers
+            .as_ref()
+            .and_then(|offers| offers.close(action_id));
+
+        let approved = matches!(status?, ActionStatus::Approved | ActionStatus::Executed);
+        if let (true, Some(scope)) = (approved, scope) {
+            grants.insert(&tool_call.name, arguments, scope);
+        }
+        Ok(if approved {
+            ApprovalOutcome::Approved
+        } else {
+            ApprovalOutcome::Denied
+        })
+    }
+
+    /// `None` when an earlier grant already covers the call.
+    fn approval_for(
+        &self,
+        tool_call: &ToolCall,
+        risk: RiskLevel,
+        arguments: &serde_json::Value,
+        escalate: bool,
+        grants: &SessionGrants,
+    ) -> Option<Approval> {
+        let mut approval = approval_rules::decide(&self.cfg, &tool_call.name, risk, arguments);
+        // After a suspected injection the owner decides, whatever the configured mode.
+        if escalate {
+            approval.mode = ApprovalMode::Human;
+        } else if approval.mode != ApprovalMode::Auto && grants.allows(&tool_call.name, arguments) {
+            tracing::info!(tool = %tool_call.name, "tool call allowed by an earlier grant");
+            return None;
+        }
+        Some(approval)
+    }
+
+    /// Record the review policy and propose the call; `None` when it's auto-approved.
+    async fn propose(
+        &self,
+        tool_call: &ToolCall,
+        risk: RiskLevel,
+        arguments: &serde_json::Value,
+        approval: &Approval,
+        skill: Option<&SkillSummary>,
+    ) -> Result<Option<Uuid>> {
+        let review_mode = match approval.mode {
             ApprovalMode::Auto => ReviewMode::Auto,
             ApprovalMode::Ai => ReviewMode::Ai,
             ApprovalMode::Human => ReviewMode::Human,
@@ -318,7 +1130,7 @@ impl AssistantAgent {
             .await;
 
         if review_mode == ReviewMode::Auto {
-            return Ok(true);
+            return Ok(None);
         }
 
         let handle_json =
@@ -328,6 +1140,8 @@ impl AssistantAgent {
             "_project_db_handle": handle_json,
             "tool": tool_call.name,
             "arguments": arguments,
+            "reviewers": approval.reviewers,
+            "skill": skill,
         });
 
         let proposal = ActionProposal::new(
@@ -344,19 +1158,165 @@ impl AssistantAgent {
         )?;
 
         let action_id = self.core_agents.propose_action(proposal, &identity).await?;
-        let status = wait_for_action_status(
-            &*self.project_db,
-            self.org_id,
-            &self.project_db_handle,
-            action_id,
-            std::time::Duration::from_secs(60),
+        if review_mode == ReviewMode::Human {
+            if let Some(offers) = self.grant_offers.as_ref() {
+                offers.open(action_id);
+            }
+        }
+        Ok(Some(action_id))
+    }
+
+    /// The package for `/skill <name>`, if it's installed and declares `channel_id`.
+    async fn load_skill(&self, name: &str, channel_id: &str) -> Result<SkillPackage> {
+        let package = match self.skills.as_ref() {
+            Some(store) => store.package(name).await,
+            None => None,
+        }
+        .ok_or_else(|| anyhow::anyhow!("skill {name} is not installed"))?;
+        if !package.requires.allows_channel(channel_id) {
+            return Err(anyhow::anyhow!(
+                "skill {name} doesn't declare the {channel_id} channel"
+            ));
+        }
+        Ok(package)
+    }
+
+    /// Run a skill's wasm module on the request in place of a model turn.
+    async fn run_wasm_skill(
+        &self,
+        skill: &SkillPackage,
+        channel_id: &str,
+        sender_id: &str,
+        tools: Vec<Arc<dyn Tool>>,
+        user_message: &str,
+        cancel: &CancellationToken,
+    ) -> Result<String> {
+        let (Some(store), Some(wasm)) = (self.skills.as_ref(), self.wasm.as_ref()) else {
+            return Err(anyhow::anyhow!("skills are not available"));
+        };
+        let module = store
+            .module(&skill.name)
+            .await
+            .ok_or_else(|| anyhow::anyhow!("skill {} has no module installed", skill.name))?;
+        let host = Arc::new(self.unattended(
+            tools,
+            Some(skill.requires.clone()),
+            format!("os.assistant.{channel_id}.{sender_id}"),
+            cancel.clone(),
+        ));
+        progress::emit(ProgressEvent::Thinking);
+        wasm.run(
+            &skill.name,
+            &module,
+            &skill.requires.host,
+            user_message,
+            host,
         )
-        .await?;
+        .await
+    }
 
-        Ok(matches!(
-            status,
-            ActionStatus::Approved | ActionStatus::Executed
-        ))
+    /// Call a tool for an automation rule, with no model or human involved; see
+    /// `UnattendedTools`.
+    pub async fn call_tool_unattended(
+        &self,
+        rule_id: &str,
+        tool: &str,
+        arguments: serde_json::Value,
+        cancel: CancellationToken,
+    ) -> std::result::Result<serde_json::Value, String> {
+        self.unattended(
+            self.tools.clone(),
+            None,
+            format!("os.automation.{rule_id}"),
+            cancel,
+        )
+        .call_tool(tool, arguments)
+        .await
+    }
+
+    fn unattended(
+        &self,
+        tools: Vec<Arc<dyn Tool>>,
+        manifest: Option<SkillManifest>,
+        agent_id: String,
+        cancel: CancellationToken,
+    ) -> UnattendedTools {
+        UnattendedTools {
+            cfg: self.cfg.clone(),
+            manifest,
+            tools,
+            tool_limits: self.tool_limits.clone(),
+            audit: self.audit.clone(),
+            memory: self.memory.clone(),
+            org_id: self.org_id,
+            agent_id,
+            cancel,
+        }
+    }
+
+    /// When several calls in a turn need a human, propose them all up front and announce
+    /// them in one webhook. Returns the batch and each proposed call's action id.
+    async fn propose_batch(
+        &self,
+        tool_calls: &[ToolCall],
+        tools: &[Arc<dyn Tool>],
+        escalate: bool,
+        grants: &SessionGrants,
+        skill: Option<&SkillPackage>,
+    ) -> Result<Option<(ApprovalBatch, HashMap<String, Uuid>)>> {
+        let mut human = Vec::new();
+        for tool_call in tool_calls {
+            let Some(tool) = tools.iter().find(|t| t.spec().name == tool_call.name) else {
+                continue;
+            };
+            let args: serde_json::Value =
+                serde_json::from_str(&tool_call.arguments).unwrap_or_else(|_| json!({}));
+            // Refused when its turn comes, without asking anyone.
+            if skill.is_some_and(|s| s.requires.check_call(&tool_call.name, &args).is_some()) {
+                continue;
+            }
+            let risk = effective_risk_level(tool.as_ref(), &args);
+            match self.approval_for(tool_call, risk, &args, escalate, grants) {
+                Some(approval) if approval.mode == ApprovalMode::Human => {
+                    human.push((tool_call, risk, args, approval))
+                }
+                _ => {}
+            }
+        }
+        if human.len() < 2 {
+            return Ok(None);
+        }
+
+        let skill = skill.map(SkillSummary::from);
+        let mut proposed = HashMap::new();
+        let mut actions = Vec::new();
+        for (tool_call, risk, args, approval) in human {
+            let Some(action_id) = self
+                .propose(tool_call, risk, &args, &approval, skill.as_ref())
+                .await?
+            else {
+                continue;
+            };
+            proposed.insert(tool_call.id.clone(), action_id);
+            actions.push(PendingAction {
+                action_id,
+                tool: tool_call.name.clone(),
+                risk_level: risk,
+                arguments: args,
+                reviewers: approval.reviewers,
+            });
+        }
+        let batch = ApprovalBatch::new(actions).with_skill(skill);
+        if let Some(hooks) = self.webhooks.as_ref() {
+            hooks.emit(
+                webhooks::APPROVAL_PENDING,
+                json!({ "batch_id": batch.id, "actions": batch.actions, "skill": batch.skill }),
+            );
+        }
+        if let Some(batches) = self.approval_batches.as_ref() {
+            batches.insert(batch.clone());
+        }
+        Ok(Some((batch, proposed)))
     }
 }
 
@@ -380,50 +1340,174 @@ fn action_type_for_tool(tool_name: &str, arguments: &serde_json::Value) -> Strin
     }
 }
 
-fn approval_mode_for_tool(
+/// Tools called without a model: by a skill's wasm module, under its manifest, or by an
+/// automation rule. No one is asked to approve these calls, so those that aren't
+/// auto-approved are refused.
+struct UnattendedTools {
+    cfg: OpenShellConfig,
+    manifest: Option<SkillManifest>,
+    tools: Vec<Arc<dyn Tool>>,
+    tool_limits: Arc<ToolLimiter>,
+    audit: Option<Arc<AuditLog>>,
+    memory: Option<Arc<dyn HorizonsMemory>>,
+    org_id: OrgId,
+    agent_id: String,
+    cancel: CancellationToken,
+}
+
+#[async_trait::async_trait]
+impl SkillHost for UnattendedTools {
+    async fn call_tool(
+        &self,
+        tool_name: &str,
+        arguments: serde_json::Value,
+    ) -> std::result::Result<serde_json::Value, String> {
+        if let Some(reason) = self
+            .manifest
+            .as_ref()
+            .and_then(|m| m.check_call(tool_name, &arguments))
+        {
+            if let Some(audit) = self.audit.as_ref() {
+                audit.policy_violation(tool_name, &reason).await;
+            }
+            return Err(format!("tool call refused: {reason}"));
+        }
+        let tool = self
+            .tools
+            .iter()
+            .find(|t| t.spec().name == tool_name)
+            .ok_or_else(|| format!("unknown tool {tool_name}"))?;
+        let risk = effective_risk_level(tool.as_ref(), &arguments);
+        if self.cfg.simulation.enabled && risk != RiskLevel::Low {
+            return Ok(json!({
+                "simulated": true,
+                "status": "not executed: the assistant is in simulation mode",
+                "tool": tool_name,
+                "risk_level": risk,
+                "arguments": arguments,
+            }));
+        }
+        if approval_rules::decide(&self.cfg, tool_name, risk, &arguments).mode != ApprovalMode::Auto
+        {
+            return Err(format!(
+                "tool call refused: {tool_name} needs approval, which unattended calls can't ask for"
+            ));
+        }
+        progress::emit(ProgressEvent::ToolStarted {
+            tool: tool_name.to_string(),
+            detail: progress::call_detail(&arguments),
+        });
+        let out = {
+            let _permit = self.tool_limits.acquire(tool_name).await;
+            execute_tool(tool.as_ref(), arguments, &self.cancel).await
+        };
+        progress::emit(ProgressEvent::ToolFinished {
+            tool: tool_name.to_string(),
+            ok: out.is_ok(),
+        });
+        match out {
+            Ok(v) => Ok(v),
+            Err(ToolError::Unauthorized(detail)) => {
+                if let Some(audit) = self.audit.as_ref() {
+                    audit.policy_violation(tool_name, &detail).await;
+                }
+                Err(detail)
+            }
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    async fn search_memory(&self, query: &str) -> std::result::Result<serde_json::Value, String> {
+        let Some(mem) = self.memory.as_ref() else {
+            return Ok(json!([]));
+        };
+        let items = mem
+            .retrieve(
+                self.org_id,
+                &self.agent_id,
+                RetrievalQuery::new(query.to_string(), 5),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(json!(items
+            .iter()
+            .map(|item| item.content_as_text())
+            .collect::<Vec<_>>()))
+    }
+}
+
+pub(crate) fn approval_mode_for_tool(
     cfg: &OpenShellConfig,
     tool_name: &str,
     risk: RiskLevel,
     arguments: &serde_json::Value,
 ) -> ApprovalMode {
-    match tool_name {
-        "shell.execute" => cfg.security.shell_approval,
-        "browser" => cfg.security.browser_approval,
-        "filesystem" => {
-            let action = arguments
-                .get("action")
-                .and_then(|v| v.as_str())
-                .unwrap_or("");
-            if action == "write_file" {
-                cfg.security.filesystem_write_approval
-            } else {
-                ApprovalMode::Auto
-            }
-        }
-        _ => match risk {
-            RiskLevel::Low => ApprovalMode::Auto,
-            RiskLevel::Medium => ApprovalMode::Ai,
-            RiskLevel::High | RiskLevel::Critical => ApprovalMode::Human,
-        },
-    }
+    approval_rules::decide(cfg, tool_name, risk, arguments).mode
+}
+
+/// Run a tool call with its conversation as the owner of any shell sessions it uses, so
+/// one conversation can't run commands in another's.
+async fn execute_tool(
+    tool: &dyn Tool,
+    arguments: serde_json::Value,
+    cancel: &CancellationToken,
+) -> std::result::Result<serde_json::Value, ToolError> {
+    let owner = CONVERSATION
+        .try_with(|o| format!("{}:{}", o.channel_id, o.sender_id))
+        .unwrap_or_default();
+    os_tools::SHELL_OWNER
+        .scope(owner, tool.execute(arguments, cancel))
+        .await
 }
 
 fn effective_risk_level(tool: &dyn Tool, arguments: &serde_json::Value) -> RiskLevel {
     let base = tool.spec().risk_level;
-    if tool.spec().name != "filesystem" {
-        return base;
-    }
     let action = arguments
         .get("action")
         .and_then(|v| v.as_str())
         .unwrap_or("");
-    match action {
-        "read_file" | "list_dir" | "search_files" => RiskLevel::Low,
-        "write_file" => RiskLevel::Medium,
+    match (tool.spec().name.as_str(), action) {
+        ("filesystem", "read_file" | "list_dir" | "search_files") => RiskLevel::Low,
+        ("filesystem", "write_file") => RiskLevel::Medium,
+        // Managing sessions runs nothing; commands in them are gated like any other.
+        ("shell.execute", "session_list" | "session_close") => RiskLevel::Low,
+        ("todoist", "list") => RiskLevel::Low,
+        ("email", action) if os_tools::EmailActions::is_read_only(action) => RiskLevel::Low,
+        // Drafts are never sent, but an attached local file sits in the mailbox once saved.
+        ("email", "draft_reply")
+            if arguments
+                .get("attachments")
+                .and_then(|v| v.as_array())
+                .is_some_and(|a| !a.is_empty()) =>
+        {
+            RiskLevel::High
+        }
+        ("email", "draft_reply" | "download_attachment") => RiskLevel::Medium,
         _ => base,
     }
 }
 
+/// The reply with its readable reasoning quoted above it, for `/think`.
+fn with_reasoning(reasoning: &[os_llm::ReasoningBlock], content: String) -> String {
+    let thinking: Vec<String> = reasoning
+        .iter()
+        .filter_map(|block| match block {
+            os_llm::ReasoningBlock::Thinking { thinking, .. } => Some(
+                thinking
+                    .lines()
+                    .map(|line| format!("> {line}"))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+            os_llm::ReasoningBlock::Redacted { .. } => None,
+        })
+        .collect();
+    if thinking.is_empty() {
+        return content;
+    }
+    format!("{}\n\n{content}", thinking.join("\n>\n"))
+}
+
 #[tracing::instrument(level = "debug", skip_all)]
 async fn wait_for_action_status(
     project_db: &dyn ProjectDb,
@@ -0,0 +1,546 @@
+//! Content-addressed attachment store.
+//!
+//! Blobs live under `<data_dir>/attachments/blobs/<aa>/<sha256>` and are shared by every
+//! attachment record with the same content. A small SQLite index keeps the per-record
+//! metadata (origin channel/message, mime, size) and a reference count per blob; the blob
+//! file is removed once its last reference is released.
+//!
+//! Attachment URLs come from channels, so fetching them is gated: http(s) URLs go through
+//! the network policy (no private or loopback hosts by default), and local paths are only
+//! read for a channel whose adapter copies attachments into a directory of its own (see
+//! [`AttachmentStore::with_local_files`]), and only from inside that directory.
+//!
+//! See: specifications/openshell/implementation_v0_1_0.md
+
+use crate::sqlite::SqlitePool;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use os_channels::Attachment;
+use os_tools::NetworkPolicy;
+use rusqlite::{params, OptionalExtension};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+const REDIRECTS_MAX: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttachmentDirection {
+    Inbound,
+    Outbound,
+}
+
+impl AttachmentDirection {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Inbound => "inbound",
+            Self::Outbound => "outbound",
+        }
+    }
+
+    fn parse(s: &str) -> Self {
+        match s {
+            "outbound" => Self::Outbound,
+            _ => Self::Inbound,
+        }
+    }
+}
+
+/// Where an attachment came from (or was sent to).
+#[derive(Debug, Clone)]
+pub struct AttachmentOrigin {
+    pub direction: AttachmentDirection,
+    pub channel_id: String,
+    pub message_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StoredAttachment {
+    pub id: Uuid,
+    pub sha256: String,
+    pub name: String,
+    pub content_type: String,
+    pub size_bytes: u64,
+    pub direction: AttachmentDirection,
+    pub channel_id: String,
+    pub message_id: Option<String>,
+    pub source_url: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AttachmentStats {
+    pub attachments: u64,
+    pub blobs: u64,
+    pub blob_bytes: u64,
+}
+
+pub struct AttachmentStore {
+    root: PathBuf,
+    max_bytes: u64,
+    http: reqwest::Client,
+    policy: NetworkPolicy,
+    /// `(channel_id, dir)`: the channel's attachments may be read from files under `dir`.
+    local_files: Vec<(String, PathBuf)>,
+    db: Arc<SqlitePool>,
+}
+
+impl AttachmentStore {
+    pub fn open(root: &Path, max_bytes: u64) -> Result<Self> {
+        std::fs::create_dir_all(root.join("blobs"))?;
+        let db = SqlitePool::open(
+            &root.join("index.db"),
+            "CREATE TABLE IF NOT EXISTS attachment_blobs (
+                sha256 TEXT PRIMARY KEY,
+                size_bytes INTEGER NOT NULL,
+                ref_count INTEGER NOT NULL,
+                created_at TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS attachments (
+                id TEXT PRIMARY KEY,
+                sha256 TEXT NOT NULL REFERENCES attachment_blobs(sha256),
+                name TEXT NOT NULL,
+                content_type TEXT NOT NULL,
+                size_bytes INTEGER NOT NULL,
+                direction TEXT NOT NULL,
+                channel_id TEXT NOT NULL,
+                message_id TEXT,
+                source_url TEXT,
+                created_at TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS attachments_sha256 ON attachments(sha256);
+            CREATE INDEX IF NOT EXISTS attachments_message ON attachments(channel_id, message_id);",
+        )?;
+
+        let policy = NetworkPolicy::default();
+        Ok(Self {
+            root: root.to_path_buf(),
+            max_bytes,
+            http: http_client(&policy),
+            policy,
+            local_files: Vec::new(),
+            db: Arc::new(db),
+        })
+    }
+
+    /// Which http(s) URLs may be fetched and which content types stored.
+    pub fn with_network_policy(mut self, policy: NetworkPolicy) -> Self {
+        self.http = http_client(&policy);
+        self.policy = policy;
+        self
+    }
+
+    /// Accept `file://` attachments from `channel_id` when they are inside `dir`, where
+    /// its adapter copies them. Other channels' local paths are refused.
+    pub fn with_local_files(mut self, channel_id: &str, dir: impl AsRef<Path>) -> Self {
+        self.local_files
+            .push((channel_id.to_string(), dir.as_ref().to_path_buf()));
+        self
+    }
+
+    fn blob_path(&self, sha256: &str) -> PathBuf {
+        self.root.join("blobs").join(&sha256[..2]).join(sha256)
+    }
+
+    pub fn sqlite_pool(&self) -> Arc<SqlitePool> {
+        self.db.clone()
+    }
+
+    /// Fetch the attachment's content (http(s), or a local file the channel may hand over)
+    /// and store it.
+    #[tracing::instrument(level = "debug", skip_all, fields(name = %attachment.name))]
+    pub async fn ingest(
+        &self,
+        attachment: &Attachment,
+        origin: AttachmentOrigin,
+    ) -> Result<StoredAttachment> {
+        let (bytes, source_url) = match attachment.inline_bytes() {
+            Some(bytes) => (bytes, None),
+            None => (
+                self.fetch(&attachment.url, &origin).await?,
+                Some(attachment.url.as_str()),
+            ),
+        };
+        self.put(
+            &bytes,
+            &attachment.name,
+            &attachment.content_type,
+            source_url,
+            origin,
+        )
+        .await
+    }
+
+    async fn fetch(&self, url: &str, origin: &AttachmentOrigin) -> Result<Vec<u8>> {
+        let bytes = if url.starts_with("http://") || url.starts_with("https://") {
+            let resp = self.get(url).await?;
+            if !resp.status().is_success() {
+                return Err(anyhow::anyhow!("fetch {url}: http {}", resp.status()));
+            }
+            let content_type = resp
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default();
+            self.policy.check_content_type(content_type)?;
+            if resp.content_length().unwrap_or(0) > self.max_bytes {
+                return Err(anyhow::anyhow!(
+                    "attachment exceeds {} bytes",
+                    self.max_bytes
+                ));
+            }
+            resp.bytes().await?.to_vec()
+        } else {
+            let path = self.local_file(url, origin).await?;
+            tokio::fs::read(&path)
+                .await
+                .map_err(|e| anyhow::anyhow!("read {}: {e}", path.display()))?
+        };
+        if bytes.len() as u64 > self.max_bytes {
+            return Err(anyhow::anyhow!(
+                "attachment exceeds {} bytes",
+                self.max_bytes
+            ));
+        }
+        Ok(bytes)
+    }
+
+    /// GET `url`, following redirects by hand so each hop is checked against the policy.
+    async fn get(&self, url: &str) -> Result<reqwest::Response> {
+        let mut url = self.policy.check_url(url).await?;
+        for _ in 0..=REDIRECTS_MAX {
+            let resp = self.http.get(url.clone()).send().await?;
+            let location = resp
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|v| v.to_str().ok());
+            let next = match location {
+                Some(location) if resp.status().is_redirection() => url.join(location)?,
+                _ => return Ok(resp),
+            };
+            url = self.policy.check_url(next.as_str()).await?;
+        }
+        Err(anyhow::anyhow!("too many redirects"))
+    }
+
+    /// The file `url` names, if `origin`'s channel may hand over local files and it is
+    /// inside that channel's directory once links are resolved.
+    async fn local_file(&self, url: &str, origin: &AttachmentOrigin) -> Result<PathBuf> {
+        let refused = || {
+            anyhow::anyhow!(
+                "{} attachments can't be read from local paths",
+                origin.channel_id
+            )
+        };
+        let Some((_, dir)) = self
+            .local_files
+            .iter()
+            .find(|(channel, _)| *channel == origin.channel_id)
+            .filter(|_| origin.direction == AttachmentDirection::Inbound)
+        else {
+            return Err(refused());
+        };
+        let path = url.strip_prefix("file://").ok_or_else(refused)?;
+        let path = tokio::fs::canonicalize(path)
+            .await
+            .map_err(|e| anyhow::anyhow!("read {path}: {e}"))?;
+        let dir = tokio::fs::canonicalize(dir).await.map_err(|_| refused())?;
+        if !path.starts_with(&dir) {
+            return Err(refused());
+        }
+        Ok(path)
+    }
+
+    /// Store `bytes`, reusing the existing blob when the content is already known.
+    pub async fn put(
+        &self,
+        bytes: &[u8],
+        name: &str,
+        content_type: &str,
+        source_url: Option<&str>,
+        origin: AttachmentOrigin,
+    ) -> Result<StoredAttachment> {
+        let sha256 = hex::encode(Sha256::digest(bytes));
+        let path = self.blob_path(&sha256);
+        write_blob_if_missing(&path, bytes).await?;
+
+        let record = StoredAttachment {
+            id: Uuid::new_v4(),
+            sha256,
+            name: name.to_string(),
+            content_type: content_type.to_string(),
+            size_bytes: bytes.len() as u64,
+            direction: origin.direction,
+            channel_id: origin.channel_id,
+            message_id: origin.message_id,
+            source_url: source_url.map(|s| s.to_string()),
+            created_at: Utc::now(),
+        };
+
+        {
+            let mut conn = self.db.write()?;
+            let tx = conn.transaction()?;
+            tx.execute(
+                "INSERT INTO attachment_blobs (sha256, size_bytes, ref_count, created_at)
+                 VALUES (?1, ?2, 1, ?3)
+                 ON CONFLICT(sha256) DO UPDATE SET ref_count = ref_count + 1",
+                params![
+                    record.sha256,
+                    record.size_bytes as i64,
+                    record.created_at.to_rfc3339()
+                ],
+            )?;
+            tx.execute(
+                "INSERT INTO attachments
+                 (id, sha256, name, content_type, size_bytes, direction, channel_id, message_id, source_url, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    record.id.to_string(),
+                    record.sha256,
+                    record.name,
+                    record.content_type,
+                    record.size_bytes as i64,
+                    record.direction.as_str(),
+                    record.channel_id,
+                    record.message_id,
+                    record.source_url,
+                    record.created_at.to_rfc3339(),
+                ],
+            )?;
+            tx.commit()?;
+        }
+        // A concurrent release may have removed the blob between the write above and our
+        // reference being recorded.
+        write_blob_if_missing(&path, bytes).await?;
+        Ok(record)
+    }
+
+    pub fn get(&self, id: Uuid) -> Result<Option<StoredAttachment>> {
+        let conn = self.db.read()?;
+        let record = conn
+            .query_row(
+                &format!("{SELECT_ATTACHMENT} WHERE id = ?1"),
+                params![id.to_string()],
+                row_to_attachment,
+            )
+            .optional()?;
+        Ok(record)
+    }
+
+    pub fn list(&self, limit: usize) -> Result<Vec<StoredAttachment>> {
+        let conn = self.db.read()?;
+        let mut stmt = conn.prepare(&format!(
+            "{SELECT_ATTACHMENT} ORDER BY created_at DESC LIMIT ?1"
+        ))?;
+        let rows = stmt
+            .query_map(params![limit as i64], row_to_attachment)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    pub fn stats(&self) -> Result<AttachmentStats> {
+        let conn = self.db.read()?;
+        let attachments: i64 =
+            conn.query_row("SELECT COUNT(*) FROM attachments", [], |r| r.get(0))?;
+        let (blobs, blob_bytes): (i64, i64) = conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(size_bytes), 0) FROM attachment_blobs",
+            [],
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        )?;
+        Ok(AttachmentStats {
+            attachments: attachments as u64,
+            blobs: blobs as u64,
+            blob_bytes: blob_bytes as u64,
+        })
+    }
+
+    pub async fn read(&self, id: Uuid) -> Result<Option<(StoredAttachment, Vec<u8>)>> {
+        let Some(record) = self.get(id)? else {
+            return Ok(None);
+        };
+        let bytes = tokio::fs::read(self.blob_path(&record.sha256)).await?;
+        Ok(Some((record, bytes)))
+    }
+
+    /// Drop one attachment record; the blob is deleted when no records reference it.
+    pub async fn release(&self, id: Uuid) -> Result<bool> {
+        let orphaned = {
+            let mut conn = self.db.write()?;
+            let tx = conn.transaction()?;
+            let sha256: Option<String> = tx
+                .query_row(
+                    "SELECT sha256 FROM attachments WHERE id = ?1",
+                    params![id.to_string()],
+                    |r| r.get(0),
+                )
+                .optional()?;
+            let Some(sha256) = sha256 else {
+                return Ok(false);
+            };
+            tx.execute(
+                "DELETE FROM attachments WHERE id = ?1",
+                params![id.to_string()],
+            )?;
+            tx.execute(
+                "UPDATE attachment_blobs SET ref_count = ref_count - 1 WHERE sha256 = ?1",
+                params![sha256],
+            )?;
+            let deleted = tx.execute(
+                "DELETE FROM attachment_blobs WHERE sha256 = ?1 AND ref_count <= 0",
+                params![sha256],
+            )?;
+            tx.commit()?;
+            (deleted > 0).then_some(sha256)
+        };
+
+        if let Some(sha256) = orphaned {
+            if let Err(e) = tokio::fs::remove_file(self.blob_path(&sha256)).await {
+                tracing::warn!(%e, %sha256, "failed to remove orphaned attachment blob");
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Connects only where `policy` allows and follows no redirects; `get` checks each hop.
+fn http_client(policy: &NetworkPolicy) -> reqwest::Client {
+    policy.client(Duration::from_secs(60)).unwrap_or_else(|e| {
+        tracing::warn!(%e, "reqwest client build failed; falling back to default client");
+        reqwest::Client::new()
+    })
+}
+
+async fn write_blob_if_missing(path: &Path, bytes: &[u8]) -> Result<()> {
+    if tokio::fs::try_exists(path).await.unwrap_or(false) {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    // Write-then-rename so a crash never leaves a truncated blob under its hash.
+    let tmp = path.with_extension(format!("tmp-{}", Uuid::new_v4()));
+    tokio::fs::write(&tmp, bytes).await?;
+    tokio::fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
+const SELECT_ATTACHMENT: &str = "SELECT id, sha256, name, content_type, size_bytes, direction, \
+     channel_id, message_id, source_url, created_at FROM attachments";
+
+fn row_to_attachment(row: &rusqlite::Row<'_>) -> rusqlite::Result<StoredAttachment> {
+    let id: String = row.get(0)?;
+    let size_bytes: i64 = row.get(4)?;
+    let direction: String = row.get(5)?;
+    let created_at: String = row.get(9)?;
+    Ok(StoredAttachment {
+        id: Uuid::parse_str(&id).unwrap_or_default(),
+        sha256: row.get(1)?,
+        name: row.get(2)?,
+        content_type: row.get(3)?,
+        size_bytes: size_bytes.max(0) as u64,
+        direction: AttachmentDirection::parse(&direction),
+        channel_id: row.get(6)?,
+        message_id: row.get(7)?,
+        source_url: row.get(8)?,
+        created_at: DateTime::parse_from_rfc3339(&created_at)
+            .map(|t| t.with_timezone(&Utc))
+            .unwrap_or_else(|_| Utc::now()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn origin() -> AttachmentOrigin {
+        AttachmentOrigin {
+            direction: AttachmentDirection::Inbound,
+            channel_id: "webchat".to_string(),
+            message_id: Some("m1".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn channel_urls_cant_read_arbitrary_files_or_internal_hosts() {
+        let tmp = std::env::temp_dir().join(format!("opencraw-attachments-{}", Uuid::new_v4()));
+        let copies = tmp.join("imessage");
+        std::fs::create_dir_all(&copies).unwrap();
+        std::fs::write(copies.join("photo.txt"), b"copied").unwrap();
+        std::fs::write(tmp.join("secret.txt"), b"secret").unwrap();
+        let store = AttachmentStore::open(&tmp.join("store"), 1024)
+            .unwrap()
+            .with_local_files("imessage", &copies);
+        let file = |path: PathBuf| Attachment {
+            name: "f.txt".to_string(),
+            content_type: "text/plain".to_string(),
+            url: format!("file://{}", path.display()),
+        };
+        let imessage = AttachmentOrigin {
+            channel_id: "imessage".to_string(),
+            ..origin()
+        };
+
+        let stored = store
+            .ingest(&file(copies.join("photo.txt")), imessage.clone())
+            .await
+            .unwrap();
+        assert_eq!(stored.size_bytes, 6);
+        // Another channel can't name the same file, nor the adapter's channel one outside.
+        assert!(store
+            .ingest(&file(copies.join("photo.txt")), origin())
+            .await
+            .is_err());
+        assert!(store
+            .ingest(&file(copies.join("../secret.txt")), imessage.clone())
+            .await
+            .is_err());
+        let plain_path = Attachment {
+            url: tmp.join("secret.txt").display().to_string(),
+            ..file(PathBuf::new())
+        };
+        assert!(store.ingest(&plain_path, imessage).await.is_err());
+
+        let internal = Attachment {
+            url: "http://127.0.0.1:9/admin".to_string(),
+            ..file(PathBuf::new())
+        };
+        let err = store.ingest(&internal, origin()).await.unwrap_err();
+        assert!(err.to_string().contains("network policy"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn identical_content_shares_one_blob_until_released() {
+        let tmp = std::env::temp_dir().join(format!("opencraw-attachments-{}", Uuid::new_v4()));
+        let store = AttachmentStore::open(&tmp, 1024).unwrap();
+
+        let a = store
+            .put(b"hello", "a.txt", "text/plain", None, origin())
+            .await
+            .unwrap();
+        let b = store
+            .put(b"hello", "b.txt", "text/plain", None, origin())
+            .await
+            .unwrap();
+        assert_eq!(a.sha256, b.sha256);
+        assert_ne!(a.id, b.id);
+
+        let stats = store.stats().unwrap();
+        assert_eq!(stats.attachments, 2);
+        assert_eq!(stats.blobs, 1);
+        assert_eq!(stats.blob_bytes, 5);
+
+        let blob = store.blob_path(&a.sha256);
+        assert!(store.release(a.id).await.unwrap());
+        assert!(blob.exists());
+        assert!(store.release(b.id).await.unwrap());
+        assert!(!blob.exists());
+        assert!(!store.release(b.id).await.unwrap());
+        assert_eq!(store.stats().unwrap().blobs, 0);
+
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+}
@@ -0,0 +1,165 @@
+//! Audit log of security-relevant tool events.
+//!
+//! When a tool refuses a call on policy grounds (a browser URL pointing into the LAN, a
+//! host on the denylist), or when untrusted tool output looks like a prompt injection, the
+//! event is recorded here with the conversation it came from, appended to `audit.jsonl`,
+//! and served by `GET /api/v1/os/audit`. With `[encryption]` on, each line is sealed on
+//! its own so the file stays appendable.
+
+use crate::encryption::DataCipher;
+use crate::tasks::{ConversationOrigin, CONVERSATION};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+/// Events kept in memory for the API; all of them are appended to `audit.jsonl`.
+const RECENT_EVENTS_MAX: usize = 200;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditKind {
+    PolicyViolation,
+    InjectionSuspected,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEvent {
+    pub id: Uuid,
+    pub at: DateTime<Utc>,
+    pub kind: AuditKind,
+    pub tool: String,
+    pub detail: String,
+    /// Absent when the tool ran outside a conversation (e.g. a background task).
+    pub origin: Option<ConversationOrigin>,
+}
+
+pub struct AuditLog {
+    path: PathBuf,
+    recent: Mutex<VecDeque<AuditEvent>>,
+    cipher: Option<Arc<DataCipher>>,
+}
+
+impl AuditLog {
+    pub fn new(data_dir: PathBuf) -> Self {
+        Self {
+            path: data_dir.join("audit.jsonl"),
+            recent: Mutex::new(VecDeque::new()),
+            cipher: None,
+        }
+    }
+
+    pub fn with_cipher(mut self, cipher: Option<Arc<DataCipher>>) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    /// Newest first.
+    pub fn recent(&self) -> Vec<AuditEvent> {
+        self.recent
+            .lock()
+            .map(|r| r.iter().rev().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub async fn policy_violation(&self, tool: &str, detail: &str) {
+        let event = AuditEvent {
+            id: Uuid::new_v4(),
+            at: Utc::now(),
+            kind: AuditKind::PolicyViolation,
+            tool: tool.to_string(),
+            detail: detail.to_string(),
+            origin: CONVERSATION.try_with(|o| o.clone()).ok(),
+        };
+        tracing::warn!(%tool, %detail, "tool call blocked by policy");
+        self.record(&event).await;
+    }
+
+    /// Untrusted tool output looked like instructions to the model.
+    pub async fn injection_suspected(&self, tool: &str, detail: &str) {
+        let event = AuditEvent {
+            id: Uuid::new_v4(),
+            at: Utc::now(),
+            kind: AuditKind::InjectionSuspected,
+            tool: tool.to_string(),
+            detail: detail.to_string(),
+            origin: CONVERSATION.try_with(|o| o.clone()).ok(),
+        };
+        tracing::warn!(%tool, %detail, "possible prompt injection in tool output");
+        self.record(&event).await;
+    }
+
+    async fn record(&self, event: &AuditEvent) {
+        if let Ok(mut recent) = self.recent.lock() {
+            recent.push_back(event.clone());
+            while recent.len() > RECENT_EVENTS_MAX {
+                recent.pop_front();
+            }
+        }
+
+        let line = match serde_json::to_string(event) {
+            Ok(v) => v,
+            Err(e) => {
+                tracing::warn!(%e, "failed to serialize audit event");
+                return;
+            }
+        };
+        let line = match self.cipher.as_deref().map(|c| c.seal(&line)) {
+            None => line + "\n",
+            Some(Ok(sealed)) => sealed + "\n",
+            Some(Err(e)) => {
+                tracing::warn!(%e, "failed to encrypt audit event");
+                return;
+            }
+        };
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await;
+        let res = match file {
+            Ok(mut f) => match f.write_all(line.as_bytes()).await {
+                Ok(()) => f.flush().await,
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        if let Err(e) = res {
+            tracing::warn!(%e, path = %self.path.display(), "failed to write audit event");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn violation_is_recorded_with_conversation() {
+        let tmp = std::env::temp_dir().join(format!("opencraw-audit-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&tmp).unwrap();
+        let audit = AuditLog::new(tmp.clone());
+        let origin = ConversationOrigin {
+            channel_id: "telegram".to_string(),
+            sender_id: "42".to_string(),
+            recipient: "42".to_string(),
+        };
+
+        CONVERSATION
+            .scope(
+                origin,
+                audit.policy_violation("browser", "network policy: 10.0.0.1 is internal"),
+            )
+            .await;
+
+        let events = audit.recent();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].origin.as_ref().unwrap().sender_id, "42");
+        let raw = std::fs::read_to_string(tmp.join("audit.jsonl")).unwrap();
+        assert!(raw.contains("\"kind\":\"policy_violation\""));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+}
@@ -0,0 +1,1079 @@
+//! Automation rules: when something happens and conditions hold, do something.
+//!
+//! Rules live in `automation.rules_path` (a TOML file of `[[rule]]` tables) and can be
+//! edited through `/api/v1/os/automations`, which rewrites the file. A rule has:
+//!
+//! - a trigger: an inbound message, a schedule (`every_minutes`, or daily `at` a local
+//!   `HH:MM`), a webhook (`POST /api/v1/os/automations/{id}/trigger`), or a channel event
+//!   (a reaction or edit);
+//! - conditions, all of which must hold: the sender, a regex over the content, and a
+//!   local time window (`22:00-07:00` wraps past midnight);
+//! - actions, run in order: a prompt for the assistant, fed into a conversation like a
+//!   message from its owner; a tool call, run only if approval rules auto-approve it;
+//!   forwarding the message to another conversation; or the daily briefing (see
+//!   `briefing`), usually on a morning schedule.
+//!
+//! Prompts, forward templates and `deliver_to` are templates. A webhook's JSON body is
+//! available in them by path, e.g. `{{ payload.pull_request.title }}`, so a rule can turn
+//! a GitHub or Grafana payload into a readable brief and pick where it goes.
+//!
+//! Only paired senders trigger rules, and messages a rule sends the assistant never do.
+//! Those wait in the gateway's queue at `inbound.priority.automation` unless the rule
+//! sets a `priority` of its own, and run as the rule's `persona` if it names one.
+//!
+//! A rule whose action fails stops there; the error is kept as the rule's `last_error`
+//! and raised as an `automation.failed` webhook.
+
+use crate::assistant::AssistantAgent;
+use crate::briefing::{self, MessageLog};
+use crate::config::{expand_home, split_target, AutomationConfig, Priority};
+use crate::outbox::Outbox;
+use crate::template::{self, Escape, Vars};
+use crate::webhooks::{self, Webhooks};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, NaiveTime, Utc};
+use dashmap::DashMap;
+use os_channels::{InboundMessage, InboundMessageKind, OutboundMessage};
+use os_tools::{CancellationToken, Tool};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock, RwLock, Weak};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// Tags messages a rule feeds to the assistant, for logs and the archive.
+const SOURCE: &str = "automation";
+/// Variables available in prompts, forward templates and `deliver_to`.
+pub const TEMPLATE_VARS: &[&str] = &[
+    "rule", "channel", "sender", "content", "today", "weekday", "now",
+];
+/// What webhook rules also get: the body, and each field in it by path.
+const PAYLOAD_VARS: &[&str] = &["payload", "payload.*"];
+const FORWARD_TEMPLATE: &str = "{{ sender }} on {{ channel }}: {{ content }}";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutomationRule {
+    pub id: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    pub trigger: Trigger,
+    #[serde(default)]
+    pub conditions: Conditions,
+    pub actions: Vec<Action>,
+    /// Queue priority of the prompts it feeds the assistant, instead of
+    /// `inbound.priority.automation`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<Priority>,
+    /// Persona the assistant answers its prompts as, instead of the conversation's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub persona: Option<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Trigger {
+    /// An inbound message on one of `channels`, or any channel when empty.
+    Message {
+        #[serde(default)]
+        channels: Vec<String>,
+    },
+    /// Every `every_minutes`, or daily `at` a local `HH:MM`; exactly one of them.
+    Schedule {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        every_minutes: Option<u64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        at: Option<String>,
+    },
+    /// A `POST` to the rule's trigger route; the body's `content` is the message.
+    Webhook,
+    /// A reaction or edit on one of `channels`, or any channel when empty.
+    ChannelEvent {
+        events: Vec<InboundMessageKind>,
+        #[serde(default)]
+        channels: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Conditions {
+    /// Sender ids; empty allows anyone.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub senders: Vec<String>,
+    /// A regex the content must match.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    /// Local `HH:MM-HH:MM`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub between: Option<String>,
+    /// `content`, compiled when the rule is validated.
+    #[serde(skip)]
+    content_regex: OnceLock<Regex>,
+}
+
+/// Why a rule last stopped short.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleFailure {
+    pub failed_at: DateTime<Utc>,
+    /// Index of the action that failed; the ones after it didn't run.
+    pub action: usize,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    /// Run the assistant on `prompt` in the `deliver_to` conversation, or the one that
+    /// triggered the rule.
+    Prompt {
+        prompt: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        deliver_to: Option<String>,
+    },
+    /// Call a tool directly; its result is sent to `deliver_to` if set.
+    Tool {
+        tool: String,
+        #[serde(default = "empty_arguments")]
+        arguments: serde_json::Value,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        deliver_to: Option<String>,
+    },
+    /// Send the message on to `deliver_to`, written with `template`.
+    Forward {
+        deliver_to: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        template: Option<String>,
+    },
+    /// Have the assistant write the briefing from `[automation.briefing]` in the
+    /// `deliver_to` conversation.
+    Briefing { deliver_to: String },
+}
+
+fn empty_arguments() -> serde_json::Value {
+    json!({})
+}
+
+/// What fired a rule. Scheduled runs have no conversation.
+#[derive(Debug, Clone)]
+struct Event {
+    channel_id: Option<String>,
+    sender_id: Option<String>,
+    /// Where replies to the triggering conversation go.
+    recipient: Option<String>,
+    content: String,
+    /// A webhook's JSON body.
+    payload: Option<serde_json::Value>,
+}
+
+impl Event {
+    fn from_inbound(inbound: &InboundMessage) -> Self {
+        Self {
+            channel_id: Some(inbound.channel_id.clone()),
+            sender_id: Some(inbound.sender_id.clone()),
+            recipient: Some(
+                inbound
+                    .thread_id
+                    .clone()
+                    .unwrap_or_else(|| inbound.sender_id.clone()),
+            ),
+            content: inbound.content.clone(),
+            payload: None,
+        }
+    }
+
+    fn scheduled() -> Self {
+        Self {
+            channel_id: None,
+            sender_id: None,
+            recipient: None,
+            content: String::new(),
+            payload: None,
+        }
+    }
+
+    /// The body's `content`, if it's a string, is the message; otherwise the whole body.
+    fn webhook(payload: serde_json::Value) -> Self {
+        let content = match payload.get("content").and_then(|v| v.as_str()) {
+            Some(content) => content.to_string(),
+            None if payload.is_null() => String::new(),
+            None => payload.to_string(),
+        };
+        Self {
+            payload: Some(payload),
+            content,
+            ..Self::scheduled()
+        }
+    }
+}
+
+impl AutomationRule {
+    pub fn validate(&self) -> Result<()> {
+        let id = &self.id;
+        if id.is_empty()
+            || id.len() > 64
+            || !id
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+        {
+            return Err(anyhow!(
+                "rule id {id:?} must be 1-64 characters of a-z, 0-9, - and _"
+            ));
+        }
+        let conversational = matches!(
+            self.trigger,
+            Trigger::Message { .. } | Trigger::ChannelEvent { .. }
+        );
+        match &self.trigger {
+            Trigger::Schedule { every_minutes, at } => match (every_minutes, at) {
+                (Some(0), None) => return Err(anyhow!("rule {id}: every_minutes must be > 0")),
+                (Some(_), None) => {}
+                (None, Some(at)) => {
+                    parse_time(at).ok_or_else(|| anyhow!("rule {id}: at must be HH:MM"))?;
+                }
+                _ => {
+                    return Err(anyhow!(
+                        "rule {id}: a schedule needs one of every_minutes or at"
+                    ))
+                }
+            },
+            Trigger::ChannelEvent { events, .. } => {
+                if events.is_empty() || events.contains(&InboundMessageKind::Message) {
+                    return Err(anyhow!(
+                        "rule {id}: channel_event triggers on reaction or edit events"
+                    ));
+                }
+            }
+            Trigger::Message { .. } | Trigger::Webhook => {}
+        }
+
+        if !conversational && !self.conditions.senders.is_empty() {
+            return Err(anyhow!(
+                "rule {id}: conditions.senders needs a message or channel_event trigger"
+            ));
+        }
+        if let Some(pattern) = self.conditions.content.as_deref() {
+            let re =
+                Regex::new(pattern).map_err(|e| anyhow!("rule {id}: conditions.content: {e}"))?;
+            let _ = self.conditions.content_regex.set(re);
+        }
+        if let Some(window) = self.conditions.between.as_deref() {
+            parse_window(window)
+                .ok_or_else(|| anyhow!("rule {id}: conditions.between must be HH:MM-HH:MM"))?;
+        }
+
+        if self.actions.is_empty() {
+            return Err(anyhow!("rule {id} has no actions"));
+        }
+        let mut vars = TEMPLATE_VARS.to_vec();
+        if matches!(self.trigger, Trigger::Webhook) {
+            vars.extend(PAYLOAD_VARS);
+        }
+        for action in &self.actions {
+            let (deliver_to, template) = match action {
+                Action::Prompt { prompt, deliver_to } => {
+                    if deliver_to.is_none() && !conversational {
+                        return Err(anyhow!(
+                            "rule {id}: a prompt needs deliver_to unless a message triggers it"
+                        ));
+                    }
+                    (deliver_to.as_deref(), Some(prompt.as_str()))
+                }
+                Action::Tool {
+                    tool,
+                    arguments,
+                    deliver_to,
+                } => {
+                    if tool.trim().is_empty() {
+                        return Err(anyhow!("rule {id}: tool actions need a tool"));
+                    }
+                    if !arguments.is_object() {
+                        return Err(anyhow!("rule {id}: tool arguments must be a table"));
+                    }
+                    (deliver_to.as_deref(), None)
+                }
+                Action::Forward {
+                    deliver_to,
+                    template,
+                } => (Some(deliver_to.as_str()), template.as_deref()),
+                Action::Briefing { deliver_to } => (Some(deliver_to.as_str()), None),
+            };
+            match deliver_to {
+                Some(target) if target.contains("{{") => template::check(target, &vars)
+                    .map_err(|e| anyhow!("rule {id}: deliver_to: {e}"))?,
+                Some(target) => {
+                    split_target(target).ok_or_else(|| {
+                        anyhow!("rule {id}: deliver_to must be channel:recipient, got {target:?}")
+                    })?;
+                }
+                None => {}
+            }
+            if let Some(source) = template {
+                template::check(source, &vars).map_err(|e| anyhow!("rule {id}: {e}"))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn triggered_by(&self, inbound: &InboundMessage) -> bool {
+        let on =
+            |channels: &[String]| channels.is_empty() || channels.contains(&inbound.channel_id);
+        match &self.trigger {
+            Trigger::Message { channels } => {
+                inbound.kind == InboundMessageKind::Message && on(channels)
+            }
+            Trigger::ChannelEvent { events, channels } => {
+                events.contains(&inbound.kind) && on(channels)
+            }
+            Trigger::Schedule { .. } | Trigger::Webhook => false,
+        }
+    }
+}
+
+impl Conditions {
+    fn hold(&self, event: &Event, now: NaiveTime) -> bool {
+        if !self.senders.is_empty()
+            && !event
+                .sender_id
+                .as_ref()
+                .is_some_and(|s| self.senders.contains(s))
+        {
+            return false;
+        }
+        if let Some(pattern) = self.content.as_deref() {
+            // Compiled by `validate`, which every stored rule has been through.
+            let matched = match self.content_regex.get() {
+                Some(re) => re.is_match(&event.content),
+                None => Regex::new(pattern).is_ok_and(|re| re.is_match(&event.content)),
+            };
+            if !matched {
+                return false;
+            }
+        }
+        match self.between.as_deref().and_then(parse_window) {
+            Some((start, end)) if start <= end => start <= now && now < end,
+            Some((start, end)) => now >= start || now < end,
+            None => true,
+        }
+    }
+}
+
+fn parse_time(s: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(s.trim(), "%H:%M").ok()
+}
+
+fn parse_window(s: &str) -> Option<(NaiveTime, NaiveTime)> {
+    let (start, end) = s.split_once('-')?;
+    Some((parse_time(start)?, parse_time(end)?))
+}
+
+/// The first scheduled run after `after`.
+fn next_run(trigger: &Trigger, after: DateTime<Local>) -> Option<DateTime<Local>> {
+    match trigger {
+        Trigger::Schedule {
+            every_minutes: Some(minutes),
+            ..
+        } => Some(after + chrono::Duration::minutes(*minutes as i64)),
+        Trigger::Schedule { at: Some(at), .. } => {
+            let at = parse_time(at)?;
+            let mut day = after.date_naive();
+            loop {
+                // A time skipped by a DST change runs the next day.
+                if let Some(run) = day.and_time(at).and_local_timezone(Local).earliest() {
+                    if run > after {
+                        return Some(run);
+                    }
+                }
+                day = day.succ_opt()?;
+            }
+        }
+        _ => None,
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RulesFile {
+    #[serde(default, rename = "rule")]
+    rules: Vec<AutomationRule>,
+}
+
+pub struct AutomationEngine {
+    cfg: AutomationConfig,
+    path: PathBuf,
+    rules: RwLock<Vec<AutomationRule>>,
+    /// Serializes edits, so the file always holds the latest rules.
+    writes: tokio::sync::Mutex<()>,
+    /// When each scheduled rule last ran, or was first seen.
+    last_run: DashMap<String, DateTime<Local>>,
+    outbox: Arc<Outbox>,
+    inbound_tx: mpsc::Sender<InboundMessage>,
+    assistant: OnceLock<Weak<AssistantAgent>>,
+    /// Recent messages from paired senders, for the briefing.
+    seen: MessageLog,
+    /// Each rule's latest failure, by rule id.
+    failures: DashMap<String, RuleFailure>,
+    webhooks: Option<Arc<Webhooks>>,
+    /// Configured persona names, which rules may ask for.
+    personas: HashSet<String>,
+    /// The `email` tool, which the briefing reads unread mail through.
+    email: Option<Arc<dyn Tool>>,
+}
+
+impl AutomationEngine {
+    /// Load the rules file; a missing file means no rules.
+    pub fn open(
+        cfg: AutomationConfig,
+        outbox: Arc<Outbox>,
+        inbound_tx: mpsc::Sender<InboundMessage>,
+    ) -> Result<Self> {
+        let path = expand_home(&cfg.rules_path)?;
+        let rules = match std::fs::read_to_string(&path) {
+            Ok(raw) => {
+                let file: RulesFile = toml::from_str(&raw)
+                    .map_err(|e| anyhow!("automation rules {}: {e}", path.display()))?;
+                for rule in &file.rules {
+                    rule.validate()?;
+                }
+                file.rules
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(anyhow!("automation rules {}: {e}", path.display())),
+        };
+        Ok(Self {
+            cfg,
+            path,
+            rules: RwLock::new(rules),
+            writes: tokio::sync::Mutex::new(()),
+            last_run: DashMap::new(),
+            outbox,
+            inbound_tx,
+            assistant: OnceLock::new(),
+            seen: MessageLog::default(),
+            failures: DashMap::new(),
+            webhooks: None,
+            personas: HashSet::new(),
+            email: None,
+        })
+    }
+
+    /// Report rules that fail as `automation.failed`.
+    pub fn with_webhooks(mut self, webhooks: Arc<Webhooks>) -> Self {
+        self.webhooks = Some(webhooks);
+        self
+    }
+
+    /// The personas rules can name.
+    pub fn with_personas(mut self, personas: impl IntoIterator<Item = String>) -> Self {
+        self.personas = personas.into_iter().collect();
+        self
+    }
+
+    /// The `email` tool, for briefings with `email` on.
+    pub fn with_email(mut self, email: Option<Arc<dyn Tool>>) -> Self {
+        self.email = email;
+        self
+    }
+
+    /// The assistant is built after the engine, so it is attached once it exists.
+    pub fn attach_assistant(&self, assistant: &Arc<AssistantAgent>) {
+        let _ = self.assistant.set(Arc::downgrade(assistant));
+    }
+
+    pub fn start(self: Arc<Self>) {
+        if !self.cfg.enabled {
+            return;
+        }
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                for rule in self.take_due(Local::now()) {
+                    let event = Event::scheduled();
+                    if rule.conditions.hold(&event, Local::now().time()) {
+                        self.run(&rule, &event).await;
+                    }
+                }
+            }
+        });
+    }
+
+    pub fn list(&self) -> Vec<AutomationRule> {
+        self.rules.read().map(|r| r.clone()).unwrap_or_default()
+    }
+
+    pub fn get(&self, id: &str) -> Option<AutomationRule> {
+        self.list().into_iter().find(|r| r.id == id)
+    }
+
+    /// The latest failure of each rule that has failed, by rule id.
+    pub fn last_errors(&self) -> HashMap<String, RuleFailure> {
+        self.failures
+            .iter()
+            .map(|e| (e.key().clone(), e.value().clone()))
+            .collect()
+    }
+
+    pub fn last_error(&self, id: &str) -> Option<RuleFailure> {
+        self.failures.get(id).map(|f| f.clone())
+    }
+
+    /// Add `rule`, or replace the rule with its id. Returns whether it replaced one.
+    pub async fn save(&self, rule: AutomationRule) -> Result<bool> {
+        rule.validate()?;
+        self.check_persona(&rule)?;
+        let _write = self.writes.lock().await;
+        let mut rules = self.list();
+        let replaced = match rules.iter_mut().find(|r| r.id == rule.id) {
+            Some(existing) => {
+                *existing = rule.clone();
+                true
+            }
+            None => {
+                rules.push(rule.clone());
+                false
+            }
+        };
+        self.persist(rules).await?;
+        // A changed schedule starts counting from now.
+        self.last_run.remove(&rule.id);
+        self.failures.remove(&rule.id);
+        Ok(replaced)
+    }
+
+    fn check_persona(&self, rule: &AutomationRule) -> Result<()> {
+        match rule.persona.as_deref() {
+            Some(name) if !self.personas.contains(name) => {
+                Err(anyhow!("rule {}: no persona named {name}", rule.id))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn remove(&self, id: &str) -> Result<bool> {
+        let _write = self.writes.lock().await;
+        let mut rules = self.list();
+        let before = rules.len();
+        rules.retain(|r| r.id != id);
+        if rules.len() == before {
+            return Ok(false);
+        }
+        self.persist(rules).await?;
+        self.last_run.remove(id);
+        self.failures.remove(id);
+        Ok(true)
+    }
+
+    async fn persist(&self, rules: Vec<AutomationRule>) -> Result<()> {
+        let file = RulesFile { rules };
+        let raw = toml::to_string_pretty(&file)
+            .map_err(|e| anyhow!("automation rules can't be written as TOML: {e}"))?;
+        if let Some(dir) = self.path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let tmp = self.path.with_extension("toml.tmp");
+        tokio::fs::write(&tmp, raw).await?;
+        tokio::fs::rename(&tmp, &self.path).await?;
+        if let Ok(mut rules) = self.rules.write() {
+            *rules = file.rules;
+        }
+        Ok(())
+    }
+
+    /// Fire the rules `inbound` triggers. Called by the gateway for paired senders.
+    pub fn observe(self: &Arc<Self>, inbound: &InboundMessage) {
+        if !self.cfg.enabled || is_generated(inbound) {
+            return;
+        }
+        if inbound.kind == InboundMessageKind::Message && self.cfg.briefing.messages {
+            let keep = chrono::Duration::hours(self.cfg.briefing.messages_hours as i64);
+            self.seen.record(inbound, keep);
+        }
+        let event = Event::from_inbound(inbound);
+        let now = Local::now().time();
+        for rule in self.list() {
+            if rule.enabled && rule.triggered_by(inbound) && rule.conditions.hold(&event, now) {
+                let this = self.clone();
+                let event = event.clone();
+                tokio::spawn(async move { this.run(&rule, &event).await });
+            }
+        }
+    }
+
+    /// Fire a webhook rule with the request's JSON body. Returns whether its conditions
+    /// held.
+    pub async fn trigger(&self, id: &str, payload: serde_json::Value) -> Result<bool> {
+        let rule = self
+            .get(id)
+            .ok_or_else(|| anyhow!("no automation rule {id}"))?;
+        if !matches!(rule.trigger, Trigger::Webhook) {
+            return Err(anyhow!("rule {id} isn't triggered by webhook"));
+        }
+        if !self.cfg.enabled || !rule.enabled {
+            return Err(anyhow!("rule {id} is disabled"));
+        }
+        let event = Event::webhook(payload);
+        if !rule.conditions.hold(&event, Local::now().time()) {
+            return Ok(false);
+        }
+        self.run(&rule, &event).await;
+        Ok(true)
+    }
+
+    /// Scheduled rules due at `now`. A rule seen for the first time starts counting, so
+    /// a restart doesn't replay runs missed while down.
+    fn take_due(&self, now: DateTime<Local>) -> Vec<AutomationRule> {
+        self.list()
+            .into_iter()
+            .filter(|rule| rule.enabled && matches!(rule.trigger, Trigger::Schedule { .. }))
+            .filter(|rule| {
+                let mut last = self.last_run.entry(rule.id.clone()).or_insert(now);
+                let due = next_run(&rule.trigger, *last).is_some_and(|next| next <= now);
+                if due {
+                    *last = now;
+                }
+                due
+            })
+            .collect()
+    }
+
+    #[tracing::instrument(level = "info", skip_all, fields(rule = %rule.id))]
+    async fn run(&self, rule: &AutomationRule, event: &Event) {
+        for (i, action) in rule.actions.iter().enumerate() {
+            if let Err(e) = self.run_action(rule, action, event).await {
+                tracing::warn!(%e, "automation action failed; skipping the rest of the rule");
+                self.fail(rule, i, e.to_string());
+                return;
+            }
+        }
+    }
+
+    fn fail(&self, rule: &AutomationRule, action: usize, error: String) {
+        if let Some(hooks) = self.webhooks.as_ref() {
+            hooks.emit(
+                webhooks::AUTOMATION_FAILED,
+                json!({ "rule": rule.id, "action": action, "error": error }),
+            );
+        }
+        self.failures.insert(
+            rule.id.clone(),
+            RuleFailure {
+                failed_at: Utc::now(),
+                action,
+                error,
+            },
+        );
+    }
+
+    async fn run_action(
+        &self,
+        rule: &AutomationRule,
+        action: &Action,
+        event: &Event,
+    ) -> Result<()> {
+        let mut vars = Vars::new()
+            .with_clock(Local::now())
+            .set("rule", rule.id.as_str())
+            .set("channel", event.channel_id.clone().unwrap_or_default())
+            .set("sender", event.sender_id.clone().unwrap_or_default())
+            .set("content", event.content.as_str());
+        if let Some(payload) = event.payload.as_ref() {
+            vars = vars.with_json("payload", payload);
+        }
+        let target = |deliver_to: &str| -> Result<(String, String)> {
+            let rendered = template::render(deliver_to, &vars, Escape::Prompt)?;
+            split_target(&rendered)
+                .map(|(channel, recipient)| (channel.to_string(), recipient.to_string()))
+                .ok_or_else(|| anyhow!("deliver_to {rendered:?} isn't channel:recipient"))
+        };
+        match action {
+            Action::Prompt { prompt, deliver_to } => {
+                // The rules file may name a persona since removed from config.
+                self.check_persona(rule)?;
+                let content = template::render(prompt, &vars, Escape::Prompt)?;
+                let inbound = match deliver_to.as_deref().map(target).transpose()? {
+                    Some((channel, recipient)) => {
+                        prompt_message(rule, &channel, &recipient, None, content)
+                    }
+                    None => {
+                        let (Some(channel), Some(sender), Some(recipient)) = (
+                            event.channel_id.as_deref(),
+                            event.sender_id.as_deref(),
+                            event.recipient.as_deref(),
+                        ) else {
+                            return Err(anyhow!("prompt has no conversation to run in"));
+                        };
+                        let thread = (recipient != sender).then_some(recipient);
+                        prompt_message(rule, channel, sender, thread, content)
+                    }
+                };
+                self.inbound_tx
+                    .send(inbound)
+                    .await
+                    .map_err(|_| anyhow!("gateway is not running"))
+            }
+            Action::Tool {
+                tool,
+                arguments,
+                deliver_to,
+            } => {
+                let assistant = self
+                    .assistant
+                    .get()
+                    .and_then(|w| w.upgrade())
+                    .ok_or_else(|| anyhow!("assistant is not available"))?;
+                let out = assistant
+                    .call_tool_unattended(
+                        &rule.id,
+                        tool,
+                        arguments.clone(),
+                        CancellationToken::new(),
+                    )
+                    .await
+                    .map_err(|e| anyhow!("{tool}: {e}"))?;
+                match deliver_to.as_deref().map(target).transpose()? {
+                    Some((channel, recipient)) => {
+                        self.send(&channel, &recipient, format!("{tool}: {out}"))
+                            .await
+                    }
+                    None => Ok(()),
+                }
+            }
+            Action::Forward {
+                deliver_to,
+                template,
+            } => {
+                let (channel, recipient) = target(deliver_to)?;
+                let source = template.as_deref().unwrap_or(FORWARD_TEMPLATE);
+                let content = template::render(source, &vars, Escape::Prompt)?;
+                self.send(&channel, &recipient, content).await
+            }
+            Action::Briefing { deliver_to } => {
+                self.check_persona(rule)?;
+                let (channel, recipient) = target(deliver_to)?;
+                let content =
+                    briefing::gather(&self.cfg.briefing, &self.seen, self.email.as_deref()).await;
+                self.inbound_tx
+                    .send(prompt_message(rule, &channel, &recipient, None, content))
+                    .await
+                    .map_err(|_| anyhow!("gateway is not running"))
+            }
+        }
+    }
+
+    async fn send(&self, channel: &str, recipient: &str, content: String) -> Result<()> {
+        self.outbox
+            .send(
+                channel,
+                recipient,
+                OutboundMessage {
+                    content,
+                    reply_to_message_id: None,
+                    attachments: vec![],
+                },
+            )
+            .await
+    }
+}
+
+/// Random per process and never sent anywhere, so only messages this process's rules
+/// made carry it. Channels and the webhook API pass metadata through, so a `source` of
+/// `"automation"` alone proves nothing. A prompt replayed from the inbound journal after
+/// a restart no longer counts as generated.
+fn marker() -> &'static str {
+    static MARKER: OnceLock<String> = OnceLock::new();
+    MARKER.get_or_init(|| Uuid::new_v4().to_string())
+}
+
+/// Whether a rule fed `inbound` to the assistant.
+pub fn is_generated(inbound: &InboundMessage) -> bool {
+    inbound.metadata.get("marker").and_then(|v| v.as_str()) == Some(marker())
+}
+
+/// The queue priority the rule that generated `inbound` asked for, if any.
+pub fn requested_priority(inbound: &InboundMessage) -> Option<Priority> {
+    if !is_generated(inbound) {
+        return None;
+    }
+    serde_json::from_value(inbound.metadata.get("priority")?.clone()).ok()
+}
+
+/// The persona the rule that generated `inbound` asked for, if any.
+pub fn requested_persona(inbound: &InboundMessage) -> Option<String> {
+    if !is_generated(inbound) {
+        return None;
+    }
+    Some(inbound.metadata.get("persona")?.as_str()?.to_string())
+}
+
+/// A prompt for the assistant, as a message from `sender` (the conversation's owner).
+pub(crate) fn prompt_message(
+    rule: &AutomationRule,
+    channel: &str,
+    sender: &str,
+    thread: Option<&str>,
+    content: String,
+) -> InboundMessage {
+    InboundMessage {
+        kind: InboundMessageKind::Message,
+        message_id: format!("automation-{}-{}", rule.id, Uuid::new_v4()),
+        channel_id: channel.to_string(),
+        sender_id: sender.to_string(),
+        thread_id: thread.map(str::to_string),
+        is_group: false,
+        content,
+        attachments: vec![],
+        metadata: json!({
+            "source": SOURCE,
+            "rule": rule.id,
+            "priority": rule.priority,
+            "persona": rule.persona,
+            "marker": marker(),
+        }),
+        received_at: Utc::now(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::collections::HashMap;
+
+    fn rule(toml_src: &str) -> AutomationRule {
+        let rule: AutomationRule = toml::from_str(toml_src).unwrap();
+        rule.validate().unwrap();
+        rule
+    }
+
+    fn message(sender: &str, content: &str) -> InboundMessage {
+        InboundMessage {
+            kind: InboundMessageKind::Message,
+            message_id: "1".to_string(),
+            channel_id: "telegram".to_string(),
+            sender_id: sender.to_string(),
+            thread_id: None,
+            is_group: false,
+            content: content.to_string(),
+            attachments: vec![],
+            metadata: json!({}),
+            received_at: Utc::now(),
+        }
+    }
+
+    fn at(hh: u32, mm: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hh, mm, 0).unwrap()
+    }
+
+    #[test]
+    fn conditions_must_all_hold() {
+        let rule = rule(
+            r#"
+            id = "urgent"
+            trigger = { type = "message", channels = ["telegram"] }
+            conditions = { senders = ["42"], content = "(?i)urgent", between = "22:00-07:00" }
+            actions = [{ type = "forward", deliver_to = "ntfy:me" }]
+            "#,
+        );
+        let urgent = message("42", "URGENT: server down");
+        assert!(rule.triggered_by(&urgent));
+        let event = Event::from_inbound(&urgent);
+        assert!(rule.conditions.hold(&event, at(23, 30)));
+        assert!(rule.conditions.hold(&event, at(6, 59)));
+        assert!(!rule.conditions.hold(&event, at(12, 0)));
+
+        let from_someone_else = Event::from_inbound(&message("7", "urgent"));
+        assert!(!rule.conditions.hold(&from_someone_else, at(23, 30)));
+        let not_urgent = Event::from_inbound(&message("42", "lunch?"));
+        assert!(!rule.conditions.hold(&not_urgent, at(23, 30)));
+
+        let mut elsewhere = urgent.clone();
+        elsewhere.channel_id = "discord".to_string();
+        assert!(!rule.triggered_by(&elsewhere));
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected() {
+        for src in [
+            // No actions.
+            r#"id = "a"
+               trigger = { type = "webhook" }
+               actions = []"#,
+            // A schedule needs exactly one of every_minutes and at.
+            r#"id = "a"
+               trigger = { type = "schedule" }
+               actions = [{ type = "forward", deliver_to = "ntfy:me" }]"#,
+            // Scheduled prompts have no conversation to default to.
+            r#"id = "a"
+               trigger = { type = "schedule", at = "08:00" }
+               actions = [{ type = "prompt", prompt = "Brief me" }]"#,
+            // Unknown template variable.
+            r#"id = "a"
+               trigger = { type = "message" }
+               actions = [{ type = "forward", deliver_to = "ntfy:me", template = "{{ nope }}" }]"#,
+            r#"id = "a"
+               trigger = { type = "message" }
+               conditions = { content = "(" }
+               actions = [{ type = "forward", deliver_to = "ntfy:me" }]"#,
+            // Only webhooks have a payload.
+            r#"id = "a"
+               trigger = { type = "message" }
+               actions = [{ type = "forward", deliver_to = "ntfy:{{ payload.user }}" }]"#,
+        ] {
+            let rule: AutomationRule = toml::from_str(src).unwrap();
+            assert!(rule.validate().is_err(), "{src}");
+        }
+    }
+
+    #[test]
+    fn schedules_run_once_per_occurrence() {
+        let daily = Trigger::Schedule {
+            every_minutes: None,
+            at: Some("08:00".to_string()),
+        };
+        let evening = Local.with_ymd_and_hms(2026, 3, 2, 20, 0, 0).unwrap();
+        let next = next_run(&daily, evening).unwrap();
+        assert_eq!(next, Local.with_ymd_and_hms(2026, 3, 3, 8, 0, 0).unwrap());
+        let early = Local.with_ymd_and_hms(2026, 3, 3, 7, 0, 0).unwrap();
+        assert_eq!(next_run(&daily, early), Some(next));
+
+        let every = Trigger::Schedule {
+            every_minutes: Some(15),
+            at: None,
+        };
+        assert_eq!(
+            next_run(&every, evening),
+            Some(evening + chrono::Duration::minutes(15))
+        );
+    }
+
+    #[tokio::test]
+    async fn webhook_payloads_fill_the_prompt_and_pick_the_conversation() {
+        let cfg = AutomationConfig {
+            enabled: true,
+            rules_path: "/nonexistent/automation.toml".to_string(),
+        };
+        let (tx, mut rx) = mpsc::channel(4);
+        let engine =
+            AutomationEngine::open(cfg, Arc::new(Outbox::new(HashMap::new())), tx).unwrap();
+        let rule = rule(
+            r#"
+            id = "github-prs"
+            trigger = { type = "webhook" }
+            [[actions]]
+            type = "prompt"
+            deliver_to = "telegram:{{ payload.chat }}"
+            prompt = "Brief me: {{ payload.sender.login }} {{ payload.action }} {{ payload.pull_request.title | truncate:20 }}"
+            "#,
+        );
+        if let Ok(mut rules) = engine.rules.write() {
+            rules.push(rule);
+        }
+
+        let payload = json!({
+            "action": "opened",
+            "chat": "12345",
+            "sender": { "login": "octocat" },
+            "pull_request": { "title": "Add automation rules for webhooks" },
+        });
+        assert!(engine.trigger("github-prs", payload).await.unwrap());
+        let prompt = rx.recv().await.unwrap();
+        assert_eq!(
+            (prompt.channel_id.as_str(), prompt.sender_id.as_str()),
+            ("telegram", "12345")
+        );
+        assert_eq!(
+            prompt.content,
+            "Brief me: octocat opened Add automation rules…"
+        );
+        // A payload missing a field the rule uses fails instead of sending half a brief.
+        assert!(engine.trigger("github-prs", json!({})).await.unwrap());
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn prompts_reach_the_assistant_without_retriggering() {
+        let path = std::env::temp_dir().join(format!(
+            "opencraw-automation-{}-{}.toml",
+            std::process::id(),
+            Uuid::new_v4()
+        ));
+        let cfg = AutomationConfig {
+            enabled: true,
+            rules_path: path.display().to_string(),
+        };
+        let (tx, mut rx) = mpsc::channel(4);
+        let outbox = Arc::new(Outbox::new(HashMap::new()));
+        let engine =
+            Arc::new(AutomationEngine::open(cfg.clone(), outbox.clone(), tx.clone()).unwrap());
+        engine
+            .save(rule(
+                r#"
+                id = "summarize-links"
+                trigger = { type = "message" }
+                conditions = { content = "https://" }
+                actions = [{ type = "prompt", prompt = "Summarize the page {{ sender }} sent: {{ content }}" }]
+                "#,
+            ))
+            .await
+            .unwrap();
+
+        engine.observe(&message("42", "no links here"));
+        engine.observe(&message("42", "https://example.com/post"));
+        let prompt = rx.recv().await.unwrap();
+        assert_eq!(prompt.sender_id, "42");
+        assert_eq!(
+            prompt.content,
+            "Summarize the page 42 sent: https://example.com/post"
+        );
+        engine.observe(&prompt);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(rx.try_recv().is_err());
+
+        // Saved rules survive a restart.
+        let reopened = AutomationEngine::open(cfg, outbox, tx).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(reopened.list().len(), 1);
+        assert!(reopened.remove("summarize-links").await.unwrap());
+        assert!(reopened.list().is_empty());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn failures_are_kept_on_the_rule() {
+        let cfg = AutomationConfig {
+            enabled: true,
+            rules_path: "/nonexistent/automation.toml".to_string(),
+        };
+        let (tx, _rx) = mpsc::channel(4);
+        let engine = AutomationEngine::open(cfg, Arc::new(Outbox::new(HashMap::new())), tx)
+            .unwrap()
+            .with_personas(["coder".to_string()]);
+        let mut rule = rule(
+            r#"
+            id = "ping"
+            trigger = { type = "webhook" }
+            persona = "poet"
+            actions = [{ type = "tool", tool = "shell.execute", arguments = { command = "true" } }]
+            "#,
+        );
+        let e = engine.save(rule.clone()).await.unwrap_err();
+        assert_eq!(e.to_string(), "rule ping: no persona named poet");
+        rule.persona = Some("coder".to_string());
+        if let Ok(mut rules) = engine.rules.write() {
+            rules.push(rule);
+        }
+
+        assert!(engine.last_error("ping").is_none());
+        assert!(engine.trigger("ping", json!({})).await.unwrap());
+        let failure = engine.last_error("ping").unwrap();
+        assert_eq!(failure.action, 0);
+        assert_eq!(failure.error, "assistant is not available");
+    }
+}
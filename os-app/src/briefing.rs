@@ -0,0 +1,259 @@
+//! The daily briefing: the `briefing` automation action.
+//!
+//! Gathers what the morning needs from each source turned on under
+//! `[automation.briefing]` (the rest of today's calendar, unread email subjects through
+//! the `email` tool's Gmail account, and what paired senders wrote in the last few
+//! hours) into one digest. The automation engine hands it to the assistant in the `deliver_to`
+//! conversation, which writes the briefing from it. A source that fails shows up as a
+//! line saying so rather than sinking the briefing.
+
+use crate::config::BriefingConfig;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, Utc};
+use os_channels::{CalendarAdapter, CalendarEvent, InboundMessage};
+use os_tools::{CancellationToken, Tool};
+use serde_json::json;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Most items listed per source; the rest are counted.
+const ITEMS_MAX: usize = 20;
+/// Messages kept for the briefing, newest last.
+const SEEN_MAX: usize = 500;
+const PREVIEW_CHARS: usize = 200;
+
+/// A message from a paired sender, remembered for the next briefing.
+#[derive(Debug, Clone)]
+pub struct SeenMessage {
+    pub channel_id: String,
+    pub sender_id: String,
+    pub preview: String,
+    pub received_at: DateTime<Utc>,
+}
+
+/// The messages a briefing can report, kept for `messages_hours`.
+#[derive(Debug, Default)]
+pub struct MessageLog {
+    seen: Mutex<VecDeque<SeenMessage>>,
+}
+
+impl MessageLog {
+    pub fn record(&self, inbound: &InboundMessage, keep: chrono::Duration) {
+        let mut preview: String = inbound.content.chars().take(PREVIEW_CHARS).collect();
+        if preview.len() < inbound.content.len() {
+            preview.push('…');
+        }
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        let cutoff = Utc::now() - keep;
+        while seen
+            .front()
+            .is_some_and(|m| m.received_at < cutoff || seen.len() >= SEEN_MAX)
+        {
+            seen.pop_front();
+        }
+        seen.push_back(SeenMessage {
+            channel_id: inbound.channel_id.clone(),
+            sender_id: inbound.sender_id.clone(),
+            preview,
+            received_at: inbound.received_at,
+        });
+    }
+
+    pub fn since(&self, since: DateTime<Utc>) -> Vec<SeenMessage> {
+        let seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        seen.iter()
+            .filter(|m| m.received_at >= since)
+            .cloned()
+            .collect()
+    }
+}
+
+/// One source's part of the digest.
+struct Section {
+    title: String,
+    items: Result<Vec<String>, String>,
+}
+
+/// The digest for the assistant: `cfg.prompt`, then a section per enabled source.
+/// `email` is the `email` tool, if it's on.
+pub async fn gather(
+    cfg: &BriefingConfig,
+    messages: &MessageLog,
+    email: Option<&dyn Tool>,
+) -> String {
+    let now = Local::now();
+    let mut sections = Vec::new();
+    if cfg.calendar {
+        sections.push(Section {
+            title: "Calendar, rest of today".to_string(),
+            items: calendar(cfg, now).await.map_err(|e| e.to_string()),
+        });
+    }
+    if cfg.email {
+        sections.push(Section {
+            title: "Unread email".to_string(),
+            items: unread_email(email, &cfg.email_query)
+                .await
+                .map_err(|e| e.to_string()),
+        });
+    }
+    if cfg.messages {
+        let since = Utc::now() - chrono::Duration::hours(cfg.messages_hours as i64);
+        sections.push(Section {
+            title: format!("Messages in the last {} hours", cfg.messages_hours),
+            items: Ok(messages.since(since).iter().map(describe_message).collect()),
+        });
+    }
+    render(&cfg.prompt, now, &sections)
+}
+
+fn render(prompt: &str, now: DateTime<Local>, sections: &[Section]) -> String {
+    let mut out = format!("{prompt}\n\n[briefing] {}", now.format("%A %Y-%m-%d"));
+    if sections.is_empty() {
+        out.push_str("\nNo briefing sources are turned on.");
+    }
+    for section in sections {
+        out.push_str(&format!("\n\n{}:", section.title));
+        match &section.items {
+            Ok(items) if items.is_empty() => out.push_str("\n- nothing"),
+            Ok(items) => {
+                for item in items.iter().take(ITEMS_MAX) {
+                    out.push_str(&format!("\n- {item}"));
+                }
+                if items.len() > ITEMS_MAX {
+                    out.push_str(&format!("\n- and {} more", items.len() - ITEMS_MAX));
+                }
+            }
+            Err(e) => out.push_str(&format!("\n- unavailable: {e}")),
+        }
+    }
+    out
+}
+
+async fn calendar(cfg: &BriefingConfig, now: DateTime<Local>) -> Result<Vec<String>> {
+    let midnight = (now.date_naive() + chrono::Days::new(1))
+        .and_hms_opt(0, 0, 0)
+        .and_then(|t| t.and_local_timezone(Local).earliest())
+        .ok_or_else(|| anyhow!("can't tell when today ends"))?;
+    let within = (midnight - now).to_std().unwrap_or_default();
+    let events = CalendarAdapter::new("", "")
+        .with_calendars(cfg.calendars.clone())
+        .events_within(within)
+        .await?;
+    Ok(events.iter().map(describe_event).collect())
+}
+
+fn describe_event(event: &CalendarEvent) -> String {
+    let title = event.title.as_deref().unwrap_or("Untitled event");
+    let mut out = if event.all_day {
+        format!("all day: {title}")
+    } else {
+        format!(
+            "{}–{} {title}",
+            event.start.with_timezone(&Local).format("%H:%M"),
+            event.end.with_timezone(&Local).format("%H:%M"),
+        )
+    };
+    if let Some(location) = event.location.as_deref().filter(|s| !s.trim().is_empty()) {
+        out.push_str(&format!(" ({location})"));
+    }
+    out
+}
+
+async fn unread_email(email: Option<&dyn Tool>, query: &str) -> Result<Vec<String>> {
+    let tool = email.ok_or_else(|| anyhow!("the email tool is off"))?;
+    let found = tool
+        .execute(
+            json!({ "action": "search", "query": query, "limit": ITEMS_MAX }),
+            &CancellationToken::new(),
+        )
+        .await?;
+    Ok(found["threads"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(describe_thread)
+        .collect())
+}
+
+/// A thread from the email tool's `search` output.
+fn describe_thread(thread: &serde_json::Value) -> String {
+    format!(
+        "{}: {}",
+        thread["from"].as_str().unwrap_or("unknown sender"),
+        thread["subject"].as_str().unwrap_or("(no subject)")
+    )
+}
+
+fn describe_message(m: &SeenMessage) -> String {
+    format!(
+        "{} {}:{}: {}",
+        m.received_at.with_timezone(&Local).format("%H:%M"),
+        m.channel_id,
+        m.sender_id,
+        m.preview
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn sections_list_items_and_say_what_failed() {
+        let now = Local.with_ymd_and_hms(2026, 3, 3, 7, 30, 0).unwrap();
+        let sections = vec![
+            Section {
+                title: "Calendar, rest of today".to_string(),
+                items: Err("calendar access denied".to_string()),
+            },
+            Section {
+                title: "Unread email".to_string(),
+                items: Ok(vec![describe_thread(&json!({
+                    "thread_id": "t1",
+                    "subject": "Invoice",
+                    "from": "Acme <billing@acme.test>",
+                    "unread": true,
+                }))]),
+            },
+            Section {
+                title: "Messages in the last 12 hours".to_string(),
+                items: Ok(vec![]),
+            },
+        ];
+        assert_eq!(
+            render("Brief me.", now, &sections),
+            "Brief me.\n\n[briefing] Tuesday 2026-03-03\
+             \n\nCalendar, rest of today:\n- unavailable: calendar access denied\
+             \n\nUnread email:\n- Acme <billing@acme.test>: Invoice\
+             \n\nMessages in the last 12 hours:\n- nothing"
+        );
+    }
+
+    #[test]
+    fn message_log_keeps_recent_messages() {
+        let log = MessageLog::default();
+        let message = |content: &str, minutes_ago: i64| InboundMessage {
+            kind: os_channels::InboundMessageKind::Message,
+            message_id: "m".to_string(),
+            channel_id: "telegram".to_string(),
+            sender_id: "42".to_string(),
+            thread_id: None,
+            is_group: false,
+            content: content.to_string(),
+            attachments: vec![],
+            metadata: json!({}),
+            received_at: Utc::now() - chrono::Duration::minutes(minutes_ago),
+        };
+        let keep = chrono::Duration::hours(12);
+        log.record(&message("old", 24 * 60), keep);
+        log.record(&message("late night", 8 * 60), keep);
+        log.record(&message(&"x".repeat(300), 5), keep);
+
+        let seen = log.since(Utc::now() - keep);
+        assert_eq!(seen.len(), 2);
+        assert_eq!(seen[0].preview, "late night");
+        assert_eq!(seen[1].preview.chars().count(), PREVIEW_CHARS + 1);
+    }
+}
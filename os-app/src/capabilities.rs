@@ -0,0 +1,214 @@
+//! What the assistant can and can't do right now, as a short block for the system prompt.
+//!
+//! Built from config and the run's actual tool list, so the model doesn't offer actions
+//! that are switched off and can say why when asked (approval, limits, channel features).
+
+use crate::assistant::{approval_mode_for_tool, RunBudget};
+use crate::config::{ApprovalMode, OpenShellConfig};
+use horizons_core::core_agents::models::RiskLevel;
+use os_channels::{ChannelAdapter, Dialect};
+use os_tools::Tool;
+use std::sync::Arc;
+
+/// Built-in tools that config can switch off.
+const OPTIONAL_TOOLS: &[&str] = &[
+    "shell.execute",
+    "browser",
+    "filesystem",
+    "clipboard",
+    "code.run",
+    "apple.reminders",
+    "apple.notes",
+    "todoist",
+    "email",
+];
+
+/// What a channel's adapter supports, captured once at startup.
+#[derive(Debug, Clone)]
+pub struct ChannelCapabilities {
+    pub dialect: Dialect,
+    pub max_message_chars: usize,
+    pub reactions: bool,
+    pub attachments: bool,
+}
+
+impl ChannelCapabilities {
+    pub fn of(adapter: &dyn ChannelAdapter) -> Self {
+        Self {
+            dialect: adapter.dialect(),
+            max_message_chars: adapter.max_message_chars(),
+            reactions: adapter.supports_reactions(),
+            attachments: adapter.supports_attachments(),
+        }
+    }
+}
+
+pub fn manifest(
+    cfg: &OpenShellConfig,
+    tools: &[Arc<dyn Tool>],
+    budget: &RunBudget,
+    channel_id: &str,
+    channel: Option<&ChannelCapabilities>,
+) -> String {
+    let mut out = String::from(
+        "Your current setup (from the owner's config; don't offer anything it rules out):",
+    );
+
+    let offered: Vec<String> = tools
+        .iter()
+        .map(|t| {
+            let spec = t.spec();
+            let approval = if spec.name == "filesystem" {
+                let write = serde_json::json!({ "action": "write_file" });
+                match approval_mode_for_tool(cfg, &spec.name, RiskLevel::Medium, &write) {
+                    ApprovalMode::Auto => None,
+                    mode => Some(format!("writes: {}", approval_text(mode))),
+                }
+            } else {
+                match approval_mode_for_tool(
+                    cfg,
+                    &spec.name,
+                    spec.risk_level,
+                    &serde_json::Value::Null,
+                ) {
+                    ApprovalMode::Auto => None,
+                    // Reading is low risk; changes keep the tool's own.
+                    mode if spec.name == "todoist" || spec.name == "email" => {
+                        Some(format!("changes: {}", approval_text(mode)))
+                    }
+                    mode => Some(approval_text(mode).to_string()),
+                }
+            };
+            match approval {
+                Some(approval) => format!("{} ({approval})", spec.name),
+                None => spec.name,
+            }
+        })
+        .collect();
+    if offered.is_empty() {
+        out.push_str("\n- Tools: none for this message.");
+    } else {
+        out.push_str(&format!("\n- Tools: {}.", offered.join(", ")));
+    }
+    let unavailable: Vec<&str> = OPTIONAL_TOOLS
+        .iter()
+        .copied()
+        .filter(|name| !tools.iter().any(|t| t.spec().name == *name))
+        .collect();
+    if !unavailable.is_empty() {
+        out.push_str(&format!("\n- Not available: {}.", unavailable.join(", ")));
+    }
+
+    if let Some(channel) = channel {
+        let mut features = vec![
+            match channel.dialect {
+                Dialect::Markdown => "Markdown",
+                Dialect::TelegramMarkdownV2 | Dialect::SlackMrkdwn => "basic formatting",
+                Dialect::Plain => "plain text only",
+            }
+            .to_string(),
+            format!("up to {} characters per message", channel.max_message_chars),
+        ];
+        features.push(if channel.attachments {
+            "can send files".to_string()
+        } else {
+            "can't send files".to_string()
+        });
+        if channel.reactions {
+            features.push("reactions".to_string());
+        }
+        out.push_str(&format!(
+            "\n- Channel {channel_id}: {}.",
+            features.join(", ")
+        ));
+    }
+
+    let mut limits = vec![format!("{} tool rounds per message", budget.tool_loops_max)];
+    if let Some(tokens) = budget.tokens_max {
+        limits.push(format!("{tokens} tokens per run"));
+    }
+    if cfg.watchdog.enabled {
+        limits.push(format!(
+            "runs cancelled after {}s",
+            cfg.watchdog.run_timeout_seconds
+        ));
+    }
+    out.push_str(&format!("\n- Limits: {}.", limits.join(", ")));
+    out
+}
+
+fn approval_text(mode: ApprovalMode) -> &'static str {
+    match mode {
+        ApprovalMode::Auto => "no approval needed",
+        ApprovalMode::Ai => "AI-reviewed",
+        ApprovalMode::Human => "owner approval required",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use os_tools::{CancellationToken, ToolSpec};
+
+    struct Fake(&'static str, RiskLevel);
+
+    #[async_trait]
+    impl Tool for Fake {
+        fn spec(&self) -> ToolSpec {
+            ToolSpec {
+                name: self.0.to_string(),
+                description: String::new(),
+                parameters_schema: serde_json::json!({}),
+                risk_level: self.1,
+            }
+        }
+
+        async fn execute(
+            &self,
+            _: serde_json::Value,
+            _: &CancellationToken,
+        ) -> os_tools::Result<serde_json::Value> {
+            Ok(serde_json::Value::Null)
+        }
+    }
+
+    #[test]
+    fn lists_tools_approvals_channel_and_limits() {
+        let mut cfg: OpenShellConfig =
+            toml::from_str(include_str!("../../config.example.toml")).unwrap();
+        cfg.security.shell_approval = ApprovalMode::Human;
+        cfg.security.filesystem_write_approval = ApprovalMode::Human;
+        let tools: Vec<Arc<dyn Tool>> = vec![
+            Arc::new(Fake("shell.execute", RiskLevel::High)),
+            Arc::new(Fake("filesystem", RiskLevel::Low)),
+            Arc::new(Fake("translate", RiskLevel::Low)),
+        ];
+        let channel = ChannelCapabilities {
+            dialect: Dialect::Plain,
+            max_message_chars: 1000,
+            reactions: true,
+            attachments: false,
+        };
+
+        let text = manifest(
+            &cfg,
+            &tools,
+            &RunBudget::interactive(),
+            "imessage",
+            Some(&channel),
+        );
+        assert!(text.contains(
+            "- Tools: shell.execute (owner approval required), filesystem (writes: owner approval required), translate."
+        ));
+        assert!(text.contains("- Not available: browser, clipboard, code.run,"));
+        assert!(text.contains(
+            "- Channel imessage: plain text only, up to 1000 characters per message, can't send files, reactions."
+        ));
+        assert!(text.contains("- Limits: 4 tool rounds per message"));
+
+        let none = manifest(&cfg, &[], &RunBudget::interactive(), "webchat", None);
+        assert!(none.contains("- Tools: none for this message."));
+        assert!(!none.contains("Channel"));
+    }
+}
@@ -0,0 +1,206 @@
+//! Running several replicas against one Postgres database.
+//!
+//! With `[cluster] database_url` set, a replica leases a conversation's lane (channel and
+//! sender) before handling a message from it, so two replicas never run the same
+//! conversation at once. A message whose lane another replica holds, or whose lease can't
+//! be taken at all, goes back in the queue and is tried again shortly; it's never handled
+//! without the lease. The session is loaded from the database when the lease is taken
+//! and saved before it is released, so the next message can land on any replica. A
+//! replica that dies mid-run loses its leases after `lease_seconds`.
+//!
+//! Only lane leases and sessions are shared. Still per replica: the inbound queue and
+//! journal, tool calls waiting for approval (decide them on the replica that asked),
+//! `/stop` and `/cancel`, and the session list and archive API. Channels that poll for
+//! messages should be enabled on one replica only; webhook-driven ones can sit behind a
+//! load balancer.
+
+use crate::config::ClusterConfig;
+use crate::encryption::{open_value, DataCipher};
+use crate::session::Session;
+use anyhow::{Context, Result};
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use sqlx::Row;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+const SCHEMA: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS opencraw_lane_leases (
+        lane TEXT PRIMARY KEY,
+        holder TEXT NOT NULL,
+        expires_at TIMESTAMPTZ NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS opencraw_sessions (
+        channel_id TEXT NOT NULL,
+        sender_id TEXT NOT NULL,
+        data TEXT NOT NULL,
+        updated_at TIMESTAMPTZ NOT NULL,
+        PRIMARY KEY (channel_id, sender_id)
+    )",
+];
+
+pub struct Cluster {
+    pool: PgPool,
+    /// Unique per process, so a restarted pod never mistakes an old lease for its own.
+    instance_id: String,
+    lease: Duration,
+    cipher: Option<Arc<DataCipher>>,
+}
+
+impl Cluster {
+    pub async fn connect(cfg: &ClusterConfig, cipher: Option<Arc<DataCipher>>) -> Result<Self> {
+        let url = cfg
+            .database_url
+            .as_deref()
+            .context("cluster.database_url is not set")?;
+        let pool = PgPoolOptions::new()
+            .max_connections(cfg.max_connections)
+            .connect(url)
+            .await
+            .context("connect to cluster.database_url")?;
+        for statement in SCHEMA {
+            sqlx::query(statement).execute(&pool).await?;
+        }
+        let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "opencraw".to_string());
+        let instance_id = format!("{host}/{}", &Uuid::new_v4().simple().to_string()[..8]);
+        tracing::info!(%instance_id, "joined cluster");
+        Ok(Self {
+            pool,
+            instance_id,
+            lease: Duration::from_secs(cfg.lease_seconds),
+            cipher,
+        })
+    }
+
+    /// Lease the conversation's lane, or `None` while another replica holds it. The
+    /// lease is renewed in the background until released.
+    pub async fn try_lock_lane(
+        self: &Arc<Self>,
+        channel_id: &str,
+        sender_id: &str,
+    ) -> Result<Option<LaneLease>> {
+        let lane = format!("{channel_id}:{sender_id}");
+        if !self.try_lease(&lane).await? {
+            return Ok(None);
+        }
+        let renew = tokio::spawn({
+            let cluster = self.clone();
+            let lane = lane.clone();
+            async move {
+                loop {
+                    tokio::time::sleep(cluster.lease / 3).await;
+                    match cluster.renew(&lane).await {
+                        Ok(true) => {}
+                        Ok(false) => {
+                            tracing::warn!(%lane, "lane lease lost before the run finished");
+                            return;
+                        }
+                        Err(e) => tracing::warn!(%e, %lane, "failed to renew lane lease"),
+                    }
+                }
+            }
+        });
+        Ok(Some(LaneLease {
+            cluster: self.clone(),
+            lane,
+            renew,
+        }))
+    }
+
+    async fn try_lease(&self, lane: &str) -> Result<bool> {
+        let res = sqlx::query(
+            "INSERT INTO opencraw_lane_leases (lane, holder, expires_at)
+             VALUES ($1, $2, now() + make_interval(secs => $3))
+             ON CONFLICT (lane) DO UPDATE
+             SET holder = EXCLUDED.holder, expires_at = EXCLUDED.expires_at
+             WHERE opencraw_lane_leases.expires_at < now()
+                OR opencraw_lane_leases.holder = EXCLUDED.holder",
+        )
+        .bind(lane)
+        .bind(&self.instance_id)
+        .bind(self.lease.as_secs_f64())
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected() == 1)
+    }
+
+    async fn renew(&self, lane: &str) -> Result<bool> {
+        let res = sqlx::query(
+            "UPDATE opencraw_lane_leases SET expires_at = now() + make_interval(secs => $3)
+             WHERE lane = $1 AND holder = $2",
+        )
+        .bind(lane)
+        .bind(&self.instance_id)
+        .bind(self.lease.as_secs_f64())
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected() == 1)
+    }
+
+    pub async fn load_session(&self, channel_id: &str, sender_id: &str) -> Result<Option<Session>> {
+        let row = sqlx::query(
+            "SELECT data FROM opencraw_sessions WHERE channel_id = $1 AND sender_id = $2",
+        )
+        .bind(channel_id)
+        .bind(sender_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let data = open_value(self.cipher.as_deref(), row.try_get("data")?)?;
+        Ok(Some(serde_json::from_str(&data)?))
+    }
+
+    pub async fn save_session(
+        &self,
+        channel_id: &str,
+        sender_id: &str,
+        session: &Session,
+    ) -> Result<()> {
+        let data = serde_json::to_string(session)?;
+        let data = match self.cipher.as_deref() {
+            Some(cipher) => cipher.seal(&data)?,
+            None => data,
+        };
+        sqlx::query(
+            "INSERT INTO opencraw_sessions (channel_id, sender_id, data, updated_at)
+             VALUES ($1, $2, $3, now())
+             ON CONFLICT (channel_id, sender_id) DO UPDATE
+             SET data = EXCLUDED.data, updated_at = EXCLUDED.updated_at",
+        )
+        .bind(channel_id)
+        .bind(sender_id)
+        .bind(data)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+/// A held lane. Dropping it without `release` stops the renewals, so the lease lapses.
+pub struct LaneLease {
+    cluster: Arc<Cluster>,
+    lane: String,
+    renew: tokio::task::JoinHandle<()>,
+}
+
+impl LaneLease {
+    pub async fn release(self) {
+        self.renew.abort();
+        let res = sqlx::query("DELETE FROM opencraw_lane_leases WHERE lane = $1 AND holder = $2")
+            .bind(&self.lane)
+            .bind(&self.cluster.instance_id)
+            .execute(&self.cluster.pool)
+            .await;
+        if let Err(e) = res {
+            tracing::warn!(%e, lane = %self.lane, "failed to release lane lease");
+        }
+    }
+}
+
+impl Drop for LaneLease {
+    fn drop(&mut self) {
+        self.renew.abort();
+    }
+}
@@ -3,6 +3,7 @@
 //! See: specifications/openshell/implementation_v0_1_0.md
 
 use crate::config::OpenShellConfig;
+use crate::integrity::IntegrityReport;
 use crate::session::Session;
 use std::time::Duration;
 
@@ -12,12 +13,19 @@ pub fn handle_command(
     input: &str,
     uptime: Duration,
     active_channels: &[String],
+    integrity: Option<&IntegrityReport>,
 ) -> Option<String> {
     let trimmed = input.trim();
     if !trimmed.starts_with('/') {
         return None;
     }
 
+    if let Some(rest) = trimmed.strip_prefix("/persona") {
+        if rest.is_empty() || rest.starts_with(' ') {
+            return Some(persona_command(cfg, session, rest.trim()));
+        }
+    }
+
     match trimmed {
         "/new" => {
             session.reset();
@@ -31,16 +39,54 @@ pub fn handle_command(
             session.show_tool_calls = !session.show_tool_calls;
             Some(format!("show_tool_calls = {}", session.show_tool_calls))
         }
+        "/trace" => Some(match session.last_trace.as_ref() {
+            Some(trace) => trace.render(),
+            None => "No run to trace yet.".to_string(),
+        }),
         "/usage" => Some(format!(
-            "prompt_tokens={} completion_tokens={}",
-            session.usage_totals.prompt_tokens, session.usage_totals.completion_tokens
+            "prompt_tokens={} completion_tokens={} reasoning_tokens={}",
+            session.usage_totals.prompt_tokens,
+            session.usage_totals.completion_tokens,
+            session.usage_totals.reasoning_tokens
         )),
         "/status" => Some(format!(
-            "model={}\nchannels={}\nuptime_seconds={}",
+            "model={}\nchannels={}\nuptime_seconds={}\nintegrity={}",
             cfg.general.model,
             active_channels.join(","),
-            uptime.as_secs()
+            uptime.as_secs(),
+            integrity
+                .map(|r| r.summary())
+                .unwrap_or_else(|| "pending".to_string())
         )),
-        _ => Some("Unknown command. Supported: /new /status /think /verbose /usage".to_string()),
+        _ => Some(
+            "Unknown command. Supported: /new /persona /status /think /verbose /usage /focus /share /move /memory /skill /trace /cancel"
+                .to_string(),
+        ),
+    }
+}
+
+fn persona_command(cfg: &OpenShellConfig, session: &mut Session, arg: &str) -> String {
+    let mut names: Vec<&str> = cfg.personas.keys().map(|s| s.as_str()).collect();
+    names.sort();
+
+    match arg {
+        "" => format!(
+            "persona={}\navailable={}",
+            session.persona.as_deref().unwrap_or("default"),
+            if names.is_empty() {
+                "(none configured)".to_string()
+            } else {
+                names.join(",")
+            }
+        ),
+        "default" => {
+            session.persona = None;
+            "Persona reset to default.".to_string()
+        }
+        name if cfg.personas.contains_key(name) => {
+            session.persona = Some(name.to_string());
+            format!("Persona set to {name}.")
+        }
+        name => format!("Unknown persona: {name}. Available: {}", names.join(",")),
     }
 }
@@ -0,0 +1,175 @@
+//! Fitting a conversation into the model's context window.
+//!
+//! Each request carries the system prompt, the tool definitions and as much of the session
+//! history as fits in the window less `[context] reserve_tokens`; older turns are left
+//! out of the request (they stay in the session and the archive). Tokens are counted with
+//! tiktoken: the model's own encoding for OpenAI models, and `cl100k_base` plus a margin
+//! for others, since Anthropic's tokenizer is not published and runs longer.
+
+use crate::config::ContextConfig;
+use os_llm::{ChatMessage, Role, ToolDefinition};
+
+/// Added to every message for its role and framing.
+const MESSAGE_OVERHEAD: usize = 4;
+/// Scale for models without a published tokenizer, in percent.
+const UNKNOWN_TOKENIZER_MARGIN: usize = 120;
+
+#[derive(Clone, Copy)]
+enum Encoding {
+    O200k,
+    Cl100k,
+}
+
+pub struct ContextWindow {
+    encoding: Encoding,
+    margin: usize,
+    /// Tokens available to the request; `None` sends the whole history.
+    budget: Option<usize>,
+}
+
+impl ContextWindow {
+    pub fn for_model(cfg: &ContextConfig, model: &str) -> Self {
+        // OpenRouter names carry a route first: `openrouter/openai/gpt-4o`.
+        let model = model
+            .rsplit('/')
+            .next()
+            .unwrap_or(model)
+            .to_ascii_lowercase();
+        let o200k = ["gpt-4o", "gpt-4.1", "gpt-5", "o1", "o3", "o4"];
+        let (encoding, margin) = if o200k.iter().any(|p| model.starts_with(p)) {
+            (Encoding::O200k, 100)
+        } else if model.starts_with("gpt-") {
+            (Encoding::Cl100k, 100)
+        } else {
+            (Encoding::Cl100k, UNKNOWN_TOKENIZER_MARGIN)
+        };
+        let budget = cfg
+            .tokens_max
+            .or_else(|| model_window(&model))
+            .map(|max| max.saturating_sub(cfg.reserve_tokens));
+        Self {
+            encoding,
+            margin,
+            budget,
+        }
+    }
+
+    pub fn count_text(&self, text: &str) -> usize {
+        // Built once per process; loading an encoding takes a moment.
+        let bpe = match self.encoding {
+            Encoding::O200k => tiktoken_rs::o200k_base_singleton(),
+            Encoding::Cl100k => tiktoken_rs::cl100k_base_singleton(),
+        };
+        let tokens = bpe.lock().encode_with_special_tokens(text).len();
+        tokens * self.margin / 100
+    }
+
+    pub fn count_message(&self, message: &ChatMessage) -> usize {
+        let calls: usize = message
+            .tool_calls
+            .iter()
+            .map(|c| self.count_text(&c.name) + self.count_text(&c.arguments))
+            .sum();
+        MESSAGE_OVERHEAD + self.count_text(&message.content) + calls
+    }
+
+    pub fn count_tools(&self, tools: &[ToolDefinition]) -> usize {
+        tools
+            .iter()
+            .map(|t| self.count_text(&serde_json::to_string(t).unwrap_or_default()))
+            .sum()
+    }
+
+    /// Index of the oldest history message to send, given `fixed` tokens already taken by
+    /// the system prompt and tools. The cut falls on a user message, so tool results are
+    /// never sent without the call, and the latest user message is always kept.
+    pub fn history_start(&self, history: &[ChatMessage], fixed: usize) -> usize {
+        let Some(budget) = self.budget else {
+            return 0;
+        };
+        let mut used = fixed;
+        let mut fits = history.len();
+        for (i, message) in history.iter().enumerate().rev() {
+            used += self.count_message(message);
+            if used > budget {
+                break;
+            }
+            fits = i;
+        }
+        let last_user = history
+            .iter()
+            .rposition(|m| matches!(m.role, Role::User))
+            .unwrap_or(0);
+        history[fits..]
+            .iter()
+            .position(|m| matches!(m.role, Role::User))
+            .map(|offset| fits + offset)
+            .unwrap_or(last_user)
+            .min(last_user)
+    }
+}
+
+/// Context window by model family; unknown models are not trimmed.
+fn model_window(model: &str) -> Option<usize> {
+    let windows: &[(&str, usize)] = &[
+        ("claude", 200_000),
+        ("gpt-4.1", 1_000_000),
+        ("gpt-5", 400_000),
+        ("gpt-4o", 128_000),
+        ("gpt-4-turbo", 128_000),
+        ("gpt-4", 8_192),
+        ("gpt-3.5", 16_385),
+        ("o1", 200_000),
+        ("o3", 200_000),
+        ("o4", 200_000),
+    ];
+    windows
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|(_, window)| *window)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: Role, content: &str) -> ChatMessage {
+        ChatMessage {
+            role,
+            content: content.to_string(),
+            tool_calls: vec![],
+            tool_call_id: None,
+            reasoning: vec![],
+        }
+    }
+
+    #[test]
+    fn counts_real_tokens_and_keeps_whole_turns() {
+        let window = ContextWindow::for_model(&ContextConfig::default(), "gpt-4o");
+        // Four characters per token is far off for CJK text.
+        let cjk = "東京都の天気予報を教えてください";
+        assert!(window.count_text(cjk) > cjk.chars().count() / 4 * 2);
+        let claude = ContextWindow::for_model(&ContextConfig::default(), "claude-sonnet-4-5");
+        assert!(claude.count_text(cjk) > window.count_text(cjk));
+
+        let cfg = ContextConfig {
+            tokens_max: Some(60),
+            reserve_tokens: 0,
+        };
+        let window = ContextWindow::for_model(&cfg, "gpt-4o");
+        let long = "word ".repeat(30);
+        let history = vec![
+            message(Role::User, "first question"),
+            message(Role::Assistant, &long),
+            message(Role::User, "second question"),
+            message(Role::Assistant, "calling a tool"),
+            message(Role::Tool, "tool output"),
+            message(Role::Assistant, "second answer"),
+        ];
+        assert_eq!(window.history_start(&history, 10), 2);
+        // Even when nothing fits, the latest user message goes.
+        assert_eq!(window.history_start(&history, 1_000), 2);
+        let unbounded = ContextWindow::for_model(&ContextConfig::default(), "local-model");
+        assert_eq!(unbounded.history_start(&history, 1_000_000), 0);
+    }
+}
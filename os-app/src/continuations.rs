@@ -0,0 +1,309 @@
+//! Assistant-scheduled follow-ups ("I'll get back to you").
+//!
+//! When a run can't finish yet (a rate limit to wait out, a background task still
+//! running), the assistant calls `schedule_followup` with a note to itself. The
+//! conversation's session is kept as is; once the wait is over, the note is fed back
+//! through the gateway as a message from the same sender, so the assistant resumes with
+//! its full history and the reply reaches the conversation like any other.
+
+use crate::config::ContinuationsConfig;
+use crate::tasks::{ConversationOrigin, TaskRegistry, CONVERSATION};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use horizons_core::core_agents::models::RiskLevel;
+use os_channels::{InboundMessage, InboundMessageKind};
+use os_tools::{CancellationToken, Tool, ToolError, ToolSpec};
+use serde::Serialize;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+pub const SCHEDULE_FOLLOWUP_TOOL: &str = "schedule_followup";
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WaitFor {
+    Until { at: DateTime<Utc> },
+    Task { task_id: Uuid },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Continuation {
+    pub id: Uuid,
+    pub origin: ConversationOrigin,
+    pub note: String,
+    pub wait_for: WaitFor,
+    pub created_at: DateTime<Utc>,
+}
+
+pub struct ContinuationRegistry {
+    cfg: ContinuationsConfig,
+    pending: DashMap<Uuid, Continuation>,
+    tasks: Arc<TaskRegistry>,
+    inbound_tx: mpsc::Sender<InboundMessage>,
+}
+
+impl ContinuationRegistry {
+    pub fn new(
+        cfg: ContinuationsConfig,
+        tasks: Arc<TaskRegistry>,
+        inbound_tx: mpsc::Sender<InboundMessage>,
+    ) -> Self {
+        Self {
+            cfg,
+            pending: DashMap::new(),
+            tasks,
+            inbound_tx,
+        }
+    }
+
+    pub fn start(self: Arc<Self>) {
+        if !self.cfg.enabled {
+            return;
+        }
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                for continuation in self.take_ready() {
+                    self.resume(continuation).await;
+                }
+            }
+        });
+    }
+
+    pub fn list(&self) -> Vec<Continuation> {
+        let mut out: Vec<Continuation> = self.pending.iter().map(|e| e.value().clone()).collect();
+        out.sort_by_key(|c| c.created_at);
+        out
+    }
+
+    pub fn cancel(&self, id: Uuid) -> bool {
+        self.pending.remove(&id).is_some()
+    }
+
+    pub fn schedule(
+        &self,
+        origin: ConversationOrigin,
+        note: String,
+        wait_for: WaitFor,
+    ) -> Result<Continuation, String> {
+        let queued = self
+            .pending
+            .iter()
+            .filter(|c| {
+                c.origin.channel_id == origin.channel_id && c.origin.sender_id == origin.sender_id
+            })
+            .count();
+        if queued >= self.cfg.max_pending_per_conversation {
+            return Err(format!(
+                "this conversation already has {queued} follow-up(s) scheduled"
+            ));
+        }
+        let continuation = Continuation {
+            id: Uuid::new_v4(),
+            origin,
+            note,
+            wait_for,
+            created_at: Utc::now(),
+        };
+        self.pending.insert(continuation.id, continuation.clone());
+        Ok(continuation)
+    }
+
+    fn take_ready(&self) -> Vec<Continuation> {
+        let now = Utc::now();
+        let ready: Vec<Uuid> = self
+            .pending
+            .iter()
+            .filter(|c| match &c.wait_for {
+                WaitFor::Until { at } => *at <= now,
+                // A task that was pruned from the registry has long finished.
+                WaitFor::Task { task_id } => self
+                    .tasks
+                    .get(*task_id)
+                    .is_none_or(|t| t.finished_at.is_some()),
+            })
+            .map(|c| c.id)
+            .collect();
+        ready
+            .into_iter()
+            .filter_map(|id| self.pending.remove(&id).map(|(_, c)| c))
+            .collect()
+    }
+
+    #[tracing::instrument(level = "info", skip_all, fields(continuation_id = %continuation.id))]
+    async fn resume(&self, continuation: Continuation) {
+        let origin = &continuation.origin;
+        let mut content = format!("[Scheduled follow-up] {}", continuation.note);
+        if let WaitFor::Task { task_id } = continuation.wait_for {
+            if let Some(task) = self.tasks.get(task_id) {
+                let outcome = task
+                    .result
+                    .or(task.error)
+                    .unwrap_or_else(|| "no output".to_string());
+                content.push_str(&format!(
+                    "\nTask {task_id} ended ({:?}): {outcome}",
+                    task.status
+                ));
+            }
+        }
+        let inbound = InboundMessage {
+            kind: InboundMessageKind::Message,
+            message_id: format!("continuation-{}", continuation.id),
+            channel_id: origin.channel_id.clone(),
+            sender_id: origin.sender_id.clone(),
+            // The gateway replies to the thread when set, else to the sender.
+            thread_id: (origin.recipient != origin.sender_id).then(|| origin.recipient.clone()),
+            is_group: false,
+            content,
+            attachments: vec![],
+            metadata: json!({ "continuation_id": continuation.id }),
+            received_at: Utc::now(),
+        };
+        if let Err(e) = self.inbound_tx.send(inbound).await {
+            tracing::warn!(%e, "failed to resume follow-up");
+        }
+    }
+}
+
+pub struct ScheduleFollowupTool {
+    registry: Arc<ContinuationRegistry>,
+}
+
+impl ScheduleFollowupTool {
+    pub fn new(registry: Arc<ContinuationRegistry>) -> Self {
+        Self { registry }
+    }
+}
+
+#[async_trait]
+impl Tool for ScheduleFollowupTool {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: SCHEDULE_FOLLOWUP_TOOL.to_string(),
+            description: "Pick this conversation back up later instead of asking the user to re-ask: after a delay (e.g. a rate limit) or when a delegate_task task finishes. The note is handed back to you when it resumes, with the conversation so far. Tell the user you will get back to them.".to_string(),
+            parameters_schema: json!({
+                "type": "object",
+                "properties": {
+                    "note": { "type": "string", "description": "What to do when resuming." },
+                    "after_seconds": { "type": "integer", "minimum": 1, "description": "Resume after this many seconds." },
+                    "task_id": { "type": "string", "description": "Resume when this background task finishes." }
+                },
+                "required": ["note"]
+            }),
+            risk_level: RiskLevel::Low,
+        }
+    }
+
+    async fn execute(
+        &self,
+        arguments: serde_json::Value,
+        _cancel: &CancellationToken,
+    ) -> os_tools::Result<serde_json::Value> {
+        let note = arguments
+            .get("note")
+            .and_then(|v| v.as_str())
+            .filter(|s| !s.trim().is_empty())
+            .ok_or_else(|| ToolError::InvalidArguments("missing key: note".to_string()))?;
+        let after_seconds = arguments.get("after_seconds").and_then(|v| v.as_u64());
+        let task_id = arguments.get("task_id").and_then(|v| v.as_str());
+
+        let wait_for = match (after_seconds, task_id) {
+            (Some(_), Some(_)) => {
+                return Err(ToolError::InvalidArguments(
+                    "use either after_seconds or task_id, not both".to_string(),
+                ))
+            }
+            (Some(secs), None) => {
+                let max = self.registry.cfg.max_wait_seconds;
+                if secs == 0 || secs > max {
+                    return Err(ToolError::InvalidArguments(format!(
+                        "after_seconds must be between 1 and {max}"
+                    )));
+                }
+                WaitFor::Until {
+                    at: Utc::now() + chrono::Duration::seconds(secs as i64),
+                }
+            }
+            (None, Some(id)) => {
+                let task_id = Uuid::parse_str(id)
+                    .map_err(|_| ToolError::InvalidArguments(format!("invalid task_id: {id}")))?;
+                if self.registry.tasks.get(task_id).is_none() {
+                    return Err(ToolError::InvalidArguments(format!("unknown task: {id}")));
+                }
+                WaitFor::Task { task_id }
+            }
+            (None, None) => {
+                return Err(ToolError::InvalidArguments(
+                    "one of after_seconds or task_id is required".to_string(),
+                ))
+            }
+        };
+
+        let origin = CONVERSATION.try_with(|o| o.clone()).map_err(|_| {
+            ToolError::ExecutionFailed(
+                "schedule_followup needs a conversation to come back to".to_string(),
+            )
+        })?;
+        let continuation = self
+            .registry
+            .schedule(origin, note.to_string(), wait_for)
+            .map_err(ToolError::ExecutionFailed)?;
+        Ok(json!({ "continuation_id": continuation.id, "wait_for": continuation.wait_for }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TasksConfig;
+    use crate::outbox::Outbox;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn due_followup_is_fed_back_as_inbound_message() {
+        let outbox = Arc::new(Outbox::new(HashMap::new()));
+        let tasks = Arc::new(TaskRegistry::new(TasksConfig::default(), outbox));
+        let (tx, mut rx) = mpsc::channel(4);
+        let registry = ContinuationRegistry::new(ContinuationsConfig::default(), tasks, tx);
+        let origin = ConversationOrigin {
+            channel_id: "telegram".to_string(),
+            sender_id: "42".to_string(),
+            recipient: "42".to_string(),
+        };
+
+        registry
+            .schedule(
+                origin.clone(),
+                "check the deploy".to_string(),
+                WaitFor::Until {
+                    at: Utc::now() + chrono::Duration::hours(1),
+                },
+            )
+            .unwrap();
+        registry
+            .schedule(
+                origin,
+                "retry the search".to_string(),
+                WaitFor::Until { at: Utc::now() },
+            )
+            .unwrap();
+
+        let ready = registry.take_ready();
+        assert_eq!(ready.len(), 1);
+        assert_eq!(registry.list().len(), 1);
+        registry.resume(ready.into_iter().next().unwrap()).await;
+
+        let inbound = rx.recv().await.unwrap();
+        assert_eq!(inbound.channel_id, "telegram");
+        assert_eq!(inbound.sender_id, "42");
+        assert!(inbound.thread_id.is_none());
+        assert!(inbound.content.ends_with("retry the search"));
+    }
+}
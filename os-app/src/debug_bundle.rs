@@ -0,0 +1,221 @@
+//! `opencraw debug-bundle`: a single gzipped JSON file to attach to bug reports.
+//!
+//! Collects version/platform info, the config with secrets redacted, integrity and live
+//! health output, the tail of a log file, and metadata (no message content) for recent
+//! conversation turns. Every configured secret value is also scrubbed from log lines.
+
+use crate::archive::ConversationArchive;
+use crate::config::{default_config_path, OpenShellConfig};
+use crate::encryption::DataCipher;
+use crate::integrity::IntegrityMonitor;
+use anyhow::Result;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde_json::{json, Value};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const REDACTED: &str = "[redacted]";
+const LOG_TAIL_LINES: usize = 500;
+
+pub struct BundleOptions {
+    pub config_path: Option<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub log_file: Option<PathBuf>,
+    pub runs: usize,
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+pub async fn write_bundle(opts: BundleOptions) -> Result<PathBuf> {
+    let config_path = opts.config_path.clone().unwrap_or_else(default_config_path);
+    let data_dir = PathBuf::from("data");
+
+    let (config_json, secrets) = match tokio::fs::read_to_string(&config_path).await {
+        Ok(raw) => match toml::from_str::<toml::Value>(&raw) {
+            Ok(v) => {
+                let mut secrets = Vec::new();
+                let mut v = serde_json::to_value(v)?;
+                redact_value(&mut v, &mut secrets);
+                (v, secrets)
+            }
+            Err(e) => (json!({ "error": format!("parse: {e}") }), Vec::new()),
+        },
+        Err(e) => (json!({ "error": format!("read: {e}") }), Vec::new()),
+    };
+
+    let (health, recent_runs) = match OpenShellConfig::load(opts.config_path.clone()).await {
+        Ok(cfg) => {
+            let integrity =
+                IntegrityMonitor::new(cfg.clone(), opts.config_path.clone(), data_dir.clone())
+                    .run_once()
+                    .await;
+            let server = probe_server(cfg.channels.webchat.port).await;
+            let runs = recent_runs(&cfg, &data_dir, opts.runs).await;
+            (
+                json!({ "config": "ok", "integrity": integrity, "server": server }),
+                runs,
+            )
+        }
+        Err(e) => (json!({ "config": e.to_string() }), json!([])),
+    };
+
+    let logs = match opts.log_file.as_deref() {
+        Some(path) => log_tail(path, &secrets).await,
+        None => json!({ "note": "no --log-file given" }),
+    };
+
+    let bundle = json!({
+        "generated_at": chrono::Utc::now(),
+        "version": env!("CARGO_PKG_VERSION"),
+        "os": std::env::consts::OS,
+        "arch": std::env::consts::ARCH,
+        "config_path": config_path.display().to_string(),
+        "config": config_json,
+        "health": health,
+        "logs": logs,
+        "recent_runs": recent_runs,
+    });
+
+    let output = opts.output.unwrap_or_else(|| {
+        PathBuf::from(format!(
+            "opencraw-debug-{}.json.gz",
+            chrono::Utc::now().format("%Y%m%dT%H%M%SZ")
+        ))
+    });
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&serde_json::to_vec_pretty(&bundle)?)?;
+    tokio::fs::write(&output, encoder.finish()?).await?;
+    Ok(output)
+}
+
+fn is_secret_key(key: &str) -> bool {
+    let k = key.to_ascii_lowercase();
+    ["key", "token", "secret", "password"]
+        .iter()
+        .any(|needle| k.contains(needle))
+}
+
+/// Replace values under secret-looking keys, collecting the originals so they can be
+/// scrubbed from free text as well.
+fn redact_value(v: &mut Value, secrets: &mut Vec<String>) {
+    match v {
+        Value::Object(map) => {
+            for (k, child) in map.iter_mut() {
+                match child {
+                    Value::String(s) if is_secret_key(k) => {
+                        if !s.is_empty() {
+                            secrets.push(s.clone());
+                            *child = Value::String(REDACTED.to_string());
+                        }
+                    }
+                    _ => redact_value(child, secrets),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|c| redact_value(c, secrets)),
+        _ => {}
+    }
+}
+
+fn scrub(line: &str, secrets: &[String]) -> String {
+    let mut out = line.to_string();
+    for s in secrets {
+        out = out.replace(s.as_str(), REDACTED);
+    }
+    out
+}
+
+async fn log_tail(path: &Path, secrets: &[String]) -> Value {
+    let mut secrets = secrets.to_vec();
+    for var in [
+        "OPENAI_API_KEY",
+        "ANTHROPIC_API_KEY",
+        "AZURE_OPENAI_API_KEY",
+        "OPENROUTER_API_KEY",
+        "TELEGRAM_BOT_TOKEN",
+        "DISCORD_BOT_TOKEN",
+    ] {
+        if let Ok(v) = std::env::var(var) {
+            if !v.trim().is_empty() {
+                secrets.push(v);
+            }
+        }
+    }
+
+    match tokio::fs::read_to_string(path).await {
+        Ok(raw) => {
+            let lines: Vec<&str> = raw.lines().collect();
+            let start = lines.len().saturating_sub(LOG_TAIL_LINES);
+            let tail: Vec<String> = lines[start..].iter().map(|l| scrub(l, &secrets)).collect();
+            json!({ "path": path.display().to_string(), "lines": tail })
+        }
+        Err(e) => json!({ "path": path.display().to_string(), "error": e.to_string() }),
+    }
+}
+
+async fn probe_server(port: u16) -> Value {
+    let url = format!("http://127.0.0.1:{port}/api/v1/os/health");
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(3))
+        .build()
+    {
+        Ok(c) => c,
+        Err(e) => return json!({ "error": e.to_string() }),
+    };
+    match client.get(&url).send().await {
+        Ok(resp) => resp
+            .json::<Value>()
+            .await
+            .unwrap_or_else(|e| json!({ "error": e.to_string() })),
+        Err(e) => json!({ "error": format!("not reachable at {url}: {e}") }),
+    }
+}
+
+async fn recent_runs(cfg: &OpenShellConfig, data_dir: &Path, limit: usize) -> Value {
+    if !cfg.archive.enabled {
+        return json!([]);
+    }
+    if cfg.archive.database_url.is_none() && !data_dir.join("conversations.db").exists() {
+        return json!([]);
+    }
+    let turns = async {
+        let cipher = DataCipher::from_config(&cfg.encryption)?;
+        ConversationArchive::from_config(cfg, data_dir, None, cipher)
+            .await?
+            .recent_turns(limit)
+            .await
+    };
+    match turns.await {
+        Ok(turns) => json!(turns),
+        Err(e) => json!({ "error": e.to_string() }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_are_redacted_from_config_and_logs() {
+        let raw = r#"
+[keys]
+openai_api_key = "sk-test-123"
+
+[channels.telegram]
+enabled = true
+bot_token = "tg-secret"
+"#;
+        let mut v = serde_json::to_value(toml::from_str::<toml::Value>(raw).unwrap()).unwrap();
+        let mut secrets = Vec::new();
+        redact_value(&mut v, &mut secrets);
+
+        assert_eq!(v["keys"]["openai_api_key"], REDACTED);
+        assert_eq!(v["channels"]["telegram"]["bot_token"], REDACTED);
+        assert_eq!(v["channels"]["telegram"]["enabled"], true);
+        assert_eq!(
+            scrub("auth failed for tg-secret", &secrets),
+            "auth failed for [redacted]"
+        );
+    }
+}
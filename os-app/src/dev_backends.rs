@@ -25,8 +25,8 @@ use horizons_core::memory::traits::{HorizonsMemory, VoyagerMemory};
 use horizons_core::memory::wiring::{build_voyager_memory, VoyagerBackedHorizonsMemory};
 use horizons_core::models::{AgentIdentity, OrgId, ProjectDbHandle, ProjectId};
 use horizons_core::onboard::traits::{
-    Cache, CentralDb, Filestore, GraphStore, OrgRecord, ProjectDb, UserRecord, UserRole,
-    VectorStore,
+    Cache, CentralDb, Filestore, GraphStore, OrgRecord, ProjectDb, ProjectDbValue, UserRecord,
+    UserRole, VectorStore,
 };
 use horizons_core::optimization::continual::ContinualLearningEngine;
 use horizons_core::optimization::engine::OptimizationEngine;
@@ -119,6 +119,7 @@ pub async fn build_dev_runtime(
 
     let project_id = dev_project_id();
     let handle = project_db.provision(org_id, project_id).await?;
+    use_wal(&*project_db, org_id, &handle).await;
 
     setup::register_subscriptions(&*event_bus, &org_id.to_string()).await?;
 
@@ -173,7 +174,7 @@ pub async fn build_dev_runtime(
 
     // Continual learning wiring (required by Horizons `all` feature).
     let mipro_llm: Arc<dyn MiproLlmClient> = Arc::new(MiproLlmAdapter {
-        llm: cfg.api_key_for_model().map(|key| os_llm::LlmClient::new(&key, &cfg.general.model)),
+        llm: cfg.llm_client_for_model(),
     });
     let sampler: Arc<dyn MiproVariantSampler> = Arc::new(mipro_v2::BasicSampler::new());
     let metric: Arc<dyn mipro_v2::EvalMetric> = Arc::new(ExactMatchMetric);
@@ -224,6 +225,29 @@ pub async fn build_dev_runtime(
     })
 }
 
+/// Action proposals live in the project database, which Horizons opens itself, so it
+/// can't go through [`crate::sqlite::SqlitePool`]. The journal mode is kept in the file,
+/// though, so switching it here means approvals are read without waiting on a run that is
+/// writing proposals. A backend that isn't SQLite is left as it is.
+async fn use_wal(project_db: &dyn ProjectDb, org_id: OrgId, handle: &ProjectDbHandle) {
+    let mode = match project_db
+        .query(org_id, handle, "PRAGMA journal_mode = WAL", &[])
+        .await
+    {
+        Ok(rows) => rows.first().and_then(|row| match row.get("journal_mode") {
+            Some(ProjectDbValue::String(mode)) => Some(mode.to_ascii_lowercase()),
+            _ => None,
+        }),
+        Err(e) => {
+            tracing::warn!(%e, "project database: journal mode not changed");
+            return;
+        }
+    };
+    if mode.as_deref() != Some("wal") {
+        tracing::warn!(?mode, "project database is not in WAL mode");
+    }
+}
+
 struct MiproLlmAdapter {
     llm: Option<os_llm::LlmClient>,
 }
@@ -243,6 +267,7 @@ impl mipro_v2::LlmClient for MiproLlmAdapter {
                     content: prompt.to_string(),
                     tool_calls: vec![],
                     tool_call_id: None,
+                    reasoning: vec![],
                 }],
                 &[],
             )
@@ -268,8 +293,7 @@ fn build_dev_voyager_memory(
 }
 
 fn build_ai_approver(cfg: &OpenShellConfig) -> Option<Arc<dyn ActionApprover>> {
-    let key = cfg.api_key_for_model()?;
-    let llm = os_llm::LlmClient::new(&key, &cfg.general.model);
+    let llm = cfg.llm_client_for_model()?;
     Some(Arc::new(LlmSafetyApprover { llm }))
 }
 
@@ -301,12 +325,14 @@ impl ActionApprover for LlmSafetyApprover {
                         content: "You review tool calls for safety.".to_string(),
                         tool_calls: vec![],
                         tool_call_id: None,
+                        reasoning: vec![],
                     },
                     os_llm::ChatMessage {
                         role: os_llm::Role::User,
                         content: prompt,
                         tool_calls: vec![],
                         tool_call_id: None,
+                        reasoning: vec![],
                     },
                 ],
                 &[],
@@ -0,0 +1,428 @@
+//! Live checks behind `opencraw doctor`.
+//!
+//! Once the config loads, each enabled integration gets one cheap authenticated call:
+//! Telegram `getMe`, Discord `users/@me`, Pushover `users/validate`, ntfy's health (and
+//! account, with a token), Todoist's projects, a Gmail token refresh, each LLM
+//! provider's model list, and a read of the iMessage database. Each result has its
+//! latency and, when it failed, what to change.
+
+use crate::config::{expand_home, OpenShellConfig};
+use futures_util::future::{join_all, BoxFuture};
+use futures_util::FutureExt;
+use reqwest::{RequestBuilder, StatusCode};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+#[derive(Debug)]
+pub struct Failure {
+    pub error: String,
+    pub hint: String,
+}
+
+#[derive(Debug)]
+pub struct CheckResult {
+    pub name: String,
+    pub latency: Duration,
+    /// What the service said about us, or why the check failed.
+    pub outcome: Result<String, Failure>,
+}
+
+/// Run every check that applies to `cfg`, concurrently.
+pub async fn run(cfg: &OpenShellConfig) -> Vec<CheckResult> {
+    let http = reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .build()
+        .unwrap_or_default();
+    let mut checks: Vec<(String, BoxFuture<'static, Result<String, Failure>>)> = Vec::new();
+
+    for (provider, check) in llm_checks(cfg, &http) {
+        checks.push((format!("llm {provider}"), check));
+    }
+    let channels = &cfg.channels;
+    if channels.telegram.enabled {
+        let url = format!(
+            "https://api.telegram.org/bot{}/getMe",
+            channels.telegram.bot_token.trim()
+        );
+        let req = http.get(url);
+        checks.push((
+            "telegram".to_string(),
+            async move {
+                let body = call(
+                    req,
+                    "channels.telegram.bot_token",
+                    "get a new token from @BotFather (/token)",
+                )
+                .await?;
+                Ok(format!(
+                    "@{}",
+                    body["result"]["username"].as_str().unwrap_or("?")
+                ))
+            }
+            .boxed(),
+        ));
+    }
+    if channels.discord.enabled {
+        let req = http.get("https://discord.com/api/v10/users/@me").header(
+            "authorization",
+            format!("Bot {}", channels.discord.bot_token.trim()),
+        );
+        checks.push((
+            "discord".to_string(),
+            async move {
+                let body = call(
+                    req,
+                    "channels.discord.bot_token",
+                    "reset it under Bot in the Discord developer portal",
+                )
+                .await?;
+                Ok(body["username"].as_str().unwrap_or("?").to_string())
+            }
+            .boxed(),
+        ));
+    }
+    if channels.pushover.enabled {
+        let req = http
+            .post("https://api.pushover.net/1/users/validate.json")
+            .form(&[
+                ("token", channels.pushover.app_token.as_str()),
+                ("user", channels.pushover.user_key.as_str()),
+            ]);
+        checks.push((
+            "pushover".to_string(),
+            async move {
+                let body = call(
+                    req,
+                    "channels.pushover.app_token or user_key",
+                    "copy both again from pushover.net",
+                )
+                .await?;
+                let devices = body["devices"].as_array().map_or(0, Vec::len);
+                Ok(format!("{devices} device(s)"))
+            }
+            .boxed(),
+        ));
+    }
+    if channels.ntfy.enabled {
+        let server = channels.ntfy.server.trim_end_matches('/').to_string();
+        let health = http.get(format!("{server}/v1/health"));
+        let account = channels
+            .ntfy
+            .token
+            .as_ref()
+            .map(|token| http.get(format!("{server}/v1/account")).bearer_auth(token));
+        checks.push((
+            "ntfy".to_string(),
+            async move {
+                call(health, "channels.ntfy.server", "check the server URL").await?;
+                let Some(account) = account else {
+                    return Ok("healthy".to_string());
+                };
+                let body = call(
+                    account,
+                    "channels.ntfy.token",
+                    "create a new access token in the ntfy web app",
+                )
+                .await?;
+                Ok(format!(
+                    "healthy, signed in as {}",
+                    body["username"].as_str().unwrap_or("?")
+                ))
+            }
+            .boxed(),
+        ));
+    }
+    if channels.imessage.enabled {
+        let source_db = channels.imessage.source_db.clone();
+        checks.push(("imessage".to_string(), imessage(source_db).boxed()));
+    }
+    if cfg.tools.todoist.enabled {
+        let req = http
+            .get("https://api.todoist.com/api/v1/projects")
+            .bearer_auth(cfg.tools.todoist.api_token.trim());
+        checks.push((
+            "todoist".to_string(),
+            async move {
+                call(
+                    req,
+                    "tools.todoist.api_token",
+                    "copy it again from Todoist settings, Integrations, Developer",
+                )
+                .await?;
+                Ok("authorized".to_string())
+            }
+            .boxed(),
+        ));
+    }
+    if cfg.tools.email.enabled {
+        let email = &cfg.tools.email;
+        let req = http.post("https://oauth2.googleapis.com/token").form(&[
+            ("grant_type", "refresh_token"),
+            ("client_id", email.gmail_client_id.trim()),
+            ("client_secret", email.gmail_client_secret.trim()),
+            ("refresh_token", email.gmail_refresh_token.trim()),
+        ]);
+        checks.push((
+            "email".to_string(),
+            async move {
+                let body = call(
+                    req,
+                    "tools.email.gmail_refresh_token",
+                    "authorize again with gmail.compose and gmail.readonly (gmail.modify for changes)",
+                )
+                .await?;
+                Ok(format!(
+                    "authorized ({})",
+                    body["scope"].as_str().unwrap_or("scopes unknown")
+                ))
+            }
+            .boxed(),
+        ));
+    }
+
+    join_all(checks.into_iter().map(|(name, check)| async move {
+        let started = Instant::now();
+        let outcome = check.await;
+        CheckResult {
+            name,
+            latency: started.elapsed(),
+            outcome,
+        }
+    }))
+    .await
+}
+
+/// One model-list call per provider the configured models use.
+fn llm_checks(
+    cfg: &OpenShellConfig,
+    http: &reqwest::Client,
+) -> Vec<(&'static str, BoxFuture<'static, Result<String, Failure>>)> {
+    let models = std::iter::once(&cfg.general.model)
+        .chain(cfg.personas.values().filter_map(|p| p.model.as_ref()))
+        .chain(cfg.tools.results.model.as_ref())
+        .chain(cfg.translation.model.as_ref());
+    let mut providers: Vec<os_llm::Provider> = Vec::new();
+    for model in models {
+        let provider = os_llm::Provider::for_model(model);
+        if !providers.contains(&provider) {
+            providers.push(provider);
+        }
+    }
+
+    let mut checks = Vec::new();
+    for provider in providers {
+        let (name, credential, req) = match provider {
+            os_llm::Provider::OpenAI => match cfg.azure_openai.endpoint.as_deref() {
+                Some(endpoint) => {
+                    let Some(key) = cfg.azure_openai.api_key.as_deref() else {
+                        // Entra ID sign-in happens per request; nothing cheap to probe.
+                        continue;
+                    };
+                    let url = format!(
+                        "{}/openai/models?api-version={}",
+                        endpoint.trim_end_matches('/'),
+                        cfg.azure_openai.api_version
+                    );
+                    (
+                        "azure",
+                        "azure_openai.api_key",
+                        Some(http.get(url).header("api-key", key)),
+                    )
+                }
+                None => (
+                    "openai",
+                    "keys.openai_api_key",
+                    cfg.keys
+                        .openai_api_key
+                        .as_deref()
+                        .filter(|k| !k.is_empty())
+                        .map(|key| {
+                            http.get("https://api.openai.com/v1/models")
+                                .bearer_auth(key)
+                        }),
+                ),
+            },
+            os_llm::Provider::Anthropic => (
+                "anthropic",
+                "keys.anthropic_api_key",
+                cfg.keys
+                    .anthropic_api_key
+                    .as_deref()
+                    .filter(|k| !k.is_empty())
+                    .map(|key| {
+                        http.get("https://api.anthropic.com/v1/models?limit=1000")
+                            .header("x-api-key", key)
+                            .header("anthropic-version", ANTHROPIC_VERSION)
+                    }),
+            ),
+            os_llm::Provider::OpenRouter => (
+                "openrouter",
+                "keys.openrouter_api_key",
+                cfg.keys
+                    .openrouter_api_key
+                    .as_deref()
+                    .filter(|k| !k.is_empty())
+                    .map(|key| {
+                        http.get("https://openrouter.ai/api/v1/key")
+                            .bearer_auth(key)
+                    }),
+            ),
+        };
+        let check = async move {
+            let Some(req) = req else {
+                return Err(Failure {
+                    error: "no API key".to_string(),
+                    hint: format!("set {credential}"),
+                });
+            };
+            let body = call(
+                req,
+                credential,
+                "create a new key in the provider's console",
+            )
+            .await?;
+            Ok(match body["data"].as_array() {
+                Some(models) => format!("{} models", models.len()),
+                None => "key accepted".to_string(),
+            })
+        };
+        checks.push((name, check.boxed()));
+    }
+    checks
+}
+
+async fn imessage(source_db: Option<String>) -> Result<String, Failure> {
+    let path = match source_db.map(|p| expand_home(&p)).transpose() {
+        Ok(path) => path.unwrap_or_else(os_channels::ImessageAdapter::default_source_db),
+        Err(e) => {
+            return Err(Failure {
+                error: e.to_string(),
+                hint: "check channels.imessage.source_db".to_string(),
+            })
+        }
+    };
+    let shown = path.display().to_string();
+    tokio::task::spawn_blocking(move || read_chat_db(path))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|r| r)
+        .map(|rows| format!("{rows} messages in {shown}"))
+        .map_err(|error| Failure {
+            error,
+            hint: "grant Full Disk Access to the app running opencraw (System Settings, \
+                   Privacy & Security), or fix channels.imessage.source_db"
+                .to_string(),
+        })
+}
+
+fn read_chat_db(path: PathBuf) -> Result<i64, String> {
+    let conn =
+        rusqlite::Connection::open_with_flags(&path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| e.to_string())?;
+    conn.query_row("SELECT COUNT(*) FROM message", [], |row| row.get(0))
+        .map_err(|e| e.to_string())
+}
+
+/// Send `req` and return its JSON body, or why it failed and what to do about it.
+/// `credential` names the config field; `fix` says how to replace a rejected one.
+async fn call(req: RequestBuilder, credential: &str, fix: &str) -> Result<Value, Failure> {
+    let resp = req.send().await.map_err(|e| Failure {
+        hint: if e.is_timeout() {
+            format!(
+                "no answer within {}s; check network access and any proxy",
+                TIMEOUT.as_secs()
+            )
+        } else {
+            "could not connect; check DNS, network access and any proxy".to_string()
+        },
+        error: e.to_string(),
+    })?;
+    let status = resp.status();
+    if !status.is_success() {
+        return Err(Failure {
+            error: format!("status {status}"),
+            hint: status_hint(status, credential, fix),
+        });
+    }
+    Ok(resp.json().await.unwrap_or_default())
+}
+
+fn status_hint(status: StatusCode, credential: &str, fix: &str) -> String {
+    match status {
+        // Telegram answers 404 for a malformed token.
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::NOT_FOUND => {
+            format!("{credential} was rejected; {fix}")
+        }
+        StatusCode::TOO_MANY_REQUESTS => "rate limited; try again in a minute".to_string(),
+        s if s.is_server_error() => {
+            "the service is having trouble; check its status page and try again".to_string()
+        }
+        _ => format!("unexpected answer; check {credential}"),
+    }
+}
+
+/// Text report; failures get their hint on the following line.
+pub fn render(results: &[CheckResult]) -> String {
+    let width = results.iter().map(|r| r.name.len()).max().unwrap_or(0);
+    let mut out = String::new();
+    for r in results {
+        let ms = r.latency.as_millis();
+        match &r.outcome {
+            Ok(detail) => {
+                out.push_str(&format!("ok    {:<width$}  {ms:>5} ms  {detail}\n", r.name))
+            }
+            Err(f) => {
+                out.push_str(&format!(
+                    "FAIL  {:<width$}  {ms:>5} ms  {}\n",
+                    r.name, f.error
+                ));
+                out.push_str(&format!("      fix: {}\n", f.hint));
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failures_say_what_to_change() {
+        assert_eq!(
+            status_hint(
+                StatusCode::UNAUTHORIZED,
+                "keys.openai_api_key",
+                "make a new one"
+            ),
+            "keys.openai_api_key was rejected; make a new one"
+        );
+        assert!(status_hint(StatusCode::BAD_GATEWAY, "x", "y").contains("status page"));
+
+        let text = render(&[
+            CheckResult {
+                name: "telegram".to_string(),
+                latency: Duration::from_millis(182),
+                outcome: Ok("@opencraw_bot".to_string()),
+            },
+            CheckResult {
+                name: "llm openai".to_string(),
+                latency: Duration::from_millis(95),
+                outcome: Err(Failure {
+                    error: "status 401 Unauthorized".to_string(),
+                    hint: "keys.openai_api_key was rejected".to_string(),
+                }),
+            },
+        ]);
+        assert_eq!(
+            text,
+            "ok    telegram      182 ms  @opencraw_bot\n\
+             FAIL  llm openai     95 ms  status 401 Unauthorized\n      \
+             fix: keys.openai_api_key was rejected\n"
+        );
+    }
+}
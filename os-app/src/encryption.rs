@@ -0,0 +1,280 @@
+//! Encryption at rest for the data directory.
+//!
+//! With `[encryption]` on, message text in `conversations.db`, fact text in `facts.db`,
+//! answer excerpts in `feedback.db`, undelivered messages in `outbox.db` and each line of
+//! `audit.jsonl` and `inbound.jsonl` are stored as `enc:v1:<base64(nonce || ciphertext || tag)>`, sealed with
+//! ChaCha20-Poly1305 under a 256-bit key from config (usually a `keychain:` or `op://`
+//! reference, see `secrets`). Values without the prefix are read as plaintext, so data
+//! written before encryption was turned on stays readable until `opencraw encrypt-data`
+//! converts it.
+//!
+//! Metadata (channel, sender, timestamps) and archive embeddings stay in the clear so
+//! listing and semantic search keep working.
+
+use crate::config::EncryptionConfig;
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use rusqlite::{params, Connection};
+use std::path::Path;
+use std::sync::Arc;
+
+const PREFIX: &str = "enc:v1:";
+
+pub struct DataCipher {
+    key: LessSafeKey,
+    rng: SystemRandom,
+}
+
+impl DataCipher {
+    /// `None` when encryption is off.
+    pub fn from_config(cfg: &EncryptionConfig) -> Result<Option<Arc<Self>>> {
+        if !cfg.enabled {
+            return Ok(None);
+        }
+        Self::from_key(&cfg.key).map(|c| Some(Arc::new(c)))
+    }
+
+    /// A base64-encoded 32-byte key, e.g. from `openssl rand -base64 32`.
+    pub fn from_key(key: &str) -> Result<Self> {
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(key.trim())
+            .map_err(|_| anyhow!("encryption.key must be base64"))?;
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &bytes)
+            .map_err(|_| anyhow!("encryption.key must decode to 32 bytes"))?;
+        Ok(Self {
+            key: LessSafeKey::new(key),
+            rng: SystemRandom::new(),
+        })
+    }
+
+    pub fn is_sealed(value: &str) -> bool {
+        value.starts_with(PREFIX)
+    }
+
+    pub fn seal(&self, plaintext: &str) -> Result<String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| anyhow!("no randomness for an encryption nonce"))?;
+        let mut buf = plaintext.as_bytes().to_vec();
+        self.key
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut buf)
+            .map_err(|_| anyhow!("encryption failed"))?;
+        let mut out = nonce.to_vec();
+        out.extend(buf);
+        Ok(format!(
+            "{PREFIX}{}",
+            base64::engine::general_purpose::STANDARD.encode(out)
+        ))
+    }
+
+    /// Decrypt a sealed value; anything else is returned as is.
+    pub fn open(&self, value: &str) -> Result<String> {
+        let Some(encoded) = value.strip_prefix(PREFIX) else {
+            return Ok(value.to_string());
+        };
+        let mut bytes = base64::engine::general_purpose::STANDARD
+            .decode(encoded.trim_end())
+            .map_err(|_| anyhow!("encrypted value is not valid base64"))?;
+        if bytes.len() < NONCE_LEN {
+            return Err(anyhow!("encrypted value is truncated"));
+        }
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce.copy_from_slice(&bytes[..NONCE_LEN]);
+        let plaintext = self
+            .key
+            .open_in_place(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::empty(),
+                &mut bytes[NONCE_LEN..],
+            )
+            .map_err(|_| anyhow!("can't decrypt data: wrong encryption.key or corrupted value"))?;
+        String::from_utf8(plaintext.to_vec()).map_err(|_| anyhow!("decrypted value isn't UTF-8"))
+    }
+}
+
+/// Read a value that may or may not be sealed, with or without a cipher.
+pub fn open_value(cipher: Option<&DataCipher>, value: String) -> Result<String> {
+    match cipher {
+        Some(cipher) => cipher.open(&value),
+        None if DataCipher::is_sealed(&value) => Err(anyhow!(
+            "data is encrypted but [encryption] is off; enable it with the same key"
+        )),
+        None => Ok(value),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MigrationReport {
+    pub archive_rows: usize,
+    pub fact_rows: usize,
+    pub feedback_rows: usize,
+    pub dead_letter_rows: usize,
+    pub audit_lines: usize,
+    pub inbound_lines: usize,
+}
+
+/// Convert existing data in `data_dir` to sealed values (or back, with `decrypt`).
+/// Values already in the target form are skipped, so it's safe to run twice. The server
+/// must be stopped: the audit log and inbound journal are rewritten in place.
+pub fn migrate(data_dir: &Path, cipher: &DataCipher, decrypt: bool) -> Result<MigrationReport> {
+    let convert = |value: &str| -> Result<Option<String>> {
+        match (decrypt, DataCipher::is_sealed(value)) {
+            (false, false) => cipher.seal(value).map(Some),
+            (true, true) => cipher.open(value).map(Some),
+            _ => Ok(None),
+        }
+    };
+    let mut report = MigrationReport::default();
+
+    report.archive_rows = migrate_column(
+        &data_dir.join("conversations.db"),
+        "conversation_messages",
+        "content",
+        &convert,
+    )?;
+    report.fact_rows = migrate_column(&data_dir.join("facts.db"), "facts", "text", &convert)?;
+    report.feedback_rows = migrate_column(
+        &data_dir.join("feedback.db"),
+        "feedback",
+        "answer",
+        &convert,
+    )?;
+    report.dead_letter_rows = migrate_column(
+        &data_dir.join("outbox.db"),
+        "dead_letters",
+        "parts",
+        &convert,
+    )?;
+    report.audit_lines = migrate_lines(&data_dir.join("audit.jsonl"), &convert)?;
+    report.inbound_lines = migrate_lines(&data_dir.join("inbound.jsonl"), &convert)?;
+    Ok(report)
+}
+
+/// Converts one text column of a SQLite table; returns how many rows changed.
+fn migrate_column(
+    path: &Path,
+    table: &str,
+    column: &str,
+    convert: &dyn Fn(&str) -> Result<Option<String>>,
+) -> Result<usize> {
+    if !path.exists() {
+        return Ok(0);
+    }
+    let mut conn = Connection::open(path).with_context(|| format!("open {}", path.display()))?;
+    let tx = conn.transaction()?;
+    let rows: Vec<(i64, String)> = {
+        let mut stmt = tx.prepare(&format!(
+            "SELECT rowid, {column} FROM {table} WHERE {column} IS NOT NULL"
+        ))?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        rows
+    };
+    let mut converted_rows = 0;
+    for (rowid, value) in rows {
+        if let Some(value) = convert(&value)? {
+            tx.execute(
+                &format!("UPDATE {table} SET {column} = ?1 WHERE rowid = ?2"),
+                params![value, rowid],
+            )?;
+            converted_rows += 1;
+        }
+    }
+    tx.commit()?;
+    // Plaintext can linger in free pages until they're reused.
+    conn.execute_batch("VACUUM")?;
+    Ok(converted_rows)
+}
+
+/// Converts each line of a JSONL file; returns how many changed.
+fn migrate_lines(path: &Path, convert: &dyn Fn(&str) -> Result<Option<String>>) -> Result<usize> {
+    if !path.exists() {
+        return Ok(0);
+    }
+    let raw = std::fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+    let mut out = String::with_capacity(raw.len());
+    let mut converted_lines = 0;
+    for line in raw.lines().filter(|l| !l.trim().is_empty()) {
+        match convert(line)? {
+            Some(converted) => {
+                out.push_str(&converted);
+                converted_lines += 1;
+            }
+            None => out.push_str(line),
+        }
+        out.push('\n');
+    }
+    let tmp = path.with_extension("jsonl.tmp");
+    std::fs::write(&tmp, out)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(converted_lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    const KEY: &str = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+
+    #[test]
+    fn seals_opens_and_migrates() {
+        let cipher = DataCipher::from_key(KEY).unwrap();
+        let sealed = cipher.seal("meet at 6").unwrap();
+        assert!(DataCipher::is_sealed(&sealed));
+        assert_ne!(sealed, cipher.seal("meet at 6").unwrap());
+        assert_eq!(cipher.open(&sealed).unwrap(), "meet at 6");
+        assert_eq!(cipher.open("legacy plaintext").unwrap(), "legacy plaintext");
+        assert!(open_value(None, sealed.clone()).is_err());
+
+        let other =
+            DataCipher::from_key(&base64::engine::general_purpose::STANDARD.encode([7u8; 32]))
+                .unwrap();
+        assert!(other.open(&sealed).is_err());
+        assert!(DataCipher::from_key("c2hvcnQ=").is_err());
+
+        let tmp = std::env::temp_dir().join(format!("opencraw-encryption-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&tmp).unwrap();
+        std::fs::write(tmp.join("audit.jsonl"), "{\"a\":1}\n{\"b\":2}\n").unwrap();
+        let conn = Connection::open(tmp.join("conversations.db")).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE conversation_messages (id INTEGER PRIMARY KEY, content TEXT NOT NULL);
+             INSERT INTO conversation_messages (content) VALUES ('hello');",
+        )
+        .unwrap();
+        drop(conn);
+        let conn = Connection::open(tmp.join("feedback.db")).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE feedback (id TEXT PRIMARY KEY, answer TEXT);
+             INSERT INTO feedback VALUES ('a', 'the lake house'), ('b', NULL);",
+        )
+        .unwrap();
+        drop(conn);
+
+        let report = migrate(&tmp, &cipher, false).unwrap();
+        assert_eq!((report.archive_rows, report.audit_lines), (1, 2));
+        assert_eq!((report.feedback_rows, report.fact_rows), (1, 0));
+        let conn = Connection::open(tmp.join("feedback.db")).unwrap();
+        let answer: String = conn
+            .query_row("SELECT answer FROM feedback WHERE id = 'a'", [], |r| {
+                r.get(0)
+            })
+            .unwrap();
+        assert!(DataCipher::is_sealed(&answer));
+        drop(conn);
+        assert_eq!(migrate(&tmp, &cipher, false).unwrap().audit_lines, 0);
+        let audit = std::fs::read_to_string(tmp.join("audit.jsonl")).unwrap();
+        assert!(audit.lines().all(DataCipher::is_sealed));
+
+        migrate(&tmp, &cipher, true).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(tmp.join("audit.jsonl")).unwrap(),
+            "{\"a\":1}\n{\"b\":2}\n"
+        );
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+}
@@ -0,0 +1,910 @@
+//! Long-term facts about the people the assistant talks to.
+//!
+//! After each completed turn a short LLM pass reads the exchange and distills durable
+//! facts and preferences ("Prefers short replies", "Works at Acme, timezone PST"). Facts
+//! are kept per person in `data/facts.db`: per `channel:sender`, or per
+//! `identities.people` name when the sender is linked, so what's learned on one channel
+//! applies on the others. The newest are listed in the system prompt. A fact that
+//! normalizes to one already known (case, punctuation, spacing) is not stored twice, and
+//! `/api/v1/os/memory/facts` lists, adds, edits and deletes them.
+//!
+//! Users manage their own facts with `/memory` (list), `/memory forget <id>` and
+//! `/memory pin <id or new fact>`. Pinned facts are always in the system prompt, however
+//! many others there are, and neither extraction nor retention ever changes or drops them.
+//!
+//! `[memory.retention]` keeps the store bounded: after each extraction, and on a timer for
+//! everyone, unpinned facts of a type (`extracted`, `api`, `command`) not confirmed
+//! within that type's `max_age_days` expire, and a person with more than
+//! `max_items_per_scope` loses the lowest-weighted ones. A fact's weight is its importance
+//! (1.0 for facts a user or the API added; 0.5 for extracted ones, plus 0.1 each time the
+//! extraction pass found it again) halved every `recency_half_life_days` since it was
+//! last confirmed.
+
+use crate::config::{IdentitiesConfig, MemoryRetentionConfig};
+use crate::encryption::{open_value, DataCipher};
+use crate::identities;
+use crate::sqlite::SqlitePool;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use os_llm::{ChatMessage, LlmClient, Role};
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Longest fact accepted; facts are meant to be one short statement.
+const FACT_CHARS_MAX: usize = 300;
+/// Unpinned facts listed in the system prompt, newest first.
+const PROMPT_FACTS_MAX: usize = 30;
+/// Characters of each side of the exchange shown to the extraction pass.
+const EXCHANGE_CHARS_MAX: usize = 4000;
+
+/// Fact types, by how they were learned; `memory.retention.max_age_days` is keyed by these.
+pub const FACT_TYPES: &[&str] = &["extracted", "api", "command"];
+
+const SELECT_FACT: &str =
+    "SELECT id, subject, text, source, pinned, confirmations, created_at, updated_at FROM facts";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemoryCommand {
+    List,
+    Forget {
+        id: String,
+    },
+    /// Pin the fact with this id, or remember this text as a new pinned fact.
+    Pin {
+        target: String,
+    },
+    Unpin {
+        id: String,
+    },
+}
+
+impl MemoryCommand {
+    /// `/memory`, `/memory list`, `/memory forget <id>`, `/memory pin <id or fact>`,
+    /// `/memory unpin <id>`.
+    pub fn parse(input: &str) -> Option<Result<Self, String>> {
+        let rest = input.trim().strip_prefix("/memory")?;
+        if !rest.is_empty() && !rest.starts_with(' ') {
+            return None;
+        }
+        let rest = rest.trim();
+        let (verb, arg) = match rest.split_once(char::is_whitespace) {
+            Some((verb, arg)) => (verb, arg.trim()),
+            None => (rest, ""),
+        };
+        Some(match (verb, arg) {
+            ("" | "list", "") => Ok(Self::List),
+            ("forget", id) if !id.is_empty() => Ok(Self::Forget { id: id.to_string() }),
+            ("pin", target) if !target.is_empty() => Ok(Self::Pin {
+                target: target.to_string(),
+            }),
+            ("unpin", id) if !id.is_empty() => Ok(Self::Unpin { id: id.to_string() }),
+            _ => Err(
+                "Usage: /memory, /memory forget <id>, /memory pin <id or fact>, /memory unpin <id>"
+                    .to_string(),
+            ),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Fact {
+    pub id: String,
+    /// Who the fact is about: `channel:sender` or an `identities.people` name.
+    pub subject: String,
+    pub text: String,
+    /// `extracted`, `api` or `command`.
+    pub source: String,
+    /// Always listed in the system prompt and left alone by extraction and retention.
+    pub pinned: bool,
+    /// Times extraction found the fact again after it was first stored.
+    pub confirmations: u32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Who facts from `channel_id:sender_id` are filed under.
+pub fn subject_for(cfg: &IdentitiesConfig, channel_id: &str, sender_id: &str) -> String {
+    identities::linked_senders(cfg, channel_id, sender_id)
+        .map(|(person, _)| person)
+        .unwrap_or_else(|| format!("{channel_id}:{sender_id}"))
+}
+
+pub struct FactStore {
+    db: Arc<SqlitePool>,
+    cipher: Option<Arc<DataCipher>>,
+    retention: MemoryRetentionConfig,
+}
+
+impl FactStore {
+    pub fn open(path: &Path) -> Result<Self> {
+        let db = SqlitePool::open(
+            path,
+            "CREATE TABLE IF NOT EXISTS facts (
+                id TEXT PRIMARY KEY,
+                subject TEXT NOT NULL,
+                text TEXT NOT NULL,
+                fingerprint TEXT NOT NULL,
+                source TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL,
+                pinned INTEGER NOT NULL DEFAULT 0,
+                confirmations INTEGER NOT NULL DEFAULT 0,
+                UNIQUE(subject, fingerprint)
+            );
+            CREATE INDEX IF NOT EXISTS facts_subject ON facts(subject, updated_at);",
+        )?;
+        Ok(Self {
+            db: Arc::new(db),
+            cipher: None,
+            retention: MemoryRetentionConfig::default(),
+        })
+    }
+
+    pub fn with_retention(mut self, retention: MemoryRetentionConfig) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Seal the text of new and edited facts with `cipher`.
+    pub fn with_cipher(mut self, cipher: Option<Arc<DataCipher>>) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    pub fn sqlite_pool(&self) -> Arc<SqlitePool> {
+        self.db.clone()
+    }
+
+    /// Facts about `subject` (or everyone): pinned ones, then the most recently added or
+    /// confirmed.
+    pub fn list(&self, subject: Option<&str>) -> Result<Vec<Fact>> {
+        let conn = self.db.read()?;
+        let mut stmt = conn.prepare(&format!(
+            "{SELECT_FACT} WHERE ?1 IS NULL OR subject = ?1 ORDER BY pinned DESC, updated_at DESC"
+        ))?;
+        let rows = stmt
+            .query_map(params![subject], raw_fact)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        rows.into_iter().map(|raw| self.open_fact(raw)).collect()
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<Fact>> {
+        let conn = self.db.read()?;
+        let raw = conn
+            .query_row(
+                &format!("{SELECT_FACT} WHERE id = ?1"),
+                params![id],
+                raw_fact,
+            )
+            .optional()?;
+        raw.map(|raw| self.open_fact(raw)).transpose()
+    }
+
+    /// Store a fact, or mark the matching one as confirmed again and return it.
+    pub fn add(&self, subject: &str, text: &str, source: &str) -> Result<Fact> {
+        let text = checked_text(text)?;
+        if subject.trim().is_empty() {
+            return Err(anyhow!("subject is empty"));
+        }
+        let fingerprint = fingerprint(&text);
+        let now = Utc::now();
+        {
+            let conn = self.db.write()?;
+            let touched = conn.execute(
+                "UPDATE facts SET updated_at = ?3, confirmations = confirmations + 1
+                 WHERE subject = ?1 AND fingerprint = ?2",
+                params![subject, fingerprint, now.to_rfc3339()],
+            )?;
+            if touched == 0 {
+                conn.execute(
+                    "INSERT INTO facts (id, subject, text, fingerprint, source, created_at, updated_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)",
+                    params![
+                        new_id(),
+                        subject,
+                        self.seal(&text)?,
+                        fingerprint,
+                        source,
+                        now.to_rfc3339()
+                    ],
+                )?;
+            }
+        }
+        self.find(subject, &fingerprint)?
+            .ok_or_else(|| anyhow!("fact vanished after it was stored"))
+    }
+
+    /// Replace a fact's text. `None` if there is no such fact.
+    pub fn update(&self, id: &str, text: &str, source: &str) -> Result<Option<Fact>> {
+        let text = checked_text(text)?;
+        let Some(fact) = self.get(id)? else {
+            return Ok(None);
+        };
+        let fingerprint = fingerprint(&text);
+        if let Some(other) = self.find(&fact.subject, &fingerprint)? {
+            if other.id != id {
+                return Err(anyhow!("fact {} already says that", other.id));
+            }
+        }
+        self.db.write()?.execute(
+            "UPDATE facts SET text = ?2, fingerprint = ?3, source = ?4, updated_at = ?5
+             WHERE id = ?1",
+            params![
+                id,
+                self.seal(&text)?,
+                fingerprint,
+                source,
+                Utc::now().to_rfc3339()
+            ],
+        )?;
+        self.get(id)
+    }
+
+    /// Pin or unpin a fact. `None` if there is no such fact.
+    pub fn set_pinned(&self, id: &str, pinned: bool) -> Result<Option<Fact>> {
+        let changed = self.db.write()?.execute(
+            "UPDATE facts SET pinned = ?2 WHERE id = ?1",
+            params![id, pinned],
+        )?;
+        if changed == 0 {
+            return Ok(None);
+        }
+        self.get(id)
+    }
+
+    pub fn delete(&self, id: &str) -> Result<bool> {
+        let deleted = self
+            .db
+            .write()?
+            .execute("DELETE FROM facts WHERE id = ?1", params![id])?;
+        Ok(deleted > 0)
+    }
+
+    /// Apply `[memory.retention]` to `subject`'s facts, or everyone's, and return how many
+    /// were dropped.
+    pub fn enforce_retention(&self, subject: Option<&str>) -> Result<usize> {
+        let now = Utc::now();
+        let mut dropped = 0;
+        for (kind, days) in self.retention.max_age_days.iter().filter(|(_, d)| **d > 0) {
+            let cutoff = now - chrono::Duration::days(*days as i64);
+            dropped += self.db.write()?.execute(
+                "DELETE FROM facts WHERE pinned = 0 AND source = ?1 AND updated_at < ?2
+                 AND (?3 IS NULL OR subject = ?3)",
+                params![kind, cutoff.to_rfc3339(), subject],
+            )?;
+        }
+
+        let max = self.retention.max_items_per_scope;
+        let crowded: Vec<String> = {
+            let conn = self.db.read()?;
+            let mut stmt = conn.prepare(
+                "SELECT subject FROM facts WHERE pinned = 0 AND (?1 IS NULL OR subject = ?1)
+                 GROUP BY subject HAVING COUNT(*) > ?2",
+            )?;
+            let rows = stmt
+                .query_map(params![subject, max as i64], |row| row.get(0))?
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            rows
+        };
+        for subject in crowded {
+            // Oldest first, so that equal weights evict the older fact.
+            let mut facts: Vec<(f64, String)> = self
+                .list(Some(&subject))?
+                .iter()
+                .rev()
+                .filter(|f| !f.pinned)
+                .map(|f| (self.weight(f, now), f.id.clone()))
+                .collect();
+            facts.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let excess = facts.len().saturating_sub(max);
+            for (_, id) in facts.into_iter().take(excess) {
+                if self.delete(&id)? {
+                    dropped += 1;
+                }
+            }
+        }
+        Ok(dropped)
+    }
+
+    /// Enforce retention for everyone every `memory.retention.interval_minutes`.
+    pub fn start_janitor(self: Arc<Self>) {
+        let every = Duration::from_secs(self.retention.interval_minutes * 60);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            loop {
+                interval.tick().await;
+                match self.enforce_retention(None) {
+                    Ok(0) => {}
+                    Ok(dropped) => tracing::info!(dropped, "expired facts dropped"),
+                    Err(e) => tracing::warn!(%e, "fact retention failed"),
+                }
+            }
+        });
+    }
+
+    /// Importance, halved every `recency_half_life_days` since the fact was last confirmed.
+    fn weight(&self, fact: &Fact, now: DateTime<Utc>) -> f64 {
+        let importance = match fact.source.as_str() {
+            "extracted" => (0.5 + 0.1 * fact.confirmations as f64).min(1.0),
+            _ => 1.0,
+        };
+        let age_days = (now - fact.updated_at).num_milliseconds().max(0) as f64 / 86_400_000.0;
+        let half_life = self.retention.recency_half_life_days.max(1) as f64;
+        importance * 0.5f64.powf(age_days / half_life)
+    }
+
+    /// The system prompt section for `subject`, or `None` when nothing is known.
+    pub fn prompt_section(&self, subject: &str) -> Option<String> {
+        let facts = match self.list(Some(subject)) {
+            Ok(facts) => facts,
+            Err(e) => {
+                tracing::warn!(%e, "failed to load facts for the system prompt");
+                return None;
+            }
+        };
+        if facts.is_empty() {
+            return None;
+        }
+        let mut out = "What you know about this user from earlier conversations:".to_string();
+        let (pinned, rest): (Vec<&Fact>, Vec<&Fact>) = facts.iter().partition(|f| f.pinned);
+        for fact in pinned
+            .into_iter()
+            .chain(rest.into_iter().take(PROMPT_FACTS_MAX))
+        {
+            out.push_str("\n- ");
+            out.push_str(&fact.text);
+        }
+        Some(out)
+    }
+
+    /// Carry out a `/memory` command from `subject`, who can only see and change facts
+    /// about themselves, and return the reply.
+    pub fn apply(&self, command: MemoryCommand, subject: &str) -> String {
+        let own = |id: &str| self.get(id).map(|f| f.filter(|f| f.subject == subject));
+        let outcome = match command {
+            MemoryCommand::List => self.list(Some(subject)).map(|facts| render_list(&facts)),
+            MemoryCommand::Forget { id } => own(&id).and_then(|fact| match fact {
+                Some(fact) => self
+                    .delete(&fact.id)
+                    .map(|_| format!("Forgot: {}", fact.text)),
+                None => Ok(format!("No remembered fact {id}. /memory lists them.")),
+            }),
+            MemoryCommand::Pin { target } => own(&target).and_then(|fact| match fact {
+                Some(fact) => self
+                    .set_pinned(&fact.id, true)
+                    .map(|_| format!("Pinned: {}", fact.text)),
+                None if is_fact_id(&target) => {
+                    Ok(format!("No remembered fact {target}. /memory lists them."))
+                }
+                None => self
+                    .add(subject, &target, "command")
+                    .and_then(|fact| self.set_pinned(&fact.id, true).map(|_| fact))
+                    .map(|fact| format!("Remembered and pinned ({}): {}", fact.id, fact.text)),
+            }),
+            MemoryCommand::Unpin { id } => own(&id).and_then(|fact| match fact {
+                Some(fact) => self
+                    .set_pinned(&fact.id, false)
+                    .map(|_| format!("Unpinned: {}", fact.text)),
+                None => Ok(format!("No remembered fact {id}. /memory lists them.")),
+            }),
+        };
+        outcome.unwrap_or_else(|e| format!("Memory command failed: {e}"))
+    }
+
+    fn find(&self, subject: &str, fingerprint: &str) -> Result<Option<Fact>> {
+        let conn = self.db.read()?;
+        let raw = conn
+            .query_row(
+                &format!("{SELECT_FACT} WHERE subject = ?1 AND fingerprint = ?2"),
+                params![subject, fingerprint],
+                raw_fact,
+            )
+            .optional()?;
+        raw.map(|raw| self.open_fact(raw)).transpose()
+    }
+
+    fn seal(&self, text: &str) -> Result<String> {
+        match self.cipher.as_deref() {
+            Some(cipher) => cipher.seal(text),
+            None => Ok(text.to_string()),
+        }
+    }
+
+    fn open_fact(&self, mut raw: Fact) -> Result<Fact> {
+        raw.text = open_value(self.cipher.as_deref(), raw.text)?;
+        Ok(raw)
+    }
+}
+
+/// A row as stored; `text` may still be sealed.
+fn raw_fact(row: &rusqlite::Row<'_>) -> rusqlite::Result<Fact> {
+    let time = |i: usize| -> rusqlite::Result<DateTime<Utc>> {
+        let s: String = row.get(i)?;
+        Ok(DateTime::parse_from_rfc3339(&s)
+            .map(|t| t.with_timezone(&Utc))
+            .unwrap_or_else(|_| Utc::now()))
+    };
+    Ok(Fact {
+        id: row.get(0)?,
+        subject: row.get(1)?,
+        text: row.get(2)?,
+        source: row.get(3)?,
+        pinned: row.get(4)?,
+        confirmations: row.get(5)?,
+        created_at: time(6)?,
+        updated_at: time(7)?,
+    })
+}
+
+fn render_list(facts: &[Fact]) -> String {
+    if facts.is_empty() {
+        return "I haven't remembered anything about you yet.".to_string();
+    }
+    let mut out = "What I remember about you:".to_string();
+    for fact in facts {
+        out.push_str(&format!(
+            "\n{}{} {}",
+            fact.id,
+            if fact.pinned { " (pinned)" } else { "" },
+            fact.text
+        ));
+    }
+    out.push_str(
+        "\n\n/memory forget <id> removes one; /memory pin <id> keeps it in every conversation.",
+    );
+    out
+}
+
+fn checked_text(text: &str) -> Result<String> {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.is_empty() {
+        return Err(anyhow!("fact is empty"));
+    }
+    if text.chars().count() > FACT_CHARS_MAX {
+        return Err(anyhow!("facts are limited to {FACT_CHARS_MAX} characters"));
+    }
+    Ok(text)
+}
+
+/// Identifies a fact regardless of case, punctuation and spacing, without keeping its text
+/// in the clear when facts are sealed.
+fn fingerprint(text: &str) -> String {
+    let normalized = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ");
+    hex::encode(Sha256::digest(normalized.as_bytes()))
+}
+
+fn new_id() -> String {
+    Uuid::new_v4().simple().to_string()[..8].to_string()
+}
+
+/// Whether `s` looks like an id from `new_id`, rather than the text of a fact.
+fn is_fact_id(s: &str) -> bool {
+    s.len() == 8 && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Changes the extraction pass asks for.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+pub struct FactChanges {
+    #[serde(default)]
+    pub add: Vec<String>,
+    #[serde(default)]
+    pub update: Vec<FactEdit>,
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct FactEdit {
+    pub id: String,
+    pub text: String,
+}
+
+/// Runs the extraction pass after each turn and files what it finds.
+pub struct FactExtractor {
+    llm: LlmClient,
+    store: Arc<FactStore>,
+}
+
+impl FactExtractor {
+    pub fn new(llm: LlmClient, store: Arc<FactStore>) -> Self {
+        Self { llm, store }
+    }
+
+    /// Extract in the background, so the reply isn't held up by a second model call.
+    pub fn observe(self: &Arc<Self>, subject: String, user_message: &str, assistant_message: &str) {
+        let this = self.clone();
+        let (user, assistant) = (user_message.to_string(), assistant_message.to_string());
+        tokio::spawn(async move {
+            if let Err(e) = this.extract(&subject, &user, &assistant).await {
+                tracing::warn!(%e, %subject, "fact extraction failed");
+            }
+        });
+    }
+
+    #[tracing::instrument(level = "info", skip_all, fields(subject = %subject))]
+    pub async fn extract(
+        &self,
+        subject: &str,
+        user_message: &str,
+        assistant_message: &str,
+    ) -> Result<FactChanges> {
+        let known = self.store.list(Some(subject))?;
+        let messages = [
+            ChatMessage {
+                role: Role::System,
+                content: extraction_prompt(&known),
+                tool_calls: vec![],
+                tool_call_id: None,
+                reasoning: vec![],
+            },
+            ChatMessage {
+                role: Role::User,
+                content: format!(
+                    "User: {}\n\nAssistant: {}",
+                    clip(user_message),
+                    clip(assistant_message)
+                ),
+                tool_calls: vec![],
+                tool_call_id: None,
+                reasoning: vec![],
+            },
+        ];
+        let resp = self
+            .llm
+            .chat_structured(&messages, "fact_changes", &changes_schema())
+            .await?;
+        let changes: FactChanges = serde_json::from_value(resp.value)
+            .map_err(|e| anyhow!("unexpected fact extraction response: {e}"))?;
+        self.apply(subject, &known, &changes);
+        Ok(changes)
+    }
+
+    /// Apply `changes`, ignoring ids that aren't unpinned facts about `subject`.
+    fn apply(&self, subject: &str, known: &[Fact], changes: &FactChanges) {
+        let is_known = |id: &str| known.iter().any(|f| f.id == id && !f.pinned);
+        for id in changes.remove.iter().filter(|id| is_known(id)) {
+            if let Err(e) = self.store.delete(id) {
+                tracing::warn!(%e, %id, "failed to remove fact");
+            }
+        }
+        for edit in changes.update.iter().filter(|e| is_known(&e.id)) {
+            if let Err(e) = self.store.update(&edit.id, &edit.text, "extracted") {
+                tracing::debug!(%e, id = %edit.id, "fact update skipped");
+            }
+        }
+        for text in &changes.add {
+            if let Err(e) = self.store.add(subject, text, "extracted") {
+                tracing::debug!(%e, "fact skipped");
+            }
+        }
+        if let Err(e) = self.store.enforce_retention(Some(subject)) {
+            tracing::warn!(%e, "fact retention failed");
+        }
+    }
+}
+
+fn extraction_prompt(known: &[Fact]) -> String {
+    let known = if known.is_empty() {
+        "(none)".to_string()
+    } else {
+        known
+            .iter()
+            .map(|f| format!("- {}: {}", f.id, f.text))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    format!(
+        "You maintain a personal assistant's long-term memory about its user. From the \
+         exchange you are given, pick out durable facts about the user and stable \
+         preferences: who they are, their work, people and places in their life, their \
+         timezone, how they like to be answered. Skip one-off requests, passing moods, \
+         anything about the assistant itself, and facts already known. Write each fact as \
+         a short statement without a subject, like \"Prefers short replies\" or \"Works at \
+         Acme\".\n\nKnown facts (id: fact):\n{known}\n\nList new facts under add. Use \
+         update, by id, when the exchange corrects a known fact and remove when it says one \
+         is no longer true. Leave all three empty when nothing changes."
+    )
+}
+
+/// What [`FactChanges`] looks like to the model.
+fn changes_schema() -> serde_json::Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "add": { "type": "array", "items": { "type": "string" } },
+            "update": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "id": { "type": "string" },
+                        "text": { "type": "string" },
+                    },
+                    "required": ["id", "text"],
+                    "additionalProperties": false,
+                },
+            },
+            "remove": { "type": "array", "items": { "type": "string" } },
+        },
+        "required": ["add", "update", "remove"],
+        "additionalProperties": false,
+    })
+}
+
+fn clip(text: &str) -> String {
+    if text.chars().count() <= EXCHANGE_CHARS_MAX {
+        return text.to_string();
+    }
+    let mut out: String = text.chars().take(EXCHANGE_CHARS_MAX).collect();
+    out.push('…');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use os_llm::{Exchange, Replay};
+
+    #[test]
+    fn facts_are_deduplicated_and_editable() {
+        let path = std::env::temp_dir().join(format!("opencraw-facts-{}.db", Uuid::new_v4()));
+        let store = FactStore::open(&path).unwrap();
+
+        let first = store
+            .add("telegram:42", "Prefers short replies.", "extracted")
+            .unwrap();
+        let again = store
+            .add("telegram:42", "  prefers SHORT replies ", "extracted")
+            .unwrap();
+        assert_eq!(again.id, first.id);
+        assert_eq!(again.text, "Prefers short replies.");
+        // The same words about someone else are a separate fact.
+        store
+            .add("discord:7", "Prefers short replies", "api")
+            .unwrap();
+        let tz = store
+            .add("telegram:42", "Timezone is PST", "extracted")
+            .unwrap();
+        assert_eq!(store.list(Some("telegram:42")).unwrap().len(), 2);
+        assert_eq!(store.list(None).unwrap().len(), 3);
+
+        assert!(store
+            .update(&tz.id, "prefers short replies", "api")
+            .is_err());
+        let moved = store
+            .update(&tz.id, "Timezone is CET", "api")
+            .unwrap()
+            .unwrap();
+        assert_eq!(moved.text, "Timezone is CET");
+        assert_eq!(moved.source, "api");
+        assert!(store.update("missing", "x", "api").unwrap().is_none());
+
+        let section = store.prompt_section("telegram:42").unwrap();
+        assert!(section.contains("- Timezone is CET"));
+        assert!(store.prompt_section("webchat:me").is_none());
+
+        let store = store.with_retention(capped(1));
+        assert_eq!(store.enforce_retention(Some("telegram:42")).unwrap(), 1);
+        assert_eq!(store.list(Some("telegram:42")).unwrap()[0].id, moved.id);
+        assert!(store.delete(&moved.id).unwrap());
+        assert!(!store.delete(&moved.id).unwrap());
+        assert!(store.add("telegram:42", " ", "api").is_err());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn memory_commands_pin_and_forget_own_facts() {
+        let path = std::env::temp_dir().join(format!("opencraw-facts-{}.db", Uuid::new_v4()));
+        let store = FactStore::open(&path).unwrap().with_retention(capped(1));
+        let short = store
+            .add("telegram:42", "Prefers short replies", "extracted")
+            .unwrap();
+        let theirs = store
+            .add("discord:7", "Lives in Oslo", "extracted")
+            .unwrap();
+
+        let reply = store.apply(
+            MemoryCommand::Pin {
+                target: short.id.clone(),
+            },
+            "telegram:42",
+        );
+        assert_eq!(reply, "Pinned: Prefers short replies");
+        let reply = store.apply(
+            MemoryCommand::Pin {
+                target: "Is vegetarian".to_string(),
+            },
+            "telegram:42",
+        );
+        assert!(reply.starts_with("Remembered and pinned"));
+        // Pinned facts survive retention and are always in the prompt.
+        for i in 0..3 {
+            store
+                .add("telegram:42", &format!("Fact number {i}"), "extracted")
+                .unwrap();
+        }
+        assert_eq!(store.enforce_retention(Some("telegram:42")).unwrap(), 2);
+        let section = store.prompt_section("telegram:42").unwrap();
+        assert!(section.contains("- Prefers short replies"));
+        assert!(section.contains("- Is vegetarian"));
+        assert!(section.contains("- Fact number 2"));
+
+        let list = store.apply(MemoryCommand::List, "telegram:42");
+        assert!(list.contains(&format!("{} (pinned) Prefers short replies", short.id)));
+        assert!(!list.contains("Oslo"));
+
+        // Someone else's fact can't be touched, and unknown ids aren't stored as facts.
+        let reply = store.apply(
+            MemoryCommand::Forget {
+                id: theirs.id.clone(),
+            },
+            "telegram:42",
+        );
+        assert!(reply.starts_with("No remembered fact"));
+        assert!(store.get(&theirs.id).unwrap().is_some());
+        let reply = store.apply(
+            MemoryCommand::Pin {
+                target: theirs.id.clone(),
+            },
+            "telegram:42",
+        );
+        assert!(reply.starts_with("No remembered fact"));
+
+        let reply = store.apply(
+            MemoryCommand::Forget {
+                id: short.id.clone(),
+            },
+            "telegram:42",
+        );
+        assert_eq!(reply, "Forgot: Prefers short replies");
+        assert!(store.get(&short.id).unwrap().is_none());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    fn capped(max_items_per_scope: usize) -> MemoryRetentionConfig {
+        MemoryRetentionConfig {
+            max_items_per_scope,
+            ..MemoryRetentionConfig::default()
+        }
+    }
+
+    #[test]
+    fn retention_expires_and_evicts_by_weight() {
+        let path = std::env::temp_dir().join(format!("opencraw-facts-{}.db", Uuid::new_v4()));
+        let store = FactStore::open(&path).unwrap().with_retention(capped(2));
+        let stale = store
+            .add("telegram:42", "Was planning a trip", "extracted")
+            .unwrap();
+        let told = store.add("telegram:42", "Works at Acme", "api").unwrap();
+        let confirmed = store
+            .add("telegram:42", "Prefers short replies", "extracted")
+            .unwrap();
+        for _ in 0..3 {
+            store
+                .add("telegram:42", "prefers short replies", "extracted")
+                .unwrap();
+        }
+        let once = store.add("telegram:42", "Likes jazz", "extracted").unwrap();
+        let old = (Utc::now() - chrono::Duration::days(400)).to_rfc3339();
+        store
+            .db
+            .write()
+            .unwrap()
+            .execute(
+                "UPDATE facts SET updated_at = ?1 WHERE id IN (?2, ?3)",
+                params![old, stale.id, told.id],
+            )
+            .unwrap();
+
+        // Extracted facts expire after 180 days; facts a user gave are kept, but an
+        // old one still weighs less than recent, confirmed ones.
+        assert_eq!(store.get(&confirmed.id).unwrap().unwrap().confirmations, 3);
+        assert_eq!(store.enforce_retention(None).unwrap(), 2);
+        assert!(store.get(&stale.id).unwrap().is_none());
+        assert!(store.get(&told.id).unwrap().is_none());
+        assert!(store.get(&confirmed.id).unwrap().is_some());
+        assert!(store.get(&once.id).unwrap().is_some());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn parses_memory_commands() {
+        assert_eq!(
+            MemoryCommand::parse("/memory"),
+            Some(Ok(MemoryCommand::List))
+        );
+        assert_eq!(
+            MemoryCommand::parse("/memory list"),
+            Some(Ok(MemoryCommand::List))
+        );
+        assert_eq!(
+            MemoryCommand::parse("/memory forget ab12cd34"),
+            Some(Ok(MemoryCommand::Forget {
+                id: "ab12cd34".to_string()
+            }))
+        );
+        assert_eq!(
+            MemoryCommand::parse("/memory pin  Works at Acme "),
+            Some(Ok(MemoryCommand::Pin {
+                target: "Works at Acme".to_string()
+            }))
+        );
+        assert!(matches!(
+            MemoryCommand::parse("/memory forget"),
+            Some(Err(_))
+        ));
+        assert!(matches!(MemoryCommand::parse("/memory wipe"), Some(Err(_))));
+        assert_eq!(MemoryCommand::parse("/memorize this"), None);
+        assert_eq!(MemoryCommand::parse("remember /memory"), None);
+    }
+
+    #[tokio::test]
+    async fn extraction_files_the_structured_changes() {
+        let path = std::env::temp_dir().join(format!("opencraw-facts-{}.db", Uuid::new_v4()));
+        let store = Arc::new(FactStore::open(&path).unwrap());
+        let old = store
+            .add("telegram:42", "Lives in Porto", "extracted")
+            .unwrap();
+        let pinned = store
+            .add("telegram:42", "Is vegetarian", "command")
+            .unwrap();
+        store.set_pinned(&pinned.id, true).unwrap();
+        let replay = Arc::new(Replay::new(vec![
+            Exchange::structured(json!({
+                "add": ["Works at Acme"],
+                "update": [{ "id": old.id, "text": "Lives in Lisbon" }],
+                "remove": [pinned.id],
+            })),
+            Exchange::structured(json!({ "add": "not a list" })),
+        ]));
+        let extractor = FactExtractor::new(LlmClient::replay("gpt-4o-mini", replay), store.clone());
+
+        let changes = extractor
+            .extract("telegram:42", "I moved to Lisbon, still at Acme", "Noted!")
+            .await
+            .unwrap();
+        assert_eq!(changes.add, vec!["Works at Acme".to_string()]);
+        let mut texts: Vec<String> = store
+            .list(Some("telegram:42"))
+            .unwrap()
+            .into_iter()
+            .map(|f| f.text)
+            .collect();
+        texts.sort();
+        // Pinned facts are never removed by extraction.
+        assert_eq!(texts, ["Is vegetarian", "Lives in Lisbon", "Works at Acme"]);
+
+        assert!(extractor
+            .extract("telegram:42", "hi", "hello")
+            .await
+            .is_err());
+        assert_eq!(store.list(Some("telegram:42")).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn changes_match_their_schema() {
+        let changes: FactChanges = serde_json::from_value(json!({
+            "add": ["Works at Acme"],
+            "update": [{ "id": "ab12cd34", "text": "Lives in Lisbon" }],
+            "remove": [],
+        }))
+        .unwrap();
+        assert_eq!(changes.add, vec!["Works at Acme".to_string()]);
+        assert_eq!(changes.update[0].text, "Lives in Lisbon");
+        assert!(changes.remove.is_empty());
+    }
+}
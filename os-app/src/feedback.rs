@@ -1,44 +1,344 @@
-//! Feedback + optimization integration.
-
-use horizons_core::evaluation_traits::VerificationCase;
-use horizons_core::optimization_traits::{ContinualLearning, Dataset, MiproConfig, Policy};
-use ulid::Ulid;
-
-/// Build a verification case from user feedback (reaction).
-#[allow(dead_code)]
-pub fn build_verification_case(
-    user_input: &str,
-    assistant_output: &str,
-    positive: bool,
-) -> VerificationCase {
-    VerificationCase {
-        id: Ulid::new(),
-        input: user_input.to_string(),
-        output: assistant_output.to_string(),
-        expected: if positive {
-            Some("good".to_string())
-        } else {
-            Some("poor".to_string())
-        },
-        metadata: serde_json::json!({}),
-    }
-}
-
-/// Placeholder for MIPRO optimization batch.
-#[allow(dead_code)]
-pub async fn run_optimization_batch(
-    learner: &dyn ContinualLearning,
-    current_prompt: &str,
-    dataset: Dataset,
-) -> Result<String, String> {
-    let cfg = MiproConfig::default();
-    let policy = Policy {
-        template: current_prompt.to_string(),
-        metadata: serde_json::json!({}),
-    };
-
-    match learner.run_batch(cfg, policy, dataset).await {
-        Ok(result) => Ok(result.best_policy.template),
-        Err(e) => Err(format!("optimization failed: {e}")),
+//! Reactions as feedback on the assistant's answers.
+//!
+//! A 👍 or 👎 (and the other emoji [`sentiment`] knows) on a message is stored in
+//! `data/feedback.db` with the answer it most likely targets: the conversation's latest
+//! assistant message, its id and the id of the run that produced it (see `trace`), plus
+//! the platform's id for the message reacted to when the adapter reports one
+//! (`metadata.message_id`). `GET /api/v1/os/feedback` serves the latest reactions and
+//! per-channel satisfaction (the share of positive reactions) per day. With
+//! `[encryption]` on, the answer excerpt is sealed before it's stored.
+
+use crate::encryption::{open_value, DataCipher};
+use crate::session::Session;
+use crate::sqlite::SqlitePool;
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use os_channels::InboundMessage;
+use rusqlite::params;
+use serde::Serialize;
+use std::path::Path;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Characters of the targeted answer kept with a reaction.
+const ANSWER_CHARS_MAX: usize = 200;
+
+/// Whether a reaction is positive, negative, or not feedback at all.
+pub fn sentiment(emoji: &str) -> Option<bool> {
+    match emoji {
+        "👍" | "❤️" | "✅" => Some(true),
+        "👎" | "❌" => Some(false),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Feedback {
+    pub id: Uuid,
+    pub channel_id: String,
+    pub sender_id: String,
+    pub emoji: String,
+    pub positive: bool,
+    /// The platform's id for the message reacted to, when the adapter reports it.
+    pub reacted_message_id: Option<String>,
+    pub session_id: Option<Uuid>,
+    /// The assistant message the reaction is taken to be about.
+    pub assistant_message_id: Option<String>,
+    /// The run that produced that message; see `GET /api/v1/os/sessions/{id}/trace`.
+    pub run_id: Option<Uuid>,
+    /// The start of that message.
+    pub answer: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Feedback {
+    /// Feedback from `inbound`, a reaction, aimed at the latest answer in `session`.
+    /// `None` when the emoji isn't feedback.
+    pub fn from_reaction(inbound: &InboundMessage, session: Option<&Session>) -> Option<Self> {
+        let positive = sentiment(inbound.content.trim())?;
+        let reacted_message_id = match inbound.metadata.get("message_id") {
+            Some(serde_json::Value::String(id)) => Some(id.clone()),
+            Some(serde_json::Value::Number(id)) => Some(id.to_string()),
+            _ => None,
+        };
+        let answer = session.and_then(|s| s.last_answer()).map(|text| {
+            let mut out: String = text.chars().take(ANSWER_CHARS_MAX).collect();
+            if out.len() < text.len() {
+                out.push('…');
+            }
+            out
+        });
+        Some(Self {
+            id: Uuid::new_v4(),
+            channel_id: inbound.channel_id.clone(),
+            sender_id: inbound.sender_id.clone(),
+            emoji: inbound.content.trim().to_string(),
+            positive,
+            reacted_message_id,
+            session_id: session.map(|s| s.id),
+            assistant_message_id: session.and_then(|s| s.last_assistant_message_id.clone()),
+            // The current trace is the answer's run unless another run is under way.
+            run_id: session
+                .and_then(|s| s.last_trace.as_ref())
+                .filter(|t| t.finished_at.is_some())
+                .map(|t| t.id),
+            answer,
+            created_at: Utc::now(),
+        })
+    }
+}
+
+/// Positive and negative reactions over some span.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Tally {
+    pub positive: u64,
+    pub negative: u64,
+    /// Share of positive reactions; `None` without any.
+    pub satisfaction: Option<f64>,
+}
+
+impl Tally {
+    fn add(&mut self, positive: u64, negative: u64) {
+        self.positive += positive;
+        self.negative += negative;
+        let total = self.positive + self.negative;
+        self.satisfaction = (total > 0).then(|| self.positive as f64 / total as f64);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DayTally {
+    /// `YYYY-MM-DD`, UTC.
+    pub day: String,
+    #[serde(flatten)]
+    pub tally: Tally,
+}
+
+/// One channel's feedback over the requested days.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChannelSatisfaction {
+    pub channel_id: String,
+    #[serde(flatten)]
+    pub total: Tally,
+    /// Days with reactions, oldest first.
+    pub days: Vec<DayTally>,
+}
+
+pub struct FeedbackStore {
+    db: Arc<SqlitePool>,
+    cipher: Option<Arc<DataCipher>>,
+}
+
+impl FeedbackStore {
+    pub fn open(path: &Path) -> Result<Self> {
+        let db = SqlitePool::open(
+            path,
+            "CREATE TABLE IF NOT EXISTS feedback (
+                id TEXT PRIMARY KEY,
+                channel_id TEXT NOT NULL,
+                sender_id TEXT NOT NULL,
+                emoji TEXT NOT NULL,
+                positive INTEGER NOT NULL,
+                reacted_message_id TEXT,
+                session_id TEXT,
+                assistant_message_id TEXT,
+                run_id TEXT,
+                answer TEXT,
+                created_at TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS feedback_created ON feedback(created_at);",
+        )?;
+        Ok(Self {
+            db: Arc::new(db),
+            cipher: None,
+        })
+    }
+
+    /// Seal answer excerpts with `cipher`.
+    pub fn with_cipher(mut self, cipher: Option<Arc<DataCipher>>) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    pub fn sqlite_pool(&self) -> Arc<SqlitePool> {
+        self.db.clone()
+    }
+
+    pub fn record(&self, feedback: &Feedback) -> Result<()> {
+        let answer = match (feedback.answer.as_deref(), self.cipher.as_deref()) {
+            (Some(answer), Some(cipher)) => Some(cipher.seal(answer)?),
+            (answer, None) => answer.map(str::to_string),
+            (None, Some(_)) => None,
+        };
+        self.db.write()?.execute(
+            "INSERT INTO feedback (id, channel_id, sender_id, emoji, positive, reacted_message_id,
+                session_id, assistant_message_id, run_id, answer, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                feedback.id.to_string(),
+                feedback.channel_id,
+                feedback.sender_id,
+                feedback.emoji,
+                feedback.positive,
+                feedback.reacted_message_id,
+                feedback.session_id.map(|id| id.to_string()),
+                feedback.assistant_message_id,
+                feedback.run_id.map(|id| id.to_string()),
+                answer,
+                feedback.created_at.to_rfc3339(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// The latest reactions, newest first.
+    pub fn recent(&self, limit: usize) -> Result<Vec<Feedback>> {
+        let rows = {
+            let conn = self.db.read()?;
+            let mut stmt = conn.prepare(
+                "SELECT id, channel_id, sender_id, emoji, positive, reacted_message_id, session_id,
+                    assistant_message_id, run_id, answer, created_at
+                 FROM feedback ORDER BY created_at DESC LIMIT ?1",
+            )?;
+            let rows = stmt
+                .query_map(params![limit as i64], |row| {
+                    let uuid = |i: usize| -> rusqlite::Result<Option<Uuid>> {
+                        let s: Option<String> = row.get(i)?;
+                        Ok(s.and_then(|s| Uuid::parse_str(&s).ok()))
+                    };
+                    let created_at: String = row.get(10)?;
+                    Ok(Feedback {
+                        id: uuid(0)?.unwrap_or_default(),
+                        channel_id: row.get(1)?,
+                        sender_id: row.get(2)?,
+                        emoji: row.get(3)?,
+                        positive: row.get(4)?,
+                        reacted_message_id: row.get(5)?,
+                        session_id: uuid(6)?,
+                        assistant_message_id: row.get(7)?,
+                        run_id: uuid(8)?,
+                        answer: row.get(9)?,
+                        created_at: DateTime::parse_from_rfc3339(&created_at)
+                            .map(|t| t.with_timezone(&Utc))
+                            .unwrap_or_else(|_| Utc::now()),
+                    })
+                })?
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            rows
+        };
+        rows.into_iter()
+            .map(|mut feedback| {
+                feedback.answer = feedback
+                    .answer
+                    .map(|a| open_value(self.cipher.as_deref(), a))
+                    .transpose()?;
+                Ok(feedback)
+            })
+            .collect()
+    }
+
+    /// Each channel's satisfaction over the last `days` days, overall and per day.
+    pub fn satisfaction(&self, days: u32) -> Result<Vec<ChannelSatisfaction>> {
+        let since = Utc::now() - Duration::days(i64::from(days));
+        let conn = self.db.read()?;
+        let mut stmt = conn.prepare(
+            "SELECT channel_id, substr(created_at, 1, 10) AS day, SUM(positive), COUNT(*)
+             FROM feedback WHERE created_at >= ?1
+             GROUP BY channel_id, day ORDER BY channel_id, day",
+        )?;
+        let rows = stmt
+            .query_map(params![since.to_rfc3339()], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)? as u64,
+                    row.get::<_, i64>(3)? as u64,
+                ))
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let mut out: Vec<ChannelSatisfaction> = Vec::new();
+        for (channel_id, day, positive, total) in rows {
+            if out.last().is_none_or(|c| c.channel_id != channel_id) {
+                out.push(ChannelSatisfaction {
+                    channel_id,
+                    total: Tally::default(),
+                    days: Vec::new(),
+                });
+            }
+            let Some(channel) = out.last_mut() else {
+                continue;
+            };
+            let mut tally = Tally::default();
+            tally.add(positive, total - positive);
+            channel.total.add(positive, total - positive);
+            channel.days.push(DayTally { day, tally });
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use os_channels::InboundMessageKind;
+
+    fn reaction(channel_id: &str, emoji: &str) -> InboundMessage {
+        InboundMessage {
+            kind: InboundMessageKind::Reaction,
+            message_id: Uuid::new_v4().to_string(),
+            channel_id: channel_id.to_string(),
+            sender_id: "42".to_string(),
+            thread_id: None,
+            is_group: false,
+            content: emoji.to_string(),
+            attachments: vec![],
+            metadata: serde_json::json!({ "message_id": 1017 }),
+            received_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn reactions_are_tallied_per_channel_and_day() {
+        let tmp = std::env::temp_dir().join(format!("opencraw-feedback-{}", Uuid::new_v4()));
+        let store = FeedbackStore::open(&tmp.join("feedback.db")).unwrap();
+        let mut session = Session::new();
+        session.last_assistant_message_id = Some("a1".to_string());
+        let mut trace = crate::trace::RunTrace::new();
+        trace.finish("completed");
+        session.last_trace = Some(trace.clone());
+
+        assert!(Feedback::from_reaction(&reaction("telegram", "🎉"), None).is_none());
+        let liked = Feedback::from_reaction(&reaction("telegram", "👍"), Some(&session)).unwrap();
+        assert_eq!(liked.reacted_message_id.as_deref(), Some("1017"));
+        assert_eq!(liked.assistant_message_id.as_deref(), Some("a1"));
+        assert_eq!(liked.run_id, Some(trace.id));
+        store.record(&liked).unwrap();
+        for (channel, emoji) in [("telegram", "👎"), ("telegram", "❤️"), ("webchat", "👎")]
+        {
+            store
+                .record(&Feedback::from_reaction(&reaction(channel, emoji), None).unwrap())
+                .unwrap();
+        }
+        let mut old = Feedback::from_reaction(&reaction("telegram", "👎"), None).unwrap();
+        old.created_at = Utc::now() - Duration::days(40);
+        store.record(&old).unwrap();
+
+        let channels = store.satisfaction(30).unwrap();
+        assert_eq!(channels.len(), 2);
+        assert_eq!(channels[0].channel_id, "telegram");
+        assert_eq!(
+            (channels[0].total.positive, channels[0].total.negative),
+            (2, 1)
+        );
+        assert_eq!(channels[0].days.len(), 1);
+        assert_eq!(channels[0].days[0].tally, channels[0].total);
+        assert_eq!(channels[1].total.satisfaction, Some(0.0));
+
+        let recent = store.recent(10).unwrap();
+        assert_eq!(recent.len(), 5);
+        assert_eq!(recent[4].id, old.id);
+        assert!(recent.iter().any(|f| f.run_id == Some(trace.id)));
+        std::fs::remove_dir_all(&tmp).unwrap();
     }
 }
@@ -0,0 +1,437 @@
+//! Time-boxed focus sessions (`/focus 2h`).
+//!
+//! While a focus session runs, messages from everyone except the person who started it
+//! are held instead of answered. Each sender gets one automatic status reply, urgent
+//! messages (by sender or keyword) still go through, and when the session ends the owner
+//! gets a summary of what came in and the held messages are replayed through the gateway
+//! in order.
+
+use crate::config::FocusConfig;
+use crate::outbox::Outbox;
+use crate::tasks::ConversationOrigin;
+use chrono::{DateTime, Local, Utc};
+use os_channels::{InboundMessage, OutboundMessage};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(15);
+/// Messages quoted per sender in the end-of-focus summary.
+const SUMMARY_QUOTES_MAX: usize = 3;
+const QUOTE_CHARS_MAX: usize = 120;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FocusCommand {
+    Start {
+        duration: chrono::Duration,
+        status: Option<String>,
+    },
+    Stop,
+    Show,
+}
+
+impl FocusCommand {
+    /// `/focus 2h [status]`, `/focus 1h30m`, `/focus 45` (minutes), `/focus off`, `/focus`.
+    pub fn parse(input: &str) -> Option<Result<Self, String>> {
+        let rest = input.trim().strip_prefix("/focus")?;
+        if !rest.is_empty() && !rest.starts_with(' ') {
+            return None;
+        }
+        let rest = rest.trim();
+        if rest.is_empty() {
+            return Some(Ok(Self::Show));
+        }
+        let (first, status) = match rest.split_once(char::is_whitespace) {
+            Some((first, status)) => (first, Some(status.trim().to_string())),
+            None => (rest, None),
+        };
+        if matches!(first, "off" | "stop" | "end") {
+            return Some(Ok(Self::Stop));
+        }
+        Some(match parse_duration(first) {
+            Some(duration) => Ok(Self::Start {
+                duration,
+                status: status.filter(|s| !s.is_empty()),
+            }),
+            None => Err(format!(
+                "Couldn't read {first:?} as a duration. Try /focus 2h, /focus 45m or /focus off."
+            )),
+        })
+    }
+}
+
+/// `2h`, `90m`, `1h30m`, `7d`, or a bare number of minutes.
+pub(crate) fn parse_duration(s: &str) -> Option<chrono::Duration> {
+    if let Ok(minutes) = s.parse::<i64>() {
+        return (minutes > 0)
+            .then(|| chrono::Duration::try_minutes(minutes))
+            .flatten();
+    }
+    let mut total = 0i64;
+    let mut digits = String::new();
+    for c in s.chars() {
+        match c {
+            '0'..='9' => digits.push(c),
+            'd' | 'h' | 'm' => {
+                let n: i64 = digits.parse().ok()?;
+                digits.clear();
+                let minutes = match c {
+                    'd' => n.checked_mul(24 * 60)?,
+                    'h' => n.checked_mul(60)?,
+                    _ => n,
+                };
+                total = total.checked_add(minutes)?;
+            }
+            _ => return None,
+        }
+    }
+    (digits.is_empty() && total > 0)
+        .then(|| chrono::Duration::try_minutes(total))
+        .flatten()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FocusStatus {
+    pub owner: ConversationOrigin,
+    pub started_at: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    pub status: Option<String>,
+    pub held_messages: usize,
+}
+
+struct Focus {
+    owner: ConversationOrigin,
+    started_at: DateTime<Utc>,
+    until: DateTime<Utc>,
+    status: Option<String>,
+    held: Vec<InboundMessage>,
+    /// Senders who already got the automatic status reply.
+    notified: HashSet<(String, String)>,
+}
+
+/// What the gateway should do with an inbound message.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Intercept {
+    Pass,
+    /// Held until focus ends; send `auto_reply` to the sender if set.
+    Held {
+        auto_reply: Option<String>,
+    },
+}
+
+pub struct FocusMode {
+    cfg: FocusConfig,
+    state: Mutex<Option<Focus>>,
+    outbox: Arc<Outbox>,
+    inbound_tx: mpsc::Sender<InboundMessage>,
+}
+
+impl FocusMode {
+    pub fn new(
+        cfg: FocusConfig,
+        outbox: Arc<Outbox>,
+        inbound_tx: mpsc::Sender<InboundMessage>,
+    ) -> Self {
+        Self {
+            cfg,
+            state: Mutex::new(None),
+            outbox,
+            inbound_tx,
+        }
+    }
+
+    pub fn start(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                let expired = self
+                    .state
+                    .lock()
+                    .map(|s| s.as_ref().is_some_and(|f| f.until <= Utc::now()))
+                    .unwrap_or(false);
+                if expired {
+                    self.end().await;
+                }
+            }
+        });
+    }
+
+    pub fn status(&self) -> Option<FocusStatus> {
+        let state = self.state.lock().ok()?;
+        let focus = state.as_ref()?;
+        Some(FocusStatus {
+            owner: focus.owner.clone(),
+            started_at: focus.started_at,
+            until: focus.until,
+            status: focus.status.clone(),
+            held_messages: focus.held.len(),
+        })
+    }
+
+    /// Carry out a `/focus` command from `owner` and return the reply for them.
+    pub async fn apply(&self, owner: ConversationOrigin, command: FocusCommand) -> String {
+        match command {
+            FocusCommand::Show => match self.status() {
+                Some(s) => format!(
+                    "Focusing until {} with {} message(s) held. /focus off to end now.",
+                    local_time(s.until),
+                    s.held_messages
+                ),
+                None => "Not focusing. Start with /focus 2h [status].".to_string(),
+            },
+            FocusCommand::Stop => {
+                if self.end().await {
+                    "Focus ended.".to_string()
+                } else {
+                    "Not focusing.".to_string()
+                }
+            }
+            FocusCommand::Start { duration, status } => {
+                let max = chrono::Duration::hours(self.cfg.max_hours as i64);
+                if duration > max {
+                    return format!("Focus sessions are limited to {}h.", self.cfg.max_hours);
+                }
+                let until = Utc::now() + duration;
+                let Ok(mut state) = self.state.lock() else {
+                    return "Focus is unavailable.".to_string();
+                };
+                // Extending a running session keeps what it already holds.
+                match state.as_mut() {
+                    Some(focus) => {
+                        focus.until = until;
+                        focus.status = status;
+                        focus.owner = owner;
+                    }
+                    None => {
+                        *state = Some(Focus {
+                            owner,
+                            started_at: Utc::now(),
+                            until,
+                            status,
+                            held: Vec::new(),
+                            notified: HashSet::new(),
+                        })
+                    }
+                }
+                format!(
+                    "Focusing until {}. Non-urgent messages are held and you'll get a summary at the end.",
+                    local_time(until)
+                )
+            }
+        }
+    }
+
+    /// Hold `inbound` if a focus session is running and it isn't from the owner or urgent.
+    /// `may_reply` is false for senders who must never get an automatic message (those
+    /// outside the allowlist).
+    pub fn intercept(&self, inbound: &InboundMessage, may_reply: bool) -> Intercept {
+        let Ok(mut state) = self.state.lock() else {
+            return Intercept::Pass;
+        };
+        let Some(focus) = state.as_mut() else {
+            return Intercept::Pass;
+        };
+        let from_owner = inbound.channel_id == focus.owner.channel_id
+            && inbound.sender_id == focus.owner.sender_id;
+        if from_owner || self.is_urgent(inbound) {
+            return Intercept::Pass;
+        }
+
+        focus.held.push(inbound.clone());
+        let first_time = focus
+            .notified
+            .insert((inbound.channel_id.clone(), inbound.sender_id.clone()));
+        let auto_reply = (may_reply && first_time).then(|| {
+            let mut reply = format!("I'm focusing until {}", local_time(focus.until));
+            if let Some(status) = focus.status.as_deref() {
+                reply.push_str(&format!(" ({status})"));
+            }
+            reply.push_str(". Your message is saved and I'll get to it then");
+            if let Some(keyword) = self.cfg.urgent_keywords.first() {
+                reply.push_str(&format!("; include \"{keyword}\" if it can't wait"));
+            }
+            reply.push('.');
+            reply
+        });
+        Intercept::Held { auto_reply }
+    }
+
+    fn is_urgent(&self, inbound: &InboundMessage) -> bool {
+        let composite = format!("{}:{}", inbound.channel_id, inbound.sender_id);
+        if self
+            .cfg
+            .urgent_senders
+            .iter()
+            .any(|s| *s == inbound.sender_id || *s == composite)
+        {
+            return true;
+        }
+        let content = inbound.content.to_lowercase();
+        self.cfg
+            .urgent_keywords
+            .iter()
+            .any(|k| content.contains(&k.to_lowercase()))
+    }
+
+    /// End the running session: summarize for the owner and replay held messages.
+    /// Returns false if there was none.
+    pub async fn end(&self) -> bool {
+        let Some(focus) = self.state.lock().ok().and_then(|mut s| s.take()) else {
+            return false;
+        };
+        self.outbox.enqueue(
+            &focus.owner.channel_id,
+            &focus.owner.recipient,
+            OutboundMessage {
+                content: summary(&focus.held),
+                reply_to_message_id: None,
+                attachments: vec![],
+            },
+        );
+        for inbound in focus.held {
+            if let Err(e) = self.inbound_tx.send(inbound).await {
+                tracing::warn!(%e, "failed to replay held message");
+            }
+        }
+        true
+    }
+}
+
+fn local_time(at: DateTime<Utc>) -> String {
+    at.with_timezone(&Local).format("%H:%M").to_string()
+}
+
+fn summary(held: &[InboundMessage]) -> String {
+    if held.is_empty() {
+        return "Focus session over. Nothing came in.".to_string();
+    }
+    let mut by_sender: BTreeMap<(String, String), Vec<&InboundMessage>> = BTreeMap::new();
+    for m in held {
+        by_sender
+            .entry((m.channel_id.clone(), m.sender_id.clone()))
+            .or_default()
+            .push(m);
+    }
+    let mut out = format!(
+        "Focus session over. {} message(s) from {} sender(s) came in; they're being handled now.",
+        held.len(),
+        by_sender.len()
+    );
+    for ((channel, sender), messages) in &by_sender {
+        out.push_str(&format!("\n\n{sender} on {channel} ({}):", messages.len()));
+        for m in messages.iter().take(SUMMARY_QUOTES_MAX) {
+            let mut quote: String = m.content.chars().take(QUOTE_CHARS_MAX).collect();
+            if quote.len() < m.content.len() {
+                quote.push('…');
+            }
+            out.push_str(&format!("\n- {}", quote.replace('\n', " ")));
+        }
+        if messages.len() > SUMMARY_QUOTES_MAX {
+            out.push_str(&format!(
+                "\n- …and {} more",
+                messages.len() - SUMMARY_QUOTES_MAX
+            ));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use os_channels::InboundMessageKind;
+
+    fn inbound(channel_id: &str, sender_id: &str, content: &str) -> InboundMessage {
+        InboundMessage {
+            kind: InboundMessageKind::Message,
+            message_id: format!("{sender_id}-{content}"),
+            channel_id: channel_id.to_string(),
+            sender_id: sender_id.to_string(),
+            thread_id: None,
+            is_group: false,
+            content: content.to_string(),
+            attachments: vec![],
+            metadata: serde_json::Value::Null,
+            received_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn parses_durations() {
+        let start = |minutes, status: Option<&str>| {
+            Some(Ok(FocusCommand::Start {
+                duration: chrono::Duration::minutes(minutes),
+                status: status.map(|s| s.to_string()),
+            }))
+        };
+        assert_eq!(FocusCommand::parse("/focus 2h"), start(120, None));
+        assert_eq!(
+            FocusCommand::parse("/focus 1h30m writing the report"),
+            start(90, Some("writing the report"))
+        );
+        assert_eq!(FocusCommand::parse("/focus 45"), start(45, None));
+        assert_eq!(
+            FocusCommand::parse("/focus off"),
+            Some(Ok(FocusCommand::Stop))
+        );
+        assert_eq!(FocusCommand::parse("/focus"), Some(Ok(FocusCommand::Show)));
+        assert!(matches!(FocusCommand::parse("/focus soon"), Some(Err(_))));
+        assert_eq!(FocusCommand::parse("/focused"), None);
+    }
+
+    #[tokio::test]
+    async fn holds_non_urgent_messages_and_replays_them_at_the_end() {
+        let (tx, mut rx) = mpsc::channel(8);
+        let focus = FocusMode::new(
+            FocusConfig::default(),
+            Arc::new(Outbox::new(Default::default())),
+            tx,
+        );
+        let owner = ConversationOrigin {
+            channel_id: "telegram".to_string(),
+            sender_id: "me".to_string(),
+            recipient: "me".to_string(),
+        };
+        focus
+            .apply(
+                owner,
+                FocusCommand::Start {
+                    duration: chrono::Duration::hours(2),
+                    status: Some("deep work".to_string()),
+                },
+            )
+            .await;
+
+        assert_eq!(
+            focus.intercept(&inbound("telegram", "me", "/status"), true),
+            Intercept::Pass
+        );
+        let Intercept::Held { auto_reply } =
+            focus.intercept(&inbound("discord", "bob", "lunch?"), true)
+        else {
+            panic!("expected held");
+        };
+        assert!(auto_reply.unwrap().contains("(deep work)"));
+        assert_eq!(
+            focus.intercept(&inbound("discord", "bob", "hello?"), true),
+            Intercept::Held { auto_reply: None }
+        );
+        assert_eq!(
+            focus.intercept(&inbound("discord", "bob", "URGENT: server down"), true),
+            Intercept::Pass
+        );
+        assert_eq!(focus.status().unwrap().held_messages, 2);
+
+        assert!(focus.end().await);
+        assert_eq!(rx.recv().await.unwrap().content, "lunch?");
+        assert_eq!(rx.recv().await.unwrap().content, "hello?");
+        assert!(focus.status().is_none());
+        assert_eq!(
+            focus.intercept(&inbound("discord", "bob", "still there?"), true),
+            Intercept::Pass
+        );
+    }
+}
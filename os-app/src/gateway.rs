@@ -1,48 +1,170 @@
 //! Session multiplexer: all channel adapters feed into a single inbound queue.
 //!
+//! Once past the debounce, queued messages run highest `inbound.priority` first: the
+//! owner's direct messages ahead of other people's, and those ahead of group chats and
+//! automation prompts.
+//!
 //! See: specifications/openshell/implementation_v0_1_0.md
 
-use crate::assistant::AssistantAgent;
+use crate::archive::ConversationArchive;
+use crate::assistant::{AssistantAgent, RunBudget};
+use crate::attachments::{AttachmentDirection, AttachmentOrigin, AttachmentStore};
+use crate::automation::{self, AutomationEngine};
+use crate::cluster::{Cluster, LaneLease};
 use crate::commands;
-use crate::config::OpenShellConfig;
-use crate::pairing;
+use crate::config::{InboundPriorityConfig, OpenShellConfig, Priority};
+use crate::facts::{self, FactStore, MemoryCommand};
+use crate::feedback::{Feedback, FeedbackStore};
+use crate::focus::{FocusCommand, FocusMode, Intercept};
+use crate::handoff::{self, MoveCommand};
+use crate::identities;
+use crate::integrity::IntegrityMonitor;
+use crate::journal::InboundJournal;
+use crate::metrics::Metrics;
+use crate::outbox::Outbox;
+use crate::pairing::{self, InviteCommand, PairingStore, Redeemed};
+use crate::progress;
 use crate::session::SessionManager;
+use crate::shares::{ShareCommand, ShareStore};
+use crate::skills;
+use crate::suggestions::{SuggestionCommand, SuggestionQueue};
+use crate::tasks::{ConversationOrigin, CONVERSATION};
+use crate::translate::Translator;
+use crate::watchdog::Watchdog;
 use anyhow::Result;
 use os_channels::{ChannelAdapter, InboundMessage, InboundMessageKind, OutboundMessage};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 
+/// Typing indicators last ~5s on Telegram and ~10s on Discord.
+const TYPING_REFRESH: Duration = Duration::from_secs(4);
+/// How long a message whose lane another replica holds waits before it's tried again.
+const LANE_RETRY: Duration = Duration::from_millis(500);
+
+/// Queued messages with the time each becomes due.
+type Pending = VecDeque<(Instant, InboundMessage)>;
+
 #[derive(Clone)]
 pub struct Gateway {
     cfg: OpenShellConfig,
     started_at: Instant,
     sessions: Arc<SessionManager>,
     assistant: Arc<AssistantAgent>,
-    channels: HashMap<String, Arc<dyn ChannelAdapter>>,
+    outbox: Arc<Outbox>,
     inbound_rx: Arc<tokio::sync::Mutex<mpsc::Receiver<InboundMessage>>>,
+    integrity: Arc<IntegrityMonitor>,
+    attachments: Option<Arc<AttachmentStore>>,
+    archive: Option<Arc<ConversationArchive>>,
+    watchdog: Arc<Watchdog>,
+    translator: Option<Arc<Translator>>,
+    suggestions: Option<Arc<SuggestionQueue>>,
+    focus: Option<Arc<FocusMode>>,
+    metrics: Option<Arc<Metrics>>,
+    shares: Option<Arc<ShareStore>>,
+    journal: Option<Arc<InboundJournal>>,
+    cluster: Option<Arc<Cluster>>,
+    automation: Option<Arc<AutomationEngine>>,
+    pairing: Option<Arc<PairingStore>>,
+    facts: Option<Arc<FactStore>>,
+    feedback: Option<Arc<FeedbackStore>>,
 }
 
 impl Gateway {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         cfg: OpenShellConfig,
         started_at: Instant,
         sessions: Arc<SessionManager>,
         assistant: Arc<AssistantAgent>,
-        channels: HashMap<String, Arc<dyn ChannelAdapter>>,
+        outbox: Arc<Outbox>,
         inbound_rx: mpsc::Receiver<InboundMessage>,
+        integrity: Arc<IntegrityMonitor>,
+        attachments: Option<Arc<AttachmentStore>>,
+        archive: Option<Arc<ConversationArchive>>,
+        watchdog: Arc<Watchdog>,
+        translator: Option<Arc<Translator>>,
     ) -> Self {
         Self {
             cfg,
             started_at,
             sessions,
             assistant,
-            channels,
+            outbox,
             inbound_rx: Arc::new(tokio::sync::Mutex::new(inbound_rx)),
+            integrity,
+            attachments,
+            archive,
+            watchdog,
+            translator,
+            suggestions: None,
+            focus: None,
+            metrics: None,
+            shares: None,
+            journal: None,
+            cluster: None,
+            automation: None,
+            pairing: None,
+            facts: None,
+            feedback: None,
         }
     }
 
+    pub fn with_suggestions(mut self, suggestions: Arc<SuggestionQueue>) -> Self {
+        self.suggestions = Some(suggestions);
+        self
+    }
+
+    pub fn with_focus(mut self, focus: Arc<FocusMode>) -> Self {
+        self.focus = Some(focus);
+        self
+    }
+
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn with_shares(mut self, shares: Arc<ShareStore>) -> Self {
+        self.shares = Some(shares);
+        self
+    }
+
+    pub fn with_journal(mut self, journal: Arc<InboundJournal>) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    pub fn with_cluster(mut self, cluster: Arc<Cluster>) -> Self {
+        self.cluster = Some(cluster);
+        self
+    }
+
+    /// Fire automation rules on what arrives; see `automation`.
+    pub fn with_automation(mut self, automation: Arc<AutomationEngine>) -> Self {
+        self.automation = Some(automation);
+        self
+    }
+
+    /// Admit senders paired with an invite code; see `pairing`.
+    pub fn with_pairing(mut self, pairing: Arc<PairingStore>) -> Self {
+        self.pairing = Some(pairing);
+        self
+    }
+
+    /// Answer `/memory` commands; see `facts`.
+    pub fn with_facts(mut self, facts: Arc<FactStore>) -> Self {
+        self.facts = Some(facts);
+        self
+    }
+
+    /// Keep reactions as feedback; see `feedback`.
+    pub fn with_feedback(mut self, feedback: Arc<FeedbackStore>) -> Self {
+        self.feedback = Some(feedback);
+        self
+    }
+
     pub fn start(self: Arc<Self>) {
         tokio::spawn(async move {
             if let Err(e) = self.run_loop().await {
@@ -53,45 +175,381 @@ impl Gateway {
 
     #[tracing::instrument(level = "info", skip_all)]
     async fn run_loop(&self) -> Result<()> {
+        let mut pending = Pending::new();
         loop {
-            let msg = {
-                let mut rx = self.inbound_rx.lock().await;
-                rx.recv().await
+            let due = ready_at(&pending).into_iter().min();
+            let wait_due = async {
+                match due {
+                    Some(at) => tokio::time::sleep_until(tokio::time::Instant::from_std(at)).await,
+                    None => std::future::pending::<()>().await,
+                }
+            };
+
+            // Drain everything already queued before starting a run, so edits that arrived
+            // during the previous run can still supersede their message.
+            let msg = tokio::select! {
+                biased;
+                msg = async { self.inbound_rx.lock().await.recv().await } => msg,
+                _ = wait_due => {
+                    let next = next_due(&pending, Instant::now(), |m| {
+                        priority(&self.cfg.inbound.priority, m)
+                    });
+                    let Some(i) = next else { continue };
+                    let Some((_, inbound)) = pending.remove(i) else { continue };
+                    if let Some(inbound) = self.handle_receiving(inbound, &mut pending).await {
+                        // Back where it was, so the conversation's later messages keep
+                        // waiting behind it.
+                        pending.insert(i, (Instant::now() + LANE_RETRY, inbound));
+                    }
+                    continue;
+                }
             };
             let Some(inbound) = msg else {
+                for (_, inbound) in pending.drain(..) {
+                    if self.handle(inbound).await.is_some() {
+                        tracing::warn!("lane held elsewhere at shutdown; left in the journal");
+                    }
+                }
                 return Ok(());
             };
+            self.receive(inbound, &mut pending);
+        }
+    }
 
-            if let Err(e) = self.handle_inbound(inbound).await {
-                tracing::warn!(%e, "handle_inbound failed");
+    /// Handle one message while still reading the queue, so a `/cancel` sent during the run
+    /// reaches it instead of waiting behind it. Returns the message if it has to wait for
+    /// its lane.
+    async fn handle_receiving(
+        &self,
+        inbound: InboundMessage,
+        pending: &mut Pending,
+    ) -> Option<InboundMessage> {
+        let handle = self.handle(inbound);
+        tokio::pin!(handle);
+        let mut open = true;
+        loop {
+            tokio::select! {
+                biased;
+                unhandled = &mut handle => return unhandled,
+                msg = async { self.inbound_rx.lock().await.recv().await }, if open => match msg {
+                    Some(msg) => self.receive(msg, pending),
+                    None => open = false,
+                },
             }
         }
     }
 
-    #[tracing::instrument(level = "info", skip_all)]
-    async fn handle_inbound(&self, inbound: InboundMessage) -> Result<()> {
-        if !pairing::is_allowed(&self.cfg, &inbound.channel_id, &inbound.sender_id) {
+    fn receive(&self, inbound: InboundMessage, pending: &mut Pending) {
+        if let Some(metrics) = self.metrics.as_ref() {
+            metrics.inbound(&inbound.channel_id);
+        }
+        // Before edits are folded into their message, so rules can see them.
+        if let Some(automation) = self.automation.as_ref() {
+            if self.is_allowed(&inbound) {
+                automation.observe(&inbound);
+            }
+        }
+
+        if inbound.kind == InboundMessageKind::Message
+            && matches!(inbound.content.trim(), "/stop" | "/cancel")
+            && self.is_allowed(&inbound)
+        {
+            self.cancel(inbound);
+            return;
+        }
+
+        if inbound.kind == InboundMessageKind::Edit {
+            apply_edit(pending, inbound);
+        } else {
+            if let Some(journal) = self.journal.as_ref() {
+                journal.record(&inbound);
+            }
+            let debounce = Duration::from_millis(self.cfg.inbound.debounce_ms);
+            pending.push_back((Instant::now() + debounce, inbound));
+        }
+    }
+
+    /// `/cancel` (or `/stop`): cancel the conversation's run, which drops its model call
+    /// and kills its shell commands, and withdraw the approvals it was waiting on.
+    fn cancel(&self, inbound: InboundMessage) {
+        let stopped = self.watchdog.stop(&inbound.channel_id, &inbound.sender_id);
+        let assistant = self.assistant.clone();
+        let outbox = self.outbox.clone();
+        tokio::spawn(async move {
+            let withdrawn = assistant
+                .withdraw_approvals(&inbound.channel_id, &inbound.sender_id)
+                .await;
+            let reply = match (stopped, withdrawn) {
+                (false, 0) => "Nothing is running.".to_string(),
+                (_, 0) => "Stopped.".to_string(),
+                (_, 1) => "Stopped; withdrew 1 pending approval.".to_string(),
+                (_, n) => format!("Stopped; withdrew {n} pending approvals."),
+            };
+            let recipient = inbound.thread_id.as_deref().unwrap_or(&inbound.sender_id);
+            outbox.enqueue(
+                &inbound.channel_id,
+                recipient,
+                OutboundMessage {
+                    content: reply,
+                    reply_to_message_id: Some(inbound.message_id.clone()),
+                    attachments: vec![],
+                },
+            );
+        });
+    }
+
+    /// Errors are logged; either way the message is done with and leaves the journal.
+    /// In a cluster, a message whose lane can't be leased is returned unhandled, still in
+    /// the journal, to be queued again.
+    async fn handle(&self, inbound: InboundMessage) -> Option<InboundMessage> {
+        let journaled = self.journal.as_ref().map(|j| (j, inbound.clone()));
+        let (channel_id, sender_id) = (inbound.channel_id.clone(), inbound.sender_id.clone());
+        let lane = match self.cluster.as_ref() {
+            Some(cluster) => match self.take_lane(cluster, &channel_id, &sender_id).await {
+                Some(lease) => Some((cluster, lease)),
+                None => return Some(inbound),
+            },
+            None => None,
+        };
+        if let Err(e) = self.handle_inbound(inbound).await {
+            tracing::warn!(%e, "handle_inbound failed");
+        }
+        if let Some((cluster, lease)) = lane {
+            if let Some(session) = self.sessions.get(&channel_id, &sender_id) {
+                if let Err(e) = cluster
+                    .save_session(&channel_id, &sender_id, &session)
+                    .await
+                {
+                    tracing::warn!(%e, "failed to save session to the cluster");
+                }
+            }
+            lease.release().await;
+        }
+        if let Some((journal, inbound)) = journaled {
+            journal.ack(&inbound);
+        }
+        None
+    }
+
+    /// Lease the conversation and pick up its session as another replica may have left it.
+    /// `None` when another replica holds the lane or it can't be leased or loaded, so the
+    /// message waits rather than risk two replicas answering it.
+    async fn take_lane(
+        &self,
+        cluster: &Arc<Cluster>,
+        channel_id: &str,
+        sender_id: &str,
+    ) -> Option<LaneLease> {
+        let lease = match cluster.try_lock_lane(channel_id, sender_id).await {
+            Ok(Some(lease)) => lease,
+            Ok(None) => {
+                tracing::debug!(%channel_id, "lane held by another replica; requeued");
+                return None;
+            }
+            Err(e) => {
+                tracing::warn!(%e, "failed to lease conversation lane; requeued");
+                return None;
+            }
+        };
+        match cluster.load_session(channel_id, sender_id).await {
+            Ok(Some(session)) => self.sessions.insert(channel_id, sender_id, session),
+            Ok(None) => {}
+            Err(e) => {
+                tracing::warn!(%e, "failed to load session from the cluster; requeued");
+                lease.release().await;
+                return None;
+            }
+        }
+        Some(lease)
+    }
+
+    #[tracing::instrument(
+        level = "info",
+        skip_all,
+        fields(channel = %inbound.channel_id, session = tracing::field::Empty)
+    )]
+    async fn handle_inbound(&self, mut inbound: InboundMessage) -> Result<()> {
+        if let Some(pairing) = self.pairing.as_ref() {
+            if inbound.kind == InboundMessageKind::Message {
+                if let Some(code) = pairing::parse_pair(&inbound.content) {
+                    return self.pair(pairing, &inbound, &code).await;
+                }
+            }
+        }
+        if !self.is_allowed(&inbound) {
+            if inbound.kind == InboundMessageKind::Message
+                && self.cfg.suggestions.is_suggest_channel(&inbound.channel_id)
+            {
+                // Drafts wait for the focus session too; strangers get no status reply.
+                if let Some(focus) = self.focus.as_ref() {
+                    if focus.intercept(&inbound, false) != Intercept::Pass {
+                        return Ok(());
+                    }
+                }
+                if let Some(suggestions) = self.suggestions.as_ref() {
+                    return self.suggest_reply(suggestions, inbound).await;
+                }
+            }
             return Ok(());
         }
 
         if inbound.kind == InboundMessageKind::Reaction {
-            self.assistant.on_reaction(&inbound).await?;
+            // No run is in flight here, so the session's latest answer is the one shown.
+            let session = self.sessions.get(&inbound.channel_id, &inbound.sender_id);
+            let Some(feedback) = Feedback::from_reaction(&inbound, session.as_ref()) else {
+                return Ok(());
+            };
+            if let Some(store) = self.feedback.as_ref() {
+                if let Err(e) = store.record(&feedback) {
+                    tracing::warn!(%e, "failed to record feedback");
+                }
+            }
+            self.assistant.on_reaction(&feedback).await?;
             return Ok(());
         }
 
         let channel = self
-            .channels
-            .get(&inbound.channel_id)
+            .outbox
+            .channel(&inbound.channel_id)
             .ok_or_else(|| anyhow::anyhow!("unknown channel: {}", inbound.channel_id))?
             .clone();
+        let active_channels = self.outbox.channel_ids();
+        let recipient = inbound
+            .thread_id
+            .clone()
+            .unwrap_or_else(|| inbound.sender_id.clone());
 
-        let mut active_channels: Vec<String> = self.channels.keys().cloned().collect();
-        active_channels.sort();
+        if self.cfg.inbound.read_receipts {
+            if let Err(e) = channel.mark_read(&recipient, &inbound.message_id).await {
+                tracing::debug!(%e, "mark_read failed");
+            }
+        }
+
+        if let Some(focus) = self.focus.as_ref() {
+            if let Some(command) = FocusCommand::parse(&inbound.content) {
+                let reply = match command {
+                    Ok(command) => {
+                        let owner = ConversationOrigin {
+                            channel_id: inbound.channel_id.clone(),
+                            sender_id: inbound.sender_id.clone(),
+                            recipient: recipient.clone(),
+                        };
+                        focus.apply(owner, command).await
+                    }
+                    Err(usage) => usage,
+                };
+                return self.reply(&inbound, &recipient, reply);
+            }
+            if let Intercept::Held { auto_reply } = focus.intercept(&inbound, true) {
+                if let Some(reply) = auto_reply {
+                    return self.reply(&inbound, &recipient, reply);
+                }
+                return Ok(());
+            }
+        }
+
+        if let Some(suggestions) = self.suggestions.as_ref() {
+            if let Some(command) = SuggestionCommand::parse(&inbound.content) {
+                let reply = suggestions.apply(command).await;
+                return self.reply(&inbound, &recipient, reply);
+            }
+        }
+
+        // Webchat is reachable by anyone who can reach the port, so like the pairing
+        // routes, minting invites there needs `control.api_token` to be set.
+        if let Some(pairing) = self
+            .pairing
+            .as_ref()
+            .filter(|_| inbound.channel_id == "webchat")
+        {
+            if let Some(command) = InviteCommand::parse(&inbound.content) {
+                let (content, attachments) = match command {
+                    Ok(_) if self.cfg.control.api_token.is_none() => (
+                        "Invites are off until control.api_token is set.".to_string(),
+                        vec![],
+                    ),
+                    Ok(command) => pairing.apply(command, &active_channels),
+                    Err(usage) => (usage, vec![]),
+                };
+                return self.outbox.post(
+                    &inbound.channel_id,
+                    &recipient,
+                    OutboundMessage {
+                        content,
+                        reply_to_message_id: Some(inbound.message_id.clone()),
+                        attachments,
+                    },
+                );
+            }
+        }
+
+        if let Some(shares) = self.shares.as_ref() {
+            if let Some(command) = ShareCommand::parse(&inbound.content) {
+                let reply = match command {
+                    Ok(command) => {
+                        let last_answer = self
+                            .sessions
+                            .get_or_create_mut(&inbound.channel_id, &inbound.sender_id)
+                            .last_answer()
+                            .map(str::to_string);
+                        let source = format!("{}:{}", inbound.channel_id, inbound.sender_id);
+                        shares.apply(command, last_answer, &source).await
+                    }
+                    Err(usage) => usage,
+                };
+                return self.reply(&inbound, &recipient, reply);
+            }
+        }
+
+        if let Some(command) = MemoryCommand::parse(&inbound.content) {
+            let reply = match (command, self.facts.as_ref()) {
+                (Ok(command), Some(store)) => {
+                    let subject = facts::subject_for(
+                        &self.cfg.identities,
+                        &inbound.channel_id,
+                        &inbound.sender_id,
+                    );
+                    store.apply(command, &subject)
+                }
+                (Ok(_), None) => {
+                    "Long-term memory is off; set [memory.facts] enabled = true.".to_string()
+                }
+                (Err(usage), _) => usage,
+            };
+            return self.reply(&inbound, &recipient, reply);
+        }
+
+        if let Some(command) = MoveCommand::parse(&inbound.content) {
+            let reply = match command {
+                Ok(command) => self.move_conversation(&inbound, command).await,
+                Err(usage) => usage,
+            };
+            return self.reply(&inbound, &recipient, reply);
+        }
+
+        // `/skill <name> <request>` runs the request as that skill.
+        let skill = match skills::parse_invocation(&inbound.content) {
+            Some(Ok((name, request))) => {
+                inbound.content = request;
+                Some(name)
+            }
+            Some(Err(usage)) => return self.reply(&inbound, &recipient, usage),
+            None => None,
+        };
 
         let uptime = self.started_at.elapsed();
+        let integrity = self.integrity.latest();
+        // Read before taking this session's entry, which may share a lock with the others.
+        let linked_context = identities::shared_context(
+            &self.cfg.identities,
+            &self.sessions,
+            &inbound.channel_id,
+            &inbound.sender_id,
+        );
         let mut session = self
             .sessions
             .get_or_create_mut(&inbound.channel_id, &inbound.sender_id);
+        tracing::Span::current().record("session", tracing::field::display(session.id));
 
         if let Some(reply) = commands::handle_command(
             &self.cfg,
@@ -99,51 +557,586 @@ impl Gateway {
             &inbound.content,
             uptime,
             &active_channels,
+            integrity.as_ref(),
         ) {
-            channel
-                .send(
-                    inbound.thread_id.as_deref().unwrap_or(&inbound.sender_id),
-                    OutboundMessage {
-                        content: reply,
-                        reply_to_message_id: Some(inbound.message_id),
-                        attachments: vec![],
-                    },
-                )
-                .await?;
-            return Ok(());
+            return self.outbox.post(
+                &inbound.channel_id,
+                &recipient,
+                OutboundMessage {
+                    content: reply,
+                    reply_to_message_id: Some(inbound.message_id),
+                    attachments: vec![],
+                },
+            );
         }
 
         session.last_user_message_id = Some(inbound.message_id.clone());
         session.last_active = chrono::Utc::now();
+        session.linked_context = linked_context;
+        session.skill = skill;
+        session.run_persona = automation::requested_persona(&inbound);
+
+        let content = self.with_stored_attachments(&inbound).await;
+        let (content, sender_language) = self.translate_inbound(&inbound.channel_id, content).await;
+        let content = with_quoted_context(&inbound, content);
+
+        let origin = ConversationOrigin {
+            channel_id: inbound.channel_id.clone(),
+            sender_id: inbound.sender_id.clone(),
+            recipient: recipient.clone(),
+        };
+        let run = self.assistant.run(
+            &inbound.channel_id,
+            &inbound.sender_id,
+            &mut session,
+            &content,
+        );
+        let run = CONVERSATION.scope(origin.clone(), run);
+        let run = progress::run_with_progress(
+            &self.cfg.progress,
+            &self.outbox,
+            &inbound.channel_id,
+            &recipient,
+            run,
+        );
+        let typing = self
+            .cfg
+            .inbound
+            .typing_indicators
+            .then(|| tokio::spawn(keep_typing(channel, recipient.clone())));
+        let outcome = self.watchdog.supervise(origin, run).await;
+        if let Some(typing) = typing {
+            typing.abort();
+        }
+        if let Some(trace) = session.last_trace.as_mut() {
+            trace.finish(match &outcome {
+                None => "cancelled".to_string(),
+                Some(Ok(_)) => "completed".to_string(),
+                Some(Err(e)) => format!("failed: {e}"),
+            });
+        }
+        let response = match outcome {
+            // Cancelled as stuck or by `/cancel`; the user has already been told.
+            None => return Ok(()),
+            Some(Ok(v)) => {
+                if let Some(archive) = self.archive.as_ref() {
+                    if let Err(e) = archive
+                        .record_turn(
+                            session.id,
+                            &inbound.channel_id,
+                            &inbound.sender_id,
+                            &content,
+                            &v,
+                        )
+                        .await
+                    {
+                        tracing::warn!(%e, "failed to archive conversation turn");
+                    }
+                }
+                v
+            }
+            Some(Err(e)) => {
+                tracing::warn!(%e, "assistant.run failed");
+                format!("Error: {e}")
+            }
+        };
+        let response = match (sender_language, self.translator.as_ref()) {
+            (Some(language), Some(translator)) => {
+                match translator.translate(&response, &language).await {
+                    Ok(t) => t.text,
+                    Err(e) => {
+                        tracing::warn!(%e, "reply translation failed");
+                        response
+                    }
+                }
+            }
+            _ => response,
+        };
+
+        self.outbox.post(
+            &inbound.channel_id,
+            &recipient,
+            OutboundMessage {
+                content: response,
+                reply_to_message_id: Some(inbound.message_id),
+                attachments: vec![],
+            },
+        )
+    }
+
+    fn is_allowed(&self, inbound: &InboundMessage) -> bool {
+        self.sender_allowed(&inbound.channel_id, &inbound.sender_id)
+    }
+
+    fn sender_allowed(&self, channel_id: &str, sender_id: &str) -> bool {
+        pairing::is_allowed(&self.cfg, channel_id, sender_id)
+            || self
+                .pairing
+                .as_ref()
+                .is_some_and(|p| p.is_paired(channel_id, sender_id))
+    }
+
+    /// `/move`: continue this conversation on another channel. Senders move to their own
+    /// linked identity; only owners may name someone else, who must be allowed there
+    /// already, so a conversation can't be pushed to a stranger.
+    async fn move_conversation(&self, inbound: &InboundMessage, command: MoveCommand) -> String {
+        let to_sender = match command.recipient(
+            &self.cfg.identities,
+            &self.cfg.inbound.priority.owners,
+            &inbound.channel_id,
+            &inbound.sender_id,
+        ) {
+            Ok(to_sender) => to_sender,
+            Err(message) => return message,
+        };
+        if command.recipient.is_some() && !self.sender_allowed(&command.channel, &to_sender) {
+            return format!(
+                "{}:{to_sender} isn't allowed to talk to me; pair them first.",
+                command.channel
+            );
+        }
+        match handoff::handoff(
+            &self.assistant,
+            &self.sessions,
+            &self.outbox,
+            (&inbound.channel_id, &inbound.sender_id),
+            (&command.channel, &to_sender),
+        )
+        .await
+        {
+            Ok(_) => format!(
+                "Moved to {}. This conversation starts fresh.",
+                command.channel
+            ),
+            Err(e) => format!("Couldn't move: {e}"),
+        }
+    }
 
-        let response = match self
-            .assistant
-            .run(
+    /// Redeem an invite code. Senders already allowed in get told so and keep the code
+    /// unused, so it can still go to whoever it was meant for.
+    async fn pair(
+        &self,
+        pairing: &PairingStore,
+        inbound: &InboundMessage,
+        code: &str,
+    ) -> Result<()> {
+        let recipient = inbound.thread_id.as_deref().unwrap_or(&inbound.sender_id);
+        let reply = if self.is_allowed(inbound) {
+            "You're already set up here.".to_string()
+        } else {
+            match pairing.redeem(code, &inbound.channel_id, &inbound.sender_id)? {
+                Redeemed::Paired => {
+                    tracing::info!(
+                        channel = %inbound.channel_id,
+                        sender = %inbound.sender_id,
+                        "sender paired with an invite"
+                    );
+                    "You're paired. Say hi!".to_string()
+                }
+                Redeemed::WrongChannel(channel) => {
+                    format!("That invite is for {channel}; send it there.")
+                }
+                Redeemed::Invalid => "That invite code isn't valid or has expired.".to_string(),
+            }
+        };
+        self.reply(inbound, recipient, reply)
+    }
+
+    /// Answer `inbound` directly, for commands handled without a run.
+    fn reply(&self, inbound: &InboundMessage, recipient: &str, content: String) -> Result<()> {
+        self.outbox.post(
+            &inbound.channel_id,
+            recipient,
+            OutboundMessage {
+                content,
+                reply_to_message_id: Some(inbound.message_id.clone()),
+                attachments: vec![],
+            },
+        )
+    }
+
+    /// Draft a reply to a third party on a suggest-mode channel and queue it for the
+    /// owner's approval. Nothing is sent to the sender, not even a read receipt or typing
+    /// indicator, and the draft is written without tools.
+    #[tracing::instrument(level = "info", skip_all)]
+    async fn suggest_reply(
+        &self,
+        suggestions: &SuggestionQueue,
+        inbound: InboundMessage,
+    ) -> Result<()> {
+        let recipient = inbound
+            .thread_id
+            .clone()
+            .unwrap_or_else(|| inbound.sender_id.clone());
+        let content = self.with_stored_attachments(&inbound).await;
+        let prompt = format!(
+            "{} wrote to me on {}:\n{content}\n\nDraft the reply I should send. Answer with only the message text, written as me.",
+            inbound.sender_id, inbound.channel_id
+        );
+        let budget = RunBudget {
+            allow_delegation: false,
+            allow_tools: false,
+            ..RunBudget::interactive()
+        };
+        let origin = ConversationOrigin {
+            channel_id: inbound.channel_id.clone(),
+            sender_id: inbound.sender_id.clone(),
+            recipient: recipient.clone(),
+        };
+
+        let draft = {
+            let mut session = self
+                .sessions
+                .get_or_create_mut(&inbound.channel_id, &inbound.sender_id);
+            session.last_active = chrono::Utc::now();
+            let run = self.assistant.run_with_budget(
                 &inbound.channel_id,
                 &inbound.sender_id,
                 &mut session,
-                &inbound.content,
+                &prompt,
+                budget,
+            );
+            CONVERSATION.scope(origin, run).await?
+        };
+        suggestions
+            .propose(
+                &inbound.channel_id,
+                &inbound.sender_id,
+                &recipient,
+                &inbound.message_id,
+                &content,
+                draft,
             )
+            .await?;
+        Ok(())
+    }
+
+    /// On auto-translate channels, returns the message in the preferred language and, if
+    /// it was written in another one, that language so the reply can be translated back.
+    async fn translate_inbound(
+        &self,
+        channel_id: &str,
+        content: String,
+    ) -> (String, Option<String>) {
+        let cfg = &self.cfg.translation;
+        if !cfg.auto_translate_channels.iter().any(|c| c == channel_id) {
+            return (content, None);
+        }
+        let Some(translator) = self.translator.as_ref() else {
+            return (content, None);
+        };
+        match translator
+            .translate(&content, &cfg.preferred_language)
             .await
         {
-            Ok(v) => v,
+            Ok(t) if !t.was_in(&cfg.preferred_language) => (t.text, Some(t.source_language)),
+            Ok(_) => (content, None),
             Err(e) => {
-                tracing::warn!(%e, "assistant.run failed");
-                format!("Error: {e}")
+                tracing::warn!(%e, "inbound translation failed; using original text");
+                (content, None)
+            }
+        }
+    }
+
+    /// Store inbound attachments and append a short reference for each to the user's text,
+    /// so the assistant knows they exist.
+    async fn with_stored_attachments(&self, inbound: &InboundMessage) -> String {
+        let mut content = inbound.content.clone();
+        let Some(store) = self.attachments.as_ref() else {
+            return content;
+        };
+        for attachment in &inbound.attachments {
+            let origin = AttachmentOrigin {
+                direction: AttachmentDirection::Inbound,
+                channel_id: inbound.channel_id.clone(),
+                message_id: Some(inbound.message_id.clone()),
+            };
+            match store.ingest(attachment, origin).await {
+                Ok(stored) => content.push_str(&format!(
+                    "\n[attachment {}: {} ({}, {} bytes)]",
+                    stored.id, stored.name, stored.content_type, stored.size_bytes
+                )),
+                Err(e) => {
+                    tracing::warn!(%e, name = %attachment.name, "failed to store inbound attachment");
+                    content.push_str(&format!(
+                        "\n[attachment not stored: {} ({})]",
+                        attachment.name, attachment.content_type
+                    ));
+                }
+            }
+        }
+        content
+    }
+}
+
+/// Refresh the channel's typing indicator until aborted.
+async fn keep_typing(channel: Arc<dyn ChannelAdapter>, recipient: String) {
+    let mut interval = tokio::time::interval(TYPING_REFRESH);
+    loop {
+        interval.tick().await;
+        if let Err(e) = channel.send_typing(&recipient).await {
+            tracing::debug!(%e, "send_typing failed");
+            return;
+        }
+    }
+}
+
+/// Spell out the message being replied to, so "do what I asked here" has a referent.
+fn with_quoted_context(inbound: &InboundMessage, content: String) -> String {
+    const QUOTE_MAX_CHARS: usize = 2000;
+
+    let Some(quoted) = inbound.quoted() else {
+        return content;
+    };
+    let whose = if quoted.from_bot {
+        "your"
+    } else if quoted.sender_id.as_deref() == Some(inbound.sender_id.as_str()) {
+        "their own"
+    } else {
+        "an"
+    };
+    let quote = match quoted.content.as_deref().map(str::trim) {
+        Some(text) if !text.is_empty() => {
+            let mut excerpt: String = text.chars().take(QUOTE_MAX_CHARS).collect();
+            if excerpt.len() < text.len() {
+                excerpt.push('…');
             }
+            excerpt
+                .lines()
+                .map(|line| format!("> {line}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+        _ => "(the quoted text isn't available)".to_string(),
+    };
+    format!(
+        "[The user is replying to {whose} earlier message (id {}):\n{quote}]\n\n{content}",
+        quoted.message_id
+    )
+}
+
+/// Where `inbound` waits in the queue; see `InboundPriorityConfig`.
+fn priority(cfg: &InboundPriorityConfig, inbound: &InboundMessage) -> Priority {
+    if automation::is_generated(inbound) {
+        return automation::requested_priority(inbound).unwrap_or(cfg.automation);
+    }
+    if inbound.is_group {
+        return cfg.groups;
+    }
+    let owner = inbound.channel_id == "webchat"
+        || cfg
+            .owners
+            .iter()
+            .any(|o| *o == format!("{}:{}", inbound.channel_id, inbound.sender_id));
+    if owner {
+        return Priority::High;
+    }
+    cfg.channels
+        .get(&inbound.channel_id)
+        .copied()
+        .unwrap_or(Priority::Normal)
+}
+
+/// When each queued message may run: once it's due and so is every earlier message of
+/// its conversation, so nothing overtakes one waiting for its lane.
+fn ready_at(pending: &Pending) -> Vec<Instant> {
+    let mut lanes: HashMap<(&str, &str), Instant> = HashMap::new();
+    pending
+        .iter()
+        .map(|(due, m)| {
+            let lane = lanes
+                .entry((m.channel_id.as_str(), m.sender_id.as_str()))
+                .or_insert(*due);
+            *lane = (*lane).max(*due);
+            *lane
+        })
+        .collect()
+}
+
+/// The queued message to run next: the highest priority among those that are ready, the
+/// oldest of them on a tie.
+fn next_due(
+    pending: &Pending,
+    now: Instant,
+    priority: impl Fn(&InboundMessage) -> Priority,
+) -> Option<usize> {
+    ready_at(pending)
+        .into_iter()
+        .zip(pending)
+        .enumerate()
+        .filter(|(_, (ready, _))| *ready <= now)
+        .max_by_key(|(i, (_, (_, m)))| (priority(m), std::cmp::Reverse(*i)))
+        .map(|(i, _)| i)
+}
+
+/// Replace the text of a queued, not yet started message with its edited version. Edits
+/// to messages already being (or done being) processed are dropped.
+fn apply_edit(pending: &mut Pending, edit: InboundMessage) {
+    let queued = pending.iter_mut().find(|(_, m)| {
+        m.kind == InboundMessageKind::Message
+            && m.channel_id == edit.channel_id
+            && m.message_id == edit.message_id
+    });
+    match queued {
+        Some((_, m)) => {
+            m.content = edit.content;
+            m.metadata = edit.metadata;
+        }
+        None => tracing::debug!(
+            channel_id = %edit.channel_id,
+            message_id = %edit.message_id,
+            "ignoring edit for a message that already started"
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automation::AutomationRule;
+
+    fn inbound(kind: InboundMessageKind, message_id: &str, content: &str) -> InboundMessage {
+        InboundMessage {
+            kind,
+            message_id: message_id.to_string(),
+            channel_id: "telegram".to_string(),
+            sender_id: "u1".to_string(),
+            thread_id: None,
+            is_group: false,
+            content: content.to_string(),
+            attachments: vec![],
+            metadata: serde_json::json!({}),
+            received_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn edit_supersedes_queued_message_only() {
+        let mut pending = VecDeque::new();
+        pending.push_back((
+            Instant::now(),
+            inbound(InboundMessageKind::Message, "1", "wether in paris"),
+        ));
+
+        apply_edit(
+            &mut pending,
+            inbound(InboundMessageKind::Edit, "1", "weather in paris"),
+        );
+        apply_edit(
+            &mut pending,
+            inbound(InboundMessageKind::Edit, "2", "unrelated"),
+        );
+
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].1.content, "weather in paris");
+    }
+
+    #[test]
+    fn owner_messages_run_first_once_due() {
+        let cfg = InboundPriorityConfig {
+            owners: vec!["telegram:owner".to_string()],
+            channels: [("discord".to_string(), Priority::Low)].into(),
+            ..InboundPriorityConfig::default()
+        };
+        let mut group = inbound(InboundMessageKind::Message, "1", "lunch?");
+        group.is_group = true;
+        let digest: AutomationRule = toml::from_str(
+            r#"
+            id = "digest"
+            trigger = { type = "webhook" }
+            actions = [{ type = "forward", deliver_to = "ntfy:me" }]
+            "#,
+        )
+        .unwrap();
+        let pager = AutomationRule {
+            id: "pager".to_string(),
+            priority: Some(Priority::High),
+            ..digest.clone()
         };
+        let mut rule =
+            automation::prompt_message(&digest, "telegram", "u1", None, "daily digest".into());
+        rule.message_id = "2".to_string();
+        let friend = inbound(InboundMessageKind::Message, "3", "hey");
+        let mut owner = inbound(InboundMessageKind::Message, "4", "what's next?");
+        owner.sender_id = "owner".to_string();
+        let mut urgent =
+            automation::prompt_message(&pager, "telegram", "u1", None, "pager alert".into());
+        urgent.message_id = "5".to_string();
+        // Claiming to come from a rule isn't enough.
+        let mut spoofed = friend.clone();
+        spoofed.metadata = serde_json::json!({ "source": "automation", "priority": "high" });
 
-        channel
-            .send(
-                inbound.thread_id.as_deref().unwrap_or(&inbound.sender_id),
-                OutboundMessage {
-                    content: response,
-                    reply_to_message_id: Some(inbound.message_id),
-                    attachments: vec![],
-                },
-            )
-            .await?;
+        assert_eq!(priority(&cfg, &group), Priority::Low);
+        assert_eq!(priority(&cfg, &rule), Priority::Low);
+        assert_eq!(priority(&cfg, &friend), Priority::Normal);
+        assert_eq!(priority(&cfg, &owner), Priority::High);
+        assert_eq!(priority(&cfg, &urgent), Priority::High);
+        assert_eq!(priority(&cfg, &spoofed), Priority::Normal);
+        let mut elsewhere = friend.clone();
+        elsewhere.channel_id = "discord".to_string();
+        assert_eq!(priority(&cfg, &elsewhere), Priority::Low);
 
-        Ok(())
+        let debounce = Duration::from_millis(100);
+        let start = Instant::now();
+        let mut pending: Pending = [group, rule, friend, owner]
+            .into_iter()
+            .enumerate()
+            .map(|(i, m)| (start + debounce + Duration::from_millis(10 * i as u64), m))
+            .collect();
+        pending.push_back((start + debounce + Duration::from_millis(500), urgent));
+        let pick = |pending: &Pending, at| {
+            next_due(pending, at, |m| priority(&cfg, m)).map(|i| pending[i].1.message_id.clone())
+        };
+
+        assert_eq!(pick(&pending, start), None);
+        // Only the group message is past the debounce yet.
+        assert_eq!(pick(&pending, start + debounce).as_deref(), Some("1"));
+        let later = start + Duration::from_millis(200);
+        assert_eq!(pick(&pending, later).as_deref(), Some("4"));
+        pending.retain(|(_, m)| m.message_id != "4");
+        assert_eq!(pick(&pending, later).as_deref(), Some("3"));
+        pending.retain(|(_, m)| m.message_id != "3");
+        // Equal priorities run oldest first.
+        assert_eq!(pick(&pending, later).as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn messages_wait_behind_one_requeued_for_its_lane() {
+        let cfg = InboundPriorityConfig::default();
+        let now = Instant::now();
+        let first = inbound(InboundMessageKind::Message, "1", "book the table");
+        let second = inbound(InboundMessageKind::Message, "2", "for four");
+        let mut other = inbound(InboundMessageKind::Message, "3", "hi");
+        other.sender_id = "u2".to_string();
+        let mut pending: Pending = [(now + LANE_RETRY, first), (now, second), (now, other)].into();
+        let pick = |pending: &Pending, at| {
+            next_due(pending, at, |m| priority(&cfg, m)).map(|i| pending[i].1.message_id.clone())
+        };
+
+        assert_eq!(pick(&pending, now).as_deref(), Some("3"));
+        pending.pop_back();
+        assert_eq!(pick(&pending, now), None);
+        assert_eq!(pick(&pending, now + LANE_RETRY).as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn replies_carry_the_quoted_message() {
+        let mut reply = inbound(InboundMessageKind::Message, "2", "do what I asked here");
+        assert_eq!(
+            with_quoted_context(&reply, reply.content.clone()),
+            "do what I asked here"
+        );
+
+        os_channels::QuotedMessage {
+            message_id: "1".to_string(),
+            sender_id: Some("u1".to_string()),
+            from_bot: false,
+            content: Some("book a table\nfor two".to_string()),
+        }
+        .attach(&mut reply.metadata);
+        assert_eq!(
+            with_quoted_context(&reply, reply.content.clone()),
+            "[The user is replying to their own earlier message (id 1):\n> book a table\n> for two]\n\ndo what I asked here"
+        );
     }
 }
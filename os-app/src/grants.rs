@@ -0,0 +1,141 @@
+//! Temporary approval grants.
+//!
+//! While a tool call waits for a human, the approver can ask for the same call (same tool,
+//! same arguments) to go through without asking again, for the rest of the session or for
+//! a while: `POST /api/v1/os/approvals/{action_id}/remember` with `{"for": "session"}` or
+//! `{"for": "1h"}`, sent before approving. The grant takes effect only if the action is
+//! then approved, is dropped by `/new`, and is ignored after a suspected prompt injection.
+
+use crate::focus::parse_duration;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Longest timed grant; "session" has no limit beyond the session itself.
+const GRANT_MAX: Duration = Duration::hours(24);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrantScope {
+    Session,
+    For(Duration),
+}
+
+impl GrantScope {
+    /// `session`, or a duration like `1h` or `30m`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("session") {
+            return Ok(Self::Session);
+        }
+        match parse_duration(s) {
+            Some(d) if d <= GRANT_MAX => Ok(Self::For(d)),
+            Some(_) => Err("grants last at most 24h".to_string()),
+            None => Err(format!(
+                "expected \"session\" or a duration like 1h, got {s:?}"
+            )),
+        }
+    }
+}
+
+/// Grants held by one session.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionGrants {
+    /// Call signature -> expiry; `None` lasts as long as the session.
+    grants: HashMap<String, Option<DateTime<Utc>>>,
+}
+
+impl SessionGrants {
+    pub fn allows(&self, tool: &str, arguments: &serde_json::Value) -> bool {
+        self.grants
+            .get(&signature(tool, arguments))
+            .is_some_and(|expiry| expiry.is_none_or(|at| Utc::now() < at))
+    }
+
+    pub fn insert(&mut self, tool: &str, arguments: &serde_json::Value, scope: GrantScope) {
+        let now = Utc::now();
+        self.grants
+            .retain(|_, expiry| expiry.is_none_or(|at| now < at));
+        let expiry = match scope {
+            GrantScope::Session => None,
+            GrantScope::For(d) => Some(now + d),
+        };
+        self.grants.insert(signature(tool, arguments), expiry);
+    }
+}
+
+/// Calls waiting for a human, and the grant asked for on each.
+#[derive(Default)]
+pub struct GrantOffers {
+    waiting: Mutex<HashMap<Uuid, Option<GrantScope>>>,
+}
+
+impl GrantOffers {
+    pub fn open(&self, action_id: Uuid) {
+        if let Ok(mut waiting) = self.waiting.lock() {
+            waiting.insert(action_id, None);
+        }
+    }
+
+    /// False when `action_id` isn't waiting (unknown, or already decided).
+    pub fn remember(&self, action_id: Uuid, scope: GrantScope) -> bool {
+        let Ok(mut waiting) = self.waiting.lock() else {
+            return false;
+        };
+        match waiting.get_mut(&action_id) {
+            Some(slot) => {
+                *slot = Some(scope);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stop waiting; returns the grant asked for, if any.
+    pub fn close(&self, action_id: Uuid) -> Option<GrantScope> {
+        self.waiting.lock().ok()?.remove(&action_id).flatten()
+    }
+}
+
+/// Object keys serialize sorted, so equal arguments give equal signatures.
+fn signature(tool: &str, arguments: &serde_json::Value) -> String {
+    format!("{tool} {arguments}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn remembered_grants_cover_the_same_call_until_they_expire() {
+        assert_eq!(GrantScope::parse("Session"), Ok(GrantScope::Session));
+        assert_eq!(
+            GrantScope::parse("1h"),
+            Ok(GrantScope::For(Duration::hours(1)))
+        );
+        assert!(GrantScope::parse("2d").is_err());
+        assert!(GrantScope::parse("forever").is_err());
+
+        let offers = GrantOffers::default();
+        let action = Uuid::new_v4();
+        assert!(!offers.remember(action, GrantScope::Session));
+        offers.open(action);
+        assert!(offers.remember(action, GrantScope::Session));
+        assert_eq!(offers.close(action), Some(GrantScope::Session));
+        assert_eq!(offers.close(action), None);
+
+        let mut grants = SessionGrants::default();
+        let status = json!({ "command": "git status", "cwd": "/repo" });
+        grants.insert("shell.execute", &status, GrantScope::Session);
+        assert!(grants.allows(
+            "shell.execute",
+            &json!({ "cwd": "/repo", "command": "git status" })
+        ));
+        assert!(!grants.allows("shell.execute", &json!({ "command": "git push" })));
+
+        grants.insert("browser", &json!({}), GrantScope::For(Duration::zero()));
+        assert!(!grants.allows("browser", &json!({})));
+    }
+}
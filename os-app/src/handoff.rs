@@ -0,0 +1,273 @@
+//! Moving a conversation to another channel (`/move`).
+//!
+//! The session, history included, is re-keyed to the destination conversation, and the
+//! assistant opens there with a short summary of where things stand, so e.g. an iMessage
+//! chat can carry on over Telegram. The conversation left behind starts fresh. A move
+//! never lands on a conversation that's already going at the destination.
+
+use crate::assistant::{AssistantAgent, RunBudget};
+use crate::config::IdentitiesConfig;
+use crate::identities;
+use crate::outbox::Outbox;
+use crate::session::SessionManager;
+use anyhow::{anyhow, Result};
+use os_channels::OutboundMessage;
+use os_llm::{ChatMessage, Role};
+
+/// Characters of each message quoted when there's no LLM summary to send instead.
+const FALLBACK_CHARS_MAX: usize = 300;
+
+const SUMMARY_PROMPT: &str = "This conversation is moving to another channel. In a few sentences addressed to me, sum up where we are: what it's about, what's been settled and what's still open. Answer with only the summary.";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoveCommand {
+    pub channel: String,
+    /// Who to continue with there; defaults to the sender's linked id on that channel.
+    /// Only owners may name someone other than themselves.
+    pub recipient: Option<String>,
+}
+
+impl MoveCommand {
+    /// `/move telegram`, `/move telegram 12345` or `/move telegram:12345`.
+    pub fn parse(input: &str) -> Option<Result<Self, String>> {
+        let rest = input.trim().strip_prefix("/move")?;
+        if !rest.is_empty() && !rest.starts_with(' ') {
+            return None;
+        }
+        let mut words = rest.split_whitespace();
+        let (channel, recipient) = match (words.next(), words.next(), words.next()) {
+            (Some(target), None, None) => match target.split_once(':') {
+                Some((channel, recipient)) => (channel, Some(recipient)),
+                None => (target, None),
+            },
+            (Some(channel), Some(recipient), None) => (channel, Some(recipient)),
+            _ => {
+                return Some(Err(
+                    "Usage: /move <channel> [recipient], e.g. /move telegram".to_string(),
+                ))
+            }
+        };
+        Some(Ok(Self {
+            channel: channel.to_string(),
+            recipient: recipient.filter(|r| !r.is_empty()).map(str::to_string),
+        }))
+    }
+
+    /// The recipient on `self.channel`: the sender's own id there per
+    /// `identities.people`, or the one given when it's that id or the sender is one of
+    /// `owners` (`channel:sender`).
+    pub fn recipient(
+        &self,
+        cfg: &IdentitiesConfig,
+        owners: &[String],
+        channel_id: &str,
+        sender_id: &str,
+    ) -> Result<String, String> {
+        let linked = identities::linked_senders(cfg, channel_id, sender_id)
+            .and_then(|(_, others)| others.into_iter().find(|(c, _)| *c == self.channel))
+            .map(|(_, sender)| sender);
+        match (self.recipient.as_ref(), linked) {
+            (Some(recipient), _) if owners.contains(&format!("{channel_id}:{sender_id}")) => {
+                Ok(recipient.clone())
+            }
+            (Some(recipient), Some(linked)) if *recipient == linked => Ok(linked),
+            (Some(_), _) => Err(format!(
+                "You can only move this conversation to yourself. Link your {0} id under identities.people, then /move {0}.",
+                self.channel
+            )),
+            (None, Some(linked)) => Ok(linked),
+            (None, None) => Err(format!(
+                "I don't know who you are on {0}. Link your ids under identities.people, then /move {0}.",
+                self.channel
+            )),
+        }
+    }
+}
+
+/// Move `from` to `to` and open the conversation there with a summary. Returns the
+/// summary that was sent.
+pub async fn handoff(
+    assistant: &AssistantAgent,
+    sessions: &SessionManager,
+    outbox: &Outbox,
+    from: (&str, &str),
+    to: (&str, &str),
+) -> Result<String> {
+    let (from_channel, from_sender) = from;
+    let (to_channel, to_sender) = to;
+    if outbox.channel(to_channel).is_none() {
+        return Err(anyhow!("{to_channel} isn't an enabled channel"));
+    }
+    if from == to {
+        return Err(anyhow!("this conversation is already there"));
+    }
+    if sessions
+        .get(to_channel, to_sender)
+        .is_some_and(|s| !s.history.is_empty())
+    {
+        return Err(anyhow!(
+            "there's already a conversation going on {to_channel}; /new there first"
+        ));
+    }
+    let mut scratch = sessions
+        .get(from_channel, from_sender)
+        .filter(|s| s.history.iter().any(|m| matches!(m.role, Role::User)))
+        .ok_or_else(|| anyhow!("there's nothing to move yet"))?;
+
+    let fallback = fallback_summary(&scratch.history);
+    let budget = RunBudget {
+        tool_loops_max: 1,
+        allow_delegation: false,
+        allow_tools: false,
+        ..RunBudget::interactive()
+    };
+    let summary = match assistant
+        .run_with_budget(to_channel, to_sender, &mut scratch, SUMMARY_PROMPT, budget)
+        .await
+    {
+        Ok(summary) if !summary.trim().is_empty() => summary,
+        Ok(_) => fallback,
+        Err(e) => {
+            tracing::warn!(%e, "handoff summary failed; quoting the last exchange instead");
+            fallback
+        }
+    };
+    let content = format!("Picking up our conversation from {from_channel}.\n\n{summary}");
+
+    sessions
+        .move_to(from_channel, from_sender, to_channel, to_sender)
+        .ok_or_else(|| anyhow!("the conversation changed before it could be moved"))?;
+    sessions
+        .get_or_create_mut(to_channel, to_sender)
+        .history
+        .push(ChatMessage {
+            role: Role::Assistant,
+            content: content.clone(),
+            tool_calls: vec![],
+            tool_call_id: None,
+            reasoning: vec![],
+        });
+    outbox.enqueue(
+        to_channel,
+        to_sender,
+        OutboundMessage {
+            content,
+            reply_to_message_id: None,
+            attachments: vec![],
+        },
+    );
+    tracing::info!(from = %from_channel, to = %to_channel, "conversation moved");
+    Ok(summary)
+}
+
+/// The last thing asked and answered, for when the LLM can't summarize.
+fn fallback_summary(history: &[ChatMessage]) -> String {
+    let last = |role: Role| {
+        history
+            .iter()
+            .rev()
+            .find(|m| m.role == role && !m.content.trim().is_empty())
+            .map(|m| clip(&m.content))
+    };
+    match (last(Role::User), last(Role::Assistant)) {
+        (Some(asked), Some(answered)) => {
+            format!("You last asked: {asked}\nI answered: {answered}")
+        }
+        (Some(asked), None) => format!("You last asked: {asked}"),
+        _ => "The full history came along.".to_string(),
+    }
+}
+
+fn clip(text: &str) -> String {
+    let mut out: String = text.chars().take(FALLBACK_CHARS_MAX).collect();
+    if out.len() < text.len() {
+        out.push('…');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn message(role: Role, content: &str) -> ChatMessage {
+        ChatMessage {
+            role,
+            content: content.to_string(),
+            tool_calls: vec![],
+            tool_call_id: None,
+            reasoning: vec![],
+        }
+    }
+
+    #[test]
+    fn parses_channel_and_optional_recipient() {
+        let cmd = |channel: &str, recipient: Option<&str>| {
+            Some(Ok(MoveCommand {
+                channel: channel.to_string(),
+                recipient: recipient.map(str::to_string),
+            }))
+        };
+        assert_eq!(MoveCommand::parse("/move telegram"), cmd("telegram", None));
+        assert_eq!(
+            MoveCommand::parse("/move telegram 42"),
+            cmd("telegram", Some("42"))
+        );
+        assert_eq!(
+            MoveCommand::parse("/move imessage:+14155551212"),
+            cmd("imessage", Some("+14155551212"))
+        );
+        assert!(matches!(MoveCommand::parse("/move"), Some(Err(_))));
+        assert_eq!(MoveCommand::parse("/moved"), None);
+    }
+
+    #[test]
+    fn recipient_defaults_to_the_linked_identity() {
+        let cfg = IdentitiesConfig {
+            people: HashMap::from([(
+                "alice".to_string(),
+                vec!["imessage:+1555".to_string(), "telegram:42".to_string()],
+            )]),
+            ..Default::default()
+        };
+        let owners = vec!["webchat:owner".to_string()];
+        let cmd = MoveCommand::parse("/move telegram").unwrap().unwrap();
+        assert_eq!(
+            cmd.recipient(&cfg, &owners, "imessage", "+1555"),
+            Ok("42".to_string())
+        );
+        assert!(cmd.recipient(&cfg, &owners, "imessage", "+1999").is_err());
+
+        // Naming a recipient only works for yourself, unless you're an owner.
+        let yourself = MoveCommand::parse("/move telegram 42").unwrap().unwrap();
+        let someone = MoveCommand::parse("/move telegram 7").unwrap().unwrap();
+        assert_eq!(
+            yourself.recipient(&cfg, &owners, "imessage", "+1555"),
+            Ok("42".to_string())
+        );
+        assert!(someone
+            .recipient(&cfg, &owners, "imessage", "+1555")
+            .is_err());
+        assert!(someone
+            .recipient(&cfg, &owners, "imessage", "+1999")
+            .is_err());
+        assert_eq!(
+            someone.recipient(&cfg, &owners, "webchat", "owner"),
+            Ok("7".to_string())
+        );
+    }
+
+    #[test]
+    fn fallback_quotes_the_last_exchange() {
+        let history = vec![
+            message(Role::User, "book a table"),
+            message(Role::Assistant, "Done, 7pm."),
+            message(Role::User, "for four"),
+        ];
+        assert_eq!(
+            fallback_summary(&history),
+            "You last asked: for four\nI answered: Done, 7pm."
+        );
+    }
+}
@@ -0,0 +1,204 @@
+//! Liveness and readiness behind `/healthz`, `/readyz` and `opencraw status`.
+//!
+//! Live means the server answers. Ready means it can do its job: every channel adapter is
+//! still receiving, the LLM isn't cooling down after repeated failures, and the data dir
+//! is writable. Channels whose sends are failing are reported but don't make the server
+//! unready, since the outbox keeps retrying them.
+
+use crate::metrics::{LlmHealth, Metrics, QueueDepth};
+use crate::outbox::Outbox;
+use chrono::{DateTime, Utc};
+use os_channels::ChannelAdapter;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const PROBE_FILE: &str = ".readyz";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelState {
+    Ok,
+    /// The last send failed.
+    Failing,
+    /// The adapter stopped receiving.
+    Crashed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelStatus {
+    pub state: ChannelState,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DataDirStatus {
+    pub path: PathBuf,
+    pub writable: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub channels: BTreeMap<String, ChannelStatus>,
+    pub llm: LlmHealth,
+    pub queues: QueueDepth,
+    pub data_dir: DataDirStatus,
+}
+
+impl Readiness {
+    pub async fn check(
+        channels: &HashMap<String, Arc<dyn ChannelAdapter>>,
+        outbox: &Outbox,
+        metrics: &Metrics,
+        data_dir: &Path,
+    ) -> Self {
+        let failing = outbox.failing_channels();
+        let channels: BTreeMap<String, ChannelStatus> = channels
+            .iter()
+            .map(|(id, adapter)| {
+                let status = match (adapter.receive_failure(), failing.get(id)) {
+                    (Some(error), _) => ChannelStatus {
+                        state: ChannelState::Crashed,
+                        error: Some(error),
+                    },
+                    (None, Some(error)) => ChannelStatus {
+                        state: ChannelState::Failing,
+                        error: Some(error.clone()),
+                    },
+                    (None, None) => ChannelStatus {
+                        state: ChannelState::Ok,
+                        error: None,
+                    },
+                };
+                (id.clone(), status)
+            })
+            .collect();
+        let llm = metrics.llm_health(Utc::now());
+        let data_dir = probe_data_dir(data_dir.to_path_buf()).await;
+        Self {
+            ready: llm.reachable
+                && data_dir.writable
+                && channels.values().all(|c| c.state != ChannelState::Crashed),
+            channels,
+            llm,
+            queues: metrics.queues(),
+            data_dir,
+        }
+    }
+}
+
+/// Create, write and remove a file in `dir`.
+async fn probe_data_dir(dir: PathBuf) -> DataDirStatus {
+    let probe = dir.join(PROBE_FILE);
+    let res = tokio::task::spawn_blocking(move || {
+        std::fs::write(&probe, b"ok")?;
+        std::fs::remove_file(&probe)
+    })
+    .await
+    .map_err(std::io::Error::other)
+    .and_then(|r| r);
+    DataDirStatus {
+        path: dir,
+        writable: res.is_ok(),
+        error: res.err().map(|e| e.to_string()),
+    }
+}
+
+/// Compact text form for `opencraw status`.
+pub fn render(r: &Readiness, now: DateTime<Utc>) -> String {
+    let mut out = format!("ready: {}\n", if r.ready { "yes" } else { "no" });
+    for (id, c) in &r.channels {
+        if c.state == ChannelState::Ok {
+            continue;
+        }
+        let state = match c.state {
+            ChannelState::Crashed => "crashed",
+            _ => "failing",
+        };
+        out.push_str(&format!(
+            "  channel {id} {state}: {}\n",
+            c.error.as_deref().unwrap_or("-")
+        ));
+    }
+    if let Some(until) = r.llm.cooldown_until {
+        out.push_str(&format!(
+            "  llm cooling down for {}s after {} failures\n",
+            (until - now).num_seconds().max(0),
+            r.llm.consecutive_failures
+        ));
+    }
+    if let Some(error) = r.llm.last_error.as_deref() {
+        out.push_str(&format!("  llm last error: {error}\n"));
+    }
+    if !r.data_dir.writable {
+        out.push_str(&format!(
+            "  data dir {} not writable: {}\n",
+            r.data_dir.path.display(),
+            r.data_dir.error.as_deref().unwrap_or("-")
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use os_channels::{InboundMessage, OutboundMessage, ReceiveFailure};
+    use tokio::sync::mpsc;
+
+    struct Stopped(ReceiveFailure);
+
+    #[async_trait]
+    impl ChannelAdapter for Stopped {
+        fn channel_id(&self) -> &str {
+            "telegram"
+        }
+
+        async fn start(&self, _tx: mpsc::Sender<InboundMessage>) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        async fn send(&self, _recipient_id: &str, _message: OutboundMessage) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn receive_failure(&self) -> Option<String> {
+            self.0.get()
+        }
+    }
+
+    #[tokio::test]
+    async fn crashed_adapters_and_unwritable_data_dirs_are_not_ready() {
+        let failure = ReceiveFailure::default();
+        let mut channels: HashMap<String, Arc<dyn ChannelAdapter>> = HashMap::new();
+        channels.insert("telegram".to_string(), Arc::new(Stopped(failure.clone())));
+        let outbox = Outbox::new(channels.clone());
+        let metrics = Metrics::new(vec!["telegram".to_string()]);
+        let dir = std::env::temp_dir().join(format!("opencraw-health-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let r = Readiness::check(&channels, &outbox, &metrics, &dir).await;
+        assert!(r.ready);
+        assert_eq!(r.channels["telegram"].state, ChannelState::Ok);
+        assert!(!dir.join(PROBE_FILE).exists());
+
+        failure.set("getUpdates: 401 Unauthorized");
+        let r = Readiness::check(&channels, &outbox, &metrics, &dir).await;
+        assert!(!r.ready);
+        assert_eq!(r.channels["telegram"].state, ChannelState::Crashed);
+        assert!(render(&r, Utc::now())
+            .contains("channel telegram crashed: getUpdates: 401 Unauthorized"));
+
+        let missing = dir.join("missing");
+        let r = Readiness::check(&HashMap::new(), &outbox, &metrics, &missing).await;
+        assert!(!r.ready);
+        assert!(!r.data_dir.writable);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,120 @@
+//! One person, several channels.
+//!
+//! `identities.people` links a person's sender ids across channels. Each conversation
+//! keeps its own session, but when a linked conversation has been active recently its
+//! latest messages are shown to the assistant alongside the current one, each line marked
+//! with the channel it came from, so replies on one channel don't contradict what was
+//! just said on another.
+
+use crate::config::IdentitiesConfig;
+use crate::session::SessionManager;
+use os_llm::Role;
+
+/// Characters kept per shared message.
+const MESSAGE_CHARS_MAX: usize = 500;
+
+/// The person `channel_id:sender_id` is linked to, and their other `(channel, sender)`s.
+pub fn linked_senders(
+    cfg: &IdentitiesConfig,
+    channel_id: &str,
+    sender_id: &str,
+) -> Option<(String, Vec<(String, String)>)> {
+    let me = format!("{channel_id}:{sender_id}");
+    let (person, senders) = cfg.people.iter().find(|(_, s)| s.contains(&me))?;
+    let others = senders
+        .iter()
+        .filter(|s| **s != me)
+        .filter_map(|s| s.split_once(':'))
+        .map(|(c, s)| (c.to_string(), s.to_string()))
+        .collect();
+    Some((person.clone(), others))
+}
+
+/// Recent messages from the sender's other active conversations, ready to append to the
+/// system prompt. `None` when there are none.
+pub fn shared_context(
+    cfg: &IdentitiesConfig,
+    sessions: &SessionManager,
+    channel_id: &str,
+    sender_id: &str,
+) -> Option<String> {
+    let (person, others) = linked_senders(cfg, channel_id, sender_id)?;
+    let since = chrono::Utc::now() - chrono::Duration::minutes(cfg.shared_context_minutes as i64);
+
+    let mut lines = Vec::new();
+    for (other_channel, other_sender) in others {
+        let messages = sessions.recent_messages(
+            &other_channel,
+            &other_sender,
+            since,
+            cfg.shared_context_messages,
+        );
+        for m in messages {
+            let speaker = match m.role {
+                Role::User => person.as_str(),
+                _ => "you",
+            };
+            let mut text: String = m.content.chars().take(MESSAGE_CHARS_MAX).collect();
+            if text.len() < m.content.len() {
+                text.push('…');
+            }
+            lines.push(format!("[via {other_channel}] {speaker}: {text}"));
+        }
+    }
+    if lines.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "{person} is also talking to you on another channel right now. Recent messages there, for context (reply only in this conversation and stay consistent with them):\n{}",
+        lines.join("\n")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use os_llm::ChatMessage;
+    use std::collections::HashMap;
+
+    fn message(role: Role, content: &str) -> ChatMessage {
+        ChatMessage {
+            role,
+            content: content.to_string(),
+            tool_calls: vec![],
+            tool_call_id: None,
+            reasoning: vec![],
+        }
+    }
+
+    #[test]
+    fn active_linked_conversation_is_shared_with_provenance() {
+        let cfg = IdentitiesConfig {
+            people: HashMap::from([(
+                "alice".to_string(),
+                vec!["telegram:1".to_string(), "imessage:+1555".to_string()],
+            )]),
+            ..IdentitiesConfig::default()
+        };
+        let sessions = SessionManager::new();
+        {
+            let mut tg = sessions.get_or_create_mut("telegram", "1");
+            tg.history.push(message(Role::User, "is the meeting at 3?"));
+            tg.history.push(message(Role::Tool, "{}"));
+            tg.history
+                .push(message(Role::Assistant, "Yes, 3pm in room B."));
+        }
+
+        let context = shared_context(&cfg, &sessions, "imessage", "+1555").unwrap();
+        assert!(context.contains("[via telegram] alice: is the meeting at 3?"));
+        assert!(context.contains("[via telegram] you: Yes, 3pm in room B."));
+        assert!(!context.contains("{}"));
+
+        // Nothing to share from the quiet side, and nothing for unlinked senders.
+        assert!(shared_context(&cfg, &sessions, "telegram", "1").is_none());
+        assert!(shared_context(&cfg, &sessions, "discord", "9").is_none());
+
+        sessions.get_or_create_mut("telegram", "1").last_active =
+            chrono::Utc::now() - chrono::Duration::hours(2);
+        assert!(shared_context(&cfg, &sessions, "imessage", "+1555").is_none());
+    }
+}
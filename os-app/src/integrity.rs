@@ -0,0 +1,318 @@
+//! Scheduled config and data integrity checks.
+//!
+//! Periodically verifies SQLite databases under the data dir (`PRAGMA integrity_check`),
+//! checks that paths referenced by config still exist (the skills dir, attachment store
+//! and code_run's sandbox root among them), and re-validates the config file.
+//! The latest report is surfaced via health and `/status`; new problems are sent to the
+//! configured notify target.
+
+use crate::config::{expand_home, OpenShellConfig};
+use crate::outbox::Outbox;
+use crate::webhooks::{self, Webhooks};
+use chrono::{DateTime, Utc};
+use os_channels::OutboundMessage;
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IntegrityProblem {
+    pub check: String,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IntegrityReport {
+    pub checked_at: DateTime<Utc>,
+    pub problems: Vec<IntegrityProblem>,
+}
+
+impl IntegrityReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    pub fn summary(&self) -> String {
+        if self.is_ok() {
+            return "ok".to_string();
+        }
+        format!("{} problem(s)", self.problems.len())
+    }
+}
+
+pub struct IntegrityMonitor {
+    cfg: OpenShellConfig,
+    config_path: Option<PathBuf>,
+    data_dir: PathBuf,
+    latest: RwLock<Option<IntegrityReport>>,
+    webhooks: Option<Arc<Webhooks>>,
+}
+
+impl IntegrityMonitor {
+    pub fn new(cfg: OpenShellConfig, config_path: Option<PathBuf>, data_dir: PathBuf) -> Self {
+        Self {
+            cfg,
+            config_path,
+            data_dir,
+            latest: RwLock::new(None),
+            webhooks: None,
+        }
+    }
+
+    pub fn with_webhooks(mut self, webhooks: Arc<Webhooks>) -> Self {
+        self.webhooks = Some(webhooks);
+        self
+    }
+
+    pub fn latest(&self) -> Option<IntegrityReport> {
+        self.latest.read().ok().and_then(|r| r.clone())
+    }
+
+    pub fn start(self: Arc<Self>, outbox: Arc<Outbox>) {
+        if !self.cfg.integrity.enabled {
+            return;
+        }
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(Duration::from_secs(self.cfg.integrity.interval_seconds));
+            let mut last_problems: Vec<IntegrityProblem> = Vec::new();
+            loop {
+                interval.tick().await;
+                let report = self.run_once().await;
+                if !report.is_ok() && report.problems != last_problems {
+                    if let Some(hooks) = self.webhooks.as_ref() {
+                        hooks.emit(
+                            webhooks::INTEGRITY_PROBLEM,
+                            serde_json::to_value(&report).unwrap_or_default(),
+                        );
+                    }
+                    self.notify(&outbox, &report).await;
+                }
+                last_problems = report.problems;
+            }
+        });
+    }
+
+    #[tracing::instrument(level = "info", skip_all)]
+    pub async fn run_once(&self) -> IntegrityReport {
+        let mut problems = Vec::new();
+
+        let data_dir = self.data_dir.clone();
+        match tokio::task::spawn_blocking(move || check_sqlite_databases(&data_dir)).await {
+            Ok(v) => problems.extend(v),
+            Err(e) => problems.push(problem("sqlite", format!("check task failed: {e}"))),
+        }
+
+        problems.extend(check_referenced_paths(&self.cfg, &self.data_dir));
+
+        if let Err(e) = OpenShellConfig::load(self.config_path.clone()).await {
+            problems.push(problem("config", e.to_string()));
+        }
+
+        for p in &problems {
+            tracing::warn!(check = %p.check, detail = %p.detail, "integrity problem");
+        }
+
+        let report = IntegrityReport {
+            checked_at: Utc::now(),
+            problems,
+        };
+        if let Ok(mut latest) = self.latest.write() {
+            *latest = Some(report.clone());
+        }
+        report
+    }
+
+    async fn notify(&self, outbox: &Outbox, report: &IntegrityReport) {
+        let (Some(channel_id), Some(recipient)) = (
+            self.cfg.integrity.notify_channel.as_deref(),
+            self.cfg.integrity.notify_recipient.as_deref(),
+        ) else {
+            return;
+        };
+
+        let mut content = format!("OpenCraw integrity check: {}\n", report.summary());
+        for p in &report.problems {
+            content.push_str(&format!("- [{}] {}\n", p.check, p.detail));
+        }
+        let msg = OutboundMessage {
+            content,
+            reply_to_message_id: None,
+            attachments: vec![],
+        };
+        if let Err(e) = outbox.send(channel_id, recipient, msg).await {
+            tracing::warn!(%e, "integrity notify failed");
+        }
+    }
+}
+
+fn problem(check: &str, detail: String) -> IntegrityProblem {
+    IntegrityProblem {
+        check: check.to_string(),
+        detail,
+    }
+}
+
+fn check_sqlite_databases(data_dir: &Path) -> Vec<IntegrityProblem> {
+    let mut problems = Vec::new();
+    for path in find_sqlite_files(data_dir) {
+        if let Err(e) = integrity_check(&path) {
+            problems.push(problem("sqlite", format!("{}: {e}", path.display())));
+        }
+    }
+    problems
+}
+
+fn find_sqlite_files(root: &Path) -> Vec<PathBuf> {
+    let mut stack = vec![root.to_path_buf()];
+    let mut out = Vec::new();
+    let mut steps = 0usize;
+    let steps_max = 10_000usize;
+
+    while let Some(dir) = stack.pop() {
+        steps += 1;
+        if steps >= steps_max {
+            break;
+        }
+        let Ok(rd) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in rd.flatten() {
+            let p = entry.path();
+            if p.is_dir() {
+                stack.push(p);
+                continue;
+            }
+            let ext = p.extension().and_then(|e| e.to_str()).unwrap_or("");
+            if matches!(ext, "db" | "sqlite" | "sqlite3") {
+                out.push(p);
+            }
+        }
+    }
+    out.sort();
+    out
+}
+
+fn integrity_check(path: &Path) -> anyhow::Result<()> {
+    let conn = Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    conn.busy_timeout(Duration::from_millis(1000))?;
+    let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+    let rows: Vec<String> = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<std::result::Result<_, _>>()?;
+    if rows.len() == 1 && rows[0] == "ok" {
+        return Ok(());
+    }
+    Err(anyhow::anyhow!(rows.join("; ")))
+}
+
+fn check_referenced_paths(cfg: &OpenShellConfig, data_dir: &Path) -> Vec<IntegrityProblem> {
+    let mut problems = Vec::new();
+
+    if !data_dir.is_dir() {
+        problems.push(problem(
+            "paths",
+            format!("data dir missing: {}", data_dir.display()),
+        ));
+    }
+
+    if cfg.tools.filesystem.enabled {
+        match std::env::current_dir() {
+            Ok(root) if root.is_dir() => {}
+            Ok(root) => problems.push(problem(
+                "paths",
+                format!("filesystem root missing: {}", root.display()),
+            )),
+            Err(e) => problems.push(problem("paths", format!("filesystem root: {e}"))),
+        }
+        for raw in &cfg.tools.filesystem.allowed_roots {
+            match expand_home(raw) {
+                Ok(p) if p.is_dir() => {}
+                Ok(p) => problems.push(problem(
+                    "paths",
+                    format!("filesystem allowed root missing: {}", p.display()),
+                )),
+                Err(e) => problems.push(problem("paths", format!("filesystem allowed root: {e}"))),
+            }
+        }
+    }
+
+    // Created at startup; gone since means installed skills or stored files went with them.
+    let skills = data_dir.join("skills");
+    if !skills.is_dir() {
+        problems.push(problem(
+            "paths",
+            format!("skills dir missing: {}", skills.display()),
+        ));
+    }
+    if cfg.attachments.enabled {
+        let blobs = data_dir.join("attachments").join("blobs");
+        if !blobs.is_dir() {
+            problems.push(problem(
+                "paths",
+                format!("attachments store missing: {}", blobs.display()),
+            ));
+        }
+    }
+
+    // Each code_run snippet gets a fresh directory under the system temp dir.
+    if cfg.tools.code_run.enabled {
+        let probe = std::env::temp_dir().join(format!("opencraw-code-probe-{}", Uuid::new_v4()));
+        match std::fs::create_dir(&probe) {
+            Ok(()) => {
+                let _ = std::fs::remove_dir(&probe);
+            }
+            Err(e) => problems.push(problem(
+                "paths",
+                format!(
+                    "code_run sandbox root {} unusable: {e}",
+                    std::env::temp_dir().display()
+                ),
+            )),
+        }
+    }
+
+    if cfg.channels.imessage.enabled {
+        if let Some(raw) = cfg.channels.imessage.source_db.as_deref() {
+            match expand_home(raw) {
+                Ok(p) if p.exists() => {}
+                Ok(p) => problems.push(problem(
+                    "paths",
+                    format!("imessage source_db missing: {}", p.display()),
+                )),
+                Err(e) => problems.push(problem("paths", format!("imessage source_db: {e}"))),
+            }
+        }
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integrity_check_accepts_healthy_db() {
+        let tmp = std::env::temp_dir().join(format!("opencraw-integrity-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&tmp).unwrap();
+        let db = tmp.join("ok.db");
+        let conn = Connection::open(&db).unwrap();
+        conn.execute_batch("CREATE TABLE t (x INTEGER); INSERT INTO t VALUES (1);")
+            .unwrap();
+        drop(conn);
+
+        assert_eq!(find_sqlite_files(&tmp), vec![db.clone()]);
+        assert!(check_sqlite_databases(&tmp).is_empty());
+
+        std::fs::write(&db, b"definitely not sqlite").unwrap();
+        assert_eq!(check_sqlite_databases(&tmp).len(), 1);
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+}
@@ -0,0 +1,215 @@
+//! Durable inbound journal.
+//!
+//! Each message is appended to `inbound.jsonl` as the gateway takes it off the queue and
+//! acknowledged once handling has finished, successfully or with an error that was
+//! already logged. Messages still unacknowledged at startup, because the process died
+//! mid-run, are queued again, so delivery is at least once: a message can be handled
+//! twice, never silently lost. The file is truncated whenever nothing is outstanding.
+//! With `[encryption]` on, each line is sealed like the audit log's.
+
+use crate::encryption::{open_value, DataCipher};
+use anyhow::{Context, Result};
+use os_channels::{InboundMessage, InboundMessageKind};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Record {
+    Received { message: InboundMessage },
+    Ack { key: String },
+}
+
+pub struct InboundJournal {
+    path: PathBuf,
+    cipher: Option<Arc<DataCipher>>,
+    /// Unacknowledged messages by key; the lock also serializes writes to the file.
+    outstanding: Mutex<HashMap<String, InboundMessage>>,
+}
+
+impl InboundJournal {
+    /// Opens the journal, returning it with the messages left unacknowledged by the last
+    /// run, oldest first.
+    pub fn open(
+        data_dir: &Path,
+        cipher: Option<Arc<DataCipher>>,
+    ) -> Result<(Self, Vec<InboundMessage>)> {
+        let path = data_dir.join("inbound.jsonl");
+        let mut order: Vec<String> = Vec::new();
+        let mut outstanding = HashMap::new();
+        if path.exists() {
+            let raw = std::fs::read_to_string(&path)
+                .with_context(|| format!("read {}", path.display()))?;
+            for line in raw.lines().filter(|l| !l.trim().is_empty()) {
+                let record = open_value(cipher.as_deref(), line.to_string())
+                    .and_then(|line| Ok(serde_json::from_str::<Record>(&line)?));
+                match record {
+                    Ok(Record::Received { message }) => {
+                        let key = key(&message);
+                        if outstanding.insert(key.clone(), message).is_none() {
+                            order.push(key);
+                        }
+                    }
+                    Ok(Record::Ack { key }) => {
+                        outstanding.remove(&key);
+                    }
+                    // A torn last line from a crash mid-write, or an unreadable one.
+                    Err(e) => tracing::warn!(%e, "skipping unreadable inbound journal line"),
+                }
+            }
+        }
+        let replay: Vec<InboundMessage> = order
+            .iter()
+            .filter_map(|k| outstanding.get(k).cloned())
+            .collect();
+
+        let journal = Self {
+            path,
+            cipher,
+            outstanding: Mutex::new(outstanding),
+        };
+        // Compact to just the outstanding messages.
+        let mut lines = String::new();
+        for message in &replay {
+            lines.push_str(&journal.line(&Record::Received {
+                message: message.clone(),
+            })?);
+        }
+        std::fs::write(&journal.path, lines)
+            .with_context(|| format!("write {}", journal.path.display()))?;
+        if !replay.is_empty() {
+            tracing::info!(
+                count = replay.len(),
+                "replaying unacknowledged inbound messages"
+            );
+        }
+        Ok((journal, replay))
+    }
+
+    /// Journal a message before it is handled. Replayed messages are already journaled.
+    pub fn record(&self, message: &InboundMessage) {
+        let Ok(mut outstanding) = self.outstanding.lock() else {
+            return;
+        };
+        let key = key(message);
+        if outstanding.contains_key(&key) {
+            return;
+        }
+        let record = Record::Received {
+            message: message.clone(),
+        };
+        if let Err(e) = self.append(&record) {
+            tracing::warn!(%e, path = %self.path.display(), "failed to journal inbound message");
+            return;
+        }
+        outstanding.insert(key, message.clone());
+    }
+
+    /// Handling of `message` has finished.
+    pub fn ack(&self, message: &InboundMessage) {
+        let Ok(mut outstanding) = self.outstanding.lock() else {
+            return;
+        };
+        let key = key(message);
+        if outstanding.remove(&key).is_none() {
+            return;
+        }
+        let res = if outstanding.is_empty() {
+            std::fs::write(&self.path, "").map_err(anyhow::Error::from)
+        } else {
+            self.append(&Record::Ack { key })
+        };
+        if let Err(e) = res {
+            tracing::warn!(%e, path = %self.path.display(), "failed to acknowledge inbound message");
+        }
+    }
+
+    fn line(&self, record: &Record) -> Result<String> {
+        let line = serde_json::to_string(record)?;
+        Ok(match self.cipher.as_deref() {
+            Some(cipher) => cipher.seal(&line)?,
+            None => line,
+        } + "\n")
+    }
+
+    fn append(&self, record: &Record) -> Result<()> {
+        let line = self.line(record)?;
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
+/// Adapters reuse a message's id for its edits, so the kind is part of the key.
+fn key(message: &InboundMessage) -> String {
+    let kind = match message.kind {
+        InboundMessageKind::Message => "message",
+        InboundMessageKind::Edit => "edit",
+        InboundMessageKind::Reaction => "reaction",
+    };
+    format!("{kind}:{}:{}", message.channel_id, message.message_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn inbound(message_id: &str) -> InboundMessage {
+        InboundMessage {
+            kind: InboundMessageKind::Message,
+            message_id: message_id.to_string(),
+            channel_id: "telegram".to_string(),
+            sender_id: "u1".to_string(),
+            thread_id: None,
+            is_group: false,
+            content: format!("hello {message_id}"),
+            attachments: vec![],
+            metadata: serde_json::Value::Null,
+            received_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn unacknowledged_messages_are_replayed_in_order() {
+        let tmp = std::env::temp_dir().join(format!("opencraw-journal-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&tmp).unwrap();
+        let cipher = Some(Arc::new(
+            DataCipher::from_key("AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=").unwrap(),
+        ));
+
+        let (journal, replay) = InboundJournal::open(&tmp, cipher.clone()).unwrap();
+        assert!(replay.is_empty());
+        for id in ["1", "2", "3"] {
+            journal.record(&inbound(id));
+        }
+        journal.ack(&inbound("2"));
+        drop(journal);
+
+        let raw = std::fs::read_to_string(tmp.join("inbound.jsonl")).unwrap();
+        assert!(!raw.contains("hello"));
+        let (journal, replay) = InboundJournal::open(&tmp, cipher.clone()).unwrap();
+        let ids: Vec<&str> = replay.iter().map(|m| m.message_id.as_str()).collect();
+        assert_eq!(ids, ["1", "3"]);
+
+        // Replayed messages come through the gateway again without a second entry.
+        journal.record(&inbound("1"));
+        journal.ack(&inbound("1"));
+        journal.ack(&inbound("3"));
+        assert_eq!(
+            std::fs::read_to_string(tmp.join("inbound.jsonl")).unwrap(),
+            ""
+        );
+        let (_, replay) = InboundJournal::open(&tmp, cipher).unwrap();
+        assert!(replay.is_empty());
+
+        std::fs::remove_dir_all(&tmp).unwrap();
+    }
+}
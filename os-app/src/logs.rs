@@ -0,0 +1,340 @@
+//! The server's log file, and `opencraw logs` / `opencraw tail` to read it.
+//!
+//! `opencraw serve` writes every event at info and above to `data/logs/opencraw.jsonl` as
+//! one JSON object per line, besides the usual `RUST_LOG` output on stderr. Past
+//! `MAX_BYTES` the file moves to `opencraw.jsonl.1`, so at most two are kept. Events carry
+//! the fields of the spans they happened in: inbound messages record their `channel` and
+//! `session`, tool calls their `tool`, and the readers filter on those.
+
+use anyhow::{Context, Result};
+use clap::Args;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{Level, Subscriber};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{EnvFilter, Layer};
+
+const FILE_NAME: &str = "opencraw.jsonl";
+const MAX_BYTES: u64 = 20 * 1024 * 1024;
+/// What the file records, whatever `RUST_LOG` says.
+const FILE_FILTER: &str = "info";
+const TAIL_BACKLOG: usize = 10;
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+pub fn log_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("logs").join(FILE_NAME)
+}
+
+fn rotated_path(path: &Path) -> PathBuf {
+    path.with_extension("jsonl.1")
+}
+
+/// The JSON file layer for `opencraw serve`.
+pub fn file_layer<S>(data_dir: &Path) -> io::Result<impl Layer<S>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let file = LogFile::open(log_path(data_dir), MAX_BYTES)?;
+    Ok(tracing_subscriber::fmt::layer()
+        .json()
+        .with_current_span(false)
+        .with_span_list(true)
+        .with_writer(file)
+        .with_filter(EnvFilter::new(FILE_FILTER)))
+}
+
+/// Append-only log file that rotates once it passes `max_bytes`.
+pub struct LogFile {
+    path: PathBuf,
+    max_bytes: u64,
+    /// The open file and its length.
+    state: Mutex<(File, u64)>,
+}
+
+impl LogFile {
+    fn open(path: PathBuf, max_bytes: u64) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            path,
+            max_bytes,
+            state: Mutex::new((file, len)),
+        })
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogFile {
+    type Writer = LogWriter<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        LogWriter(self)
+    }
+}
+
+pub struct LogWriter<'a>(&'a LogFile);
+
+impl Write for LogWriter<'_> {
+    /// The formatter writes each event in one call, so rotating here keeps lines whole.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let log = self.0;
+        let mut state = log.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.1 > 0 && state.1 + buf.len() as u64 > log.max_bytes {
+            std::fs::rename(&log.path, rotated_path(&log.path))?;
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&log.path)?;
+            *state = (file, 0);
+        }
+        state.0.write_all(buf)?;
+        state.1 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut state = self.0.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.0.flush()
+    }
+}
+
+#[derive(Debug, Clone, Default, Args)]
+pub struct LogFilter {
+    /// Only events for this channel, e.g. `telegram`.
+    #[arg(long)]
+    pub channel: Option<String>,
+    /// Only events for this session id.
+    #[arg(long)]
+    pub session: Option<String>,
+    /// Only events during calls to this tool, e.g. `shell`.
+    #[arg(long)]
+    pub tool: Option<String>,
+    /// Only events at this level or above: error, warn, info, debug or trace.
+    #[arg(long)]
+    pub level: Option<Level>,
+}
+
+impl LogFilter {
+    pub fn matches(&self, event: &Value) -> bool {
+        if let Some(min) = self.level {
+            // Levels order by verbosity: ERROR is the smallest.
+            match event["level"]
+                .as_str()
+                .and_then(|l| l.parse::<Level>().ok())
+            {
+                Some(level) if level <= min => {}
+                _ => return false,
+            }
+        }
+        [
+            ("channel", &self.channel),
+            ("session", &self.session),
+            ("tool", &self.tool),
+        ]
+        .into_iter()
+        .all(|(key, want)| {
+            want.as_deref()
+                .is_none_or(|want| field(event, key) == Some(want))
+        })
+    }
+}
+
+/// `key` from the event's own fields, else from the innermost span that has it.
+fn field<'a>(event: &'a Value, key: &str) -> Option<&'a str> {
+    std::iter::once(&event["fields"])
+        .chain(event["spans"].as_array().into_iter().flatten().rev())
+        .find_map(|fields| fields[key].as_str())
+}
+
+/// One line for the terminal: time, level, context, message, then the other fields.
+pub fn format(event: &Value) -> String {
+    let mut out = format!(
+        "{} {:<5}",
+        event["timestamp"].as_str().unwrap_or("-"),
+        event["level"].as_str().unwrap_or("-")
+    );
+    for key in ["channel", "session", "tool"] {
+        if let Some(value) = field(event, key) {
+            out.push_str(&format!(" {key}={value}"));
+        }
+    }
+    let Some(fields) = event["fields"].as_object() else {
+        return out;
+    };
+    if let Some(message) = fields.get("message").and_then(Value::as_str) {
+        out.push_str(&format!(" {message}"));
+    }
+    for (key, value) in fields {
+        match (key.as_str(), value) {
+            ("message" | "channel" | "session" | "tool", _) => {}
+            (_, Value::String(s)) => out.push_str(&format!(" {key}={s}")),
+            _ => out.push_str(&format!(" {key}={value}")),
+        }
+    }
+    out
+}
+
+fn show(event: &Value, json: bool) {
+    if json {
+        println!("{event}");
+    } else {
+        println!("{}", format(event));
+    }
+}
+
+/// The last `limit` matching events across the rotated and current files, oldest first.
+fn recent(path: &Path, filter: &LogFilter, limit: usize) -> Result<Vec<Value>> {
+    let mut events = VecDeque::with_capacity(limit);
+    if limit == 0 {
+        return Ok(Vec::new());
+    }
+    for path in [rotated_path(path), path.to_path_buf()] {
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("open {}", path.display())),
+        };
+        for line in BufReader::new(file).lines() {
+            let Ok(event) = serde_json::from_str::<Value>(&line?) else {
+                continue;
+            };
+            if filter.matches(&event) {
+                if events.len() == limit {
+                    events.pop_front();
+                }
+                events.push_back(event);
+            }
+        }
+    }
+    Ok(events.into())
+}
+
+/// `opencraw logs`: print the last `lines` matching events.
+pub fn print(path: &Path, filter: &LogFilter, lines: usize, json: bool) -> Result<()> {
+    if !path.exists() && !rotated_path(path).exists() {
+        anyhow::bail!(
+            "no log file at {}; it is written by `opencraw serve` run from this directory",
+            path.display()
+        );
+    }
+    for event in recent(path, filter, lines)? {
+        show(&event, json);
+    }
+    Ok(())
+}
+
+/// `opencraw tail`: print the last few matching events, then new ones as they're written.
+pub async fn follow(path: &Path, filter: &LogFilter, json: bool) -> Result<()> {
+    for event in recent(path, filter, TAIL_BACKLOG)? {
+        show(&event, json);
+    }
+    let mut offset = std::fs::metadata(path).map_or(0, |m| m.len());
+    let mut partial = Vec::new();
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        let len = match std::fs::metadata(path) {
+            Ok(m) => m.len(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("stat {}", path.display())),
+        };
+        if len < offset {
+            // Rotated: start the new file from the top.
+            offset = 0;
+            partial.clear();
+        }
+        if len == offset {
+            continue;
+        }
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        offset += file.read_to_end(&mut partial)? as u64;
+        while let Some(end) = partial.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = partial.drain(..=end).collect();
+            let Ok(event) = serde_json::from_slice::<Value>(&line) else {
+                continue;
+            };
+            if filter.matches(&event) {
+                show(&event, json);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tool_warning() -> Value {
+        json!({
+            "timestamp": "2026-10-16T09:30:00.000000Z",
+            "level": "WARN",
+            "fields": { "message": "tool failed", "error": "timed out", "attempt": 2 },
+            "target": "opencraw::assistant",
+            "spans": [
+                { "name": "handle_inbound", "channel": "telegram", "session": "7f3c" },
+                { "name": "tool", "tool": "browser" }
+            ]
+        })
+    }
+
+    #[test]
+    fn filters_match_span_fields_and_levels() {
+        let event = tool_warning();
+        assert!(LogFilter::default().matches(&event));
+        let by = |f: LogFilter| f.matches(&event);
+        assert!(by(LogFilter {
+            channel: Some("telegram".to_string()),
+            tool: Some("browser".to_string()),
+            ..LogFilter::default()
+        }));
+        assert!(!by(LogFilter {
+            channel: Some("discord".to_string()),
+            ..LogFilter::default()
+        }));
+        assert!(!by(LogFilter {
+            session: Some("other".to_string()),
+            ..LogFilter::default()
+        }));
+        assert!(by(LogFilter {
+            level: Some(Level::INFO),
+            ..LogFilter::default()
+        }));
+        assert!(!by(LogFilter {
+            level: Some(Level::ERROR),
+            ..LogFilter::default()
+        }));
+
+        assert_eq!(
+            format(&event),
+            "2026-10-16T09:30:00.000000Z WARN  channel=telegram session=7f3c tool=browser \
+             tool failed attempt=2 error=timed out"
+        );
+    }
+
+    #[test]
+    fn the_file_rotates_and_readers_see_both_parts() {
+        let dir = std::env::temp_dir().join(format!("opencraw-logs-{}", uuid::Uuid::new_v4()));
+        let path = log_path(&dir);
+        let line = format!("{}\n", tool_warning());
+        let file = LogFile::open(path.clone(), 4 * line.len() as u64).unwrap();
+        for _ in 0..6 {
+            file.make_writer().write_all(line.as_bytes()).unwrap();
+        }
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+        let rotated = std::fs::read_to_string(rotated_path(&path)).unwrap();
+        assert_eq!(rotated.lines().count(), 4);
+
+        let events = recent(&path, &LogFilter::default(), 5).unwrap();
+        assert_eq!(events.len(), 5);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -2,19 +2,70 @@
 //!
 //! See: specifications/openshell/implementation_v0_1_0.md
 
+mod admin;
+mod approval_batches;
+mod approval_rules;
+mod archive;
 mod assistant;
+mod attachments;
+mod audit;
+mod automation;
+mod briefing;
+mod capabilities;
+mod cluster;
 mod commands;
 mod config;
+mod context_window;
+mod continuations;
+mod debug_bundle;
 mod dev_backends;
+mod doctor;
+mod encryption;
+mod facts;
+mod feedback;
+mod focus;
 mod gateway;
+mod grants;
+mod handoff;
+mod health;
+mod identities;
+mod injection;
+mod integrity;
+mod journal;
+mod logs;
+mod metrics;
+mod outbox;
 mod pairing;
+mod pii;
+mod progress;
+mod redaction;
 mod routes;
+mod search;
+mod secrets;
 mod server;
 mod session;
 mod setup;
+mod shares;
+mod skill_wasm;
+mod skills;
+mod sqlite;
+mod suggestions;
+mod supervisor;
+mod tasks;
+mod template;
+mod tool_limits;
+mod tool_results;
+mod tool_selection;
+mod trace;
+mod translate;
+mod watchdog;
+mod webhooks;
 
 use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use tracing_subscriber::layer::SubscriberExt as _;
+use tracing_subscriber::util::SubscriberInitExt as _;
+use tracing_subscriber::Layer as _;
 
 #[derive(Debug, Parser)]
 #[command(name = "opencraw", version, about = "OpenCraw personal AI assistant")]
@@ -31,12 +82,39 @@ enum Command {
         #[arg(long)]
         config: Option<PathBuf>,
     },
-    /// Validate config and perform basic health checks.
+    /// Validate config and check each enabled integration's credentials live.
     Doctor {
         /// Path to config file. Defaults to ~/.opencraw/config.toml
         #[arg(long)]
         config: Option<PathBuf>,
     },
+    /// Show a live snapshot from the running server: readiness, channel activity, queues,
+    /// LLM errors, pending approvals and disk use.
+    Status {
+        /// Path to config file. Defaults to ~/.opencraw/config.toml
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Server base URL. Defaults to http://127.0.0.1:<channels.webchat.port>
+        #[arg(long)]
+        url: Option<String>,
+    },
+    /// Create a one-time invite that pairs whoever sends its code on `channel`, via the
+    /// running server.
+    Invite {
+        channel: String,
+        /// How long the invite works, e.g. `48h` or `3d`. Defaults to `pairing.expire_hours`.
+        #[arg(long)]
+        expires: Option<String>,
+        /// Who it's for, e.g. `Mom`.
+        #[arg(long)]
+        note: Option<String>,
+        /// Path to config file. Defaults to ~/.opencraw/config.toml
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Server base URL. Defaults to http://127.0.0.1:<channels.webchat.port>
+        #[arg(long)]
+        url: Option<String>,
+    },
     /// One-shot send to a recipient via a configured channel.
     Send {
         channel: String,
@@ -46,24 +124,166 @@ enum Command {
         #[arg(long)]
         config: Option<PathBuf>,
     },
+    /// Run one assistant turn and print the answer, e.g. from a script or cron job.
+    Ask {
+        /// The prompt; `-` reads it from stdin.
+        prompt: String,
+        /// Persona to answer as (its model, prompt and tool list).
+        #[arg(long)]
+        persona: Option<String>,
+        /// Comma-separated tools to offer, e.g. `browser,filesystem`. Defaults to every
+        /// enabled tool.
+        #[arg(long, value_delimiter = ',')]
+        tools: Option<Vec<String>>,
+        /// Print the answer and run metadata (usage, tool calls, timing) as JSON.
+        #[arg(long)]
+        json: bool,
+        /// Path to config file. Defaults to ~/.opencraw/config.toml
+        #[arg(long)]
+        config: Option<PathBuf>,
+    },
+    /// Encrypt existing conversation history and audit log with `encryption.key`.
+    /// Stop the server first.
+    EncryptData {
+        /// Path to config file. Defaults to ~/.opencraw/config.toml
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Turn encrypted data back into plaintext instead.
+        #[arg(long)]
+        decrypt: bool,
+    },
+    /// Install, list, update or remove skill packages.
+    Skills {
+        #[command(subcommand)]
+        command: skills::SkillsCommand,
+        /// Path to config file. Defaults to ~/.opencraw/config.toml
+        #[arg(long, global = true)]
+        config: Option<PathBuf>,
+    },
+    /// Print recent events from the server's log file (data/logs/opencraw.jsonl).
+    Logs {
+        #[command(flatten)]
+        filter: logs::LogFilter,
+        /// How many matching events to print.
+        #[arg(long, short = 'n', default_value_t = 100)]
+        lines: usize,
+        /// Print the raw JSON lines.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Follow the server's log file, printing matching events as they're written.
+    Tail {
+        #[command(flatten)]
+        filter: logs::LogFilter,
+        /// Print the raw JSON lines.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Write a sanitized debug bundle (.json.gz) to attach to bug reports.
+    DebugBundle {
+        /// Path to config file. Defaults to ~/.opencraw/config.toml
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Output file. Defaults to ./opencraw-debug-<timestamp>.json.gz
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Log file to include (last 500 lines, secrets scrubbed).
+        #[arg(long)]
+        log_file: Option<PathBuf>,
+        /// Number of recent conversation turns to summarize (metadata only).
+        #[arg(long, default_value_t = 20)]
+        runs: usize,
+    },
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .init();
-
     let cli = Cli::parse();
 
+    let data_dir = Path::new("data");
+    let file_log = match cli.command {
+        None | Some(Command::Serve { .. }) => match logs::file_layer(data_dir) {
+            Ok(layer) => Some(layer),
+            Err(e) => {
+                eprintln!("not writing {}: {e}", logs::log_path(data_dir).display());
+                None
+            }
+        },
+        _ => None,
+    };
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_filter(tracing_subscriber::EnvFilter::from_default_env()),
+        )
+        .with(file_log)
+        .init();
+
     match cli.command.unwrap_or(Command::Serve { config: None }) {
         Command::Serve { config } => server::serve(config).await,
         Command::Doctor { config } => server::doctor(config).await,
+        Command::Status { config, url } => server::status(config, url).await,
+        Command::Invite {
+            channel,
+            expires,
+            note,
+            config,
+            url,
+        } => server::invite(config, url, channel, expires, note).await,
         Command::Send {
             channel,
             recipient,
             message,
             config,
         } => server::send_one_shot(config, &channel, &recipient, &message).await,
+        Command::Ask {
+            prompt,
+            persona,
+            tools,
+            json,
+            config,
+        } => {
+            let prompt = if prompt == "-" {
+                std::io::read_to_string(std::io::stdin())?
+            } else {
+                prompt
+            };
+            server::ask(
+                config,
+                server::AskOptions {
+                    prompt,
+                    persona,
+                    tools,
+                    json,
+                },
+            )
+            .await
+        }
+        Command::EncryptData { config, decrypt } => server::encrypt_data(config, decrypt).await,
+        Command::Skills { command, config } => server::skills(config, command).await,
+        Command::Logs {
+            filter,
+            lines,
+            json,
+        } => logs::print(&logs::log_path(data_dir), &filter, lines, json),
+        Command::Tail { filter, json } => {
+            logs::follow(&logs::log_path(data_dir), &filter, json).await
+        }
+        Command::DebugBundle {
+            config,
+            output,
+            log_file,
+            runs,
+        } => {
+            let path = debug_bundle::write_bundle(debug_bundle::BundleOptions {
+                config_path: config,
+                output,
+                log_file,
+                runs,
+            })
+            .await?;
+            println!("wrote {}", path.display());
+            Ok(())
+        }
     }
 }
@@ -0,0 +1,390 @@
+//! Live counters behind `GET /api/v1/os/metrics` and `opencraw status`.
+//!
+//! The gateway, outbox and assistant report into one `Metrics`; a snapshot adds the
+//! figures that are cheaper to read on demand (queue depth, pending approvals, disk use,
+//! SQLite connection use).
+
+use crate::sqlite::{SqlitePool, SqliteStats};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use os_channels::InboundMessage;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+/// Window for the LLM error rate.
+const LLM_WINDOW: chrono::Duration = chrono::Duration::hours(1);
+/// Failures in a row after which the LLM counts as unreachable for `LLM_COOLDOWN`.
+const LLM_COOLDOWN_FAILURES: u32 = 3;
+const LLM_COOLDOWN: chrono::Duration = chrono::Duration::minutes(1);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChannelActivity {
+    pub last_inbound: Option<DateTime<Utc>>,
+    pub last_outbound: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueDepth {
+    /// Received from channels, not yet picked up by the gateway.
+    pub inbound: usize,
+    /// Handed to the outbox, not yet delivered.
+    pub outbound: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LlmStats {
+    pub calls: usize,
+    pub errors: usize,
+    pub error_rate: f64,
+}
+
+/// The LLM as the last calls saw it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LlmHealth {
+    /// False while cooling down after repeated failures.
+    pub reachable: bool,
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<DateTime<Utc>>,
+    pub cooldown_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Default)]
+struct LlmFailures {
+    consecutive: u32,
+    last: Option<(DateTime<Utc>, String)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    pub started_at: DateTime<Utc>,
+    pub uptime_seconds: i64,
+    pub channels: BTreeMap<String, ChannelActivity>,
+    pub queues: QueueDepth,
+    /// LLM calls over the last hour.
+    pub llm: LlmStats,
+    /// Suggested replies waiting for the owner.
+    pub pending_approvals: usize,
+    pub data_dir: PathBuf,
+    pub disk_bytes: u64,
+    /// By database; empty from servers that predate it.
+    #[serde(default)]
+    pub sqlite: BTreeMap<String, SqliteStats>,
+}
+
+pub struct Metrics {
+    started_at: DateTime<Utc>,
+    channels: DashMap<String, ChannelActivity>,
+    inbound: Option<mpsc::WeakSender<InboundMessage>>,
+    outbound_queued: AtomicUsize,
+    llm_calls: Mutex<VecDeque<(DateTime<Utc>, bool)>>,
+    llm_failures: Mutex<LlmFailures>,
+    sqlite: Vec<(String, Arc<SqlitePool>)>,
+}
+
+impl Metrics {
+    pub fn new(channel_ids: Vec<String>) -> Self {
+        Self {
+            started_at: Utc::now(),
+            channels: channel_ids
+                .into_iter()
+                .map(|id| (id, ChannelActivity::default()))
+                .collect(),
+            inbound: None,
+            outbound_queued: AtomicUsize::new(0),
+            llm_calls: Mutex::new(VecDeque::new()),
+            llm_failures: Mutex::new(LlmFailures::default()),
+            sqlite: Vec::new(),
+        }
+    }
+
+    /// Report the gateway's inbound queue depth. Holds a weak handle only, so the queue
+    /// still closes when the channels go away.
+    pub fn with_inbound_queue(mut self, tx: &mpsc::Sender<InboundMessage>) -> Self {
+        self.inbound = Some(tx.downgrade());
+        self
+    }
+
+    /// Report how much the database's connections are waited on.
+    pub fn with_sqlite(mut self, name: &str, pool: Arc<SqlitePool>) -> Self {
+        self.sqlite.push((name.to_string(), pool));
+        self
+    }
+
+    pub fn inbound(&self, channel_id: &str) {
+        self.channels
+            .entry(channel_id.to_string())
+            .or_default()
+            .last_inbound = Some(Utc::now());
+    }
+
+    pub fn outbound_queued(&self) {
+        self.outbound_queued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A queued outbound message was handled; `delivered` is false if the send failed.
+    pub fn outbound_done(&self, channel_id: &str, delivered: bool) {
+        let _ = self
+            .outbound_queued
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+        if delivered {
+            self.channels
+                .entry(channel_id.to_string())
+                .or_default()
+                .last_outbound = Some(Utc::now());
+        }
+    }
+
+    /// A chat call finished, with its error if it failed.
+    pub fn llm_call(&self, error: Option<String>) {
+        let now = Utc::now();
+        if let Ok(mut calls) = self.llm_calls.lock() {
+            calls.push_back((now, error.is_none()));
+            prune(&mut calls, now);
+        }
+        if let Ok(mut failures) = self.llm_failures.lock() {
+            match error {
+                Some(e) => {
+                    failures.consecutive += 1;
+                    failures.last = Some((now, e));
+                }
+                None => failures.consecutive = 0,
+            }
+        }
+    }
+
+    pub fn llm_health(&self, now: DateTime<Utc>) -> LlmHealth {
+        let failures = self.llm_failures.lock();
+        let (consecutive, last) = failures
+            .map(|f| (f.consecutive, f.last.clone()))
+            .unwrap_or_default();
+        let cooldown_until = last
+            .as_ref()
+            .filter(|_| consecutive >= LLM_COOLDOWN_FAILURES)
+            .map(|(at, _)| *at + LLM_COOLDOWN)
+            .filter(|until| *until > now);
+        LlmHealth {
+            reachable: cooldown_until.is_none(),
+            consecutive_failures: consecutive,
+            last_error_at: last.as_ref().map(|(at, _)| *at),
+            last_error: last.map(|(_, e)| e),
+            cooldown_until,
+        }
+    }
+
+    pub fn uptime_seconds(&self) -> i64 {
+        (Utc::now() - self.started_at).num_seconds()
+    }
+
+    pub fn queues(&self) -> QueueDepth {
+        let inbound = self
+            .inbound
+            .as_ref()
+            .and_then(|tx| tx.upgrade())
+            .map(|tx| tx.max_capacity() - tx.capacity())
+            .unwrap_or(0);
+        QueueDepth {
+            inbound,
+            outbound: self.outbound_queued.load(Ordering::Relaxed),
+        }
+    }
+
+    pub async fn snapshot(&self, pending_approvals: usize, data_dir: &Path) -> MetricsSnapshot {
+        let now = Utc::now();
+        let (calls, errors) = self
+            .llm_calls
+            .lock()
+            .map(|mut calls| {
+                prune(&mut calls, now);
+                (calls.len(), calls.iter().filter(|(_, ok)| !ok).count())
+            })
+            .unwrap_or_default();
+
+        MetricsSnapshot {
+            started_at: self.started_at,
+            uptime_seconds: (now - self.started_at).num_seconds(),
+            channels: self
+                .channels
+                .iter()
+                .map(|e| (e.key().clone(), e.value().clone()))
+                .collect(),
+            queues: self.queues(),
+            llm: LlmStats {
+                calls,
+                errors,
+                error_rate: if calls == 0 {
+                    0.0
+                } else {
+                    errors as f64 / calls as f64
+                },
+            },
+            pending_approvals,
+            data_dir: data_dir.to_path_buf(),
+            disk_bytes: disk_usage(data_dir.to_path_buf()).await,
+            sqlite: self
+                .sqlite
+                .iter()
+                .map(|(name, pool)| (name.clone(), pool.stats()))
+                .collect(),
+        }
+    }
+}
+
+/// Compact text form for `opencraw status`.
+pub fn render(s: &MetricsSnapshot, now: DateTime<Utc>) -> String {
+    let mut out = format!("up {}\n", span(s.uptime_seconds));
+    if !s.channels.is_empty() {
+        out.push_str("channels:\n");
+        let width = s.channels.keys().map(|k| k.len()).max().unwrap_or(0);
+        for (id, a) in &s.channels {
+            out.push_str(&format!(
+                "  {id:<width$}  in {:<10}  out {}\n",
+                ago(a.last_inbound, now),
+                ago(a.last_outbound, now)
+            ));
+        }
+    }
+    out.push_str(&format!(
+        "queues: {} inbound, {} outbound\n",
+        s.queues.inbound, s.queues.outbound
+    ));
+    out.push_str(&format!(
+        "llm (last hour): {} calls, {} errors ({:.1}%)\n",
+        s.llm.calls,
+        s.llm.errors,
+        s.llm.error_rate * 100.0
+    ));
+    out.push_str(&format!("pending approvals: {}\n", s.pending_approvals));
+    out.push_str(&format!(
+        "disk: {:.1} MB in {}\n",
+        s.disk_bytes as f64 / 1_000_000.0,
+        s.data_dir.display()
+    ));
+    for (name, db) in &s.sqlite {
+        out.push_str(&format!(
+            "sqlite {name}: {} reads, {} writes, {} waited ({} ms)\n",
+            db.reads, db.writes, db.waits, db.wait_ms
+        ));
+    }
+    out
+}
+
+fn ago(at: Option<DateTime<Utc>>, now: DateTime<Utc>) -> String {
+    match at {
+        Some(at) => format!("{} ago", span((now - at).num_seconds())),
+        None => "-".to_string(),
+    }
+}
+
+fn span(seconds: i64) -> String {
+    let seconds = seconds.max(0);
+    match seconds {
+        s if s < 60 => format!("{s}s"),
+        s if s < 3600 => format!("{}m", s / 60),
+        s if s < 86_400 => format!("{}h{}m", s / 3600, s % 3600 / 60),
+        s => format!("{}d{}h", s / 86_400, s % 86_400 / 3600),
+    }
+}
+
+fn prune(calls: &mut VecDeque<(DateTime<Utc>, bool)>, now: DateTime<Utc>) {
+    while calls.front().is_some_and(|(at, _)| *at < now - LLM_WINDOW) {
+        calls.pop_front();
+    }
+}
+
+/// Total size of the files under `dir`; unreadable entries count as zero.
+async fn disk_usage(dir: PathBuf) -> u64 {
+    fn walk(dir: &Path) -> u64 {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return 0;
+        };
+        entries
+            .flatten()
+            .map(|entry| match entry.metadata() {
+                Ok(m) if m.is_dir() => walk(&entry.path()),
+                Ok(m) => m.len(),
+                Err(_) => 0,
+            })
+            .sum()
+    }
+    tokio::task::spawn_blocking(move || walk(&dir))
+        .await
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn snapshot_reports_activity_queues_and_errors() {
+        let (tx, _rx) = mpsc::channel(8);
+        let tmp = std::env::temp_dir().join(format!("opencraw-metrics-{}", uuid::Uuid::new_v4()));
+        let db_dir = tmp.with_extension("db");
+        let db = SqlitePool::open(&db_dir.join("index.db"), "").unwrap();
+        drop(db.write().unwrap());
+        let metrics = Metrics::new(vec!["telegram".to_string(), "discord".to_string()])
+            .with_inbound_queue(&tx)
+            .with_sqlite("index", Arc::new(db));
+        metrics.inbound("telegram");
+        metrics.outbound_queued();
+        metrics.outbound_queued();
+        metrics.outbound_done("telegram", true);
+        metrics.llm_call(None);
+        metrics.llm_call(None);
+        metrics.llm_call(None);
+        metrics.llm_call(Some("timeout".to_string()));
+
+        std::fs::create_dir_all(tmp.join("attachments")).unwrap();
+        std::fs::write(tmp.join("attachments").join("a.bin"), [0u8; 1000]).unwrap();
+        std::fs::write(tmp.join("audit.jsonl"), [0u8; 24]).unwrap();
+
+        let snap = metrics.snapshot(2, &tmp).await;
+        assert!(snap.channels["telegram"].last_inbound.is_some());
+        assert!(snap.channels["telegram"].last_outbound.is_some());
+        assert!(snap.channels["discord"].last_inbound.is_none());
+        assert_eq!(snap.queues.outbound, 1);
+        assert_eq!((snap.llm.calls, snap.llm.errors), (4, 1));
+        assert_eq!(snap.llm.error_rate, 0.25);
+        assert_eq!(snap.pending_approvals, 2);
+        assert_eq!(snap.disk_bytes, 1024);
+        assert_eq!(snap.sqlite["index"].writes, 1);
+
+        let seen = snap.channels["telegram"].last_inbound.unwrap();
+        let text = render(&snap, seen + chrono::Duration::minutes(90));
+        assert!(text.starts_with("up "));
+        assert!(text.contains("discord   in -"));
+        assert!(text.contains("telegram  in 1h30m ago"));
+        assert!(text.contains("1 errors (25.0%)"));
+        assert!(text.contains("sqlite index: 0 reads, 1 writes, 0 waited (0 ms)"));
+        let _ = std::fs::remove_dir_all(&tmp);
+        let _ = std::fs::remove_dir_all(&db_dir);
+    }
+
+    #[test]
+    fn repeated_llm_failures_start_a_cooldown() {
+        let metrics = Metrics::new(vec![]);
+        let now = Utc::now();
+        metrics.llm_call(Some("status 529".to_string()));
+        metrics.llm_call(Some("status 529".to_string()));
+        let health = metrics.llm_health(now);
+        assert!(health.reachable);
+        assert_eq!(health.last_error.as_deref(), Some("status 529"));
+
+        metrics.llm_call(Some("status 529".to_string()));
+        let health = metrics.llm_health(now);
+        assert!(!health.reachable);
+        assert_eq!(health.consecutive_failures, 3);
+        assert!(metrics.llm_health(now + LLM_COOLDOWN * 2).reachable);
+
+        metrics.llm_call(None);
+        let health = metrics.llm_health(Utc::now());
+        assert!(health.reachable);
+        assert_eq!(health.consecutive_failures, 0);
+        assert_eq!(health.last_error.as_deref(), Some("status 529"));
+    }
+}
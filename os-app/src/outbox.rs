@@ -0,0 +1,961 @@
+//! Ordered outbound delivery.
+//!
+//! Every send goes through a lane keyed by `(channel, recipient)`. A lane is a queue with
+//! one worker, so progress notes, task results and final answers reach a conversation in
+//! the order they were produced, while different conversations deliver in parallel.
+//! Idle lanes shut down and are recreated on the next send.
+//!
+//! Lanes also move long code blocks into file attachments where the channel takes them,
+//! and split replies that exceed the channel's length limit into numbered parts. Secrets
+//! are masked before a message is queued.
+//!
+//! Channels listed in `[recipients.allow]` only deliver to the recipients given there.
+//! The check runs here rather than at approval time, so nothing that reaches the outbox,
+//! approved or not, can go elsewhere. Refused sends are written to the audit log.
+//!
+//! A failed send is retried with exponential backoff, holding the lane so later messages
+//! don't overtake it. A send the platform refused outright (a 4xx other than a timeout or
+//! rate limit) isn't retried. Whatever wasn't delivered becomes a dead letter, kept in
+//! `data/outbox.db` until re-driven or discarded through `/api/v1/os/messages/outbox`.
+//!
+//! In `[simulation]` mode nothing is delivered: each message is logged once it has passed
+//! the recipient check and redaction, and reported as sent.
+
+use crate::audit::AuditLog;
+use crate::config::{FormattingConfig, OutboxConfig, RecipientsConfig};
+use crate::encryption::{open_value, DataCipher};
+use crate::metrics::Metrics;
+use crate::redaction::Redactor;
+use crate::sqlite::SqlitePool;
+use crate::webhooks::{self, Webhooks};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use os_channels::{
+    extract_code_blocks, split_message, Attachment, ChannelAdapter, HttpSendError, OutboundMessage,
+};
+use rusqlite::params;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use uuid::Uuid;
+
+const LANE_IDLE: Duration = Duration::from_secs(60);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+
+type LaneKey = (String, String);
+
+struct Job {
+    payload: Payload,
+    done: Option<oneshot::Sender<Result<()>>>,
+}
+
+enum Payload {
+    /// Shaped for the channel by the lane.
+    Message(OutboundMessage),
+    /// Already shaped; re-driven from a dead letter.
+    Parts(Vec<OutboundMessage>),
+}
+
+/// A send that failed after every retry.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeadLetter {
+    pub id: Uuid,
+    pub channel_id: String,
+    pub recipient: String,
+    pub failed_at: DateTime<Utc>,
+    /// Attempts made at the part that failed.
+    pub attempts: u32,
+    pub error: String,
+    /// The parts not delivered; earlier parts of a split reply were.
+    pub parts: Vec<OutboundMessage>,
+}
+
+const DEAD_LETTER_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS dead_letters (
+    id TEXT PRIMARY KEY,
+    channel_id TEXT NOT NULL,
+    recipient TEXT NOT NULL,
+    failed_at TEXT NOT NULL,
+    attempts INTEGER NOT NULL,
+    error TEXT NOT NULL,
+    parts TEXT NOT NULL
+);";
+
+struct DeadLetters {
+    max: usize,
+    letters: Mutex<VecDeque<DeadLetter>>,
+    /// Where letters outlive a restart; without it they're only kept in memory.
+    db: Option<DeadLetterDb>,
+}
+
+struct DeadLetterDb {
+    pool: SqlitePool,
+    /// Seals the undelivered parts, which hold message text.
+    cipher: Option<Arc<DataCipher>>,
+}
+
+impl DeadLetters {
+    fn new(max: usize) -> Self {
+        Self {
+            max,
+            letters: Mutex::new(VecDeque::new()),
+            db: None,
+        }
+    }
+
+    /// Open `path` and pick up the letters left there.
+    fn open(max: usize, path: &Path, cipher: Option<Arc<DataCipher>>) -> Result<Self> {
+        let db = DeadLetterDb {
+            pool: SqlitePool::open(path, DEAD_LETTER_SCHEMA)?,
+            cipher,
+        };
+        let letters = db.load()?;
+        Ok(Self {
+            max,
+            letters: Mutex::new(letters),
+            db: Some(db),
+        })
+    }
+
+    fn push(&self, letter: DeadLetter) {
+        if let Some(db) = self.db.as_ref() {
+            if let Err(e) = db.insert(&letter, self.max) {
+                tracing::warn!(%e, "failed to store dead letter");
+            }
+        }
+        if let Ok(mut letters) = self.letters.lock() {
+            letters.push_back(letter);
+            while letters.len() > self.max {
+                letters.pop_front();
+            }
+        }
+    }
+
+    fn take(&self, id: Uuid) -> Option<DeadLetter> {
+        let letter = {
+            let mut letters = self.letters.lock().ok()?;
+            let i = letters.iter().position(|l| l.id == id)?;
+            letters.remove(i)?
+        };
+        if let Some(db) = self.db.as_ref() {
+            if let Err(e) = db.delete(id) {
+                tracing::warn!(%e, "failed to remove stored dead letter");
+            }
+        }
+        Some(letter)
+    }
+}
+
+impl DeadLetterDb {
+    /// Oldest first.
+    fn load(&self) -> Result<VecDeque<DeadLetter>> {
+        let rows = {
+            let conn = self.pool.read()?;
+            let mut stmt = conn.prepare(
+                "SELECT id, channel_id, recipient, failed_at, attempts, error, parts
+                 FROM dead_letters ORDER BY rowid",
+            )?;
+            let rows = stmt
+                .query_map([], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                        row.get::<_, u32>(4)?,
+                        row.get::<_, String>(5)?,
+                        row.get::<_, String>(6)?,
+                    ))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            rows
+        };
+        let mut letters = VecDeque::with_capacity(rows.len());
+        for (id, channel_id, recipient, failed_at, attempts, error, parts) in rows {
+            let parts = open_value(self.cipher.as_deref(), parts)?;
+            letters.push_back(DeadLetter {
+                id: id.parse()?,
+                channel_id,
+                recipient,
+                failed_at: DateTime::parse_from_rfc3339(&failed_at)?.with_timezone(&Utc),
+                attempts,
+                error,
+                parts: serde_json::from_str(&parts)?,
+            });
+        }
+        Ok(letters)
+    }
+
+    /// Store `letter`, dropping the oldest beyond `max`.
+    fn insert(&self, letter: &DeadLetter, max: usize) -> Result<()> {
+        let mut parts = serde_json::to_string(&letter.parts)?;
+        if let Some(cipher) = self.cipher.as_deref() {
+            parts = cipher.seal(&parts)?;
+        }
+        let mut conn = self.pool.write()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO dead_letters (id, channel_id, recipient, failed_at, attempts, error, parts)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                letter.id.to_string(),
+                letter.channel_id,
+                letter.recipient,
+                letter.failed_at.to_rfc3339(),
+                letter.attempts,
+                letter.error,
+                parts,
+            ],
+        )?;
+        tx.execute(
+            "DELETE FROM dead_letters WHERE rowid NOT IN
+                (SELECT rowid FROM dead_letters ORDER BY rowid DESC LIMIT ?1)",
+            params![max as i64],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn delete(&self, id: Uuid) -> Result<()> {
+        self.pool
+            .write()?
+            .execute("DELETE FROM dead_letters WHERE id = ?1", [id.to_string()])?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RetryPolicy {
+    retries: u32,
+    backoff: Duration,
+}
+
+impl RetryPolicy {
+    fn new(cfg: &OutboxConfig) -> Self {
+        Self {
+            retries: cfg.retries,
+            backoff: Duration::from_millis(cfg.retry_backoff_ms),
+        }
+    }
+
+    /// Wait before retry number `retry`, counting from 0.
+    fn delay(&self, retry: u32) -> Duration {
+        self.backoff
+            .saturating_mul(1 << retry.min(16))
+            .min(RETRY_MAX_DELAY)
+    }
+}
+
+pub struct Outbox {
+    channels: HashMap<String, Arc<dyn ChannelAdapter>>,
+    formatting: FormattingConfig,
+    lanes: Arc<DashMap<LaneKey, mpsc::UnboundedSender<Job>>>,
+    health: Arc<ChannelHealth>,
+    metrics: Option<Arc<Metrics>>,
+    redactor: Option<Redactor>,
+    recipients: RecipientsConfig,
+    audit: Option<Arc<AuditLog>>,
+    retry: RetryPolicy,
+    dead: Arc<DeadLetters>,
+    simulation: bool,
+}
+
+impl Outbox {
+    pub fn new(channels: HashMap<String, Arc<dyn ChannelAdapter>>) -> Self {
+        Self {
+            channels,
+            formatting: FormattingConfig::default(),
+            lanes: Arc::new(DashMap::new()),
+            health: Arc::new(ChannelHealth::default()),
+            metrics: None,
+            redactor: None,
+            recipients: RecipientsConfig::default(),
+            audit: None,
+            retry: RetryPolicy::new(&OutboxConfig::default()),
+            dead: Arc::new(DeadLetters::new(OutboxConfig::default().dead_letter_max)),
+            simulation: false,
+        }
+    }
+
+    /// Report channels going down (a send failed) and coming back (a send succeeded).
+    pub fn with_webhooks(mut self, webhooks: Arc<Webhooks>) -> Self {
+        self.health = Arc::new(ChannelHealth {
+            down: DashMap::new(),
+            webhooks: Some(webhooks),
+        });
+        self
+    }
+
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn with_formatting(mut self, formatting: FormattingConfig) -> Self {
+        self.formatting = formatting;
+        self
+    }
+
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = Some(redactor);
+        self
+    }
+
+    pub fn with_recipients(mut self, recipients: RecipientsConfig) -> Self {
+        self.recipients = recipients;
+        self
+    }
+
+    pub fn with_audit(mut self, audit: Arc<AuditLog>) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    /// Retry and dead-letter settings.
+    pub fn with_retries(mut self, cfg: &OutboxConfig) -> Self {
+        self.retry = RetryPolicy::new(cfg);
+        self.dead = Arc::new(DeadLetters::new(cfg.dead_letter_max));
+        self
+    }
+
+    /// Keep dead letters in the SQLite database at `path` so they survive a restart,
+    /// sealing their text with `cipher`. Call after `with_retries`.
+    pub fn with_dead_letter_db(
+        mut self,
+        path: &Path,
+        cipher: Option<Arc<DataCipher>>,
+    ) -> Result<Self> {
+        self.dead = Arc::new(DeadLetters::open(self.dead.max, path, cipher)?);
+        Ok(self)
+    }
+
+    /// Channels whose last send failed, with the error.
+    pub fn failing_channels(&self) -> HashMap<String, String> {
+        self.health
+            .down
+            .iter()
+            .map(|e| (e.key().clone(), e.value().clone()))
+            .collect()
+    }
+
+    /// Log messages instead of delivering them.
+    pub fn with_simulation(mut self, simulation: bool) -> Self {
+        self.simulation = simulation;
+        self
+    }
+
+    pub fn channel(&self, channel_id: &str) -> Option<&Arc<dyn ChannelAdapter>> {
+        self.channels.get(channel_id)
+    }
+
+    pub fn channel_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.channels.keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Queue a message and wait until it has been handed to the channel.
+    pub async fn send(
+        &self,
+        channel_id: &str,
+        recipient: &str,
+        message: OutboundMessage,
+    ) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.push(channel_id, recipient, message, Some(tx))?;
+        rx.await
+            .map_err(|_| anyhow::anyhow!("outbox lane for {channel_id} closed"))?
+    }
+
+    /// Queue a message without waiting for delivery, which its lane retries and
+    /// dead-letters on its own. Errs only when the message can't be queued at all.
+    pub fn post(&self, channel_id: &str, recipient: &str, message: OutboundMessage) -> Result<()> {
+        self.push(channel_id, recipient, message, None)
+    }
+
+    /// Queue a message without waiting; failures are logged.
+    pub fn enqueue(&self, channel_id: &str, recipient: &str, message: OutboundMessage) {
+        if let Err(e) = self.post(channel_id, recipient, message) {
+            tracing::warn!(%e, %channel_id, "outbound message dropped");
+        }
+    }
+
+    /// Oldest first.
+    pub fn dead_letters(&self) -> Vec<DeadLetter> {
+        self.dead
+            .letters
+            .lock()
+            .map(|letters| letters.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Queue a dead letter's parts again. False when there's no such letter.
+    pub fn redrive(&self, id: Uuid) -> Result<bool> {
+        let Some(letter) = self.dead.take(id) else {
+            return Ok(false);
+        };
+        let job = Job {
+            payload: Payload::Parts(letter.parts.clone()),
+            done: None,
+        };
+        if let Err(e) = self.push_job(&letter.channel_id, &letter.recipient, job) {
+            self.dead.push(letter);
+            return Err(e);
+        }
+        Ok(true)
+    }
+
+    /// Drop a dead letter. False when there's no such letter.
+    pub fn discard(&self, id: Uuid) -> bool {
+        self.dead.take(id).is_some()
+    }
+
+    fn push(
+        &self,
+        channel_id: &str,
+        recipient: &str,
+        mut message: OutboundMessage,
+        done: Option<oneshot::Sender<Result<()>>>,
+    ) -> Result<()> {
+        if let Some(allowed) = self.recipients.allow.get(channel_id) {
+            if !allowed.iter().any(|p| recipient_matches(p, recipient)) {
+                let detail = format!("{recipient} is not in recipients.allow.{channel_id}");
+                if let Some(audit) = self.audit.clone() {
+                    let channel_id = channel_id.to_string();
+                    let detail = detail.clone();
+                    tokio::spawn(async move { audit.policy_violation(&channel_id, &detail).await });
+                }
+                return Err(anyhow::anyhow!("outbound message refused: {detail}"));
+            }
+        }
+        if let Some(redactor) = self.redactor.as_ref() {
+            message.content = redactor.redact(&message.content);
+        }
+        if self.simulation {
+            if !self.channels.contains_key(channel_id) {
+                return Err(anyhow::anyhow!("unknown channel: {channel_id}"));
+            }
+            tracing::info!(
+                %channel_id,
+                %recipient,
+                content = %message.content,
+                attachments = message.attachments.len(),
+                "simulation: outbound message not delivered"
+            );
+            if let Some(done) = done {
+                let _ = done.send(Ok(()));
+            }
+            return Ok(());
+        }
+        let job = Job {
+            payload: Payload::Message(message),
+            done,
+        };
+        self.push_job(channel_id, recipient, job)
+    }
+
+    fn push_job(&self, channel_id: &str, recipient: &str, job: Job) -> Result<()> {
+        let channel = self
+            .channels
+            .get(channel_id)
+            .ok_or_else(|| anyhow::anyhow!("unknown channel: {channel_id}"))?
+            .clone();
+        let shaping = Shaping {
+            max_chars: self
+                .formatting
+                .max_chars
+                .get(channel_id)
+                .copied()
+                .unwrap_or_else(|| channel.max_message_chars()),
+            code_attachment_min_lines: if channel.supports_attachments() {
+                self.formatting.code_attachment_min_lines
+            } else {
+                0
+            },
+        };
+        let key = (channel_id.to_string(), recipient.to_string());
+        // The entry guard is held while pushing so a lane can't retire in between.
+        let lane = self.lanes.entry(key.clone()).or_insert_with(|| {
+            let (tx, rx) = mpsc::unbounded_channel();
+            tokio::spawn(run_lane(
+                self.lanes.clone(),
+                self.health.clone(),
+                self.metrics.clone(),
+                self.dead.clone(),
+                key,
+                channel,
+                shaping,
+                self.retry,
+                rx,
+            ));
+            tx
+        });
+        // Counted before the send so the lane can't report it done first.
+        if let Some(metrics) = self.metrics.as_ref() {
+            metrics.outbound_queued();
+        }
+        lane.send(job).map_err(|_| {
+            if let Some(metrics) = self.metrics.as_ref() {
+                metrics.outbound_done(channel_id, false);
+            }
+            anyhow::anyhow!("outbox lane for {channel_id} closed")
+        })
+    }
+}
+
+/// Case-insensitive; phone numbers are compared without spaces, dashes, dots or parens.
+fn recipient_matches(pattern: &str, recipient: &str) -> bool {
+    let normalize = |s: &str| {
+        let s = s.trim().to_lowercase();
+        if s.starts_with('+') && s.chars().all(|c| "+0123456789 -().*".contains(c)) {
+            s.chars().filter(|c| !" -().".contains(*c)).collect()
+        } else {
+            s
+        }
+    };
+    let (pattern, recipient) = (normalize(pattern), normalize(recipient));
+    if pattern == "*" {
+        true
+    } else if let Some(suffix) = pattern.strip_prefix('*') {
+        recipient.ends_with(suffix)
+    } else if let Some(prefix) = pattern.strip_suffix('*') {
+        recipient.starts_with(prefix)
+    } else {
+        pattern == recipient
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run_lane(
+    lanes: Arc<DashMap<LaneKey, mpsc::UnboundedSender<Job>>>,
+    health: Arc<ChannelHealth>,
+    metrics: Option<Arc<Metrics>>,
+    dead: Arc<DeadLetters>,
+    key: LaneKey,
+    channel: Arc<dyn ChannelAdapter>,
+    shaping: Shaping,
+    retry: RetryPolicy,
+    mut rx: mpsc::UnboundedReceiver<Job>,
+) {
+    loop {
+        let job = match tokio::time::timeout(LANE_IDLE, rx.recv()).await {
+            Ok(Some(job)) => job,
+            Ok(None) => return,
+            Err(_) => {
+                // Retire only if nothing was queued meanwhile; pushes hold the entry lock.
+                if lanes.remove_if(&key, |_, _| rx.is_empty()).is_some() {
+                    return;
+                }
+                continue;
+            }
+        };
+
+        let parts = match job.payload {
+            Payload::Message(message) => shape(channel.as_ref(), shaping, message),
+            Payload::Parts(parts) => parts,
+        };
+        let res = match deliver(channel.as_ref(), &key.1, retry, parts).await {
+            Ok(()) => Ok(()),
+            Err(failed) => {
+                tracing::warn!(
+                    e = %failed.error,
+                    channel_id = %key.0,
+                    attempts = failed.attempts,
+                    "outbound send failed; dead-lettered"
+                );
+                dead.push(DeadLetter {
+                    id: Uuid::new_v4(),
+                    channel_id: key.0.clone(),
+                    recipient: key.1.clone(),
+                    failed_at: Utc::now(),
+                    attempts: failed.attempts,
+                    error: failed.error.to_string(),
+                    parts: failed.parts,
+                });
+                Err(failed.error)
+            }
+        };
+        health.observe(&key.0, res.as_ref().err());
+        if let Some(metrics) = metrics.as_ref() {
+            metrics.outbound_done(&key.0, res.is_ok());
+        }
+        if let Some(done) = job.done {
+            let _ = done.send(res);
+        }
+    }
+}
+
+/// Which channels last failed a send, and with what, so webhooks fire on transitions only.
+#[derive(Default)]
+struct ChannelHealth {
+    down: DashMap<String, String>,
+    webhooks: Option<Arc<Webhooks>>,
+}
+
+impl ChannelHealth {
+    fn observe(&self, channel_id: &str, err: Option<&anyhow::Error>) {
+        let (event, data) = match err {
+            Some(e) => {
+                let error = e.to_string();
+                if self
+                    .down
+                    .insert(channel_id.to_string(), error.clone())
+                    .is_some()
+                {
+                    return;
+                }
+                (
+                    webhooks::CHANNEL_DOWN,
+                    serde_json::json!({ "channel_id": channel_id, "error": error }),
+                )
+            }
+            None if self.down.remove(channel_id).is_some() => (
+                webhooks::CHANNEL_UP,
+                serde_json::json!({ "channel_id": channel_id }),
+            ),
+            None => return,
+        };
+        if let Some(hooks) = self.webhooks.as_ref() {
+            hooks.emit(event, data);
+        }
+    }
+}
+
+/// How a lane reshapes messages for its channel.
+#[derive(Debug, Clone, Copy)]
+struct Shaping {
+    max_chars: usize,
+    /// 0 leaves code blocks inline.
+    code_attachment_min_lines: usize,
+}
+
+fn shape(
+    channel: &dyn ChannelAdapter,
+    shaping: Shaping,
+    mut message: OutboundMessage,
+) -> Vec<OutboundMessage> {
+    if shaping.code_attachment_min_lines > 0 {
+        let (content, blocks) =
+            extract_code_blocks(&message.content, shaping.code_attachment_min_lines);
+        if !blocks.is_empty() {
+            message.content = content;
+            message.attachments.extend(
+                blocks
+                    .iter()
+                    .map(|b| Attachment::from_bytes(&b.filename, "text/plain", b.code.as_bytes())),
+            );
+        }
+    }
+
+    let parts = split_message(&message.content, shaping.max_chars, channel.dialect());
+    if parts.len() == 1 {
+        return vec![message];
+    }
+    // The reply reference and attachments ride on the first part.
+    parts
+        .into_iter()
+        .enumerate()
+        .map(|(i, content)| {
+            if i == 0 {
+                OutboundMessage {
+                    content,
+                    reply_to_message_id: message.reply_to_message_id.clone(),
+                    attachments: message.attachments.clone(),
+                }
+            } else {
+                OutboundMessage {
+                    content,
+                    reply_to_message_id: None,
+                    attachments: vec![],
+                }
+            }
+        })
+        .collect()
+}
+
+struct Undelivered {
+    error: anyhow::Error,
+    attempts: u32,
+    parts: Vec<OutboundMessage>,
+}
+
+/// Sends the parts in order, retrying each one that fails.
+async fn deliver(
+    channel: &dyn ChannelAdapter,
+    recipient: &str,
+    retry: RetryPolicy,
+    parts: Vec<OutboundMessage>,
+) -> Result<(), Undelivered> {
+    for (i, part) in parts.iter().enumerate() {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            match channel.send(recipient, part.clone()).await {
+                Ok(()) => break,
+                Err(error) if attempts > retry.retries || is_permanent(&error) => {
+                    return Err(Undelivered {
+                        error,
+                        attempts,
+                        parts: parts[i..].to_vec(),
+                    });
+                }
+                Err(e) => {
+                    tracing::debug!(%e, attempts, "outbound send failed; retrying");
+                    tokio::time::sleep(retry.delay(attempts - 1)).await;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The platform refused the message itself, so sending it again won't help.
+fn is_permanent(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<HttpSendError>()
+        .is_some_and(HttpSendError::is_permanent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use os_channels::InboundMessage;
+    use std::sync::Mutex;
+
+    struct SlowChannel {
+        sent: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl ChannelAdapter for SlowChannel {
+        fn channel_id(&self) -> &str {
+            "slow"
+        }
+
+        async fn start(&self, _tx: mpsc::Sender<InboundMessage>) -> Result<()> {
+            Ok(())
+        }
+
+        async fn send(&self, recipient_id: &str, message: OutboundMessage) -> Result<()> {
+            // Earlier messages take longer, so unordered delivery would reverse them.
+            let delay = 30u64.saturating_sub(self.sent.lock().unwrap().len() as u64 * 10);
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            self.sent
+                .lock()
+                .unwrap()
+                .push(format!("{recipient_id}:{}", message.content));
+            Ok(())
+        }
+    }
+
+    fn text(content: &str) -> OutboundMessage {
+        OutboundMessage {
+            content: content.to_string(),
+            reply_to_message_id: None,
+            attachments: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_to_one_recipient_keep_their_order() {
+        let channel = Arc::new(SlowChannel {
+            sent: Mutex::new(Vec::new()),
+        });
+        let mut channels: HashMap<String, Arc<dyn ChannelAdapter>> = HashMap::new();
+        channels.insert("slow".to_string(), channel.clone());
+        let outbox = Outbox::new(channels);
+
+        outbox.enqueue("slow", "u1", text("one"));
+        outbox.enqueue("slow", "u1", text("two"));
+        outbox.send("slow", "u1", text("three")).await.unwrap();
+
+        assert_eq!(
+            *channel.sent.lock().unwrap(),
+            vec!["u1:one", "u1:two", "u1:three"]
+        );
+        assert!(outbox.send("missing", "u1", text("x")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn only_allowed_recipients_are_delivered_to() {
+        let channel = Arc::new(SlowChannel {
+            sent: Mutex::new(Vec::new()),
+        });
+        let mut channels: HashMap<String, Arc<dyn ChannelAdapter>> = HashMap::new();
+        channels.insert("slow".to_string(), channel.clone());
+        let outbox = Outbox::new(channels).with_recipients(RecipientsConfig {
+            allow: HashMap::from([(
+                "slow".to_string(),
+                vec!["+1 (415) 555-0100".to_string(), "*@Example.com".to_string()],
+            )]),
+        });
+
+        outbox
+            .send("slow", "+14155550100", text("a"))
+            .await
+            .unwrap();
+        outbox
+            .send("slow", "ana@example.COM", text("b"))
+            .await
+            .unwrap();
+        assert!(outbox
+            .send("slow", "+14155550199", text("c"))
+            .await
+            .is_err());
+        assert!(outbox
+            .send("slow", "x@example.com.evil", text("d"))
+            .await
+            .is_err());
+
+        assert_eq!(
+            *channel.sent.lock().unwrap(),
+            vec!["+14155550100:a", "ana@example.COM:b"]
+        );
+    }
+
+    #[tokio::test]
+    async fn simulation_delivers_nothing() {
+        let channel = Arc::new(SlowChannel {
+            sent: Mutex::new(Vec::new()),
+        });
+        let mut channels: HashMap<String, Arc<dyn ChannelAdapter>> = HashMap::new();
+        channels.insert("slow".to_string(), channel.clone());
+        let outbox = Outbox::new(channels).with_simulation(true);
+
+        outbox.enqueue("slow", "u1", text("one"));
+        outbox.send("slow", "u1", text("two")).await.unwrap();
+        assert!(outbox.send("missing", "u1", text("x")).await.is_err());
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(channel.sent.lock().unwrap().is_empty());
+    }
+
+    /// Fails its first `failures` sends.
+    struct FlakyChannel {
+        failures: Mutex<usize>,
+        sent: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl ChannelAdapter for FlakyChannel {
+        fn channel_id(&self) -> &str {
+            "flaky"
+        }
+
+        async fn start(&self, _tx: mpsc::Sender<InboundMessage>) -> Result<()> {
+            Ok(())
+        }
+
+        async fn send(&self, _recipient_id: &str, message: OutboundMessage) -> Result<()> {
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return Err(anyhow::anyhow!("rate limited"));
+            }
+            self.sent.lock().unwrap().push(message.content);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_sends_are_retried_then_dead_lettered_until_redriven() {
+        let channel = Arc::new(FlakyChannel {
+            failures: Mutex::new(2),
+            sent: Mutex::new(Vec::new()),
+        });
+        let mut channels: HashMap<String, Arc<dyn ChannelAdapter>> = HashMap::new();
+        channels.insert("flaky".to_string(), channel.clone());
+        let outbox = Outbox::new(channels).with_retries(&OutboxConfig {
+            retries: 1,
+            retry_backoff_ms: 1,
+            ..Default::default()
+        });
+
+        assert!(outbox.send("flaky", "u1", text("lost")).await.is_err());
+        let letters = outbox.dead_letters();
+        assert_eq!(letters.len(), 1);
+        assert_eq!(letters[0].attempts, 2);
+        assert_eq!(letters[0].error, "rate limited");
+
+        *channel.failures.lock().unwrap() = 1;
+        outbox.send("flaky", "u1", text("retried")).await.unwrap();
+
+        assert!(outbox.redrive(letters[0].id).unwrap());
+        assert!(!outbox.redrive(letters[0].id).unwrap());
+        outbox.send("flaky", "u1", text("after")).await.unwrap();
+        assert_eq!(
+            *channel.sent.lock().unwrap(),
+            vec!["retried", "lost", "after"]
+        );
+        assert!(outbox.dead_letters().is_empty());
+    }
+
+    /// Refuses every send with a client error, counting the attempts.
+    struct RefusingChannel {
+        attempts: Mutex<u32>,
+    }
+
+    #[async_trait]
+    impl ChannelAdapter for RefusingChannel {
+        fn channel_id(&self) -> &str {
+            "refusing"
+        }
+
+        async fn start(&self, _tx: mpsc::Sender<InboundMessage>) -> Result<()> {
+            Ok(())
+        }
+
+        async fn send(&self, _recipient_id: &str, _message: OutboundMessage) -> Result<()> {
+            *self.attempts.lock().unwrap() += 1;
+            Err(HttpSendError {
+                channel_id: "refusing".to_string(),
+                status: 400,
+                body: "chat not found".to_string(),
+            }
+            .into())
+        }
+    }
+
+    #[tokio::test]
+    async fn refused_sends_are_not_retried_and_dead_letters_survive_a_restart() {
+        let tmp = std::env::temp_dir().join(format!("opencraw-outbox-{}", Uuid::new_v4()));
+        let db = tmp.join("outbox.db");
+        let channel = Arc::new(RefusingChannel {
+            attempts: Mutex::new(0),
+        });
+        let mut channels: HashMap<String, Arc<dyn ChannelAdapter>> = HashMap::new();
+        channels.insert("refusing".to_string(), channel.clone());
+        let cfg = OutboxConfig {
+            retries: 3,
+            retry_backoff_ms: 1,
+            ..Default::default()
+        };
+        let open = || {
+            Outbox::new(channels.clone())
+                .with_retries(&cfg)
+                .with_dead_letter_db(&db, None)
+                .unwrap()
+        };
+
+        let outbox = open();
+        let e = outbox
+            .send("refusing", "u1", text("hello"))
+            .await
+            .unwrap_err();
+        assert_eq!(e.to_string(), "refusing send failed (400): chat not found");
+        assert_eq!(*channel.attempts.lock().unwrap(), 1);
+        drop(outbox);
+
+        let outbox = open();
+        let letters = outbox.dead_letters();
+        assert_eq!(letters.len(), 1);
+        assert_eq!(letters[0].parts[0].content, "hello");
+        assert!(outbox.discard(letters[0].id));
+        assert!(open().dead_letters().is_empty());
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+}
@@ -1,8 +1,27 @@
-//! Allowlist enforcement.
+//! Allowlist enforcement, and invite codes that add to it.
+//!
+//! An invite is a one-time code for one channel with an expiry. The first sender on that
+//! channel to send `/pair <code>` (or open the invite's Telegram link, which sends
+//! `/start <code>`) is paired: admitted like an allowlisted sender from then on, without
+//! editing `security.allowed_users` or opening the channel to everyone. Invites and
+//! paired senders are kept in `data/pairing.json`.
 //!
 //! See: specifications/openshell/implementation_v0_1_0.md
 
-use crate::config::OpenShellConfig;
+use crate::config::{OpenShellConfig, PairingConfig};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use os_channels::Attachment;
+use qrcode::render::{svg, unicode};
+use qrcode::QrCode;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// No 0/O or 1/I, so a code read aloud or off a screen is typed right.
+const CODE_ALPHABET: &[u8; 32] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const CODE_LEN: usize = 10;
 
 pub fn is_allowed(cfg: &OpenShellConfig, channel_id: &str, sender_id: &str) -> bool {
     // WebChat is a local/dev channel; allow by default.
@@ -23,13 +42,336 @@ pub fn is_allowed(cfg: &OpenShellConfig, channel_id: &str, sender_id: &str) -> b
         .any(|u| u == sender_id || u == &composite)
 }
 
+/// `/pair <code>`, or `/start <code>` from a Telegram invite link. The code comes back
+/// normalized; see `normalize_code`.
+pub fn parse_pair(input: &str) -> Option<String> {
+    let mut words = input.split_whitespace();
+    if !matches!(words.next()?, "/pair" | "/start") {
+        return None;
+    }
+    let code = normalize_code(words.next()?);
+    (words.next().is_none() && code.len() == CODE_LEN).then_some(code)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InviteCommand {
+    pub channel: String,
+    pub expires_in: Option<chrono::Duration>,
+    /// Who it's for, e.g. `Mom`; kept with the paired sender.
+    pub note: Option<String>,
+}
+
+impl InviteCommand {
+    /// `/invite telegram`, `/invite telegram 48h`, `/invite telegram 2d Mom`.
+    pub fn parse(input: &str) -> Option<Result<Self, String>> {
+        let rest = input.trim().strip_prefix("/invite")?;
+        if !rest.is_empty() && !rest.starts_with(' ') {
+            return None;
+        }
+        let mut words = rest.split_whitespace().peekable();
+        let Some(channel) = words.next() else {
+            return Some(Err(
+                "Usage: /invite <channel> [duration] [who it's for], e.g. /invite telegram 48h Mom"
+                    .to_string(),
+            ));
+        };
+        let expires_in = words.peek().and_then(|w| crate::focus::parse_duration(w));
+        if expires_in.is_some() {
+            words.next();
+        }
+        let note = words.collect::<Vec<_>>().join(" ");
+        Some(Ok(Self {
+            channel: channel.to_string(),
+            expires_in,
+            note: (!note.is_empty()).then_some(note),
+        }))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Invite {
+    pub code: String,
+    pub channel: String,
+    #[serde(default)]
+    pub note: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairedSender {
+    pub channel_id: String,
+    pub sender_id: String,
+    #[serde(default)]
+    pub note: Option<String>,
+    pub paired_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Redeemed {
+    Paired,
+    /// A live invite, but for another channel; it stays usable there.
+    WrongChannel(String),
+    /// Unknown, used or expired.
+    Invalid,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PairingState {
+    #[serde(default)]
+    invites: Vec<Invite>,
+    #[serde(default)]
+    paired: Vec<PairedSender>,
+}
+
+pub struct PairingStore {
+    cfg: PairingConfig,
+    path: PathBuf,
+    state: Mutex<PairingState>,
+}
+
+impl PairingStore {
+    /// Load `data_dir/pairing.json`, if there is one.
+    pub async fn open(cfg: PairingConfig, data_dir: &Path) -> Result<Self> {
+        let path = data_dir.join("pairing.json");
+        let state = match tokio::fs::read(&path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| anyhow!("{} is corrupt: {e}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => PairingState::default(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            cfg,
+            path,
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Mint an invite for `channel`, for the given time or the configured default.
+    pub fn create(
+        &self,
+        channel: &str,
+        expires_in: Option<chrono::Duration>,
+        note: Option<String>,
+    ) -> Result<Invite> {
+        if channel == "webchat" {
+            return Err(anyhow!("webchat doesn't need an invite"));
+        }
+        let max = chrono::Duration::hours(self.cfg.max_expire_hours as i64);
+        let expires_in =
+            expires_in.unwrap_or_else(|| chrono::Duration::hours(self.cfg.expire_hours as i64));
+        if expires_in > max {
+            return Err(anyhow!(
+                "invites can last at most {} hours",
+                self.cfg.max_expire_hours
+            ));
+        }
+        let now = Utc::now();
+        let invite = Invite {
+            code: new_code(),
+            channel: channel.to_string(),
+            note,
+            created_at: now,
+            expires_at: now + expires_in,
+        };
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.invites.retain(|i| i.expires_at > now);
+        state.invites.push(invite.clone());
+        self.save(&state)?;
+        Ok(invite)
+    }
+
+    /// Use up `code` to pair `sender_id` on `channel_id`.
+    pub fn redeem(&self, code: &str, channel_id: &str, sender_id: &str) -> Result<Redeemed> {
+        let code = normalize_code(code);
+        let now = Utc::now();
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.invites.retain(|i| i.expires_at > now);
+        let Some(index) = state.invites.iter().position(|i| i.code == code) else {
+            return Ok(Redeemed::Invalid);
+        };
+        if state.invites[index].channel != channel_id {
+            return Ok(Redeemed::WrongChannel(state.invites[index].channel.clone()));
+        }
+        let invite = state.invites.remove(index);
+        state
+            .paired
+            .retain(|p| !(p.channel_id == channel_id && p.sender_id == sender_id));
+        state.paired.push(PairedSender {
+            channel_id: channel_id.to_string(),
+            sender_id: sender_id.to_string(),
+            note: invite.note,
+            paired_at: now,
+        });
+        self.save(&state)?;
+        Ok(Redeemed::Paired)
+    }
+
+    pub fn is_paired(&self, channel_id: &str, sender_id: &str) -> bool {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state
+            .paired
+            .iter()
+            .any(|p| p.channel_id == channel_id && p.sender_id == sender_id)
+    }
+
+    /// Invites that haven't expired, and everyone paired so far.
+    pub fn list(&self) -> (Vec<Invite>, Vec<PairedSender>) {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Utc::now();
+        let invites = state
+            .invites
+            .iter()
+            .filter(|i| i.expires_at > now)
+            .cloned()
+            .collect();
+        (invites, state.paired.clone())
+    }
+
+    pub fn revoke(&self, code: &str) -> Result<bool> {
+        let code = normalize_code(code);
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let before = state.invites.len();
+        state.invites.retain(|i| i.code != code);
+        let removed = state.invites.len() != before;
+        if removed {
+            self.save(&state)?;
+        }
+        Ok(removed)
+    }
+
+    /// Take back a pairing; the sender is a stranger again.
+    pub fn unpair(&self, channel_id: &str, sender_id: &str) -> Result<bool> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let before = state.paired.len();
+        state
+            .paired
+            .retain(|p| !(p.channel_id == channel_id && p.sender_id == sender_id));
+        let removed = state.paired.len() != before;
+        if removed {
+            self.save(&state)?;
+        }
+        Ok(removed)
+    }
+
+    /// A link that opens the channel with the code filled in, where the channel has one.
+    pub fn link(&self, invite: &Invite) -> Option<String> {
+        match invite.channel.as_str() {
+            "telegram" => self.cfg.telegram_bot.as_ref().map(|bot| {
+                format!(
+                    "https://t.me/{}?start={}",
+                    bot.trim_start_matches('@'),
+                    invite.code
+                )
+            }),
+            _ => None,
+        }
+    }
+
+    /// What to hand the person being invited.
+    pub fn instructions(&self, invite: &Invite) -> String {
+        let how = match self.link(invite) {
+            Some(link) => format!("open {link}, or send /pair {}", display_code(&invite.code)),
+            None => format!("send /pair {}", display_code(&invite.code)),
+        };
+        format!(
+            "Invite for {}{}: {how} on {}. Works once, until {} UTC.",
+            invite.channel,
+            invite
+                .note
+                .as_deref()
+                .map(|n| format!(" ({n})"))
+                .unwrap_or_default(),
+            invite.channel,
+            invite.expires_at.format("%Y-%m-%d %H:%M"),
+        )
+    }
+
+    /// Reply to `/invite`: the instructions, with the link as a QR code when there is one.
+    pub fn apply(
+        &self,
+        command: InviteCommand,
+        active_channels: &[String],
+    ) -> (String, Vec<Attachment>) {
+        if !active_channels.contains(&command.channel) {
+            return (
+                format!("{} isn't an enabled channel.", command.channel),
+                vec![],
+            );
+        }
+        match self.create(&command.channel, command.expires_in, command.note) {
+            Ok(invite) => {
+                let qr = self
+                    .link(&invite)
+                    .and_then(|link| qr_svg(&link))
+                    .map(|svg| {
+                        Attachment::from_bytes("invite.svg", "image/svg+xml", svg.as_bytes())
+                    });
+                (self.instructions(&invite), qr.into_iter().collect())
+            }
+            Err(e) => (format!("Couldn't create an invite: {e}"), vec![]),
+        }
+    }
+
+    fn save(&self, state: &PairingState) -> Result<()> {
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(state)?)?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+/// A QR code of `text` as an SVG document.
+pub fn qr_svg(text: &str) -> Option<String> {
+    let code = QrCode::new(text).ok()?;
+    Some(code.render::<svg::Color>().min_dimensions(240, 240).build())
+}
+
+/// A QR code of `text` drawn with block characters, for a terminal.
+pub fn qr_terminal(text: &str) -> Option<String> {
+    let code = QrCode::new(text).ok()?;
+    Some(
+        code.render::<unicode::Dense1x2>()
+            .dark_color(unicode::Dense1x2::Light)
+            .light_color(unicode::Dense1x2::Dark)
+            .build(),
+    )
+}
+
+/// `ABCDE-FGHJK`, easier to read out than ten letters in a row.
+pub fn display_code(code: &str) -> String {
+    match code.len() {
+        CODE_LEN => format!("{}-{}", &code[..CODE_LEN / 2], &code[CODE_LEN / 2..]),
+        _ => code.to_string(),
+    }
+}
+
+/// Uppercase, without the dash or spaces people copy along with it.
+fn normalize_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+fn new_code() -> String {
+    // Bytes of a v4 UUID other than the version and variant ones are uniformly random, and
+    // 32 divides 256, so each character is too: 50 bits in all.
+    let bytes = *Uuid::new_v4().as_bytes();
+    [0, 1, 2, 3, 4, 5, 7, 9, 10, 11]
+        .iter()
+        .map(|&i| CODE_ALPHABET[bytes[i] as usize % CODE_ALPHABET.len()] as char)
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::config::{
-        ApprovalMode, ChannelsConfig, DiscordConfig, GeneralConfig, ImessageConfig, KeysConfig,
-        MemoryConfig, OpenShellConfig, OptimizationConfig, SecurityConfig, TelegramConfig,
-        ToolsConfig, WebChatConfig,
+        ApprovalMode, ArchiveConfig, AttachmentsConfig, ChannelsConfig, DiscordConfig,
+        EmbeddingsConfig, GeneralConfig, ImessageConfig, IntegrityConfig, KeysConfig, MemoryConfig,
+        OpenShellConfig, OptimizationConfig, SecurityConfig, TelegramConfig, ToolsConfig,
+        WebChatConfig,
     };
 
     fn base_cfg() -> OpenShellConfig {
@@ -37,8 +379,12 @@ mod tests {
             general: GeneralConfig {
                 model: "gpt-4o-mini".to_string(),
                 system_prompt: "x".to_string(),
+                reasoning: Default::default(),
+                sampling: Default::default(),
+                routing: Default::default(),
             },
             keys: KeysConfig::default(),
+            azure_openai: Default::default(),
             channels: ChannelsConfig {
                 webchat: WebChatConfig {
                     enabled: true,
@@ -47,6 +393,9 @@ mod tests {
                 telegram: TelegramConfig::default(),
                 discord: DiscordConfig::default(),
                 imessage: ImessageConfig::default(),
+                ntfy: Default::default(),
+                pushover: Default::default(),
+                calendar: Default::default(),
             },
             tools: ToolsConfig::default(),
             security: SecurityConfig {
@@ -55,9 +404,45 @@ mod tests {
                 filesystem_write_approval: ApprovalMode::Ai,
                 allowed_users: vec![],
                 allow_all_senders: false,
+                rules: Vec::new(),
             },
             memory: MemoryConfig::default(),
             optimization: OptimizationConfig::default(),
+            integrity: IntegrityConfig::default(),
+            attachments: AttachmentsConfig::default(),
+            archive: ArchiveConfig::default(),
+            embeddings: EmbeddingsConfig::default(),
+            personas: Default::default(),
+            tasks: Default::default(),
+            progress: Default::default(),
+            watchdog: Default::default(),
+            inbound: Default::default(),
+            sessions: Default::default(),
+            formatting: Default::default(),
+            translation: Default::default(),
+            webhooks: Default::default(),
+            continuations: Default::default(),
+            suggestions: Default::default(),
+            identities: Default::default(),
+            focus: Default::default(),
+            shares: Default::default(),
+            redaction: Default::default(),
+            encryption: Default::default(),
+            pii: Default::default(),
+            injection: Default::default(),
+            recipients: Default::default(),
+            approvals: Default::default(),
+            outbox: Default::default(),
+            cluster: Default::default(),
+            context: Default::default(),
+            recording: Default::default(),
+            simulation: Default::default(),
+            skills: Default::default(),
+            automation: Default::default(),
+            supervisor: Default::default(),
+            admin: Default::default(),
+            control: Default::default(),
+            pairing: Default::default(),
         }
     }
 
@@ -92,4 +477,102 @@ mod tests {
         cfg.security.allowed_users = vec!["imessage:+14155551212".to_string()];
         assert!(is_allowed(&cfg, "imessage", "+14155551212"));
     }
+
+    fn store(dir: &Path) -> PairingStore {
+        PairingStore {
+            cfg: PairingConfig {
+                telegram_bot: Some("@craw_bot".to_string()),
+                ..Default::default()
+            },
+            path: dir.join("pairing.json"),
+            state: Mutex::new(PairingState::default()),
+        }
+    }
+
+    #[test]
+    fn pair_accepts_codes_as_people_type_them() {
+        assert_eq!(
+            parse_pair("/pair abcde-fghjk"),
+            Some("ABCDEFGHJK".to_string())
+        );
+        assert_eq!(
+            parse_pair("/start ABCDEFGHJK"),
+            Some("ABCDEFGHJK".to_string())
+        );
+        assert_eq!(parse_pair("/start"), None);
+        assert_eq!(parse_pair("/pair ABC"), None);
+        assert_eq!(parse_pair("please /pair ABCDEFGHJK"), None);
+    }
+
+    #[test]
+    fn invite_command_takes_an_optional_duration_and_note() {
+        assert_eq!(
+            InviteCommand::parse("/invite telegram 48h Aunt May"),
+            Some(Ok(InviteCommand {
+                channel: "telegram".to_string(),
+                expires_in: chrono::Duration::try_hours(48),
+                note: Some("Aunt May".to_string()),
+            }))
+        );
+        assert_eq!(
+            InviteCommand::parse("/invite discord Mom"),
+            Some(Ok(InviteCommand {
+                channel: "discord".to_string(),
+                expires_in: None,
+                note: Some("Mom".to_string()),
+            }))
+        );
+        assert!(matches!(InviteCommand::parse("/invite"), Some(Err(_))));
+        assert_eq!(InviteCommand::parse("/invitee telegram"), None);
+    }
+
+    #[test]
+    fn an_invite_pairs_one_sender_on_its_channel_once() {
+        let dir = std::env::temp_dir().join(format!("pairing-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let store = store(&dir);
+        let invite = store
+            .create("telegram", None, Some("Mom".to_string()))
+            .unwrap();
+        assert_eq!(invite.code.len(), CODE_LEN);
+        assert_eq!(
+            store.link(&invite),
+            Some(format!("https://t.me/craw_bot?start={}", invite.code))
+        );
+
+        assert_eq!(
+            store.redeem(&invite.code, "discord", "1").unwrap(),
+            Redeemed::WrongChannel("telegram".to_string())
+        );
+        assert!(!store.is_paired("telegram", "42"));
+        let typed = display_code(&invite.code).to_lowercase();
+        assert_eq!(
+            store.redeem(&typed, "telegram", "42").unwrap(),
+            Redeemed::Paired
+        );
+        assert!(store.is_paired("telegram", "42"));
+        assert_eq!(
+            store.redeem(&invite.code, "telegram", "43").unwrap(),
+            Redeemed::Invalid
+        );
+
+        let bytes = std::fs::read(dir.join("pairing.json")).unwrap();
+        let saved: PairingState = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(saved.paired.len(), 1);
+        assert_eq!(saved.paired[0].note.as_deref(), Some("Mom"));
+
+        assert!(store.unpair("telegram", "42").unwrap());
+        assert!(!store.is_paired("telegram", "42"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn invites_respect_the_expiry_limit() {
+        let dir = std::env::temp_dir();
+        let store = store(&dir);
+        assert!(store
+            .create("telegram", chrono::Duration::try_days(30), None)
+            .is_err());
+        assert!(store.create("webchat", None, None).is_err());
+    }
 }
@@ -0,0 +1,243 @@
+//! Personal data masking for LLM calls.
+//!
+//! Before each chat request, emails, phone numbers, card numbers and configured patterns
+//! in the conversation (user messages, tool results, earlier tool calls) are replaced with
+//! placeholders like `[EMAIL_1]`. The session keeps the mapping, so the same value always
+//! gets the same placeholder, and the model's reply and tool arguments are filled back in
+//! before anything runs or is sent. Session history itself keeps the real values.
+
+use crate::config::PiiConfig;
+use anyhow::Result;
+use os_llm::ChatMessage;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+const EMAIL: &str = r"\b[A-Za-z0-9._%+\-]+@[A-Za-z0-9.\-]+\.[A-Za-z]{2,}\b";
+/// 13-19 digits, optionally grouped with spaces or dashes; checked with Luhn.
+const CARD: &str = r"\b\d(?:[ \-]?\d){12,18}\b";
+/// International numbers with a `+`, and (555) 123-4567-style local ones.
+const PHONE: &str = r"\+\d{1,3}[\s.\-]?\(?\d[\d\s.\-()]{5,15}\d\b|(?:\(\d{3}\)\s?|\b\d{3}[\s.\-])\d{3}[\s.\-]\d{4}\b";
+
+static PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[(?:EMAIL|PHONE|CARD|PII)_\d+\]").expect("valid regex"));
+
+/// Told to the model whenever masking is on.
+pub const PROMPT_NOTE: &str = "Text like [EMAIL_1], [PHONE_1], [CARD_1] or [PII_1] stands in for personal data kept on the owner's machine. Use these placeholders exactly as written in tool calls and replies; they are swapped back for the real values locally.";
+
+struct Detector {
+    label: &'static str,
+    regex: Regex,
+    luhn: bool,
+}
+
+pub struct PiiMasker {
+    detectors: Vec<Detector>,
+}
+
+impl PiiMasker {
+    pub fn new(cfg: &PiiConfig) -> Result<Self> {
+        let mut detectors = Vec::new();
+        if cfg.emails {
+            detectors.push(Detector {
+                label: "EMAIL",
+                regex: Regex::new(EMAIL)?,
+                luhn: false,
+            });
+        }
+        // Before phones, so a card number isn't half-matched as one.
+        if cfg.credit_cards {
+            detectors.push(Detector {
+                label: "CARD",
+                regex: Regex::new(CARD)?,
+                luhn: true,
+            });
+        }
+        if cfg.phone_numbers {
+            detectors.push(Detector {
+                label: "PHONE",
+                regex: Regex::new(PHONE)?,
+                luhn: false,
+            });
+        }
+        for pattern in &cfg.patterns {
+            detectors.push(Detector {
+                label: "PII",
+                regex: Regex::new(pattern)?,
+                luhn: false,
+            });
+        }
+        Ok(Self { detectors })
+    }
+
+    pub fn mask(&self, text: &str, vault: &mut PiiVault) -> String {
+        let mut out = text.to_string();
+        for detector in &self.detectors {
+            if !detector.regex.is_match(&out) {
+                continue;
+            }
+            out = detector
+                .regex
+                .replace_all(&out, |caps: &Captures| {
+                    let value = &caps[0];
+                    if PLACEHOLDER.is_match(value) || (detector.luhn && !luhn_valid(value)) {
+                        value.to_string()
+                    } else {
+                        vault.placeholder(detector.label, value)
+                    }
+                })
+                .into_owned();
+        }
+        out
+    }
+
+    /// Copies of `messages` with message text and tool call arguments masked.
+    pub fn mask_messages(
+        &self,
+        messages: &[ChatMessage],
+        vault: &mut PiiVault,
+    ) -> Vec<ChatMessage> {
+        messages
+            .iter()
+            .map(|m| {
+                let mut m = m.clone();
+                m.content = self.mask(&m.content, vault);
+                for call in &mut m.tool_calls {
+                    call.arguments = self.mask(&call.arguments, vault);
+                }
+                m
+            })
+            .collect()
+    }
+}
+
+/// Placeholder <-> value mapping for one conversation.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PiiVault {
+    by_value: HashMap<String, String>,
+    by_placeholder: HashMap<String, String>,
+    counts: HashMap<String, usize>,
+}
+
+impl PiiVault {
+    fn placeholder(&mut self, label: &'static str, value: &str) -> String {
+        if let Some(existing) = self.by_value.get(value) {
+            return existing.clone();
+        }
+        let n = self.counts.entry(label.to_string()).or_default();
+        *n += 1;
+        let placeholder = format!("[{label}_{n}]");
+        self.by_value.insert(value.to_string(), placeholder.clone());
+        self.by_placeholder
+            .insert(placeholder.clone(), value.to_string());
+        placeholder
+    }
+
+    /// Put real values back; placeholders this vault didn't hand out are left alone.
+    pub fn unmask(&self, text: &str) -> String {
+        if self.by_placeholder.is_empty() {
+            return text.to_string();
+        }
+        PLACEHOLDER
+            .replace_all(text, |caps: &Captures| {
+                self.by_placeholder
+                    .get(&caps[0])
+                    .cloned()
+                    .unwrap_or_else(|| caps[0].to_string())
+            })
+            .into_owned()
+    }
+
+    /// Unmask a model reply in place: its text and each tool call's JSON arguments. Values
+    /// are substituted into parsed JSON strings, so quotes in them can't break the JSON.
+    pub fn unmask_message(&self, message: &mut ChatMessage) {
+        message.content = self.unmask(&message.content);
+        for call in &mut message.tool_calls {
+            call.arguments = match serde_json::from_str::<serde_json::Value>(&call.arguments) {
+                Ok(mut args) => {
+                    self.unmask_json(&mut args);
+                    args.to_string()
+                }
+                Err(_) => self.unmask(&call.arguments),
+            };
+        }
+    }
+
+    fn unmask_json(&self, value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::String(s) => *s = self.unmask(s),
+            serde_json::Value::Array(items) => items.iter_mut().for_each(|v| self.unmask_json(v)),
+            serde_json::Value::Object(map) => map.values_mut().for_each(|v| self.unmask_json(v)),
+            _ => {}
+        }
+    }
+}
+
+fn luhn_valid(candidate: &str) -> bool {
+    let digits: Vec<u32> = candidate.chars().filter_map(|c| c.to_digit(10)).collect();
+    if !(13..=19).contains(&digits.len()) {
+        return false;
+    }
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| match i % 2 {
+            1 if d * 2 > 9 => d * 2 - 9,
+            1 => d * 2,
+            _ => d,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use os_llm::{Role, ToolCall};
+
+    #[test]
+    fn masks_consistently_and_restores_replies() {
+        let cfg = PiiConfig {
+            enabled: true,
+            patterns: vec![r"\bEMP-\d{5}\b".to_string()],
+            ..Default::default()
+        };
+        let masker = PiiMasker::new(&cfg).unwrap();
+        let mut vault = PiiVault::default();
+
+        let masked = masker.mask(
+            "Mail ana@example.com or call +1 415 555 0100 / (415) 555-0199. \
+             Card 4111 1111 1111 1111, order 1234567890123, id EMP-00042, ana@example.com again.",
+            &mut vault,
+        );
+        assert_eq!(
+            masked,
+            "Mail [EMAIL_1] or call [PHONE_1] / [PHONE_2]. \
+             Card [CARD_1], order 1234567890123, id [PII_1], [EMAIL_1] again."
+        );
+
+        let mut reply = ChatMessage {
+            role: Role::Assistant,
+            content: "Emailing [EMAIL_1] about [CARD_9].".to_string(),
+            tool_calls: vec![ToolCall {
+                id: "1".to_string(),
+                name: "email".to_string(),
+                arguments: r#"{"to":["[EMAIL_1]"],"body":"ref [PII_1]"}"#.to_string(),
+            }],
+            tool_call_id: None,
+            reasoning: vec![],
+        };
+        vault.unmask_message(&mut reply);
+        assert_eq!(reply.content, "Emailing ana@example.com about [CARD_9].");
+        assert_eq!(
+            reply.tool_calls[0].arguments,
+            r#"{"body":"ref EMP-00042","to":["ana@example.com"]}"#
+        );
+
+        let history = masker.mask_messages(&[reply], &mut vault);
+        assert_eq!(history[0].content, "Emailing [EMAIL_1] about [CARD_9].");
+        assert!(history[0].tool_calls[0].arguments.contains("[PII_1]"));
+    }
+}
@@ -0,0 +1,494 @@
+//! Progress updates for long assistant runs.
+//!
+//! The tool loop publishes [`ProgressEvent`]s on a per-run bus (a task-local sender the
+//! gateway installs around each run). While the run is in flight the gateway folds those
+//! events into a [`ProgressState`] and, once `progress.after_seconds` have passed, sends a
+//! short status line every `progress.interval_seconds` on channels that cannot stream.
+//! Channels that show activity inline (webchat) get each event as it happens instead, and
+//! channels that can edit messages (Telegram, Discord) get one status message, sent when
+//! the first tool starts and rewritten as tools run and finish.
+
+use crate::config::ProgressConfig;
+use crate::outbox::Outbox;
+use os_channels::{ChannelAdapter, OutboundMessage, RunActivity};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::{Instant, MissedTickBehavior};
+
+tokio::task_local! {
+    static PROGRESS: mpsc::UnboundedSender<ProgressEvent>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgressEvent {
+    /// Waiting on the model.
+    Thinking,
+    ToolStarted {
+        tool: String,
+        /// What the call works on, like a shell command or a URL; see [`call_detail`].
+        detail: Option<String>,
+    },
+    ToolFinished {
+        tool: String,
+        ok: bool,
+    },
+}
+
+impl ProgressEvent {
+    fn activity(&self) -> RunActivity {
+        match self {
+            Self::Thinking => RunActivity::Thinking,
+            Self::ToolStarted { tool, .. } => RunActivity::ToolStarted { tool: tool.clone() },
+            Self::ToolFinished { tool, .. } => RunActivity::ToolFinished { tool: tool.clone() },
+        }
+    }
+}
+
+/// Longest detail shown for a tool call.
+const DETAIL_MAX_CHARS: usize = 60;
+/// Telegram rejects more than about one edit per second in a chat.
+const STATUS_EDIT_INTERVAL: Duration = Duration::from_secs(1);
+/// Tool calls listed in a status message; earlier ones are counted.
+const STATUS_STEPS_MAX: usize = 10;
+
+/// A short description of what a call works on, from the first of its `command`, `url`,
+/// `query` or `path` arguments.
+pub fn call_detail(arguments: &serde_json::Value) -> Option<String> {
+    let value = ["command", "url", "query", "path"]
+        .iter()
+        .find_map(|key| arguments.get(key)?.as_str())?;
+    let line = value.lines().next().unwrap_or_default().trim();
+    if line.is_empty() {
+        return None;
+    }
+    let mut out: String = line.chars().take(DETAIL_MAX_CHARS).collect();
+    if out.len() < line.len() || value.trim().lines().nth(1).is_some() {
+        out.push('…');
+    }
+    Some(out)
+}
+
+/// Publish an event for the current run. A no-op outside a [`run_with_progress`] scope
+/// (background tasks, API-triggered runs).
+pub fn emit(event: ProgressEvent) {
+    let _ = PROGRESS.try_with(|tx| tx.send(event));
+}
+
+#[derive(Debug, Default)]
+pub struct ProgressState {
+    tools_run: usize,
+    current: Option<String>,
+}
+
+impl ProgressState {
+    pub fn apply(&mut self, event: ProgressEvent) {
+        match event {
+            ProgressEvent::Thinking => self.current = Some("thinking".to_string()),
+            ProgressEvent::ToolStarted { tool, .. } => self.current = Some(describe_tool(&tool)),
+            ProgressEvent::ToolFinished { .. } => {
+                self.tools_run += 1;
+                self.current = None;
+            }
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        let mut out = format!(
+            "Still working: ran {} tool{}",
+            self.tools_run,
+            if self.tools_run == 1 { "" } else { "s" }
+        );
+        if let Some(current) = self.current.as_deref() {
+            out.push_str(&format!(", currently {current}"));
+        }
+        out.push('…');
+        out
+    }
+}
+
+/// The status message for a run on a channel that can edit messages.
+struct StatusMessage {
+    adapter: Arc<dyn ChannelAdapter>,
+    message_id: Option<String>,
+    /// Each tool call's line, and whether it succeeded once finished.
+    steps: Vec<(String, Option<bool>)>,
+    /// Changed since last sent.
+    dirty: bool,
+}
+
+impl StatusMessage {
+    fn new(adapter: Arc<dyn ChannelAdapter>) -> Self {
+        Self {
+            adapter,
+            message_id: None,
+            steps: Vec::new(),
+            dirty: false,
+        }
+    }
+
+    fn apply(&mut self, event: &ProgressEvent) {
+        match event {
+            ProgressEvent::Thinking => {}
+            ProgressEvent::ToolStarted { tool, detail } => {
+                let line = match detail {
+                    Some(detail) => format!("🔧 {tool}: {detail}"),
+                    None => format!("🔧 {tool}"),
+                };
+                self.steps.push((line, None));
+                self.dirty = true;
+            }
+            ProgressEvent::ToolFinished { ok, .. } => {
+                if let Some(step) = self.steps.iter_mut().rev().find(|(_, done)| done.is_none()) {
+                    step.1 = Some(*ok);
+                    self.dirty = true;
+                }
+            }
+        }
+    }
+
+    fn render(&self) -> String {
+        let skipped = self.steps.len().saturating_sub(STATUS_STEPS_MAX);
+        let mut lines = Vec::new();
+        if skipped > 0 {
+            lines.push(format!("({skipped} earlier)"));
+        }
+        for (line, done) in &self.steps[skipped..] {
+            lines.push(match done {
+                None => format!("{line}…"),
+                Some(true) => format!("{line}… done ✅"),
+                Some(false) => format!("{line}… failed ❌"),
+            });
+        }
+        lines.join("\n")
+    }
+
+    /// Send or rewrite the message if anything changed.
+    async fn flush(&mut self, recipient: &str) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+        let text = self.render();
+        let result = match self.message_id.as_deref() {
+            Some(id) => self.adapter.edit_status(recipient, id, &text).await,
+            None => self
+                .adapter
+                .send_status(recipient, &text)
+                .await
+                .map(|id| self.message_id = Some(id)),
+        };
+        if let Err(e) = result {
+            tracing::debug!(%e, "status message update failed");
+        }
+    }
+}
+
+fn describe_tool(name: &str) -> String {
+    match name {
+        "shell.execute" => "executing shell command".to_string(),
+        "browser" => "browsing".to_string(),
+        "filesystem" => "working with files".to_string(),
+        "conversation_search" => "searching past conversations".to_string(),
+        "search_everywhere" => "searching your messages".to_string(),
+        other => format!("running {other}"),
+    }
+}
+
+/// Drive `run` to completion, sending periodic progress lines to `recipient` while it
+/// takes longer than configured, each event as it happens where the channel shows them,
+/// or a status message kept up to date where the channel can edit one.
+pub async fn run_with_progress<F: Future>(
+    cfg: &ProgressConfig,
+    outbox: &Outbox,
+    channel_id: &str,
+    recipient: &str,
+    run: F,
+) -> F::Output {
+    let adapter = outbox.channel(channel_id);
+    let live = adapter.filter(|c| c.supports_activity()).cloned();
+    let editable = adapter
+        .filter(|c| live.is_none() && cfg.status_message && c.supports_edits())
+        .cloned();
+    if !cfg.enabled && live.is_none() && editable.is_none() {
+        return run.await;
+    }
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let run = PROGRESS.scope(tx, run);
+    tokio::pin!(run);
+
+    let first = Instant::now() + Duration::from_secs(cfg.after_seconds);
+    // Unused with `live`, where the interval may be 0 if progress lines are off.
+    let period = Duration::from_secs(cfg.interval_seconds.max(1));
+    let mut ticker = tokio::time::interval_at(first, period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut edits = tokio::time::interval(STATUS_EDIT_INTERVAL);
+    edits.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut state = ProgressState::default();
+    let mut status = editable.map(StatusMessage::new);
+
+    loop {
+        tokio::select! {
+            biased;
+            out = &mut run => {
+                // The last tool may have finished just before the run did.
+                while let Ok(event) = rx.try_recv() {
+                    show(live.as_ref(), recipient, &event).await;
+                    if let Some(status) = status.as_mut() {
+                        status.apply(&event);
+                    }
+                }
+                if let Some(status) = status.as_mut() {
+                    status.flush(recipient).await;
+                }
+                return out;
+            }
+            Some(event) = rx.recv() => {
+                show(live.as_ref(), recipient, &event).await;
+                if let Some(status) = status.as_mut() {
+                    status.apply(&event);
+                }
+                state.apply(event);
+            }
+            _ = edits.tick(), if status.as_ref().is_some_and(|s| s.dirty) => {
+                if let Some(status) = status.as_mut() {
+                    status.flush(recipient).await;
+                }
+            }
+            // The status message replaces these once it's up.
+            _ = ticker.tick(), if cfg.enabled
+                && live.is_none()
+                && status.as_ref().is_none_or(|s| s.steps.is_empty()) =>
+            {
+                let msg = OutboundMessage {
+                    content: state.summary(),
+                    reply_to_message_id: None,
+                    attachments: vec![],
+                };
+                outbox.enqueue(channel_id, recipient, msg);
+            }
+        }
+    }
+}
+
+async fn show(live: Option<&Arc<dyn ChannelAdapter>>, recipient: &str, event: &ProgressEvent) {
+    let Some(adapter) = live else {
+        return;
+    };
+    if let Err(e) = adapter.send_activity(recipient, event.activity()).await {
+        tracing::debug!(%e, "activity update failed");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use os_channels::InboundMessage;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Live {
+        shown: Mutex<Vec<RunActivity>>,
+    }
+
+    #[async_trait]
+    impl ChannelAdapter for Live {
+        fn channel_id(&self) -> &str {
+            "webchat"
+        }
+
+        async fn start(&self, _tx: mpsc::Sender<InboundMessage>) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        async fn send(&self, _recipient_id: &str, _message: OutboundMessage) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        async fn send_activity(
+            &self,
+            _recipient_id: &str,
+            activity: RunActivity,
+        ) -> anyhow::Result<()> {
+            self.shown.lock().unwrap().push(activity);
+            Ok(())
+        }
+
+        fn supports_activity(&self) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn activity_channels_see_every_step_even_with_progress_lines_off() {
+        let live = Arc::new(Live::default());
+        let mut channels: HashMap<String, Arc<dyn ChannelAdapter>> = HashMap::new();
+        channels.insert("webchat".to_string(), live.clone());
+        let outbox = Outbox::new(channels);
+        let cfg = ProgressConfig {
+            enabled: false,
+            interval_seconds: 0,
+            ..ProgressConfig::default()
+        };
+
+        let out = run_with_progress(&cfg, &outbox, "webchat", "u1", async {
+            emit(ProgressEvent::ToolStarted {
+                tool: "browser".to_string(),
+                detail: None,
+            });
+            tokio::task::yield_now().await;
+            emit(ProgressEvent::ToolFinished {
+                tool: "browser".to_string(),
+                ok: true,
+            });
+            7
+        })
+        .await;
+        assert_eq!(out, 7);
+        assert_eq!(
+            *live.shown.lock().unwrap(),
+            vec![
+                RunActivity::ToolStarted {
+                    tool: "browser".to_string()
+                },
+                RunActivity::ToolFinished {
+                    tool: "browser".to_string()
+                },
+            ]
+        );
+    }
+
+    #[derive(Default)]
+    struct Editable {
+        sent: Mutex<Vec<String>>,
+        edits: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl ChannelAdapter for Editable {
+        fn channel_id(&self) -> &str {
+            "telegram"
+        }
+
+        async fn start(&self, _tx: mpsc::Sender<InboundMessage>) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        async fn send(&self, _recipient_id: &str, _message: OutboundMessage) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        async fn send_status(&self, _recipient_id: &str, text: &str) -> anyhow::Result<String> {
+            self.sent.lock().unwrap().push(text.to_string());
+            Ok("1".to_string())
+        }
+
+        async fn edit_status(
+            &self,
+            _recipient_id: &str,
+            message_id: &str,
+            text: &str,
+        ) -> anyhow::Result<()> {
+            assert_eq!(message_id, "1");
+            self.edits.lock().unwrap().push(text.to_string());
+            Ok(())
+        }
+
+        fn supports_edits(&self) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn editable_channels_get_one_status_message() {
+        let chat = Arc::new(Editable::default());
+        let mut channels: HashMap<String, Arc<dyn ChannelAdapter>> = HashMap::new();
+        channels.insert("telegram".to_string(), chat.clone());
+        let outbox = Outbox::new(channels);
+
+        run_with_progress(
+            &ProgressConfig::default(),
+            &outbox,
+            "telegram",
+            "42",
+            async {
+                emit(ProgressEvent::ToolStarted {
+                    tool: "shell.execute".to_string(),
+                    detail: call_detail(&serde_json::json!({ "command": "cargo test" })),
+                });
+                // Long enough for the first update, well short of the next edit.
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                emit(ProgressEvent::ToolFinished {
+                    tool: "shell.execute".to_string(),
+                    ok: true,
+                });
+                emit(ProgressEvent::ToolStarted {
+                    tool: "browser".to_string(),
+                    detail: None,
+                });
+                emit(ProgressEvent::ToolFinished {
+                    tool: "browser".to_string(),
+                    ok: false,
+                });
+            },
+        )
+        .await;
+
+        assert_eq!(
+            *chat.sent.lock().unwrap(),
+            vec!["🔧 shell.execute: cargo test…".to_string()]
+        );
+        assert_eq!(
+            *chat.edits.lock().unwrap(),
+            vec!["🔧 shell.execute: cargo test… done ✅\n🔧 browser… failed ❌".to_string()]
+        );
+    }
+
+    #[test]
+    fn call_details_are_one_short_line() {
+        let long = "x".repeat(80);
+        assert_eq!(
+            call_detail(&serde_json::json!({ "url": "https://example.com" })).as_deref(),
+            Some("https://example.com")
+        );
+        assert_eq!(
+            call_detail(&serde_json::json!({ "command": "cd app\nmake" })).as_deref(),
+            Some("cd app…")
+        );
+        assert_eq!(
+            call_detail(&serde_json::json!({ "command": long }))
+                .unwrap()
+                .chars()
+                .count(),
+            DETAIL_MAX_CHARS + 1
+        );
+        assert_eq!(call_detail(&serde_json::json!({ "action": "list" })), None);
+    }
+
+    #[test]
+    fn summary_reports_tool_count_and_current_step() {
+        let mut state = ProgressState::default();
+        assert_eq!(state.summary(), "Still working: ran 0 tools…");
+
+        state.apply(ProgressEvent::ToolStarted {
+            tool: "browser".to_string(),
+            detail: None,
+        });
+        state.apply(ProgressEvent::ToolFinished {
+            tool: "browser".to_string(),
+            ok: true,
+        });
+        state.apply(ProgressEvent::ToolStarted {
+            tool: "shell.execute".to_string(),
+            detail: None,
+        });
+        assert_eq!(
+            state.summary(),
+            "Still working: ran 1 tool, currently executing shell command…"
+        );
+    }
+}
@@ -0,0 +1,110 @@
+//! Outbound secret masking.
+//!
+//! The outbox runs every message through a `Redactor` before it reaches a channel, so a
+//! key the assistant read from a file or a tool echoed back never lands in a chat log.
+//! Secrets from the config file are matched literally; patterns catch the rest.
+
+use crate::config::{OpenShellConfig, RedactionConfig};
+use anyhow::Result;
+use regex::{Captures, Regex};
+
+/// Common key formats and `password = ...`-style assignments.
+const BUILTIN_PATTERNS: &[&str] = &[
+    r"\bsk-(?:ant-|proj-)?[A-Za-z0-9_\-]{20,}",
+    r"\bgh[pousr]_[A-Za-z0-9]{36,}\b",
+    r"\bgithub_pat_[A-Za-z0-9_]{40,}\b",
+    r"\bxox[abprs]-[A-Za-z0-9\-]{10,}",
+    r"\bAKIA[0-9A-Z]{16}\b",
+    r"\b\d{8,10}:AA[A-Za-z0-9_\-]{33}\b",
+    r"-----BEGIN [A-Z ]*PRIVATE KEY-----[\s\S]*?-----END [A-Z ]*PRIVATE KEY-----",
+    r#"(?i)(?:password|passwd|pwd|secret|api[_-]?key|access[_-]?token|auth[_-]?token)\b["']?\s*[:=]\s*["']?(?P<secret>[^\s"',;]{6,})"#,
+];
+
+pub struct Redactor {
+    values: Vec<String>,
+    patterns: Vec<Regex>,
+    mask: String,
+}
+
+impl Redactor {
+    pub fn new(cfg: &OpenShellConfig) -> Result<Self> {
+        Self::from_parts(&cfg.redaction, cfg.secret_values())
+    }
+
+    fn from_parts(cfg: &RedactionConfig, values: Vec<String>) -> Result<Self> {
+        let builtin = BUILTIN_PATTERNS
+            .iter()
+            .filter(|_| cfg.builtin_patterns)
+            .map(|p| p.to_string());
+        let patterns = builtin
+            .chain(cfg.patterns.iter().cloned())
+            .map(|p| Regex::new(&p))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            // Longest first, so a secret containing another is masked whole.
+            values: {
+                let mut values = values;
+                values.sort_by_key(|v| std::cmp::Reverse(v.len()));
+                values
+            },
+            patterns,
+            mask: cfg.mask.clone(),
+        })
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        let mut out = text.to_string();
+        for value in &self.values {
+            if out.contains(value.as_str()) {
+                out = out.replace(value.as_str(), &self.mask);
+            }
+        }
+        for pattern in &self.patterns {
+            if !pattern.is_match(&out) {
+                continue;
+            }
+            out = pattern
+                .replace_all(&out, |caps: &Captures| match caps.name("secret") {
+                    Some(secret) => {
+                        let whole = caps.get(0).expect("group 0 always matches");
+                        let start = secret.start() - whole.start();
+                        let end = secret.end() - whole.start();
+                        let text = whole.as_str();
+                        format!("{}{}{}", &text[..start], self.mask, &text[end..])
+                    }
+                    None => self.mask.clone(),
+                })
+                .into_owned();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masks_config_secrets_key_formats_and_assignments() {
+        let cfg = RedactionConfig {
+            patterns: vec![r"\bACME-\d{6}\b".to_string()],
+            ..Default::default()
+        };
+        let redactor = Redactor::from_parts(&cfg, vec!["hunter2hunter2".to_string()]).unwrap();
+
+        let text = "token hunter2hunter2, key sk-ant-REDACTED, \
+                    DB_PASSWORD=\"s3cr3t-value\" and ACME-123456. Password reset done.";
+        assert_eq!(
+            redactor.redact(text),
+            "token [redacted], key [redacted], DB_PASSWORD=\"[redacted]\" and [redacted]. \
+             Password reset done."
+        );
+
+        let off = RedactionConfig {
+            builtin_patterns: false,
+            ..Default::default()
+        };
+        let redactor = Redactor::from_parts(&off, Vec::new()).unwrap();
+        assert_eq!(redactor.redact("password: abcdefgh"), "password: abcdefgh");
+    }
+}
@@ -0,0 +1,185 @@
+use crate::grants::GrantScope;
+use crate::server::OsState;
+use axum::extract::Path;
+use axum::routing::{get, post};
+use axum::{Extension, Json};
+use horizons_core::core_agents::traits::CoreAgents as _;
+use horizons_core::models::AgentIdentity;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+struct RememberRequest {
+    /// `session`, or a duration like `1h`.
+    #[serde(rename = "for")]
+    scope: String,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Decision {
+    Approve,
+    Deny,
+}
+
+#[derive(Debug, Deserialize)]
+struct DecisionRequest {
+    decision: Decision,
+    /// With an approval, also allow the same call again for this long; see `grants`.
+    #[serde(default)]
+    remember: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DecideBatchRequest {
+    /// Applies to every action without its own entry in `decisions`.
+    #[serde(default)]
+    decision: Option<Decision>,
+    #[serde(default)]
+    decisions: HashMap<Uuid, Decision>,
+}
+
+pub fn router() -> axum::Router {
+    axum::Router::new()
+        .route("/api/v1/os/approvals", get(list_batches))
+        .route(
+            "/api/v1/os/approvals/batches/{batch_id}",
+            post(decide_batch),
+        )
+        .route(
+            "/api/v1/os/approvals/{action_id}/decision",
+            post(decide_action),
+        )
+        .route(
+            "/api/v1/os/approvals/{action_id}/remember",
+            post(remember_approval),
+        )
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+async fn list_batches(Extension(state): Extension<Arc<OsState>>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "batches": state.approval_batches.list() }))
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn decide_action(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(action_id): Path<Uuid>,
+    Json(req): Json<DecisionRequest>,
+) -> Json<serde_json::Value> {
+    if !state.approval_batches.contains_action(action_id) {
+        return Json(serde_json::json!({ "status": "not_found" }));
+    }
+    if let (Decision::Approve, Some(remember)) = (req.decision, req.remember.as_deref()) {
+        match GrantScope::parse(remember) {
+            Ok(scope) => {
+                state.grant_offers.remember(action_id, scope);
+            }
+            Err(e) => return Json(serde_json::json!({ "status": "error", "error": e })),
+        }
+    }
+    match decide(&state, action_id, req.decision).await {
+        Ok(done) => Json(serde_json::json!({ "status": "ok", "result": done })),
+        Err(e) => Json(serde_json::json!({ "status": "error", "error": e })),
+    }
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn decide_batch(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(batch_id): Path<Uuid>,
+    Json(req): Json<DecideBatchRequest>,
+) -> Json<serde_json::Value> {
+    let Some(batch) = state.approval_batches.get(batch_id) else {
+        return Json(serde_json::json!({ "status": "not_found" }));
+    };
+    if let Some(id) = req
+        .decisions
+        .keys()
+        .find(|id| !batch.actions.iter().any(|a| a.action_id == **id))
+    {
+        return Json(serde_json::json!({
+            "status": "error",
+            "error": format!("action {id} is not in this batch"),
+        }));
+    }
+
+    let mut results = serde_json::Map::new();
+    for action in &batch.actions {
+        let Some(decision) = req
+            .decisions
+            .get(&action.action_id)
+            .or(req.decision.as_ref())
+        else {
+            continue;
+        };
+        let outcome = match decide(&state, action.action_id, *decision).await {
+            Ok(done) => serde_json::json!(done),
+            Err(e) => serde_json::json!({ "error": e }),
+        };
+        results.insert(action.action_id.to_string(), outcome);
+    }
+    Json(serde_json::json!({ "status": "ok", "results": results }))
+}
+
+async fn decide(
+    state: &OsState,
+    action_id: Uuid,
+    decision: Decision,
+) -> Result<&'static str, String> {
+    let identity = AgentIdentity::System {
+        name: "openshell".to_string(),
+    };
+    let res = match decision {
+        Decision::Approve => {
+            state
+                .core_agents
+                .approve(
+                    state.org_id,
+                    state.project_id,
+                    &state.project_db_handle,
+                    action_id,
+                    &identity,
+                    "approved via the approvals API",
+                )
+                .await
+        }
+        Decision::Deny => {
+            state
+                .core_agents
+                .deny(
+                    state.org_id,
+                    state.project_id,
+                    &state.project_db_handle,
+                    action_id,
+                    &identity,
+                    "denied via the approvals API",
+                )
+                .await
+        }
+    };
+    res.map(|()| match decision {
+        Decision::Approve => "approved",
+        Decision::Deny => "denied",
+    })
+    .map_err(|e| e.to_string())
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn remember_approval(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(action_id): Path<Uuid>,
+    Json(req): Json<RememberRequest>,
+) -> Json<serde_json::Value> {
+    let scope = match GrantScope::parse(&req.scope) {
+        Ok(scope) => scope,
+        Err(e) => return Json(serde_json::json!({ "status": "error", "error": e })),
+    };
+    if state.grant_offers.remember(action_id, scope) {
+        Json(serde_json::json!({ "status": "ok" }))
+    } else {
+        Json(serde_json::json!({ "status": "not_found" }))
+    }
+}
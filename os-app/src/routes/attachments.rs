@@ -0,0 +1,111 @@
+use crate::server::OsState;
+use axum::extract::{Path, Query};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Extension, Json};
+use serde::Deserialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+struct ListQuery {
+    #[serde(default = "default_limit")]
+    limit: usize,
+}
+
+fn default_limit() -> usize {
+    100
+}
+
+pub fn router() -> axum::Router {
+    axum::Router::new()
+        .route("/api/v1/os/attachments", get(list_attachments))
+        .route(
+            "/api/v1/os/attachments/{id}",
+            get(get_attachment).delete(delete_attachment),
+        )
+        .route("/api/v1/os/attachments/{id}/content", get(get_content))
+}
+
+fn disabled() -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "status": "error", "error": "attachments are disabled" }))
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+async fn list_attachments(
+    Extension(state): Extension<Arc<OsState>>,
+    Query(q): Query<ListQuery>,
+) -> Json<serde_json::Value> {
+    let Some(store) = state.attachments.as_ref() else {
+        return disabled();
+    };
+    let result = store
+        .list(q.limit.min(1000))
+        .and_then(|list| Ok((list, store.stats()?)));
+    match result {
+        Ok((attachments, stats)) => Json(serde_json::json!({
+            "status": "ok",
+            "attachments": attachments,
+            "stats": stats,
+        })),
+        Err(e) => Json(serde_json::json!({ "status": "error", "error": e.to_string() })),
+    }
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+async fn get_attachment(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(id): Path<String>,
+) -> Json<serde_json::Value> {
+    let Some(store) = state.attachments.as_ref() else {
+        return disabled();
+    };
+    let Ok(id) = Uuid::parse_str(&id) else {
+        return Json(serde_json::json!({ "status": "error", "error": "invalid id" }));
+    };
+    match store.get(id) {
+        Ok(Some(attachment)) => {
+            Json(serde_json::json!({ "status": "ok", "attachment": attachment }))
+        }
+        Ok(None) => Json(serde_json::json!({ "status": "not_found" })),
+        Err(e) => Json(serde_json::json!({ "status": "error", "error": e.to_string() })),
+    }
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+async fn get_content(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(id): Path<String>,
+) -> Response {
+    let Some(store) = state.attachments.as_ref() else {
+        return (StatusCode::NOT_FOUND, "attachments are disabled").into_response();
+    };
+    let Ok(id) = Uuid::parse_str(&id) else {
+        return (StatusCode::BAD_REQUEST, "invalid id").into_response();
+    };
+    match store.read(id).await {
+        Ok(Some((attachment, bytes))) => {
+            ([(header::CONTENT_TYPE, attachment.content_type)], bytes).into_response()
+        }
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn delete_attachment(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(id): Path<String>,
+) -> Json<serde_json::Value> {
+    let Some(store) = state.attachments.as_ref() else {
+        return disabled();
+    };
+    let Ok(id) = Uuid::parse_str(&id) else {
+        return Json(serde_json::json!({ "status": "error", "error": "invalid id" }));
+    };
+    match store.release(id).await {
+        Ok(ok) => Json(serde_json::json!({ "status": if ok { "ok" } else { "not_found" } })),
+        Err(e) => Json(serde_json::json!({ "status": "error", "error": e.to_string() })),
+    }
+}
@@ -0,0 +1,13 @@
+use crate::server::OsState;
+use axum::routing::get;
+use axum::{Extension, Json};
+use std::sync::Arc;
+
+pub fn router() -> axum::Router {
+    axum::Router::new().route("/api/v1/os/audit", get(list_audit_events))
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+async fn list_audit_events(Extension(state): Extension<Arc<OsState>>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "events": state.audit.recent() }))
+}
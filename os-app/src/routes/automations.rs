@@ -0,0 +1,106 @@
+use crate::automation::AutomationRule;
+use crate::server::OsState;
+use axum::extract::Path;
+use axum::routing::{get, post};
+use axum::{Extension, Json};
+use std::sync::Arc;
+
+pub fn router() -> axum::Router {
+    axum::Router::new()
+        .route("/api/v1/os/automations", get(list_rules).post(create_rule))
+        .route(
+            "/api/v1/os/automations/{id}",
+            get(get_rule).put(replace_rule).delete(remove_rule),
+        )
+        .route("/api/v1/os/automations/{id}/trigger", post(trigger_rule))
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+async fn list_rules(Extension(state): Extension<Arc<OsState>>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "status": "ok",
+        "enabled": state.cfg.automation.enabled,
+        "rules": state.automation.list(),
+        "last_errors": state.automation.last_errors(),
+    }))
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+async fn get_rule(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(id): Path<String>,
+) -> Json<serde_json::Value> {
+    match state.automation.get(&id) {
+        Some(rule) => Json(serde_json::json!({
+            "status": "ok",
+            "last_error": state.automation.last_error(&rule.id),
+            "rule": rule,
+        })),
+        None => Json(serde_json::json!({ "status": "not_found" })),
+    }
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn create_rule(
+    Extension(state): Extension<Arc<OsState>>,
+    Json(rule): Json<AutomationRule>,
+) -> Json<serde_json::Value> {
+    if state.automation.get(&rule.id).is_some() {
+        return Json(serde_json::json!({
+            "status": "error",
+            "error": format!("rule {} already exists", rule.id),
+        }));
+    }
+    save(&state, rule).await
+}
+
+/// Replace the rule at `id`; the body's id, if any, is ignored.
+#[tracing::instrument(level = "info", skip_all)]
+async fn replace_rule(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(id): Path<String>,
+    Json(mut rule): Json<AutomationRule>,
+) -> Json<serde_json::Value> {
+    if state.automation.get(&id).is_none() {
+        return Json(serde_json::json!({ "status": "not_found" }));
+    }
+    rule.id = id;
+    save(&state, rule).await
+}
+
+async fn save(state: &OsState, rule: AutomationRule) -> Json<serde_json::Value> {
+    match state.automation.save(rule.clone()).await {
+        Ok(_) => Json(serde_json::json!({ "status": "ok", "rule": rule })),
+        Err(e) => Json(serde_json::json!({ "status": "error", "error": e.to_string() })),
+    }
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn remove_rule(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(id): Path<String>,
+) -> Json<serde_json::Value> {
+    match state.automation.remove(&id).await {
+        Ok(true) => Json(serde_json::json!({ "status": "ok" })),
+        Ok(false) => Json(serde_json::json!({ "status": "not_found" })),
+        Err(e) => Json(serde_json::json!({ "status": "error", "error": e.to_string() })),
+    }
+}
+
+/// Fire a webhook rule. Its templates see the JSON body as `payload`, and its conditions
+/// see the body's `content` (or the whole body, if it has none).
+#[tracing::instrument(level = "info", skip_all)]
+async fn trigger_rule(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(id): Path<String>,
+    body: Option<Json<serde_json::Value>>,
+) -> Json<serde_json::Value> {
+    let payload = body.map(|Json(v)| v).unwrap_or_default();
+    match state.automation.trigger(&id, payload).await {
+        Ok(true) => Json(serde_json::json!({ "status": "ok" })),
+        Ok(false) => {
+            Json(serde_json::json!({ "status": "skipped", "reason": "conditions not met" }))
+        }
+        Err(e) => Json(serde_json::json!({ "status": "error", "error": e.to_string() })),
+    }
+}
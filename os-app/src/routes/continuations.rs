@@ -0,0 +1,29 @@
+use crate::server::OsState;
+use axum::extract::Path;
+use axum::routing::{delete, get};
+use axum::{Extension, Json};
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub fn router() -> axum::Router {
+    axum::Router::new()
+        .route("/api/v1/os/continuations", get(list_continuations))
+        .route("/api/v1/os/continuations/{id}", delete(cancel_continuation))
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+async fn list_continuations(Extension(state): Extension<Arc<OsState>>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "continuations": state.continuations.list() }))
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn cancel_continuation(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(id): Path<String>,
+) -> Json<serde_json::Value> {
+    let Ok(id) = Uuid::parse_str(&id) else {
+        return Json(serde_json::json!({ "status": "error", "error": "invalid id" }));
+    };
+    let ok = state.continuations.cancel(id);
+    Json(serde_json::json!({ "status": if ok { "ok" } else { "not_found" } }))
+}
@@ -0,0 +1,137 @@
+use crate::server::OsState;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Extension, Json};
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+
+const INDEX_HTML: &str = include_str!("../../assets/admin/index.html");
+const ADMIN_JS: &str = include_str!("../../assets/admin/admin.js");
+const ADMIN_CSS: &str = include_str!("../../assets/admin/admin.css");
+/// Messages shown per conversation, and how much of each.
+const RECENT_MESSAGES: usize = 3;
+const PREVIEW_CHARS: usize = 280;
+
+/// The page and its assets. They hold no data, so they're served without the API token;
+/// the page asks for it when `/api/v1/os/dashboard` answers 401.
+pub fn assets() -> axum::Router {
+    axum::Router::new()
+        .route("/admin", get(index))
+        .route("/admin/admin.js", get(script))
+        .route("/admin/admin.css", get(style))
+}
+
+pub fn router() -> axum::Router {
+    axum::Router::new().route("/api/v1/os/dashboard", get(get_dashboard))
+}
+
+fn asset(state: &OsState, content_type: &'static str, body: &'static str) -> Response {
+    if !state.cfg.control.dashboard {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    ([(header::CONTENT_TYPE, content_type)], body).into_response()
+}
+
+async fn index(Extension(state): Extension<Arc<OsState>>) -> Response {
+    asset(&state, "text/html; charset=utf-8", INDEX_HTML)
+}
+
+async fn script(Extension(state): Extension<Arc<OsState>>) -> Response {
+    asset(&state, "text/javascript; charset=utf-8", ADMIN_JS)
+}
+
+async fn style(Extension(state): Extension<Arc<OsState>>) -> Response {
+    asset(&state, "text/css; charset=utf-8", ADMIN_CSS)
+}
+
+/// Everything the dashboard shows, in one call: sessions with their last messages and
+/// token use, pending approvals, metrics and a summary of the config.
+#[tracing::instrument(level = "debug", skip_all)]
+async fn get_dashboard(Extension(state): Extension<Arc<OsState>>) -> Json<serde_json::Value> {
+    let sessions: Vec<serde_json::Value> = state
+        .sessions
+        .list()
+        .into_iter()
+        .map(|s| {
+            let recent: Vec<serde_json::Value> = state
+                .sessions
+                .recent_messages(
+                    &s.channel_id,
+                    &s.sender_id,
+                    DateTime::<Utc>::MIN_UTC,
+                    RECENT_MESSAGES,
+                )
+                .into_iter()
+                .map(|m| {
+                    serde_json::json!({
+                        "role": m.role,
+                        "content": preview(&m.content),
+                    })
+                })
+                .collect();
+            let mut s = serde_json::json!(s);
+            s["recent"] = serde_json::json!(recent);
+            s
+        })
+        .collect();
+    let metrics = state
+        .metrics
+        .snapshot(state.suggestions.list().len(), &state.data_dir)
+        .await;
+    Json(serde_json::json!({
+        "status": "ok",
+        "sessions": sessions,
+        "approvals": state.approval_batches.list(),
+        "metrics": metrics,
+        "config": config_summary(&state),
+    }))
+}
+
+fn preview(content: &str) -> String {
+    match content.char_indices().nth(PREVIEW_CHARS) {
+        Some((end, _)) => format!("{}…", &content[..end]),
+        None => content.to_string(),
+    }
+}
+
+/// What's running and how it's guarded; no credentials.
+fn config_summary(state: &OsState) -> serde_json::Value {
+    let cfg = &state.cfg;
+    let tools = &cfg.tools;
+    let enabled_tools: Vec<&str> = [
+        ("shell", tools.shell),
+        ("browser", tools.browser),
+        ("filesystem", tools.filesystem.enabled),
+        ("clipboard", tools.clipboard),
+        ("apple.reminders", tools.apple_reminders),
+        ("apple.notes", tools.apple_notes),
+        ("code.run", tools.code_run.enabled),
+        ("todoist", tools.todoist.enabled),
+    ]
+    .into_iter()
+    .filter_map(|(name, on)| on.then_some(name))
+    .collect();
+    let mut channels: Vec<&String> = state.channels.keys().collect();
+    channels.sort();
+    let mut personas: Vec<&String> = cfg.personas.keys().collect();
+    personas.sort();
+    serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "model": cfg.general.model,
+        "channels": channels,
+        "personas": personas,
+        "tools": enabled_tools,
+        "approvals": {
+            "shell": cfg.security.shell_approval,
+            "browser": cfg.security.browser_approval,
+            "filesystem_write": cfg.security.filesystem_write_approval,
+            "rules": cfg.security.rules.len(),
+        },
+        "allow_all_senders": cfg.security.allow_all_senders,
+        "admin": cfg.admin.deliver_to,
+        "webhooks": cfg.webhooks.endpoints.len(),
+        "api_token": cfg.control.api_token.is_some(),
+        "data_dir": state.data_dir,
+    })
+}
@@ -0,0 +1,47 @@
+use crate::server::OsState;
+use axum::extract::Query;
+use axum::routing::get;
+use axum::{Extension, Json};
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+struct FeedbackQuery {
+    /// Days of per-channel satisfaction to include.
+    #[serde(default = "default_days")]
+    days: u32,
+    /// Latest reactions to list.
+    #[serde(default = "default_limit")]
+    limit: usize,
+}
+
+fn default_days() -> u32 {
+    30
+}
+
+fn default_limit() -> usize {
+    50
+}
+
+pub fn router() -> axum::Router {
+    axum::Router::new().route("/api/v1/os/feedback", get(get_feedback))
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+async fn get_feedback(
+    Extension(state): Extension<Arc<OsState>>,
+    Query(q): Query<FeedbackQuery>,
+) -> Json<serde_json::Value> {
+    let result = state
+        .feedback
+        .satisfaction(q.days.clamp(1, 365))
+        .and_then(|channels| Ok((channels, state.feedback.recent(q.limit.min(500))?)));
+    match result {
+        Ok((channels, recent)) => Json(serde_json::json!({
+            "status": "ok",
+            "channels": channels,
+            "recent": recent,
+        })),
+        Err(e) => Json(serde_json::json!({ "status": "error", "error": e.to_string() })),
+    }
+}
@@ -0,0 +1,23 @@
+use crate::server::OsState;
+use axum::routing::get;
+use axum::{Extension, Json};
+use std::sync::Arc;
+
+pub fn router() -> axum::Router {
+    axum::Router::new().route("/api/v1/os/focus", get(get_focus).delete(end_focus))
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+async fn get_focus(Extension(state): Extension<Arc<OsState>>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "focus": state.focus.status() }))
+}
+
+/// End the running focus session early, as `/focus off` would.
+#[tracing::instrument(level = "info", skip_all)]
+async fn end_focus(Extension(state): Extension<Arc<OsState>>) -> Json<serde_json::Value> {
+    if state.focus.end().await {
+        Json(serde_json::json!({ "status": "ok" }))
+    } else {
+        Json(serde_json::json!({ "status": "not_found" }))
+    }
+}
@@ -1,11 +1,60 @@
+use crate::health::Readiness;
+use crate::server::OsState;
+use axum::http::StatusCode;
 use axum::routing::get;
-use axum::Json;
+use axum::{Extension, Json};
+use std::sync::Arc;
 
 pub fn router() -> axum::Router {
-    axum::Router::new().route("/api/v1/os/health", get(get_health))
+    axum::Router::new()
+        .route("/api/v1/os/health", get(get_health))
+        .route("/healthz", get(get_liveness))
+        .route("/readyz", get(get_readiness))
 }
 
 #[tracing::instrument(level = "debug", skip_all)]
-async fn get_health() -> Json<serde_json::Value> {
-    Json(serde_json::json!({ "status": "ok" }))
+async fn get_health(Extension(state): Extension<Arc<OsState>>) -> Json<serde_json::Value> {
+    let integrity = state.integrity.latest();
+    let status = match integrity.as_ref() {
+        Some(r) if !r.is_ok() => "degraded",
+        _ => "ok",
+    };
+    Json(serde_json::json!({
+        "status": status,
+        "integrity": integrity,
+        "code_presets": state.code_presets,
+    }))
+}
+
+/// Liveness: answers as long as the server does.
+#[tracing::instrument(level = "trace", skip_all)]
+async fn get_liveness(Extension(state): Extension<Arc<OsState>>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "status": "ok",
+        "uptime_seconds": state.metrics.uptime_seconds(),
+    }))
+}
+
+/// Readiness: 503 while a channel adapter has crashed, the LLM is cooling down or the
+/// data dir isn't writable, with the detail either way.
+#[tracing::instrument(level = "trace", skip_all)]
+async fn get_readiness(
+    Extension(state): Extension<Arc<OsState>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let readiness = Readiness::check(
+        &state.channels,
+        &state.outbox,
+        &state.metrics,
+        &state.data_dir,
+    )
+    .await;
+    let (code, status) = if readiness.ready {
+        (StatusCode::OK, "ok")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+    };
+    (
+        code,
+        Json(serde_json::json!({ "status": status, "readiness": readiness })),
+    )
 }
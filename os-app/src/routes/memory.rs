@@ -0,0 +1,154 @@
+use crate::server::OsState;
+use axum::extract::{Path, Query};
+use axum::routing::{get, post};
+use axum::{Extension, Json};
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+struct ListFactsQuery {
+    /// `channel:sender` or an `identities.people` name; all facts when absent.
+    #[serde(default)]
+    subject: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateFactRequest {
+    subject: String,
+    text: String,
+    #[serde(default)]
+    pinned: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateFactRequest {
+    text: String,
+}
+
+pub fn router() -> axum::Router {
+    axum::Router::new()
+        .route("/api/v1/os/memory/facts", get(list_facts).post(create_fact))
+        .route(
+            "/api/v1/os/memory/facts/{id}",
+            get(get_fact).put(update_fact).delete(delete_fact),
+        )
+        .route("/api/v1/os/memory/facts/{id}/pin", post(pin_fact))
+        .route("/api/v1/os/memory/facts/{id}/unpin", post(unpin_fact))
+}
+
+fn disabled() -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "status": "error", "error": "memory.facts is disabled" }))
+}
+
+fn failed(e: anyhow::Error) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "status": "error", "error": e.to_string() }))
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+async fn list_facts(
+    Extension(state): Extension<Arc<OsState>>,
+    Query(query): Query<ListFactsQuery>,
+) -> Json<serde_json::Value> {
+    let Some(facts) = state.facts.as_ref() else {
+        return disabled();
+    };
+    match facts.list(query.subject.as_deref()) {
+        Ok(list) => Json(serde_json::json!({ "status": "ok", "facts": list })),
+        Err(e) => failed(e),
+    }
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn create_fact(
+    Extension(state): Extension<Arc<OsState>>,
+    Json(req): Json<CreateFactRequest>,
+) -> Json<serde_json::Value> {
+    let Some(facts) = state.facts.as_ref() else {
+        return disabled();
+    };
+    let added = facts
+        .add(req.subject.trim(), &req.text, "api")
+        .and_then(|fact| {
+            if req.pinned {
+                facts.set_pinned(&fact.id, true).map(|f| f.unwrap_or(fact))
+            } else {
+                Ok(fact)
+            }
+        });
+    match added {
+        Ok(fact) => Json(serde_json::json!({ "status": "ok", "fact": fact })),
+        Err(e) => failed(e),
+    }
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+async fn get_fact(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(id): Path<String>,
+) -> Json<serde_json::Value> {
+    let Some(facts) = state.facts.as_ref() else {
+        return disabled();
+    };
+    match facts.get(&id) {
+        Ok(Some(fact)) => Json(serde_json::json!({ "status": "ok", "fact": fact })),
+        Ok(None) => Json(serde_json::json!({ "status": "not_found" })),
+        Err(e) => failed(e),
+    }
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn update_fact(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(id): Path<String>,
+    Json(req): Json<UpdateFactRequest>,
+) -> Json<serde_json::Value> {
+    let Some(facts) = state.facts.as_ref() else {
+        return disabled();
+    };
+    match facts.update(&id, &req.text, "api") {
+        Ok(Some(fact)) => Json(serde_json::json!({ "status": "ok", "fact": fact })),
+        Ok(None) => Json(serde_json::json!({ "status": "not_found" })),
+        Err(e) => failed(e),
+    }
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn delete_fact(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(id): Path<String>,
+) -> Json<serde_json::Value> {
+    let Some(facts) = state.facts.as_ref() else {
+        return disabled();
+    };
+    match facts.delete(&id) {
+        Ok(ok) => Json(serde_json::json!({ "status": if ok { "ok" } else { "not_found" } })),
+        Err(e) => failed(e),
+    }
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn pin_fact(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(id): Path<String>,
+) -> Json<serde_json::Value> {
+    set_pinned(&state, &id, true)
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn unpin_fact(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(id): Path<String>,
+) -> Json<serde_json::Value> {
+    set_pinned(&state, &id, false)
+}
+
+fn set_pinned(state: &OsState, id: &str, pinned: bool) -> Json<serde_json::Value> {
+    let Some(facts) = state.facts.as_ref() else {
+        return disabled();
+    };
+    match facts.set_pinned(id, pinned) {
+        Ok(Some(fact)) => Json(serde_json::json!({ "status": "ok", "fact": fact })),
+        Ok(None) => Json(serde_json::json!({ "status": "not_found" })),
+        Err(e) => failed(e),
+    }
+}
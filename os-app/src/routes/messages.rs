@@ -1,18 +1,33 @@
+use crate::attachments::{AttachmentDirection, AttachmentOrigin};
 use crate::server::OsState;
-use axum::routing::post;
+use axum::extract::Path;
+use axum::routing::{delete, get, post};
 use axum::{Extension, Json};
 use serde::Deserialize;
 use std::sync::Arc;
+use uuid::Uuid;
 
 #[derive(Debug, Deserialize)]
 struct SendRequest {
     channel: String,
     recipient: String,
     message: String,
+    #[serde(default)]
+    attachments: Vec<os_channels::Attachment>,
 }
 
 pub fn router() -> axum::Router {
-    axum::Router::new().route("/api/v1/os/messages/send", post(send_message))
+    axum::Router::new()
+        .route("/api/v1/os/messages/send", post(send_message))
+        .route("/api/v1/os/messages/outbox", get(list_dead_letters))
+        .route(
+            "/api/v1/os/messages/outbox/{id}",
+            delete(discard_dead_letter),
+        )
+        .route(
+            "/api/v1/os/messages/outbox/{id}/retry",
+            post(redrive_dead_letter),
+        )
 }
 
 #[tracing::instrument(level = "info", skip_all)]
@@ -20,17 +35,32 @@ async fn send_message(
     Extension(state): Extension<Arc<OsState>>,
     Json(req): Json<SendRequest>,
 ) -> Json<serde_json::Value> {
-    let Some(adapter) = state.channels.get(&req.channel) else {
+    if !state.channels.contains_key(&req.channel) {
         return Json(serde_json::json!({ "status": "error", "error": "unknown channel" }));
-    };
+    }
+
+    if let Some(store) = state.attachments.as_ref() {
+        for attachment in &req.attachments {
+            let origin = AttachmentOrigin {
+                direction: AttachmentDirection::Outbound,
+                channel_id: req.channel.clone(),
+                message_id: None,
+            };
+            if let Err(e) = store.ingest(attachment, origin).await {
+                tracing::warn!(%e, name = %attachment.name, "failed to store outbound attachment");
+            }
+        }
+    }
 
-    if let Err(e) = adapter
+    if let Err(e) = state
+        .outbox
         .send(
+            &req.channel,
             &req.recipient,
             os_channels::OutboundMessage {
                 content: req.message,
                 reply_to_message_id: None,
-                attachments: vec![],
+                attachments: req.attachments,
             },
         )
         .await
@@ -40,3 +70,32 @@ async fn send_message(
 
     Json(serde_json::json!({ "status": "ok" }))
 }
+
+#[tracing::instrument(level = "debug", skip_all)]
+async fn list_dead_letters(Extension(state): Extension<Arc<OsState>>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "dead_letters": state.outbox.dead_letters() }))
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn redrive_dead_letter(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(id): Path<Uuid>,
+) -> Json<serde_json::Value> {
+    match state.outbox.redrive(id) {
+        Ok(true) => Json(serde_json::json!({ "status": "ok" })),
+        Ok(false) => Json(serde_json::json!({ "status": "not_found" })),
+        Err(e) => Json(serde_json::json!({ "status": "error", "error": e.to_string() })),
+    }
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn discard_dead_letter(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(id): Path<Uuid>,
+) -> Json<serde_json::Value> {
+    if state.outbox.discard(id) {
+        Json(serde_json::json!({ "status": "ok" }))
+    } else {
+        Json(serde_json::json!({ "status": "not_found" }))
+    }
+}
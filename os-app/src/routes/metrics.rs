@@ -0,0 +1,17 @@
+use crate::server::OsState;
+use axum::routing::get;
+use axum::{Extension, Json};
+use std::sync::Arc;
+
+pub fn router() -> axum::Router {
+    axum::Router::new().route("/api/v1/os/metrics", get(get_metrics))
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+async fn get_metrics(Extension(state): Extension<Arc<OsState>>) -> Json<serde_json::Value> {
+    let snapshot = state
+        .metrics
+        .snapshot(state.suggestions.list().len(), &state.data_dir)
+        .await;
+    Json(serde_json::json!({ "status": "ok", "metrics": snapshot }))
+}
@@ -1,16 +1,147 @@
+pub mod approvals;
+pub mod attachments;
+pub mod audit;
+pub mod automations;
 pub mod channels;
+pub mod continuations;
+pub mod dashboard;
+pub mod feedback;
+pub mod focus;
 pub mod health;
+pub mod incidents;
+pub mod memory;
 pub mod messages;
+pub mod metrics;
+pub mod pairing;
+pub mod personas;
 pub mod sessions;
+pub mod shares;
 pub mod skills;
+pub mod suggestions;
+pub mod tasks;
 
-use axum::Router;
+use crate::config::ControlConfig;
+use axum::extract::{Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::{Json, Router};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
 
-pub fn router() -> Router {
-    Router::new()
+/// Probes and the dashboard's static page stay open; everything else needs
+/// `control.api_token` when one is set. Routes that approve tool calls, install skills,
+/// define automations or admit senders are only mounted when it is.
+pub fn router(control: &ControlConfig) -> Router {
+    let token: Option<Arc<str>> = control.api_token.as_deref().map(Arc::from);
+    let mut router = Router::new()
         .merge(health::router())
+        .merge(dashboard::assets())
+        .merge(api().layer(middleware::from_fn_with_state(token.clone(), require_token)));
+    if token.is_some() {
+        router =
+            router.merge(privileged().layer(middleware::from_fn_with_state(token, require_token)));
+    } else {
+        tracing::warn!(
+            "control.api_token is unset; approval, skill, automation and pairing routes are off"
+        );
+    }
+    router
+}
+
+fn api() -> Router {
+    Router::new()
         .merge(channels::router())
         .merge(sessions::router())
         .merge(messages::router())
+        .merge(personas::router())
+        .merge(attachments::router())
+        .merge(tasks::router())
+        .merge(incidents::router())
+        .merge(continuations::router())
+        .merge(audit::router())
+        .merge(suggestions::router())
+        .merge(focus::router())
+        .merge(metrics::router())
+        .merge(shares::router())
+        .merge(memory::router())
+        .merge(feedback::router())
+        .merge(dashboard::router())
+}
+
+/// Routes that act with the owner's authority over the network.
+fn privileged() -> Router {
+    Router::new()
+        .merge(approvals::router())
         .merge(skills::router())
+        .merge(automations::router())
+        .merge(pairing::router())
+}
+
+async fn require_token(
+    State(token): State<Option<Arc<str>>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Some(token) = token.as_deref() else {
+        return next.run(req).await;
+    };
+    let given = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    // Comparing digests keeps the time taken independent of how much of the token matched.
+    if Sha256::digest(given) == Sha256::digest(token) {
+        return next.run(req).await;
+    }
+    (
+        StatusCode::UNAUTHORIZED,
+        Json(serde_json::json!({ "status": "unauthorized" })),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DECISION: &str = "/api/v1/os/approvals/6f1c2a0e-8d7b-4f3e-9a51-2b6c0d4e7f18/decision";
+
+    async fn serve(control: ControlConfig) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = router(&control);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("http://{addr}")
+    }
+
+    #[tokio::test]
+    async fn approvals_need_a_token() {
+        let client = reqwest::Client::new();
+        let decision = serde_json::json!({ "decision": "approve" });
+
+        let open = serve(ControlConfig::default()).await;
+        let resp = client
+            .post(format!("{open}{DECISION}"))
+            .json(&decision)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let locked = serve(ControlConfig {
+            api_token: Some("s3cret".to_string()),
+            ..ControlConfig::default()
+        })
+        .await;
+        for auth in [None, Some("Bearer wrong")] {
+            let mut req = client.post(format!("{locked}{DECISION}")).json(&decision);
+            if let Some(auth) = auth {
+                req = req.header(header::AUTHORIZATION, auth);
+            }
+            assert_eq!(req.send().await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        }
+    }
 }
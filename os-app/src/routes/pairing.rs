@@ -0,0 +1,116 @@
+use crate::pairing;
+use crate::server::OsState;
+use axum::extract::Path;
+use axum::routing::{delete, get, post};
+use axum::{Extension, Json};
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+struct CreateInviteRequest {
+    channel: String,
+    /// Defaults to `pairing.expire_hours`.
+    #[serde(default)]
+    expire_hours: Option<u64>,
+    #[serde(default)]
+    note: Option<String>,
+}
+
+pub fn router() -> axum::Router {
+    axum::Router::new()
+        .route("/api/v1/os/pairing", get(list_pairing))
+        .route("/api/v1/os/pairing/invites", post(create_invite))
+        .route("/api/v1/os/pairing/invites/{code}", delete(revoke_invite))
+        .route(
+            "/api/v1/os/pairing/paired/{channel_id}/{sender_id}",
+            delete(unpair),
+        )
+}
+
+fn disabled() -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "status": "error", "error": "pairing is disabled" }))
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+async fn list_pairing(Extension(state): Extension<Arc<OsState>>) -> Json<serde_json::Value> {
+    let Some(store) = state.pairing.as_ref() else {
+        return disabled();
+    };
+    let (invites, paired) = store.list();
+    let invites: Vec<serde_json::Value> = invites
+        .iter()
+        .map(|invite| {
+            serde_json::json!({
+                "code": pairing::display_code(&invite.code),
+                "channel": invite.channel,
+                "note": invite.note,
+                "created_at": invite.created_at,
+                "expires_at": invite.expires_at,
+                "link": store.link(invite),
+            })
+        })
+        .collect();
+    Json(serde_json::json!({ "status": "ok", "invites": invites, "paired": paired }))
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn create_invite(
+    Extension(state): Extension<Arc<OsState>>,
+    Json(req): Json<CreateInviteRequest>,
+) -> Json<serde_json::Value> {
+    let Some(store) = state.pairing.as_ref() else {
+        return disabled();
+    };
+    if !state.channels.contains_key(&req.channel) {
+        return Json(serde_json::json!({
+            "status": "error",
+            "error": format!("{} isn't an enabled channel", req.channel),
+        }));
+    }
+    let expires_in = req
+        .expire_hours
+        .map(|h| chrono::Duration::hours(h.min(1 << 32) as i64));
+    match store.create(&req.channel, expires_in, req.note) {
+        Ok(invite) => {
+            let link = store.link(&invite);
+            Json(serde_json::json!({
+                "status": "ok",
+                "code": pairing::display_code(&invite.code),
+                "channel": invite.channel,
+                "expires_at": invite.expires_at,
+                "link": link,
+                "qr_svg": link.as_deref().and_then(pairing::qr_svg),
+                "instructions": store.instructions(&invite),
+            }))
+        }
+        Err(e) => Json(serde_json::json!({ "status": "error", "error": e.to_string() })),
+    }
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn revoke_invite(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(code): Path<String>,
+) -> Json<serde_json::Value> {
+    let Some(store) = state.pairing.as_ref() else {
+        return disabled();
+    };
+    match store.revoke(&code) {
+        Ok(ok) => Json(serde_json::json!({ "status": if ok { "ok" } else { "not_found" } })),
+        Err(e) => Json(serde_json::json!({ "status": "error", "error": e.to_string() })),
+    }
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn unpair(
+    Extension(state): Extension<Arc<OsState>>,
+    Path((channel_id, sender_id)): Path<(String, String)>,
+) -> Json<serde_json::Value> {
+    let Some(store) = state.pairing.as_ref() else {
+        return disabled();
+    };
+    match store.unpair(&channel_id, &sender_id) {
+        Ok(ok) => Json(serde_json::json!({ "status": if ok { "ok" } else { "not_found" } })),
+        Err(e) => Json(serde_json::json!({ "status": "error", "error": e.to_string() })),
+    }
+}
@@ -0,0 +1,59 @@
+use crate::server::OsState;
+use axum::extract::Path;
+use axum::routing::{get, post};
+use axum::{Extension, Json};
+use serde::Deserialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+struct SetPersonaRequest {
+    /// Persona name, or `null` to fall back to the channel default.
+    persona: Option<String>,
+}
+
+pub fn router() -> axum::Router {
+    axum::Router::new()
+        .route("/api/v1/os/personas", get(list_personas))
+        .route(
+            "/api/v1/os/sessions/{id}/persona",
+            post(set_session_persona),
+        )
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+async fn list_personas(Extension(state): Extension<Arc<OsState>>) -> Json<serde_json::Value> {
+    let mut personas: Vec<serde_json::Value> = state
+        .cfg
+        .personas
+        .iter()
+        .map(|(name, p)| {
+            serde_json::json!({
+                "name": name,
+                "model": p.model.as_deref().unwrap_or(&state.cfg.general.model),
+                "tools": p.tools,
+                "channels": p.channels,
+            })
+        })
+        .collect();
+    personas.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+    Json(serde_json::json!({ "personas": personas }))
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn set_session_persona(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(id): Path<String>,
+    Json(req): Json<SetPersonaRequest>,
+) -> Json<serde_json::Value> {
+    let Ok(id) = Uuid::parse_str(&id) else {
+        return Json(serde_json::json!({ "status": "error", "error": "invalid id" }));
+    };
+    if let Some(name) = req.persona.as_deref() {
+        if !state.cfg.personas.contains_key(name) {
+            return Json(serde_json::json!({ "status": "error", "error": "unknown persona" }));
+        }
+    }
+    let ok = state.sessions.set_persona_by_id(id, req.persona);
+    Json(serde_json::json!({ "status": if ok { "ok" } else { "not_found" } }))
+}
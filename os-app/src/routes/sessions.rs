@@ -1,14 +1,23 @@
+use crate::archive::SearchScope;
+use crate::handoff;
 use crate::server::OsState;
-use axum::extract::Path;
-use axum::routing::{delete, get};
+use crate::session::RestoreOutcome;
+use axum::extract::{Path, Query};
+use axum::routing::{delete, get, post};
 use axum::{Extension, Json};
+use serde::Deserialize;
 use std::sync::Arc;
 use uuid::Uuid;
 
 pub fn router() -> axum::Router {
     axum::Router::new()
         .route("/api/v1/os/sessions", get(list_sessions))
+        .route("/api/v1/os/sessions/search", get(search_sessions))
+        .route("/api/v1/os/sessions/archived", get(list_archived_sessions))
         .route("/api/v1/os/sessions/{id}", delete(delete_session))
+        .route("/api/v1/os/sessions/{id}/restore", post(restore_session))
+        .route("/api/v1/os/sessions/{id}/move", post(move_session))
+        .route("/api/v1/os/sessions/{id}/trace", get(session_trace))
 }
 
 #[tracing::instrument(level = "debug", skip_all)]
@@ -17,14 +26,136 @@ async fn list_sessions(Extension(state): Extension<Arc<OsState>>) -> Json<serde_
     Json(serde_json::json!({ "sessions": sessions }))
 }
 
+#[tracing::instrument(level = "debug", skip_all)]
+async fn list_archived_sessions(
+    Extension(state): Extension<Arc<OsState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "sessions": state.sessions.list_archived() }))
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchQuery {
+    q: String,
+    #[serde(default = "default_search_limit")]
+    limit: usize,
+}
+
+fn default_search_limit() -> usize {
+    10
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn search_sessions(
+    Extension(state): Extension<Arc<OsState>>,
+    Query(q): Query<SearchQuery>,
+) -> Json<serde_json::Value> {
+    let Some(archive) = state.archive.as_ref() else {
+        return Json(serde_json::json!({ "status": "error", "error": "archive is disabled" }));
+    };
+    match archive
+        .search(&q.q, q.limit.clamp(1, 100), &SearchScope::Everyone)
+        .await
+    {
+        Ok(results) => Json(serde_json::json!({ "status": "ok", "results": results })),
+        Err(e) => Json(serde_json::json!({ "status": "error", "error": e.to_string() })),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DeleteQuery {
+    /// Skip the archive and delete permanently.
+    #[serde(default)]
+    purge: bool,
+}
+
 #[tracing::instrument(level = "info", skip_all)]
 async fn delete_session(
     Extension(state): Extension<Arc<OsState>>,
     Path(id): Path<String>,
+    Query(q): Query<DeleteQuery>,
 ) -> Json<serde_json::Value> {
     let Ok(id) = Uuid::parse_str(&id) else {
         return Json(serde_json::json!({ "status": "error", "error": "invalid id" }));
     };
-    let ok = state.sessions.delete_by_id(id);
+    let ok = if q.purge {
+        state.sessions.delete_by_id(id)
+    } else {
+        state.sessions.archive_by_id(id)
+    };
     Json(serde_json::json!({ "status": if ok { "ok" } else { "not_found" } }))
 }
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn restore_session(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(id): Path<String>,
+) -> Json<serde_json::Value> {
+    let Ok(id) = Uuid::parse_str(&id) else {
+        return Json(serde_json::json!({ "status": "error", "error": "invalid id" }));
+    };
+    match state.sessions.restore_by_id(id) {
+        RestoreOutcome::Restored => Json(serde_json::json!({ "status": "ok" })),
+        RestoreOutcome::NotFound => Json(serde_json::json!({ "status": "not_found" })),
+        RestoreOutcome::Conflict => Json(serde_json::json!({
+            "status": "error",
+            "error": "conversation has a newer session; delete it first"
+        })),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct MoveRequest {
+    channel: String,
+    recipient: String,
+}
+
+/// Continue the conversation on another channel; see `handoff`.
+#[tracing::instrument(level = "info", skip_all)]
+async fn move_session(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(id): Path<String>,
+    Json(req): Json<MoveRequest>,
+) -> Json<serde_json::Value> {
+    let Ok(id) = Uuid::parse_str(&id) else {
+        return Json(serde_json::json!({ "status": "error", "error": "invalid id" }));
+    };
+    let Some((channel_id, sender_id)) = state.sessions.key_for(id) else {
+        return Json(serde_json::json!({ "status": "not_found" }));
+    };
+    match handoff::handoff(
+        &state.assistant,
+        &state.sessions,
+        &state.outbox,
+        (&channel_id, &sender_id),
+        (&req.channel, &req.recipient),
+    )
+    .await
+    {
+        Ok(summary) => Json(serde_json::json!({ "status": "ok", "summary": summary })),
+        Err(e) => Json(serde_json::json!({ "status": "error", "error": e.to_string() })),
+    }
+}
+
+/// What the session's last run did, as steps and as the `/trace` text; see `trace`.
+#[tracing::instrument(level = "debug", skip_all)]
+async fn session_trace(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(id): Path<String>,
+) -> Json<serde_json::Value> {
+    let Ok(id) = Uuid::parse_str(&id) else {
+        return Json(serde_json::json!({ "status": "error", "error": "invalid id" }));
+    };
+    let trace = state
+        .sessions
+        .key_for(id)
+        .and_then(|(channel_id, sender_id)| state.sessions.get(&channel_id, &sender_id))
+        .and_then(|session| session.last_trace);
+    match trace {
+        Some(trace) => Json(serde_json::json!({
+            "status": "ok",
+            "text": trace.render(),
+            "trace": trace,
+        })),
+        None => Json(serde_json::json!({ "status": "not_found" })),
+    }
+}
@@ -0,0 +1,91 @@
+use crate::server::OsState;
+use axum::extract::Path;
+use axum::routing::{delete, get};
+use axum::{Extension, Json};
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+struct CreateShareRequest {
+    content: String,
+    #[serde(default)]
+    title: Option<String>,
+    /// Defaults to `shares.expire_hours`.
+    #[serde(default)]
+    expire_hours: Option<u64>,
+}
+
+pub fn router() -> axum::Router {
+    axum::Router::new()
+        .route("/api/v1/os/shares", get(list_shares).post(create_share))
+        .route("/api/v1/os/shares/{id}", delete(revoke_share))
+}
+
+fn disabled() -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "status": "error", "error": "shares are disabled" }))
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+async fn list_shares(Extension(state): Extension<Arc<OsState>>) -> Json<serde_json::Value> {
+    let Some(shares) = state.shares.as_ref() else {
+        return disabled();
+    };
+    match shares.list().await {
+        Ok(list) => {
+            let items: Vec<serde_json::Value> = list
+                .iter()
+                .map(|share| {
+                    serde_json::json!({
+                        "id": share.id,
+                        "title": share.title,
+                        "created_at": share.created_at,
+                        "expires_at": share.expires_at,
+                        "source": share.source,
+                        "url": shares.link(share),
+                    })
+                })
+                .collect();
+            Json(serde_json::json!({ "status": "ok", "shares": items }))
+        }
+        Err(e) => Json(serde_json::json!({ "status": "error", "error": e.to_string() })),
+    }
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn create_share(
+    Extension(state): Extension<Arc<OsState>>,
+    Json(req): Json<CreateShareRequest>,
+) -> Json<serde_json::Value> {
+    let Some(shares) = state.shares.as_ref() else {
+        return disabled();
+    };
+    // Anything past the configured maximum is refused by the store; the cap only keeps
+    // the conversion in range.
+    let expires_in = req
+        .expire_hours
+        .map(|h| chrono::Duration::hours(h.min(1 << 32) as i64));
+    match shares
+        .create(&req.content, req.title, expires_in, "api")
+        .await
+    {
+        Ok((share, url)) => Json(serde_json::json!({
+            "status": "ok",
+            "id": share.id,
+            "expires_at": share.expires_at,
+            "url": url,
+        })),
+        Err(e) => Json(serde_json::json!({ "status": "error", "error": e.to_string() })),
+    }
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn revoke_share(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(id): Path<String>,
+) -> Json<serde_json::Value> {
+    let Some(shares) = state.shares.as_ref() else {
+        return disabled();
+    };
+    let ok = shares.revoke(&id).await;
+    Json(serde_json::json!({ "status": if ok { "ok" } else { "not_found" } }))
+}
@@ -1,6 +1,7 @@
 use crate::server::OsState;
-use axum::extract::Query;
-use axum::routing::{get, post};
+use crate::skills::UpdateOutcome;
+use axum::extract::{Path, Query};
+use axum::routing::{delete, get, post};
 use axum::{Extension, Json};
 use horizons_core::memory::traits::{MemoryItem, MemoryType, RetrievalQuery, Scope};
 use serde::Deserialize;
@@ -12,6 +13,11 @@ struct InstallSkillRequest {
     description: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct InstallPackageRequest {
+    url: String,
+}
+
 #[derive(Debug, Deserialize)]
 struct SearchQuery {
     q: String,
@@ -19,31 +25,100 @@ struct SearchQuery {
 
 pub fn router() -> axum::Router {
     axum::Router::new()
+        .route("/api/v1/os/skills", get(list_skills).post(install_package))
+        .route("/api/v1/os/skills/{name}", delete(remove_skill))
+        .route("/api/v1/os/skills/{name}/update", post(update_skill))
         .route("/api/v1/os/skills/install", post(install_skill))
         .route("/api/v1/os/skills/search", get(search_skills))
 }
 
+#[tracing::instrument(level = "debug", skip_all)]
+async fn list_skills(Extension(state): Extension<Arc<OsState>>) -> Json<serde_json::Value> {
+    match state.skills.list().await {
+        Ok(skills) => Json(serde_json::json!({ "status": "ok", "skills": skills })),
+        Err(e) => Json(serde_json::json!({ "status": "error", "error": e.to_string() })),
+    }
+}
+
+/// Install a skill package from a URL, under the `[skills]` source and signature rules.
+#[tracing::instrument(level = "info", skip_all)]
+async fn install_package(
+    Extension(state): Extension<Arc<OsState>>,
+    Json(req): Json<InstallPackageRequest>,
+) -> Json<serde_json::Value> {
+    match state.skills.install(&req.url).await {
+        Ok(skill) => {
+            remember(&state, &skill.name, &skill.description).await;
+            Json(serde_json::json!({ "status": "ok", "skill": skill }))
+        }
+        Err(e) => Json(serde_json::json!({ "status": "error", "error": e.to_string() })),
+    }
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn update_skill(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(name): Path<String>,
+) -> Json<serde_json::Value> {
+    match state.skills.update(&name).await {
+        Ok(UpdateOutcome::Unchanged(skill)) => {
+            Json(serde_json::json!({ "status": "unchanged", "skill": skill }))
+        }
+        Ok(UpdateOutcome::Updated {
+            from_version,
+            skill,
+        }) => {
+            remember(&state, &skill.name, &skill.description).await;
+            Json(serde_json::json!({
+                "status": "ok",
+                "from_version": from_version,
+                "skill": skill,
+            }))
+        }
+        Err(e) => Json(serde_json::json!({ "status": "error", "error": e.to_string() })),
+    }
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn remove_skill(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(name): Path<String>,
+) -> Json<serde_json::Value> {
+    match state.skills.remove(&name).await {
+        Ok(true) => Json(serde_json::json!({ "status": "ok" })),
+        Ok(false) => Json(serde_json::json!({ "status": "not_found" })),
+        Err(e) => Json(serde_json::json!({ "status": "error", "error": e.to_string() })),
+    }
+}
+
 #[tracing::instrument(level = "info", skip_all)]
 async fn install_skill(
     Extension(state): Extension<Arc<OsState>>,
     Json(req): Json<InstallSkillRequest>,
 ) -> Json<serde_json::Value> {
-    let Some(mem) = state.memory.as_ref() else {
+    if state.memory.is_none() {
         return Json(serde_json::json!({ "status": "error", "error": "memory disabled" }));
+    }
+    remember(&state, &req.name, &req.description).await;
+    Json(serde_json::json!({ "status": "ok" }))
+}
+
+/// Index a skill in memory so `/skills/search` finds it. A no-op with memory disabled.
+async fn remember(state: &OsState, name: &str, description: &str) {
+    let Some(mem) = state.memory.as_ref() else {
+        return;
     };
 
     let scope = Scope::new(state.org_id.to_string(), "os.skills".to_string());
     let content = serde_json::json!({
-        "name": req.name,
-        "description": req.description,
+        "name": name,
+        "description": description,
     });
 
     let item = MemoryItem::new(&scope, MemoryType::skill(), content, chrono::Utc::now())
         .with_importance(1.0)
         .with_index_text("skill".to_string());
     let _ = mem.append_item(state.org_id, item).await;
-
-    Json(serde_json::json!({ "status": "ok" }))
 }
 
 #[tracing::instrument(level = "debug", skip_all)]
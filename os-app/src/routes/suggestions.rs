@@ -0,0 +1,48 @@
+use crate::server::OsState;
+use axum::extract::Path;
+use axum::routing::{delete, get, post};
+use axum::{Extension, Json};
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+struct SendSuggestionRequest {
+    /// Sent instead of the draft.
+    #[serde(default)]
+    text: Option<String>,
+}
+
+pub fn router() -> axum::Router {
+    axum::Router::new()
+        .route("/api/v1/os/suggestions", get(list_suggestions))
+        .route("/api/v1/os/suggestions/{code}/send", post(send_suggestion))
+        .route("/api/v1/os/suggestions/{code}", delete(dismiss_suggestion))
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+async fn list_suggestions(Extension(state): Extension<Arc<OsState>>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "suggestions": state.suggestions.list() }))
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn send_suggestion(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(code): Path<String>,
+    req: Option<Json<SendSuggestionRequest>>,
+) -> Json<serde_json::Value> {
+    let text = req.and_then(|Json(r)| r.text);
+    match state.suggestions.send(&code, text).await {
+        Ok(Some(_)) => Json(serde_json::json!({ "status": "ok" })),
+        Ok(None) => Json(serde_json::json!({ "status": "not_found" })),
+        Err(e) => Json(serde_json::json!({ "status": "error", "error": e.to_string() })),
+    }
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn dismiss_suggestion(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(code): Path<String>,
+) -> Json<serde_json::Value> {
+    let ok = state.suggestions.dismiss(&code);
+    Json(serde_json::json!({ "status": if ok { "ok" } else { "not_found" } }))
+}
@@ -0,0 +1,43 @@
+use crate::server::OsState;
+use axum::extract::Path;
+use axum::routing::get;
+use axum::{Extension, Json};
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub fn router() -> axum::Router {
+    axum::Router::new()
+        .route("/api/v1/os/tasks", get(list_tasks))
+        .route("/api/v1/os/tasks/{id}", get(get_task).delete(cancel_task))
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+async fn list_tasks(Extension(state): Extension<Arc<OsState>>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "tasks": state.tasks.list() }))
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+async fn get_task(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(id): Path<String>,
+) -> Json<serde_json::Value> {
+    let Ok(id) = Uuid::parse_str(&id) else {
+        return Json(serde_json::json!({ "status": "error", "error": "invalid id" }));
+    };
+    match state.tasks.get(id) {
+        Some(task) => Json(serde_json::json!({ "status": "ok", "task": task })),
+        None => Json(serde_json::json!({ "status": "not_found" })),
+    }
+}
+
+#[tracing::instrument(level = "info", skip_all)]
+async fn cancel_task(
+    Extension(state): Extension<Arc<OsState>>,
+    Path(id): Path<String>,
+) -> Json<serde_json::Value> {
+    let Ok(id) = Uuid::parse_str(&id) else {
+        return Json(serde_json::json!({ "status": "error", "error": "invalid id" }));
+    };
+    let ok = state.tasks.cancel(id);
+    Json(serde_json::json!({ "status": if ok { "ok" } else { "not_found" } }))
+}
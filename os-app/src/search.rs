@@ -0,0 +1,396 @@
+//! One search over every place a conversation might have happened.
+//!
+//! `search_everywhere` asks the conversation archive (what reached the assistant on any
+//! channel), iMessage's `chat.db` (everything in Messages, not just what was sent to the
+//! assistant) and Gmail, then merges the hits newest first, each with a link back to
+//! where it happened when the platform has one. A source that fails is reported next to
+//! the results instead of failing the search.
+//!
+//! iMessage and Gmail are the owner's, so only owners (`inbound.priority.owners`) search
+//! them. Anyone else gets their own archived conversations, as with `conversation_search`.
+
+use crate::archive::{ConversationArchive, SearchHit, SearchScope};
+use crate::config::IdentitiesConfig;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use horizons_core::core_agents::models::RiskLevel;
+use os_channels::{ImessageAdapter, ImessageHit};
+use os_tools::{until_cancelled, CancellationToken, Tool, ToolError, ToolSpec};
+use serde::Serialize;
+use serde_json::json;
+use std::sync::Arc;
+
+const DEFAULT_LIMIT: usize = 10;
+const LIMIT_MAX: usize = 50;
+
+/// A hit from any source, in one shape.
+#[derive(Debug, Clone, Serialize)]
+pub struct UnifiedHit {
+    /// `history`, `imessage` or `email`.
+    pub source: &'static str,
+    pub channel: String,
+    pub from: String,
+    pub snippet: String,
+    pub at: Option<DateTime<Utc>>,
+    pub link: Option<String>,
+    /// Gmail thread id, for following up with the email tool.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread_id: Option<String>,
+}
+
+/// Searches the archive, iMessage and Gmail at once.
+pub struct SearchEverywhereTool {
+    archive: Option<Arc<ConversationArchive>>,
+    imessage: Option<ImessageAdapter>,
+    email: Option<Arc<dyn Tool>>,
+    owners: Vec<String>,
+    people: IdentitiesConfig,
+}
+
+impl SearchEverywhereTool {
+    pub fn new(archive: Option<Arc<ConversationArchive>>) -> Self {
+        Self {
+            archive,
+            imessage: None,
+            email: None,
+            owners: Vec::new(),
+            people: IdentitiesConfig::default(),
+        }
+    }
+
+    /// Who searches everything, and whose conversations are linked, as for
+    /// [`crate::archive::ConversationSearchTool::with_access`].
+    pub fn with_access(mut self, owners: Vec<String>, people: IdentitiesConfig) -> Self {
+        self.owners = owners;
+        self.people = people;
+        self
+    }
+
+    /// Also search `chat.db` through `adapter`.
+    pub fn with_imessage(mut self, adapter: ImessageAdapter) -> Self {
+        self.imessage = Some(adapter);
+        self
+    }
+
+    /// Also search Gmail through the `email` tool's `search` action.
+    pub fn with_email(mut self, tool: Arc<dyn Tool>) -> Self {
+        self.email = Some(tool);
+        self
+    }
+
+    fn sources(&self) -> Vec<&'static str> {
+        let mut out = Vec::new();
+        if self.archive.is_some() {
+            out.push("history");
+        }
+        if self.imessage.is_some() {
+            out.push("imessage");
+        }
+        if self.email.is_some() {
+            out.push("email");
+        }
+        out
+    }
+
+    async fn history(
+        &self,
+        query: &str,
+        limit: usize,
+        scope: &SearchScope,
+    ) -> anyhow::Result<Vec<UnifiedHit>> {
+        let Some(archive) = self.archive.as_ref() else {
+            return Ok(Vec::new());
+        };
+        Ok(archive
+            .search(query, limit, scope)
+            .await?
+            .into_iter()
+            .map(from_history)
+            .collect())
+    }
+
+    async fn imessage(&self, query: &str, limit: usize) -> anyhow::Result<Vec<UnifiedHit>> {
+        let Some(adapter) = self.imessage.as_ref() else {
+            return Ok(Vec::new());
+        };
+        Ok(adapter
+            .search(query, limit)
+            .await?
+            .into_iter()
+            .map(from_imessage)
+            .collect())
+    }
+
+    async fn email(
+        &self,
+        query: &str,
+        limit: usize,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<Vec<UnifiedHit>> {
+        let Some(tool) = self.email.as_ref() else {
+            return Ok(Vec::new());
+        };
+        let found = tool
+            .execute(
+                json!({ "action": "search", "query": query, "limit": limit }),
+                cancel,
+            )
+            .await?;
+        Ok(from_email(&found))
+    }
+}
+
+#[async_trait]
+impl Tool for SearchEverywhereTool {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "search_everywhere".to_string(),
+            description: format!(
+                "Search messages across every connected source ({}) at once. Returns results \
+                 newest first with source, channel, sender, snippet, time and a link back to \
+                 the conversation where there is one. iMessage and email match keywords, so \
+                 prefer a few distinctive words over a sentence.",
+                self.sources().join(", ")
+            ),
+            parameters_schema: json!({
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string", "description": "Words to look for, e.g. \"contract\"." },
+                    "sources": {
+                        "type": "array",
+                        "items": { "type": "string", "enum": self.sources() },
+                        "description": "Only search these sources. Defaults to all."
+                    },
+                    "limit": { "type": "integer", "minimum": 1, "maximum": LIMIT_MAX, "description": "Results per source." }
+                },
+                "required": ["query"]
+            }),
+            risk_level: RiskLevel::Low,
+        }
+    }
+
+    async fn execute(
+        &self,
+        arguments: serde_json::Value,
+        cancel: &CancellationToken,
+    ) -> os_tools::Result<serde_json::Value> {
+        let query = arguments
+            .get("query")
+            .and_then(|v| v.as_str())
+            .map(str::trim)
+            .filter(|q| !q.is_empty())
+            .ok_or_else(|| ToolError::InvalidArguments("missing key: query".to_string()))?;
+        let limit = arguments
+            .get("limit")
+            .and_then(|v| v.as_u64())
+            .map(|n| n as usize)
+            .unwrap_or(DEFAULT_LIMIT)
+            .clamp(1, LIMIT_MAX);
+        let wanted: Option<Vec<&str>> = arguments
+            .get("sources")
+            .and_then(|v| v.as_array())
+            .map(|a| a.iter().filter_map(|s| s.as_str()).collect());
+        let scope = SearchScope::current(&self.owners, &self.people).ok_or_else(|| {
+            ToolError::Unauthorized("search_everywhere only runs inside a conversation".to_string())
+        })?;
+        let wants = |source: &str| {
+            (scope == SearchScope::Everyone || source == "history")
+                && wanted.as_ref().is_none_or(|w| w.contains(&source))
+        };
+
+        let (history, imessage, email) = until_cancelled(cancel, async {
+            Ok(tokio::join!(
+                async {
+                    if wants("history") {
+                        Some(self.history(query, limit, &scope).await)
+                    } else {
+                        None
+                    }
+                },
+                async {
+                    if wants("imessage") {
+                        Some(self.imessage(query, limit).await)
+                    } else {
+                        None
+                    }
+                },
+                async {
+                    if wants("email") {
+                        Some(self.email(query, limit, cancel).await)
+                    } else {
+                        None
+                    }
+                },
+            ))
+        })
+        .await?;
+
+        let mut results = Vec::new();
+        let mut failed = Vec::new();
+        for (source, outcome) in [
+            ("history", history),
+            ("imessage", imessage),
+            ("email", email),
+        ] {
+            match outcome {
+                Some(Ok(hits)) => results.extend(hits),
+                Some(Err(e)) => {
+                    tracing::warn!(%e, source, "search source failed");
+                    failed.push(json!({ "source": source, "error": e.to_string() }));
+                }
+                None => {}
+            }
+        }
+        sort_newest_first(&mut results);
+        Ok(json!({ "results": results, "failed": failed }))
+    }
+}
+
+fn sort_newest_first(hits: &mut [UnifiedHit]) {
+    // Undated hits go last; `None` sorts before any `Some`.
+    hits.sort_by_key(|h| std::cmp::Reverse(h.at));
+}
+
+fn from_history(hit: SearchHit) -> UnifiedHit {
+    let from = if hit.role == "assistant" {
+        "assistant".to_string()
+    } else {
+        hit.sender_id.clone()
+    };
+    UnifiedHit {
+        source: "history",
+        link: deep_link(&hit.channel_id, &hit.sender_id),
+        channel: hit.channel_id,
+        from,
+        snippet: hit.snippet,
+        at: Some(hit.created_at),
+        thread_id: None,
+    }
+}
+
+fn from_imessage(hit: ImessageHit) -> UnifiedHit {
+    let from = if hit.is_from_me {
+        "me".to_string()
+    } else {
+        hit.handle.clone().unwrap_or_default()
+    };
+    let channel = match hit.chat_name.as_deref() {
+        Some(name) => format!("imessage ({name})"),
+        None => "imessage".to_string(),
+    };
+    UnifiedHit {
+        source: "imessage",
+        link: hit.handle.as_deref().and_then(|h| deep_link("imessage", h)),
+        channel,
+        from,
+        snippet: hit.text,
+        at: hit.sent_at,
+        thread_id: None,
+    }
+}
+
+/// Hits from the email tool's `search` output (`{"threads": [..]}`).
+fn from_email(found: &serde_json::Value) -> Vec<UnifiedHit> {
+    found["threads"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|t| {
+            let id = t["thread_id"].as_str()?;
+            let snippet = match (t["subject"].as_str(), t["snippet"].as_str()) {
+                (Some(subject), Some(snippet)) => format!("{subject}: {snippet}"),
+                (subject, snippet) => subject.or(snippet).unwrap_or_default().to_string(),
+            };
+            Some(UnifiedHit {
+                source: "email",
+                channel: "email".to_string(),
+                from: t["from"].as_str().unwrap_or_default().to_string(),
+                snippet,
+                at: t["date"]
+                    .as_str()
+                    .and_then(|d| DateTime::parse_from_rfc2822(d.trim()).ok())
+                    .map(|d| d.with_timezone(&Utc)),
+                link: Some(format!("https://mail.google.com/mail/u/0/#all/{id}")),
+                thread_id: Some(id.to_string()),
+            })
+        })
+        .collect()
+}
+
+/// A link that opens the conversation with `sender` in the platform's own app, where
+/// the sender id is enough to build one.
+fn deep_link(channel_id: &str, sender: &str) -> Option<String> {
+    let sender = sender.trim();
+    if sender.is_empty() {
+        return None;
+    }
+    match channel_id {
+        "telegram" if sender.chars().all(|c| c.is_ascii_digit()) => {
+            Some(format!("tg://user?id={sender}"))
+        }
+        "discord" if sender.chars().all(|c| c.is_ascii_digit()) => {
+            Some(format!("https://discord.com/users/{sender}"))
+        }
+        // Group chat ids (`iMessage;+;chat…`) have no URL form.
+        "imessage" if !sender.contains(';') => Some(format!("imessage:{sender}")),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn email_threads_become_linked_hits() {
+        let found = json!({ "threads": [
+            {
+                "thread_id": "18c2a",
+                "subject": "Contract",
+                "from": "Dana <dana@example.com>",
+                "date": "Tue, 3 Sep 2024 09:15:00 -0700",
+                "snippet": "Signed copy attached"
+            },
+            { "thread_id": "18c2b", "snippet": "no subject or date" }
+        ]});
+        let mut hits = from_email(&found);
+        hits.push(UnifiedHit {
+            source: "history",
+            channel: "telegram".to_string(),
+            from: "42".to_string(),
+            snippet: "the contract is signed".to_string(),
+            at: Some("2024-09-04T00:00:00Z".parse().unwrap()),
+            link: deep_link("telegram", "42"),
+            thread_id: None,
+        });
+        sort_newest_first(&mut hits);
+
+        assert_eq!(hits[0].link.as_deref(), Some("tg://user?id=42"));
+        assert_eq!(hits[1].snippet, "Contract: Signed copy attached");
+        assert_eq!(
+            hits[1].at.unwrap().to_rfc3339(),
+            "2024-09-03T16:15:00+00:00"
+        );
+        assert_eq!(
+            hits[1].link.as_deref(),
+            Some("https://mail.google.com/mail/u/0/#all/18c2a")
+        );
+        assert!(hits[2].at.is_none());
+        assert_eq!(hits[2].snippet, "no subject or date");
+    }
+
+    #[test]
+    fn links_only_where_the_platform_has_them() {
+        assert_eq!(
+            deep_link("discord", "1234").as_deref(),
+            Some("https://discord.com/users/1234")
+        );
+        assert_eq!(
+            deep_link("imessage", "+14155551212").as_deref(),
+            Some("imessage:+14155551212")
+        );
+        assert!(deep_link("imessage", "iMessage;+;chat123").is_none());
+        assert!(deep_link("telegram", "@dana").is_none());
+        assert!(deep_link("webchat", "me").is_none());
+    }
+}
@@ -0,0 +1,175 @@
+//! Secret references in config values.
+//!
+//! A credential field can name where the secret lives instead of holding it:
+//!
+//! - `env:NAME` reads an environment variable.
+//! - `keychain:service` or `keychain:service/account` reads a generic password from the
+//!   macOS login keychain.
+//! - `op://vault/item/field` reads from 1Password through the `op` CLI.
+//!
+//! References are resolved once when the config loads, so everything downstream sees
+//! plain values. Anything else is used as written.
+
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+
+/// `op` can wait on a biometric or password unlock prompt.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretRef<'a> {
+    Env(&'a str),
+    Keychain {
+        service: &'a str,
+        account: Option<&'a str>,
+    },
+    OnePassword(&'a str),
+}
+
+impl<'a> SecretRef<'a> {
+    pub fn parse(value: &'a str) -> Option<Self> {
+        let value = value.trim();
+        if let Some(name) = value.strip_prefix("env:") {
+            return Some(Self::Env(name.trim()));
+        }
+        if let Some(rest) = value.strip_prefix("keychain:") {
+            let (service, account) = match rest.split_once('/') {
+                Some((service, account)) => (service, Some(account)),
+                None => (rest, None),
+            };
+            return Some(Self::Keychain { service, account });
+        }
+        if value.starts_with("op://") {
+            return Some(Self::OnePassword(value));
+        }
+        None
+    }
+
+    async fn fetch(&self) -> Result<String> {
+        match self {
+            Self::Env(name) => {
+                if name.is_empty() {
+                    return Err(anyhow!("env: needs a variable name"));
+                }
+                std::env::var(name).map_err(|_| anyhow!("environment variable {name} is not set"))
+            }
+            Self::Keychain { service, account } => {
+                if !cfg!(target_os = "macos") {
+                    return Err(anyhow!("keychain: references only work on macOS"));
+                }
+                let mut args = vec!["find-generic-password", "-s", service];
+                if let Some(account) = account {
+                    args.extend(["-a", account]);
+                }
+                args.push("-w");
+                run("security", &args)
+                    .await
+                    .map_err(|e| anyhow!("keychain item {service:?}: {e}"))
+            }
+            Self::OnePassword(reference) => run("op", &["read", "--no-newline", reference])
+                .await
+                .map_err(|e| anyhow!("1Password: {e}")),
+        }
+    }
+}
+
+/// Resolves references, asking each provider at most once per reference.
+#[derive(Default)]
+pub struct Resolver {
+    cache: HashMap<String, String>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace `value` with the secret it refers to; plain values are left alone.
+    pub async fn resolve(&mut self, field: &str, value: &mut String) -> Result<()> {
+        let Some(reference) = SecretRef::parse(value) else {
+            return Ok(());
+        };
+        let key = value.trim().to_string();
+        let secret = match self.cache.get(&key) {
+            Some(secret) => secret.clone(),
+            None => {
+                let secret = reference
+                    .fetch()
+                    .await
+                    .map_err(|e| anyhow!("{field} ({key}): {e}"))?;
+                if secret.trim().is_empty() {
+                    return Err(anyhow!("{field} ({key}) resolved to an empty value"));
+                }
+                self.cache.insert(key, secret.clone());
+                secret
+            }
+        };
+        *value = secret;
+        Ok(())
+    }
+}
+
+async fn run(program: &str, args: &[&str]) -> Result<String> {
+    let child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| anyhow!("run {program}: {e}"))?;
+    let output = tokio::time::timeout(COMMAND_TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| anyhow!("{program} timed out after {}s", COMMAND_TIMEOUT.as_secs()))??;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!(
+            "{program} failed: {}",
+            stderr.lines().next().unwrap_or("no output").trim()
+        ));
+    }
+    let stdout = String::from_utf8(output.stdout)
+        .map_err(|_| anyhow!("{program} returned a value that isn't UTF-8"))?;
+    Ok(stdout.trim_end_matches(['\r', '\n']).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn parses_references_and_resolves_env() {
+        assert_eq!(SecretRef::parse("env:X"), Some(SecretRef::Env("X")));
+        assert_eq!(
+            SecretRef::parse("keychain:openai/me"),
+            Some(SecretRef::Keychain {
+                service: "openai",
+                account: Some("me")
+            })
+        );
+        assert_eq!(
+            SecretRef::parse(" op://Private/OpenAI/credential "),
+            Some(SecretRef::OnePassword("op://Private/OpenAI/credential"))
+        );
+        assert_eq!(SecretRef::parse("sk-plain-value"), None);
+
+        let path = std::env::var("PATH").unwrap();
+        let mut resolver = Resolver::new();
+        let mut value = "env:PATH".to_string();
+        resolver.resolve("keys.x", &mut value).await.unwrap();
+        assert_eq!(value, path);
+
+        let mut plain = "sk-plain-value".to_string();
+        resolver.resolve("keys.x", &mut plain).await.unwrap();
+        assert_eq!(plain, "sk-plain-value");
+
+        let mut missing = "env:OPENCRAW_TEST_UNSET_VARIABLE".to_string();
+        let err = resolver.resolve("keys.x", &mut missing).await.unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("keys.x (env:OPENCRAW_TEST_UNSET_VARIABLE)"));
+    }
+}
@@ -3,21 +3,63 @@
 //! Builds a Horizons `AppState` (dev backends) and mounts OpenShell routes on top.
 //! See: specifications/openshell/implementation_v0_1_0.md
 
-use crate::assistant::AssistantAgent;
-use crate::config::OpenShellConfig;
+use crate::admin::AdminAlerts;
+use crate::approval_batches::ApprovalBatches;
+use crate::archive::{ConversationArchive, ConversationSearchTool};
+use crate::assistant::{AssistantAgent, RunBudget};
+use crate::attachments::AttachmentStore;
+use crate::audit::AuditLog;
+use crate::automation::AutomationEngine;
+use crate::cluster::Cluster;
+use crate::config::{expand_home, OpenShellConfig};
+use crate::continuations::{ContinuationRegistry, ScheduleFollowupTool};
 use crate::dev_backends;
+use crate::doctor;
+use crate::encryption::{self, DataCipher};
+use crate::facts::{FactExtractor, FactStore};
+use crate::feedback::FeedbackStore;
+use crate::focus::{self, FocusMode};
 use crate::gateway::Gateway;
+use crate::grants::GrantOffers;
+use crate::health::{self, Readiness};
+use crate::integrity::IntegrityMonitor;
+use crate::journal::InboundJournal;
+use crate::metrics::{self, Metrics, MetricsSnapshot};
+use crate::outbox::Outbox;
+use crate::pairing::{self, PairingStore};
+use crate::redaction::Redactor;
 use crate::routes;
-use crate::session::SessionManager;
+use crate::search::SearchEverywhereTool;
+use crate::session::{Session, SessionManager};
+use crate::shares::{self, ShareStore};
+use crate::skill_wasm::WasmRuntime;
+use crate::skills::{SkillStore, SkillsCommand, UpdateOutcome};
+use crate::suggestions::SuggestionQueue;
+use crate::supervisor::Supervisor;
+use crate::tasks::{self, DelegateTaskTool, TaskRegistry};
+use crate::tool_results::{MemorySearchTool, ToolResultSummarizer};
+use crate::translate::{TranslateTool, Translator};
+use crate::watchdog::Watchdog;
+use crate::webhooks::Webhooks;
 use anyhow::Result;
-use os_channels::{ChannelAdapter, DiscordAdapter, ImessageAdapter, TelegramAdapter, WebChatAdapter};
-use os_tools::{BrowserTool, ClipboardTool, FilesystemTool, ShellTool, Tool};
+use os_channels::{
+    CalendarAdapter, ChannelAdapter, DiscordAdapter, HistoryMessage, ImessageAdapter, NtfyAdapter,
+    PushoverAdapter, TelegramAdapter, WebChatAdapter,
+};
+use os_llm::Role;
+use os_tools::{
+    BrowserTool, ClipboardTool, CodePreset, CodeRunOptions, CodeRunTool, FilesystemTool,
+    NetworkPolicy, ShellPolicy, ShellTool, Tool,
+};
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Instant;
 
+/// Earlier messages shown to a webchat browser that reconnects.
+const WEBCHAT_HISTORY: usize = 200;
+
 pub struct OsState {
     pub cfg: OpenShellConfig,
     pub org_id: horizons_core::OrgId,
@@ -25,12 +67,241 @@ pub struct OsState {
     pub project_db_handle: horizons_core::ProjectDbHandle,
     pub channels: HashMap<String, Arc<dyn ChannelAdapter>>,
     pub sessions: Arc<SessionManager>,
+    pub assistant: Arc<AssistantAgent>,
     pub memory: Option<Arc<dyn horizons_core::memory::traits::HorizonsMemory>>,
+    pub integrity: Arc<IntegrityMonitor>,
+    pub attachments: Option<Arc<AttachmentStore>>,
+    pub archive: Option<Arc<ConversationArchive>>,
+    pub tasks: Arc<TaskRegistry>,
+    pub outbox: Arc<Outbox>,
+    pub watchdog: Arc<Watchdog>,
+    pub continuations: Arc<ContinuationRegistry>,
+    pub audit: Arc<AuditLog>,
+    pub grant_offers: Arc<GrantOffers>,
+    pub approval_batches: Arc<ApprovalBatches>,
+    pub core_agents: Arc<horizons_core::core_agents::executor::CoreAgentsExecutor>,
+    pub suggestions: Arc<SuggestionQueue>,
+    pub focus: Arc<FocusMode>,
+    pub metrics: Arc<Metrics>,
+    pub shares: Option<Arc<ShareStore>>,
+    pub pairing: Option<Arc<PairingStore>>,
+    pub facts: Option<Arc<FactStore>>,
+    pub feedback: Arc<FeedbackStore>,
+    pub skills: Arc<SkillStore>,
+    pub automation: Arc<AutomationEngine>,
+    pub data_dir: PathBuf,
+    /// Languages `code.run` can execute, and how.
+    pub code_presets: Vec<CodePreset>,
 }
 
+/// Validate the config, then check each enabled integration's credentials live.
 pub async fn doctor(config_path: Option<PathBuf>) -> Result<()> {
     let cfg = OpenShellConfig::load(config_path).await?;
-    tracing::info!(model = %cfg.general.model, "config ok");
+    println!("config ok (model {})", cfg.general.model);
+    let results = doctor::run(&cfg).await;
+    print!("{}", doctor::render(&results));
+    let failed = results.iter().filter(|r| r.outcome.is_err()).count();
+    if failed > 0 {
+        return Err(anyhow::anyhow!("{failed} check(s) failed"));
+    }
+    Ok(())
+}
+
+/// Validate the config, then print the running server's readiness and metrics.
+pub async fn status(config_path: Option<PathBuf>, url: Option<String>) -> Result<()> {
+    let cfg = OpenShellConfig::load(config_path).await?;
+    println!("config ok (model {})", cfg.general.model);
+
+    let base = url.unwrap_or_else(|| format!("http://127.0.0.1:{}", cfg.channels.webchat.port));
+    let base = base.trim_end_matches('/');
+    let client = reqwest::Client::new();
+    let get = |path: &str| {
+        let req = client
+            .get(format!("{base}{path}"))
+            .timeout(std::time::Duration::from_secs(5));
+        match cfg.control.api_token.as_deref() {
+            Some(token) => req.bearer_auth(token),
+            None => req,
+        }
+        .send()
+    };
+    // `/readyz` answers 503 when not ready; the body says why.
+    let body: serde_json::Value = get("/readyz")
+        .await
+        .map_err(|e| anyhow::anyhow!("server not reachable at {base}: {e}"))?
+        .json()
+        .await?;
+    let readiness: Readiness = serde_json::from_value(body["readiness"].clone())?;
+    let now = chrono::Utc::now();
+    print!("{}", health::render(&readiness, now));
+
+    let body: serde_json::Value = get("/api/v1/os/metrics")
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| anyhow::anyhow!("server not reachable at {base}: {e}"))?
+        .json()
+        .await?;
+    let snapshot: MetricsSnapshot = serde_json::from_value(body["metrics"].clone())?;
+    print!("{}", metrics::render(&snapshot, now));
+    Ok(())
+}
+
+/// Mint a pairing invite on the running server and print it, with a QR code when the
+/// channel has an invite link.
+pub async fn invite(
+    config_path: Option<PathBuf>,
+    url: Option<String>,
+    channel: String,
+    expires: Option<String>,
+    note: Option<String>,
+) -> Result<()> {
+    let cfg = OpenShellConfig::load(config_path).await?;
+    let expire_hours = match expires.as_deref() {
+        Some(s) => {
+            let duration = focus::parse_duration(s)
+                .ok_or_else(|| anyhow::anyhow!("couldn't read {s:?} as a duration, e.g. 48h"))?;
+            Some(duration.num_hours().max(1))
+        }
+        None => None,
+    };
+    let base = url.unwrap_or_else(|| format!("http://127.0.0.1:{}", cfg.channels.webchat.port));
+    let base = base.trim_end_matches('/');
+    let req = reqwest::Client::new()
+        .post(format!("{base}/api/v1/os/pairing/invites"))
+        .timeout(std::time::Duration::from_secs(5))
+        .json(&serde_json::json!({
+            "channel": channel,
+            "expire_hours": expire_hours,
+            "note": note,
+        }));
+    let req = match cfg.control.api_token.as_deref() {
+        Some(token) => req.bearer_auth(token),
+        None => req,
+    };
+    let body: serde_json::Value = req
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| anyhow::anyhow!("server not reachable at {base}: {e}"))?
+        .json()
+        .await?;
+    if body["status"] != "ok" {
+        return Err(anyhow::anyhow!(
+            "{}",
+            body["error"].as_str().unwrap_or("invite failed")
+        ));
+    }
+    if let Some(qr) = body["link"].as_str().and_then(pairing::qr_terminal) {
+        println!("{qr}");
+    }
+    println!("{}", body["instructions"].as_str().unwrap_or_default());
+    Ok(())
+}
+
+/// Seal existing plaintext in the data dir with `encryption.key`, or open it again.
+pub async fn encrypt_data(config_path: Option<PathBuf>, decrypt: bool) -> Result<()> {
+    let cfg = OpenShellConfig::load(config_path).await?;
+    if cfg.encryption.key.trim().is_empty() {
+        return Err(anyhow::anyhow!(
+            "set encryption.key first (base64 of 32 bytes, e.g. `openssl rand -base64 32`)"
+        ));
+    }
+    let cipher = DataCipher::from_key(&cfg.encryption.key)?;
+    let port = cfg.channels.webchat.port;
+    if tokio::net::TcpStream::connect(("127.0.0.1", port))
+        .await
+        .is_ok()
+    {
+        return Err(anyhow::anyhow!(
+            "something is listening on port {port}; stop the server before migrating its data"
+        ));
+    }
+
+    let data_dir = PathBuf::from("data");
+    let report =
+        tokio::task::spawn_blocking(move || encryption::migrate(&data_dir, &cipher, decrypt))
+            .await??;
+    let verb = if decrypt { "decrypted" } else { "encrypted" };
+    println!(
+        "{verb} {} archived messages, {} facts, {} feedback answers, {} dead letters, {} audit events and {} inbound journal lines",
+        report.archive_rows,
+        report.fact_rows,
+        report.feedback_rows,
+        report.dead_letter_rows,
+        report.audit_lines,
+        report.inbound_lines
+    );
+    if !decrypt && !cfg.encryption.enabled {
+        println!("set encryption.enabled = true so new data is encrypted too");
+    }
+    if decrypt && cfg.encryption.enabled {
+        println!("set encryption.enabled = false, or new data will be encrypted again");
+    }
+    Ok(())
+}
+
+/// Install, list, update or remove skills in the data dir. A running server sees the
+/// change on its next request.
+pub async fn skills(config_path: Option<PathBuf>, command: SkillsCommand) -> Result<()> {
+    let cfg = OpenShellConfig::load(config_path).await?;
+    let store = SkillStore::open(cfg.skills.clone(), &PathBuf::from("data")).await?;
+    match command {
+        SkillsCommand::Install { url } => {
+            let skill = store.install(&url).await?;
+            println!("installed {} {}", skill.name, skill.version);
+        }
+        SkillsCommand::List => {
+            let skills = store.list().await?;
+            if skills.is_empty() {
+                println!("no skills installed");
+            }
+            for skill in skills {
+                let signed = skill
+                    .signed_by
+                    .map(|key| format!(", signed by {key}"))
+                    .unwrap_or_default();
+                println!(
+                    "{} {} ({}{signed})\n  {}",
+                    skill.name, skill.version, skill.source, skill.description
+                );
+                for line in skill.requires.describe().lines() {
+                    println!("  {line}");
+                }
+            }
+        }
+        SkillsCommand::Update { name } => {
+            let names = match name {
+                Some(name) => vec![name],
+                None => store.list().await?.into_iter().map(|s| s.name).collect(),
+            };
+            // One failed source doesn't hold back the rest.
+            let mut failed = 0;
+            for name in names {
+                match store.update(&name).await {
+                    Ok(UpdateOutcome::Unchanged(skill)) => {
+                        println!("{name} {} is up to date", skill.version)
+                    }
+                    Ok(UpdateOutcome::Updated {
+                        from_version,
+                        skill,
+                    }) => println!("{name} {from_version} -> {}", skill.version),
+                    Err(e) => {
+                        failed += 1;
+                        eprintln!("{name}: {e}");
+                    }
+                }
+            }
+            if failed > 0 {
+                return Err(anyhow::anyhow!("{failed} skill(s) failed to update"));
+            }
+        }
+        SkillsCommand::Remove { name } => {
+            if !store.remove(&name).await? {
+                return Err(anyhow::anyhow!("skill {name} is not installed"));
+            }
+            println!("removed {name}");
+        }
+    }
     Ok(())
 }
 
@@ -60,27 +331,366 @@ pub async fn send_one_shot(
     Ok(())
 }
 
-pub async fn serve(config_path: Option<PathBuf>) -> Result<()> {
+pub struct AskOptions {
+    pub prompt: String,
+    pub persona: Option<String>,
+    /// Only offer these tools (by name); `None` offers every locally available one.
+    pub tools: Option<Vec<String>>,
+    pub json: bool,
+}
+
+/// Run one assistant turn outside the server and print the answer, or with `json` the
+/// answer plus run metadata. Delegation and follow-ups are off: there is no conversation
+/// to come back to.
+pub async fn ask(config_path: Option<PathBuf>, opts: AskOptions) -> Result<()> {
     let cfg = OpenShellConfig::load(config_path).await?;
-    let started_at = Instant::now();
+    if let Some(persona) = opts.persona.as_deref() {
+        if !cfg.personas.contains_key(persona) {
+            return Err(anyhow::anyhow!("unknown persona: {persona}"));
+        }
+    }
+    let Some(llm) = cfg.llm_client_for_model() else {
+        return Err(anyhow::anyhow!(
+            "no api key for {}; set keys in the config",
+            cfg.general.model
+        ));
+    };
 
     let data_dir = PathBuf::from("data");
     let runtime = dev_backends::build_dev_runtime(&cfg, &data_dir).await?;
+    let cipher = DataCipher::from_config(&cfg.encryption)?;
+    let archive = if cfg.archive.enabled {
+        Some(Arc::new(
+            ConversationArchive::from_config(
+                &cfg,
+                &data_dir,
+                cfg.embedding_client(),
+                cipher.clone(),
+            )
+            .await?,
+        ))
+    } else {
+        None
+    };
+    let (mut tools, _) = local_tools(&cfg, archive.as_ref()).await?;
+    let summarizer = tool_results(&cfg, &runtime, &mut tools);
+    if let Some(only) = opts.tools.as_ref() {
+        if let Some(unknown) = only
+            .iter()
+            .find(|n| !tools.iter().any(|t| t.spec().name == **n))
+        {
+            return Err(anyhow::anyhow!(
+                "tool {unknown} is not enabled in the config"
+            ));
+        }
+        tools.retain(|t| only.contains(&t.spec().name));
+    }
+    let mut assistant = AssistantAgent::new(
+        cfg.clone(),
+        Some(
+            llm.with_reasoning(cfg.general.reasoning.clone())
+                .with_sampling(cfg.general.sampling.clone())
+                .with_routing(cfg.general.routing.clone()),
+        ),
+        tools,
+        runtime.memory.clone(),
+        runtime.project_db.clone(),
+        runtime.core_agents.clone(),
+        runtime.org_id,
+        runtime.project_id,
+        runtime.project_db_handle.clone(),
+        runtime.evaluation.clone(),
+    )
+    .with_audit(Arc::new(
+        AuditLog::new(data_dir.clone()).with_cipher(cipher),
+    ));
+    if let Some(summarizer) = summarizer {
+        assistant = assistant.with_tool_results(summarizer);
+    }
+    if let Some(store) = facts.as_ref() {
+        let model = cfg
+            .memory
+            .facts
+            .model
+            .clone()
+            .unwrap_or_else(|| cfg.general.model.clone());
+        let extractor = cfg
+            .llm_client(&model)
+            .map(|llm| Arc::new(FactExtractor::new(llm, store.clone())));
+        if extractor.is_none() {
+            tracing::warn!(%model, "no api key for fact extraction; known facts are still used");
+        }
+        assistant = assistant.with_facts(store.clone(), extractor);
+    }
 
-    // Tools.
+    let mut session = Session::new();
+    session.persona = opts.persona.clone();
+    let budget = RunBudget {
+        allow_delegation: false,
+        ..RunBudget::interactive()
+    };
+    let deadline = cfg
+        .watchdog
+        .enabled
+        .then(|| std::time::Duration::from_secs(cfg.watchdog.run_timeout_seconds));
+    let started = Instant::now();
+    let run = assistant.run_with_budget("cli", "cli", &mut session, &opts.prompt, budget);
+    let outcome = tasks::run_cancellable(os_tools::CancellationToken::new(), deadline, run)
+        .await
+        .unwrap_or_else(|| Err(anyhow::anyhow!("run timed out")));
+    let duration_ms = started.elapsed().as_millis() as u64;
+    let redactor = cfg
+        .redaction
+        .enabled
+        .then(|| Redactor::new(&cfg))
+        .transpose()?;
+    let outcome = outcome.map(|answer| match redactor.as_ref() {
+        Some(redactor) => redactor.redact(&answer),
+        None => answer,
+    });
+
+    if !opts.json {
+        println!("{}", outcome?);
+        return Ok(());
+    }
+    let tool_calls: Vec<&str> = session
+        .history
+        .iter()
+        .flat_map(|m| m.tool_calls.iter().map(|c| c.name.as_str()))
+        .collect();
+    let (answer, error) = match &outcome {
+        Ok(answer) => (Some(answer.as_str()), None),
+        Err(e) => (None, Some(e.to_string())),
+    };
+    let out = serde_json::json!({
+        "status": if error.is_none() { "ok" } else { "error" },
+        "answer": answer,
+        "error": error,
+        "model": cfg.general.model,
+        "persona": opts.persona,
+        "duration_ms": duration_ms,
+        "usage": {
+            "prompt_tokens": session.usage_totals.prompt_tokens,
+            "completion_tokens": session.usage_totals.completion_tokens,
+            "reasoning_tokens": session.usage_totals.reasoning_tokens,
+        },
+        "tool_calls": tool_calls,
+    });
+    println!("{}", serde_json::to_string_pretty(&out)?);
+    // Non-zero exit for scripts, after the JSON has been printed.
+    outcome.map(|_| ())
+}
+
+/// Tools that work without the gateway: everything except delegation, follow-ups and
+/// translation, which need the outbox or a second model.
+async fn local_tools(
+    cfg: &OpenShellConfig,
+    archive: Option<&Arc<ConversationArchive>>,
+) -> Result<(Vec<Arc<dyn Tool>>, Vec<CodePreset>)> {
     let mut tools: Vec<Arc<dyn Tool>> = Vec::new();
     if cfg.tools.shell {
-        tools.push(Arc::new(ShellTool::new(std::time::Duration::from_secs(30))));
+        let policy = &cfg.tools.shell_policy;
+        let timeout = std::time::Duration::from_secs(policy.timeout_seconds);
+        tools.push(Arc::new(
+            ShellTool::new(timeout)
+                .with_policy(ShellPolicy {
+                    timeout,
+                    max_output_bytes: policy.max_output_bytes,
+                })
+                .with_session_idle_timeout(std::time::Duration::from_secs(
+                    cfg.tools.shell_session_idle_seconds,
+                )),
+        ));
     }
-    if cfg.tools.filesystem {
-        tools.push(Arc::new(FilesystemTool::new(std::env::current_dir()?)?));
+    // The email tool may attach files from the same directories the filesystem tool
+    // can reach, and none when it's off.
+    let mut file_roots = Vec::new();
+    if cfg.tools.filesystem.enabled {
+        file_roots = cfg.tools.filesystem.allowed_root_paths()?;
+        if file_roots.is_empty() {
+            file_roots.push(std::env::current_dir()?);
+        }
+        tools.push(Arc::new(
+            FilesystemTool::new(std::env::current_dir()?)?
+                .with_allowed_roots(file_roots.clone())?,
+        ));
     }
     if cfg.tools.clipboard {
         tools.push(Arc::new(ClipboardTool::new()));
     }
+    #[cfg(target_os = "macos")]
+    {
+        if cfg.tools.apple_reminders {
+            tools.push(Arc::new(os_tools::AppleRemindersTool::new()));
+        }
+        if cfg.tools.apple_notes {
+            tools.push(Arc::new(os_tools::AppleNotesTool::new()));
+        }
+    }
+    #[cfg(not(target_os = "macos"))]
+    if cfg.tools.apple_reminders || cfg.tools.apple_notes {
+        tracing::warn!("tools.apple_reminders and tools.apple_notes only work on macOS; ignoring");
+    }
+    if cfg.tools.todoist.enabled {
+        tools.push(Arc::new(os_tools::TodoistTool::new(
+            cfg.tools.todoist.api_token.trim(),
+        )));
+    }
+    let mut email_tool: Option<Arc<dyn Tool>> = None;
+    if cfg.tools.email.enabled {
+        let email = &cfg.tools.email;
+        let tool: Arc<dyn Tool> = Arc::new(
+            os_tools::EmailTool::new(
+                email.gmail_client_id.trim(),
+                email.gmail_client_secret.trim(),
+                email.gmail_refresh_token.trim(),
+            )
+            .with_actions(os_tools::EmailActions {
+                labels: email.labels,
+                archive: email.archive,
+                mark_read: email.mark_read,
+                star: email.star,
+            })
+            .with_attachments(
+                PathBuf::from("data").join("email"),
+                email.attachment_max_bytes,
+            )
+            .with_attachment_roots(file_roots)?,
+        );
+        tools.push(tool.clone());
+        email_tool = Some(tool);
+    }
     if cfg.tools.browser {
-        tools.push(Arc::new(BrowserTool::new()));
+        let policy = &cfg.tools.browser_policy;
+        tools.push(Arc::new(BrowserTool::new().with_policy(NetworkPolicy {
+            allow_hosts: policy.allow_hosts.clone(),
+            deny_hosts: policy.deny_hosts.clone(),
+            allow_private_networks: policy.allow_private_networks,
+            allowed_content_types: policy.allowed_content_types.clone(),
+        })));
+    }
+    let mut code_presets = Vec::new();
+    if cfg.tools.code_run.enabled {
+        let code_run = CodeRunTool::discover(CodeRunOptions {
+            timeout: std::time::Duration::from_secs(cfg.tools.code_run.timeout_seconds),
+            prefer_containers: cfg.tools.code_run.prefer_containers,
+            container_cli: cfg.tools.code_run.container_cli.clone(),
+            images: cfg.tools.code_run.images.clone(),
+        })
+        .await;
+        code_presets = code_run.presets().to_vec();
+        if code_presets.is_empty() {
+            tracing::warn!("code_run enabled but no container runtime or toolchain found");
+        } else {
+            tools.push(Arc::new(code_run));
+        }
+    }
+    if let Some(archive) = archive {
+        tools.push(Arc::new(
+            ConversationSearchTool::new(archive.clone())
+                .with_access(cfg.inbound.priority.owners.clone(), cfg.identities.clone()),
+        ));
+    }
+    // Only worth a second search tool when there is more than the archive to search.
+    if cfg.channels.imessage.enabled || email_tool.is_some() {
+        let mut search = SearchEverywhereTool::new(archive.cloned())
+            .with_access(cfg.inbound.priority.owners.clone(), cfg.identities.clone());
+        if cfg.channels.imessage.enabled {
+            let source_db = cfg
+                .channels
+                .imessage
+                .source_db
+                .clone()
+                .map(|p| expand_home(&p))
+                .transpose()?
+                .unwrap_or_else(ImessageAdapter::default_source_db);
+            search = search.with_imessage(ImessageAdapter::new(source_db));
+        }
+        if let Some(email) = email_tool {
+            search = search.with_email(email);
+        }
+        tools.push(Arc::new(search));
+    }
+
+    Ok((tools, code_presets))
+}
+
+/// The `[tools.results]` summarizer, adding `memory_search` to `tools` when there is
+/// memory to save full outputs in.
+fn tool_results(
+    cfg: &OpenShellConfig,
+    runtime: &dev_backends::DevRuntime,
+    tools: &mut Vec<Arc<dyn Tool>>,
+) -> Option<Arc<ToolResultSummarizer>> {
+    if !cfg.tools.results.summarize {
+        return None;
+    }
+    let model = cfg
+        .tools
+        .results
+        .model
+        .clone()
+        .unwrap_or_else(|| cfg.general.model.clone());
+    let llm = cfg.llm_client(&model);
+    if llm.is_none() {
+        tracing::warn!(%model, "no api key for tool output summaries; long outputs will be cut");
     }
+    if let Some(memory) = runtime.memory.as_ref() {
+        tools.push(Arc::new(MemorySearchTool::new(
+            memory.clone(),
+            runtime.org_id,
+        )));
+    }
+    Some(Arc::new(ToolResultSummarizer::new(
+        cfg.tools.results.clone(),
+        llm,
+        runtime.memory.clone(),
+        runtime.org_id,
+    )))
+}
+
+pub async fn serve(config_path: Option<PathBuf>) -> Result<()> {
+    let cfg = OpenShellConfig::load(config_path.clone()).await?;
+    let started_at = Instant::now();
+
+    let data_dir = PathBuf::from("data");
+    let runtime = dev_backends::build_dev_runtime(&cfg, &data_dir).await?;
+
+    let attachments = if cfg.attachments.enabled {
+        Some(Arc::new(
+            AttachmentStore::open(&data_dir.join("attachments"), cfg.attachments.max_bytes)?
+                .with_network_policy(NetworkPolicy {
+                    allowed_content_types: cfg.attachments.allowed_content_types.clone(),
+                    ..NetworkPolicy::default()
+                })
+                .with_local_files("imessage", data_dir.join("imessage-attachments")),
+        ))
+    } else {
+        None
+    };
+
+    let cipher = DataCipher::from_config(&cfg.encryption)?;
+    let archive = if cfg.archive.enabled {
+        Some(Arc::new(
+            ConversationArchive::from_config(
+                &cfg,
+                &data_dir,
+                cfg.embedding_client(),
+                cipher.clone(),
+            )
+            .await?,
+        ))
+    } else {
+        None
+    };
+
+    let (mut tools, code_presets) = local_tools(&cfg, archive.as_ref()).await?;
+
+    let sessions = Arc::new(
+        SessionManager::new().with_archive_retention(chrono::Duration::hours(
+            cfg.sessions.archive_retention_hours as i64,
+        )),
+    );
 
     // Channels.
     let (inbound_tx, inbound_rx) = tokio::sync::mpsc::channel(1024);
@@ -88,7 +698,26 @@ pub async fn serve(config_path: Option<PathBuf>) -> Result<()> {
 
     let mut webchat_adapter: Option<Arc<WebChatAdapter>> = None;
     if cfg.channels.webchat.enabled {
-        let webchat = Arc::new(WebChatAdapter::new());
+        let history = sessions.clone();
+        let webchat = Arc::new(WebChatAdapter::new().with_history(move |sender_id| {
+            history
+                .recent_messages(
+                    "webchat",
+                    sender_id,
+                    chrono::DateTime::<chrono::Utc>::MIN_UTC,
+                    WEBCHAT_HISTORY,
+                )
+                .into_iter()
+                .map(|m| HistoryMessage {
+                    role: match m.role {
+                        Role::User => "user",
+                        _ => "assistant",
+                    }
+                    .to_string(),
+                    content: m.content,
+                })
+                .collect()
+        }));
         webchat.start(inbound_tx.clone()).await?;
         channels.insert("webchat".to_string(), webchat.clone());
         webchat_adapter = Some(webchat);
@@ -116,24 +745,187 @@ pub async fn serve(config_path: Option<PathBuf>) -> Result<()> {
             .transpose()?
             .unwrap_or_else(ImessageAdapter::default_source_db);
 
-        let im = Arc::new(
-            ImessageAdapter::new(source_db)
-                .with_poll_interval(std::time::Duration::from_millis(
-                    cfg.channels.imessage.poll_interval_ms,
-                ))
-                .with_start_from_latest(cfg.channels.imessage.start_from_latest)
-                .with_group_prefixes(cfg.channels.imessage.group_prefixes.clone()),
-        );
+        let im = ImessageAdapter::new(source_db)
+            .with_poll_interval(std::time::Duration::from_millis(
+                cfg.channels.imessage.poll_interval_ms,
+            ))
+            .with_start_from_latest(cfg.channels.imessage.start_from_latest)
+            .with_group_prefixes(cfg.channels.imessage.group_prefixes.clone())
+            .with_delivery_timeout(std::time::Duration::from_secs(
+                cfg.channels.imessage.delivery_timeout_seconds,
+            ));
+        let im = match cfg.channels.imessage.send_shortcut.as_deref() {
+            Some(shortcut) => im.with_send_shortcut(shortcut),
+            None => im,
+        };
+        // Copies are only useful when the gateway stores attachments for the assistant.
+        let im = if cfg.attachments.enabled {
+            im.with_attachments_dir(data_dir.join("imessage-attachments"))
+                .with_max_attachment_bytes(cfg.attachments.max_bytes)
+        } else {
+            im
+        };
+        let im = Arc::new(im);
         im.start(inbound_tx.clone()).await?;
         channels.insert("imessage".to_string(), im);
     }
 
-    let llm = cfg
-        .api_key_for_model()
-        .map(|key| os_llm::LlmClient::new(&key, &cfg.general.model));
+    if cfg.channels.ntfy.enabled {
+        let ntfy = Arc::new(
+            NtfyAdapter::new(&cfg.channels.ntfy.server, &cfg.channels.ntfy.topic)
+                .with_token(cfg.channels.ntfy.token.clone()),
+        );
+        channels.insert("ntfy".to_string(), ntfy);
+    }
+
+    if cfg.channels.pushover.enabled {
+        let pushover = Arc::new(
+            PushoverAdapter::new(
+                &cfg.channels.pushover.app_token,
+                &cfg.channels.pushover.user_key,
+            )
+            .with_device(cfg.channels.pushover.device.clone()),
+        );
+        channels.insert("pushover".to_string(), pushover);
+    }
+
+    // Only produces messages, into the deliver_to conversation, so it isn't an outbox channel.
+    if cfg.channels.calendar.enabled {
+        let cal = &cfg.channels.calendar;
+        if let Some((channel, recipient)) = cal.delivery_target() {
+            let mut calendar = CalendarAdapter::new(channel, recipient)
+                .with_lead_time(std::time::Duration::from_secs(cal.lead_minutes * 60))
+                .with_poll_interval(std::time::Duration::from_secs(cal.poll_interval_seconds))
+                .with_calendars(cal.calendars.clone());
+            if let Some(prompt) = cal.prompt.as_deref() {
+                calendar = calendar.with_prompt(prompt);
+            }
+            calendar.start(inbound_tx.clone()).await?;
+        }
+    }
+
+    let webhooks = Arc::new(Webhooks::new(&cfg.webhooks));
+    webhooks.clone().watch_channels(channels.clone());
+    let mut channel_ids: Vec<String> = channels.keys().cloned().collect();
+    channel_ids.sort();
+    let mut metrics = Metrics::new(channel_ids).with_inbound_queue(&inbound_tx);
+    if let Some(pool) = archive.as_ref().and_then(|a| a.sqlite_pool()) {
+        metrics = metrics.with_sqlite("archive", pool);
+    }
+    if let Some(attachments) = attachments.as_ref() {
+        metrics = metrics.with_sqlite("attachments", attachments.sqlite_pool());
+    }
+    let facts = if cfg.memory.facts.enabled {
+        let store = Arc::new(
+            FactStore::open(&data_dir.join("facts.db"))?
+                .with_cipher(cipher.clone())
+                .with_retention(cfg.memory.retention.clone()),
+        );
+        metrics = metrics.with_sqlite("facts", store.sqlite_pool());
+        store.clone().start_janitor();
+        Some(store)
+    } else {
+        None
+    };
+    let feedback =
+        Arc::new(FeedbackStore::open(&data_dir.join("feedback.db"))?.with_cipher(cipher.clone()));
+    metrics = metrics.with_sqlite("feedback", feedback.sqlite_pool());
+    let metrics = Arc::new(metrics);
+    let audit = Arc::new(AuditLog::new(data_dir.clone()).with_cipher(cipher.clone()));
+    let grant_offers = Arc::new(GrantOffers::default());
+    let outbox = Outbox::new(channels.clone())
+        .with_formatting(cfg.formatting.clone())
+        .with_recipients(cfg.recipients.clone())
+        .with_retries(&cfg.outbox)
+        .with_dead_letter_db(&data_dir.join("outbox.db"), cipher.clone())?
+        .with_audit(audit.clone())
+        .with_webhooks(webhooks.clone())
+        .with_metrics(metrics.clone())
+        .with_simulation(cfg.simulation.enabled);
+    if cfg.simulation.enabled {
+        tracing::warn!(
+            "simulation mode: tool calls that change anything won't run and nothing is sent"
+        );
+    }
+    let outbox = Arc::new(if cfg.redaction.enabled {
+        outbox.with_redactor(Redactor::new(&cfg)?)
+    } else {
+        outbox
+    });
+    let approval_batches = ApprovalBatches::default();
+    let approval_batches = Arc::new(
+        match (
+            cfg.approvals.notify_channel.as_deref(),
+            cfg.approvals.notify_recipient.as_deref(),
+        ) {
+            (Some(channel_id), Some(recipient)) => {
+                approval_batches.with_notify(outbox.clone(), channel_id, recipient)
+            }
+            _ => approval_batches,
+        },
+    );
+    let tasks = Arc::new(
+        TaskRegistry::new(cfg.tasks.clone(), outbox.clone()).with_webhooks(webhooks.clone()),
+    );
+    if cfg.tasks.enabled {
+        tools.push(Arc::new(DelegateTaskTool::new(tasks.clone())));
+    }
+    let continuations = Arc::new(ContinuationRegistry::new(
+        cfg.continuations.clone(),
+        tasks.clone(),
+        inbound_tx.clone(),
+    ));
+    if cfg.continuations.enabled {
+        tools.push(Arc::new(ScheduleFollowupTool::new(continuations.clone())));
+    }
+    continuations.clone().start();
+    let automation = Arc::new(
+        AutomationEngine::open(cfg.automation.clone(), outbox.clone(), inbound_tx.clone())?
+            .with_webhooks(webhooks.clone())
+            .with_personas(cfg.personas.keys().cloned())
+            .with_email(tools.iter().find(|t| t.spec().name == "email").cloned()),
+    );
+
+    let translation_model = cfg
+        .translation
+        .model
+        .clone()
+        .unwrap_or_else(|| cfg.general.model.clone());
+    let translator = (cfg.translation.enabled
+        || !cfg.translation.auto_translate_channels.is_empty())
+    .then(|| cfg.llm_client(&translation_model))
+    .flatten()
+    .map(|llm| Translator::new(llm).with_pii(&cfg.pii))
+    .transpose()?
+    .map(Arc::new);
+    if cfg.translation.enabled {
+        if let Some(translator) = translator.as_ref() {
+            tools.push(Arc::new(TranslateTool::new(translator.clone())));
+        }
+    }
+
+    let summarizer = tool_results(&cfg, &runtime, &mut tools);
+    let llm = cfg.llm_client_for_model().map(|llm| {
+        llm.with_reasoning(cfg.general.reasoning.clone())
+            .with_sampling(cfg.general.sampling.clone())
+            .with_routing(cfg.general.routing.clone())
+    });
 
-    let sessions = Arc::new(SessionManager::new());
-    let assistant = Arc::new(AssistantAgent::new(
+    let integrity = Arc::new(
+        IntegrityMonitor::new(cfg.clone(), config_path, data_dir.clone())
+            .with_webhooks(webhooks.clone()),
+    );
+    integrity.clone().start(outbox.clone());
+
+    let watchdog = Arc::new(
+        Watchdog::new(cfg.watchdog.clone(), data_dir.clone(), outbox.clone())
+            .with_webhooks(webhooks.clone()),
+    );
+    watchdog.clone().start();
+
+    let skills = Arc::new(SkillStore::open(cfg.skills.clone(), &data_dir).await?);
+    let wasm = Arc::new(WasmRuntime::new(&cfg.skills)?);
+    let mut assistant = AssistantAgent::new(
         cfg.clone(),
         llm,
         tools,
@@ -144,17 +936,109 @@ pub async fn serve(config_path: Option<PathBuf>) -> Result<()> {
         runtime.project_id,
         runtime.project_db_handle.clone(),
         runtime.evaluation.clone(),
-    ));
+    )
+    .with_webhooks(webhooks)
+    .with_admin(Arc::new(AdminAlerts::new(&cfg.admin, outbox.clone())))
+    .with_audit(audit.clone())
+    .with_grant_offers(grant_offers.clone())
+    .with_approval_batches(approval_batches.clone())
+    .with_metrics(metrics.clone())
+    .with_channels(&channels)
+    .with_skills(skills.clone(), wasm);
+    if let Some(summarizer) = summarizer {
+        assistant = assistant.with_tool_results(summarizer);
+    }
+    let assistant = Arc::new(assistant);
+    tasks.attach_assistant(&assistant);
+    automation.attach_assistant(&assistant);
+    automation.clone().start();
 
-    let gateway = Arc::new(Gateway::new(
+    let suggestions = Arc::new(SuggestionQueue::new(
+        cfg.suggestions.clone(),
+        outbox.clone(),
+    ));
+    let focus = Arc::new(FocusMode::new(
+        cfg.focus.clone(),
+        outbox.clone(),
+        inbound_tx.clone(),
+    ));
+    focus.clone().start();
+    let mut gateway = Gateway::new(
         cfg.clone(),
         started_at,
         sessions.clone(),
-        assistant,
-        channels.clone(),
+        assistant.clone(),
+        outbox.clone(),
         inbound_rx,
-    ));
+        integrity.clone(),
+        attachments.clone(),
+        archive.clone(),
+        watchdog.clone(),
+        translator,
+    )
+    .with_metrics(metrics.clone());
+    if !cfg.suggestions.channels.is_empty() {
+        gateway = gateway.with_suggestions(suggestions.clone());
+    }
+    if cfg.focus.enabled {
+        gateway = gateway.with_focus(focus.clone());
+    }
+    if cfg.automation.enabled {
+        gateway = gateway.with_automation(automation.clone());
+    }
+    if cfg.cluster.database_url.is_some() {
+        let cluster = Cluster::connect(&cfg.cluster, cipher.clone()).await?;
+        gateway = gateway.with_cluster(Arc::new(cluster));
+    }
+    if cfg.inbound.journal {
+        let (journal, replay) = InboundJournal::open(&data_dir, cipher.clone())?;
+        gateway = gateway.with_journal(Arc::new(journal));
+        let inbound_tx = inbound_tx.clone();
+        tokio::spawn(async move {
+            for inbound in replay {
+                if inbound_tx.send(inbound).await.is_err() {
+                    return;
+                }
+            }
+        });
+    }
+    let shares = if cfg.shares.enabled {
+        let store = Arc::new(ShareStore::open(cfg.shares.clone(), &data_dir).await?);
+        gateway = gateway.with_shares(store.clone());
+        let listener = tokio::net::TcpListener::bind(&cfg.shares.listen).await?;
+        tracing::info!(addr = %cfg.shares.listen, "serving share links");
+        let app = shares::public_router(store.clone());
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                tracing::error!(%e, "share link server exited");
+            }
+        });
+        Some(store)
+    } else {
+        None
+    };
+    if let Some(store) = facts.as_ref() {
+        gateway = gateway.with_facts(store.clone());
+    }
+    gateway = gateway.with_feedback(feedback.clone());
+    let pairing = if cfg.pairing.enabled {
+        let store = Arc::new(PairingStore::open(cfg.pairing.clone(), &data_dir).await?);
+        gateway = gateway.with_pairing(store.clone());
+        Some(store)
+    } else {
+        None
+    };
+    let gateway = Arc::new(gateway);
     gateway.start();
+    if cfg.supervisor.enabled {
+        Arc::new(Supervisor::new(
+            cfg.supervisor.clone(),
+            channels.clone(),
+            &inbound_tx,
+            outbox.clone(),
+        ))
+        .start();
+    }
 
     let os_state = Arc::new(OsState {
         cfg: cfg.clone(),
@@ -163,10 +1047,33 @@ pub async fn serve(config_path: Option<PathBuf>) -> Result<()> {
         project_db_handle: runtime.project_db_handle.clone(),
         channels: channels.clone(),
         sessions: sessions.clone(),
+        assistant,
         memory: runtime.memory.clone(),
+        integrity,
+        attachments,
+        archive,
+        tasks,
+        outbox,
+        watchdog,
+        continuations,
+        audit,
+        grant_offers,
+        approval_batches,
+        core_agents: runtime.core_agents.clone(),
+        suggestions,
+        focus,
+        metrics,
+        shares,
+        pairing,
+        facts,
+        feedback,
+        skills,
+        automation,
+        data_dir,
+        code_presets,
     });
 
-    let mut os_router = routes::router().layer(axum::Extension(os_state.clone()));
+    let mut os_router = routes::router(&cfg.control).layer(axum::Extension(os_state.clone()));
     if let Some(webchat) = webchat_adapter {
         os_router = os_router.merge(webchat.clone().router());
     }
@@ -180,12 +1087,3 @@ pub async fn serve(config_path: Option<PathBuf>) -> Result<()> {
 
     Ok(())
 }
-
-fn expand_home(path: &str) -> Result<std::path::PathBuf> {
-    let trimmed = path.trim().to_string();
-    if !trimmed.starts_with("~/") {
-        return Ok(std::path::PathBuf::from(trimmed));
-    }
-    let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
-    Ok(std::path::PathBuf::from(trimmed.replacen("~", &home, 1)))
-}
@@ -1,13 +1,19 @@
 //! Session manager for (channel_id, sender_id) isolation.
 //!
+//! Deleting a session archives it; archived sessions can be restored until the retention
+//! window passes, after which they are purged.
+//!
 //! See: specifications/openshell/implementation_v0_1_0.md
 
+use crate::grants::SessionGrants;
+use crate::pii::PiiVault;
+use crate::trace::{RunTrace, TraceStep};
 use chrono::{DateTime, Utc};
 use dashmap::DashMap;
-use os_llm::{ChatMessage, Usage};
+use os_llm::{ChatMessage, Role, Usage};
 use uuid::Uuid;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Session {
     pub id: Uuid,
     pub history: Vec<ChatMessage>,
@@ -18,10 +24,27 @@ pub struct Session {
     pub usage_totals: Usage,
     pub last_assistant_message_id: Option<String>,
     pub last_user_message_id: Option<String>,
+    /// Persona picked with `/persona`; `None` falls back to the channel default.
+    pub persona: Option<String>,
+    /// The same person's recent messages on their other channels, set by the gateway for
+    /// the next run only.
+    pub linked_context: Option<String>,
+    /// Skill named with `/skill`, set by the gateway for the next run only.
+    pub skill: Option<String>,
+    /// Persona an automation rule asked for, set by the gateway for the next run only.
+    #[serde(default)]
+    pub run_persona: Option<String>,
+    /// Placeholders handed to the LLM for personal data in this conversation.
+    pub pii: PiiVault,
+    /// Tool calls the owner said not to ask about again.
+    pub grants: SessionGrants,
+    /// What the last run did; see `trace`.
+    #[serde(default)]
+    pub last_trace: Option<RunTrace>,
 }
 
 impl Session {
-    fn new() -> Self {
+    pub fn new() -> Self {
         let now = Utc::now();
         Self {
             id: Uuid::new_v4(),
@@ -30,37 +53,82 @@ impl Session {
             last_active: now,
             show_thinking: false,
             show_tool_calls: false,
-            usage_totals: Usage {
-                prompt_tokens: 0,
-                completion_tokens: 0,
-            },
+            usage_totals: Usage::default(),
             last_assistant_message_id: None,
             last_user_message_id: None,
+            persona: None,
+            linked_context: None,
+            skill: None,
+            run_persona: None,
+            pii: PiiVault::default(),
+            grants: SessionGrants::default(),
+            last_trace: None,
         }
     }
 
     pub fn reset(&mut self) {
         self.history.clear();
-        self.usage_totals.prompt_tokens = 0;
-        self.usage_totals.completion_tokens = 0;
+        self.pii = PiiVault::default();
+        self.grants = SessionGrants::default();
+        self.usage_totals = Usage::default();
         self.last_assistant_message_id = None;
         self.last_user_message_id = None;
         self.last_active = Utc::now();
     }
+
+    /// Add a step to the current run's trace.
+    pub fn trace(&mut self, step: TraceStep) {
+        if let Some(trace) = self.last_trace.as_mut() {
+            trace.steps.push(step);
+        }
+    }
+
+    /// The most recent answer the assistant gave in this conversation.
+    pub fn last_answer(&self) -> Option<&str> {
+        self.history
+            .iter()
+            .rev()
+            .find(|m| matches!(m.role, Role::Assistant) && !m.content.trim().is_empty())
+            .map(|m| m.content.as_str())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ArchivedSession {
+    key: (String, String),
+    session: Session,
+    archived_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestoreOutcome {
+    Restored,
+    NotFound,
+    /// The conversation has started a new session since; restoring would overwrite it.
+    Conflict,
 }
 
 #[derive(Clone)]
 pub struct SessionManager {
     sessions: DashMap<(String, String), Session>,
+    archived: DashMap<Uuid, ArchivedSession>,
+    archive_retention: chrono::Duration,
 }
 
 impl SessionManager {
     pub fn new() -> Self {
         Self {
             sessions: DashMap::new(),
+            archived: DashMap::new(),
+            archive_retention: chrono::Duration::days(7),
         }
     }
 
+    pub fn with_archive_retention(mut self, retention: chrono::Duration) -> Self {
+        self.archive_retention = retention;
+        self
+    }
+
     pub fn get_or_create_mut(
         &self,
         channel_id: &str,
@@ -71,6 +139,44 @@ impl SessionManager {
             .or_insert_with(Session::new)
     }
 
+    pub fn get(&self, channel_id: &str, sender_id: &str) -> Option<Session> {
+        let key = (channel_id.to_string(), sender_id.to_string());
+        self.sessions.get(&key).map(|s| s.clone())
+    }
+
+    /// Replace a conversation's session, e.g. with the copy another replica saved.
+    pub fn insert(&self, channel_id: &str, sender_id: &str, session: Session) {
+        self.sessions
+            .insert((channel_id.to_string(), sender_id.to_string()), session);
+    }
+
+    /// The last `max` user and assistant messages of a session active since `since`.
+    pub fn recent_messages(
+        &self,
+        channel_id: &str,
+        sender_id: &str,
+        since: DateTime<Utc>,
+        max: usize,
+    ) -> Vec<ChatMessage> {
+        let key = (channel_id.to_string(), sender_id.to_string());
+        let Some(session) = self.sessions.get(&key) else {
+            return Vec::new();
+        };
+        if session.last_active < since {
+            return Vec::new();
+        }
+        let mut out: Vec<ChatMessage> = session
+            .history
+            .iter()
+            .rev()
+            .filter(|m| matches!(m.role, Role::User | Role::Assistant) && !m.content.is_empty())
+            .take(max)
+            .cloned()
+            .collect();
+        out.reverse();
+        out
+    }
+
     pub fn list(&self) -> Vec<SessionSummary> {
         let mut out: Vec<SessionSummary> = self
             .sessions
@@ -84,6 +190,8 @@ impl SessionManager {
                     created_at: s.created_at,
                     last_active: s.last_active,
                     messages: s.history.len(),
+                    persona: s.persona.clone(),
+                    usage: s.usage_totals.clone(),
                 }
             })
             .collect();
@@ -92,20 +200,129 @@ impl SessionManager {
         out
     }
 
-    pub fn delete_by_id(&self, id: Uuid) -> bool {
-        let mut to_remove = None;
-        for e in self.sessions.iter() {
+    pub fn set_persona_by_id(&self, id: Uuid, persona: Option<String>) -> bool {
+        for mut e in self.sessions.iter_mut() {
             if e.value().id == id {
-                to_remove = Some(e.key().clone());
-                break;
+                e.value_mut().persona = persona;
+                return true;
             }
         }
-        if let Some(key) = to_remove {
+        false
+    }
+
+    /// Soft-delete: move the session to the archive, where it can be restored.
+    pub fn archive_by_id(&self, id: Uuid) -> bool {
+        self.purge_expired();
+        let Some(key) = self.key_for(id) else {
+            return false;
+        };
+        let Some((key, session)) = self.sessions.remove(&key) else {
+            return false;
+        };
+        self.archived.insert(
+            id,
+            ArchivedSession {
+                key,
+                session,
+                archived_at: Utc::now(),
+            },
+        );
+        true
+    }
+
+    pub fn restore_by_id(&self, id: Uuid) -> RestoreOutcome {
+        self.purge_expired();
+        let Some((_, archived)) = self.archived.remove(&id) else {
+            return RestoreOutcome::NotFound;
+        };
+        match self.sessions.entry(archived.key.clone()) {
+            dashmap::Entry::Occupied(_) => {
+                self.archived.insert(id, archived);
+                RestoreOutcome::Conflict
+            }
+            dashmap::Entry::Vacant(v) => {
+                v.insert(archived.session);
+                RestoreOutcome::Restored
+            }
+        }
+    }
+
+    /// Move a conversation's session to another `(channel_id, sender_id)`, which carries
+    /// on with its history. Refused when a session with messages is already there, so a
+    /// move never displaces another conversation. Returns the moved session's id.
+    pub fn move_to(
+        &self,
+        channel_id: &str,
+        sender_id: &str,
+        to_channel_id: &str,
+        to_sender_id: &str,
+    ) -> Option<Uuid> {
+        let to = (to_channel_id.to_string(), to_sender_id.to_string());
+        if (channel_id, sender_id) == (to_channel_id, to_sender_id) {
+            return None;
+        }
+        if self
+            .sessions
+            .get(&to)
+            .is_some_and(|s| !s.history.is_empty())
+        {
+            return None;
+        }
+        let (_, mut session) = self
+            .sessions
+            .remove(&(channel_id.to_string(), sender_id.to_string()))?;
+        session.last_active = Utc::now();
+        let id = session.id;
+        self.sessions.insert(to, session);
+        Some(id)
+    }
+
+    /// Hard delete, from either the live sessions or the archive.
+    pub fn delete_by_id(&self, id: Uuid) -> bool {
+        if self.archived.remove(&id).is_some() {
+            return true;
+        }
+        if let Some(key) = self.key_for(id) {
             self.sessions.remove(&key);
             return true;
         }
         false
     }
+
+    pub fn list_archived(&self) -> Vec<ArchivedSessionSummary> {
+        self.purge_expired();
+        let mut out: Vec<ArchivedSessionSummary> = self
+            .archived
+            .iter()
+            .map(|e| {
+                let a = e.value();
+                ArchivedSessionSummary {
+                    id: a.session.id,
+                    channel_id: a.key.0.clone(),
+                    sender_id: a.key.1.clone(),
+                    archived_at: a.archived_at,
+                    restorable_until: a.archived_at + self.archive_retention,
+                    messages: a.session.history.len(),
+                }
+            })
+            .collect();
+        out.sort_by_key(|s| s.archived_at);
+        out.reverse();
+        out
+    }
+
+    /// The `(channel_id, sender_id)` a live session belongs to.
+    pub fn key_for(&self, id: Uuid) -> Option<(String, String)> {
+        self.sessions
+            .iter()
+            .find(|e| e.value().id == id)
+            .map(|e| e.key().clone())
+    }
+
+    fn purge_expired(&self) {
+        let cutoff = Utc::now() - self.archive_retention;
+        self.archived.retain(|_, a| a.archived_at > cutoff);
+    }
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -116,4 +333,108 @@ pub struct SessionSummary {
     pub created_at: DateTime<Utc>,
     pub last_active: DateTime<Utc>,
     pub messages: usize,
+    pub persona: Option<String>,
+    pub usage: Usage,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ArchivedSessionSummary {
+    pub id: Uuid,
+    pub channel_id: String,
+    pub sender_id: String,
+    pub archived_at: DateTime<Utc>,
+    pub restorable_until: DateTime<Utc>,
+    pub messages: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn archived_session_can_be_restored_until_replaced() {
+        let sessions = SessionManager::new();
+        let id = sessions.get_or_create_mut("webchat", "u1").id;
+
+        assert!(sessions.archive_by_id(id));
+        assert!(sessions.list().is_empty());
+        assert_eq!(sessions.list_archived().len(), 1);
+        assert_eq!(sessions.restore_by_id(id), RestoreOutcome::Restored);
+        assert_eq!(sessions.list()[0].id, id);
+
+        assert!(sessions.archive_by_id(id));
+        let newer = sessions.get_or_create_mut("webchat", "u1").id;
+        assert_ne!(newer, id);
+        assert_eq!(sessions.restore_by_id(id), RestoreOutcome::Conflict);
+        assert_eq!(sessions.list_archived().len(), 1);
+
+        let expired = SessionManager::new().with_archive_retention(chrono::Duration::zero());
+        let id = expired.get_or_create_mut("webchat", "u2").id;
+        assert!(expired.archive_by_id(id));
+        assert_eq!(expired.restore_by_id(id), RestoreOutcome::NotFound);
+    }
+
+    #[test]
+    fn moved_session_keeps_its_history_and_never_replaces_a_live_one() {
+        let sessions = SessionManager::new();
+        let moved = {
+            let mut session = sessions.get_or_create_mut("imessage", "+1555");
+            session.history.push(ChatMessage {
+                role: Role::User,
+                content: "plan the trip".to_string(),
+                tool_calls: vec![],
+                tool_call_id: None,
+                reasoning: vec![],
+            });
+            session.id
+        };
+        let history = sessions.get("imessage", "+1555").unwrap().history;
+        let live = {
+            let mut session = sessions.get_or_create_mut("telegram", "42");
+            session.history = history;
+            session.id
+        };
+
+        assert_eq!(
+            sessions.move_to("imessage", "+1555", "telegram", "42"),
+            None
+        );
+        assert_eq!(sessions.get("telegram", "42").unwrap().id, live);
+        assert!(sessions.get("imessage", "+1555").is_some());
+
+        sessions.get_or_create_mut("telegram", "42").reset();
+        assert_eq!(
+            sessions.move_to("imessage", "+1555", "telegram", "42"),
+            Some(moved)
+        );
+        assert!(sessions.get("imessage", "+1555").is_none());
+        let session = sessions.get("telegram", "42").unwrap();
+        assert_eq!(session.id, moved);
+        assert_eq!(session.history.len(), 1);
+        assert!(sessions.list_archived().is_empty());
+
+        assert_eq!(sessions.move_to("telegram", "42", "telegram", "42"), None);
+        assert_eq!(sessions.move_to("discord", "7", "telegram", "42"), None);
+    }
+
+    #[test]
+    fn session_survives_a_round_trip_through_json() {
+        let masker = crate::pii::PiiMasker::new(&crate::config::PiiConfig {
+            emails: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let mut session = Session::new();
+        let masked = masker.mask("mail ana@example.com", &mut session.pii);
+        let args = serde_json::json!({ "command": "ls" });
+        session
+            .grants
+            .insert("shell.execute", &args, crate::grants::GrantScope::Session);
+
+        let json = serde_json::to_string(&session).unwrap();
+        let restored: Session = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.id, session.id);
+        assert_eq!(restored.pii.unmask(&masked), "mail ana@example.com");
+        assert!(restored.grants.allows("shell.execute", &args));
+    }
 }
@@ -0,0 +1,464 @@
+//! Read-only public links to assistant answers (`/share`).
+//!
+//! A share is a stored copy of one answer with an expiry. Its link carries the share id,
+//! the expiry and an HMAC-SHA256 signature over both, so a link can't be guessed, forged
+//! or extended, and revoking a share deletes the copy. Pages are served by
+//! `public_router` on a listener of their own that exposes nothing else.
+
+use crate::config::SharesConfig;
+use crate::focus::parse_duration;
+use anyhow::{anyhow, Result};
+use axum::extract::Path as UrlPath;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Extension;
+use chrono::{DateTime, TimeZone, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Answers longer than this are refused rather than cut, so a link never shows half of one.
+const CONTENT_MAX_BYTES: usize = 256 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShareCommand {
+    /// Share the last answer, for the given time or the configured default.
+    Create {
+        expires_in: Option<chrono::Duration>,
+    },
+    Revoke {
+        id: String,
+    },
+}
+
+impl ShareCommand {
+    /// `/share`, `/share 24h`, `/share 7d`, `/share revoke <id>`.
+    pub fn parse(input: &str) -> Option<Result<Self, String>> {
+        let rest = input.trim().strip_prefix("/share")?;
+        if !rest.is_empty() && !rest.starts_with(' ') {
+            return None;
+        }
+        let rest = rest.trim();
+        if rest.is_empty() {
+            return Some(Ok(Self::Create { expires_in: None }));
+        }
+        if let Some(id) = rest.strip_prefix("revoke") {
+            return Some(match id.trim() {
+                "" => Err("Usage: /share revoke <id>".to_string()),
+                id => Ok(Self::Revoke { id: id.to_string() }),
+            });
+        }
+        Some(match parse_duration(rest) {
+            Some(duration) => Ok(Self::Create {
+                expires_in: Some(duration),
+            }),
+            None => Err(format!(
+                "Couldn't read {rest:?} as a duration. Try /share, /share 24h or /share 7d."
+            )),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Share {
+    pub id: String,
+    pub title: Option<String>,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// `channel:sender` for `/share`, `api` for the control API.
+    pub source: String,
+}
+
+pub struct ShareStore {
+    cfg: SharesConfig,
+    dir: PathBuf,
+    secret: Vec<u8>,
+}
+
+impl ShareStore {
+    /// Open `data_dir/shares`, creating the signing key there if the config has none.
+    pub async fn open(cfg: SharesConfig, data_dir: &Path) -> Result<Self> {
+        let dir = data_dir.join("shares");
+        tokio::fs::create_dir_all(&dir).await?;
+        let secret = match cfg.secret.as_ref() {
+            Some(secret) => secret.as_bytes().to_vec(),
+            None => load_or_create_secret(&dir.join("secret")).await?,
+        };
+        Ok(Self { cfg, dir, secret })
+    }
+
+    /// Store `content` and return the share with its public link.
+    pub async fn create(
+        &self,
+        content: &str,
+        title: Option<String>,
+        expires_in: Option<chrono::Duration>,
+        source: &str,
+    ) -> Result<(Share, String)> {
+        if content.trim().is_empty() {
+            return Err(anyhow!("nothing to share"));
+        }
+        if content.len() > CONTENT_MAX_BYTES {
+            return Err(anyhow!(
+                "answer is {} bytes; shares are limited to {CONTENT_MAX_BYTES}",
+                content.len()
+            ));
+        }
+        let max = chrono::Duration::hours(self.cfg.max_expire_hours as i64);
+        let expires_in =
+            expires_in.unwrap_or_else(|| chrono::Duration::hours(self.cfg.expire_hours as i64));
+        if expires_in > max {
+            return Err(anyhow!(
+                "links can last at most {} hours",
+                self.cfg.max_expire_hours
+            ));
+        }
+
+        let now = Utc::now();
+        let share = Share {
+            id: Uuid::new_v4().simple().to_string(),
+            title,
+            content: content.to_string(),
+            created_at: now,
+            // Whole seconds, so the expiry in the link matches the stored one.
+            expires_at: Utc
+                .timestamp_opt((now + expires_in).timestamp(), 0)
+                .single()
+                .ok_or_else(|| anyhow!("expiry out of range"))?,
+            source: source.to_string(),
+        };
+        let tmp = self.dir.join(format!("{}.json.tmp", share.id));
+        tokio::fs::write(&tmp, serde_json::to_vec(&share)?).await?;
+        tokio::fs::rename(&tmp, self.path(&share.id)).await?;
+        self.prune().await;
+        let link = self.link(&share);
+        Ok((share, link))
+    }
+
+    pub fn link(&self, share: &Share) -> String {
+        format!(
+            "{}/s/{}",
+            self.cfg.public_url.trim_end_matches('/'),
+            self.token(&share.id, share.expires_at.timestamp())
+        )
+    }
+
+    /// The share a link token points at, if the signature holds and it hasn't expired.
+    pub async fn resolve(&self, token: &str) -> Option<Share> {
+        let mut parts = token.splitn(3, '.');
+        let (id, expires, signature) = (parts.next()?, parts.next()?, parts.next()?);
+        if !valid_id(id) {
+            return None;
+        }
+        let mut mac = self.mac();
+        mac.update(format!("{id}.{expires}").as_bytes());
+        mac.verify_slice(&hex::decode(signature).ok()?).ok()?;
+        if expires.parse::<i64>().ok()? <= Utc::now().timestamp() {
+            return None;
+        }
+        let share = self.read(id).await?;
+        (share.expires_at > Utc::now()).then_some(share)
+    }
+
+    /// Shares that haven't expired, newest first.
+    pub async fn list(&self) -> Result<Vec<Share>> {
+        self.prune().await;
+        let mut out = Vec::new();
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if let Some(share) = match name.strip_suffix(".json") {
+                Some(id) if valid_id(id) => self.read(id).await,
+                _ => None,
+            } {
+                out.push(share);
+            }
+        }
+        out.sort_by_key(|share| std::cmp::Reverse(share.created_at));
+        Ok(out)
+    }
+
+    /// Delete a share; its link stops working at once.
+    pub async fn revoke(&self, id: &str) -> bool {
+        valid_id(id) && tokio::fs::remove_file(self.path(id)).await.is_ok()
+    }
+
+    /// Run a `/share` command. `last_answer` is the most recent assistant message in the
+    /// conversation it was sent from, and `source` that conversation; only shares made
+    /// from it can be revoked from it.
+    pub async fn apply(
+        &self,
+        command: ShareCommand,
+        last_answer: Option<String>,
+        source: &str,
+    ) -> String {
+        match command {
+            ShareCommand::Create { expires_in } => {
+                let Some(answer) = last_answer else {
+                    return "There's no answer in this conversation to share yet.".to_string();
+                };
+                match self.create(&answer, None, expires_in, source).await {
+                    Ok((share, link)) => format!(
+                        "Anyone with this link can read that answer until {} UTC:\n{link}\n\n/share revoke {} disables it.",
+                        share.expires_at.format("%Y-%m-%d %H:%M"),
+                        share.id
+                    ),
+                    Err(e) => format!("Couldn't share: {e}"),
+                }
+            }
+            ShareCommand::Revoke { id } => {
+                // Someone else's share is "not found", so ids can't be probed either.
+                let mine = valid_id(&id)
+                    && self
+                        .read(&id)
+                        .await
+                        .is_some_and(|share| share.source == source);
+                if mine && self.revoke(&id).await {
+                    "Link revoked.".to_string()
+                } else {
+                    format!("No share with id {id}.")
+                }
+            }
+        }
+    }
+
+    fn token(&self, id: &str, expires: i64) -> String {
+        let mut mac = self.mac();
+        mac.update(format!("{id}.{expires}").as_bytes());
+        format!(
+            "{id}.{expires}.{}",
+            hex::encode(mac.finalize().into_bytes())
+        )
+    }
+
+    fn mac(&self) -> Hmac<Sha256> {
+        // HMAC takes keys of any length, so this can't fail.
+        Hmac::<Sha256>::new_from_slice(&self.secret).expect("hmac key")
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{id}.json"))
+    }
+
+    async fn read(&self, id: &str) -> Option<Share> {
+        let bytes = tokio::fs::read(self.path(id)).await.ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    /// Delete expired shares.
+    async fn prune(&self) {
+        let Ok(mut entries) = tokio::fs::read_dir(&self.dir).await else {
+            return;
+        };
+        let now = Utc::now();
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some(id) = name.strip_suffix(".json").filter(|id| valid_id(id)) else {
+                continue;
+            };
+            if self.read(id).await.is_some_and(|s| s.expires_at <= now) {
+                let _ = tokio::fs::remove_file(entry.path()).await;
+            }
+        }
+    }
+}
+
+/// Ids are 32 lowercase hex characters; anything else never reaches the filesystem.
+fn valid_id(id: &str) -> bool {
+    id.len() == 32 && id.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f'))
+}
+
+async fn load_or_create_secret(path: &Path) -> Result<Vec<u8>> {
+    if let Ok(secret) = tokio::fs::read_to_string(path).await {
+        if !secret.trim().is_empty() {
+            return Ok(secret.trim().as_bytes().to_vec());
+        }
+    }
+    let secret = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    tokio::fs::write(path, &secret).await?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        tokio::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600)).await?;
+    }
+    Ok(secret.into_bytes())
+}
+
+/// The public side: `GET /s/{token}` and nothing else.
+pub fn public_router(store: Arc<ShareStore>) -> axum::Router {
+    axum::Router::new()
+        .route("/s/{token}", get(get_share))
+        .layer(Extension(store))
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+async fn get_share(
+    Extension(store): Extension<Arc<ShareStore>>,
+    UrlPath(token): UrlPath<String>,
+) -> Response {
+    let headers = [
+        (header::CACHE_CONTROL, "no-store"),
+        (header::REFERRER_POLICY, "no-referrer"),
+        (header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+        (
+            header::CONTENT_SECURITY_POLICY,
+            "default-src 'none'; style-src 'unsafe-inline'",
+        ),
+        (header::HeaderName::from_static("x-robots-tag"), "noindex"),
+    ];
+    match store.resolve(&token).await {
+        Some(share) => (
+            headers,
+            [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
+            render_page(&share),
+        )
+            .into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            headers,
+            "This link is invalid, expired or revoked.",
+        )
+            .into_response(),
+    }
+}
+
+fn render_page(share: &Share) -> String {
+    let title = escape_html(share.title.as_deref().unwrap_or("Shared answer"));
+    format!(
+        "<!doctype html>\n<html><head><meta charset=\"utf-8\">\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+         <meta name=\"robots\" content=\"noindex\"><title>{title}</title>\
+         <style>body{{font:16px/1.5 system-ui,sans-serif;max-width:46rem;margin:2rem auto;padding:0 1rem}}\
+         pre{{white-space:pre-wrap;font:inherit}}footer{{color:#777;font-size:.85rem}}</style></head>\
+         <body><h1>{title}</h1><pre>{}</pre><footer>Shared {} UTC · expires {} UTC</footer></body></html>\n",
+        escape_html(&share.content),
+        share.created_at.format("%Y-%m-%d %H:%M"),
+        share.expires_at.format("%Y-%m-%d %H:%M"),
+    )
+}
+
+fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn links_verify_expire_and_revoke() {
+        let tmp = std::env::temp_dir().join(format!("opencraw-shares-{}", Uuid::new_v4()));
+        let cfg = SharesConfig {
+            enabled: true,
+            public_url: "https://share.example.com/".to_string(),
+            ..Default::default()
+        };
+        let store = ShareStore::open(cfg, &tmp).await.unwrap();
+        assert!(tmp.join("shares").join("secret").exists());
+
+        let (share, link) = store
+            .create("<b>42</b>", None, None, "telegram:1")
+            .await
+            .unwrap();
+        let token = link
+            .strip_prefix("https://share.example.com/s/")
+            .unwrap()
+            .to_string();
+        assert_eq!(store.resolve(&token).await.unwrap().content, "<b>42</b>");
+        assert!(render_page(&share).contains("&lt;b&gt;42&lt;/b&gt;"));
+
+        // Extending the expiry or pointing at another id breaks the signature.
+        let (id, rest) = token.split_once('.').unwrap();
+        let (expires, signature) = rest.split_once('.').unwrap();
+        let later = expires.parse::<i64>().unwrap() + 3600;
+        assert!(store
+            .resolve(&format!("{id}.{later}.{signature}"))
+            .await
+            .is_none());
+        assert!(store
+            .resolve(&format!("{}.{expires}.{signature}", "0".repeat(32)))
+            .await
+            .is_none());
+        // A correctly signed but expired link doesn't resolve either.
+        let past = Utc::now().timestamp() - 1;
+        assert!(store.resolve(&store.token(id, past)).await.is_none());
+
+        assert!(store
+            .create("x", None, Some(chrono::Duration::days(365)), "api")
+            .await
+            .is_err());
+        assert_eq!(store.list().await.unwrap().len(), 1);
+        assert!(store.revoke(id).await);
+        assert!(store.resolve(&token).await.is_none());
+        assert!(!store.revoke("../secret").await);
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[tokio::test]
+    async fn shares_are_only_revoked_from_where_they_were_made() {
+        let tmp = std::env::temp_dir().join(format!("opencraw-shares-{}", Uuid::new_v4()));
+        let cfg = SharesConfig {
+            enabled: true,
+            public_url: "https://share.example.com/".to_string(),
+            ..Default::default()
+        };
+        let store = ShareStore::open(cfg, &tmp).await.unwrap();
+        let (share, link) = store
+            .create("the plan", None, None, "telegram:1")
+            .await
+            .unwrap();
+        let token = link.rsplit('/').next().unwrap().to_string();
+        let revoke = || ShareCommand::Revoke {
+            id: share.id.clone(),
+        };
+
+        let reply = store.apply(revoke(), None, "telegram:2").await;
+        assert_eq!(reply, format!("No share with id {}.", share.id));
+        assert!(store.resolve(&token).await.is_some());
+
+        assert_eq!(
+            store.apply(revoke(), None, "telegram:1").await,
+            "Link revoked."
+        );
+        assert!(store.resolve(&token).await.is_none());
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn parses_share_commands() {
+        assert_eq!(
+            ShareCommand::parse("/share"),
+            Some(Ok(ShareCommand::Create { expires_in: None }))
+        );
+        assert_eq!(
+            ShareCommand::parse("/share 7d"),
+            Some(Ok(ShareCommand::Create {
+                expires_in: Some(chrono::Duration::days(7))
+            }))
+        );
+        assert_eq!(
+            ShareCommand::parse("/share revoke abc"),
+            Some(Ok(ShareCommand::Revoke {
+                id: "abc".to_string()
+            }))
+        );
+        assert!(matches!(ShareCommand::parse("/share soon"), Some(Err(_))));
+        assert_eq!(ShareCommand::parse("/shared"), None);
+    }
+}
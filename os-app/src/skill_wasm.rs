@@ -0,0 +1,328 @@
+//! Running skills that ship a WebAssembly module.
+//!
+//! A package may name a `[module]` with its URL and SHA-256; the module is fetched with
+//! the package, pinned by that hash, and run by `/skill` in place of a model turn. It
+//! gets the request as input and returns the reply. Nothing else is reachable from
+//! inside the sandbox except the host functions the package lists in `requires.host`:
+//!
+//! - `tools`: `call_tool`, under the same manifest check as a model's tool calls.
+//! - `memory`: `search_memory` over the conversation's memory.
+//!
+//! `log` is always available. Each run is bounded by `skills.wasm_fuel` and
+//! `skills.wasm_memory_mb`.
+//!
+//! The ABI is plain linear memory. The module exports `memory`, `alloc(len) -> ptr` and
+//! `run(ptr, len) -> i64`; strings are UTF-8 passed as `(ptr, len)`, and a returned
+//! string is packed as `ptr << 32 | len`. Host functions import from module `opencraw`:
+//!
+//! ```text
+//! log(ptr: i32, len: i32)
+//! call_tool(name_ptr: i32, name_len: i32, args_ptr: i32, args_len: i32) -> i64
+//! search_memory(query_ptr: i32, query_len: i32) -> i64
+//! ```
+//!
+//! `call_tool` and `search_memory` return JSON, `{"ok": <value>}` or `{"error": "..."}`,
+//! written to memory the host gets from the module's `alloc`.
+
+use crate::config::SkillsConfig;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use std::sync::Arc;
+use wasmtime::{
+    Caller, Config, Engine, Extern, Linker, Memory, Module, Store, StoreLimits,
+    StoreLimitsBuilder,
+};
+
+pub const HOST_MODULE: &str = "opencraw";
+/// Host function groups a package can ask for in `requires.host`.
+pub const HOST_CAPABILITIES: &[&str] = &["tools", "memory"];
+/// How often a running module yields, so a cancelled run stops promptly.
+const FUEL_YIELD_INTERVAL: u64 = 100_000;
+
+/// What a module can reach outside its sandbox.
+#[async_trait]
+pub trait SkillHost: Send + Sync {
+    async fn call_tool(
+        &self,
+        tool: &str,
+        arguments: serde_json::Value,
+    ) -> std::result::Result<serde_json::Value, String>;
+
+    async fn search_memory(&self, query: &str) -> std::result::Result<serde_json::Value, String>;
+}
+
+struct HostState {
+    host: Arc<dyn SkillHost>,
+    limits: StoreLimits,
+    skill: String,
+}
+
+pub struct WasmRuntime {
+    engine: Engine,
+    fuel: u64,
+    memory_bytes: usize,
+}
+
+impl WasmRuntime {
+    pub fn new(cfg: &SkillsConfig) -> Result<Self> {
+        let mut config = Config::new();
+        config.async_support(true).consume_fuel(true);
+        let engine = Engine::new(&config).map_err(|e| anyhow!("wasm engine: {e}"))?;
+        Ok(Self {
+            engine,
+            fuel: cfg.wasm_fuel,
+            memory_bytes: cfg.wasm_memory_mb * 1024 * 1024,
+        })
+    }
+
+    /// Run `wasm` on `input`, linking only the host functions in `capabilities`.
+    pub async fn run(
+        &self,
+        skill: &str,
+        wasm: &[u8],
+        capabilities: &[String],
+        input: &str,
+        host: Arc<dyn SkillHost>,
+    ) -> Result<String> {
+        let module = Module::new(&self.engine, wasm)
+            .map_err(|e| anyhow!("skill {skill}: invalid wasm module: {e}"))?;
+        let mut linker: Linker<HostState> = Linker::new(&self.engine);
+        link(&mut linker, capabilities)?;
+
+        let state = HostState {
+            host,
+            limits: StoreLimitsBuilder::new()
+                .memory_size(self.memory_bytes)
+                .instances(1)
+                .build(),
+            skill: skill.to_string(),
+        };
+        let mut store = Store::new(&self.engine, state);
+        store.limiter(|s| &mut s.limits);
+        store.set_fuel(self.fuel)?;
+        store.fuel_async_yield_interval(Some(FUEL_YIELD_INTERVAL))?;
+
+        // An import the package didn't declare fails here, naming the function.
+        let instance = linker
+            .instantiate_async(&mut store, &module)
+            .await
+            .map_err(|e| anyhow!("skill {skill}: {e}"))?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| anyhow!("skill {skill}: module exports no memory"))?;
+        let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+        let run = instance.get_typed_func::<(i32, i32), i64>(&mut store, "run")?;
+
+        let ptr = alloc.call_async(&mut store, len_i32(input.len())?).await?;
+        memory.write(&mut store, ptr as u32 as usize, input.as_bytes())?;
+        let packed = run
+            .call_async(&mut store, (ptr, len_i32(input.len())?))
+            .await
+            .map_err(|e| anyhow!("skill {skill} failed: {e}"))?;
+        let out = read(&memory, &store, packed)?;
+        String::from_utf8(out).map_err(|_| anyhow!("skill {skill} returned invalid UTF-8"))
+    }
+}
+
+fn link(linker: &mut Linker<HostState>, capabilities: &[String]) -> Result<()> {
+    linker.func_wrap(
+        HOST_MODULE,
+        "log",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> wasmtime::Result<()> {
+            let memory = guest_memory(&mut caller)?;
+            let bytes = read_slice(&memory, &caller, ptr, len)?;
+            tracing::info!(
+                skill = %caller.data().skill,
+                message = %String::from_utf8_lossy(&bytes),
+                "skill log"
+            );
+            Ok(())
+        },
+    )?;
+    if capabilities.iter().any(|c| c == "tools") {
+        linker.func_wrap_async(
+            HOST_MODULE,
+            "call_tool",
+            |mut caller: Caller<'_, HostState>,
+             (name_ptr, name_len, args_ptr, args_len): (i32, i32, i32, i32)| {
+                Box::new(async move {
+                    let memory = guest_memory(&mut caller)?;
+                    let name = read_slice(&memory, &caller, name_ptr, name_len)?;
+                    let args = read_slice(&memory, &caller, args_ptr, args_len)?;
+                    let name = String::from_utf8_lossy(&name).to_string();
+                    let reply = match serde_json::from_slice(&args) {
+                        Ok(args) => {
+                            let host = caller.data().host.clone();
+                            host.call_tool(&name, args).await
+                        }
+                        Err(e) => Err(format!("arguments are not JSON: {e}")),
+                    };
+                    write_reply(&mut caller, &memory, reply).await
+                })
+            },
+        )?;
+    }
+    if capabilities.iter().any(|c| c == "memory") {
+        linker.func_wrap_async(
+            HOST_MODULE,
+            "search_memory",
+            |mut caller: Caller<'_, HostState>, (ptr, len): (i32, i32)| {
+                Box::new(async move {
+                    let memory = guest_memory(&mut caller)?;
+                    let query = read_slice(&memory, &caller, ptr, len)?;
+                    let query = String::from_utf8_lossy(&query).to_string();
+                    let host = caller.data().host.clone();
+                    let reply = host.search_memory(&query).await;
+                    write_reply(&mut caller, &memory, reply).await
+                })
+            },
+        )?;
+    }
+    Ok(())
+}
+
+fn guest_memory(caller: &mut Caller<'_, HostState>) -> wasmtime::Result<Memory> {
+    match caller.get_export("memory") {
+        Some(Extern::Memory(memory)) => Ok(memory),
+        _ => Err(anyhow!("module exports no memory")),
+    }
+}
+
+fn read_slice(
+    memory: &Memory,
+    caller: &Caller<'_, HostState>,
+    ptr: i32,
+    len: i32,
+) -> wasmtime::Result<Vec<u8>> {
+    let start = ptr as u32 as usize;
+    let end = start + len as u32 as usize;
+    memory
+        .data(caller)
+        .get(start..end)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| anyhow!("string out of bounds"))
+}
+
+fn read(memory: &Memory, store: &Store<HostState>, packed: i64) -> Result<Vec<u8>> {
+    let (start, len) = ((packed >> 32) as u32 as usize, packed as u32 as usize);
+    memory
+        .data(store)
+        .get(start..start + len)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| anyhow!("skill output out of bounds"))
+}
+
+/// Copy a host function's reply into memory from the module's `alloc`.
+async fn write_reply(
+    caller: &mut Caller<'_, HostState>,
+    memory: &Memory,
+    reply: std::result::Result<serde_json::Value, String>,
+) -> wasmtime::Result<i64> {
+    let body = match reply {
+        Ok(value) => serde_json::json!({ "ok": value }),
+        Err(error) => serde_json::json!({ "error": error }),
+    }
+    .to_string();
+    let alloc = match caller.get_export("alloc") {
+        Some(Extern::Func(f)) => f.typed::<i32, i32>(&*caller)?,
+        _ => return Err(anyhow!("module exports no alloc")),
+    };
+    let len = len_i32(body.len())?;
+    let ptr = alloc.call_async(&mut *caller, len).await?;
+    memory.write(&mut *caller, ptr as u32 as usize, body.as_bytes())?;
+    Ok(((ptr as u32 as i64) << 32) | len as u32 as i64)
+}
+
+fn len_i32(len: usize) -> Result<i32> {
+    i32::try_from(len).map_err(|_| anyhow!("string too long for wasm"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct RecordingHost {
+        calls: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl SkillHost for RecordingHost {
+        async fn call_tool(
+            &self,
+            tool: &str,
+            arguments: serde_json::Value,
+        ) -> std::result::Result<serde_json::Value, String> {
+            self.calls.lock().unwrap().push(format!("{tool} {arguments}"));
+            Ok(serde_json::json!("done"))
+        }
+
+        async fn search_memory(
+            &self,
+            _query: &str,
+        ) -> std::result::Result<serde_json::Value, String> {
+            Err("not used".to_string())
+        }
+    }
+
+    /// Calls `clipboard` with `{}` and returns the host's reply.
+    const TOOL_SKILL: &str = r#"
+        (module
+          (import "opencraw" "call_tool" (func $call_tool (param i32 i32 i32 i32) (result i64)))
+          (memory (export "memory") 1)
+          (global $next (mut i32) (i32.const 1024))
+          (data (i32.const 0) "clipboard{}")
+          (func (export "alloc") (param $len i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (global.get $next))
+            (global.set $next (i32.add (global.get $next) (local.get $len)))
+            (local.get $ptr))
+          (func (export "run") (param i32 i32) (result i64)
+            (call $call_tool (i32.const 0) (i32.const 9) (i32.const 9) (i32.const 2))))
+    "#;
+
+    const SPIN_SKILL: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param i32) (result i32) (i32.const 0))
+          (func (export "run") (param i32 i32) (result i64)
+            (loop $forever (br $forever))
+            (i64.const 0)))
+    "#;
+
+    fn runtime() -> WasmRuntime {
+        WasmRuntime::new(&SkillsConfig {
+            wasm_fuel: 1_000_000,
+            ..SkillsConfig::default()
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn modules_reach_only_declared_host_functions() {
+        let host = Arc::new(RecordingHost::default());
+        let tools = vec!["tools".to_string()];
+        let out = runtime()
+            .run("t", TOOL_SKILL.as_bytes(), &tools, "hi", host.clone())
+            .await
+            .unwrap();
+        assert_eq!(out, r#"{"ok":"done"}"#);
+        assert_eq!(*host.calls.lock().unwrap(), vec!["clipboard {}"]);
+
+        let err = runtime()
+            .run("t", TOOL_SKILL.as_bytes(), &[], "hi", host)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("call_tool"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn runaway_modules_run_out_of_fuel() {
+        let host = Arc::new(RecordingHost::default());
+        assert!(runtime()
+            .run("spin", SPIN_SKILL.as_bytes(), &[], "", host)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,635 @@
+//! Installed skills: packages fetched from a URL, checked against `[skills]`, and kept
+//! under `data/skills/<name>/`.
+//!
+//! A package is a TOML file with a `name`, `version`, `description`, the `instructions`
+//! the assistant follows when it uses the skill, and a `[requires]` manifest of the
+//! tools, channels and network domains it needs. Installing records where the package
+//! came from, its SHA-256 and the key that signed it, so `update` can fetch the same URL
+//! again and `list` shows exactly what is installed.
+//!
+//! A package can also pin a WebAssembly module by URL and SHA-256, fetched alongside it
+//! and run in a sandbox instead of a model turn; see `skill_wasm`.
+//!
+//! `/skill <name> <request>` runs one turn as the skill. Only its declared tools are
+//! offered, it can only be invoked from its declared channels, and a call outside the
+//! manifest (another action of a declared tool, a URL on an undeclared host) is refused
+//! and written to the audit log. Approval requests raised during the turn show the
+//! manifest, so a reviewer sees what the skill asked for as well as what it is doing.
+//!
+//! Sources must start with one of `skills.sources` when that is set, and must be https
+//! unless `skills.allow_http` is on. When `skills.trusted_keys` is set, a package must
+//! also carry a detached Ed25519 signature over the file, base64-encoded at `<url>.sig`,
+//! from one of those keys.
+
+use crate::config::SkillsConfig;
+use crate::skill_wasm::HOST_CAPABILITIES;
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use ring::signature::{UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+const NAME_MAX_CHARS: usize = 64;
+
+#[derive(Debug, clap::Subcommand)]
+pub enum SkillsCommand {
+    /// Install a skill package from a URL.
+    Install { url: String },
+    /// List installed skills with their versions and sources.
+    List,
+    /// Fetch installed skills again from the URLs they were installed from.
+    Update {
+        /// One skill; defaults to all of them.
+        name: Option<String>,
+    },
+    /// Uninstall a skill.
+    Remove { name: String },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SkillPackage {
+    pub name: String,
+    pub version: String,
+    pub description: String,
+    #[serde(default)]
+    pub instructions: String,
+    pub requires: SkillManifest,
+    #[serde(default)]
+    pub module: Option<SkillModule>,
+}
+
+/// A wasm module shipped with a package, pinned by its hash.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SkillModule {
+    /// Absolute, or relative to the package's URL.
+    pub url: String,
+    /// Hex SHA-256 of the module.
+    pub sha256: String,
+}
+
+/// What a skill declares it needs. Nothing outside it is allowed while the skill runs.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SkillManifest {
+    /// Tool names, or `tool.action` for one action of a tool, e.g. `filesystem.read_file`.
+    #[serde(default)]
+    pub tools: Vec<String>,
+    /// Channels the skill may be invoked from; `*` for any.
+    #[serde(default)]
+    pub channels: Vec<String>,
+    /// Hosts URLs in tool arguments may point at: `example.com`, or `*.example.com` for
+    /// it and its subdomains.
+    #[serde(default)]
+    pub domains: Vec<String>,
+    /// Host functions a wasm module may import: `tools`, `memory`.
+    #[serde(default)]
+    pub host: Vec<String>,
+}
+
+impl SkillManifest {
+    /// Whether any part of `tool` was declared.
+    pub fn offers_tool(&self, tool: &str) -> bool {
+        self.tools
+            .iter()
+            .any(|t| t == tool || t.strip_prefix(tool).is_some_and(|a| a.starts_with('.')))
+    }
+
+    pub fn allows_channel(&self, channel_id: &str) -> bool {
+        self.channels.iter().any(|c| c == "*" || c == channel_id)
+    }
+
+    /// Why a call is outside the manifest, if it is.
+    pub fn check_call(&self, tool: &str, arguments: &serde_json::Value) -> Option<String> {
+        let action = arguments.get("action").and_then(|v| v.as_str());
+        let declared = self
+            .tools
+            .iter()
+            .any(|t| t == tool || action.is_some_and(|action| *t == format!("{tool}.{action}")));
+        if !declared {
+            return Some(match action {
+                Some(action) => format!("{tool}.{action} is not in the skill's declared tools"),
+                None => format!("{tool} is not in the skill's declared tools"),
+            });
+        }
+        let mut urls = Vec::new();
+        collect_urls(arguments, &mut urls);
+        urls.into_iter().find_map(|url| {
+            let host = reqwest::Url::parse(url)
+                .ok()
+                .and_then(|u| u.host_str().map(str::to_ascii_lowercase))?;
+            (!self.domains.iter().any(|d| host_matches(d, &host)))
+                .then(|| format!("{host} is not in the skill's declared domains"))
+        })
+    }
+
+    /// One line per kind, for approval prompts.
+    pub fn describe(&self) -> String {
+        let list = |items: &[String]| {
+            if items.is_empty() {
+                "none".to_string()
+            } else {
+                items.join(", ")
+            }
+        };
+        let mut out = format!(
+            "tools: {}\nchannels: {}\ndomains: {}",
+            list(&self.tools),
+            list(&self.channels),
+            list(&self.domains)
+        );
+        if !self.host.is_empty() {
+            out.push_str(&format!("\nhost functions: {}", self.host.join(", ")));
+        }
+        out
+    }
+}
+
+fn collect_urls<'a>(value: &'a serde_json::Value, out: &mut Vec<&'a str>) {
+    match value {
+        serde_json::Value::String(s) if s.starts_with("http://") || s.starts_with("https://") => {
+            out.push(s)
+        }
+        serde_json::Value::Array(items) => items.iter().for_each(|v| collect_urls(v, out)),
+        serde_json::Value::Object(map) => map.values().for_each(|v| collect_urls(v, out)),
+        _ => {}
+    }
+}
+
+fn host_matches(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.trim().to_ascii_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host == domain || host.ends_with(&format!(".{domain}")),
+        None => pattern == host,
+    }
+}
+
+/// A skill as shown to a reviewer: which one is asking, and what it declared.
+#[derive(Debug, Clone, Serialize)]
+pub struct SkillSummary {
+    pub name: String,
+    pub version: String,
+    pub requires: SkillManifest,
+}
+
+impl From<&SkillPackage> for SkillSummary {
+    fn from(package: &SkillPackage) -> Self {
+        Self {
+            name: package.name.clone(),
+            version: package.version.clone(),
+            requires: package.requires.clone(),
+        }
+    }
+}
+
+/// `/skill <name> <request>`: the skill and the request to run it with.
+pub fn parse_invocation(input: &str) -> Option<Result<(String, String), String>> {
+    let rest = input.trim().strip_prefix("/skill")?;
+    if !rest.is_empty() && !rest.starts_with(' ') {
+        return None;
+    }
+    let (name, request) = rest.trim().split_once(' ').unwrap_or((rest.trim(), ""));
+    if name.is_empty() || request.trim().is_empty() {
+        return Some(Err("Usage: /skill <name> <request>".to_string()));
+    }
+    Some(Ok((name.to_string(), request.trim().to_string())))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstalledSkill {
+    pub name: String,
+    pub version: String,
+    pub description: String,
+    pub source: String,
+    #[serde(default)]
+    pub requires: SkillManifest,
+    /// Hex SHA-256 of the package file.
+    pub sha256: String,
+    /// Fingerprint of the trusted key that signed the package, when keys are configured.
+    pub signed_by: Option<String>,
+    pub installed_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub enum UpdateOutcome {
+    /// The source still serves the installed file.
+    Unchanged(InstalledSkill),
+    Updated {
+        from_version: String,
+        skill: InstalledSkill,
+    },
+}
+
+struct Fetched {
+    package: SkillPackage,
+    bytes: Vec<u8>,
+    sha256: String,
+    signed_by: Option<String>,
+    module: Option<Vec<u8>>,
+}
+
+pub struct SkillStore {
+    cfg: SkillsConfig,
+    dir: PathBuf,
+    http: reqwest::Client,
+}
+
+impl SkillStore {
+    /// Open `data_dir/skills`. Nothing is cached, so a CLI and a running server can share
+    /// the directory.
+    pub async fn open(cfg: SkillsConfig, data_dir: &Path) -> Result<Self> {
+        let dir = data_dir.join("skills");
+        tokio::fs::create_dir_all(&dir).await?;
+        let http = reqwest::Client::builder().timeout(FETCH_TIMEOUT).build()?;
+        Ok(Self { cfg, dir, http })
+    }
+
+    /// Fetch, check and install the package at `url`. A skill that is already installed
+    /// is left alone; use `update` for that.
+    pub async fn install(&self, url: &str) -> Result<InstalledSkill> {
+        let fetched = self.fetch(url).await?;
+        let name = fetched.package.name.clone();
+        if self.get(&name).await.is_some() {
+            return Err(anyhow!(
+                "skill {name} is already installed; update or remove it first"
+            ));
+        }
+        let now = Utc::now();
+        let skill = InstalledSkill {
+            name,
+            version: fetched.package.version.clone(),
+            description: fetched.package.description.clone(),
+            source: url.to_string(),
+            requires: fetched.package.requires.clone(),
+            sha256: fetched.sha256.clone(),
+            signed_by: fetched.signed_by.clone(),
+            installed_at: now,
+            updated_at: now,
+        };
+        self.write(&skill, &fetched).await?;
+        Ok(skill)
+    }
+
+    /// Installed skills by name.
+    pub async fn list(&self) -> Result<Vec<InstalledSkill>> {
+        let mut out = Vec::new();
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if let Some(skill) = self.get(&name).await {
+                out.push(skill);
+            }
+        }
+        out.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(out)
+    }
+
+    pub async fn get(&self, name: &str) -> Option<InstalledSkill> {
+        if !valid_name(name) {
+            return None;
+        }
+        let bytes = tokio::fs::read(self.dir.join(name).join("install.json"))
+            .await
+            .ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    /// The installed package, as written by its author.
+    pub async fn package(&self, name: &str) -> Option<SkillPackage> {
+        if !valid_name(name) {
+            return None;
+        }
+        let raw = tokio::fs::read_to_string(self.dir.join(name).join("skill.toml"))
+            .await
+            .ok()?;
+        toml::from_str(&raw).ok()
+    }
+
+    /// The installed package's wasm module, if it ships one.
+    pub async fn module(&self, name: &str) -> Option<Vec<u8>> {
+        if !valid_name(name) {
+            return None;
+        }
+        tokio::fs::read(self.dir.join(name).join("skill.wasm"))
+            .await
+            .ok()
+    }
+
+    /// Fetch a skill again from its source, under the same checks as an install.
+    pub async fn update(&self, name: &str) -> Result<UpdateOutcome> {
+        let installed = self
+            .get(name)
+            .await
+            .ok_or_else(|| anyhow!("skill {name} is not installed"))?;
+        let fetched = self.fetch(&installed.source).await?;
+        if fetched.package.name != installed.name {
+            return Err(anyhow!(
+                "{} now serves skill {}, not {name}; remove {name} and install it instead",
+                installed.source,
+                fetched.package.name
+            ));
+        }
+        if fetched.sha256 == installed.sha256 {
+            return Ok(UpdateOutcome::Unchanged(installed));
+        }
+        let skill = InstalledSkill {
+            version: fetched.package.version.clone(),
+            description: fetched.package.description.clone(),
+            requires: fetched.package.requires.clone(),
+            sha256: fetched.sha256.clone(),
+            signed_by: fetched.signed_by.clone(),
+            updated_at: Utc::now(),
+            ..installed.clone()
+        };
+        self.write(&skill, &fetched).await?;
+        Ok(UpdateOutcome::Updated {
+            from_version: installed.version,
+            skill,
+        })
+    }
+
+    /// False when there's no such skill.
+    pub async fn remove(&self, name: &str) -> Result<bool> {
+        if self.get(name).await.is_none() {
+            return Ok(false);
+        }
+        tokio::fs::remove_dir_all(self.dir.join(name)).await?;
+        Ok(true)
+    }
+
+    async fn fetch(&self, url: &str) -> Result<Fetched> {
+        self.check_source(url)?;
+        let bytes = self.download(url).await?;
+        let sha256 = hex::encode(Sha256::digest(&bytes));
+        let signed_by = if self.cfg.trusted_keys.is_empty() {
+            None
+        } else {
+            let signature = self.download(&format!("{url}.sig")).await.map_err(|e| {
+                anyhow!("skills.trusted_keys is set, but there's no signature at {url}.sig: {e}")
+            })?;
+            Some(verify_signature(
+                &self.cfg.trusted_keys,
+                &bytes,
+                &signature,
+            )?)
+        };
+        let raw = std::str::from_utf8(&bytes).context("skill package is not UTF-8")?;
+        let package: SkillPackage = toml::from_str(raw).context("invalid skill package")?;
+        if !valid_name(&package.name) {
+            return Err(anyhow!(
+                "skill name {:?} must be 1-{NAME_MAX_CHARS} lowercase letters, digits, - or _",
+                package.name
+            ));
+        }
+        if package.version.trim().is_empty() {
+            return Err(anyhow!("skill {} has no version", package.name));
+        }
+        if let Some(host) = package
+            .requires
+            .host
+            .iter()
+            .find(|h| !HOST_CAPABILITIES.contains(&h.as_str()))
+        {
+            return Err(anyhow!(
+                "skill {} asks for unknown host functions {host:?}",
+                package.name
+            ));
+        }
+        // The package's hash (and signature) cover the module through its pinned digest.
+        let module = match package.module.as_ref() {
+            Some(module) => {
+                let url = reqwest::Url::parse(url)
+                    .and_then(|base| base.join(&module.url))
+                    .map_err(|e| anyhow!("skill module url {}: {e}", module.url))?
+                    .to_string();
+                self.check_source(&url)?;
+                let wasm = self.download(&url).await?;
+                if !hex::encode(Sha256::digest(&wasm)).eq_ignore_ascii_case(module.sha256.trim()) {
+                    return Err(anyhow!("skill module at {url} doesn't match its sha256"));
+                }
+                Some(wasm)
+            }
+            None => None,
+        };
+        Ok(Fetched {
+            package,
+            bytes,
+            sha256,
+            signed_by,
+            module,
+        })
+    }
+
+    fn check_source(&self, url: &str) -> Result<()> {
+        let http = self.cfg.allow_http && url.starts_with("http://");
+        if !url.starts_with("https://") && !http {
+            return Err(anyhow!(
+                "skills are installed over https (set skills.allow_http for http): {url}"
+            ));
+        }
+        if !self.cfg.sources.is_empty() && !self.cfg.sources.iter().any(|s| url.starts_with(s)) {
+            return Err(anyhow!("{url} is not under any of skills.sources"));
+        }
+        Ok(())
+    }
+
+    async fn download(&self, url: &str) -> Result<Vec<u8>> {
+        let response = self.http.get(url).send().await?.error_for_status()?;
+        if response
+            .content_length()
+            .is_some_and(|len| len > self.cfg.max_bytes as u64)
+        {
+            return Err(anyhow!("{url} is over skills.max_bytes"));
+        }
+        let bytes = response.bytes().await?;
+        if bytes.len() > self.cfg.max_bytes {
+            return Err(anyhow!("{url} is over skills.max_bytes"));
+        }
+        Ok(bytes.to_vec())
+    }
+
+    async fn write(&self, skill: &InstalledSkill, fetched: &Fetched) -> Result<()> {
+        let dir = self.dir.join(&skill.name);
+        tokio::fs::create_dir_all(&dir).await?;
+        let module = dir.join("skill.wasm");
+        match fetched.module.as_ref() {
+            Some(wasm) => write_atomic(&module, wasm).await?,
+            // An update that drops the module leaves none behind.
+            None if tokio::fs::try_exists(&module).await? => {
+                tokio::fs::remove_file(&module).await?
+            }
+            None => {}
+        }
+        write_atomic(&dir.join("skill.toml"), &fetched.bytes).await?;
+        // Written last: a skill is installed once this exists.
+        write_atomic(
+            &dir.join("install.json"),
+            &serde_json::to_vec_pretty(skill)?,
+        )
+        .await
+    }
+}
+
+async fn write_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    tokio::fs::write(&tmp, bytes).await?;
+    tokio::fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= NAME_MAX_CHARS
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+/// A base64 Ed25519 public key, as listed in `skills.trusted_keys`.
+pub fn public_key(key: &str) -> Result<Vec<u8>> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(key.trim())
+        .map_err(|_| anyhow!("must be base64"))?;
+    if bytes.len() != 32 {
+        return Err(anyhow!("must decode to a 32-byte Ed25519 public key"));
+    }
+    Ok(bytes)
+}
+
+/// Short id for a trusted key: the first 16 hex digits of its SHA-256.
+fn fingerprint(key: &[u8]) -> String {
+    hex::encode(Sha256::digest(key))[..16].to_string()
+}
+
+/// The fingerprint of the key in `trusted_keys` that signed `package`.
+fn verify_signature(trusted_keys: &[String], package: &[u8], signature: &[u8]) -> Result<String> {
+    let signature = std::str::from_utf8(signature)
+        .ok()
+        .and_then(|s| {
+            base64::engine::general_purpose::STANDARD
+                .decode(s.trim())
+                .ok()
+        })
+        .ok_or_else(|| anyhow!("skill signature must be base64"))?;
+    trusted_keys
+        .iter()
+        .filter_map(|key| public_key(key).ok())
+        .find(|key| {
+            UnparsedPublicKey::new(&ED25519, key)
+                .verify(package, &signature)
+                .is_ok()
+        })
+        .map(|key| fingerprint(&key))
+        .ok_or_else(|| anyhow!("skill package is not signed by any of skills.trusted_keys"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    use serde_json::json;
+
+    fn keypair() -> Ed25519KeyPair {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap()
+    }
+
+    fn b64(bytes: &[u8]) -> String {
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    }
+
+    #[test]
+    fn signatures_must_come_from_a_trusted_key() {
+        let (trusted, other) = (keypair(), keypair());
+        let keys = vec![b64(trusted.public_key().as_ref())];
+        let package =
+            b"name = \"weekly-review\"\nversion = \"1.0.0\"\ndescription = \"x\"\n[requires]\n";
+
+        let signature = b64(trusted.sign(package).as_ref());
+        let signed_by = verify_signature(&keys, package, signature.as_bytes()).unwrap();
+        assert_eq!(signed_by, fingerprint(trusted.public_key().as_ref()));
+
+        let forged = b64(other.sign(package).as_ref());
+        assert!(verify_signature(&keys, package, forged.as_bytes()).is_err());
+        assert!(verify_signature(&keys, b"tampered", signature.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn calls_must_stay_inside_the_manifest() {
+        let manifest = SkillManifest {
+            tools: vec!["filesystem.read_file".to_string(), "browser".to_string()],
+            channels: vec!["webchat".to_string()],
+            domains: vec!["*.example.com".to_string()],
+            ..SkillManifest::default()
+        };
+        assert!(manifest.offers_tool("filesystem"));
+        assert!(!manifest.offers_tool("file"));
+        assert!(!manifest.offers_tool("shell.execute"));
+        assert!(manifest.allows_channel("webchat"));
+        assert!(!manifest.allows_channel("telegram"));
+
+        let read = json!({ "action": "read_file", "path": "notes.md" });
+        assert_eq!(manifest.check_call("filesystem", &read), None);
+        let write = json!({ "action": "write_file", "path": "notes.md" });
+        assert!(manifest.check_call("filesystem", &write).is_some());
+        assert!(manifest.check_call("email", &json!({})).is_some());
+
+        let page = |url: &str| json!({ "url": url });
+        assert_eq!(
+            manifest.check_call("browser", &page("https://docs.example.com/a")),
+            None
+        );
+        assert!(manifest
+            .check_call("browser", &page("https://example.com.evil/a"))
+            .is_some());
+    }
+
+    #[test]
+    fn invocations_need_a_skill_and_a_request() {
+        assert_eq!(
+            parse_invocation("/skill weekly-review sum up my week"),
+            Some(Ok((
+                "weekly-review".to_string(),
+                "sum up my week".to_string()
+            )))
+        );
+        assert!(matches!(
+            parse_invocation("/skill weekly-review"),
+            Some(Err(_))
+        ));
+        assert_eq!(parse_invocation("/skills"), None);
+        assert_eq!(parse_invocation("hello"), None);
+    }
+
+    #[tokio::test]
+    async fn sources_are_checked_before_fetching() {
+        let dir = std::env::temp_dir().join(format!("opencraw-skills-{}", uuid::Uuid::new_v4()));
+        let store = SkillStore::open(
+            SkillsConfig {
+                sources: vec!["https://skills.example.com/".to_string()],
+                ..SkillsConfig::default()
+            },
+            &dir,
+        )
+        .await
+        .unwrap();
+        assert!(store
+            .check_source("https://skills.example.com/a.toml")
+            .is_ok());
+        assert!(store
+            .check_source("http://skills.example.com/a.toml")
+            .is_err());
+        assert!(store
+            .check_source("https://skills.example.com.evil/a.toml")
+            .is_err());
+        assert!(store
+            .check_source("https://other.example.com/a.toml")
+            .is_err());
+        assert!(store.get("../secrets").await.is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
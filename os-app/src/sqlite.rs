@@ -0,0 +1,173 @@
+//! SQLite connections for the data dir's databases.
+//!
+//! Each database runs in WAL mode, so reads never wait on a writer. Writes go through a
+//! single connection, so writers queue on a lock here instead of racing for SQLite's and
+//! failing with "database is locked"; the busy timeout only covers other processes, such
+//! as `opencraw encrypt-data` or a backup. How often callers had to queue shows up in
+//! `GET /api/v1/os/metrics` and `opencraw status`.
+//!
+//! Remembered facts (`facts.db`) go through a pool like the rest. Action proposals are in
+//! Horizons' project database, which Horizons connects to itself; it is only switched to
+//! WAL at startup, so it has no pool and no entry in the metrics.
+
+use anyhow::Result;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, TryLockError};
+use std::time::{Duration, Instant};
+
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Read connections per database.
+const READERS: usize = 4;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SqliteStats {
+    pub readers: usize,
+    pub reads: u64,
+    pub writes: u64,
+    /// Reads or writes that found every suitable connection in use.
+    pub waits: u64,
+    pub wait_ms: u64,
+}
+
+pub struct SqlitePool {
+    writer: Mutex<Connection>,
+    readers: Vec<Mutex<Connection>>,
+    next_reader: AtomicUsize,
+    reads: AtomicU64,
+    writes: AtomicU64,
+    waits: AtomicU64,
+    wait_us: AtomicU64,
+}
+
+impl SqlitePool {
+    /// Open `path`, creating it if needed, and run `schema` on the writer.
+    pub fn open(path: &Path, schema: &str) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let writer = connect(path)?;
+        writer.pragma_update(None, "journal_mode", "WAL")?;
+        writer.pragma_update(None, "synchronous", "NORMAL")?;
+        writer.execute_batch(schema)?;
+        let readers = (0..READERS)
+            .map(|_| {
+                let conn = connect(path)?;
+                conn.pragma_update(None, "query_only", true)?;
+                Ok(Mutex::new(conn))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            writer: Mutex::new(writer),
+            readers,
+            next_reader: AtomicUsize::new(0),
+            reads: AtomicU64::new(0),
+            writes: AtomicU64::new(0),
+            waits: AtomicU64::new(0),
+            wait_us: AtomicU64::new(0),
+        })
+    }
+
+    /// The write connection; writers take turns.
+    pub fn write(&self) -> Result<MutexGuard<'_, Connection>> {
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        match self.writer.try_lock() {
+            Ok(conn) => Ok(conn),
+            Err(TryLockError::WouldBlock) => self.wait(&self.writer),
+            Err(TryLockError::Poisoned(_)) => Err(anyhow::anyhow!("sqlite writer lock poisoned")),
+        }
+    }
+
+    /// A free read connection, or the next one in turn when all are busy.
+    pub fn read(&self) -> Result<MutexGuard<'_, Connection>> {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        for reader in &self.readers {
+            match reader.try_lock() {
+                Ok(conn) => return Ok(conn),
+                Err(TryLockError::WouldBlock) => {}
+                Err(TryLockError::Poisoned(_)) => {
+                    return Err(anyhow::anyhow!("sqlite reader lock poisoned"))
+                }
+            }
+        }
+        let next = self.next_reader.fetch_add(1, Ordering::Relaxed) % self.readers.len();
+        self.wait(&self.readers[next])
+    }
+
+    pub fn stats(&self) -> SqliteStats {
+        SqliteStats {
+            readers: self.readers.len(),
+            reads: self.reads.load(Ordering::Relaxed),
+            writes: self.writes.load(Ordering::Relaxed),
+            waits: self.waits.load(Ordering::Relaxed),
+            wait_ms: self.wait_us.load(Ordering::Relaxed) / 1000,
+        }
+    }
+
+    fn wait<'a>(&self, conn: &'a Mutex<Connection>) -> Result<MutexGuard<'a, Connection>> {
+        let started = Instant::now();
+        let conn = conn
+            .lock()
+            .map_err(|_| anyhow::anyhow!("sqlite connection lock poisoned"))?;
+        self.waits.fetch_add(1, Ordering::Relaxed);
+        self.wait_us
+            .fetch_add(started.elapsed().as_micros() as u64, Ordering::Relaxed);
+        Ok(conn)
+    }
+}
+
+fn connect(path: &Path) -> Result<Connection> {
+    let conn = Connection::open(path)?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    Ok(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn reads_see_committed_writes_while_a_write_is_open() {
+        let tmp = std::env::temp_dir().join(format!("opencraw-sqlite-{}", Uuid::new_v4()));
+        let pool = SqlitePool::open(
+            &tmp.join("test.db"),
+            "CREATE TABLE IF NOT EXISTS notes (body TEXT NOT NULL);",
+        )
+        .unwrap();
+        let mode: String = pool
+            .read()
+            .unwrap()
+            .query_row("PRAGMA journal_mode", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(mode, "wal");
+
+        pool.write()
+            .unwrap()
+            .execute("INSERT INTO notes (body) VALUES ('first')", [])
+            .unwrap();
+        let mut writer = pool.write().unwrap();
+        let tx = writer.transaction().unwrap();
+        tx.execute("INSERT INTO notes (body) VALUES ('second')", [])
+            .unwrap();
+        let count: i64 = pool
+            .read()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM notes", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(count, 1);
+        assert!(pool
+            .read()
+            .unwrap()
+            .execute("INSERT INTO notes (body) VALUES ('third')", [])
+            .is_err());
+        tx.commit().unwrap();
+        drop(writer);
+
+        let stats = pool.stats();
+        assert_eq!((stats.readers, stats.writes, stats.waits), (READERS, 2, 0));
+        std::fs::remove_dir_all(&tmp).unwrap();
+    }
+}
@@ -0,0 +1,353 @@
+//! Suggest mode: drafted replies to third parties, sent only once approved.
+//!
+//! On channels listed in `suggestions.channels`, a message from someone outside the
+//! allowlist doesn't get an answer. The gateway has the assistant draft one (without
+//! tools, since the text is untrusted) and hands it to this queue, which sends the draft
+//! privately to `suggestions.deliver_to` under a short code. The owner replies
+//! `/send_<code>` to send it as is, `/send_<code> <text>` to send their own wording, or
+//! `/dismiss_<code>`. Telegram renders `/send_<code>` as a tappable command.
+
+use crate::config::SuggestionsConfig;
+use crate::outbox::Outbox;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use os_channels::OutboundMessage;
+use serde::Serialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Characters of the incoming message quoted above the draft.
+const QUOTE_CHARS_MAX: usize = 300;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Suggestion {
+    pub code: String,
+    pub channel_id: String,
+    /// The third party the draft answers.
+    pub contact: String,
+    /// Where the reply goes (thread/chat id, falling back to the contact).
+    pub recipient: String,
+    pub reply_to_message_id: String,
+    pub incoming: String,
+    pub draft: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SuggestionCommand {
+    /// Send the draft, or the given text in its place.
+    Send {
+        code: String,
+        text: Option<String>,
+    },
+    Dismiss {
+        code: String,
+    },
+}
+
+impl SuggestionCommand {
+    /// Accepts `/send_<code>`, `/send <code>`, either followed by replacement text, and
+    /// the same forms of `/dismiss`. A Telegram `@botname` suffix is ignored.
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+        let (verb, rest) = if let Some(rest) = input.strip_prefix("/send") {
+            ("send", rest)
+        } else if let Some(rest) = input.strip_prefix("/dismiss") {
+            ("dismiss", rest)
+        } else {
+            return None;
+        };
+        let rest = rest
+            .strip_prefix('_')
+            .or_else(|| rest.strip_prefix(' '))?
+            .trim_start();
+        let (code, text) = match rest.split_once(char::is_whitespace) {
+            Some((code, text)) => (code, Some(text.trim())),
+            None => (rest, None),
+        };
+        let code = code
+            .split('@')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if code.is_empty() {
+            return None;
+        }
+        let text = text.filter(|t| !t.is_empty()).map(|t| t.to_string());
+        Some(match verb {
+            "send" => Self::Send { code, text },
+            _ => Self::Dismiss { code },
+        })
+    }
+}
+
+pub struct SuggestionQueue {
+    cfg: SuggestionsConfig,
+    outbox: Arc<Outbox>,
+    pending: DashMap<String, Suggestion>,
+}
+
+impl SuggestionQueue {
+    pub fn new(cfg: SuggestionsConfig, outbox: Arc<Outbox>) -> Self {
+        Self {
+            cfg,
+            outbox,
+            pending: DashMap::new(),
+        }
+    }
+
+    pub fn list(&self) -> Vec<Suggestion> {
+        self.drop_expired();
+        let mut out: Vec<Suggestion> = self.pending.iter().map(|e| e.value().clone()).collect();
+        out.sort_by_key(|s| s.created_at);
+        out
+    }
+
+    /// Queue a draft and deliver it to the owner for approval.
+    #[tracing::instrument(level = "info", skip_all, fields(channel_id = %channel_id))]
+    pub async fn propose(
+        &self,
+        channel_id: &str,
+        contact: &str,
+        recipient: &str,
+        reply_to_message_id: &str,
+        incoming: &str,
+        draft: String,
+    ) -> Result<Suggestion> {
+        let (target_channel, target_recipient) = self
+            .cfg
+            .delivery_target()
+            .ok_or_else(|| anyhow::anyhow!("suggestions.deliver_to is not set"))?;
+        self.drop_expired();
+
+        let suggestion = Suggestion {
+            code: self.new_code(),
+            channel_id: channel_id.to_string(),
+            contact: contact.to_string(),
+            recipient: recipient.to_string(),
+            reply_to_message_id: reply_to_message_id.to_string(),
+            incoming: incoming.to_string(),
+            draft,
+            created_at: Utc::now(),
+        };
+        self.pending
+            .insert(suggestion.code.clone(), suggestion.clone());
+
+        let content = approval_prompt(&suggestion);
+        self.outbox
+            .send(
+                target_channel,
+                target_recipient,
+                OutboundMessage {
+                    content,
+                    reply_to_message_id: None,
+                    attachments: vec![],
+                },
+            )
+            .await?;
+        Ok(suggestion)
+    }
+
+    /// Carry out an owner's command and return the confirmation to show them.
+    pub async fn apply(&self, command: SuggestionCommand) -> String {
+        match command {
+            SuggestionCommand::Send { code, text } => match self.send(&code, text).await {
+                Ok(Some(s)) => format!("Sent to {} on {}.", s.contact, s.channel_id),
+                Ok(None) => format!("No pending suggestion {code}."),
+                Err(e) => format!("Failed to send suggestion {code}: {e}"),
+            },
+            SuggestionCommand::Dismiss { code } => {
+                if self.dismiss(&code) {
+                    format!("Dismissed suggestion {code}.")
+                } else {
+                    format!("No pending suggestion {code}.")
+                }
+            }
+        }
+    }
+
+    /// Send a pending draft (or `text` instead). `None` if there is no such suggestion.
+    /// On failure the suggestion stays queued so it can be retried.
+    pub async fn send(&self, code: &str, text: Option<String>) -> Result<Option<Suggestion>> {
+        self.drop_expired();
+        let Some(suggestion) = self.pending.get(code).map(|s| s.clone()) else {
+            return Ok(None);
+        };
+        self.outbox
+            .send(
+                &suggestion.channel_id,
+                &suggestion.recipient,
+                OutboundMessage {
+                    content: text.unwrap_or_else(|| suggestion.draft.clone()),
+                    reply_to_message_id: Some(suggestion.reply_to_message_id.clone()),
+                    attachments: vec![],
+                },
+            )
+            .await?;
+        self.pending.remove(code);
+        Ok(Some(suggestion))
+    }
+
+    pub fn dismiss(&self, code: &str) -> bool {
+        self.pending.remove(code).is_some()
+    }
+
+    fn new_code(&self) -> String {
+        loop {
+            let code = Uuid::new_v4().simple().to_string()[..6].to_string();
+            if !self.pending.contains_key(&code) {
+                return code;
+            }
+        }
+    }
+
+    fn drop_expired(&self) {
+        let cutoff = Utc::now() - chrono::Duration::hours(self.cfg.expire_hours as i64);
+        self.pending.retain(|_, s| s.created_at > cutoff);
+    }
+}
+
+fn approval_prompt(s: &Suggestion) -> String {
+    let mut quoted: String = s.incoming.chars().take(QUOTE_CHARS_MAX).collect();
+    if quoted.len() < s.incoming.len() {
+        quoted.push('…');
+    }
+    format!(
+        "Suggested reply to {} on {}:\n> {}\n\n{}\n\n/send_{code} to send it, /send_{code} <text> to send your own, /dismiss_{code} to drop it.",
+        s.contact,
+        s.channel_id,
+        quoted.replace('\n', "\n> "),
+        s.draft,
+        code = s.code,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use os_channels::{ChannelAdapter, InboundMessage};
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn parses_tappable_and_spaced_commands() {
+        assert_eq!(
+            SuggestionCommand::parse("/send_ab12cd"),
+            Some(SuggestionCommand::Send {
+                code: "ab12cd".to_string(),
+                text: None
+            })
+        );
+        assert_eq!(
+            SuggestionCommand::parse("/send_ab12cd@opencraw_bot"),
+            Some(SuggestionCommand::Send {
+                code: "ab12cd".to_string(),
+                text: None
+            })
+        );
+        assert_eq!(
+            SuggestionCommand::parse("/send AB12CD  Sounds good, see you at 7"),
+            Some(SuggestionCommand::Send {
+                code: "ab12cd".to_string(),
+                text: Some("Sounds good, see you at 7".to_string())
+            })
+        );
+        assert_eq!(
+            SuggestionCommand::parse("/dismiss_ab12cd"),
+            Some(SuggestionCommand::Dismiss {
+                code: "ab12cd".to_string()
+            })
+        );
+        assert_eq!(SuggestionCommand::parse("/sendall"), None);
+        assert_eq!(SuggestionCommand::parse("/send"), None);
+        assert_eq!(SuggestionCommand::parse("/status"), None);
+    }
+
+    struct Recorder {
+        id: &'static str,
+        sent: Mutex<Vec<(String, String)>>,
+    }
+
+    #[async_trait]
+    impl ChannelAdapter for Recorder {
+        fn channel_id(&self) -> &str {
+            self.id
+        }
+
+        async fn start(&self, _tx: mpsc::Sender<InboundMessage>) -> Result<()> {
+            Ok(())
+        }
+
+        async fn send(&self, recipient_id: &str, message: OutboundMessage) -> Result<()> {
+            self.sent
+                .lock()
+                .unwrap()
+                .push((recipient_id.to_string(), message.content));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn draft_goes_to_owner_and_reaches_contact_only_when_sent() {
+        let recorder = |id| {
+            Arc::new(Recorder {
+                id,
+                sent: Mutex::new(vec![]),
+            })
+        };
+        let imessage = recorder("imessage");
+        let telegram = recorder("telegram");
+        let channels: HashMap<String, Arc<dyn ChannelAdapter>> = HashMap::from([
+            (
+                "imessage".to_string(),
+                imessage.clone() as Arc<dyn ChannelAdapter>,
+            ),
+            (
+                "telegram".to_string(),
+                telegram.clone() as Arc<dyn ChannelAdapter>,
+            ),
+        ]);
+        let cfg = SuggestionsConfig {
+            channels: vec!["imessage".to_string()],
+            deliver_to: "telegram:999".to_string(),
+            ..SuggestionsConfig::default()
+        };
+        let queue = SuggestionQueue::new(cfg, Arc::new(Outbox::new(channels)));
+
+        let s = queue
+            .propose(
+                "imessage",
+                "+15550001111",
+                "+15550001111",
+                "m1",
+                "dinner tonight?",
+                "Yes! 7pm?".to_string(),
+            )
+            .await
+            .unwrap();
+        {
+            let to_owner = telegram.sent.lock().unwrap();
+            assert_eq!(to_owner.len(), 1);
+            assert_eq!(to_owner[0].0, "999");
+            assert!(to_owner[0].1.contains(&format!("/send_{}", s.code)));
+        }
+        assert!(imessage.sent.lock().unwrap().is_empty());
+
+        let reply = queue
+            .apply(SuggestionCommand::Send {
+                code: s.code.clone(),
+                text: Some("Yes, 8pm works".to_string()),
+            })
+            .await;
+        assert!(reply.starts_with("Sent"));
+        assert_eq!(
+            imessage.sent.lock().unwrap().as_slice(),
+            [("+15550001111".to_string(), "Yes, 8pm works".to_string())]
+        );
+        assert!(queue.list().is_empty());
+        assert!(queue.send(&s.code, None).await.unwrap().is_none());
+    }
+}
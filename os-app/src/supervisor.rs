@@ -0,0 +1,251 @@
+//! Restarting channel adapters whose receive loop stopped.
+//!
+//! An adapter that stops receiving (a revoked bot token, an unreadable database) would
+//! otherwise leave its channel silent until the server restarts. The supervisor notices
+//! through `ChannelAdapter::receive_failure`, starts the adapter again with exponential
+//! backoff, and tells `supervisor.notify_*` after `notify_after` failures in a row. An
+//! adapter that stays up for `STABLE_AFTER` starts its count over.
+
+use crate::config::SupervisorConfig;
+use crate::outbox::Outbox;
+use os_channels::{ChannelAdapter, InboundMessage, OutboundMessage};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+const STABLE_AFTER: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Default)]
+struct Restarts {
+    /// Failures in a row, counting a restart that didn't stay up.
+    failures: u32,
+    /// Set while the adapter is down.
+    retry_at: Option<Instant>,
+    started_at: Option<Instant>,
+    /// From the last `start`, when it failed outright.
+    start_error: Option<String>,
+}
+
+pub struct Supervisor {
+    cfg: SupervisorConfig,
+    channels: HashMap<String, Arc<dyn ChannelAdapter>>,
+    /// Weak, so the inbound queue still closes when the channels go away.
+    inbound_tx: mpsc::WeakSender<InboundMessage>,
+    outbox: Arc<Outbox>,
+    restarts: tokio::sync::Mutex<HashMap<String, Restarts>>,
+}
+
+impl Supervisor {
+    pub fn new(
+        cfg: SupervisorConfig,
+        channels: HashMap<String, Arc<dyn ChannelAdapter>>,
+        inbound_tx: &mpsc::Sender<InboundMessage>,
+        outbox: Arc<Outbox>,
+    ) -> Self {
+        Self {
+            cfg,
+            channels,
+            inbound_tx: inbound_tx.downgrade(),
+            outbox,
+            restarts: tokio::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn start(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(CHECK_INTERVAL);
+            loop {
+                tick.tick().await;
+                self.check(Instant::now()).await;
+            }
+        });
+    }
+
+    async fn check(&self, now: Instant) {
+        let Some(inbound_tx) = self.inbound_tx.upgrade() else {
+            return;
+        };
+        let mut restarts = self.restarts.lock().await;
+        for (channel_id, adapter) in &self.channels {
+            let state = restarts.entry(channel_id.clone()).or_default();
+            let Some(error) = state
+                .start_error
+                .clone()
+                .or_else(|| adapter.receive_failure())
+            else {
+                if state
+                    .started_at
+                    .is_some_and(|at| now.duration_since(at) >= STABLE_AFTER)
+                {
+                    state.failures = 0;
+                    state.started_at = None;
+                }
+                continue;
+            };
+
+            let retry_at = match state.retry_at {
+                Some(at) => at,
+                None => {
+                    state.failures += 1;
+                    let delay = self.backoff(state.failures);
+                    tracing::warn!(
+                        channel = %channel_id,
+                        %error,
+                        failures = state.failures,
+                        retry_in_ms = delay.as_millis() as u64,
+                        "channel adapter stopped"
+                    );
+                    if state.failures == self.cfg.notify_after {
+                        self.notify(channel_id, state.failures, &error).await;
+                    }
+                    *state.retry_at.insert(now + delay)
+                }
+            };
+            if now < retry_at {
+                continue;
+            }
+
+            state.retry_at = None;
+            state.started_at = Some(now);
+            state.start_error = match adapter.start(inbound_tx.clone()).await {
+                Ok(()) => {
+                    tracing::info!(channel = %channel_id, "channel adapter restarted");
+                    None
+                }
+                Err(e) => Some(e.to_string()),
+            };
+        }
+    }
+
+    /// Wait before restart number `failures`, counting from 1.
+    fn backoff(&self, failures: u32) -> Duration {
+        Duration::from_millis(self.cfg.restart_backoff_ms)
+            .saturating_mul(1 << failures.saturating_sub(1).min(16))
+            .min(Duration::from_secs(self.cfg.restart_backoff_max_seconds))
+    }
+
+    async fn notify(&self, channel_id: &str, failures: u32, error: &str) {
+        let (Some(notify_channel), Some(recipient)) = (
+            self.cfg.notify_channel.as_deref(),
+            self.cfg.notify_recipient.as_deref(),
+        ) else {
+            return;
+        };
+        let msg = OutboundMessage {
+            content: format!(
+                "OpenCraw: the {channel_id} channel stopped {failures} times in a row and is \
+                 still being restarted. Last error: {error}"
+            ),
+            reply_to_message_id: None,
+            attachments: vec![],
+        };
+        if let Err(e) = self.outbox.send(notify_channel, recipient, msg).await {
+            tracing::warn!(%e, "supervisor notify failed");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use os_channels::ReceiveFailure;
+    use std::sync::Mutex;
+
+    /// Stops again as soon as it starts, like a revoked token would.
+    #[derive(Default)]
+    struct Revoked {
+        failure: ReceiveFailure,
+        starts: Mutex<u32>,
+    }
+
+    #[async_trait]
+    impl ChannelAdapter for Revoked {
+        fn channel_id(&self) -> &str {
+            "telegram"
+        }
+
+        async fn start(&self, _tx: mpsc::Sender<InboundMessage>) -> anyhow::Result<()> {
+            *self.starts.lock().unwrap() += 1;
+            self.failure.set("401 Unauthorized");
+            Ok(())
+        }
+
+        async fn send(&self, _recipient_id: &str, _message: OutboundMessage) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn receive_failure(&self) -> Option<String> {
+            self.failure.get()
+        }
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        sent: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl ChannelAdapter for Recorder {
+        fn channel_id(&self) -> &str {
+            "ntfy"
+        }
+
+        async fn start(&self, _tx: mpsc::Sender<InboundMessage>) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        async fn send(&self, _recipient_id: &str, message: OutboundMessage) -> anyhow::Result<()> {
+            self.sent.lock().unwrap().push(message.content);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn stopped_adapters_restart_with_backoff_and_notify_once() {
+        let telegram = Arc::new(Revoked::default());
+        telegram.failure.set("401 Unauthorized");
+        let ntfy = Arc::new(Recorder::default());
+        let mut channels: HashMap<String, Arc<dyn ChannelAdapter>> = HashMap::new();
+        channels.insert("telegram".to_string(), telegram.clone());
+        channels.insert("ntfy".to_string(), ntfy.clone());
+        let (tx, _rx) = mpsc::channel(8);
+        let supervisor = Supervisor::new(
+            SupervisorConfig {
+                notify_after: 2,
+                notify_channel: Some("ntfy".to_string()),
+                notify_recipient: Some("alerts".to_string()),
+                ..SupervisorConfig::default()
+            },
+            channels.clone(),
+            &tx,
+            Arc::new(Outbox::new(channels)),
+        );
+
+        let t0 = Instant::now();
+        let starts = || *telegram.starts.lock().unwrap();
+        supervisor.check(t0).await;
+        assert_eq!(starts(), 0, "waits out the first backoff");
+        supervisor.check(t0 + Duration::from_secs(1)).await;
+        assert_eq!(starts(), 1);
+
+        // Down again: the second failure waits twice as long and notifies.
+        supervisor.check(t0 + Duration::from_secs(2)).await;
+        supervisor.check(t0 + Duration::from_secs(3)).await;
+        assert_eq!(starts(), 1);
+        supervisor.check(t0 + Duration::from_secs(4)).await;
+        assert_eq!(starts(), 2);
+        for i in 5..20 {
+            supervisor.check(t0 + Duration::from_secs(i)).await;
+        }
+        assert!(starts() > 2);
+
+        let sent = ntfy.sent.lock().unwrap();
+        assert_eq!(sent.len(), 1, "{sent:?}");
+        assert!(sent[0].contains("telegram channel stopped 2 times"));
+        assert!(sent[0].contains("401 Unauthorized"));
+    }
+}
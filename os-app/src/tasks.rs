@@ -0,0 +1,374 @@
+//! Background task delegation.
+//!
+//! The assistant can hand a long job to `delegate_task`, which queues a bounded background
+//! agent run (its own session, tool-loop and token budget) and returns a task id at once.
+//! When the run finishes, the result is sent back to the conversation it came from.
+//!
+//! A task runs outside the gateway's lanes and the `/stop` scope of the run that started
+//! it, under the `[tasks]` budget and timeout instead; only the tasks API cancels it. So
+//! `delegate_task` is medium risk and goes through approval like other medium-risk tools.
+//!
+//! See: specifications/openshell/implementation_v0_1_0.md
+
+use crate::assistant::{AssistantAgent, RunBudget};
+use crate::config::TasksConfig;
+use crate::outbox::Outbox;
+use crate::session::Session;
+use crate::webhooks::{self, Webhooks};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use horizons_core::core_agents::models::RiskLevel;
+use os_channels::OutboundMessage;
+use os_tools::{CancellationToken, Tool, ToolError, ToolSpec};
+use serde::Serialize;
+use serde_json::json;
+use std::future::Future;
+use std::sync::{Arc, OnceLock, Weak};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use uuid::Uuid;
+
+pub const DELEGATE_TASK_TOOL: &str = "delegate_task";
+
+/// Finished tasks kept for the status API.
+const FINISHED_TASKS_MAX: usize = 200;
+/// How long a cancelled run gets to stop its tools before it is dropped.
+const CANCEL_GRACE: Duration = Duration::from_secs(3);
+
+tokio::task_local! {
+    /// The conversation the current assistant run belongs to. Set by the gateway so tools
+    /// can address replies without widening the `Tool` interface.
+    pub static CONVERSATION: ConversationOrigin;
+
+    /// Fires when the current run should stop. The assistant hands it to every tool call.
+    pub static RUN_CANCEL: CancellationToken;
+}
+
+/// Run `run` with `cancel` as its `RUN_CANCEL`. Returns `None` if the run was cancelled,
+/// through `cancel` or by outliving `deadline`; it then gets `CANCEL_GRACE` to wind down
+/// (tools kill their processes and return) before whatever is left is dropped.
+pub async fn run_cancellable<F: Future>(
+    cancel: CancellationToken,
+    deadline: Option<Duration>,
+    run: F,
+) -> Option<F::Output> {
+    let run = RUN_CANCEL.scope(cancel.clone(), run);
+    tokio::pin!(run);
+    let expired = async {
+        match deadline {
+            Some(deadline) => tokio::time::sleep(deadline).await,
+            None => std::future::pending().await,
+        }
+    };
+    let finished = tokio::select! {
+        out = &mut run => Some(out),
+        _ = cancel.cancelled() => None,
+        _ = expired => {
+            cancel.cancel();
+            None
+        }
+    };
+    match finished {
+        Some(out) => (!cancel.is_cancelled()).then_some(out),
+        None => {
+            let _ = tokio::time::timeout(CANCEL_GRACE, run).await;
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConversationOrigin {
+    pub channel_id: String,
+    pub sender_id: String,
+    /// Where replies go (thread/chat id, falling back to the sender).
+    pub recipient: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl TaskStatus {
+    fn is_finished(self) -> bool {
+        matches!(self, Self::Completed | Self::Failed | Self::Cancelled)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskRecord {
+    pub id: Uuid,
+    pub description: String,
+    pub origin: ConversationOrigin,
+    pub status: TaskStatus,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub result: Option<String>,
+    pub error: Option<String>,
+}
+
+pub struct TaskRegistry {
+    cfg: TasksConfig,
+    tasks: DashMap<Uuid, TaskRecord>,
+    cancels: DashMap<Uuid, CancellationToken>,
+    slots: Arc<Semaphore>,
+    outbox: Arc<Outbox>,
+    webhooks: Option<Arc<Webhooks>>,
+    assistant: OnceLock<Weak<AssistantAgent>>,
+}
+
+impl TaskRegistry {
+    pub fn new(cfg: TasksConfig, outbox: Arc<Outbox>) -> Self {
+        Self {
+            slots: Arc::new(Semaphore::new(cfg.max_concurrent)),
+            cfg,
+            tasks: DashMap::new(),
+            cancels: DashMap::new(),
+            outbox,
+            webhooks: None,
+            assistant: OnceLock::new(),
+        }
+    }
+
+    pub fn with_webhooks(mut self, webhooks: Arc<Webhooks>) -> Self {
+        self.webhooks = Some(webhooks);
+        self
+    }
+
+    /// The assistant is built after its tools, so it is attached once it exists.
+    pub fn attach_assistant(&self, assistant: &Arc<AssistantAgent>) {
+        let _ = self.assistant.set(Arc::downgrade(assistant));
+    }
+
+    pub fn list(&self) -> Vec<TaskRecord> {
+        let mut out: Vec<TaskRecord> = self.tasks.iter().map(|e| e.value().clone()).collect();
+        out.sort_by_key(|t| t.created_at);
+        out.reverse();
+        out
+    }
+
+    pub fn get(&self, id: Uuid) -> Option<TaskRecord> {
+        self.tasks.get(&id).map(|e| e.value().clone())
+    }
+
+    pub fn cancel(&self, id: Uuid) -> bool {
+        let Some(mut task) = self.tasks.get_mut(&id) else {
+            return false;
+        };
+        if task.status.is_finished() {
+            return false;
+        }
+        if let Some((_, cancel)) = self.cancels.remove(&id) {
+            cancel.cancel();
+        }
+        task.status = TaskStatus::Cancelled;
+        task.finished_at = Some(Utc::now());
+        true
+    }
+
+    pub fn submit(self: &Arc<Self>, origin: ConversationOrigin, description: String) -> TaskRecord {
+        self.prune();
+        let record = TaskRecord {
+            id: Uuid::new_v4(),
+            description,
+            origin,
+            status: TaskStatus::Queued,
+            created_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+            result: None,
+            error: None,
+        };
+        self.tasks.insert(record.id, record.clone());
+
+        let this = self.clone();
+        let task = record.clone();
+        let cancel = CancellationToken::new();
+        self.cancels.insert(record.id, cancel.clone());
+        tokio::spawn(async move { this.run_task(task, cancel).await });
+        record
+    }
+
+    #[tracing::instrument(level = "info", skip_all, fields(task_id = %task.id))]
+    async fn run_task(&self, task: TaskRecord, cancel: CancellationToken) {
+        let permit = tokio::select! {
+            permit = self.slots.clone().acquire_owned() => permit,
+            _ = cancel.cancelled() => return,
+        };
+        let Ok(_permit) = permit else {
+            return;
+        };
+        self.update(task.id, |t| {
+            t.status = TaskStatus::Running;
+            t.started_at = Some(Utc::now());
+        });
+
+        let outcome = match self.assistant.get().and_then(|w| w.upgrade()) {
+            Some(assistant) => {
+                let mut session = Session::new();
+                let budget = RunBudget {
+                    tool_loops_max: self.cfg.tool_loops_max,
+                    tokens_max: Some(self.cfg.tokens_max),
+                    allow_delegation: false,
+                    allow_tools: true,
+                };
+                let run = assistant.run_with_budget(
+                    &task.origin.channel_id,
+                    &task.origin.sender_id,
+                    &mut session,
+                    &task.description,
+                    budget,
+                );
+                let run = CONVERSATION.scope(task.origin.clone(), run);
+                let timeout = Duration::from_secs(self.cfg.timeout_seconds);
+                match run_cancellable(cancel, Some(timeout), run).await {
+                    Some(Ok(v)) => Ok(v),
+                    Some(Err(e)) => Err(e.to_string()),
+                    None => Err(format!("timed out after {}s", self.cfg.timeout_seconds)),
+                }
+            }
+            None => Err("assistant is not available".to_string()),
+        };
+
+        self.cancels.remove(&task.id);
+        // Cancelled through the API: already marked, and nobody is waiting for a result.
+        if self
+            .get(task.id)
+            .is_some_and(|t| t.status == TaskStatus::Cancelled)
+        {
+            return;
+        }
+        self.update(task.id, |t| {
+            t.finished_at = Some(Utc::now());
+            match &outcome {
+                Ok(v) => {
+                    t.status = TaskStatus::Completed;
+                    t.result = Some(v.clone());
+                }
+                Err(e) => {
+                    t.status = TaskStatus::Failed;
+                    t.error = Some(e.clone());
+                }
+            }
+        });
+
+        if let Some(hooks) = self.webhooks.as_ref() {
+            let (event, detail) = match &outcome {
+                Ok(_) => (webhooks::TASK_COMPLETED, json!(null)),
+                Err(e) => (webhooks::TASK_FAILED, json!(e)),
+            };
+            hooks.emit(
+                event,
+                json!({
+                    "task_id": task.id,
+                    "description": task.description,
+                    "channel_id": task.origin.channel_id,
+                    "error": detail,
+                }),
+            );
+        }
+
+        let content = match &outcome {
+            Ok(v) => format!("Task {} finished:\n{v}", task.id),
+            Err(e) => format!("Task {} failed: {e}", task.id),
+        };
+        self.deliver(&task.origin, content).await;
+    }
+
+    async fn deliver(&self, origin: &ConversationOrigin, content: String) {
+        let msg = OutboundMessage {
+            content,
+            reply_to_message_id: None,
+            attachments: vec![],
+        };
+        if let Err(e) = self
+            .outbox
+            .send(&origin.channel_id, &origin.recipient, msg)
+            .await
+        {
+            tracing::warn!(%e, "task result delivery failed");
+        }
+    }
+
+    fn update(&self, id: Uuid, f: impl FnOnce(&mut TaskRecord)) {
+        if let Some(mut t) = self.tasks.get_mut(&id) {
+            // A cancelled task keeps its status even if the run raced to completion.
+            if t.status != TaskStatus::Cancelled {
+                f(&mut t);
+            }
+        }
+    }
+
+    fn prune(&self) {
+        let mut finished: Vec<(DateTime<Utc>, Uuid)> = self
+            .tasks
+            .iter()
+            .filter(|e| e.status.is_finished())
+            .map(|e| (e.created_at, e.id))
+            .collect();
+        if finished.len() <= FINISHED_TASKS_MAX {
+            return;
+        }
+        finished.sort();
+        let excess = finished.len() - FINISHED_TASKS_MAX;
+        for (_, id) in finished.into_iter().take(excess) {
+            self.tasks.remove(&id);
+        }
+    }
+}
+
+pub struct DelegateTaskTool {
+    registry: Arc<TaskRegistry>,
+}
+
+impl DelegateTaskTool {
+    pub fn new(registry: Arc<TaskRegistry>) -> Self {
+        Self { registry }
+    }
+}
+
+#[async_trait]
+impl Tool for DelegateTaskTool {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: DELEGATE_TASK_TOOL.to_string(),
+            description: "Start a long-running job in the background. Returns a task id immediately; the result is sent to this conversation when the job finishes. Describe the job completely, the background agent does not see this conversation.".to_string(),
+            parameters_schema: json!({
+                "type": "object",
+                "properties": {
+                    "task": { "type": "string", "description": "Self-contained description of the job." }
+                },
+                "required": ["task"]
+            }),
+            // The job outlives this run and `/stop`; see the module docs.
+            risk_level: RiskLevel::Medium,
+        }
+    }
+
+    async fn execute(
+        &self,
+        arguments: serde_json::Value,
+        _cancel: &CancellationToken,
+    ) -> os_tools::Result<serde_json::Value> {
+        let task = arguments
+            .get("task")
+            .and_then(|v| v.as_str())
+            .filter(|s| !s.trim().is_empty())
+            .ok_or_else(|| ToolError::InvalidArguments("missing key: task".to_string()))?;
+        let origin = CONVERSATION.try_with(|o| o.clone()).map_err(|_| {
+            ToolError::ExecutionFailed(
+                "delegate_task needs a conversation to report to".to_string(),
+            )
+        })?;
+        let record = self.registry.submit(origin, task.to_string());
+        Ok(json!({ "task_id": record.id, "status": record.status }))
+    }
+}
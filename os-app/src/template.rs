@@ -0,0 +1,430 @@
+//! Restricted templates for prompts and outgoing text.
+//!
+//! The only construct is a placeholder: `{{ name }}`, optionally followed by filters
+//! (`{{ name | trim | truncate:200 | default:"none" }}`). There are no conditionals, loops
+//! or expressions. Values are substituted in a single pass, so a value containing `{{`
+//! is never expanded, and each value is escaped for where the text is going unless the
+//! placeholder says `| raw`. Unknown variables and filters are errors, and so are
+//! templates or outputs over the size limits, so a bad template fails loudly instead of
+//! sending something half-filled.
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local};
+use std::collections::BTreeMap;
+
+const SOURCE_MAX_BYTES: usize = 32 * 1024;
+const PLACEHOLDERS_MAX: usize = 200;
+/// Variables taken from one JSON document; the rest of a large one is left out.
+const JSON_VARS_MAX: usize = 1000;
+pub const OUTPUT_MAX_BYTES: usize = 64 * 1024;
+
+/// Variables available in `general.system_prompt` and persona prompts.
+pub const SYSTEM_PROMPT_VARS: &[&str] = &["today", "weekday", "now", "channel"];
+
+/// Where the rendered text goes, which decides how substituted values are escaped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Escape {
+    /// Text for an LLM: control characters (other than newlines and tabs), zero-width
+    /// characters and bidi overrides are dropped, so a value can't hide text from a reader.
+    Prompt,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Text(String),
+    /// Rendered one item per line as `- item`.
+    List(Vec<String>),
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Self::Text(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Self::Text(s)
+    }
+}
+
+impl From<Vec<String>> for Value {
+    fn from(items: Vec<String>) -> Self {
+        Self::List(items)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Vars(BTreeMap<String, Value>);
+
+impl Vars {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.0.insert(name.to_string(), value.into());
+        self
+    }
+
+    /// `today` (2026-03-01), `weekday` (Sunday) and `now` (14:05), in local time.
+    pub fn with_clock(self, now: DateTime<Local>) -> Self {
+        self.set("today", now.format("%Y-%m-%d").to_string())
+            .set("weekday", now.format("%A").to_string())
+            .set("now", now.format("%H:%M").to_string())
+    }
+
+    /// `value` as `name`, and each field in it by path: `name.repository.full_name`,
+    /// `name.commits.0.message`. Strings are set as they are, arrays as lists, and
+    /// objects and other values as JSON; `null` is empty.
+    pub fn with_json(mut self, name: &str, value: &serde_json::Value) -> Self {
+        let mut stack = vec![(name.to_string(), value)];
+        let mut count = 0;
+        while let Some((path, value)) = stack.pop() {
+            count += 1;
+            if count > JSON_VARS_MAX {
+                break;
+            }
+            let entry = match value {
+                serde_json::Value::Array(items) => {
+                    for (i, item) in items.iter().enumerate().rev() {
+                        stack.push((format!("{path}.{i}"), item));
+                    }
+                    Value::List(items.iter().map(json_text).collect())
+                }
+                serde_json::Value::Object(fields) => {
+                    for (key, field) in fields.iter().rev() {
+                        stack.push((format!("{path}.{key}"), field));
+                    }
+                    Value::Text(value.to_string())
+                }
+                other => Value::Text(json_text(other)),
+            };
+            self.0.insert(path, entry);
+        }
+        self
+    }
+}
+
+fn json_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Filter {
+    Raw,
+    Trim,
+    Truncate(usize),
+    Default(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Placeholder { name: String, filters: Vec<Filter> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self> {
+        if source.len() > SOURCE_MAX_BYTES {
+            return Err(anyhow!(
+                "template is {} bytes; the limit is {SOURCE_MAX_BYTES}",
+                source.len()
+            ));
+        }
+        let mut parts = Vec::new();
+        let mut placeholders = 0;
+        let mut rest = source;
+        while let Some(open) = rest.find("{{") {
+            if open > 0 {
+                parts.push(Part::Text(rest[..open].to_string()));
+            }
+            let offset = source.len() - rest.len() + open;
+            let after = &rest[open + 2..];
+            let close = after
+                .find("}}")
+                .ok_or_else(|| anyhow!("unclosed {{{{ at byte {offset}"))?;
+            placeholders += 1;
+            if placeholders > PLACEHOLDERS_MAX {
+                return Err(anyhow!(
+                    "template has more than {PLACEHOLDERS_MAX} placeholders"
+                ));
+            }
+            parts.push(parse_placeholder(&after[..close])?);
+            rest = &after[close + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        Ok(Self { parts })
+    }
+
+    /// Names of the variables the template uses, in order of first use.
+    pub fn variables(&self) -> Vec<&str> {
+        let mut out: Vec<&str> = Vec::new();
+        for part in &self.parts {
+            if let Part::Placeholder { name, .. } = part {
+                if !out.contains(&name.as_str()) {
+                    out.push(name);
+                }
+            }
+        }
+        out
+    }
+
+    pub fn render(&self, vars: &Vars, escape: Escape) -> Result<String> {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => out.push_str(text),
+                Part::Placeholder { name, filters } => {
+                    out.push_str(&render_value(name, filters, vars, escape)?)
+                }
+            }
+            if out.len() > OUTPUT_MAX_BYTES {
+                return Err(anyhow!("template output exceeds {OUTPUT_MAX_BYTES} bytes"));
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Check that `source` parses and only uses `allowed` variables. An allowed `name.*`
+/// admits any path under `name`, as set by [`Vars::with_json`].
+pub fn check(source: &str, allowed: &[&str]) -> Result<()> {
+    let template = Template::parse(source)?;
+    let admits = |v: &str| {
+        allowed.iter().any(|a| match a.strip_suffix('*') {
+            Some(prefix) => v.starts_with(prefix),
+            None => *a == v,
+        })
+    };
+    match template.variables().into_iter().find(|v| !admits(v)) {
+        Some(unknown) => Err(anyhow!(
+            "unknown variable {unknown:?} (available: {})",
+            allowed.join(", ")
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Parse and render in one step.
+pub fn render(source: &str, vars: &Vars, escape: Escape) -> Result<String> {
+    Template::parse(source)?.render(vars, escape)
+}
+
+fn parse_placeholder(inner: &str) -> Result<Part> {
+    let mut segments = split_filters(inner).into_iter();
+    let name = segments.next().unwrap_or_default().trim().to_string();
+    let valid_name = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+    if !valid_name {
+        return Err(anyhow!("invalid placeholder {{{{{inner}}}}}"));
+    }
+    let filters = segments
+        .map(|f| parse_filter(f.trim()))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Part::Placeholder { name, filters })
+}
+
+/// Split on `|` outside double quotes, so `default:"a|b"` stays whole.
+fn split_filters(inner: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    for (i, c) in inner.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '|' if !quoted => {
+                out.push(&inner[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    out.push(&inner[start..]);
+    out
+}
+
+fn parse_filter(filter: &str) -> Result<Filter> {
+    let (name, arg) = match filter.split_once(':') {
+        Some((name, arg)) => (name.trim(), Some(arg.trim())),
+        None => (filter, None),
+    };
+    match (name, arg) {
+        ("raw", None) => Ok(Filter::Raw),
+        ("trim", None) => Ok(Filter::Trim),
+        ("truncate", Some(n)) => n
+            .parse()
+            .map(Filter::Truncate)
+            .map_err(|_| anyhow!("truncate needs a number of characters, got {n:?}")),
+        ("default", Some(text)) => Ok(Filter::Default(
+            text.strip_prefix('"')
+                .and_then(|t| t.strip_suffix('"'))
+                .unwrap_or(text)
+                .to_string(),
+        )),
+        _ => Err(anyhow!("unknown filter {filter:?}")),
+    }
+}
+
+fn render_value(name: &str, filters: &[Filter], vars: &Vars, escape: Escape) -> Result<String> {
+    let value = vars.0.get(name);
+    let is_empty = match value {
+        Some(Value::Text(text)) => text.is_empty(),
+        Some(Value::List(items)) => items.is_empty(),
+        None => true,
+    };
+    if is_empty {
+        // The default is the template author's text, so it isn't escaped.
+        if let Some(text) = filters.iter().find_map(|f| match f {
+            Filter::Default(text) => Some(text),
+            _ => None,
+        }) {
+            return Ok(text.clone());
+        }
+    }
+    let (items, is_list) = match value {
+        Some(Value::Text(text)) => (vec![text.clone()], false),
+        Some(Value::List(items)) => (items.clone(), true),
+        None => return Err(anyhow!("unknown variable {name:?}")),
+    };
+
+    let raw = filters.contains(&Filter::Raw);
+    let rendered: Vec<String> = items
+        .into_iter()
+        .map(|mut item| {
+            for filter in filters {
+                match filter {
+                    Filter::Trim => item = item.trim().to_string(),
+                    Filter::Truncate(n) if item.chars().count() > *n => {
+                        item = item.chars().take(*n).collect::<String>() + "…";
+                    }
+                    _ => {}
+                }
+            }
+            if raw {
+                item
+            } else {
+                escape_value(&item, escape)
+            }
+        })
+        .collect();
+    Ok(if is_list {
+        rendered
+            .iter()
+            .map(|item| format!("- {item}"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    } else {
+        rendered.concat()
+    })
+}
+
+fn escape_value(value: &str, escape: Escape) -> String {
+    match escape {
+        Escape::Prompt => value
+            .chars()
+            .filter(|c| *c == '\n' || *c == '\t' || !(c.is_control() || is_invisible(*c)))
+            .collect(),
+    }
+}
+
+/// Zero-width characters and bidi overrides, which can hide or reorder text.
+fn is_invisible(c: char) -> bool {
+    matches!(c, '\u{200b}'..='\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}' | '\u{feff}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substitutes_once_and_escapes_by_default() {
+        let vars = Vars::new()
+            .set("name", "{{secret}} *bold*")
+            .set("secret", "hunter2")
+            .set("items", vec!["a\u{202e}".to_string(), "b".to_string()])
+            .set("empty", "");
+
+        let out = render(
+            "Hi {{ name }} / {{name|raw}}\n{{ items }}\n{{ empty | default:\"n/a\" }}",
+            &vars,
+            Escape::Prompt,
+        )
+        .unwrap();
+        assert_eq!(
+            out,
+            "Hi {{secret}} *bold* / {{secret}} *bold*\n- a\n- b\nn/a"
+        );
+
+        assert_eq!(
+            render("{{ name | truncate:4 }}", &vars, Escape::Prompt).unwrap(),
+            "{{se…"
+        );
+        assert_eq!(
+            render(
+                "<{{x}}>",
+                &Vars::new().set("x", "a\u{1b}[31mb"),
+                Escape::Prompt
+            )
+            .unwrap(),
+            "<a[31mb>"
+        );
+    }
+
+    #[test]
+    fn rejects_bad_templates() {
+        let vars = Vars::new().set("x", "1");
+        assert!(render("{{ missing }}", &vars, Escape::Prompt).is_err());
+        assert!(render("{{ x | shout }}", &vars, Escape::Prompt).is_err());
+        assert!(render("{{ x ", &vars, Escape::Prompt).is_err());
+        assert!(render("{{ x + 1 }}", &vars, Escape::Prompt).is_err());
+        assert!(Template::parse(&"{{x}}".repeat(PLACEHOLDERS_MAX + 1)).is_err());
+
+        let big = Vars::new().set("x", "y".repeat(OUTPUT_MAX_BYTES / 2 + 1));
+        assert!(render("{{x}}{{x}}", &big, Escape::Prompt).is_err());
+        assert_eq!(
+            Template::parse("{{a}} {{b}} {{a}}").unwrap().variables(),
+            ["a", "b"]
+        );
+        assert!(check("Today is {{ today }}.", SYSTEM_PROMPT_VARS).is_ok());
+        assert!(check("{{ user_secret }}", SYSTEM_PROMPT_VARS).is_err());
+        assert!(check("{{ payload.a.b }}", &["payload.*"]).is_ok());
+        assert!(check("{{ payloads }}", &["payload.*"]).is_err());
+    }
+
+    #[test]
+    fn json_fields_are_variables_by_path() {
+        let payload = serde_json::json!({
+            "repository": { "full_name": "octo/app" },
+            "commits": [{ "message": "fix" }, { "message": "docs" }],
+            "labels": ["bug", 7],
+            "draft": false,
+            "body": null,
+        });
+        let vars = Vars::new().with_json("payload", &payload);
+        let out = render(
+            "{{ payload.repository.full_name }}: {{ payload.commits.1.message }} {{ payload.draft }}\n{{ payload.labels }}\n{{ payload.body | default:\"(no body)\" }}",
+            &vars,
+            Escape::Prompt,
+        )
+        .unwrap();
+        assert_eq!(out, "octo/app: docs false\n- bug\n- 7\n(no body)");
+        assert!(render("{{ payload.missing }}", &vars, Escape::Prompt).is_err());
+    }
+}
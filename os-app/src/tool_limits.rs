@@ -0,0 +1,93 @@
+//! Concurrency caps for tool execution.
+//!
+//! One global semaphore bounds total in-flight tool calls; optional per-tool semaphores
+//! (from `tools.concurrency`) bound expensive tools such as the browser. The limiter is
+//! owned by the assistant, so the caps hold for every session that shares it.
+
+use crate::config::ToolsConfig;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+pub struct ToolLimiter {
+    global: Arc<Semaphore>,
+    per_tool: HashMap<String, Arc<Semaphore>>,
+}
+
+/// Held for the duration of one tool execution.
+pub struct ToolPermit {
+    _tool: Option<OwnedSemaphorePermit>,
+    _global: OwnedSemaphorePermit,
+}
+
+impl ToolLimiter {
+    pub fn new(cfg: &ToolsConfig) -> Self {
+        Self {
+            global: Arc::new(Semaphore::new(cfg.max_concurrent)),
+            per_tool: cfg
+                .concurrency
+                .iter()
+                .map(|(name, n)| (name.clone(), Arc::new(Semaphore::new(*n))))
+                .collect(),
+        }
+    }
+
+    /// Wait for a slot for `tool_name`. The per-tool permit is taken first so a queue of
+    /// browser calls doesn't hold global slots other tools could use.
+    pub async fn acquire(&self, tool_name: &str) -> ToolPermit {
+        let started = Instant::now();
+        let tool = match self.per_tool.get(tool_name) {
+            Some(sem) => sem.clone().acquire_owned().await.ok(),
+            None => None,
+        };
+        let global = self
+            .global
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("tool semaphore is never closed");
+
+        let waited = started.elapsed();
+        if waited.as_millis() > 100 {
+            tracing::info!(
+                tool = %tool_name,
+                waited_ms = waited.as_millis() as u64,
+                "tool call waited for a concurrency slot"
+            );
+        }
+        ToolPermit {
+            _tool: tool,
+            _global: global,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn per_tool_cap_blocks_until_permit_is_released() {
+        let cfg = ToolsConfig {
+            max_concurrent: 4,
+            concurrency: HashMap::from([("browser".to_string(), 1)]),
+            ..ToolsConfig::default()
+        };
+        let limiter = ToolLimiter::new(&cfg);
+
+        let first = limiter.acquire("browser").await;
+        let blocked =
+            tokio::time::timeout(Duration::from_millis(50), limiter.acquire("browser")).await;
+        assert!(blocked.is_err());
+
+        // Other tools only compete for the global slots.
+        let _shell = limiter.acquire("shell.execute").await;
+
+        drop(first);
+        let second =
+            tokio::time::timeout(Duration::from_millis(50), limiter.acquire("browser")).await;
+        assert!(second.is_ok());
+    }
+}
@@ -0,0 +1,152 @@
+//! Per-turn tool selection.
+//!
+//! With many tools enabled, sending every schema on every call costs a lot of prompt
+//! tokens. When `tools.max_definitions` is set, each turn sends only the most relevant
+//! tools: pinned ones first, then tools used recently in the session, then tools whose
+//! name or description shares words with the user's message. A `request_all_tools`
+//! pseudo-tool lets the model ask for the full set when what it needs was left out.
+
+use crate::config::ToolsConfig;
+use os_llm::{ChatMessage, ToolDefinition};
+use os_tools::Tool;
+use serde_json::json;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+pub const REQUEST_ALL_TOOLS: &str = "request_all_tools";
+
+/// History messages scanned for recent tool use.
+const RECENT_MESSAGES: usize = 20;
+
+const PINNED_SCORE: usize = 1_000;
+const RECENT_SCORE: usize = 100;
+
+/// Tools to offer this turn. `None` means all of them (no ceiling, or it isn't reached).
+pub fn select(
+    tools: &[Arc<dyn Tool>],
+    cfg: &ToolsConfig,
+    history: &[ChatMessage],
+    user_message: &str,
+) -> Option<Vec<Arc<dyn Tool>>> {
+    let max = cfg.max_definitions;
+    if max == 0 || tools.len() <= max {
+        return None;
+    }
+
+    let recent: HashSet<&str> = history
+        .iter()
+        .rev()
+        .take(RECENT_MESSAGES)
+        .flat_map(|m| m.tool_calls.iter().map(|c| c.name.as_str()))
+        .collect();
+    let message_words = words(user_message);
+
+    let mut scored: Vec<(usize, usize, &Arc<dyn Tool>)> = tools
+        .iter()
+        .enumerate()
+        .map(|(i, tool)| {
+            let spec = tool.spec();
+            let mut score = 0;
+            if cfg.pinned.iter().any(|p| p == &spec.name) {
+                score += PINNED_SCORE;
+            }
+            if recent.contains(spec.name.as_str()) {
+                score += RECENT_SCORE;
+            }
+            let tool_words = words(&format!("{} {}", spec.name, spec.description));
+            score += message_words.intersection(&tool_words).count();
+            (score, i, tool)
+        })
+        .collect();
+    // Highest score first; ties keep configuration order.
+    scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    Some(
+        scored
+            .into_iter()
+            .take(max)
+            .map(|(_, _, t)| t.clone())
+            .collect(),
+    )
+}
+
+pub fn request_all_tools_def() -> ToolDefinition {
+    ToolDefinition {
+        name: REQUEST_ALL_TOOLS.to_string(),
+        description: "Only some tools are listed this turn. Call this to get every available tool if none of the listed ones fits.".to_string(),
+        parameters: json!({ "type": "object", "properties": {} }),
+    }
+}
+
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.len() >= 3)
+        .map(|w| w.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use horizons_core::core_agents::models::RiskLevel;
+    use os_llm::{Role, ToolCall};
+    use os_tools::ToolSpec;
+
+    struct Named(&'static str, &'static str);
+
+    #[async_trait]
+    impl Tool for Named {
+        fn spec(&self) -> ToolSpec {
+            ToolSpec {
+                name: self.0.to_string(),
+                description: self.1.to_string(),
+                parameters_schema: json!({ "type": "object" }),
+                risk_level: RiskLevel::Low,
+            }
+        }
+
+        async fn execute(
+            &self,
+            _: serde_json::Value,
+            _: &os_tools::CancellationToken,
+        ) -> os_tools::Result<serde_json::Value> {
+            Ok(json!({}))
+        }
+    }
+
+    fn names(tools: &[Arc<dyn Tool>]) -> Vec<String> {
+        tools.iter().map(|t| t.spec().name).collect()
+    }
+
+    #[test]
+    fn ranks_pinned_then_recent_then_keyword_matches() {
+        let tools: Vec<Arc<dyn Tool>> = vec![
+            Arc::new(Named("shell.execute", "Run a shell command")),
+            Arc::new(Named("browser", "Open a web page")),
+            Arc::new(Named("filesystem", "Read and write files")),
+            Arc::new(Named("clipboard", "Read the clipboard")),
+        ];
+        let history = vec![ChatMessage {
+            role: Role::Assistant,
+            content: String::new(),
+            tool_calls: vec![ToolCall {
+                id: "1".to_string(),
+                name: "browser".to_string(),
+                arguments: "{}".to_string(),
+            }],
+            tool_call_id: None,
+            reasoning: vec![],
+        }];
+        let mut cfg = ToolsConfig {
+            max_definitions: 3,
+            pinned: vec!["clipboard".to_string()],
+            ..ToolsConfig::default()
+        };
+
+        let picked = select(&tools, &cfg, &history, "please write these files").unwrap();
+        assert_eq!(names(&picked), ["clipboard", "browser", "filesystem"]);
+
+        cfg.max_definitions = 0;
+        assert!(select(&tools, &cfg, &history, "anything").is_none());
+    }
+}
@@ -0,0 +1,245 @@
+//! What a conversation's last run did, for `/trace` and
+//! `GET /api/v1/os/sessions/{id}/trace`.
+//!
+//! The assistant records each model call (model, time, tokens, how many tool calls it
+//! asked for) and each tool call that reached approval (the outcome and how long it
+//! waited, then how long the call ran) on the session as the run goes, so a cancelled or
+//! failed run still leaves its trace. The gateway stamps how the run ended.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunTrace {
+    /// Links reactions to the run whose answer they were about; see `feedback`.
+    #[serde(default = "Uuid::new_v4")]
+    pub id: Uuid,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// `completed`, `failed: <error>` or `cancelled`, once finished.
+    pub outcome: Option<String>,
+    pub steps: Vec<TraceStep>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TraceStep {
+    Model {
+        model: String,
+        duration_ms: u64,
+        prompt_tokens: u64,
+        completion_tokens: u64,
+        tool_calls: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    Tool {
+        tool: String,
+        approval: ApprovalOutcome,
+        /// Time spent waiting for the approval.
+        approval_ms: u64,
+        /// Time the call itself ran; 0 when it was denied.
+        duration_ms: u64,
+        outcome: ToolOutcome,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalOutcome {
+    /// Auto-approved, or covered by an earlier grant.
+    NotNeeded,
+    Approved,
+    Denied,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolOutcome {
+    Ok,
+    /// Refused by a network or filesystem policy.
+    Refused,
+    Failed,
+    Denied,
+}
+
+impl RunTrace {
+    pub fn new() -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            started_at: Utc::now(),
+            finished_at: None,
+            outcome: None,
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn finish(&mut self, outcome: impl Into<String>) {
+        self.finished_at = Some(Utc::now());
+        self.outcome = Some(outcome.into());
+    }
+
+    /// One line per step, then totals, short enough for a chat reply.
+    pub fn render(&self) -> String {
+        let end = self.finished_at.unwrap_or_else(Utc::now);
+        let elapsed = (end - self.started_at).num_milliseconds().max(0) as u64;
+        let mut out = format!(
+            "Last run: {}, {}",
+            secs(elapsed),
+            self.outcome.as_deref().unwrap_or("still running")
+        );
+        let (mut models, mut model_ms, mut tokens) = (0, 0, 0);
+        let (mut tools, mut tool_ms, mut approval_ms) = (0, 0, 0);
+        for (i, step) in self.steps.iter().enumerate() {
+            out.push_str(&format!("\n{}. ", i + 1));
+            match step {
+                TraceStep::Model {
+                    model,
+                    duration_ms,
+                    prompt_tokens,
+                    completion_tokens,
+                    tool_calls,
+                    error,
+                } => {
+                    models += 1;
+                    model_ms += duration_ms;
+                    tokens += prompt_tokens + completion_tokens;
+                    out.push_str(&format!(
+                        "{model} {} · {}→{} tokens",
+                        secs(*duration_ms),
+                        count(*prompt_tokens),
+                        count(*completion_tokens)
+                    ));
+                    match (error, tool_calls) {
+                        (Some(e), _) => out.push_str(&format!(" · failed: {e}")),
+                        (None, 0) => {}
+                        (None, 1) => out.push_str(" · 1 tool call"),
+                        (None, n) => out.push_str(&format!(" · {n} tool calls")),
+                    }
+                }
+                TraceStep::Tool {
+                    tool,
+                    approval,
+                    approval_ms: waited,
+                    duration_ms,
+                    outcome,
+                } => {
+                    tools += 1;
+                    tool_ms += duration_ms;
+                    approval_ms += waited;
+                    out.push_str(tool);
+                    match approval {
+                        ApprovalOutcome::NotNeeded => {}
+                        ApprovalOutcome::Approved => {
+                            out.push_str(&format!(" · approved after {}", secs(*waited)))
+                        }
+                        ApprovalOutcome::Denied => {
+                            out.push_str(&format!(" · denied after {}", secs(*waited)))
+                        }
+                    }
+                    if *outcome != ToolOutcome::Denied {
+                        out.push_str(&format!(" · ran {}", secs(*duration_ms)));
+                    }
+                    match outcome {
+                        ToolOutcome::Refused => out.push_str(" · refused by policy"),
+                        ToolOutcome::Failed => out.push_str(" · failed"),
+                        ToolOutcome::Ok | ToolOutcome::Denied => {}
+                    }
+                }
+            }
+        }
+        out.push_str(&format!(
+            "\nModel: {models} call{} in {}, {} tokens. Tools: {tools} in {}",
+            if models == 1 { "" } else { "s" },
+            secs(model_ms),
+            count(tokens),
+            secs(tool_ms)
+        ));
+        if approval_ms > 0 {
+            out.push_str(&format!(
+                ", plus {} waiting for approval",
+                secs(approval_ms)
+            ));
+        }
+        out.push('.');
+        out
+    }
+}
+
+impl Default for RunTrace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn elapsed_ms(since: Instant) -> u64 {
+    since.elapsed().as_millis() as u64
+}
+
+fn secs(ms: u64) -> String {
+    format!("{:.1}s", ms as f64 / 1000.0)
+}
+
+fn count(n: u64) -> String {
+    if n >= 1000 {
+        format!("{:.1}k", n as f64 / 1000.0)
+    } else {
+        n.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_steps_and_totals() {
+        let mut trace = RunTrace::new();
+        trace.steps = vec![
+            TraceStep::Model {
+                model: "gpt-4o".to_string(),
+                duration_ms: 3_100,
+                prompt_tokens: 1_204,
+                completion_tokens: 80,
+                tool_calls: 2,
+                error: None,
+            },
+            TraceStep::Tool {
+                tool: "shell.execute".to_string(),
+                approval: ApprovalOutcome::Approved,
+                approval_ms: 30_200,
+                duration_ms: 45_000,
+                outcome: ToolOutcome::Ok,
+            },
+            TraceStep::Tool {
+                tool: "browser".to_string(),
+                approval: ApprovalOutcome::Denied,
+                approval_ms: 5_000,
+                duration_ms: 0,
+                outcome: ToolOutcome::Denied,
+            },
+            TraceStep::Model {
+                model: "gpt-4o".to_string(),
+                duration_ms: 1_800,
+                prompt_tokens: 1_530,
+                completion_tokens: 212,
+                tool_calls: 0,
+                error: None,
+            },
+        ];
+        trace.finished_at = Some(trace.started_at + chrono::Duration::milliseconds(90_400));
+        trace.outcome = Some("completed".to_string());
+
+        assert_eq!(
+            trace.render(),
+            "Last run: 90.4s, completed\n\
+             1. gpt-4o 3.1s · 1.2k→80 tokens · 2 tool calls\n\
+             2. shell.execute · approved after 30.2s · ran 45.0s\n\
+             3. browser · denied after 5.0s\n\
+             4. gpt-4o 1.8s · 1.5k→212 tokens\n\
+             Model: 2 calls in 4.9s, 3.0k tokens. Tools: 2 in 45.0s, plus 35.2s waiting for approval."
+        );
+    }
+}
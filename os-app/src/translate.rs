@@ -0,0 +1,200 @@
+//! Translation through the configured LLM.
+//!
+//! Backs the `translate` tool and per-channel auto-translate: inbound text in another
+//! language is translated to `translation.preferred_language` before the assistant sees
+//! it, and the reply is translated back to the sender's language. With `[pii]` on, the
+//! text is masked before it goes to the model and unmasked in the translation.
+
+use crate::config::PiiConfig;
+use crate::pii::{PiiMasker, PiiVault};
+use anyhow::Result;
+use async_trait::async_trait;
+use horizons_core::core_agents::models::RiskLevel;
+use os_llm::{ChatMessage, LlmClient, Role};
+use os_tools::{until_cancelled, CancellationToken, Tool, ToolError, ToolSpec};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Translation {
+    /// Language of the input, as an English name ("German").
+    pub source_language: String,
+    pub text: String,
+}
+
+impl Translation {
+    pub fn was_in(&self, language: &str) -> bool {
+        self.source_language
+            .trim()
+            .eq_ignore_ascii_case(language.trim())
+    }
+}
+
+pub struct Translator {
+    llm: LlmClient,
+    pii: Option<PiiMasker>,
+}
+
+impl Translator {
+    pub fn new(llm: LlmClient) -> Self {
+        Self { llm, pii: None }
+    }
+
+    /// Mask personal data as `cfg` says before text goes to the model.
+    pub fn with_pii(mut self, cfg: &PiiConfig) -> Result<Self> {
+        self.pii = cfg.enabled.then(|| PiiMasker::new(cfg)).transpose()?;
+        Ok(self)
+    }
+
+    #[tracing::instrument(level = "info", skip_all, fields(target = %target_language))]
+    pub async fn translate(&self, text: &str, target_language: &str) -> Result<Translation> {
+        let system = format!(
+            "Detect the language of the user's text and translate it into {target_language}. \
+             Keep names, code, URLs, placeholders like [EMAIL_1] and formatting as they are. If the text is already in \
+             {target_language}, return it unchanged. Reply with JSON only: \
+             {{\"source_language\": \"<English name of the language>\", \"text\": \"<translation>\"}}"
+        );
+        let mut vault = PiiVault::default();
+        let text = match self.pii.as_ref() {
+            Some(masker) => masker.mask(text, &mut vault),
+            None => text.to_string(),
+        };
+        let messages = [
+            ChatMessage {
+                role: Role::System,
+                content: system,
+                tool_calls: vec![],
+                tool_call_id: None,
+                reasoning: vec![],
+            },
+            ChatMessage {
+                role: Role::User,
+                content: text,
+                tool_calls: vec![],
+                tool_call_id: None,
+                reasoning: vec![],
+            },
+        ];
+        let resp = self.llm.chat(&messages, &[]).await?;
+        let mut translation = parse_translation(&resp.message.content)?;
+        translation.text = vault.unmask(&translation.text);
+        Ok(translation)
+    }
+}
+
+/// Models sometimes wrap JSON in a code fence despite being asked not to.
+fn parse_translation(raw: &str) -> Result<Translation> {
+    let trimmed = raw.trim();
+    let body = trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|s| s.strip_suffix("```"))
+        .unwrap_or(trimmed);
+    serde_json::from_str(body.trim())
+        .map_err(|e| anyhow::anyhow!("unexpected translation response: {e}"))
+}
+
+pub struct TranslateTool {
+    translator: Arc<Translator>,
+}
+
+impl TranslateTool {
+    pub fn new(translator: Arc<Translator>) -> Self {
+        Self { translator }
+    }
+}
+
+#[async_trait]
+impl Tool for TranslateTool {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "translate".to_string(),
+            description:
+                "Translate text into another language. Also reports the detected source language."
+                    .to_string(),
+            parameters_schema: json!({
+                "type": "object",
+                "properties": {
+                    "text": { "type": "string" },
+                    "target_language": { "type": "string", "description": "Language name, e.g. \"Spanish\"." }
+                },
+                "required": ["text", "target_language"]
+            }),
+            risk_level: RiskLevel::Low,
+        }
+    }
+
+    async fn execute(
+        &self,
+        arguments: serde_json::Value,
+        cancel: &CancellationToken,
+    ) -> os_tools::Result<serde_json::Value> {
+        let text = arguments
+            .get("text")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| ToolError::InvalidArguments("missing key: text".to_string()))?;
+        let target = arguments
+            .get("target_language")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| {
+                ToolError::InvalidArguments("missing key: target_language".to_string())
+            })?;
+        let t = until_cancelled(cancel, async {
+            self.translator
+                .translate(text, target)
+                .await
+                .map_err(|e| ToolError::ExecutionFailed(e.to_string()))
+        })
+        .await?;
+        Ok(json!({ "source_language": t.source_language, "translation": t.text }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use os_llm::{Exchange, Recorder, Replay};
+
+    #[test]
+    fn parses_fenced_and_bare_json() {
+        let bare = r#"{"source_language": "German", "text": "Good morning"}"#;
+        let t = parse_translation(bare).unwrap();
+        assert_eq!(t.text, "Good morning");
+        assert!(t.was_in("german"));
+
+        let fenced = format!("```json\n{bare}\n```");
+        assert_eq!(
+            parse_translation(&fenced).unwrap().source_language,
+            "German"
+        );
+        assert!(parse_translation("Guten Morgen").is_err());
+    }
+
+    #[tokio::test]
+    async fn personal_data_is_masked_for_the_model() {
+        let path =
+            std::env::temp_dir().join(format!("opencraw-translate-{}.jsonl", uuid::Uuid::new_v4()));
+        let replay = Arc::new(Replay::new(vec![Exchange::reply(
+            r#"{"source_language": "German", "text": "Write to [EMAIL_1]"}"#,
+        )]));
+        let llm = LlmClient::replay("gpt-4o-mini", replay)
+            .with_recorder(Arc::new(Recorder::new(path.clone())));
+        let translator = Translator::new(llm)
+            .with_pii(&PiiConfig {
+                enabled: true,
+                ..Default::default()
+            })
+            .unwrap();
+
+        let t = translator
+            .translate("Schreib an ana@example.com", "English")
+            .await
+            .unwrap();
+        let recorded = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert!(recorded.contains("Schreib an [EMAIL_1]"), "{recorded}");
+        assert!(!recorded.contains("ana@example.com"), "{recorded}");
+        assert_eq!(t.text, "Write to ana@example.com");
+    }
+}
@@ -0,0 +1,284 @@
+//! Watchdog for stuck conversation runs.
+//!
+//! Every assistant run the gateway starts is registered here. A background sweep
+//! force-cancels runs that are still in flight past `watchdog.run_timeout_seconds` (for
+//! example a tool blocked on I/O that never returns), tells the user, and records an
+//! incident, so one stuck conversation cannot hold up everyone else's. `/cancel` (or
+//! `/stop`) cancels a conversation's run through the same registry.
+
+use crate::config::WatchdogConfig;
+use crate::outbox::Outbox;
+use crate::tasks::{self, ConversationOrigin};
+use crate::webhooks::{self, Webhooks};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use os_channels::OutboundMessage;
+use os_tools::CancellationToken;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+/// Incidents kept in memory for the API; all of them are appended to `incidents.jsonl`.
+const RECENT_INCIDENTS_MAX: usize = 100;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Incident {
+    pub id: Uuid,
+    pub origin: ConversationOrigin,
+    pub started_at: DateTime<Utc>,
+    pub cancelled_at: DateTime<Utc>,
+    pub elapsed_seconds: u64,
+}
+
+struct InFlight {
+    origin: ConversationOrigin,
+    started_at: DateTime<Utc>,
+    started: Instant,
+    cancel: CancellationToken,
+}
+
+pub struct Watchdog {
+    cfg: WatchdogConfig,
+    incidents_path: PathBuf,
+    outbox: Arc<Outbox>,
+    webhooks: Option<Arc<Webhooks>>,
+    inflight: DashMap<Uuid, InFlight>,
+    recent: Mutex<VecDeque<Incident>>,
+}
+
+impl Watchdog {
+    pub fn new(cfg: WatchdogConfig, data_dir: PathBuf, outbox: Arc<Outbox>) -> Self {
+        Self {
+            cfg,
+            incidents_path: data_dir.join("incidents.jsonl"),
+            outbox,
+            webhooks: None,
+            inflight: DashMap::new(),
+            recent: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn with_webhooks(mut self, webhooks: Arc<Webhooks>) -> Self {
+        self.webhooks = Some(webhooks);
+        self
+    }
+
+    pub fn start(self: Arc<Self>) {
+        if !self.cfg.enabled {
+            return;
+        }
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(Duration::from_secs(self.cfg.check_interval_seconds));
+            loop {
+                interval.tick().await;
+                for incident in self.sweep() {
+                    self.record(&incident).await;
+                    self.notify(&incident).await;
+                }
+            }
+        });
+    }
+
+    pub fn recent_incidents(&self) -> Vec<Incident> {
+        self.recent
+            .lock()
+            .map(|r| r.iter().rev().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Run `run` under supervision. Returns `None` if it was cancelled, by the sweep or by
+    /// `stop`. Runs are registered even with the sweep disabled, so `/stop` still works.
+    pub async fn supervise<F: Future>(
+        &self,
+        origin: ConversationOrigin,
+        run: F,
+    ) -> Option<F::Output> {
+        let id = Uuid::new_v4();
+        let cancel = CancellationToken::new();
+        self.inflight.insert(
+            id,
+            InFlight {
+                origin,
+                started_at: Utc::now(),
+                started: Instant::now(),
+                cancel: cancel.clone(),
+            },
+        );
+        let _registration = Registration {
+            inflight: &self.inflight,
+            id,
+        };
+
+        tasks::run_cancellable(cancel, None, run).await
+    }
+
+    /// Cancel the conversation's in-flight run, if it has one.
+    pub fn stop(&self, channel_id: &str, sender_id: &str) -> bool {
+        let mut stopped = false;
+        for entry in self.inflight.iter() {
+            if entry.origin.channel_id == channel_id
+                && entry.origin.sender_id == sender_id
+                && !entry.cancel.is_cancelled()
+            {
+                entry.cancel.cancel();
+                stopped = true;
+            }
+        }
+        stopped
+    }
+
+    /// Signal every run past the cap and return an incident for each.
+    fn sweep(&self) -> Vec<Incident> {
+        let cap = Duration::from_secs(self.cfg.run_timeout_seconds);
+        let mut incidents = Vec::new();
+        for entry in self.inflight.iter() {
+            if entry.started.elapsed() < cap || entry.cancel.is_cancelled() {
+                continue;
+            }
+            entry.cancel.cancel();
+            tracing::error!(
+                channel_id = %entry.origin.channel_id,
+                sender_id = %entry.origin.sender_id,
+                elapsed_seconds = entry.started.elapsed().as_secs(),
+                "watchdog cancelled stuck run"
+            );
+            incidents.push(Incident {
+                id: Uuid::new_v4(),
+                origin: entry.origin.clone(),
+                started_at: entry.started_at,
+                cancelled_at: Utc::now(),
+                elapsed_seconds: entry.started.elapsed().as_secs(),
+            });
+        }
+        incidents
+    }
+
+    async fn record(&self, incident: &Incident) {
+        if let Ok(mut recent) = self.recent.lock() {
+            recent.push_back(incident.clone());
+            while recent.len() > RECENT_INCIDENTS_MAX {
+                recent.pop_front();
+            }
+        }
+
+        let line = match serde_json::to_string(incident) {
+            Ok(v) => v + "\n",
+            Err(e) => {
+                tracing::warn!(%e, "failed to serialize incident");
+                return;
+            }
+        };
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.incidents_path)
+            .await;
+        let res = match file {
+            Ok(mut f) => match f.write_all(line.as_bytes()).await {
+                Ok(()) => f.flush().await,
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        if let Err(e) = res {
+            tracing::warn!(%e, path = %self.incidents_path.display(), "failed to record incident");
+        }
+    }
+
+    async fn notify(&self, incident: &Incident) {
+        let msg = OutboundMessage {
+            content: format!(
+                "Sorry, that request was still running after {}s and has been cancelled. Please try again.",
+                incident.elapsed_seconds
+            ),
+            reply_to_message_id: None,
+            attachments: vec![],
+        };
+        self.outbox
+            .enqueue(&incident.origin.channel_id, &incident.origin.recipient, msg);
+        if let Some(hooks) = self.webhooks.as_ref() {
+            hooks.emit(
+                webhooks::RUN_TIMED_OUT,
+                serde_json::to_value(incident).unwrap_or_default(),
+            );
+        }
+    }
+}
+
+/// Drops the in-flight entry however the supervised run ends.
+struct Registration<'a> {
+    inflight: &'a DashMap<Uuid, InFlight>,
+    id: Uuid,
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        self.inflight.remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn stuck_run_is_cancelled_and_recorded() {
+        let tmp = std::env::temp_dir().join(format!("opencraw-watchdog-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&tmp).unwrap();
+        let cfg = WatchdogConfig {
+            enabled: true,
+            run_timeout_seconds: 0,
+            check_interval_seconds: 1,
+        };
+        let watchdog = Arc::new(Watchdog::new(
+            cfg,
+            tmp.clone(),
+            Arc::new(Outbox::new(Default::default())),
+        ));
+        let origin = ConversationOrigin {
+            channel_id: "webchat".to_string(),
+            sender_id: "u1".to_string(),
+            recipient: "u1".to_string(),
+        };
+
+        // Cooperative, like the assistant loop: it returns once its token is cancelled.
+        let spawn_run = |origin| {
+            let w = watchdog.clone();
+            tokio::spawn(async move {
+                let run = async { tasks::RUN_CANCEL.with(|c| c.clone()).cancelled().await };
+                w.supervise(origin, run).await
+            })
+        };
+        let wait_registered = || async {
+            while watchdog.inflight.is_empty() {
+                tokio::task::yield_now().await;
+            }
+        };
+
+        let run = spawn_run(origin.clone());
+        wait_registered().await;
+        let incidents = watchdog.sweep();
+        assert_eq!(incidents.len(), 1);
+        assert_eq!(run.await.unwrap(), None);
+        assert!(watchdog.inflight.is_empty());
+
+        let run = spawn_run(origin);
+        wait_registered().await;
+        assert!(!watchdog.stop("webchat", "u2"));
+        assert!(watchdog.stop("webchat", "u1"));
+        assert_eq!(run.await.unwrap(), None);
+        assert!(watchdog.sweep().is_empty());
+
+        watchdog.record(&incidents[0]).await;
+        assert_eq!(watchdog.recent_incidents().len(), 1);
+        let raw = std::fs::read_to_string(tmp.join("incidents.jsonl")).unwrap();
+        assert_eq!(raw.lines().count(), 1);
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+}
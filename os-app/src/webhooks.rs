@@ -0,0 +1,183 @@
+//! Outbound webhooks for lifecycle events.
+//!
+//! Each configured endpoint gets a JSON `POST` for the events it subscribes to, so ntfy,
+//! Pushover or home automation can react without polling the control API. Bodies look like
+//! `{"id", "event", "timestamp", "data"}`; with a secret set they are signed with
+//! HMAC-SHA256 in `X-OpenCraw-Signature: sha256=<hex>`. Delivery happens in the background
+//! with a few retries, so a slow endpoint never holds up the code that raised the event.
+//!
+//! `channel.down` means sends to a channel are failing; `channel.crashed` means its
+//! adapter stopped receiving and won't pick up new messages until the supervisor restarts it.
+
+use crate::config::{WebhookEndpointConfig, WebhooksConfig};
+use hmac::{Hmac, Mac};
+use os_channels::ChannelAdapter;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+pub const RUN_COMPLETED: &str = "run.completed";
+pub const APPROVAL_PENDING: &str = "approval.pending";
+pub const TASK_COMPLETED: &str = "task.completed";
+pub const TASK_FAILED: &str = "task.failed";
+pub const RUN_TIMED_OUT: &str = "run.timed_out";
+pub const CHANNEL_DOWN: &str = "channel.down";
+pub const CHANNEL_UP: &str = "channel.up";
+pub const INTEGRITY_PROBLEM: &str = "integrity.problem";
+pub const TOOL_DENIED: &str = "tool.denied";
+pub const BUDGET_EXCEEDED: &str = "budget.exceeded";
+pub const CHANNEL_CRASHED: &str = "channel.crashed";
+pub const AUTOMATION_FAILED: &str = "automation.failed";
+
+pub const EVENTS: &[&str] = &[
+    RUN_COMPLETED,
+    APPROVAL_PENDING,
+    TASK_COMPLETED,
+    TASK_FAILED,
+    RUN_TIMED_OUT,
+    CHANNEL_DOWN,
+    CHANNEL_UP,
+    INTEGRITY_PROBLEM,
+    TOOL_DENIED,
+    BUDGET_EXCEEDED,
+    CHANNEL_CRASHED,
+    AUTOMATION_FAILED,
+];
+
+const ATTEMPTS: u32 = 3;
+const CRASH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+pub struct Webhooks {
+    endpoints: Vec<WebhookEndpointConfig>,
+    client: reqwest::Client,
+}
+
+impl Webhooks {
+    pub fn new(cfg: &WebhooksConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(cfg.timeout_seconds))
+            .build()
+            .unwrap_or_default();
+        Self {
+            endpoints: cfg.endpoints.clone(),
+            client,
+        }
+    }
+
+    /// Deliver `event` to every subscribed endpoint. Returns immediately.
+    pub fn emit(&self, event: &'static str, data: Value) {
+        let targets: Vec<WebhookEndpointConfig> = self
+            .endpoints
+            .iter()
+            .filter(|e| e.events.is_empty() || e.events.iter().any(|name| name == event))
+            .cloned()
+            .collect();
+        if targets.is_empty() {
+            return;
+        }
+
+        let id = Uuid::new_v4();
+        let body = json!({
+            "id": id,
+            "event": event,
+            "timestamp": chrono::Utc::now(),
+            "data": data,
+        })
+        .to_string();
+        for endpoint in targets {
+            let client = self.client.clone();
+            let body = body.clone();
+            tokio::spawn(async move { deliver(&client, &endpoint, event, id, body).await });
+        }
+    }
+
+    /// Raise `channel.crashed` when an adapter's receive loop stops, once until it's restarted.
+    pub fn watch_channels(self: Arc<Self>, channels: HashMap<String, Arc<dyn ChannelAdapter>>) {
+        if self.endpoints.is_empty() {
+            return;
+        }
+        tokio::spawn(async move {
+            let mut crashed = HashSet::new();
+            let mut tick = tokio::time::interval(CRASH_CHECK_INTERVAL);
+            loop {
+                tick.tick().await;
+                self.check_channels(&channels, &mut crashed);
+            }
+        });
+    }
+
+    fn check_channels(
+        &self,
+        channels: &HashMap<String, Arc<dyn ChannelAdapter>>,
+        crashed: &mut HashSet<String>,
+    ) {
+        for (channel_id, adapter) in channels {
+            let Some(error) = adapter.receive_failure() else {
+                crashed.remove(channel_id);
+                continue;
+            };
+            if crashed.insert(channel_id.clone()) {
+                self.emit(
+                    CHANNEL_CRASHED,
+                    json!({ "channel_id": channel_id, "error": error }),
+                );
+            }
+        }
+    }
+}
+
+#[tracing::instrument(level = "debug", skip_all, fields(%event, url = %endpoint.url))]
+async fn deliver(
+    client: &reqwest::Client,
+    endpoint: &WebhookEndpointConfig,
+    event: &str,
+    id: Uuid,
+    body: String,
+) {
+    let signature = endpoint.secret.as_deref().map(|s| sign(s, &body));
+    for attempt in 1..=ATTEMPTS {
+        let mut req = client
+            .post(&endpoint.url)
+            .header("content-type", "application/json")
+            .header("x-opencraw-event", event)
+            .header("x-opencraw-delivery", id.to_string())
+            .body(body.clone());
+        if let Some(sig) = signature.as_deref() {
+            req = req.header("x-opencraw-signature", sig);
+        }
+        let err = match req.send().await {
+            Ok(resp) if resp.status().is_success() => return,
+            Ok(resp) => format!("status {}", resp.status()),
+            Err(e) => e.to_string(),
+        };
+        if attempt == ATTEMPTS {
+            tracing::warn!(%err, "webhook delivery failed");
+            return;
+        }
+        tokio::time::sleep(Duration::from_secs(2u64.pow(attempt))).await;
+    }
+}
+
+fn sign(secret: &str, body: &str) -> String {
+    // HMAC takes keys of any length, so this can't fail.
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac key");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signature_matches_known_vector() {
+        // RFC 4231 test case 2.
+        assert_eq!(
+            sign("Jefe", "what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
@@ -0,0 +1,337 @@
+// OpenCraw webchat client. Talks to /ws: sends `message` frames (with `attachments` as
+// data: URLs) and renders `hello`, `history`, `message`, `typing`, `read` and `activity`.
+// The conversation id from `hello` is kept in localStorage so a reload or reconnect
+// continues the same conversation and gets its history back.
+"use strict";
+
+const SESSION_KEY = "opencraw.webchat.session";
+const MAX_FILE_BYTES = 8 * 1024 * 1024;
+
+const $ = (id) => document.getElementById(id);
+const log = $("log");
+const input = $("input");
+
+let socket = null;
+let retryMs = 1000;
+let pending = [];
+// Tool activity rows of the current run, by tool name, so "finished" can update them.
+const running = new Map();
+
+function escapeHtml(text) {
+  return text.replace(/[&<>"']/g, (c) => ({
+    "&": "&amp;", "<": "&lt;", ">": "&gt;", '"': "&quot;", "'": "&#39;",
+  })[c]);
+}
+
+// Inline Markdown on already-escaped text.
+function inline(text) {
+  return text
+    .split(/(`[^`\n]+`)/g)
+    .map((part, i) => {
+      if (i % 2) return `<code>${part.slice(1, -1)}</code>`;
+      return part
+        .replace(/\*\*([^*\n]+)\*\*/g, "<strong>$1</strong>")
+        .replace(/(^|[^*\w])\*([^*\n]+)\*(?!\w)/g, "$1<em>$2</em>")
+        .replace(/~~([^~\n]+)~~/g, "<del>$1</del>")
+        .replace(/\[([^\]\n]+)\]\((https?:\/\/[^\s)]+)\)/g,
+          '<a href="$2" target="_blank" rel="noopener noreferrer">$1</a>')
+        .replace(/(^|[\s(])(https?:\/\/[^\s<)]*[^\s<).,;:!?])/g,
+          '$1<a href="$2" target="_blank" rel="noopener noreferrer">$2</a>');
+    })
+    .join("");
+}
+
+// A small Markdown renderer: fenced code, headings, lists, quotes and paragraphs. All
+// text is escaped before any markup is added.
+function markdown(source) {
+  const lines = source.replace(/\r\n/g, "\n").split("\n");
+  const out = [];
+  let paragraph = [];
+  let list = null;
+
+  const flush = () => {
+    if (paragraph.length) out.push(`<p>${paragraph.map(inline).join("<br>")}</p>`);
+    paragraph = [];
+    if (list) out.push(`<${list.tag}>${list.items.map((i) => `<li>${inline(i)}</li>`).join("")}</${list.tag}>`);
+    list = null;
+  };
+
+  for (let i = 0; i < lines.length; i++) {
+    const line = lines[i];
+    const fence = line.match(/^\s*```\s*([\w+-]*)/);
+    if (fence) {
+      flush();
+      const code = [];
+      for (i++; i < lines.length && !/^\s*```/.test(lines[i]); i++) code.push(lines[i]);
+      const lang = fence[1] ? `<span class="lang">${escapeHtml(fence[1])}</span>` : "";
+      out.push(`<div class="codeblock"><button type="button" class="copy">Copy</button>` +
+        `<pre><code>${escapeHtml(code.join("\n"))}</code></pre>${lang}</div>`);
+      continue;
+    }
+    const escaped = escapeHtml(line);
+    const heading = escaped.match(/^(#{1,3})\s+(.*)$/);
+    const bullet = escaped.match(/^\s*[-*+]\s+(.*)$/);
+    const numbered = escaped.match(/^\s*\d+[.)]\s+(.*)$/);
+    const quote = escaped.match(/^&gt;\s?(.*)$/);
+    if (heading) {
+      flush();
+      out.push(`<h${heading[1].length}>${inline(heading[2])}</h${heading[1].length}>`);
+    } else if (bullet || numbered) {
+      const tag = bullet ? "ul" : "ol";
+      if (paragraph.length || (list && list.tag !== tag)) flush();
+      list = list || { tag, items: [] };
+      list.items.push((bullet || numbered)[1]);
+    } else if (quote) {
+      flush();
+      out.push(`<blockquote>${inline(quote[1])}</blockquote>`);
+    } else if (!line.trim()) {
+      flush();
+    } else {
+      if (list) flush();
+      paragraph.push(escaped);
+    }
+  }
+  flush();
+  return out.join("");
+}
+
+function scrollDown() {
+  log.scrollTop = log.scrollHeight;
+}
+
+function renderAttachments(attachments) {
+  if (!attachments || !attachments.length) return null;
+  const box = document.createElement("div");
+  box.className = "attachments";
+  for (const a of attachments) {
+    const safeUrl = /^(https?:|data:)/.test(a.url) ? a.url : null;
+    if (safeUrl && a.content_type.startsWith("image/")) {
+      const img = document.createElement("img");
+      img.src = safeUrl;
+      img.alt = a.name;
+      box.append(img);
+    } else {
+      const link = document.createElement("a");
+      link.className = "file";
+      link.textContent = `📄 ${a.name}`;
+      if (safeUrl) {
+        link.href = safeUrl;
+        link.download = a.name;
+      }
+      box.append(link);
+    }
+  }
+  return box;
+}
+
+function addMessage(role, content, attachments, id) {
+  const node = document.createElement("div");
+  node.className = `msg ${role}`;
+  if (id) node.dataset.id = id;
+  if (role === "assistant") {
+    node.innerHTML = markdown(content);
+  } else {
+    node.textContent = content;
+  }
+  const files = renderAttachments(attachments);
+  if (files) node.append(files);
+  log.append(node);
+  scrollDown();
+  return node;
+}
+
+function addNotice(text) {
+  const node = document.createElement("div");
+  node.className = "notice";
+  node.textContent = text;
+  log.append(node);
+  scrollDown();
+}
+
+function showActivity(event) {
+  if (event.state === "thinking") {
+    $("typing").hidden = false;
+    return;
+  }
+  if (event.state === "tool_started") {
+    const row = document.createElement("div");
+    row.className = "activity running";
+    row.textContent = `🔧 ${event.tool}`;
+    log.append(row);
+    running.set(event.tool, row);
+    scrollDown();
+  } else if (event.state === "tool_finished") {
+    const row = running.get(event.tool);
+    if (row) {
+      row.classList.remove("running");
+      row.textContent = `✓ ${event.tool}`;
+      running.delete(event.tool);
+    }
+  }
+}
+
+function onFrame(frame) {
+  switch (frame.type) {
+    case "hello":
+      if (!frame.resumed) log.replaceChildren();
+      localStorage.setItem(SESSION_KEY, frame.sender_id);
+      break;
+    case "history":
+      log.replaceChildren();
+      for (const m of frame.messages) addMessage(m.role, m.content);
+      if (frame.messages.length) addNotice("Earlier messages above");
+      break;
+    case "message":
+      $("typing").hidden = true;
+      for (const row of running.values()) row.classList.remove("running");
+      running.clear();
+      addMessage("assistant", frame.content, frame.attachments);
+      break;
+    case "typing":
+      $("typing").hidden = false;
+      break;
+    case "read": {
+      const node = log.querySelector(`.msg.user[data-id="${CSS.escape(frame.message_id)}"]`);
+      if (node && !node.querySelector(".receipt")) {
+        const tick = document.createElement("span");
+        tick.className = "receipt";
+        tick.textContent = "✓";
+        node.append(tick);
+      }
+      break;
+    }
+    case "activity":
+      showActivity(frame);
+      break;
+  }
+}
+
+function setStatus(text, online) {
+  $("status").textContent = text;
+  $("status").className = online ? "status online" : "status";
+}
+
+function connect() {
+  const session = localStorage.getItem(SESSION_KEY);
+  const scheme = location.protocol === "https:" ? "wss" : "ws";
+  const query = session ? `?session=${encodeURIComponent(session)}` : "";
+  socket = new WebSocket(`${scheme}://${location.host}/ws${query}`);
+  socket.onopen = () => {
+    retryMs = 1000;
+    setStatus("connected", true);
+  };
+  socket.onmessage = (event) => {
+    try {
+      onFrame(JSON.parse(event.data));
+    } catch (e) {
+      console.warn("bad frame", e);
+    }
+  };
+  socket.onclose = () => {
+    setStatus("reconnecting", false);
+    setTimeout(connect, retryMs);
+    retryMs = Math.min(retryMs * 2, 30000);
+  };
+}
+
+function readFile(file) {
+  return new Promise((resolve, reject) => {
+    const reader = new FileReader();
+    reader.onload = () => resolve({
+      name: file.name,
+      content_type: file.type || "application/octet-stream",
+      url: reader.result,
+    });
+    reader.onerror = () => reject(reader.error);
+    reader.readAsDataURL(file);
+  });
+}
+
+function renderPending() {
+  $("pending").replaceChildren(...pending.map((a, i) => {
+    const chip = document.createElement("span");
+    chip.className = "chip";
+    chip.textContent = a.name;
+    const remove = document.createElement("button");
+    remove.type = "button";
+    remove.textContent = "×";
+    remove.title = "Remove";
+    remove.onclick = () => {
+      pending.splice(i, 1);
+      renderPending();
+    };
+    chip.append(remove);
+    return chip;
+  }));
+}
+
+async function addFiles(files) {
+  for (const file of files) {
+    if (file.size > MAX_FILE_BYTES) {
+      addNotice(`${file.name} is larger than ${MAX_FILE_BYTES / 1024 / 1024} MB`);
+      continue;
+    }
+    pending.push(await readFile(file));
+  }
+  renderPending();
+}
+
+function send() {
+  const content = input.value.trim();
+  if ((!content && !pending.length) || !socket || socket.readyState !== WebSocket.OPEN) return;
+  const id = crypto.randomUUID();
+  socket.send(JSON.stringify({ type: "message", id, content, attachments: pending }));
+  addMessage("user", content, pending, id);
+  input.value = "";
+  input.style.height = "";
+  pending = [];
+  renderPending();
+}
+
+$("composer").addEventListener("submit", (event) => {
+  event.preventDefault();
+  send();
+});
+input.addEventListener("keydown", (event) => {
+  if (event.key === "Enter" && !event.shiftKey && !event.isComposing) {
+    event.preventDefault();
+    send();
+  }
+});
+input.addEventListener("input", () => {
+  input.style.height = "";
+  input.style.height = `${input.scrollHeight}px`;
+});
+input.addEventListener("paste", (event) => {
+  const files = [...event.clipboardData.files];
+  if (files.length) addFiles(files);
+});
+$("files").addEventListener("change", (event) => {
+  addFiles([...event.target.files]);
+  event.target.value = "";
+});
+document.addEventListener("dragover", (event) => {
+  event.preventDefault();
+  document.body.classList.add("dragging");
+});
+document.addEventListener("dragleave", () => document.body.classList.remove("dragging"));
+document.addEventListener("drop", (event) => {
+  event.preventDefault();
+  document.body.classList.remove("dragging");
+  addFiles([...event.dataTransfer.files]);
+});
+log.addEventListener("click", (event) => {
+  if (!event.target.classList.contains("copy")) return;
+  const code = event.target.parentElement.querySelector("code").textContent;
+  navigator.clipboard.writeText(code).then(() => {
+    event.target.textContent = "Copied";
+    setTimeout(() => (event.target.textContent = "Copy"), 1500);
+  });
+});
+$("new-chat").addEventListener("click", () => {
+  localStorage.removeItem(SESSION_KEY);
+  log.replaceChildren();
+  if (socket) socket.close();
+});
+
+connect();
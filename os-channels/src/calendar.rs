@@ -0,0 +1,317 @@
+use crate::traits::ChannelAdapter;
+use crate::types::{InboundMessage, InboundMessageKind, OutboundMessage};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::sync::mpsc;
+
+const QUERY_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Reads the events starting within `argv[0]` seconds through EventKit, so recurring
+/// events are expanded and every account synced to Calendar.app is included.
+const EVENTS_SCRIPT: &str = r#"
+ObjC.import("EventKit");
+function run(argv) {
+  const status = $.EKEventStore.authorizationStatusForEntityType($.EKEntityTypeEvent);
+  const store = $.EKEventStore.alloc.init;
+  if (status === 0) {
+    store.requestAccessToEntityTypeCompletion($.EKEntityTypeEvent, () => {});
+    throw new Error("calendar access requested; approve the prompt");
+  }
+  if (status !== 3) {
+    throw new Error("calendar access denied; allow it in System Settings > Privacy & Security > Calendars");
+  }
+  const names = argv[1] ? argv[1].split("\n") : [];
+  const predicate = store.predicateForEventsWithStartDateEndDateCalendars(
+    $.NSDate.date, $.NSDate.dateWithTimeIntervalSinceNow(parseInt(argv[0])), $());
+  const str = (v) => { const s = ObjC.unwrap(v); return s ? String(s) : null; };
+  const iso = (d) => new Date(d.timeIntervalSince1970 * 1000).toISOString();
+  const out = [];
+  for (const e of ObjC.unwrap(store.eventsMatchingPredicate(predicate)) || []) {
+    const calendar = str(e.calendar.title);
+    if (names.length && !names.includes(calendar)) continue;
+    out.push({
+      uid: str(e.eventIdentifier),
+      title: str(e.title),
+      calendar,
+      start: iso(e.startDate),
+      end: iso(e.endDate),
+      all_day: e.allDay,
+      location: str(e.location),
+      notes: str(e.notes),
+      url: str(e.URL.absoluteString),
+      attendees: (ObjC.unwrap(e.attendees) || []).map((a) => ({
+        name: str(a.name),
+        address: str(a.URL.absoluteString),
+        status: a.participantStatus,
+      })),
+    });
+  }
+  return JSON.stringify(out);
+}
+"#;
+
+/// Upcoming-event source backed by macOS Calendar (EventKit, via `osascript`).
+///
+/// This adapter only produces messages. Shortly before each event it emits one into the
+/// owner's conversation on another channel (`deliver_channel`/`deliver_recipient`), with the
+/// event's time, location and attendees and a prompt asking for a briefing, so the reply
+/// lands where the owner reads it.
+///
+/// Permissions required on macOS: Calendars access for the terminal running OpenShell.
+#[derive(Clone)]
+pub struct CalendarAdapter {
+    deliver_channel: String,
+    deliver_recipient: String,
+    lead_time: Duration,
+    poll_interval: Duration,
+    calendars: Vec<String>,
+    prompt: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CalendarEvent {
+    pub uid: String,
+    pub title: Option<String>,
+    pub calendar: Option<String>,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    #[serde(default)]
+    pub all_day: bool,
+    pub location: Option<String>,
+    pub notes: Option<String>,
+    pub url: Option<String>,
+    #[serde(default)]
+    pub attendees: Vec<Attendee>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attendee {
+    pub name: Option<String>,
+    /// Usually `mailto:`.
+    pub address: Option<String>,
+    /// EKParticipantStatus.
+    pub status: Option<i64>,
+}
+
+impl CalendarAdapter {
+    pub fn new(deliver_channel: &str, deliver_recipient: &str) -> Self {
+        Self {
+            deliver_channel: deliver_channel.to_string(),
+            deliver_recipient: deliver_recipient.to_string(),
+            lead_time: Duration::from_secs(15 * 60),
+            poll_interval: Duration::from_secs(60),
+            calendars: Vec::new(),
+            prompt: "Brief me for this meeting: who is attending, what it is likely about, \
+                     and anything I should prepare."
+                .to_string(),
+        }
+    }
+
+    pub fn with_lead_time(mut self, lead_time: Duration) -> Self {
+        self.lead_time = lead_time;
+        self
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Only watch these calendars (by name). Empty watches all.
+    pub fn with_calendars(mut self, calendars: Vec<String>) -> Self {
+        self.calendars = calendars;
+        self
+    }
+
+    pub fn with_prompt(mut self, prompt: &str) -> Self {
+        self.prompt = prompt.to_string();
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl ChannelAdapter for CalendarAdapter {
+    fn channel_id(&self) -> &str {
+        "calendar"
+    }
+
+    async fn start(&self, tx: mpsc::Sender<InboundMessage>) -> Result<()> {
+        let adapter = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = adapter.poll_loop(tx).await {
+                tracing::error!(%e, "calendar poll loop exited");
+            }
+        });
+        Ok(())
+    }
+
+    async fn send(&self, _recipient_id: &str, _message: OutboundMessage) -> Result<()> {
+        Err(anyhow!("calendar is a read-only channel"))
+    }
+}
+
+impl CalendarAdapter {
+    #[tracing::instrument(level = "info", skip_all)]
+    async fn poll_loop(&self, tx: mpsc::Sender<InboundMessage>) -> Result<()> {
+        // (event id, start): recurring events share an id across occurrences.
+        let mut announced: HashSet<(String, DateTime<Utc>)> = HashSet::new();
+        let mut interval = tokio::time::interval(self.poll_interval);
+        loop {
+            interval.tick().await;
+            let events = match self.upcoming().await {
+                Ok(events) => events,
+                Err(e) => {
+                    tracing::warn!(%e, "calendar poll failed");
+                    continue;
+                }
+            };
+            let now = Utc::now();
+            announced.retain(|(_, start)| *start > now - chrono::Duration::days(1));
+            for event in events {
+                // Already running (e.g. at startup), or all-day: nothing to brief ahead of.
+                if event.all_day || event.start <= now {
+                    continue;
+                }
+                if !announced.insert((event.uid.clone(), event.start)) {
+                    continue;
+                }
+                tx.send(self.briefing_message(&event, now))
+                    .await
+                    .map_err(|_| anyhow!("inbound channel closed"))?;
+            }
+        }
+    }
+
+    async fn upcoming(&self) -> Result<Vec<CalendarEvent>> {
+        self.events_within(self.lead_time).await
+    }
+
+    /// Events starting between now and `within` from now, in the watched calendars.
+    pub async fn events_within(&self, within: Duration) -> Result<Vec<CalendarEvent>> {
+        let mut cmd = Command::new("osascript");
+        cmd.args(["-l", "JavaScript", "-e", EVENTS_SCRIPT])
+            .arg(within.as_secs().to_string())
+            .arg(self.calendars.join("\n"))
+            .kill_on_drop(true);
+        let output = tokio::time::timeout(QUERY_TIMEOUT, cmd.output())
+            .await
+            .map_err(|_| anyhow!("osascript timed out"))?
+            .context("spawn osascript")?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow!("osascript failed: {}", stderr.trim()));
+        }
+        serde_json::from_slice(&output.stdout).context("parse calendar events")
+    }
+
+    fn briefing_message(&self, event: &CalendarEvent, now: DateTime<Utc>) -> InboundMessage {
+        InboundMessage {
+            kind: InboundMessageKind::Message,
+            message_id: format!("calendar-{}-{}", event.uid, event.start.timestamp()),
+            channel_id: self.deliver_channel.clone(),
+            sender_id: self.deliver_recipient.clone(),
+            thread_id: None,
+            is_group: false,
+            content: format!("{}\n\n{}", describe(event, now), self.prompt),
+            attachments: vec![],
+            metadata: serde_json::json!({ "source": "calendar", "event": event }),
+            received_at: now,
+        }
+    }
+}
+
+fn describe(event: &CalendarEvent, now: DateTime<Utc>) -> String {
+    let minutes = (event.start - now).num_minutes().max(0);
+    let mut out = format!(
+        "[calendar] {} starts in {minutes} min ({}–{})",
+        event.title.as_deref().unwrap_or("Untitled event"),
+        event.start.with_timezone(&Local).format("%H:%M"),
+        event.end.with_timezone(&Local).format("%H:%M"),
+    );
+    if let Some(calendar) = event.calendar.as_deref() {
+        out.push_str(&format!("\nCalendar: {calendar}"));
+    }
+    if let Some(location) = event.location.as_deref().filter(|s| !s.trim().is_empty()) {
+        out.push_str(&format!("\nLocation: {location}"));
+    }
+    if let Some(url) = event.url.as_deref() {
+        out.push_str(&format!("\nLink: {url}"));
+    }
+    if !event.attendees.is_empty() {
+        let attendees: Vec<String> = event.attendees.iter().map(describe_attendee).collect();
+        out.push_str(&format!("\nAttendees: {}", attendees.join(", ")));
+    }
+    if let Some(notes) = event.notes.as_deref().filter(|s| !s.trim().is_empty()) {
+        out.push_str(&format!("\nNotes: {}", notes.trim()));
+    }
+    out
+}
+
+fn describe_attendee(a: &Attendee) -> String {
+    let address = a
+        .address
+        .as_deref()
+        .map(|s| s.strip_prefix("mailto:").unwrap_or(s));
+    let mut out = match (a.name.as_deref(), address) {
+        (Some(name), Some(address)) if name != address => format!("{name} <{address}>"),
+        (Some(name), _) => name.to_string(),
+        (None, Some(address)) => address.to_string(),
+        (None, None) => "unknown".to_string(),
+    };
+    let status = match a.status {
+        Some(2) => Some("accepted"),
+        Some(3) => Some("declined"),
+        Some(4) => Some("tentative"),
+        Some(1) => Some("no response"),
+        _ => None,
+    };
+    if let Some(status) = status {
+        out.push_str(&format!(" ({status})"));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn briefing_includes_location_and_attendees() {
+        let now = Utc::now();
+        let event: CalendarEvent = serde_json::from_value(serde_json::json!({
+            "uid": "E1",
+            "title": "Design review",
+            "calendar": "Work",
+            "start": (now + chrono::Duration::minutes(15)).to_rfc3339(),
+            "end": (now + chrono::Duration::minutes(60)).to_rfc3339(),
+            "all_day": false,
+            "location": "Room 4",
+            "notes": null,
+            "url": null,
+            "attendees": [
+                { "name": "Alice", "address": "mailto:alice@example.com", "status": 2 },
+                { "name": null, "address": "mailto:bob@example.com", "status": 1 }
+            ]
+        }))
+        .unwrap();
+
+        let msg = CalendarAdapter::new("telegram", "42").briefing_message(&event, now);
+        assert_eq!(
+            (msg.channel_id.as_str(), msg.sender_id.as_str()),
+            ("telegram", "42")
+        );
+        assert!(msg
+            .content
+            .starts_with("[calendar] Design review starts in 15 min"));
+        assert!(msg.content.contains("Location: Room 4"));
+        assert!(msg.content.contains(
+            "Attendees: Alice <alice@example.com> (accepted), bob@example.com (no response)"
+        ));
+        assert!(msg.content.ends_with("anything I should prepare."));
+        assert_eq!(msg.metadata["source"], "calendar");
+    }
+}
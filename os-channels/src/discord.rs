@@ -1,5 +1,9 @@
-use crate::traits::ChannelAdapter;
-use crate::types::{InboundMessage, InboundMessageKind, OutboundMessage};
+use crate::format::Dialect;
+use crate::multipart::{self, FilePart};
+use crate::traits::{ChannelAdapter, HttpSendError, ReceiveFailure};
+use crate::types::{
+    Attachment, InboundMessage, InboundMessageKind, OutboundMessage, QuotedMessage,
+};
 use anyhow::Result;
 use chrono::Utc;
 use futures_util::{SinkExt, StreamExt};
@@ -14,6 +18,7 @@ const DISCORD_GATEWAY_URL: &str = "wss://gateway.discord.gg/?v=10&encoding=json"
 pub struct DiscordAdapter {
     http: reqwest::Client,
     bot_token: String,
+    failure: ReceiveFailure,
 }
 
 impl DiscordAdapter {
@@ -30,6 +35,7 @@ impl DiscordAdapter {
                     reqwest::Client::new()
                 }),
             bot_token: bot_token.to_string(),
+            failure: ReceiveFailure::default(),
         }
     }
 
@@ -47,35 +53,140 @@ impl ChannelAdapter for DiscordAdapter {
     async fn start(&self, tx: mpsc::Sender<InboundMessage>) -> Result<()> {
         let http = self.http.clone();
         let token = self.bot_token.clone();
+        self.failure.clear();
+        let failure = self.failure.clone();
         tokio::spawn(async move {
             let adapter = DiscordAdapter {
                 http,
                 bot_token: token,
+                failure,
             };
             if let Err(e) = adapter.run_gateway_loop(tx).await {
                 tracing::error!(%e, "discord gateway loop exited");
+                adapter.failure.set(e);
             }
         });
         Ok(())
     }
 
+    fn receive_failure(&self) -> Option<String> {
+        self.failure.get()
+    }
+
     async fn send(&self, recipient_id: &str, message: OutboundMessage) -> Result<()> {
         let url = self.api_url(&format!("/channels/{recipient_id}/messages"));
-        let body = serde_json::json!({ "content": message.content });
+
+        // Inline files are uploaded; attachments that are only URLs are linked.
+        let mut content = message.content;
+        let mut files = Vec::new();
+        for a in &message.attachments {
+            match a.inline_bytes() {
+                Some(bytes) => files.push((a, bytes)),
+                None => content.push_str(&format!("\n{}", a.url)),
+            }
+        }
+
+        let request = self
+            .http
+            .post(url)
+            .header("Authorization", format!("Bot {}", self.bot_token));
+        let resp = if files.is_empty() {
+            request
+                .json(&serde_json::json!({ "content": content }))
+                .send()
+                .await?
+        } else {
+            let payload = serde_json::json!({ "content": content }).to_string();
+            let parts: Vec<FilePart<'_>> = files
+                .iter()
+                .enumerate()
+                .map(|(i, (a, bytes))| FilePart {
+                    field: format!("files[{i}]"),
+                    filename: &a.name,
+                    content_type: &a.content_type,
+                    bytes,
+                })
+                .collect();
+            let (content_type, body) = multipart::encode(&[("payload_json", &payload)], &parts);
+            request
+                .header(reqwest::header::CONTENT_TYPE, content_type)
+                .body(body)
+                .send()
+                .await?
+        };
+        if !resp.status().is_success() {
+            return Err(HttpSendError::from_response("discord", resp).await.into());
+        }
+        Ok(())
+    }
+
+    async fn send_typing(&self, recipient_id: &str) -> Result<()> {
+        let url = self.api_url(&format!("/channels/{recipient_id}/typing"));
+        let resp = self
+            .http
+            .post(url)
+            .header("Authorization", format!("Bot {}", self.bot_token))
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            tracing::debug!(%status, "discord typing failed");
+        }
+        Ok(())
+    }
+
+    async fn send_status(&self, recipient_id: &str, text: &str) -> Result<String> {
+        let url = self.api_url(&format!("/channels/{recipient_id}/messages"));
         let resp = self
             .http
             .post(url)
             .header("Authorization", format!("Bot {}", self.bot_token))
-            .json(&body)
+            .json(&serde_json::json!({ "content": text }))
             .send()
             .await?;
         if !resp.status().is_success() {
             let status = resp.status();
             let text = resp.text().await.unwrap_or_default();
-            tracing::warn!(%status, %text, "discord send failed");
+            return Err(anyhow::anyhow!("discord send failed ({status}): {text}"));
+        }
+        let sent: serde_json::Value = resp.json().await?;
+        sent["id"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow::anyhow!("discord send returned no message id"))
+    }
+
+    async fn edit_status(&self, recipient_id: &str, message_id: &str, text: &str) -> Result<()> {
+        let url = self.api_url(&format!("/channels/{recipient_id}/messages/{message_id}"));
+        let resp = self
+            .http
+            .patch(url)
+            .header("Authorization", format!("Bot {}", self.bot_token))
+            .json(&serde_json::json!({ "content": text }))
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            tracing::debug!(%status, "discord message edit failed");
         }
         Ok(())
     }
+
+    fn supports_edits(&self) -> bool {
+        true
+    }
+
+    fn supports_attachments(&self) -> bool {
+        true
+    }
+
+    fn dialect(&self) -> Dialect {
+        Dialect::Markdown
+    }
+
+    fn max_message_chars(&self) -> usize {
+        2000
+    }
 }
 
 impl DiscordAdapter {
@@ -174,13 +285,25 @@ impl DiscordAdapter {
                         .map(|s| s.to_string());
                     *bot_user_id.write().await = id;
                 }
-                "MESSAGE_CREATE" => {
-                    let event: DiscordMessageCreate = serde_json::from_value(
-                        v.get("d").cloned().unwrap_or_else(|| serde_json::json!({})),
-                    )?;
+                "MESSAGE_CREATE" | "MESSAGE_UPDATE" => {
+                    let kind = if t == "MESSAGE_UPDATE" {
+                        InboundMessageKind::Edit
+                    } else {
+                        InboundMessageKind::Message
+                    };
+                    let d = v.get("d").cloned().unwrap_or_else(|| serde_json::json!({}));
+                    let event: DiscordMessageCreate = match serde_json::from_value(d) {
+                        Ok(event) => event,
+                        // Embed/pin updates arrive as partial messages without an author.
+                        Err(_) if kind == InboundMessageKind::Edit => continue,
+                        Err(e) => return Err(e.into()),
+                    };
                     if event.author.bot.unwrap_or(false) {
                         continue;
                     }
+                    if kind == InboundMessageKind::Edit && event.content.is_empty() {
+                        continue;
+                    }
 
                     let is_group = event.guild_id.is_some();
                     if is_group {
@@ -195,16 +318,32 @@ impl DiscordAdapter {
                         }
                     }
 
-                    let metadata =
+                    let mut metadata =
                         serde_json::to_value(&event).unwrap_or_else(|_| serde_json::json!({}));
+                    if let Some(quoted) = quoted_message(&event) {
+                        quoted.attach(&mut metadata);
+                    }
+                    let attachments = event
+                        .attachments
+                        .iter()
+                        .map(|a| Attachment {
+                            name: a.filename.clone(),
+                            content_type: a
+                                .content_type
+                                .clone()
+                                .unwrap_or_else(|| "application/octet-stream".to_string()),
+                            url: a.url.clone(),
+                        })
+                        .collect();
                     let inbound = InboundMessage {
-                        kind: InboundMessageKind::Message,
+                        kind,
                         message_id: event.id,
                         channel_id: "discord".to_string(),
                         sender_id: event.author.id,
                         thread_id: Some(event.channel_id),
                         is_group,
                         content: event.content,
+                        attachments,
                         metadata,
                         received_at: Utc::now(),
                     };
@@ -227,6 +366,63 @@ struct DiscordMessageCreate {
     #[serde(default)]
     content: String,
     author: DiscordAuthor,
+    #[serde(default)]
+    attachments: Vec<DiscordAttachment>,
+    #[serde(default)]
+    message_reference: Option<DiscordMessageReference>,
+    /// The replied-to message; null if Discord didn't load it or it was deleted.
+    #[serde(default)]
+    referenced_message: Option<DiscordReferencedMessage>,
+}
+
+#[derive(Debug, Deserialize, serde::Serialize)]
+struct DiscordMessageReference {
+    /// 0 for replies, 1 for forwards.
+    #[serde(default, rename = "type")]
+    kind: Option<i64>,
+    #[serde(default)]
+    message_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, serde::Serialize)]
+struct DiscordReferencedMessage {
+    id: String,
+    #[serde(default)]
+    content: String,
+    author: DiscordAuthor,
+}
+
+fn quoted_message(event: &DiscordMessageCreate) -> Option<QuotedMessage> {
+    if event
+        .message_reference
+        .as_ref()
+        .is_some_and(|r| r.kind == Some(1))
+    {
+        return None;
+    }
+    if let Some(referenced) = event.referenced_message.as_ref() {
+        return Some(QuotedMessage {
+            message_id: referenced.id.clone(),
+            sender_id: Some(referenced.author.id.clone()),
+            from_bot: referenced.author.bot.unwrap_or(false),
+            content: Some(referenced.content.clone()).filter(|c| !c.is_empty()),
+        });
+    }
+    let message_id = event.message_reference.as_ref()?.message_id.clone()?;
+    Some(QuotedMessage {
+        message_id,
+        sender_id: None,
+        from_bot: false,
+        content: None,
+    })
+}
+
+#[derive(Debug, Deserialize, serde::Serialize)]
+struct DiscordAttachment {
+    url: String,
+    filename: String,
+    #[serde(default)]
+    content_type: Option<String>,
 }
 
 #[derive(Debug, Deserialize, serde::Serialize)]
@@ -0,0 +1,502 @@
+//! Outbound formatting: assistant replies are Markdown; each platform speaks its own
+//! dialect and has its own length limit.
+//!
+//! `render` converts Markdown to a dialect. `split_message` cuts long Markdown into
+//! pieces that each fit a limit *after* rendering, preferring paragraph, then line, then
+//! word boundaries, keeping code fences balanced, and appending `(1/3)` markers.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Dialect {
+    /// CommonMark-ish, passed through unchanged (Discord, WebChat).
+    Markdown,
+    TelegramMarkdownV2,
+    SlackMrkdwn,
+    /// Formatting stripped (SMS-like channels).
+    Plain,
+}
+
+const FENCE: &str = "```";
+
+/// Characters Telegram requires escaped outside entities.
+const MDV2_SPECIAL: &[char] = &[
+    '_', '*', '[', ']', '(', ')', '~', '`', '>', '#', '+', '-', '=', '|', '{', '}', '.', '!', '\\',
+];
+
+pub fn render(markdown: &str, dialect: Dialect) -> String {
+    if dialect == Dialect::Markdown {
+        return markdown.to_string();
+    }
+
+    let mut out: Vec<String> = Vec::new();
+    let mut lines = markdown.split('\n');
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim_start();
+        if let Some(lang) = trimmed.strip_prefix(FENCE) {
+            let mut body: Vec<&str> = Vec::new();
+            for inner in lines.by_ref() {
+                if inner.trim_start().starts_with(FENCE) {
+                    break;
+                }
+                body.push(inner);
+            }
+            out.push(render_code_block(lang.trim(), &body.join("\n"), dialect));
+            continue;
+        }
+        out.push(render_line(line, dialect));
+    }
+    out.join("\n")
+}
+
+fn render_code_block(lang: &str, body: &str, dialect: Dialect) -> String {
+    match dialect {
+        Dialect::TelegramMarkdownV2 => format!("```{lang}\n{}\n```", escape_code(body)),
+        Dialect::SlackMrkdwn => format!("```\n{body}\n```"),
+        Dialect::Plain => body.to_string(),
+        Dialect::Markdown => format!("```{lang}\n{body}\n```"),
+    }
+}
+
+fn render_line(line: &str, dialect: Dialect) -> String {
+    let trimmed = line.trim_start();
+    let hashes = trimmed.chars().take_while(|c| *c == '#').count();
+    if (1..=6).contains(&hashes) && trimmed[hashes..].starts_with(' ') {
+        let text = trimmed[hashes..].trim();
+        return match dialect {
+            Dialect::TelegramMarkdownV2 => format!("*{}*", escape_mdv2(text)),
+            Dialect::SlackMrkdwn => format!("*{}*", escape_slack(text)),
+            _ => text.to_string(),
+        };
+    }
+
+    let indent = &line[..line.len() - trimmed.len()];
+    if let Some(item) = trimmed
+        .strip_prefix("- ")
+        .or_else(|| trimmed.strip_prefix("* "))
+    {
+        return format!("{indent}• {}", render_inline(item, dialect));
+    }
+    render_inline(line, dialect)
+}
+
+fn render_inline(text: &str, dialect: Dialect) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c == '`' {
+            if let Some(end) = find_from(&chars, i + 1, "`") {
+                let inner: String = chars[i + 1..end].iter().collect();
+                out.push_str(&match dialect {
+                    Dialect::TelegramMarkdownV2 => format!("`{}`", escape_code(&inner)),
+                    Dialect::Plain => inner,
+                    _ => format!("`{inner}`"),
+                });
+                i = end + 1;
+                continue;
+            }
+        }
+
+        if c == '*' && chars.get(i + 1) == Some(&'*') {
+            if let Some(end) = find_from(&chars, i + 2, "**").filter(|e| *e > i + 2) {
+                let inner: String = chars[i + 2..end].iter().collect();
+                out.push_str(&match dialect {
+                    Dialect::TelegramMarkdownV2 => format!("*{}*", escape_mdv2(&inner)),
+                    Dialect::SlackMrkdwn => format!("*{}*", escape_slack(&inner)),
+                    _ => inner,
+                });
+                i = end + 2;
+                continue;
+            }
+        }
+
+        if (c == '*' || c == '_') && opens_emphasis(&chars, i) {
+            if let Some(end) = find_emphasis_close(&chars, i) {
+                let inner: String = chars[i + 1..end].iter().collect();
+                out.push_str(&match dialect {
+                    Dialect::TelegramMarkdownV2 => format!("_{}_", escape_mdv2(&inner)),
+                    Dialect::SlackMrkdwn => format!("_{}_", escape_slack(&inner)),
+                    _ => inner,
+                });
+                i = end + 1;
+                continue;
+            }
+        }
+
+        if c == '[' {
+            if let Some((label, url, end)) = parse_link(&chars, i) {
+                out.push_str(&match dialect {
+                    Dialect::TelegramMarkdownV2 => format!(
+                        "[{}]({})",
+                        escape_mdv2(&label),
+                        url.replace('\\', "\\\\").replace(')', "\\)")
+                    ),
+                    Dialect::SlackMrkdwn => format!("<{url}|{}>", escape_slack(&label)),
+                    _ => format!("{label} ({url})"),
+                });
+                i = end;
+                continue;
+            }
+        }
+
+        match dialect {
+            Dialect::TelegramMarkdownV2 => out.push_str(&escape_mdv2(&c.to_string())),
+            Dialect::SlackMrkdwn => out.push_str(&escape_slack(&c.to_string())),
+            _ => out.push(c),
+        }
+        i += 1;
+    }
+    out
+}
+
+fn find_from(chars: &[char], start: usize, needle: &str) -> Option<usize> {
+    let needle: Vec<char> = needle.chars().collect();
+    (start..chars.len()).find(|&j| chars[j..].starts_with(&needle))
+}
+
+/// Single `*`/`_` only opens emphasis at a word start, so `snake_case` stays literal.
+fn opens_emphasis(chars: &[char], i: usize) -> bool {
+    let before_ok = i == 0 || !chars[i - 1].is_alphanumeric();
+    let after_ok = chars.get(i + 1).is_some_and(|c| !c.is_whitespace());
+    before_ok && after_ok
+}
+
+fn find_emphasis_close(chars: &[char], open: usize) -> Option<usize> {
+    let marker = chars[open];
+    (open + 2..chars.len()).find(|&j| {
+        chars[j] == marker
+            && !chars[j - 1].is_whitespace()
+            && chars.get(j + 1).is_none_or(|c| !c.is_alphanumeric())
+    })
+}
+
+fn parse_link(chars: &[char], open: usize) -> Option<(String, String, usize)> {
+    let close = find_from(chars, open + 1, "](")?;
+    let end = find_from(chars, close + 2, ")")?;
+    let label: String = chars[open + 1..close].iter().collect();
+    let url: String = chars[close + 2..end].iter().collect();
+    if url.contains(char::is_whitespace) {
+        return None;
+    }
+    Some((label, url, end + 1))
+}
+
+fn escape_mdv2(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if MDV2_SPECIAL.contains(&c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn escape_code(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('`', "\\`")
+}
+
+fn escape_slack(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeBlock {
+    pub filename: String,
+    pub code: String,
+}
+
+/// Pull fenced code blocks of at least `min_lines` lines out of `markdown`, leaving an
+/// `[attached: snippet-1.rs]` note in their place.
+pub fn extract_code_blocks(markdown: &str, min_lines: usize) -> (String, Vec<CodeBlock>) {
+    let mut out: Vec<String> = Vec::new();
+    let mut blocks = Vec::new();
+    let mut lines = markdown.split('\n');
+    while let Some(line) = lines.next() {
+        let Some(lang) = line.trim_start().strip_prefix(FENCE) else {
+            out.push(line.to_string());
+            continue;
+        };
+        let mut body: Vec<&str> = Vec::new();
+        let mut closed = false;
+        for inner in lines.by_ref() {
+            if inner.trim_start().starts_with(FENCE) {
+                closed = true;
+                break;
+            }
+            body.push(inner);
+        }
+        if closed && body.len() >= min_lines {
+            let filename = format!(
+                "snippet-{}.{}",
+                blocks.len() + 1,
+                extension_for(lang.trim())
+            );
+            out.push(format!("[attached: {filename}]"));
+            blocks.push(CodeBlock {
+                filename,
+                code: body.join("\n") + "\n",
+            });
+        } else {
+            out.push(line.to_string());
+            out.extend(body.iter().map(|l| l.to_string()));
+            if closed {
+                out.push(FENCE.to_string());
+            }
+        }
+    }
+    (out.join("\n"), blocks)
+}
+
+fn extension_for(lang: &str) -> &'static str {
+    match lang.to_ascii_lowercase().as_str() {
+        "rust" | "rs" => "rs",
+        "python" | "py" => "py",
+        "javascript" | "js" | "jsx" => "js",
+        "typescript" | "ts" | "tsx" => "ts",
+        "json" => "json",
+        "yaml" | "yml" => "yaml",
+        "toml" => "toml",
+        "bash" | "sh" | "shell" | "zsh" => "sh",
+        "go" | "golang" => "go",
+        "java" => "java",
+        "kotlin" | "kt" => "kt",
+        "swift" => "swift",
+        "c" => "c",
+        "cpp" | "c++" | "cc" => "cpp",
+        "csharp" | "cs" | "c#" => "cs",
+        "ruby" | "rb" => "rb",
+        "html" => "html",
+        "css" => "css",
+        "sql" => "sql",
+        "markdown" | "md" => "md",
+        "diff" | "patch" => "diff",
+        _ => "txt",
+    }
+}
+
+/// Split Markdown into pieces whose rendering fits `max_chars`. A message that already
+/// fits is returned unchanged, without a marker.
+pub fn split_message(markdown: &str, max_chars: usize, dialect: Dialect) -> Vec<String> {
+    let len = |s: &str| render(s, dialect).chars().count();
+    if len(markdown) <= max_chars {
+        return vec![markdown.to_string()];
+    }
+
+    let budget = max_chars.saturating_sub(len("\n(99/99)")).max(1);
+    let blocks = split_blocks(markdown);
+    let chunks = pack(
+        blocks.iter().map(|s| s.as_str()),
+        "\n\n",
+        budget,
+        &len,
+        &|s| s.to_string(),
+        &|block| split_block(block, budget, &len),
+    );
+
+    let total = chunks.len();
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, c)| format!("{c}\n({}/{total})", i + 1))
+        .collect()
+}
+
+/// Paragraphs, with each fenced code block kept whole.
+fn split_blocks(markdown: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    let mut in_fence = false;
+    for line in markdown.split('\n') {
+        let is_fence = line.trim_start().starts_with(FENCE);
+        if !in_fence && is_fence && !current.is_empty() {
+            blocks.push(current.join("\n"));
+            current.clear();
+        }
+        if !in_fence && !is_fence && line.trim().is_empty() {
+            if !current.is_empty() {
+                blocks.push(current.join("\n"));
+                current.clear();
+            }
+            continue;
+        }
+        current.push(line);
+        if is_fence {
+            in_fence = !in_fence;
+            if !in_fence {
+                blocks.push(current.join("\n"));
+                current.clear();
+            }
+        }
+    }
+    if !current.is_empty() {
+        blocks.push(current.join("\n"));
+    }
+    blocks
+}
+
+fn split_block(block: &str, budget: usize, len: &dyn Fn(&str) -> usize) -> Vec<String> {
+    if block.trim_start().starts_with(FENCE) {
+        let mut lines = block.split('\n');
+        let open = lines.next().unwrap_or(FENCE).trim_start().to_string();
+        let mut body: Vec<&str> = lines.collect();
+        if body
+            .last()
+            .is_some_and(|l| l.trim_start().starts_with(FENCE))
+        {
+            body.pop();
+        }
+        let wrap = |s: &str| format!("{open}\n{s}\n{FENCE}");
+        let hard = |s: &str| hard_split(s, budget, len, &wrap);
+        return pack(body.into_iter(), "\n", budget, len, &wrap, &hard);
+    }
+    split_text(block, budget, len)
+}
+
+/// Lines, then words, then characters.
+fn split_text(text: &str, budget: usize, len: &dyn Fn(&str) -> usize) -> Vec<String> {
+    let plain = |s: &str| s.to_string();
+    if text.contains('\n') {
+        pack(text.split('\n'), "\n", budget, len, &plain, &|s| {
+            split_text(s, budget, len)
+        })
+    } else if text.contains(' ') {
+        pack(text.split(' '), " ", budget, len, &plain, &|s| {
+            hard_split(s, budget, len, &plain)
+        })
+    } else {
+        hard_split(text, budget, len, &plain)
+    }
+}
+
+/// Greedily join `units` with `sep` while the wrapped result fits; units that are too
+/// big on their own go through `oversize`.
+fn pack<'a>(
+    units: impl Iterator<Item = &'a str>,
+    sep: &str,
+    budget: usize,
+    len: &dyn Fn(&str) -> usize,
+    wrap: &dyn Fn(&str) -> String,
+    oversize: &dyn Fn(&str) -> Vec<String>,
+) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut current = String::new();
+    for unit in units {
+        let candidate = if current.is_empty() {
+            unit.to_string()
+        } else {
+            format!("{current}{sep}{unit}")
+        };
+        if len(&wrap(&candidate)) <= budget {
+            current = candidate;
+            continue;
+        }
+        if !current.is_empty() {
+            out.push(wrap(&current));
+            current.clear();
+        }
+        if len(&wrap(unit)) <= budget {
+            current = unit.to_string();
+        } else {
+            out.extend(oversize(unit));
+        }
+    }
+    if !current.is_empty() {
+        out.push(wrap(&current));
+    }
+    out
+}
+
+fn hard_split(
+    text: &str,
+    budget: usize,
+    len: &dyn Fn(&str) -> usize,
+    wrap: &dyn Fn(&str) -> String,
+) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut current = String::new();
+    for c in text.chars() {
+        current.push(c);
+        if len(&wrap(&current)) > budget && current.chars().count() > 1 {
+            current.pop();
+            out.push(wrap(&current));
+            current = c.to_string();
+        }
+    }
+    if !current.is_empty() {
+        out.push(wrap(&current));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_dialects() {
+        let md = "## Plan\nUse **bold** and `x_y` in snake_case. See [docs](https://e.com/a_b).";
+
+        assert_eq!(render(md, Dialect::Markdown), md);
+        assert_eq!(
+            render(md, Dialect::TelegramMarkdownV2),
+            "*Plan*\nUse *bold* and `x_y` in snake\\_case\\. See [docs](https://e.com/a_b)\\."
+        );
+        assert_eq!(
+            render(md, Dialect::SlackMrkdwn),
+            "*Plan*\nUse *bold* and `x_y` in snake_case. See <https://e.com/a_b|docs>."
+        );
+        assert_eq!(
+            render(md, Dialect::Plain),
+            "Plan\nUse bold and x_y in snake_case. See docs (https://e.com/a_b)."
+        );
+    }
+
+    #[test]
+    fn splits_within_limit_and_keeps_fences_balanced() {
+        let paragraph = "word ".repeat(40);
+        let code: String = (0..40).map(|i| format!("let v{i} = {i};\n")).collect();
+        let md = format!("{paragraph}\n\n```rust\n{code}```\n\n{paragraph}");
+
+        for dialect in [
+            Dialect::Markdown,
+            Dialect::TelegramMarkdownV2,
+            Dialect::Plain,
+        ] {
+            let chunks = split_message(&md, 200, dialect);
+            let total = chunks.len();
+            assert!(total > 2);
+            for (i, chunk) in chunks.iter().enumerate() {
+                assert!(render(chunk, dialect).chars().count() <= 200, "{chunk}");
+                assert!(chunk.ends_with(&format!("\n({}/{total})", i + 1)));
+                assert_eq!(chunk.matches(FENCE).count() % 2, 0, "{chunk}");
+            }
+        }
+
+        assert_eq!(split_message("short", 200, Dialect::Plain), vec!["short"]);
+    }
+
+    #[test]
+    fn long_code_blocks_are_extracted() {
+        let md = "Here:\n```python\na = 1\nb = 2\nc = 3\n```\nand `inline`\n```\nx\n```";
+        let (text, blocks) = extract_code_blocks(md, 3);
+
+        assert_eq!(
+            text,
+            "Here:\n[attached: snippet-1.py]\nand `inline`\n```\nx\n```"
+        );
+        assert_eq!(
+            blocks,
+            vec![CodeBlock {
+                filename: "snippet-1.py".to_string(),
+                code: "a = 1\nb = 2\nc = 3\n".to_string(),
+            }]
+        );
+    }
+}
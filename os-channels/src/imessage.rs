@@ -1,19 +1,28 @@
-use crate::traits::ChannelAdapter;
-use crate::types::{InboundMessage, InboundMessageKind, OutboundMessage};
+use crate::format::{render, Dialect};
+use crate::traits::{ChannelAdapter, ReceiveFailure};
+use crate::types::{Attachment, InboundMessage, InboundMessageKind, OutboundMessage};
 use anyhow::{anyhow, Context, Result};
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use rusqlite::{params, Connection, OpenFlags};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
 use std::path::{Path, PathBuf};
+use std::process::Stdio;
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 
+const SHORTCUT_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// iMessage adapter backed by the local macOS Messages database (`chat.db`) for reads,
 /// and AppleScript (`osascript`) for sends.
 ///
 /// Permissions required on macOS:
 /// - Full Disk Access for the terminal running OpenShell (to read `~/Library/Messages/chat.db`).
 /// - Automation permission to control the Messages app (for `osascript` sends).
+///
+/// Messages sometimes accepts a send and then drops it, so by default each send is
+/// confirmed by finding the outgoing row in `chat.db`; see [`ImessageSendError`].
 #[derive(Clone)]
 pub struct ImessageAdapter {
     source_db: PathBuf,
@@ -21,8 +30,49 @@ pub struct ImessageAdapter {
     start_from_latest: bool,
     max_per_poll: usize,
     group_prefixes: Vec<String>,
+    attachments_dir: Option<PathBuf>,
+    max_attachment_bytes: u64,
+    delivery_timeout: Duration,
+    send_shortcut: Option<String>,
+    failure: ReceiveFailure,
+}
+
+/// Why an iMessage send is known (or presumed) not to have gone out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImessageSendError {
+    /// `osascript` (and the Shortcuts fallback, if configured) failed.
+    Rejected(String),
+    /// No outgoing row for the message showed up in `chat.db`.
+    NotRecorded { waited: Duration },
+    /// The row is there but Messages never marked it sent.
+    Unconfirmed { waited: Duration },
+    /// Messages recorded the send as failed (the `message.error` column).
+    Failed { code: i64 },
+}
+
+impl fmt::Display for ImessageSendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Rejected(reason) => write!(f, "Messages did not accept the message: {reason}"),
+            Self::NotRecorded { waited } => write!(
+                f,
+                "the message did not appear in Messages within {}s and was probably not sent",
+                waited.as_secs()
+            ),
+            Self::Unconfirmed { waited } => write!(
+                f,
+                "Messages had not sent the message after {}s",
+                waited.as_secs()
+            ),
+            Self::Failed { code } => {
+                write!(f, "Messages failed to send the message (error {code})")
+            }
+        }
+    }
 }
 
+impl std::error::Error for ImessageSendError {}
+
 impl ImessageAdapter {
     pub fn new(source_db: impl AsRef<Path>) -> Self {
         Self {
@@ -32,6 +82,11 @@ impl ImessageAdapter {
             max_per_poll: 200,
             // Avoid replying to every group message by default; require an explicit prefix.
             group_prefixes: vec!["@openshell".to_string(), "openshell".to_string()],
+            attachments_dir: None,
+            max_attachment_bytes: 25 * 1024 * 1024,
+            delivery_timeout: Duration::from_secs(15),
+            send_shortcut: None,
+            failure: ReceiveFailure::default(),
         }
     }
 
@@ -66,6 +121,62 @@ impl ImessageAdapter {
             .collect();
         self
     }
+
+    /// Copy files attached to incoming messages into `dir` and pass them on as
+    /// `file://` attachments. Without this, attachments are dropped.
+    pub fn with_attachments_dir(mut self, dir: impl AsRef<Path>) -> Self {
+        self.attachments_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Attachments larger than this are skipped.
+    pub fn with_max_attachment_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_attachment_bytes = max_bytes;
+        self
+    }
+
+    /// How long to wait for a sent message to be recorded as sent. Zero skips the check.
+    pub fn with_delivery_timeout(mut self, timeout: Duration) -> Self {
+        self.delivery_timeout = timeout;
+        self
+    }
+
+    /// Send through this Shortcuts shortcut when AppleScript fails or the message never
+    /// reaches `chat.db`. It is run with a JSON file `{"recipient": .., "text": ..}` as input.
+    pub fn with_send_shortcut(mut self, name: &str) -> Self {
+        self.send_shortcut = Some(name.trim().to_string()).filter(|s| !s.is_empty());
+        self
+    }
+
+    /// The newest messages (ours and theirs) whose text contains `query`, case-insensitively.
+    ///
+    /// chat.db has no full-text index, and newer macOS versions leave `text` empty for some
+    /// messages (the body is only in `attributedBody`), so those are not found.
+    pub async fn search(&self, query: &str, limit: usize) -> Result<Vec<ImessageHit>> {
+        let query = query.trim().to_string();
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+        let source_db = self.source_db.clone();
+        tokio::task::spawn_blocking(move || {
+            let conn = open_chat_db_readonly(&source_db)?;
+            search_messages(&conn, &query, limit)
+        })
+        .await?
+    }
+}
+
+/// A chat.db message matching [`ImessageAdapter::search`].
+#[derive(Debug, Clone, Serialize)]
+pub struct ImessageHit {
+    pub guid: String,
+    pub text: String,
+    pub is_from_me: bool,
+    /// Phone number or email of the other side; `None` for some of our own messages.
+    pub handle: Option<String>,
+    pub chat_guid: Option<String>,
+    pub chat_name: Option<String>,
+    pub sent_at: Option<DateTime<Utc>>,
 }
 
 #[async_trait::async_trait]
@@ -75,21 +186,27 @@ impl ChannelAdapter for ImessageAdapter {
     }
 
     async fn start(&self, tx: mpsc::Sender<InboundMessage>) -> Result<()> {
+        self.failure.clear();
         let adapter = self.clone();
         tokio::spawn(async move {
             if let Err(e) = adapter.poll_loop(tx).await {
                 tracing::error!(%e, "imessage poll loop exited");
+                adapter.failure.set(e);
             }
         });
         Ok(())
     }
 
+    fn receive_failure(&self) -> Option<String> {
+        self.failure.get()
+    }
+
     async fn send(&self, recipient_id: &str, message: OutboundMessage) -> Result<()> {
         let handle = recipient_id.trim();
         if handle.is_empty() {
             return Err(anyhow!("recipient_id is required"));
         }
-        let body = message.content.trim().to_string();
+        let body = render(message.content.trim(), Dialect::Plain);
         if body.is_empty() {
             return Err(anyhow!("message content is empty"));
         }
@@ -99,8 +216,52 @@ impl ChannelAdapter for ImessageAdapter {
         let parsed = parse_imessage_handle(handle);
         let script = build_send_script(&parsed, &body);
 
-        tokio::task::spawn_blocking(move || run_osascript(&script)).await??;
-        Ok(())
+        // Without Full Disk Access there is nothing to confirm against; send unverified.
+        let verify_after = if self.delivery_timeout.is_zero() {
+            None
+        } else {
+            let source_db = self.source_db.clone();
+            match tokio::task::spawn_blocking(move || {
+                current_max_rowid(&open_chat_db_readonly(&source_db)?)
+            })
+            .await?
+            {
+                Ok(rowid) => Some(rowid),
+                Err(e) => {
+                    tracing::warn!(%e, "cannot read chat.db; imessage send is unverified");
+                    None
+                }
+            }
+        };
+
+        let sent = tokio::task::spawn_blocking(move || run_osascript(&script)).await?;
+        let Some(after) = verify_after else {
+            return match (sent, self.send_shortcut.as_deref()) {
+                (Err(e), Some(shortcut)) => {
+                    tracing::warn!(%e, "osascript send failed; trying shortcut");
+                    self.send_via_shortcut(shortcut, handle, &body).await
+                }
+                (sent, _) => sent.map_err(|e| ImessageSendError::Rejected(e.to_string()).into()),
+            };
+        };
+
+        let outcome = match sent {
+            Ok(()) => self.confirm_delivery(after, &body).await,
+            Err(e) => Err(ImessageSendError::Rejected(e.to_string())),
+        };
+        let outcome = match (outcome, self.send_shortcut.as_deref()) {
+            // Only retry when nothing was recorded, so a slow send is never duplicated.
+            (
+                Err(e @ (ImessageSendError::Rejected(_) | ImessageSendError::NotRecorded { .. })),
+                Some(shortcut),
+            ) => {
+                tracing::warn!(%e, "imessage send failed; trying shortcut");
+                self.send_via_shortcut(shortcut, handle, &body).await?;
+                self.confirm_delivery(after, &body).await
+            }
+            (outcome, _) => outcome,
+        };
+        outcome.map_err(Into::into)
     }
 
     fn supports_reactions(&self) -> bool {
@@ -144,6 +305,7 @@ impl ImessageAdapter {
         let last_seen = last_rowid.unwrap_or(0);
         let max_per_poll = self.max_per_poll;
         let group_prefixes = self.group_prefixes.clone();
+        let with_attachments = self.attachments_dir.is_some();
 
         let poll = tokio::task::spawn_blocking(move || {
             let conn = open_chat_db_readonly(&source_db)?;
@@ -186,12 +348,21 @@ LIMIT ?2
                     chat_guid: row.get::<_, Option<String>>(6)?,
                     chat_display_name: row.get::<_, Option<String>>(7)?,
                     chat_service_name: row.get::<_, Option<String>>(8)?,
+                    attachments: Vec::new(),
                 })
             })?;
 
             for item in iter {
                 out.push(item?);
             }
+            if with_attachments {
+                if let Some(newest) = out.last().map(|r| r.rowid) {
+                    let mut found = attachments_between(&conn, last, newest)?;
+                    for raw in &mut out {
+                        raw.attachments = found.remove(&raw.rowid).unwrap_or_default();
+                    }
+                }
+            }
             Ok::<_, anyhow::Error>(PollResult {
                 start_rowid: last,
                 rows: out,
@@ -218,9 +389,11 @@ LIMIT ?2
                 continue;
             };
 
-            let text = raw.text.unwrap_or_default();
+            // Messages carry U+FFFC where each attachment sat in the text.
+            let text = raw.text.unwrap_or_default().replace('\u{fffc}', "");
             let mut content = text.trim().to_string();
-            if content.is_empty() {
+            let attachments = self.copy_attachments(&raw.guid, &raw.attachments).await;
+            if content.is_empty() && attachments.is_empty() {
                 continue;
             }
 
@@ -254,6 +427,7 @@ LIMIT ?2
                 thread_id,
                 is_group,
                 content,
+                attachments,
                 metadata: meta,
                 received_at: Utc::now(),
             };
@@ -268,6 +442,243 @@ LIMIT ?2
     }
 }
 
+impl ImessageAdapter {
+    /// Wait for the outgoing row for `body` after `after` and check how Messages recorded it.
+    async fn confirm_delivery(&self, after: i64, body: &str) -> Result<(), ImessageSendError> {
+        let deadline = Instant::now() + self.delivery_timeout;
+        let mut seen = false;
+        loop {
+            let source_db = self.source_db.clone();
+            let text = body.to_string();
+            let row = tokio::task::spawn_blocking(move || {
+                let conn = open_chat_db_readonly(&source_db)?;
+                sent_row_status(&conn, after, &text)
+            })
+            .await
+            .map_err(|e| ImessageSendError::Rejected(e.to_string()))?;
+            match row {
+                Ok(Some(SentRow { error, .. })) if error != 0 => {
+                    return Err(ImessageSendError::Failed { code: error })
+                }
+                Ok(Some(SentRow { is_sent: true, .. })) => return Ok(()),
+                Ok(Some(_)) => seen = true,
+                Ok(None) => {}
+                Err(e) => tracing::debug!(%e, "chat.db read failed while confirming send"),
+            }
+            if Instant::now() >= deadline {
+                let waited = self.delivery_timeout;
+                return Err(if seen {
+                    ImessageSendError::Unconfirmed { waited }
+                } else {
+                    ImessageSendError::NotRecorded { waited }
+                });
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+
+    async fn send_via_shortcut(&self, shortcut: &str, recipient: &str, body: &str) -> Result<()> {
+        let input =
+            std::env::temp_dir().join(format!("opencraw-imessage-{}.json", uuid::Uuid::new_v4()));
+        tokio::fs::write(
+            &input,
+            serde_json::to_vec(&serde_json::json!({ "recipient": recipient, "text": body }))?,
+        )
+        .await?;
+        let output = tokio::process::Command::new("shortcuts")
+            .args(["run", shortcut, "--input-path"])
+            .arg(&input)
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .output();
+        let output = tokio::time::timeout(SHORTCUT_TIMEOUT, output).await;
+        let _ = tokio::fs::remove_file(&input).await;
+        let output = output
+            .map_err(|_| ImessageSendError::Rejected("shortcut timed out".to_string()))?
+            .map_err(|e| ImessageSendError::Rejected(format!("spawn shortcuts: {e}")))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(ImessageSendError::Rejected(format!(
+                "shortcut {shortcut:?} failed: {}",
+                stderr.trim()
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Copy each attachment out of `~/Library/Messages/Attachments`, which only stays
+    /// readable while Messages keeps it. Failures are logged and the file skipped.
+    async fn copy_attachments(&self, message_guid: &str, raw: &[RawAttachment]) -> Vec<Attachment> {
+        let Some(dir) = self.attachments_dir.as_ref() else {
+            return Vec::new();
+        };
+        let mut out = Vec::new();
+        for (index, attachment) in raw.iter().enumerate() {
+            match self
+                .copy_attachment(dir, message_guid, index, attachment)
+                .await
+            {
+                Ok(Some(copied)) => out.push(copied),
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::warn!(%e, message_guid, "failed to copy imessage attachment")
+                }
+            }
+        }
+        out
+    }
+
+    async fn copy_attachment(
+        &self,
+        dir: &Path,
+        message_guid: &str,
+        index: usize,
+        raw: &RawAttachment,
+    ) -> Result<Option<Attachment>> {
+        let Some(source) = raw.filename.as_deref().map(expand_home) else {
+            // Not downloaded yet (e.g. still in iCloud).
+            return Err(anyhow!("attachment has no local file"));
+        };
+        // Link previews and app payloads, not something the sender attached.
+        if source
+            .extension()
+            .is_some_and(|ext| ext == "pluginPayloadAttachment")
+        {
+            return Ok(None);
+        }
+        let size = tokio::fs::metadata(&source)
+            .await
+            .with_context(|| format!("stat {}", source.display()))?
+            .len();
+        if size > self.max_attachment_bytes {
+            return Err(anyhow!(
+                "attachment is {size} bytes; the limit is {}",
+                self.max_attachment_bytes
+            ));
+        }
+
+        let name = raw
+            .transfer_name
+            .clone()
+            .or_else(|| {
+                source
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+            })
+            .unwrap_or_else(|| "attachment".to_string());
+        let target = dir.join(format!(
+            "{}-{index}-{}",
+            safe_file_name(message_guid),
+            safe_file_name(&name)
+        ));
+        tokio::fs::create_dir_all(dir).await?;
+        tokio::fs::copy(&source, &target)
+            .await
+            .with_context(|| format!("copy {}", source.display()))?;
+
+        let content_type = raw
+            .mime_type
+            .clone()
+            .unwrap_or_else(|| guess_content_type(&name).to_string());
+        Ok(Some(Attachment {
+            name,
+            content_type,
+            url: format!("file://{}", target.display()),
+        }))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RawAttachment {
+    /// Local path, usually starting with `~/Library/Messages/Attachments/`.
+    filename: Option<String>,
+    mime_type: Option<String>,
+    /// The name the sender's device gave the file.
+    transfer_name: Option<String>,
+}
+
+/// Attachments of messages with `after < ROWID <= until`, by message ROWID.
+fn attachments_between(
+    conn: &Connection,
+    after: i64,
+    until: i64,
+) -> Result<HashMap<i64, Vec<RawAttachment>>> {
+    let mut stmt = conn.prepare_cached(
+        r#"
+SELECT maj.message_id, a.filename, a.mime_type, a.transfer_name
+FROM message_attachment_join maj
+JOIN attachment a ON a.ROWID = maj.attachment_id
+WHERE maj.message_id > ?1 AND maj.message_id <= ?2
+ORDER BY maj.message_id ASC, a.ROWID ASC
+"#,
+    )?;
+    let rows = stmt.query_map(params![after, until], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            RawAttachment {
+                filename: row.get(1)?,
+                mime_type: row.get(2)?,
+                transfer_name: row.get(3)?,
+            },
+        ))
+    })?;
+    let mut out: HashMap<i64, Vec<RawAttachment>> = HashMap::new();
+    for row in rows {
+        let (message_id, attachment) = row?;
+        out.entry(message_id).or_default().push(attachment);
+    }
+    Ok(out)
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(rest) => {
+            let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+            Path::new(&home).join(rest)
+        }
+        None => PathBuf::from(path),
+    }
+}
+
+fn safe_file_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    cleaned.trim_start_matches('.').to_string()
+}
+
+/// For attachments chat.db has no MIME type for (it is often empty for HEIC and audio).
+fn guess_content_type(name: &str) -> &'static str {
+    let ext = name
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "heic" => "image/heic",
+        "webp" => "image/webp",
+        "pdf" => "application/pdf",
+        "txt" => "text/plain",
+        "mov" => "video/quicktime",
+        "mp4" => "video/mp4",
+        "m4a" => "audio/mp4",
+        "caf" => "audio/x-caf",
+        "mp3" => "audio/mpeg",
+        _ => "application/octet-stream",
+    }
+}
+
 #[derive(Debug, Clone)]
 struct RawMessage {
     rowid: i64,
@@ -279,6 +690,7 @@ struct RawMessage {
     chat_guid: Option<String>,
     chat_display_name: Option<String>,
     chat_service_name: Option<String>,
+    attachments: Vec<RawAttachment>,
 }
 
 #[derive(Debug)]
@@ -305,6 +717,91 @@ fn current_max_rowid(conn: &Connection) -> Result<i64> {
     Ok(v)
 }
 
+/// Seconds between the Unix epoch and 2001-01-01, where chat.db dates count from.
+const APPLE_EPOCH_OFFSET: i64 = 978_307_200;
+
+fn search_messages(conn: &Connection, query: &str, limit: usize) -> Result<Vec<ImessageHit>> {
+    let pattern = format!(
+        "%{}%",
+        query
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    );
+    let mut stmt = conn.prepare_cached(
+        r#"
+SELECT
+  m.guid,
+  m.text,
+  m.is_from_me,
+  h.id AS handle_id,
+  c.guid AS chat_guid,
+  c.display_name AS chat_display_name,
+  m.date
+FROM message m
+LEFT JOIN handle h ON h.ROWID = m.handle_id
+LEFT JOIN chat_message_join cmj ON cmj.message_id = m.ROWID
+LEFT JOIN chat c ON c.ROWID = cmj.chat_id
+WHERE m.text LIKE ?1 ESCAPE '\'
+ORDER BY m.ROWID DESC
+LIMIT ?2
+"#,
+    )?;
+    let rows = stmt.query_map(params![pattern, limit as i64], |row| {
+        Ok(ImessageHit {
+            guid: row.get(0)?,
+            text: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+            is_from_me: row.get::<_, i64>(2)? != 0,
+            handle: row.get(3)?,
+            chat_guid: row.get(4)?,
+            chat_name: row
+                .get::<_, Option<String>>(5)?
+                .filter(|n| !n.trim().is_empty()),
+            sent_at: row.get::<_, Option<i64>>(6)?.and_then(apple_time),
+        })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+/// chat.db `date` values count from 2001: in seconds before macOS 10.13, nanoseconds since.
+fn apple_time(value: i64) -> Option<DateTime<Utc>> {
+    if value <= 0 {
+        return None;
+    }
+    let secs = if value > 1_000_000_000_000 {
+        value / 1_000_000_000
+    } else {
+        value
+    };
+    DateTime::from_timestamp(secs + APPLE_EPOCH_OFFSET, 0)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SentRow {
+    is_sent: bool,
+    error: i64,
+}
+
+/// The first outgoing message after `after` with exactly this text.
+fn sent_row_status(conn: &Connection, after: i64, text: &str) -> Result<Option<SentRow>> {
+    let mut stmt = conn.prepare_cached(
+        r#"
+SELECT is_sent, error
+FROM message
+WHERE ROWID > ?1 AND is_from_me = 1 AND text = ?2
+ORDER BY ROWID ASC
+LIMIT 1
+"#,
+    )?;
+    let mut rows = stmt.query_map(params![after, text], |row| {
+        Ok(SentRow {
+            is_sent: row.get::<_, i64>(0)? != 0,
+            error: row.get(1)?,
+        })
+    })?;
+    Ok(rows.next().transpose()?)
+}
+
 fn strip_any_prefix(input: &str, prefixes: &[String]) -> Option<String> {
     let trimmed = input.trim_start();
     for p in prefixes {
@@ -417,6 +914,8 @@ fn run_osascript(script: &str) -> Result<()> {
     let mut child = Command::new("osascript")
         .arg("-e")
         .arg(script)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
         .spawn()
         .context("spawn osascript")?;
 
@@ -424,7 +923,12 @@ fn run_osascript(script: &str) -> Result<()> {
     loop {
         if let Some(status) = child.try_wait().context("wait on osascript")? {
             if !status.success() {
-                return Err(anyhow!("osascript failed"));
+                let mut stderr = String::new();
+                if let Some(mut pipe) = child.stderr.take() {
+                    use std::io::Read;
+                    let _ = pipe.read_to_string(&mut stderr);
+                }
+                return Err(anyhow!("osascript failed: {}", stderr.trim()));
             }
             return Ok(());
         }
@@ -461,6 +965,144 @@ mod tests {
         assert_eq!(p.chat_id, "iMessage;+;chat123");
     }
 
+    #[tokio::test]
+    async fn attachments_are_copied_out_of_chat_db() {
+        let tmp = std::env::temp_dir().join(format!("opencraw-imessage-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&tmp).unwrap();
+        let photo = tmp.join("IMG_0001.HEIC");
+        std::fs::write(&photo, b"heic").unwrap();
+
+        let db = tmp.join("chat.db");
+        let conn = Connection::open(&db).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE message (ROWID INTEGER PRIMARY KEY, guid TEXT, text TEXT, is_from_me INTEGER, handle_id INTEGER);
+             CREATE TABLE handle (ROWID INTEGER PRIMARY KEY, id TEXT, service TEXT);
+             CREATE TABLE chat (ROWID INTEGER PRIMARY KEY, guid TEXT, display_name TEXT, service_name TEXT);
+             CREATE TABLE chat_message_join (chat_id INTEGER, message_id INTEGER);
+             CREATE TABLE attachment (ROWID INTEGER PRIMARY KEY, filename TEXT, mime_type TEXT, transfer_name TEXT);
+             CREATE TABLE message_attachment_join (message_id INTEGER, attachment_id INTEGER);
+             INSERT INTO handle VALUES (1, '+14155551212', 'iMessage');
+             INSERT INTO message VALUES (1, 'G1', char(65532), 0, 1);
+             INSERT INTO message VALUES (2, 'G2', 'no files', 0, 1);
+             INSERT INTO message VALUES (3, 'G3', char(65532), 0, 1);",
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO attachment VALUES (1, ?1, NULL, 'IMG_0001.HEIC')",
+            params![photo.to_string_lossy()],
+        )
+        .unwrap();
+        conn.execute_batch(
+            "INSERT INTO attachment VALUES (2, '~/missing.pdf', 'application/pdf', 'missing.pdf');
+             INSERT INTO message_attachment_join VALUES (1, 1);
+             INSERT INTO message_attachment_join VALUES (3, 2);",
+        )
+        .unwrap();
+        drop(conn);
+
+        let adapter = ImessageAdapter::new(&db)
+            .with_start_from_latest(false)
+            .with_attachments_dir(tmp.join("copied"));
+        let (tx, mut rx) = mpsc::channel(8);
+        let mut last_rowid = None;
+        adapter.poll_once(&tx, &mut last_rowid).await.unwrap();
+        drop(tx);
+
+        let first = rx.recv().await.unwrap();
+        assert_eq!(first.content, "");
+        assert_eq!(first.attachments.len(), 1);
+        assert_eq!(first.attachments[0].name, "IMG_0001.HEIC");
+        assert_eq!(first.attachments[0].content_type, "image/heic");
+        let copied = first.attachments[0].url.strip_prefix("file://").unwrap();
+        assert_eq!(std::fs::read(copied).unwrap(), b"heic");
+
+        let second = rx.recv().await.unwrap();
+        assert_eq!(second.content, "no files");
+        assert!(second.attachments.is_empty());
+        // The third message's only attachment is missing, so there's nothing to deliver.
+        assert!(rx.recv().await.is_none());
+        assert_eq!(last_rowid, Some(3));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[tokio::test]
+    async fn sends_are_confirmed_against_chat_db() {
+        let tmp = std::env::temp_dir().join(format!("opencraw-imessage-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&tmp).unwrap();
+        let db = tmp.join("chat.db");
+        let conn = Connection::open(&db).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE message (ROWID INTEGER PRIMARY KEY, text TEXT, is_from_me INTEGER, is_sent INTEGER, error INTEGER);
+             INSERT INTO message VALUES (1, 'hello', 1, 1, 0);
+             INSERT INTO message VALUES (2, 'hello', 0, 0, 0);
+             INSERT INTO message VALUES (3, 'bounced', 1, 0, 22);
+             INSERT INTO message VALUES (4, 'stuck', 1, 0, 0);",
+        )
+        .unwrap();
+        drop(conn);
+
+        let adapter = ImessageAdapter::new(&db).with_delivery_timeout(Duration::ZERO);
+        assert_eq!(adapter.confirm_delivery(0, "hello").await, Ok(()));
+        // Only rows after the pre-send ROWID, and only our own, count.
+        assert_eq!(
+            adapter.confirm_delivery(1, "hello").await,
+            Err(ImessageSendError::NotRecorded {
+                waited: Duration::ZERO
+            })
+        );
+        assert_eq!(
+            adapter.confirm_delivery(0, "bounced").await,
+            Err(ImessageSendError::Failed { code: 22 })
+        );
+        assert_eq!(
+            adapter.confirm_delivery(0, "stuck").await,
+            Err(ImessageSendError::Unconfirmed {
+                waited: Duration::ZERO
+            })
+        );
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[tokio::test]
+    async fn search_matches_text_literally() {
+        let tmp = std::env::temp_dir().join(format!("opencraw-imessage-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&tmp).unwrap();
+        let db = tmp.join("chat.db");
+        let conn = Connection::open(&db).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE message (ROWID INTEGER PRIMARY KEY, guid TEXT, text TEXT, is_from_me INTEGER, handle_id INTEGER, date INTEGER);
+             CREATE TABLE handle (ROWID INTEGER PRIMARY KEY, id TEXT, service TEXT);
+             CREATE TABLE chat (ROWID INTEGER PRIMARY KEY, guid TEXT, display_name TEXT, service_name TEXT);
+             CREATE TABLE chat_message_join (chat_id INTEGER, message_id INTEGER);
+             INSERT INTO handle VALUES (1, '+14155551212', 'iMessage');
+             INSERT INTO chat VALUES (1, 'iMessage;+;chat1', 'Legal', 'iMessage');
+             INSERT INTO message VALUES (1, 'G1', 'sent the Contract over', 0, 1, 700000000000000000);
+             INSERT INTO message VALUES (2, 'G2', 'contract is 100% done', 1, 0, NULL);
+             INSERT INTO message VALUES (3, 'G3', 'lunch?', 0, 1, 0);
+             INSERT INTO chat_message_join VALUES (1, 2);",
+        )
+        .unwrap();
+        drop(conn);
+
+        let adapter = ImessageAdapter::new(&db);
+        let hits = adapter.search("contract", 10).await.unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].guid, "G2");
+        assert!(hits[0].is_from_me);
+        assert_eq!(hits[0].chat_name.as_deref(), Some("Legal"));
+        assert!(hits[0].sent_at.is_none());
+        assert_eq!(hits[1].handle.as_deref(), Some("+14155551212"));
+        assert_eq!(
+            hits[1].sent_at.unwrap().to_rfc3339(),
+            "2023-03-08T20:26:40+00:00"
+        );
+        // `%` is matched as itself rather than as a wildcard.
+        let hits = adapter.search("100%", 10).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(adapter.search("1%0", 10).await.unwrap().is_empty());
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
     #[test]
     fn parse_buddy_handle() {
         let p = parse_imessage_handle("iMessage;-;+14155551212");
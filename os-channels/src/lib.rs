@@ -5,16 +5,27 @@
 //!
 //! See: specifications/openshell/implementation_v0_1_0.md
 
+mod calendar;
 mod discord;
+mod format;
 mod imessage;
+mod multipart;
+mod ntfy;
+mod pushover;
 mod telegram;
 mod traits;
 mod types;
 mod webchat;
 
+pub use calendar::{Attendee, CalendarAdapter, CalendarEvent};
 pub use discord::DiscordAdapter;
-pub use imessage::ImessageAdapter;
+pub use format::{extract_code_blocks, render, split_message, CodeBlock, Dialect};
+pub use imessage::{ImessageAdapter, ImessageHit, ImessageSendError};
+pub use ntfy::NtfyAdapter;
+pub use pushover::PushoverAdapter;
 pub use telegram::TelegramAdapter;
-pub use traits::ChannelAdapter;
-pub use types::{Attachment, InboundMessage, InboundMessageKind, OutboundMessage};
-pub use webchat::WebChatAdapter;
+pub use traits::{ChannelAdapter, HttpSendError, ReceiveFailure};
+pub use types::{
+    Attachment, InboundMessage, InboundMessageKind, OutboundMessage, QuotedMessage, RunActivity,
+};
+pub use webchat::{HistoryMessage, WebChatAdapter};
@@ -0,0 +1,102 @@
+//! ntfy push notifications (outbound only).
+//!
+//! Each message is published to a topic on an ntfy server. The recipient is the topic;
+//! an empty recipient uses the configured default topic.
+
+use crate::format::Dialect;
+use crate::traits::{ChannelAdapter, HttpSendError};
+use crate::types::{InboundMessage, OutboundMessage};
+use anyhow::Result;
+use tokio::sync::mpsc;
+
+pub struct NtfyAdapter {
+    http: reqwest::Client,
+    server: String,
+    topic: String,
+    token: Option<String>,
+    title: String,
+}
+
+impl NtfyAdapter {
+    pub fn new(server: &str, topic: &str) -> Self {
+        Self {
+            http: reqwest::Client::builder()
+                .timeout(std::time::Duration::from_secs(30))
+                .build()
+                .unwrap_or_else(|e| {
+                    tracing::warn!(
+                        %e,
+                        "reqwest client build failed; falling back to default client"
+                    );
+                    reqwest::Client::new()
+                }),
+            server: server.trim_end_matches('/').to_string(),
+            topic: topic.to_string(),
+            token: None,
+            title: "OpenCraw".to_string(),
+        }
+    }
+
+    /// Access token for protected topics.
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token.filter(|t| !t.trim().is_empty());
+        self
+    }
+
+    pub fn with_title(mut self, title: &str) -> Self {
+        self.title = title.to_string();
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl ChannelAdapter for NtfyAdapter {
+    fn channel_id(&self) -> &str {
+        "ntfy"
+    }
+
+    async fn start(&self, _tx: mpsc::Sender<InboundMessage>) -> Result<()> {
+        Ok(())
+    }
+
+    async fn send(&self, recipient_id: &str, message: OutboundMessage) -> Result<()> {
+        let topic = if recipient_id.trim().is_empty() {
+            self.topic.as_str()
+        } else {
+            recipient_id
+        };
+        if topic.is_empty() {
+            return Err(anyhow::anyhow!("ntfy: no topic to publish to"));
+        }
+
+        let mut body = message.content;
+        for a in &message.attachments {
+            if a.inline_bytes().is_none() {
+                body.push_str(&format!("\n{}", a.url));
+            }
+        }
+
+        let mut req = self
+            .http
+            .post(format!("{}/{topic}", self.server))
+            .header("Title", &self.title)
+            .header("Markdown", "yes")
+            .body(body);
+        if let Some(token) = self.token.as_deref() {
+            req = req.bearer_auth(token);
+        }
+        let resp = req.send().await?;
+        if !resp.status().is_success() {
+            return Err(HttpSendError::from_response("ntfy", resp).await.into());
+        }
+        Ok(())
+    }
+
+    fn dialect(&self) -> Dialect {
+        Dialect::Markdown
+    }
+
+    fn max_message_chars(&self) -> usize {
+        4000
+    }
+}
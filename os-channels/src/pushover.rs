@@ -0,0 +1,103 @@
+//! Pushover push notifications (outbound only).
+//!
+//! The recipient is a Pushover user or group key; an empty recipient uses the configured
+//! default key.
+
+use crate::traits::{ChannelAdapter, HttpSendError};
+use crate::types::{InboundMessage, OutboundMessage};
+use anyhow::Result;
+use tokio::sync::mpsc;
+
+const PUSHOVER_MESSAGES_URL: &str = "https://api.pushover.net/1/messages.json";
+
+pub struct PushoverAdapter {
+    http: reqwest::Client,
+    app_token: String,
+    user_key: String,
+    device: Option<String>,
+    title: String,
+}
+
+impl PushoverAdapter {
+    pub fn new(app_token: &str, user_key: &str) -> Self {
+        Self {
+            http: reqwest::Client::builder()
+                .timeout(std::time::Duration::from_secs(30))
+                .build()
+                .unwrap_or_else(|e| {
+                    tracing::warn!(
+                        %e,
+                        "reqwest client build failed; falling back to default client"
+                    );
+                    reqwest::Client::new()
+                }),
+            app_token: app_token.to_string(),
+            user_key: user_key.to_string(),
+            device: None,
+            title: "OpenCraw".to_string(),
+        }
+    }
+
+    /// Deliver to one device instead of all of the user's devices.
+    pub fn with_device(mut self, device: Option<String>) -> Self {
+        self.device = device.filter(|d| !d.trim().is_empty());
+        self
+    }
+
+    pub fn with_title(mut self, title: &str) -> Self {
+        self.title = title.to_string();
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl ChannelAdapter for PushoverAdapter {
+    fn channel_id(&self) -> &str {
+        "pushover"
+    }
+
+    async fn start(&self, _tx: mpsc::Sender<InboundMessage>) -> Result<()> {
+        Ok(())
+    }
+
+    async fn send(&self, recipient_id: &str, message: OutboundMessage) -> Result<()> {
+        let user = if recipient_id.trim().is_empty() {
+            self.user_key.as_str()
+        } else {
+            recipient_id
+        };
+
+        let mut body = serde_json::json!({
+            "token": self.app_token,
+            "user": user,
+            "title": self.title,
+            "message": message.content,
+        });
+        // Pushover shows one supplementary URL per message.
+        if let Some(a) = message
+            .attachments
+            .iter()
+            .find(|a| a.inline_bytes().is_none())
+        {
+            body["url"] = serde_json::json!(a.url);
+        }
+        if let Some(device) = self.device.as_deref() {
+            body["device"] = serde_json::json!(device);
+        }
+
+        let resp = self
+            .http
+            .post(PUSHOVER_MESSAGES_URL)
+            .json(&body)
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(HttpSendError::from_response("pushover", resp).await.into());
+        }
+        Ok(())
+    }
+
+    fn max_message_chars(&self) -> usize {
+        1024
+    }
+}
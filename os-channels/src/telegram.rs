@@ -1,5 +1,9 @@
-use crate::traits::ChannelAdapter;
-use crate::types::{InboundMessage, InboundMessageKind, OutboundMessage};
+use crate::format::{render, Dialect};
+use crate::multipart::{self, FilePart};
+use crate::traits::{ChannelAdapter, HttpSendError, ReceiveFailure};
+use crate::types::{
+    Attachment, InboundMessage, InboundMessageKind, OutboundMessage, QuotedMessage,
+};
 use anyhow::Result;
 use chrono::Utc;
 use reqwest::Url;
@@ -11,6 +15,7 @@ use uuid::Uuid;
 pub struct TelegramAdapter {
     http: reqwest::Client,
     bot_token: String,
+    failure: ReceiveFailure,
 }
 
 impl TelegramAdapter {
@@ -27,6 +32,7 @@ impl TelegramAdapter {
                     reqwest::Client::new()
                 }),
             bot_token: bot_token.to_string(),
+            failure: ReceiveFailure::default(),
         }
     }
 
@@ -47,39 +53,168 @@ impl ChannelAdapter for TelegramAdapter {
     async fn start(&self, tx: mpsc::Sender<InboundMessage>) -> Result<()> {
         let http = self.http.clone();
         let token = self.bot_token.clone();
+        self.failure.clear();
+        let failure = self.failure.clone();
         tokio::spawn(async move {
             let adapter = TelegramAdapter {
                 http,
                 bot_token: token,
+                failure,
             };
             if let Err(e) = adapter.run_poll_loop(tx).await {
                 tracing::error!(%e, "telegram poll loop exited");
+                adapter.failure.set(e);
             }
         });
         Ok(())
     }
 
+    fn receive_failure(&self) -> Option<String> {
+        self.failure.get()
+    }
+
     async fn send(&self, recipient_id: &str, message: OutboundMessage) -> Result<()> {
+        if !message.content.trim().is_empty() {
+            self.send_text(recipient_id, &message.content).await?;
+        }
+        for attachment in &message.attachments {
+            self.send_document(recipient_id, attachment).await?;
+        }
+        Ok(())
+    }
+
+    async fn send_typing(&self, recipient_id: &str) -> Result<()> {
+        let url = self.api_url("sendChatAction")?;
+        let body = serde_json::json!({
+            "chat_id": recipient_id,
+            "action": "typing",
+        });
+        let resp = self.http.post(url).json(&body).send().await?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            tracing::debug!(%status, "telegram sendChatAction failed");
+        }
+        Ok(())
+    }
+
+    async fn send_status(&self, recipient_id: &str, text: &str) -> Result<String> {
         let url = self.api_url("sendMessage")?;
         let body = serde_json::json!({
             "chat_id": recipient_id,
-            "text": message.content,
+            "text": text,
+            "disable_notification": true,
         });
         let resp = self.http.post(url).json(&body).send().await?;
         if !resp.status().is_success() {
             let status = resp.status();
             let text = resp.text().await.unwrap_or_default();
-            tracing::warn!(%status, %text, "telegram send failed");
+            return Err(anyhow::anyhow!(
+                "telegram sendMessage failed ({status}): {text}"
+            ));
+        }
+        let sent: serde_json::Value = resp.json().await?;
+        sent["result"]["message_id"]
+            .as_i64()
+            .map(|id| id.to_string())
+            .ok_or_else(|| anyhow::anyhow!("telegram sendMessage returned no message id"))
+    }
+
+    async fn edit_status(&self, recipient_id: &str, message_id: &str, text: &str) -> Result<()> {
+        let url = self.api_url("editMessageText")?;
+        let body = serde_json::json!({
+            "chat_id": recipient_id,
+            "message_id": message_id.parse::<i64>()?,
+            "text": text,
+        });
+        let resp = self.http.post(url).json(&body).send().await?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let text = resp.text().await.unwrap_or_default();
+            tracing::debug!(%status, %text, "telegram editMessageText failed");
         }
         Ok(())
     }
 
+    fn supports_edits(&self) -> bool {
+        true
+    }
+
     fn supports_reactions(&self) -> bool {
         true
     }
+
+    fn supports_attachments(&self) -> bool {
+        true
+    }
+
+    fn dialect(&self) -> Dialect {
+        Dialect::TelegramMarkdownV2
+    }
+
+    fn max_message_chars(&self) -> usize {
+        4096
+    }
 }
 
 impl TelegramAdapter {
+    async fn send_text(&self, recipient_id: &str, content: &str) -> Result<()> {
+        let url = self.api_url("sendMessage")?;
+        let body = serde_json::json!({
+            "chat_id": recipient_id,
+            "text": render(content, Dialect::TelegramMarkdownV2),
+            "parse_mode": "MarkdownV2",
+        });
+        let resp = self.http.post(url.clone()).json(&body).send().await?;
+        if resp.status() == reqwest::StatusCode::BAD_REQUEST {
+            // Entity parsing rejected the markup; the text is still worth delivering.
+            let text = resp.text().await.unwrap_or_default();
+            tracing::debug!(%text, "telegram rejected MarkdownV2; resending as plain text");
+            let body = serde_json::json!({
+                "chat_id": recipient_id,
+                "text": render(content, Dialect::Plain),
+            });
+            let resp = self.http.post(url).json(&body).send().await?;
+            if !resp.status().is_success() {
+                return Err(HttpSendError::from_response("telegram", resp).await.into());
+            }
+        } else if !resp.status().is_success() {
+            return Err(HttpSendError::from_response("telegram", resp).await.into());
+        }
+        Ok(())
+    }
+
+    async fn send_document(&self, recipient_id: &str, attachment: &Attachment) -> Result<()> {
+        let url = self.api_url("sendDocument")?;
+        let resp = match attachment.inline_bytes() {
+            Some(bytes) => {
+                let file = FilePart {
+                    field: "document".to_string(),
+                    filename: &attachment.name,
+                    content_type: &attachment.content_type,
+                    bytes: &bytes,
+                };
+                let (content_type, body) = multipart::encode(&[("chat_id", recipient_id)], &[file]);
+                self.http
+                    .post(url)
+                    .header(reqwest::header::CONTENT_TYPE, content_type)
+                    .body(body)
+                    .send()
+                    .await?
+            }
+            None => {
+                let body = serde_json::json!({
+                    "chat_id": recipient_id,
+                    "document": attachment.url,
+                });
+                self.http.post(url).json(&body).send().await?
+            }
+        };
+        if !resp.status().is_success() {
+            return Err(HttpSendError::from_response("telegram", resp).await.into());
+        }
+        Ok(())
+    }
+
     #[tracing::instrument(level = "info", skip_all)]
     async fn run_poll_loop(&self, tx: mpsc::Sender<InboundMessage>) -> Result<()> {
         let mut offset: i64 = 0;
@@ -92,7 +227,10 @@ impl TelegramAdapter {
                 .query(&[
                     ("timeout", "30"),
                     ("offset", &offset.to_string()),
-                    ("allowed_updates", r#"[\"message\",\"message_reaction\"]"#),
+                    (
+                        "allowed_updates",
+                        r#"[\"message\",\"edited_message\",\"message_reaction\"]"#,
+                    ),
                 ])
                 .send()
                 .await?;
@@ -109,7 +247,9 @@ impl TelegramAdapter {
             for update in parsed.result {
                 offset = update.update_id + 1;
 
-                if let Some(m) = update.message {
+                let edited = update.edited_message.map(|m| (InboundMessageKind::Edit, m));
+                let message = update.message.map(|m| (InboundMessageKind::Message, m));
+                if let Some((kind, m)) = message.or(edited) {
                     let Some(ref text) = m.text else { continue };
                     let is_group = m.chat.r#type != "private";
                     let sender_id = m
@@ -117,16 +257,20 @@ impl TelegramAdapter {
                         .as_ref()
                         .map(|f| f.id.to_string())
                         .unwrap_or_default();
-                    let metadata =
+                    let mut metadata =
                         serde_json::to_value(&m).unwrap_or_else(|_| serde_json::json!({}));
+                    if let Some(quoted) = quoted_message(&m) {
+                        quoted.attach(&mut metadata);
+                    }
                     let inbound = InboundMessage {
-                        kind: InboundMessageKind::Message,
+                        kind,
                         message_id: m.message_id.to_string(),
                         channel_id: "telegram".to_string(),
                         sender_id,
                         thread_id: Some(m.chat.id.to_string()),
                         is_group,
                         content: text.clone(),
+                        attachments: vec![],
                         metadata,
                         received_at: Utc::now(),
                     };
@@ -152,6 +296,7 @@ impl TelegramAdapter {
                         thread_id: Some(r.chat.id.to_string()),
                         is_group: r.chat.r#type != "private",
                         content: emoji,
+                        attachments: vec![],
                         metadata: serde_json::to_value(&r)
                             .unwrap_or_else(|_| serde_json::json!({})),
                         received_at: Utc::now(),
@@ -175,6 +320,8 @@ struct TelegramUpdate {
     #[serde(default)]
     message: Option<TelegramMessage>,
     #[serde(default)]
+    edited_message: Option<TelegramMessage>,
+    #[serde(default)]
     message_reaction: Option<TelegramMessageReaction>,
 }
 
@@ -186,11 +333,25 @@ struct TelegramMessage {
     chat: TelegramChat,
     #[serde(default)]
     text: Option<String>,
+    #[serde(default)]
+    caption: Option<String>,
+    #[serde(default)]
+    reply_to_message: Option<Box<TelegramMessage>>,
+    /// The excerpt the user highlighted when replying, if any.
+    #[serde(default)]
+    quote: Option<TelegramTextQuote>,
+}
+
+#[derive(Debug, Deserialize, serde::Serialize)]
+struct TelegramTextQuote {
+    text: String,
 }
 
 #[derive(Debug, Deserialize, serde::Serialize)]
 struct TelegramMessageReaction {
     chat: TelegramChat,
+    /// The message reacted to; kept in the inbound metadata as `message_id`.
+    message_id: i64,
     #[serde(default)]
     user: Option<TelegramUser>,
     #[serde(default)]
@@ -206,6 +367,23 @@ struct TelegramReaction {
 #[derive(Debug, Deserialize, serde::Serialize)]
 struct TelegramUser {
     id: i64,
+    #[serde(default)]
+    is_bot: bool,
+}
+
+fn quoted_message(m: &TelegramMessage) -> Option<QuotedMessage> {
+    let replied = m.reply_to_message.as_deref()?;
+    Some(QuotedMessage {
+        message_id: replied.message_id.to_string(),
+        sender_id: replied.from.as_ref().map(|f| f.id.to_string()),
+        from_bot: replied.from.as_ref().is_some_and(|f| f.is_bot),
+        content: m
+            .quote
+            .as_ref()
+            .map(|q| q.text.clone())
+            .or_else(|| replied.text.clone())
+            .or_else(|| replied.caption.clone()),
+    })
 }
 
 #[derive(Debug, Deserialize, serde::Serialize)]
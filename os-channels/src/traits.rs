@@ -1,8 +1,74 @@
-use crate::types::{InboundMessage, OutboundMessage};
+use crate::format::Dialect;
+use crate::types::{InboundMessage, OutboundMessage, RunActivity};
 use anyhow::Result;
 use async_trait::async_trait;
+use std::fmt;
+use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc;
 
+/// Why an adapter's receive loop stopped, set when it exits with an error and cleared when
+/// it starts again. Clones share the slot, so the task running the loop can report to the
+/// adapter it came from.
+#[derive(Debug, Clone, Default)]
+pub struct ReceiveFailure(Arc<Mutex<Option<String>>>);
+
+impl ReceiveFailure {
+    pub fn set(&self, error: impl std::fmt::Display) {
+        if let Ok(mut slot) = self.0.lock() {
+            *slot = Some(error.to_string());
+        }
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut slot) = self.0.lock() {
+            *slot = None;
+        }
+    }
+
+    pub fn get(&self) -> Option<String> {
+        self.0.lock().ok().and_then(|slot| slot.clone())
+    }
+}
+
+/// The platform answered a send with an error status. Adapters return it so the caller
+/// can tell a message the platform refused from one that may go through on a retry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpSendError {
+    pub channel_id: String,
+    pub status: u16,
+    pub body: String,
+}
+
+impl HttpSendError {
+    pub async fn from_response(channel_id: &str, resp: reqwest::Response) -> Self {
+        let status = resp.status().as_u16();
+        let body = resp.text().await.unwrap_or_default();
+        Self {
+            channel_id: channel_id.to_string(),
+            status,
+            body,
+        }
+    }
+
+    /// A client error other than a timeout or rate limit: the same message won't be
+    /// accepted if sent again.
+    pub fn is_permanent(&self) -> bool {
+        (400..500).contains(&self.status) && !matches!(self.status, 408 | 425 | 429)
+    }
+}
+
+impl fmt::Display for HttpSendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} send failed ({}): {}",
+            self.channel_id, self.status, self.body
+        )
+    }
+}
+
+impl std::error::Error for HttpSendError {}
+
 #[async_trait]
 pub trait ChannelAdapter: Send + Sync {
     /// Unique channel identifier: "webchat", "telegram", "discord".
@@ -14,7 +80,67 @@ pub trait ChannelAdapter: Send + Sync {
     /// Send a message to a specific user/thread on this platform.
     async fn send(&self, recipient_id: &str, message: OutboundMessage) -> Result<()>;
 
+    /// Set once the adapter has stopped receiving, with why; `start` again to restart it.
+    /// Adapters without a receive loop of their own never stop.
+    fn receive_failure(&self) -> Option<String> {
+        None
+    }
+
+    /// Show a typing indicator to the recipient. Platforms expire these after a few
+    /// seconds, so callers repeat it while a reply is being produced.
+    async fn send_typing(&self, _recipient_id: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Acknowledge that an inbound message was picked up, where the platform has read
+    /// receipts.
+    async fn mark_read(&self, _recipient_id: &str, _message_id: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Show a step of the current run, like a tool starting, while it happens. Channels
+    /// that support this get it instead of periodic progress lines.
+    async fn send_activity(&self, _recipient_id: &str, _activity: RunActivity) -> Result<()> {
+        Ok(())
+    }
+
+    fn supports_activity(&self) -> bool {
+        false
+    }
+
+    /// Send a plain-text status message that `edit_status` can rewrite later, and return
+    /// its id. Only called when `supports_edits`.
+    async fn send_status(&self, _recipient_id: &str, _text: &str) -> Result<String> {
+        anyhow::bail!("{} can't edit messages", self.channel_id())
+    }
+
+    /// Replace the text of a message sent with `send_status`.
+    async fn edit_status(&self, _recipient_id: &str, _message_id: &str, _text: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Whether the platform can edit sent messages. Such channels get a single status
+    /// message, updated as tools run, instead of periodic progress lines.
+    fn supports_edits(&self) -> bool {
+        false
+    }
+
     fn supports_reactions(&self) -> bool {
         false
     }
+
+    /// Whether `send` delivers `OutboundMessage.attachments`, including inline `data:` ones.
+    fn supports_attachments(&self) -> bool {
+        false
+    }
+
+    /// Formatting the platform understands. `send` takes Markdown and renders it.
+    fn dialect(&self) -> Dialect {
+        Dialect::Plain
+    }
+
+    /// Longest message the platform accepts, measured after rendering.
+    fn max_message_chars(&self) -> usize {
+        4000
+    }
 }
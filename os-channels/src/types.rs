@@ -1,3 +1,4 @@
+use base64::Engine as _;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
@@ -5,14 +6,39 @@ use serde::{Deserialize, Serialize};
 pub struct Attachment {
     pub name: String,
     pub content_type: String,
+    /// `http(s)://`, `file://`, or a `data:` URL for generated content.
     pub url: String,
 }
 
+impl Attachment {
+    /// An attachment carrying its content inline as a base64 `data:` URL.
+    pub fn from_bytes(name: &str, content_type: &str, bytes: &[u8]) -> Self {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(bytes);
+        Self {
+            name: name.to_string(),
+            content_type: content_type.to_string(),
+            url: format!("data:{content_type};base64,{encoded}"),
+        }
+    }
+
+    /// Inline content, if `url` is a base64 `data:` URL.
+    pub fn inline_bytes(&self) -> Option<Vec<u8>> {
+        let rest = self.url.strip_prefix("data:")?;
+        let (meta, data) = rest.split_once(',')?;
+        if !meta.ends_with(";base64") {
+            return None;
+        }
+        base64::engine::general_purpose::STANDARD.decode(data).ok()
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum InboundMessageKind {
     Message,
     Reaction,
+    /// New text for an earlier message; `message_id` is the id of the edited message.
+    Edit,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,10 +51,44 @@ pub struct InboundMessage {
     pub is_group: bool,
     pub content: String,
     #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    #[serde(default)]
     pub metadata: serde_json::Value,
     pub received_at: DateTime<Utc>,
 }
 
+impl InboundMessage {
+    /// The message this one replies to, if the adapter recorded one.
+    pub fn quoted(&self) -> Option<QuotedMessage> {
+        serde_json::from_value(self.metadata.get(QuotedMessage::METADATA_KEY)?.clone()).ok()
+    }
+}
+
+/// The message an inbound message replies to (a Telegram reply, a Discord reply, a
+/// webchat client's `reply_to`). Adapters store it under `metadata["reply_to"]`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuotedMessage {
+    pub message_id: String,
+    #[serde(default)]
+    pub sender_id: Option<String>,
+    /// Sent by a bot, normally the assistant itself.
+    #[serde(default)]
+    pub from_bot: bool,
+    /// The quoted text; just the highlighted part when the user quoted an excerpt.
+    #[serde(default)]
+    pub content: Option<String>,
+}
+
+impl QuotedMessage {
+    pub const METADATA_KEY: &'static str = "reply_to";
+
+    pub fn attach(self, metadata: &mut serde_json::Value) {
+        if let (Some(map), Ok(value)) = (metadata.as_object_mut(), serde_json::to_value(self)) {
+            map.insert(Self::METADATA_KEY.to_string(), value);
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OutboundMessage {
     pub content: String,
@@ -37,3 +97,17 @@ pub struct OutboundMessage {
     #[serde(default)]
     pub attachments: Vec<Attachment>,
 }
+
+/// A step of an assistant run, for channels whose clients show it as it happens.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum RunActivity {
+    /// Waiting on the model.
+    Thinking,
+    ToolStarted {
+        tool: String,
+    },
+    ToolFinished {
+        tool: String,
+    },
+}
@@ -1,22 +1,47 @@
+use crate::format::Dialect;
 use crate::traits::ChannelAdapter;
-use crate::types::{InboundMessage, InboundMessageKind, OutboundMessage};
+use crate::types::{Attachment, InboundMessage, InboundMessageKind, OutboundMessage, RunActivity};
 use anyhow::Result;
 use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
-use axum::extract::State;
+use axum::extract::{Query, State};
+use axum::http::header;
 use axum::response::IntoResponse;
 use axum::routing::get;
 use axum::Router;
 use chrono::Utc;
 use dashmap::DashMap;
 use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tokio::sync::mpsc;
 use uuid::Uuid;
 
+const INDEX_HTML: &str = include_str!("../assets/webchat/index.html");
+const WEBCHAT_JS: &str = include_str!("../assets/webchat/webchat.js");
+const WEBCHAT_CSS: &str = include_str!("../assets/webchat/webchat.css");
+
+/// A message from earlier in the conversation, replayed to a browser that reconnects.
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryMessage {
+    /// `user` or `assistant`.
+    pub role: String,
+    pub content: String,
+}
+
+/// Looks up a sender's earlier messages, oldest first.
+type HistoryFn = Arc<dyn Fn(&str) -> Vec<HistoryMessage> + Send + Sync>;
+
 #[derive(Clone)]
 struct WebChatState {
     inbound_tx: Arc<tokio::sync::RwLock<Option<mpsc::Sender<InboundMessage>>>>,
     connections: Arc<DashMap<String, mpsc::UnboundedSender<Message>>>,
+    history: Option<HistoryFn>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ConnectParams {
+    /// The `sender_id` from an earlier `hello`, to continue that conversation.
+    session: Option<String>,
 }
 
 #[derive(Clone)]
@@ -30,48 +55,100 @@ impl WebChatAdapter {
             state: WebChatState {
                 inbound_tx: Arc::new(tokio::sync::RwLock::new(None)),
                 connections: Arc::new(DashMap::new()),
+                history: None,
             },
         }
     }
 
-    /// Router that serves the WebChat WebSocket at `/ws`.
+    /// Where a reconnecting browser's earlier messages come from. Without it, reconnects
+    /// keep the conversation but start with an empty window.
+    pub fn with_history(
+        mut self,
+        history: impl Fn(&str) -> Vec<HistoryMessage> + Send + Sync + 'static,
+    ) -> Self {
+        self.state.history = Some(Arc::new(history));
+        self
+    }
+
+    /// Router that serves the WebChat WebSocket at `/ws` and the browser client at `/chat`.
     pub fn router(self: Arc<Self>) -> Router {
-        Router::new().route("/ws", get(ws_upgrade)).with_state(self)
+        Router::new()
+            .route("/ws", get(ws_upgrade))
+            .route("/chat", get(index))
+            .route("/chat/webchat.js", get(script))
+            .route("/chat/webchat.css", get(style))
+            .with_state(self)
     }
 }
 
+async fn index() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
+        INDEX_HTML,
+    )
+}
+
+async fn script() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/javascript; charset=utf-8")],
+        WEBCHAT_JS,
+    )
+}
+
+async fn style() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/css; charset=utf-8")],
+        WEBCHAT_CSS,
+    )
+}
+
 async fn ws_upgrade(
     State(adapter): State<Arc<WebChatAdapter>>,
+    Query(params): Query<ConnectParams>,
     upgrade: WebSocketUpgrade,
 ) -> impl IntoResponse {
-    upgrade.on_upgrade(move |socket| handle_socket(adapter, socket))
+    // Only ids we could have handed out; a random UUID is as hard to guess as a token.
+    let resumed = params
+        .session
+        .and_then(|s| Uuid::parse_str(&s).ok())
+        .map(|id| id.to_string());
+    upgrade.on_upgrade(move |socket| handle_socket(adapter, socket, resumed))
 }
 
 #[tracing::instrument(level = "info", skip_all)]
-async fn handle_socket(adapter: Arc<WebChatAdapter>, socket: WebSocket) {
-    let sender_id = Uuid::new_v4().to_string();
+async fn handle_socket(adapter: Arc<WebChatAdapter>, socket: WebSocket, resumed: Option<String>) {
+    let is_resumed = resumed.is_some();
+    let sender_id = resumed.unwrap_or_else(|| Uuid::new_v4().to_string());
     let (mut ws_sender, mut ws_receiver) = socket.split();
 
+    // A newer tab on the same conversation takes over its replies.
     let (outbound_tx, mut outbound_rx) = mpsc::unbounded_channel::<Message>();
     adapter
         .state
         .connections
-        .insert(sender_id.clone(), outbound_tx);
+        .insert(sender_id.clone(), outbound_tx.clone());
 
-    let hello = serde_json::json!({ "type": "hello", "sender_id": sender_id });
+    let hello = serde_json::json!({
+        "type": "hello",
+        "sender_id": sender_id,
+        "resumed": is_resumed,
+    });
     let _ = ws_sender
         .send(Message::Text(hello.to_string().into()))
         .await;
+    if let (true, Some(history)) = (is_resumed, adapter.state.history.as_ref()) {
+        let payload = serde_json::json!({ "type": "history", "messages": history(&sender_id) });
+        let _ = ws_sender
+            .send(Message::Text(payload.to_string().into()))
+            .await;
+    }
 
-    let adapter_out = adapter.clone();
-    let sender_id_out = sender_id.clone();
     let outbound_task = tokio::spawn(async move {
         while let Some(msg) = outbound_rx.recv().await {
             if ws_sender.send(msg).await.is_err() {
                 break;
             }
         }
-        adapter_out.state.connections.remove(&sender_id_out);
     });
 
     while let Some(Ok(msg)) = ws_receiver.next().await {
@@ -107,14 +184,27 @@ async fn handle_socket(adapter: Arc<WebChatAdapter>, socket: WebSocket) {
             ),
         };
 
+        let attachments: Vec<Attachment> = parsed
+            .get("attachments")
+            .cloned()
+            .and_then(|v| serde_json::from_value(v).ok())
+            .unwrap_or_default();
+
         let inbound = InboundMessage {
             kind,
-            message_id: Uuid::new_v4().to_string(),
+            // Clients may supply their own id so receipts can be matched up.
+            message_id: parsed
+                .get("id")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| Uuid::new_v4().to_string()),
             channel_id: "webchat".to_string(),
             sender_id: sender_id.clone(),
             thread_id: Some(sender_id.clone()),
             is_group: false,
             content,
+            attachments,
+            // Clients may set `reply_to` (a `QuotedMessage`) when the user replies to a message.
             metadata: parsed,
             received_at: Utc::now(),
         };
@@ -126,7 +216,10 @@ async fn handle_socket(adapter: Arc<WebChatAdapter>, socket: WebSocket) {
     }
 
     outbound_task.abort();
-    adapter.state.connections.remove(&sender_id);
+    adapter
+        .state
+        .connections
+        .remove_if(&sender_id, |_, tx| tx.same_channel(&outbound_tx));
 }
 
 #[async_trait::async_trait]
@@ -147,12 +240,54 @@ impl ChannelAdapter for WebChatAdapter {
         let payload = serde_json::json!({
             "type": "message",
             "content": message.content,
+            "attachments": message.attachments,
         });
         let _ = conn.send(Message::Text(payload.to_string().into()));
         Ok(())
     }
 
+    async fn send_typing(&self, recipient_id: &str) -> Result<()> {
+        if let Some(conn) = self.state.connections.get(recipient_id) {
+            let payload = serde_json::json!({ "type": "typing" });
+            let _ = conn.send(Message::Text(payload.to_string().into()));
+        }
+        Ok(())
+    }
+
+    async fn mark_read(&self, recipient_id: &str, message_id: &str) -> Result<()> {
+        if let Some(conn) = self.state.connections.get(recipient_id) {
+            let payload = serde_json::json!({ "type": "read", "message_id": message_id });
+            let _ = conn.send(Message::Text(payload.to_string().into()));
+        }
+        Ok(())
+    }
+
+    async fn send_activity(&self, recipient_id: &str, activity: RunActivity) -> Result<()> {
+        if let Some(conn) = self.state.connections.get(recipient_id) {
+            let mut payload = serde_json::json!(activity);
+            payload["type"] = "activity".into();
+            let _ = conn.send(Message::Text(payload.to_string().into()));
+        }
+        Ok(())
+    }
+
+    fn supports_activity(&self) -> bool {
+        true
+    }
+
     fn supports_reactions(&self) -> bool {
         true
     }
+
+    fn supports_attachments(&self) -> bool {
+        true
+    }
+
+    fn dialect(&self) -> Dialect {
+        Dialect::Markdown
+    }
+
+    fn max_message_chars(&self) -> usize {
+        64_000
+    }
 }
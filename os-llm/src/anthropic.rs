@@ -1,5 +1,8 @@
 use crate::error::{LlmError, Result};
-use crate::types::{ChatMessage, ChatResponse, Role, StreamChunk, ToolCall, ToolDefinition, Usage};
+use crate::types::{
+    ChatMessage, ChatResponse, ReasoningBlock, Role, SamplingConfig, StreamChunk, ToolCall,
+    ToolDefinition, Usage,
+};
 use bytes::Bytes;
 use futures_util::Stream;
 use futures_util::StreamExt;
@@ -9,12 +12,18 @@ use std::pin::Pin;
 
 const ANTHROPIC_MESSAGES_URL: &str = "https://api.anthropic.com/v1/messages";
 const ANTHROPIC_VERSION: &str = "2023-06-01";
+/// Reply tokens unless the profile sets its own; a thinking budget comes on top.
+const MAX_TOKENS: u32 = 2048;
+/// The API's minimum thinking budget.
+const THINKING_BUDGET_MIN: u32 = 1024;
 
 #[derive(Clone)]
 pub struct AnthropicClient {
     http: reqwest::Client,
     api_key: String,
     model: String,
+    thinking_budget: Option<u32>,
+    sampling: SamplingConfig,
 }
 
 impl AnthropicClient {
@@ -23,23 +32,87 @@ impl AnthropicClient {
             http,
             api_key: api_key.to_string(),
             model: model.to_string(),
+            thinking_budget: None,
+            sampling: SamplingConfig::default(),
         }
     }
 
+    /// Turn on extended thinking with this many tokens to think with.
+    pub fn with_thinking_budget(mut self, budget_tokens: Option<u32>) -> Self {
+        self.thinking_budget = budget_tokens;
+        self
+    }
+
+    pub fn with_sampling(mut self, sampling: SamplingConfig) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    fn request(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolDefinition],
+        stream: bool,
+    ) -> Result<AnthropicRequest> {
+        let mut req = self.request_without_thinking(messages, tools, stream)?;
+        if let Some(budget) = self.thinking_budget {
+            let budget = budget.max(THINKING_BUDGET_MIN);
+            req.max_tokens += budget;
+            req.thinking = Some(serde_json::json!({
+                "type": "enabled",
+                "budget_tokens": budget,
+            }));
+            // Thinking requires the default temperature and a top_p of at least 0.95.
+            req.temperature = None;
+            req.top_p = req.top_p.filter(|p| *p >= 0.95);
+        }
+        Ok(req)
+    }
+
+    fn request_without_thinking(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolDefinition],
+        stream: bool,
+    ) -> Result<AnthropicRequest> {
+        let mut req = AnthropicRequest::new(&self.model, messages, tools, stream)?;
+        req.max_tokens = self.sampling.max_output_tokens.unwrap_or(MAX_TOKENS);
+        req.temperature = self.sampling.temperature;
+        req.top_p = self.sampling.top_p;
+        req.stop_sequences = self.sampling.stop.clone();
+        Ok(req)
+    }
+
     #[tracing::instrument(level = "info", skip_all)]
     pub async fn chat(
         &self,
         messages: &[ChatMessage],
         tools: &[ToolDefinition],
     ) -> Result<ChatResponse> {
-        let req = AnthropicRequest::new(&self.model, messages, tools, false)?;
+        let req = self.request(messages, tools, false)?;
+        self.send(&req).await
+    }
+
+    /// A reply that must be a call to `tool`; its input is the structured result. Sent
+    /// without thinking, which the API does not allow alongside a forced tool.
+    #[tracing::instrument(level = "info", skip_all)]
+    pub async fn chat_forced_tool(
+        &self,
+        messages: &[ChatMessage],
+        tool: &ToolDefinition,
+    ) -> Result<ChatResponse> {
+        let mut req = self.request_without_thinking(messages, std::slice::from_ref(tool), false)?;
+        req.tool_choice = Some(serde_json::json!({ "type": "tool", "name": tool.name }));
+        self.send(&req).await
+    }
 
+    async fn send(&self, req: &AnthropicRequest) -> Result<ChatResponse> {
         let response = self
             .http
             .post(ANTHROPIC_MESSAGES_URL)
             .header("x-api-key", &self.api_key)
             .header("anthropic-version", ANTHROPIC_VERSION)
-            .json(&req)
+            .json(req)
             .send()
             .await?;
 
@@ -61,7 +134,7 @@ impl AnthropicClient {
         messages: &[ChatMessage],
         tools: &[ToolDefinition],
     ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>>> {
-        let req = AnthropicRequest::new(&self.model, messages, tools, true)?;
+        let req = self.request(messages, tools, true)?;
 
         let response = self
             .http
@@ -145,6 +218,8 @@ impl AnthropicClient {
                                         ));
                                     }
                                 }
+                                // Thinking is not streamed to the reader.
+                                AnthropicDelta::Other => {}
                             }
                         }
                         "message_delta" => {
@@ -177,6 +252,16 @@ struct AnthropicRequest {
     #[serde(skip_serializing_if = "Vec::is_empty")]
     tools: Vec<AnthropicTool>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     stream: Option<bool>,
 }
 
@@ -206,10 +291,15 @@ impl AnthropicRequest {
 
         Ok(Self {
             model: model.to_string(),
-            max_tokens: 2048,
+            max_tokens: MAX_TOKENS,
             system,
             messages: out_messages,
             tools: tools.iter().map(to_anthropic_tool).collect(),
+            tool_choice: None,
+            thinking: None,
+            temperature: None,
+            top_p: None,
+            stop_sequences: vec![],
             stream: if stream { Some(true) } else { None },
         })
     }
@@ -251,6 +341,13 @@ enum AnthropicContentBlock {
         tool_use_id: String,
         content: String,
     },
+    Thinking {
+        thinking: String,
+        signature: String,
+    },
+    RedactedThinking {
+        data: String,
+    },
 }
 
 fn to_anthropic_user_message(m: &ChatMessage) -> AnthropicMessage {
@@ -274,7 +371,25 @@ fn to_anthropic_tool_result_message(m: &ChatMessage) -> AnthropicMessage {
 }
 
 fn to_anthropic_assistant_message(m: &ChatMessage) -> Result<AnthropicMessage> {
-    let mut blocks = Vec::new();
+    // The API wants thinking back, unchanged, ahead of the tool calls it led to. Reasoning
+    // from other providers has no signature and can't be sent.
+    let mut blocks: Vec<AnthropicContentBlock> = m
+        .reasoning
+        .iter()
+        .filter_map(|block| match block {
+            ReasoningBlock::Thinking {
+                thinking,
+                signature,
+            } if !signature.is_empty() => Some(AnthropicContentBlock::Thinking {
+                thinking: thinking.clone(),
+                signature: signature.clone(),
+            }),
+            ReasoningBlock::Thinking { .. } => None,
+            ReasoningBlock::Redacted { data } => {
+                Some(AnthropicContentBlock::RedactedThinking { data: data.clone() })
+            }
+        })
+        .collect();
     if !m.content.trim().is_empty() {
         blocks.push(AnthropicContentBlock::Text {
             text: m.content.clone(),
@@ -321,6 +436,7 @@ impl TryFrom<AnthropicResponse> for ChatResponse {
     fn try_from(v: AnthropicResponse) -> Result<Self> {
         let mut content = String::new();
         let mut tool_calls = Vec::new();
+        let mut reasoning = Vec::new();
 
         for block in v.content {
             match block {
@@ -333,6 +449,16 @@ impl TryFrom<AnthropicResponse> for ChatResponse {
                     });
                 }
                 AnthropicContentBlock::ToolResult { .. } => {}
+                AnthropicContentBlock::Thinking {
+                    thinking,
+                    signature,
+                } => reasoning.push(ReasoningBlock::Thinking {
+                    thinking,
+                    signature,
+                }),
+                AnthropicContentBlock::RedactedThinking { data } => {
+                    reasoning.push(ReasoningBlock::Redacted { data })
+                }
             }
         }
 
@@ -342,10 +468,12 @@ impl TryFrom<AnthropicResponse> for ChatResponse {
                 content,
                 tool_calls,
                 tool_call_id: None,
+                reasoning,
             },
             usage: Usage {
                 prompt_tokens: v.usage.input_tokens as u32,
                 completion_tokens: v.usage.output_tokens as u32,
+                reasoning_tokens: 0,
             },
             finish_reason: v.stop_reason,
         })
@@ -414,10 +542,7 @@ struct AnthropicStreamState {
 impl AnthropicStreamState {
     fn new() -> Self {
         Self {
-            usage: Usage {
-                prompt_tokens: 0,
-                completion_tokens: 0,
-            },
+            usage: Usage::default(),
             tool_started: HashMap::new(),
         }
     }
@@ -446,8 +571,14 @@ struct AnthropicContentBlockDelta {
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 enum AnthropicDelta {
-    TextDelta { text: String },
-    InputJsonDelta { partial_json: String },
+    TextDelta {
+        text: String,
+    },
+    InputJsonDelta {
+        partial_json: String,
+    },
+    #[serde(other)]
+    Other,
 }
 
 #[derive(Debug, Deserialize)]
@@ -0,0 +1,115 @@
+//! Azure OpenAI: the OpenAI chat API served from a deployment in your own Azure resource.
+
+use crate::error::{LlmError, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+const AD_SCOPE: &str = "https://cognitiveservices.azure.com/.default";
+/// Tokens are replaced this long before they expire.
+const AD_TOKEN_MARGIN: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Default)]
+pub struct AzureConfig {
+    /// `https://<resource>.openai.azure.com`.
+    pub endpoint: String,
+    pub api_version: String,
+    /// Model name to deployment name. A model not listed goes to the deployment of the
+    /// same name.
+    pub deployments: HashMap<String, String>,
+    /// Microsoft Entra ID (Azure AD) app credentials. When set they are used instead of
+    /// the api key.
+    pub ad: Option<AzureAdCredentials>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AzureAdCredentials {
+    pub tenant_id: String,
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+impl AzureConfig {
+    pub(crate) fn chat_url(&self, model: &str) -> String {
+        let deployment = self.deployments.get(model).map_or(model, String::as_str);
+        format!(
+            "{}/openai/deployments/{deployment}/chat/completions?api-version={}",
+            self.endpoint.trim_end_matches('/'),
+            self.api_version
+        )
+    }
+}
+
+/// Entra ID access tokens from the client credentials grant, reused until shortly before
+/// they expire. Clones share the cache.
+#[derive(Clone, Default)]
+pub(crate) struct AdTokens {
+    cached: Arc<Mutex<Option<(String, Instant)>>>,
+}
+
+impl AdTokens {
+    pub async fn get(&self, http: &reqwest::Client, creds: &AzureAdCredentials) -> Result<String> {
+        let mut cached = self.cached.lock().await;
+        if let Some((token, expires)) = cached.as_ref() {
+            if Instant::now() + AD_TOKEN_MARGIN < *expires {
+                return Ok(token.clone());
+            }
+        }
+        let url = format!(
+            "https://login.microsoftonline.com/{}/oauth2/v2.0/token",
+            creds.tenant_id
+        );
+        let response = http
+            .post(url)
+            .form(&[
+                ("grant_type", "client_credentials"),
+                ("client_id", creds.client_id.as_str()),
+                ("client_secret", creds.client_secret.as_str()),
+                ("scope", AD_SCOPE),
+            ])
+            .send()
+            .await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(LlmError::Http(format!(
+                "azure ad token status={status} body={body}"
+            )));
+        }
+        let parsed: AdTokenResponse = serde_json::from_str(&body)?;
+        let expires = Instant::now() + Duration::from_secs(parsed.expires_in);
+        *cached = Some((parsed.access_token.clone(), expires));
+        Ok(parsed.access_token)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AdTokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn models_route_to_their_deployment() {
+        let cfg = AzureConfig {
+            endpoint: "https://acme.openai.azure.com/".to_string(),
+            api_version: "2024-10-21".to_string(),
+            deployments: HashMap::from([("gpt-4o".to_string(), "prod-4o".to_string())]),
+            ad: None,
+        };
+        assert_eq!(
+            cfg.chat_url("gpt-4o"),
+            "https://acme.openai.azure.com/openai/deployments/prod-4o/chat/completions?api-version=2024-10-21"
+        );
+        assert_eq!(
+            cfg.chat_url("gpt-4o-mini"),
+            "https://acme.openai.azure.com/openai/deployments/gpt-4o-mini/chat/completions?api-version=2024-10-21"
+        );
+    }
+}
@@ -1,16 +1,45 @@
 use crate::anthropic::AnthropicClient;
-use crate::error::Result;
-use crate::openai::OpenAiClient;
-use crate::types::{ChatMessage, ChatResponse, StreamChunk, ToolDefinition};
+use crate::azure::{AdTokens, AzureConfig};
+use crate::embeddings::EmbeddingsClient;
+use crate::error::{LlmError, Result};
+use crate::openai::{OpenAiAuth, OpenAiClient};
+use crate::openrouter::{self, OpenRouterRouting};
+use crate::recording::{Exchange, ExchangeOutcome, ExchangeRequest, Recorder, Replay};
+use crate::structured;
+use crate::types::{
+    ChatMessage, ChatResponse, EmbeddingConfig, ReasoningConfig, SamplingConfig, StreamChunk,
+    StructuredResponse, ToolDefinition,
+};
 use futures_util::Stream;
 use futures_util::StreamExt;
 use std::collections::HashMap;
 use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+const REASONING_REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Provider {
     OpenAI,
     Anthropic,
+    OpenRouter,
+}
+
+impl Provider {
+    /// The provider serving `model`: `claude-*` is Anthropic, `openrouter/*` OpenRouter,
+    /// anything else OpenAI-compatible.
+    pub fn for_model(model: &str) -> Self {
+        let m = model.to_ascii_lowercase();
+        if m.starts_with("claude-") {
+            return Self::Anthropic;
+        }
+        if m.starts_with(openrouter::MODEL_PREFIX) {
+            return Self::OpenRouter;
+        }
+        Self::OpenAI
+    }
 }
 
 #[derive(Clone)]
@@ -19,27 +48,93 @@ pub struct LlmClient {
     api_key: String,
     model: String,
     client: reqwest::Client,
+    embedding: EmbeddingConfig,
+    embedding_api_key: Option<String>,
+    reasoning: ReasoningConfig,
+    sampling: SamplingConfig,
+    azure: Option<AzureConfig>,
+    ad_tokens: AdTokens,
+    routing: OpenRouterRouting,
+    recorder: Option<Arc<Recorder>>,
+    replay: Option<Arc<Replay>>,
 }
 
 impl LlmClient {
     #[tracing::instrument(level = "debug", skip_all)]
     pub fn new(api_key: &str, model: &str) -> Self {
-        let provider = detect_provider(model);
-        let client = reqwest::Client::builder()
-            .timeout(std::time::Duration::from_secs(60))
-            .build()
-            .unwrap_or_else(|e| {
-                tracing::warn!(%e, "reqwest client build failed; falling back to default client");
-                reqwest::Client::new()
-            });
+        let provider = Provider::for_model(model);
         Self {
             provider,
             api_key: api_key.to_string(),
             model: model.to_string(),
-            client,
+            client: http_client(REQUEST_TIMEOUT),
+            embedding: EmbeddingConfig::default(),
+            embedding_api_key: None,
+            reasoning: ReasoningConfig::default(),
+            sampling: SamplingConfig::default(),
+            azure: None,
+            ad_tokens: AdTokens::default(),
+            routing: OpenRouterRouting::default(),
+            recorder: None,
+            replay: None,
         }
     }
 
+    /// A client for tests that answers from `replay` instead of calling a provider.
+    pub fn replay(model: &str, replay: Arc<Replay>) -> Self {
+        let mut client = Self::new("", model);
+        client.replay = Some(replay);
+        client
+    }
+
+    /// Append every chat and structured exchange to `recorder`.
+    pub fn with_recorder(mut self, recorder: Arc<Recorder>) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Reasoning effort (OpenAI) or thinking budget (Anthropic) for chat requests.
+    /// Reasoning replies take longer, so requests get a longer timeout.
+    pub fn with_reasoning(mut self, cfg: ReasoningConfig) -> Self {
+        if cfg != ReasoningConfig::default() {
+            self.client = http_client(REASONING_REQUEST_TIMEOUT);
+        }
+        self.reasoning = cfg;
+        self
+    }
+
+    /// Send OpenAI chat requests to an Azure OpenAI resource. The api key, if any, is
+    /// the resource's key.
+    pub fn with_azure(mut self, cfg: AzureConfig) -> Self {
+        self.azure = Some(cfg);
+        self
+    }
+
+    /// Provider preferences for `openrouter/` models; ignored by other providers.
+    pub fn with_routing(mut self, routing: OpenRouterRouting) -> Self {
+        self.routing = routing;
+        self
+    }
+
+    /// Temperature, top_p, reply length and stop sequences for chat requests.
+    pub fn with_sampling(mut self, cfg: SamplingConfig) -> Self {
+        self.sampling = cfg;
+        self
+    }
+
+    /// Configure the embedding model used by [`LlmClient::embed`].
+    pub fn with_embeddings(mut self, cfg: EmbeddingConfig) -> Self {
+        self.embedding = cfg;
+        self
+    }
+
+    /// Key for the embeddings endpoint when it differs from the chat key (e.g. an Anthropic
+    /// chat model paired with OpenAI embeddings). Pass an empty string for keyless servers.
+    pub fn with_embedding_api_key(mut self, api_key: &str) -> Self {
+        self.embedding_api_key = Some(api_key.to_string());
+        self
+    }
+
     pub fn provider(&self) -> Provider {
         self.provider
     }
@@ -53,10 +148,33 @@ impl LlmClient {
         &self,
         messages: &[ChatMessage],
         tools: &[ToolDefinition],
+    ) -> Result<ChatResponse> {
+        let result = match self.replay.as_ref() {
+            Some(replay) => replay.next_chat(),
+            None => self.provider_chat(messages, tools).await,
+        };
+        if let Some(recorder) = self.recorder.as_ref() {
+            let request = ExchangeRequest::Chat {
+                messages: messages.to_vec(),
+                tools: tools.to_vec(),
+            };
+            let outcome = match &result {
+                Ok(response) => ExchangeOutcome::Chat(response.clone()),
+                Err(e) => ExchangeOutcome::Error(e.clone()),
+            };
+            self.record(recorder, request, outcome).await;
+        }
+        result
+    }
+
+    async fn provider_chat(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolDefinition],
     ) -> Result<ChatResponse> {
         match self.provider {
-            Provider::OpenAI => {
-                let c = OpenAiClient::new(self.client.clone(), &self.api_key, &self.model);
+            Provider::OpenAI | Provider::OpenRouter => {
+                let c = self.openai().await?;
                 let (tools_sanitized, forward, reverse) = sanitize_tools_for_openai(tools);
                 let messages_sanitized = sanitize_messages_for_openai(messages, &forward);
                 let mut resp = c.chat(&messages_sanitized, &tools_sanitized).await?;
@@ -64,21 +182,101 @@ impl LlmClient {
                 Ok(resp)
             }
             Provider::Anthropic => {
-                let c = AnthropicClient::new(self.client.clone(), &self.api_key, &self.model);
+                let c = self.anthropic();
                 c.chat(messages, tools).await
             }
         }
     }
 
+    /// A reply matching `schema` (JSON Schema), asked for natively: OpenAI's
+    /// `response_format`, or for Anthropic a tool named `name` the model must call. The
+    /// reply, replayed ones included, is validated before it is returned; a mismatch is a
+    /// `ResponseFormat` error.
+    #[tracing::instrument(level = "info", skip_all, fields(name = %name))]
+    pub async fn chat_structured(
+        &self,
+        messages: &[ChatMessage],
+        name: &str,
+        schema: &serde_json::Value,
+    ) -> Result<StructuredResponse> {
+        // A bad schema is the caller's mistake; don't spend a request finding out.
+        structured::compile(schema)?;
+        let result = match self.replay.as_ref() {
+            Some(replay) => replay.next_structured().and_then(|response| {
+                structured::validate(schema, &response.value)?;
+                Ok(response)
+            }),
+            None => self.provider_chat_structured(messages, name, schema).await,
+        };
+        if let Some(recorder) = self.recorder.as_ref() {
+            let request = ExchangeRequest::Structured {
+                messages: messages.to_vec(),
+                name: name.to_string(),
+                schema: schema.clone(),
+            };
+            let outcome = match &result {
+                Ok(response) => ExchangeOutcome::Structured(response.clone()),
+                Err(e) => ExchangeOutcome::Error(e.clone()),
+            };
+            self.record(recorder, request, outcome).await;
+        }
+        result
+    }
+
+    async fn provider_chat_structured(
+        &self,
+        messages: &[ChatMessage],
+        name: &str,
+        schema: &serde_json::Value,
+    ) -> Result<StructuredResponse> {
+        let name = sanitize_openai_tool_name(name);
+        match self.provider {
+            Provider::OpenAI | Provider::OpenRouter => {
+                let c = self.openai().await?;
+                let resp = c.chat_json(messages, &name, schema).await?;
+                Ok(StructuredResponse {
+                    value: structured::parse_and_validate(&resp.message.content, schema)?,
+                    usage: resp.usage,
+                })
+            }
+            Provider::Anthropic => {
+                let c = self.anthropic();
+                let tool = ToolDefinition {
+                    name: name.clone(),
+                    description: "Give the result in this structure.".to_string(),
+                    parameters: schema.clone(),
+                };
+                let resp = c.chat_forced_tool(messages, &tool).await?;
+                let call = resp
+                    .message
+                    .tool_calls
+                    .iter()
+                    .find(|c| c.name == name)
+                    .ok_or_else(|| {
+                        LlmError::ResponseFormat(format!("anthropic reply has no {name} call"))
+                    })?;
+                Ok(StructuredResponse {
+                    value: structured::parse_and_validate(&call.arguments, schema)?,
+                    usage: resp.usage,
+                })
+            }
+        }
+    }
+
     #[tracing::instrument(level = "info", skip_all)]
     pub async fn chat_stream(
         &self,
         messages: &[ChatMessage],
         tools: &[ToolDefinition],
     ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>>> {
+        if self.replay.is_some() {
+            return Err(LlmError::InvalidInput(
+                "replay does not support streaming".to_string(),
+            ));
+        }
         match self.provider {
-            Provider::OpenAI => {
-                let c = OpenAiClient::new(self.client.clone(), &self.api_key, &self.model);
+            Provider::OpenAI | Provider::OpenRouter => {
+                let c = self.openai().await?;
                 let (tools_sanitized, forward, reverse) = sanitize_tools_for_openai(tools);
                 let messages_sanitized = sanitize_messages_for_openai(messages, &forward);
                 let stream = c
@@ -93,19 +291,93 @@ impl LlmClient {
                 })))
             }
             Provider::Anthropic => {
-                let c = AnthropicClient::new(self.client.clone(), &self.api_key, &self.model);
+                let c = self.anthropic();
                 c.chat_stream(messages, tools).await
             }
         }
     }
-}
 
-fn detect_provider(model: &str) -> Provider {
-    let m = model.to_ascii_lowercase();
-    if m.starts_with("claude-") {
-        return Provider::Anthropic;
+    async fn record(
+        &self,
+        recorder: &Recorder,
+        request: ExchangeRequest,
+        outcome: ExchangeOutcome,
+    ) {
+        let exchange = Exchange {
+            model: self.model.clone(),
+            request,
+            outcome,
+        };
+        recorder.record(&exchange).await;
+    }
+
+    async fn openai(&self) -> Result<OpenAiClient> {
+        if self.provider == Provider::OpenRouter {
+            let model = &self.model[openrouter::MODEL_PREFIX.len()..];
+            return Ok(OpenAiClient::new(self.client.clone(), &self.api_key, model)
+                .with_endpoint(
+                    openrouter::OPENROUTER_CHAT_COMPLETIONS_URL.to_string(),
+                    OpenAiAuth::Bearer(self.api_key.clone()),
+                )
+                .with_headers(openrouter::ATTRIBUTION_HEADERS)
+                .with_routing((!self.routing.is_empty()).then(|| self.routing.clone()))
+                .with_reasoning_effort(self.reasoning.effort.clone())
+                .with_sampling(self.sampling.clone()));
+        }
+        let mut c = OpenAiClient::new(self.client.clone(), &self.api_key, &self.model)
+            .with_reasoning_effort(self.reasoning.effort.clone())
+            .with_sampling(self.sampling.clone());
+        if let Some(azure) = self.azure.as_ref() {
+            let auth = match azure.ad.as_ref() {
+                Some(creds) => OpenAiAuth::Bearer(self.ad_tokens.get(&self.client, creds).await?),
+                None => OpenAiAuth::ApiKey(self.api_key.clone()),
+            };
+            c = c.with_endpoint(azure.chat_url(&self.model), auth);
+        }
+        Ok(c)
+    }
+
+    fn anthropic(&self) -> AnthropicClient {
+        AnthropicClient::new(self.client.clone(), &self.api_key, &self.model)
+            .with_thinking_budget(self.reasoning.budget_tokens)
+            .with_sampling(self.sampling.clone())
+    }
+
+    /// Embed `inputs`, one vector per input, in order. Inputs are sent in batches of
+    /// `EmbeddingConfig::batch_size`.
+    #[tracing::instrument(level = "info", skip_all)]
+    pub async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        if inputs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let api_key = match (&self.embedding_api_key, self.provider) {
+            (Some(key), _) => key.as_str(),
+            (None, Provider::OpenAI) => self.api_key.as_str(),
+            (None, Provider::Anthropic | Provider::OpenRouter)
+                if self.embedding.base_url.is_some() =>
+            {
+                ""
+            }
+            (None, Provider::Anthropic | Provider::OpenRouter) => {
+                return Err(LlmError::InvalidInput(format!(
+                    "{} has no embeddings API; configure an embeddings base_url or key",
+                    format!("{:?}", self.provider).to_ascii_lowercase()
+                )))
+            }
+        };
+        let c = EmbeddingsClient::new(self.client.clone(), api_key, self.embedding.clone());
+        c.embed(inputs).await
     }
-    Provider::OpenAI
+}
+
+fn http_client(timeout: Duration) -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(timeout)
+        .build()
+        .unwrap_or_else(|e| {
+            tracing::warn!(%e, "reqwest client build failed; falling back to default client");
+            reqwest::Client::new()
+        })
 }
 
 fn sanitize_tools_for_openai(
@@ -189,6 +461,19 @@ mod tests {
     use crate::types::{ChatMessage, Role, ToolCall, ToolDefinition};
     use serde_json::json;
 
+    #[test]
+    fn provider_follows_model_name() {
+        assert_eq!(
+            Provider::for_model("claude-sonnet-4-5"),
+            Provider::Anthropic
+        );
+        assert_eq!(Provider::for_model("gpt-4o"), Provider::OpenAI);
+        assert_eq!(
+            Provider::for_model("openrouter/anthropic/claude-sonnet-4"),
+            Provider::OpenRouter
+        );
+    }
+
     #[test]
     fn openai_tool_names_are_sanitized_and_unique() {
         let tools = vec![
@@ -247,6 +532,7 @@ mod tests {
                 arguments: "{}".to_string(),
             }],
             tool_call_id: None,
+            reasoning: vec![],
         }];
 
         let sanitized = sanitize_messages_for_openai(&messages, &forward);
@@ -0,0 +1,141 @@
+use crate::error::{LlmError, Result};
+use crate::types::EmbeddingConfig;
+use serde::{Deserialize, Serialize};
+
+const OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+
+/// Client for the OpenAI `/embeddings` endpoint, or any server that speaks the same protocol.
+#[derive(Clone)]
+pub struct EmbeddingsClient {
+    http: reqwest::Client,
+    api_key: String,
+    cfg: EmbeddingConfig,
+}
+
+impl EmbeddingsClient {
+    pub fn new(http: reqwest::Client, api_key: &str, cfg: EmbeddingConfig) -> Self {
+        Self {
+            http,
+            api_key: api_key.to_string(),
+            cfg,
+        }
+    }
+
+    #[tracing::instrument(level = "info", skip_all, fields(inputs = inputs.len()))]
+    pub async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        if self.cfg.batch_size == 0 {
+            return Err(LlmError::InvalidInput(
+                "embedding batch_size must be > 0".to_string(),
+            ));
+        }
+        let mut out = Vec::with_capacity(inputs.len());
+        for batch in inputs.chunks(self.cfg.batch_size) {
+            out.extend(self.embed_batch(batch).await?);
+        }
+        Ok(out)
+    }
+
+    async fn embed_batch(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        let base = self
+            .cfg
+            .base_url
+            .as_deref()
+            .unwrap_or(OPENAI_BASE_URL)
+            .trim_end_matches('/');
+        let req = EmbeddingRequest {
+            model: &self.cfg.model,
+            input: inputs,
+            dimensions: self.cfg.dimensions,
+        };
+
+        let mut builder = self.http.post(format!("{base}/embeddings")).json(&req);
+        // Local servers usually don't need a key.
+        if !self.api_key.is_empty() {
+            builder = builder.bearer_auth(&self.api_key);
+        }
+        let response = builder.send().await?;
+
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(LlmError::Http(format!(
+                "embeddings status={status} body={body}"
+            )));
+        }
+
+        let mut parsed: EmbeddingResponse = serde_json::from_str(&body)?;
+        if parsed.data.len() != inputs.len() {
+            return Err(LlmError::ResponseFormat(format!(
+                "expected {} embeddings, got {}",
+                inputs.len(),
+                parsed.data.len()
+            )));
+        }
+        parsed.data.sort_by_key(|d| d.index);
+        parsed
+            .data
+            .into_iter()
+            .map(|d| fit_dimensions(d.embedding, self.cfg.dimensions))
+            .collect()
+    }
+}
+
+/// Truncate to `dimensions` and re-normalize (valid for Matryoshka-style models).
+fn fit_dimensions(mut v: Vec<f32>, dimensions: Option<usize>) -> Result<Vec<f32>> {
+    let Some(d) = dimensions else {
+        return Ok(v);
+    };
+    if v.len() < d {
+        return Err(LlmError::ResponseFormat(format!(
+            "embedding has {} dimensions, expected {d}",
+            v.len()
+        )));
+    }
+    if v.len() > d {
+        v.truncate(d);
+        let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            v.iter_mut().for_each(|x| *x /= norm);
+        }
+    }
+    Ok(v)
+}
+
+#[derive(Debug, Serialize)]
+struct EmbeddingRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dimensions: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+    #[serde(default)]
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn longer_vectors_are_truncated_and_normalized() {
+        let v = fit_dimensions(vec![3.0, 4.0, 12.0], Some(2)).unwrap();
+        assert_eq!(v.len(), 2);
+        assert!((v[0] - 0.6).abs() < 1e-6);
+        assert!((v[1] - 0.8).abs() < 1e-6);
+
+        assert_eq!(
+            fit_dimensions(vec![1.0, 2.0], None).unwrap(),
+            vec![1.0, 2.0]
+        );
+        assert!(fit_dimensions(vec![1.0], Some(2)).is_err());
+    }
+}
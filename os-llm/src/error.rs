@@ -1,8 +1,10 @@
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 pub type Result<T> = std::result::Result<T, LlmError>;
 
-#[derive(Debug, Error)]
+#[derive(Debug, Clone, Error, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum LlmError {
     #[error("invalid input: {0}")]
     InvalidInput(String),
@@ -4,11 +4,24 @@
 //! See: specifications/openshell/implementation_v0_1_0.md
 
 mod anthropic;
+mod azure;
 mod client;
+mod embeddings;
 mod error;
 mod openai;
+mod openrouter;
+mod reasoning;
+mod recording;
+mod structured;
 mod types;
 
+pub use azure::{AzureAdCredentials, AzureConfig};
 pub use client::{LlmClient, Provider};
 pub use error::{LlmError, Result};
-pub use types::{ChatMessage, ChatResponse, Role, StreamChunk, ToolCall, ToolDefinition, Usage};
+pub use openrouter::{OpenRouterMaxPrice, OpenRouterRouting};
+pub use reasoning::strip_thinking_tags;
+pub use recording::{Exchange, ExchangeOutcome, ExchangeRequest, Recorder, Replay};
+pub use types::{
+    ChatMessage, ChatResponse, EmbeddingConfig, ReasoningBlock, ReasoningConfig, Role,
+    SamplingConfig, StreamChunk, StructuredResponse, ToolCall, ToolDefinition, Usage,
+};
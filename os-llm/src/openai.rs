@@ -1,5 +1,10 @@
 use crate::error::{LlmError, Result};
-use crate::types::{ChatMessage, ChatResponse, Role, StreamChunk, ToolCall, ToolDefinition, Usage};
+use crate::openrouter::OpenRouterRouting;
+use crate::reasoning::strip_thinking_tags;
+use crate::types::{
+    ChatMessage, ChatResponse, ReasoningBlock, Role, SamplingConfig, StreamChunk, ToolCall,
+    ToolDefinition, Usage,
+};
 use bytes::Bytes;
 use futures_util::Stream;
 use futures_util::StreamExt;
@@ -9,37 +14,123 @@ use std::pin::Pin;
 
 const OPENAI_CHAT_COMPLETIONS_URL: &str = "https://api.openai.com/v1/chat/completions";
 
+/// A bearer token for OpenAI and Entra ID, the `api-key` header for Azure keys.
+#[derive(Clone)]
+pub enum OpenAiAuth {
+    Bearer(String),
+    ApiKey(String),
+}
+
 #[derive(Clone)]
 pub struct OpenAiClient {
     http: reqwest::Client,
-    api_key: String,
+    url: String,
+    auth: OpenAiAuth,
+    headers: &'static [(&'static str, &'static str)],
     model: String,
+    reasoning_effort: Option<String>,
+    sampling: SamplingConfig,
+    routing: Option<OpenRouterRouting>,
 }
 
 impl OpenAiClient {
     pub fn new(http: reqwest::Client, api_key: &str, model: &str) -> Self {
         Self {
             http,
-            api_key: api_key.to_string(),
+            url: OPENAI_CHAT_COMPLETIONS_URL.to_string(),
+            auth: OpenAiAuth::Bearer(api_key.to_string()),
+            headers: &[],
             model: model.to_string(),
+            reasoning_effort: None,
+            sampling: SamplingConfig::default(),
+            routing: None,
         }
     }
 
+    /// Send to `url` instead of api.openai.com, e.g. an Azure OpenAI deployment.
+    pub fn with_endpoint(mut self, url: String, auth: OpenAiAuth) -> Self {
+        self.url = url;
+        self.auth = auth;
+        self
+    }
+
+    /// Extra headers sent with every request.
+    pub fn with_headers(mut self, headers: &'static [(&'static str, &'static str)]) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// OpenRouter provider preferences.
+    pub fn with_routing(mut self, routing: Option<OpenRouterRouting>) -> Self {
+        self.routing = routing;
+        self
+    }
+
+    /// `reasoning_effort` for o-series and other reasoning models.
+    pub fn with_reasoning_effort(mut self, effort: Option<String>) -> Self {
+        self.reasoning_effort = effort;
+        self
+    }
+
+    pub fn with_sampling(mut self, sampling: SamplingConfig) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
     #[tracing::instrument(level = "info", skip_all)]
     pub async fn chat(
         &self,
         messages: &[ChatMessage],
         tools: &[ToolDefinition],
     ) -> Result<ChatResponse> {
-        let req = OpenAiChatRequest::new(&self.model, messages, tools, false);
+        let req = self.request(messages, tools, false);
+        self.send(&req).await
+    }
 
-        let response = self
-            .http
-            .post(OPENAI_CHAT_COMPLETIONS_URL)
-            .bearer_auth(&self.api_key)
-            .json(&req)
-            .send()
-            .await?;
+    /// A reply constrained to `schema` through `response_format`.
+    #[tracing::instrument(level = "info", skip_all)]
+    pub async fn chat_json(
+        &self,
+        messages: &[ChatMessage],
+        name: &str,
+        schema: &serde_json::Value,
+    ) -> Result<ChatResponse> {
+        let mut req = self.request(messages, &[], false);
+        req.response_format = Some(json_schema_format(name, schema));
+        self.send(&req).await
+    }
+
+    fn request(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolDefinition],
+        stream: bool,
+    ) -> OpenAiChatRequest {
+        let mut req = OpenAiChatRequest::new(&self.model, messages, tools, stream);
+        req.reasoning_effort = self.reasoning_effort.clone();
+        req.temperature = self.sampling.temperature;
+        req.top_p = self.sampling.top_p;
+        req.max_completion_tokens = self.sampling.max_output_tokens;
+        req.stop = self.sampling.stop.clone();
+        req.provider = self.routing.clone();
+        req
+    }
+
+    fn post(&self) -> reqwest::RequestBuilder {
+        let builder = self
+            .headers
+            .iter()
+            .fold(self.http.post(&self.url), |b, (name, value)| {
+                b.header(*name, *value)
+            });
+        match &self.auth {
+            OpenAiAuth::Bearer(token) => builder.bearer_auth(token),
+            OpenAiAuth::ApiKey(key) => builder.header("api-key", key),
+        }
+    }
+
+    async fn send(&self, req: &OpenAiChatRequest) -> Result<ChatResponse> {
+        let response = self.post().json(req).send().await?;
 
         let status = response.status();
         let body = response.text().await?;
@@ -59,15 +150,9 @@ impl OpenAiClient {
         messages: &[ChatMessage],
         tools: &[ToolDefinition],
     ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>>> {
-        let req = OpenAiChatRequest::new(&self.model, messages, tools, true);
+        let req = self.request(messages, tools, true);
 
-        let response = self
-            .http
-            .post(OPENAI_CHAT_COMPLETIONS_URL)
-            .bearer_auth(&self.api_key)
-            .json(&req)
-            .send()
-            .await?;
+        let response = self.post().json(&req).send().await?;
 
         let status = response.status();
         if !status.is_success() {
@@ -87,10 +172,7 @@ impl OpenAiClient {
                     match next {
                         Ok(SseEvent::Data(data)) => {
                             if data.trim() == "[DONE]" {
-                                let usage = state.usage.clone().unwrap_or(Usage {
-                                    prompt_tokens: 0,
-                                    completion_tokens: 0,
-                                });
+                                let usage = state.usage.clone().unwrap_or_default();
                                 return Some((Ok(StreamChunk::Done { usage }), (sse, state)));
                             }
 
@@ -111,6 +193,10 @@ impl OpenAiClient {
                                 state.usage = Some(Usage {
                                     prompt_tokens: u.prompt_tokens.unwrap_or(0) as u32,
                                     completion_tokens: u.completion_tokens.unwrap_or(0) as u32,
+                                    reasoning_tokens: u
+                                        .completion_tokens_details
+                                        .as_ref()
+                                        .map_or(0, |d| d.reasoning_tokens),
                                 });
                             }
 
@@ -191,6 +277,30 @@ struct OpenAiChatRequest {
     stream: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     stream_options: Option<OpenAiStreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning_effort: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    /// `max_tokens` is deprecated, and reasoning models reject it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_completion_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    provider: Option<OpenRouterRouting>,
+}
+
+/// Not `strict`: strict mode rejects schemas with optional properties, and the reply is
+/// validated against the schema afterwards anyway.
+fn json_schema_format(name: &str, schema: &serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
+        "type": "json_schema",
+        "json_schema": { "name": name, "schema": schema, "strict": false },
+    })
 }
 
 #[derive(Debug, Serialize)]
@@ -207,6 +317,13 @@ impl OpenAiChatRequest {
             tool_choice: None,
             stream: None,
             stream_options: None,
+            response_format: None,
+            reasoning_effort: None,
+            temperature: None,
+            top_p: None,
+            max_completion_tokens: None,
+            stop: vec![],
+            provider: None,
         };
 
         if !out.tools.is_empty() {
@@ -316,6 +433,9 @@ struct OpenAiChoice {
 struct OpenAiChoiceMessage {
     #[serde(default)]
     content: Option<String>,
+    /// DeepSeek's API and some servers in front of open models.
+    #[serde(default)]
+    reasoning_content: Option<String>,
     #[serde(default)]
     tool_calls: Vec<OpenAiChoiceToolCall>,
 }
@@ -335,12 +455,20 @@ struct OpenAiChoiceToolCallFunction {
     arguments: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 struct OpenAiUsage {
     #[serde(default)]
     prompt_tokens: u32,
     #[serde(default)]
     completion_tokens: u32,
+    #[serde(default)]
+    completion_tokens_details: Option<OpenAiCompletionTokensDetails>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct OpenAiCompletionTokensDetails {
+    #[serde(default)]
+    reasoning_tokens: u32,
 }
 
 impl TryFrom<OpenAiChatResponse> for ChatResponse {
@@ -351,10 +479,7 @@ impl TryFrom<OpenAiChatResponse> for ChatResponse {
             LlmError::ResponseFormat("openai response missing choices".to_string())
         })?;
 
-        let usage = v.usage.unwrap_or(OpenAiUsage {
-            prompt_tokens: 0,
-            completion_tokens: 0,
-        });
+        let usage = v.usage.unwrap_or_default();
 
         let tool_calls = choice
             .message
@@ -367,16 +492,33 @@ impl TryFrom<OpenAiChatResponse> for ChatResponse {
             })
             .collect();
 
+        let (content, tagged) = strip_thinking_tags(&choice.message.content.unwrap_or_default());
+        let reasoning = choice
+            .message
+            .reasoning_content
+            .filter(|t| !t.trim().is_empty())
+            .into_iter()
+            .chain(tagged)
+            .map(|thinking| ReasoningBlock::Thinking {
+                thinking,
+                signature: String::new(),
+            })
+            .collect();
+
         Ok(ChatResponse {
             message: ChatMessage {
                 role: Role::Assistant,
-                content: choice.message.content.unwrap_or_default(),
+                content,
                 tool_calls,
                 tool_call_id: None,
+                reasoning,
             },
             usage: Usage {
                 prompt_tokens: usage.prompt_tokens,
                 completion_tokens: usage.completion_tokens,
+                reasoning_tokens: usage
+                    .completion_tokens_details
+                    .map_or(0, |d| d.reasoning_tokens),
             },
             finish_reason: choice
                 .finish_reason
@@ -445,6 +587,8 @@ struct OpenAiStreamUsage {
     prompt_tokens: Option<u64>,
     #[serde(default)]
     completion_tokens: Option<u64>,
+    #[serde(default)]
+    completion_tokens_details: Option<OpenAiCompletionTokensDetails>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -0,0 +1,55 @@
+//! OpenRouter: one key for many providers' models, over the OpenAI chat API.
+//!
+//! Models are named `openrouter/<vendor>/<model>`, e.g.
+//! `openrouter/anthropic/claude-sonnet-4`; the prefix picks this provider and is dropped
+//! from the request.
+
+use serde::{Deserialize, Serialize};
+
+pub(crate) const OPENROUTER_CHAT_COMPLETIONS_URL: &str =
+    "https://openrouter.ai/api/v1/chat/completions";
+pub(crate) const MODEL_PREFIX: &str = "openrouter/";
+/// App attribution, shown in OpenRouter's rankings and activity log.
+pub(crate) const ATTRIBUTION_HEADERS: &[(&str, &str)] = &[
+    ("HTTP-Referer", "https://github.com/JoshuaPurtell/OpenCraw"),
+    ("X-Title", "OpenCraw"),
+];
+
+/// OpenRouter's provider preferences: which providers may serve a request, and how to
+/// choose between them. Sent as the request's `provider` object.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OpenRouterRouting {
+    /// Provider slugs to try in this order, e.g. `["anthropic", "amazon-bedrock"]`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub order: Vec<String>,
+    /// Whether providers outside `order` may be used when those fail.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_fallbacks: Option<bool>,
+    /// "price", "throughput" or "latency". Unset balances price and uptime.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub only: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignore: Vec<String>,
+    /// "deny" skips providers that may store or train on prompts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_collection: Option<String>,
+    /// Skip providers that charge more, in USD per million tokens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_price: Option<OpenRouterMaxPrice>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OpenRouterMaxPrice {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completion: Option<f64>,
+}
+
+impl OpenRouterRouting {
+    pub(crate) fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
@@ -0,0 +1,64 @@
+//! Reasoning that arrives inside the reply text.
+//!
+//! Open reasoning models served over the OpenAI API (DeepSeek R1, Qwen3 and the like, on
+//! Ollama or vLLM) write their reasoning into the content between `<think>` tags instead
+//! of a separate field.
+
+const TAGS: &[(&str, &str)] = &[("<think>", "</think>"), ("<thinking>", "</thinking>")];
+
+/// Split leading `<think>…</think>` blocks off `text`: the reply without them, and the
+/// reasoning they held. An unclosed block means the reply was cut off mid-thought, so all
+/// of it is reasoning.
+pub fn strip_thinking_tags(text: &str) -> (String, Option<String>) {
+    let mut rest = text.trim_start();
+    let mut thinking: Vec<&str> = Vec::new();
+    'blocks: loop {
+        for (open, close) in TAGS {
+            let Some(body) = rest.strip_prefix(open) else {
+                continue;
+            };
+            match body.find(close) {
+                Some(end) => {
+                    thinking.push(body[..end].trim());
+                    rest = body[end + close.len()..].trim_start();
+                    continue 'blocks;
+                }
+                None => {
+                    thinking.push(body.trim());
+                    rest = "";
+                    break 'blocks;
+                }
+            }
+        }
+        break;
+    }
+    if thinking.is_empty() {
+        return (text.to_string(), None);
+    }
+    let thinking = thinking.join("\n\n");
+    (rest.to_string(), (!thinking.is_empty()).then_some(thinking))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leading_think_blocks_are_split_off() {
+        assert_eq!(
+            strip_thinking_tags("<think>\nThe user wants 2+2.\n</think>\n\nIt's 4."),
+            ("It's 4.".to_string(), Some("The user wants 2+2.".to_string()))
+        );
+        assert_eq!(
+            strip_thinking_tags("<think></think>Hi"),
+            ("Hi".to_string(), None)
+        );
+        assert_eq!(
+            strip_thinking_tags("<thinking>still going"),
+            (String::new(), Some("still going".to_string()))
+        );
+        // Only leading blocks are reasoning; tags later on are part of the answer.
+        let answer = "Wrap it in <think></think> tags.";
+        assert_eq!(strip_thinking_tags(answer), (answer.to_string(), None));
+    }
+}
@@ -0,0 +1,320 @@
+//! Recording LLM exchanges, and replaying them in place of a provider.
+//!
+//! A [`Recorder`] appends each request and its outcome to a JSONL file. Exchanges are
+//! taken at the [`crate::LlmClient`] level, above the provider clients, so keys and
+//! headers are never part of them. A [`Replay`] answers requests from such a file, or
+//! from exchanges a test writes out, in order, so code driving the model can be tested
+//! without calling a provider. Streams are neither recorded nor replayed.
+
+use crate::error::{LlmError, Result};
+use crate::types::{
+    ChatMessage, ChatResponse, Role, StructuredResponse, ToolCall, ToolDefinition, Usage,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tokio::io::AsyncWriteExt;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Exchange {
+    pub model: String,
+    pub request: ExchangeRequest,
+    pub outcome: ExchangeOutcome,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ExchangeRequest {
+    Chat {
+        messages: Vec<ChatMessage>,
+        tools: Vec<ToolDefinition>,
+    },
+    Structured {
+        messages: Vec<ChatMessage>,
+        name: String,
+        schema: serde_json::Value,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExchangeOutcome {
+    Chat(ChatResponse),
+    Structured(StructuredResponse),
+    Error(LlmError),
+}
+
+impl Exchange {
+    /// A chat turn answered with `content`, for scripting a [`Replay`].
+    pub fn reply(content: &str) -> Self {
+        Self::chat_outcome(ExchangeOutcome::Chat(ChatResponse {
+            message: assistant_message(content, vec![]),
+            usage: Usage::default(),
+            finish_reason: "stop".to_string(),
+        }))
+    }
+
+    /// A chat turn answered with calls to tools, given as `(name, arguments)`.
+    pub fn tool_calls(calls: &[(&str, serde_json::Value)]) -> Self {
+        let calls = calls
+            .iter()
+            .enumerate()
+            .map(|(i, (name, arguments))| ToolCall {
+                id: format!("call_{i}"),
+                name: name.to_string(),
+                arguments: arguments.to_string(),
+            })
+            .collect();
+        Self::chat_outcome(ExchangeOutcome::Chat(ChatResponse {
+            message: assistant_message("", calls),
+            usage: Usage::default(),
+            finish_reason: "tool_calls".to_string(),
+        }))
+    }
+
+    /// A structured reply of `value`, for [`crate::LlmClient::chat_structured`].
+    pub fn structured(value: serde_json::Value) -> Self {
+        Self {
+            model: String::new(),
+            request: ExchangeRequest::Structured {
+                messages: vec![],
+                name: String::new(),
+                schema: serde_json::Value::Null,
+            },
+            outcome: ExchangeOutcome::Structured(StructuredResponse {
+                value,
+                usage: Usage::default(),
+            }),
+        }
+    }
+
+    /// A chat turn that fails with `error`.
+    pub fn error(error: LlmError) -> Self {
+        Self::chat_outcome(ExchangeOutcome::Error(error))
+    }
+
+    fn chat_outcome(outcome: ExchangeOutcome) -> Self {
+        Self {
+            model: String::new(),
+            request: ExchangeRequest::Chat {
+                messages: vec![],
+                tools: vec![],
+            },
+            outcome,
+        }
+    }
+}
+
+fn assistant_message(content: &str, tool_calls: Vec<ToolCall>) -> ChatMessage {
+    ChatMessage {
+        role: Role::Assistant,
+        content: content.to_string(),
+        tool_calls,
+        tool_call_id: None,
+        reasoning: vec![],
+    }
+}
+
+/// Appends exchanges to a JSONL file, one line each. Failures to write are logged and
+/// don't fail the request.
+pub struct Recorder {
+    path: PathBuf,
+    lock: tokio::sync::Mutex<()>,
+}
+
+impl Recorder {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub(crate) async fn record(&self, exchange: &Exchange) {
+        if let Err(e) = self.append(exchange).await {
+            tracing::warn!(%e, path = %self.path.display(), "failed to record llm exchange");
+        }
+    }
+
+    async fn append(&self, exchange: &Exchange) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(exchange)?;
+        line.push(b'\n');
+        let _guard = self.lock.lock().await;
+        if let Some(dir) = self.path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        // One write per line, so clients sharing the file don't interleave.
+        file.write_all(&line).await?;
+        // tokio finishes writes in the background; the line must be there once this returns.
+        file.flush().await
+    }
+}
+
+/// Answers requests with recorded outcomes, first to last.
+pub struct Replay {
+    exchanges: Mutex<VecDeque<Exchange>>,
+}
+
+impl Replay {
+    pub fn new(exchanges: Vec<Exchange>) -> Self {
+        Self {
+            exchanges: Mutex::new(exchanges.into()),
+        }
+    }
+
+    /// Exchanges from a file written by a [`Recorder`].
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = std::fs::read_to_string(path).map_err(|e| {
+            LlmError::InvalidInput(format!("read recording {}: {e}", path.display()))
+        })?;
+        let exchanges = raw
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<std::result::Result<Vec<Exchange>, _>>()?;
+        Ok(Self::new(exchanges))
+    }
+
+    /// Exchanges not yet replayed.
+    pub fn remaining(&self) -> usize {
+        self.exchanges.lock().map(|e| e.len()).unwrap_or(0)
+    }
+
+    pub(crate) fn next_chat(&self) -> Result<ChatResponse> {
+        match self.next()? {
+            ExchangeOutcome::Chat(response) => Ok(response),
+            ExchangeOutcome::Error(e) => Err(e),
+            ExchangeOutcome::Structured(_) => Err(LlmError::InvalidInput(
+                "replay: next recorded exchange is a structured reply, not a chat".to_string(),
+            )),
+        }
+    }
+
+    pub(crate) fn next_structured(&self) -> Result<StructuredResponse> {
+        match self.next()? {
+            ExchangeOutcome::Structured(response) => Ok(response),
+            ExchangeOutcome::Error(e) => Err(e),
+            ExchangeOutcome::Chat(_) => Err(LlmError::InvalidInput(
+                "replay: next recorded exchange is a chat, not a structured reply".to_string(),
+            )),
+        }
+    }
+
+    fn next(&self) -> Result<ExchangeOutcome> {
+        self.exchanges
+            .lock()
+            .ok()
+            .and_then(|mut e| e.pop_front())
+            .map(|e| e.outcome)
+            .ok_or_else(|| LlmError::InvalidInput("replay: no recorded exchanges left".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LlmClient;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn user(content: &str) -> ChatMessage {
+        ChatMessage {
+            role: Role::User,
+            content: content.to_string(),
+            tool_calls: vec![],
+            tool_call_id: None,
+            reasoning: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn scripted_replay_answers_in_order() {
+        let replay = Arc::new(Replay::new(vec![
+            Exchange::tool_calls(&[("shell.execute", json!({ "command": "ls" }))]),
+            Exchange::error(LlmError::Http("status=529 overloaded".to_string())),
+            Exchange::reply("done"),
+        ]));
+        let llm = LlmClient::replay("gpt-4o", replay.clone());
+        let messages = [user("list files")];
+
+        let first = llm.chat(&messages, &[]).await.expect("tool call");
+        assert_eq!(first.message.tool_calls[0].name, "shell.execute");
+        let err = llm.chat(&messages, &[]).await.expect_err("overloaded");
+        assert!(matches!(err, LlmError::Http(m) if m.contains("529")));
+        let last = llm.chat(&messages, &[]).await.expect("reply");
+        assert_eq!(last.message.content, "done");
+        assert_eq!(replay.remaining(), 0);
+        assert!(llm.chat(&messages, &[]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn structured_replies_are_checked_against_the_schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": { "city": { "type": "string" } },
+            "required": ["city"],
+        });
+        let replay = Arc::new(Replay::new(vec![
+            Exchange::structured(json!({ "city": "Lisbon" })),
+            Exchange::structured(json!({ "town": "Porto" })),
+            Exchange::reply("Lisbon"),
+        ]));
+        let llm = LlmClient::replay("gpt-4o", replay.clone());
+        let messages = [user("where do I live?")];
+
+        let first = llm
+            .chat_structured(&messages, "place", &schema)
+            .await
+            .expect("matches");
+        assert_eq!(first.value["city"], "Lisbon");
+        let err = llm
+            .chat_structured(&messages, "place", &schema)
+            .await
+            .expect_err("missing city");
+        assert!(matches!(err, LlmError::ResponseFormat(_)));
+        // A chat where a structured reply is due is a scripting mistake.
+        assert!(llm
+            .chat_structured(&messages, "place", &schema)
+            .await
+            .is_err());
+        assert!(llm
+            .chat_structured(&messages, "place", &json!({ "type": 5 }))
+            .await
+            .is_err());
+        assert_eq!(replay.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn recordings_replay_what_was_recorded() {
+        let path = std::env::temp_dir().join(format!(
+            "opencraw-llm-recording-{}-{}.jsonl",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or(0)
+        ));
+        let source = Arc::new(Replay::new(vec![
+            Exchange::reply("first"),
+            Exchange::reply("second"),
+        ]));
+        let recording = LlmClient::replay("gpt-4o", source)
+            .with_recorder(Arc::new(Recorder::new(path.clone())));
+        for prompt in ["one", "two"] {
+            recording.chat(&[user(prompt)], &[]).await.expect("reply");
+        }
+
+        let replay = Arc::new(Replay::load(&path).expect("load"));
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(replay.remaining(), 2);
+        let llm = LlmClient::replay("gpt-4o", replay);
+        let reply = llm.chat(&[user("one")], &[]).await.expect("reply");
+        assert_eq!(reply.message.content, "first");
+    }
+}
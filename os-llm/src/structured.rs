@@ -0,0 +1,81 @@
+//! Checking structured replies against the caller's JSON Schema.
+
+use crate::error::{LlmError, Result};
+
+/// Parse `raw` as JSON and check it against `schema`. Models sometimes wrap JSON in a
+/// code fence even in JSON mode.
+pub(crate) fn parse_and_validate(
+    raw: &str,
+    schema: &serde_json::Value,
+) -> Result<serde_json::Value> {
+    let trimmed = raw.trim();
+    let body = trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|s| s.strip_suffix("```"))
+        .unwrap_or(trimmed);
+    let value: serde_json::Value = serde_json::from_str(body.trim())?;
+    validate(schema, &value)?;
+    Ok(value)
+}
+
+pub(crate) fn compile(schema: &serde_json::Value) -> Result<jsonschema::JSONSchema> {
+    jsonschema::JSONSchema::compile(schema)
+        .map_err(|e| LlmError::InvalidInput(format!("invalid schema: {e}")))
+}
+
+pub(crate) fn validate(schema: &serde_json::Value, value: &serde_json::Value) -> Result<()> {
+    let compiled = compile(schema)?;
+    let problems: Vec<String> = match compiled.validate(value) {
+        Ok(()) => return Ok(()),
+        Err(errors) => errors
+            .map(|e| {
+                let path = e.instance_path.to_string();
+                if path.is_empty() {
+                    e.to_string()
+                } else {
+                    format!("{path}: {e}")
+                }
+            })
+            .collect(),
+    };
+    Err(LlmError::ResponseFormat(format!(
+        "reply does not match the schema: {}",
+        problems.join("; ")
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn replies_are_checked_against_the_schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "title": { "type": "string" },
+                "priority": { "type": "integer", "minimum": 1, "maximum": 4 }
+            },
+            "required": ["title"]
+        });
+        let value = parse_and_validate(
+            "```json\n{\"title\": \"Pay rent\", \"priority\": 2}\n```",
+            &schema,
+        )
+        .unwrap();
+        assert_eq!(value["priority"], 2);
+
+        let err = parse_and_validate("{\"priority\": 9}", &schema)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("\"title\" is a required property"), "{err}");
+        assert!(err.contains("/priority"), "{err}");
+        assert!(parse_and_validate("Pay rent", &schema).is_err());
+        assert!(matches!(
+            validate(&json!({ "type": "nonsense" }), &json!({})),
+            Err(LlmError::InvalidInput(_))
+        ));
+    }
+}
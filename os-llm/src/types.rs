@@ -25,6 +25,44 @@ pub struct ChatMessage {
     pub tool_calls: Vec<ToolCall>,
     #[serde(default)]
     pub tool_call_id: Option<String>,
+    /// The model's reasoning before this message. Never shown on a channel; kept so it
+    /// can be sent back where the provider requires it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reasoning: Vec<ReasoningBlock>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ReasoningBlock {
+    /// Readable reasoning. `signature` is Anthropic's, empty for other providers.
+    Thinking { thinking: String, signature: String },
+    /// Anthropic reasoning flagged by its safety systems, encrypted.
+    Redacted { data: String },
+}
+
+/// Per-model reasoning settings. Each provider reads its own field and ignores the other.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReasoningConfig {
+    /// OpenAI reasoning models' `reasoning_effort`: "minimal", "low", "medium" or "high".
+    #[serde(default)]
+    pub effort: Option<String>,
+    /// Anthropic extended thinking: tokens the model may spend thinking, at least 1024.
+    #[serde(default)]
+    pub budget_tokens: Option<u32>,
+}
+
+/// Per-model sampling settings. Unset fields are left to the provider's default.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SamplingConfig {
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    /// Reply tokens, not counting an Anthropic thinking budget.
+    #[serde(default)]
+    pub max_output_tokens: Option<u32>,
+    #[serde(default)]
+    pub stop: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,10 +73,15 @@ pub struct ToolDefinition {
     pub parameters: serde_json::Value,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Usage {
     pub prompt_tokens: u32,
+    /// Includes `reasoning_tokens`.
     pub completion_tokens: u32,
+    /// Completion tokens spent reasoning, where the provider reports them (OpenAI does;
+    /// Anthropic bills thinking as output without a separate count).
+    #[serde(default)]
+    pub reasoning_tokens: u32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -55,3 +98,42 @@ pub enum StreamChunk {
     ToolCallDelta { arguments: String },
     Done { usage: Usage },
 }
+
+/// A reply from [`crate::LlmClient::chat_structured`], already checked against the schema.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StructuredResponse {
+    pub value: serde_json::Value,
+    pub usage: Usage,
+}
+
+/// Embedding model settings for [`crate::LlmClient::embed`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingConfig {
+    pub model: String,
+    /// OpenAI-compatible server to use instead of api.openai.com
+    /// (e.g. `http://localhost:11434/v1` for Ollama).
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Desired vector size. Sent to the API, and longer vectors are truncated and
+    /// re-normalized for servers that ignore it.
+    #[serde(default)]
+    pub dimensions: Option<usize>,
+    /// Max inputs per request.
+    #[serde(default = "default_embedding_batch_size")]
+    pub batch_size: usize,
+}
+
+fn default_embedding_batch_size() -> usize {
+    64
+}
+
+impl Default for EmbeddingConfig {
+    fn default() -> Self {
+        Self {
+            model: "text-embedding-3-small".to_string(),
+            base_url: None,
+            dimensions: None,
+            batch_size: default_embedding_batch_size(),
+        }
+    }
+}
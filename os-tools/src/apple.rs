@@ -0,0 +1,369 @@
+//! Apple Reminders and Notes, scripted through `osascript`.
+//!
+//! Scripts are JavaScript for Automation and receive user input as `argv`, so nothing is
+//! spliced into source. The first call triggers the macOS Automation prompt for each app;
+//! a denied prompt surfaces as an error that says so.
+
+use crate::error::{Result, ToolError};
+use crate::traits::{optional_string, require_string, until_cancelled, Tool, ToolSpec};
+use async_trait::async_trait;
+use horizons_core::core_agents::models::RiskLevel;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+
+/// Notes and Reminders are slow to answer for large libraries.
+const SCRIPT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_LIMIT: u64 = 50;
+const LIMIT_MAX: u64 = 200;
+/// Note bodies longer than this are cut when read.
+const NOTE_CHARS_MAX: usize = 20_000;
+
+const REMINDERS_LIST: &str = r#"
+function run(argv) {
+  const [listName, includeCompleted, limit] = [argv[0], argv[1] === "true", parseInt(argv[2])];
+  const app = Application("Reminders");
+  const lists = listName ? [app.lists.byName(listName)] : app.lists();
+  const out = [];
+  for (const list of lists) {
+    const items = includeCompleted ? list.reminders() : list.reminders.whose({ completed: false })();
+    for (const r of items) {
+      if (out.length >= limit) break;
+      const due = r.dueDate();
+      out.push({
+        id: r.id(),
+        title: r.name(),
+        list: list.name(),
+        completed: r.completed(),
+        due: due ? due.toISOString() : null,
+        notes: r.body() || null,
+      });
+    }
+  }
+  return JSON.stringify(out);
+}
+"#;
+
+const REMINDERS_CREATE: &str = r#"
+function run(argv) {
+  const [title, listName, due, notes] = argv;
+  const app = Application("Reminders");
+  const list = listName ? app.lists.byName(listName) : app.defaultList();
+  const props = { name: title };
+  if (notes) props.body = notes;
+  if (due) {
+    const date = new Date(due);
+    if (isNaN(date)) throw new Error("invalid due date: " + due);
+    props.dueDate = date;
+  }
+  const reminder = app.Reminder(props);
+  list.reminders.push(reminder);
+  return JSON.stringify({ id: reminder.id(), title: reminder.name(), list: list.name() });
+}
+"#;
+
+const REMINDERS_COMPLETE: &str = r#"
+function run(argv) {
+  const reminder = Application("Reminders").reminders.byId(argv[0]);
+  reminder.completed = true;
+  return JSON.stringify({ id: reminder.id(), title: reminder.name(), completed: true });
+}
+"#;
+
+const NOTES_SEARCH: &str = r#"
+function run(argv) {
+  const [query, limit] = [argv[0], parseInt(argv[1])];
+  const app = Application("Notes");
+  const notes = app.notes.whose({ _or: [{ name: { _contains: query } }, { plaintext: { _contains: query } }] })();
+  return JSON.stringify(notes.slice(0, limit).map((n) => ({
+    id: n.id(),
+    title: n.name(),
+    folder: n.container().name(),
+    modified: n.modificationDate().toISOString(),
+    snippet: n.plaintext().slice(0, 200),
+  })));
+}
+"#;
+
+const NOTES_READ: &str = r#"
+function run(argv) {
+  const n = Application("Notes").notes.byId(argv[0]);
+  return JSON.stringify({
+    id: n.id(),
+    title: n.name(),
+    folder: n.container().name(),
+    modified: n.modificationDate().toISOString(),
+    body: n.plaintext(),
+  });
+}
+"#;
+
+const NOTES_CREATE: &str = r#"
+function run(argv) {
+  const [title, body, folderName] = argv;
+  const app = Application("Notes");
+  const folder = folderName ? app.folders.byName(folderName) : app.defaultAccount().defaultFolder();
+  const escape = (s) => s.replace(/&/g, "&amp;").replace(/</g, "&lt;").replace(/>/g, "&gt;");
+  const html = "<h1>" + escape(title) + "</h1>" + body.split("\n").map((l) => "<div>" + (escape(l) || "<br>") + "</div>").join("");
+  const note = app.Note({ body: html });
+  folder.notes.push(note);
+  return JSON.stringify({ id: note.id(), title: note.name(), folder: folder.name() });
+}
+"#;
+
+#[derive(Default)]
+pub struct AppleRemindersTool;
+
+impl AppleRemindersTool {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[async_trait]
+impl Tool for AppleRemindersTool {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "apple.reminders".to_string(),
+            description: "Apple Reminders on this Mac. list: open reminders (optionally one list, or include_completed). create: a reminder with optional list, due (ISO 8601) and notes. complete: mark a reminder done by id.".to_string(),
+            parameters_schema: serde_json::json!({
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "action": { "type": "string", "enum": ["list", "create", "complete"] },
+                    "list": { "type": "string", "description": "Reminders list name; default list when omitted" },
+                    "include_completed": { "type": "boolean" },
+                    "limit": { "type": "integer", "minimum": 1, "maximum": LIMIT_MAX },
+                    "title": { "type": "string" },
+                    "due": { "type": "string", "description": "ISO 8601, e.g. 2026-03-01T09:00:00-08:00" },
+                    "notes": { "type": "string" },
+                    "id": { "type": "string" }
+                },
+                "required": ["action"]
+            }),
+            risk_level: RiskLevel::Medium,
+        }
+    }
+
+    #[tracing::instrument(level = "info", skip_all)]
+    async fn execute(
+        &self,
+        arguments: serde_json::Value,
+        cancel: &CancellationToken,
+    ) -> Result<serde_json::Value> {
+        let action = require_string(&arguments, "action")?;
+        match action.as_str() {
+            "list" => {
+                let include_completed = arguments
+                    .get("include_completed")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false);
+                let reminders = run_jxa(
+                    REMINDERS_LIST,
+                    &[
+                        optional_string(&arguments, "list")?.unwrap_or_default(),
+                        include_completed.to_string(),
+                        limit(&arguments).to_string(),
+                    ],
+                    cancel,
+                )
+                .await?;
+                Ok(serde_json::json!({ "reminders": reminders }))
+            }
+            "create" => {
+                let title = require_string(&arguments, "title")?;
+                if title.trim().is_empty() {
+                    return Err(ToolError::InvalidArguments("title is empty".to_string()));
+                }
+                run_jxa(
+                    REMINDERS_CREATE,
+                    &[
+                        title,
+                        optional_string(&arguments, "list")?.unwrap_or_default(),
+                        optional_string(&arguments, "due")?.unwrap_or_default(),
+                        optional_string(&arguments, "notes")?.unwrap_or_default(),
+                    ],
+                    cancel,
+                )
+                .await
+            }
+            "complete" => {
+                run_jxa(
+                    REMINDERS_COMPLETE,
+                    &[require_string(&arguments, "id")?],
+                    cancel,
+                )
+                .await
+            }
+            other => Err(ToolError::InvalidArguments(format!(
+                "unknown action: {other}"
+            ))),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct AppleNotesTool;
+
+impl AppleNotesTool {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[async_trait]
+impl Tool for AppleNotesTool {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "apple.notes".to_string(),
+            description: "Apple Notes on this Mac. search: notes whose title or text contains query. read: a note's full text by id. create: a new note with title and body, optionally in a folder.".to_string(),
+            parameters_schema: serde_json::json!({
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "action": { "type": "string", "enum": ["search", "read", "create"] },
+                    "query": { "type": "string" },
+                    "limit": { "type": "integer", "minimum": 1, "maximum": LIMIT_MAX },
+                    "id": { "type": "string" },
+                    "title": { "type": "string" },
+                    "body": { "type": "string", "description": "Plain text; line breaks are kept" },
+                    "folder": { "type": "string", "description": "Folder name; the default folder when omitted" }
+                },
+                "required": ["action"]
+            }),
+            risk_level: RiskLevel::Medium,
+        }
+    }
+
+    #[tracing::instrument(level = "info", skip_all)]
+    async fn execute(
+        &self,
+        arguments: serde_json::Value,
+        cancel: &CancellationToken,
+    ) -> Result<serde_json::Value> {
+        let action = require_string(&arguments, "action")?;
+        match action.as_str() {
+            "search" => {
+                let query = require_string(&arguments, "query")?;
+                let notes = run_jxa(
+                    NOTES_SEARCH,
+                    &[query, limit(&arguments).to_string()],
+                    cancel,
+                )
+                .await?;
+                Ok(serde_json::json!({ "notes": notes }))
+            }
+            "read" => {
+                let mut note =
+                    run_jxa(NOTES_READ, &[require_string(&arguments, "id")?], cancel).await?;
+                let body = note
+                    .get("body")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default();
+                if body.chars().count() > NOTE_CHARS_MAX {
+                    let cut: String = body.chars().take(NOTE_CHARS_MAX).collect();
+                    note["body"] = serde_json::Value::String(cut);
+                    note["truncated"] = serde_json::Value::Bool(true);
+                }
+                Ok(note)
+            }
+            "create" => {
+                let title = require_string(&arguments, "title")?;
+                if title.trim().is_empty() {
+                    return Err(ToolError::InvalidArguments("title is empty".to_string()));
+                }
+                run_jxa(
+                    NOTES_CREATE,
+                    &[
+                        title,
+                        optional_string(&arguments, "body")?.unwrap_or_default(),
+                        optional_string(&arguments, "folder")?.unwrap_or_default(),
+                    ],
+                    cancel,
+                )
+                .await
+            }
+            other => Err(ToolError::InvalidArguments(format!(
+                "unknown action: {other}"
+            ))),
+        }
+    }
+}
+
+fn limit(arguments: &serde_json::Value) -> u64 {
+    arguments
+        .get("limit")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(DEFAULT_LIMIT)
+        .clamp(1, LIMIT_MAX)
+}
+
+/// Run a JXA script's `run(argv)` and parse the JSON it returns.
+async fn run_jxa(
+    script: &str,
+    args: &[String],
+    cancel: &CancellationToken,
+) -> Result<serde_json::Value> {
+    let mut cmd = Command::new("osascript");
+    cmd.args(["-l", "JavaScript", "-e", script])
+        .args(args)
+        .stdin(Stdio::null())
+        .kill_on_drop(true);
+    let output = until_cancelled(cancel, async {
+        tokio::time::timeout(SCRIPT_TIMEOUT, cmd.output())
+            .await
+            .map_err(|_| {
+                ToolError::ExecutionFailed(format!(
+                    "osascript timed out after {}s",
+                    SCRIPT_TIMEOUT.as_secs()
+                ))
+            })?
+            .map_err(|e| ToolError::ExecutionFailed(format!("osascript: {e}")))
+    })
+    .await?;
+    if !output.status.success() {
+        return Err(script_error(&String::from_utf8_lossy(&output.stderr)));
+    }
+    serde_json::from_slice(&output.stdout)
+        .map_err(|e| ToolError::ExecutionFailed(format!("unexpected osascript output: {e}")))
+}
+
+/// osascript reports `execution error: <message> (<code>)`; keep the message, and explain
+/// the codes that mean the user has to act.
+fn script_error(stderr: &str) -> ToolError {
+    let line = stderr.lines().last().unwrap_or_default().trim();
+    let message = line
+        .split_once("execution error: ")
+        .map(|(_, rest)| rest)
+        .unwrap_or(line);
+    if message.ends_with("(-1743)") {
+        return ToolError::ExecutionFailed(
+            "not allowed to control this app; grant Automation access in System Settings > Privacy & Security".to_string(),
+        );
+    }
+    if message.ends_with("(-1728)") || message.ends_with("(-1719)") {
+        return ToolError::ExecutionFailed(format!("not found: {message}"));
+    }
+    ToolError::ExecutionFailed(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn osascript_errors_are_explained() {
+        assert!(matches!(
+            script_error("execution error: Error: Not authorized to send Apple events to Reminders. (-1743)\n"),
+            ToolError::ExecutionFailed(m) if m.contains("Automation")
+        ));
+        let ToolError::ExecutionFailed(message) =
+            script_error("execution error: Error: Can't get object. (-1728)")
+        else {
+            panic!("expected ExecutionFailed");
+        };
+        assert_eq!(message, "not found: Error: Can't get object. (-1728)");
+        assert_eq!(limit(&serde_json::json!({ "limit": 1000 })), LIMIT_MAX);
+    }
+}
@@ -1,16 +1,106 @@
 use crate::error::{Result, ToolError};
-use crate::traits::{require_string, Tool, ToolSpec};
+use crate::network_policy::NetworkPolicy;
+use crate::traits::{require_string, until_cancelled, Tool, ToolSpec};
 use async_trait::async_trait;
 use horizons_core::core_agents::models::RiskLevel;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const REDIRECTS_MAX: usize = 5;
+/// Most of a page `fetch` reads; the rest is cut off.
+const BODY_BYTES_MAX: usize = 200_000;
 
 /// Browser automation tool backed by Chrome DevTools Protocol.
 ///
-/// v0.1.0 keeps this as a compile-time placeholder.
-pub struct BrowserTool;
+/// v0.1.0 keeps `navigate` and `screenshot` as placeholders. `fetch` reads a page over
+/// plain HTTP; its URL and every redirect are checked against the network policy, and
+/// so is the content type of the response before the body is read.
+pub struct BrowserTool {
+    policy: NetworkPolicy,
+    client: reqwest::Client,
+}
 
 impl BrowserTool {
     pub fn new() -> Self {
-        Self
+        let policy = NetworkPolicy::default();
+        Self {
+            // Redirects are followed by hand, so each hop goes through the policy.
+            client: policy.client(REQUEST_TIMEOUT).unwrap_or_default(),
+            policy,
+        }
+    }
+
+    pub fn with_policy(mut self, policy: NetworkPolicy) -> Self {
+        self.client = policy.client(REQUEST_TIMEOUT).unwrap_or_default();
+        self.policy = policy;
+        self
+    }
+
+    async fn fetch(&self, raw: &str) -> Result<serde_json::Value> {
+        let mut url = self.policy.check_url(raw).await?;
+        let mut redirects = 0;
+        let mut resp = loop {
+            let resp = self
+                .client
+                .get(url.clone())
+                .send()
+                .await
+                .map_err(|e| ToolError::ExecutionFailed(format!("fetch {url}: {e}")))?;
+            if !resp.status().is_redirection() {
+                break resp;
+            }
+            let location = resp
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|v| v.to_str().ok())
+                .ok_or_else(|| {
+                    ToolError::ExecutionFailed(format!("{url} redirected without a location"))
+                })?;
+            let next = url.join(location).map_err(|e| {
+                ToolError::ExecutionFailed(format!("{url} redirected to {location}: {e}"))
+            })?;
+            redirects += 1;
+            if redirects > REDIRECTS_MAX {
+                return Err(ToolError::ExecutionFailed(format!(
+                    "{raw} redirected more than {REDIRECTS_MAX} times"
+                )));
+            }
+            url = self.policy.check_url(next.as_str()).await?;
+        };
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(ToolError::ExecutionFailed(format!("fetch {url}: {status}")));
+        }
+        let content_type = resp
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        self.policy.check_content_type(&content_type)?;
+
+        let mut body = Vec::new();
+        let mut truncated = false;
+        while let Some(chunk) = resp
+            .chunk()
+            .await
+            .map_err(|e| ToolError::ExecutionFailed(format!("read {url}: {e}")))?
+        {
+            body.extend_from_slice(&chunk);
+            if body.len() >= BODY_BYTES_MAX {
+                body.truncate(BODY_BYTES_MAX);
+                truncated = true;
+                break;
+            }
+        }
+        Ok(serde_json::json!({
+            "url": url.as_str(),
+            "status": status.as_u16(),
+            "content_type": content_type,
+            "body": String::from_utf8_lossy(&body),
+            "truncated": truncated,
+        }))
     }
 }
 
@@ -19,12 +109,14 @@ impl Tool for BrowserTool {
     fn spec(&self) -> ToolSpec {
         ToolSpec {
             name: "browser".to_string(),
-            description: "Control a local Chrome/Chromium instance via CDP.".to_string(),
+            description: "Control a local Chrome/Chromium instance via CDP, or fetch a page's \
+                          text with action=fetch."
+                .to_string(),
             parameters_schema: serde_json::json!({
                 "type": "object",
                 "additionalProperties": false,
                 "properties": {
-                    "action": { "type": "string", "enum": ["navigate", "screenshot"] },
+                    "action": { "type": "string", "enum": ["navigate", "screenshot", "fetch"] },
                     "url": { "type": "string" }
                 },
                 "required": ["action"]
@@ -33,13 +125,22 @@ impl Tool for BrowserTool {
         }
     }
 
-    async fn execute(&self, arguments: serde_json::Value) -> Result<serde_json::Value> {
+    async fn execute(
+        &self,
+        arguments: serde_json::Value,
+        cancel: &CancellationToken,
+    ) -> Result<serde_json::Value> {
         let action = require_string(&arguments, "action")?;
         match action.as_str() {
             "navigate" => {
                 let url = require_string(&arguments, "url")?;
+                until_cancelled(cancel, self.policy.check_url(&url)).await?;
                 Ok(serde_json::json!({ "result": format!("navigate not implemented (url={url})") }))
             }
+            "fetch" => {
+                let url = require_string(&arguments, "url")?;
+                until_cancelled(cancel, self.fetch(&url)).await
+            }
             "screenshot" => Ok(serde_json::json!({ "result": "screenshot not implemented" })),
             other => Err(ToolError::InvalidArguments(format!(
                 "unknown action: {other}"
@@ -47,3 +148,78 @@ impl Tool for BrowserTool {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Answers each connection with the next of `responses`, and gives the base url.
+    async fn serve(responses: Vec<String>) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = [0u8; 1024];
+                let _ = stream.read(&mut request).await;
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        format!("http://{addr}")
+    }
+
+    fn response(head: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {head}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    #[tokio::test]
+    async fn fetch_checks_redirects_and_content_types() {
+        let cancel = CancellationToken::new();
+        let fetch = |url: String| serde_json::json!({ "action": "fetch", "url": url });
+        let local = NetworkPolicy {
+            allow_private_networks: true,
+            allowed_content_types: vec!["text/*".to_string()],
+            ..NetworkPolicy::default()
+        };
+
+        let base = serve(vec![
+            response("302 Found\r\nLocation: /page", ""),
+            response("200 OK\r\nContent-Type: text/plain; charset=utf-8", "hello"),
+            response("200 OK\r\nContent-Type: application/octet-stream", "\0\0"),
+        ])
+        .await;
+        let tool = BrowserTool::new().with_policy(local.clone());
+        let page = tool
+            .execute(fetch(format!("{base}/")), &cancel)
+            .await
+            .unwrap();
+        assert_eq!(page["url"], format!("{base}/page"));
+        assert_eq!(page["body"], "hello");
+        let err = tool
+            .execute(fetch(format!("{base}/file")), &cancel)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ToolError::Unauthorized(_)), "{err}");
+
+        // A redirect is checked like the url it came from.
+        let base = serve(vec![response(
+            "301 Moved Permanently\r\nLocation: http://127.0.0.1:9/admin",
+            "",
+        )])
+        .await;
+        let tool = BrowserTool::new().with_policy(NetworkPolicy {
+            deny_hosts: vec!["127.0.0.1".to_string()],
+            allow_hosts: vec!["localhost".to_string()],
+            ..local
+        });
+        let err = tool
+            .execute(fetch(base.replace("127.0.0.1", "localhost")), &cancel)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ToolError::Unauthorized(m) if m.contains("127.0.0.1")));
+    }
+}
@@ -1,7 +1,25 @@
+//! System clipboard: plain text, HTML, RTF and images.
+//!
+//! Text, HTML and images go through `arboard`. RTF has no portable API, so it uses the
+//! platform tools: `pbpaste`/`pbcopy` on macOS, `wl-paste`/`wl-copy` under Wayland and
+//! `xclip` on X11. Images are exchanged as PNG files so they can travel as attachments
+//! without putting the pixels in the model's context.
+
 use crate::error::{Result, ToolError};
-use crate::traits::{require_string, Tool, ToolSpec};
+use crate::traits::{optional_string, require_string, Tool, ToolSpec};
+use arboard::ImageData;
 use async_trait::async_trait;
+use base64::Engine as _;
 use horizons_core::core_agents::models::RiskLevel;
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+
+const RTF_HEADER: &str = "{\\rtf";
 
 pub struct ClipboardTool;
 
@@ -16,13 +34,16 @@ impl Tool for ClipboardTool {
     fn spec(&self) -> ToolSpec {
         ToolSpec {
             name: "clipboard".to_string(),
-            description: "Read or write the system clipboard.".to_string(),
+            description: "Read or write the system clipboard. format selects the flavor: text (default), html, rtf, or image. Reading an image returns a PNG attachment; to copy an image, pass a PNG file path or base64/data URL in content.".to_string(),
             parameters_schema: serde_json::json!({
                 "type": "object",
                 "additionalProperties": false,
                 "properties": {
                     "action": { "type": "string", "enum": ["get", "set"] },
-                    "content": { "type": "string" }
+                    "format": { "type": "string", "enum": ["text", "html", "rtf", "image"] },
+                    "content": { "type": "string" },
+                    "path": { "type": "string", "description": "set, format=image: PNG file to copy" },
+                    "alt_text": { "type": "string", "description": "set, format=html: plain-text fallback" }
                 },
                 "required": ["action"]
             }),
@@ -31,29 +52,242 @@ impl Tool for ClipboardTool {
     }
 
     #[tracing::instrument(level = "info", skip_all)]
-    async fn execute(&self, arguments: serde_json::Value) -> Result<serde_json::Value> {
+    async fn execute(
+        &self,
+        arguments: serde_json::Value,
+        _cancel: &CancellationToken,
+    ) -> Result<serde_json::Value> {
         let action = require_string(&arguments, "action")?;
+        let format = optional_string(&arguments, "format")?.unwrap_or_else(|| "text".to_string());
 
-        let mut clipboard =
-            arboard::Clipboard::new().map_err(|e| ToolError::ExecutionFailed(e.to_string()))?;
-
-        match action.as_str() {
-            "get" => {
-                let text = clipboard
-                    .get_text()
-                    .map_err(|e| ToolError::ExecutionFailed(e.to_string()))?;
-                Ok(serde_json::json!({ "content": text }))
+        match (action.as_str(), format.as_str()) {
+            ("get", "text") => {
+                let text = clipboard()?.get_text().map_err(failed)?;
+                Ok(serde_json::json!({ "format": "text", "content": text }))
+            }
+            ("get", "html") => {
+                let html = clipboard()?.get().html().map_err(failed)?;
+                Ok(serde_json::json!({ "format": "html", "content": html }))
+            }
+            ("get", "rtf") => {
+                let rtf = read_rtf().await?;
+                Ok(serde_json::json!({ "format": "rtf", "content": rtf }))
+            }
+            ("get", "image") => {
+                let image = clipboard()?.get_image().map_err(failed)?;
+                let png = encode_png(&image)?;
+                let path = temp_png_path();
+                tokio::fs::write(&path, &png).await?;
+                Ok(serde_json::json!({
+                    "format": "image",
+                    "width": image.width,
+                    "height": image.height,
+                    "size_bytes": png.len(),
+                    // Same shape as a channel attachment, so it can be sent or stored as is.
+                    "attachment": {
+                        "name": "clipboard.png",
+                        "content_type": "image/png",
+                        "url": format!("file://{}", path.display()),
+                    },
+                }))
+            }
+            ("set", "text") => {
+                let content = require_string(&arguments, "content")?;
+                clipboard()?.set_text(content).map_err(failed)?;
+                Ok(serde_json::json!({ "status": "ok" }))
+            }
+            ("set", "html") => {
+                let content = require_string(&arguments, "content")?;
+                let alt_text = optional_string(&arguments, "alt_text")?;
+                clipboard()?.set_html(content, alt_text).map_err(failed)?;
+                Ok(serde_json::json!({ "status": "ok" }))
             }
-            "set" => {
+            ("set", "rtf") => {
                 let content = require_string(&arguments, "content")?;
-                clipboard
-                    .set_text(content)
-                    .map_err(|e| ToolError::ExecutionFailed(e.to_string()))?;
+                if !content.trim_start().starts_with(RTF_HEADER) {
+                    return Err(ToolError::InvalidArguments(
+                        "content is not RTF (must start with {\\rtf)".to_string(),
+                    ));
+                }
+                write_rtf(&content).await?;
+                Ok(serde_json::json!({ "status": "ok" }))
+            }
+            ("set", "image") => {
+                let png = match optional_string(&arguments, "path")? {
+                    Some(path) => tokio::fs::read(&path).await?,
+                    None => decode_base64(&require_string(&arguments, "content")?)?,
+                };
+                let image = decode_png(&png)?;
+                clipboard()?.set_image(image).map_err(failed)?;
                 Ok(serde_json::json!({ "status": "ok" }))
             }
-            other => Err(ToolError::InvalidArguments(format!(
+            ("get" | "set", other) => Err(ToolError::InvalidArguments(format!(
+                "unknown format: {other}"
+            ))),
+            (other, _) => Err(ToolError::InvalidArguments(format!(
                 "unknown action: {other}"
             ))),
         }
     }
 }
+
+fn clipboard() -> Result<arboard::Clipboard> {
+    arboard::Clipboard::new().map_err(failed)
+}
+
+fn failed(e: impl std::fmt::Display) -> ToolError {
+    ToolError::ExecutionFailed(e.to_string())
+}
+
+fn wayland() -> bool {
+    std::env::var_os("WAYLAND_DISPLAY").is_some()
+}
+
+async fn read_rtf() -> Result<String> {
+    let argv: &[&str] = if cfg!(target_os = "macos") {
+        &["pbpaste", "-Prefer", "rtf"]
+    } else if wayland() {
+        &["wl-paste", "--no-newline", "--type", "text/rtf"]
+    } else {
+        &["xclip", "-selection", "clipboard", "-t", "text/rtf", "-o"]
+    };
+    let output = Command::new(argv[0])
+        .args(&argv[1..])
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(|e| ToolError::ExecutionFailed(format!("{}: {e}", argv[0])))?;
+    let text = String::from_utf8_lossy(&output.stdout).to_string();
+    // pbpaste falls back to plain text when there is no RTF.
+    if !output.status.success() || !text.trim_start().starts_with(RTF_HEADER) {
+        return Err(ToolError::ExecutionFailed(
+            "clipboard has no RTF content".to_string(),
+        ));
+    }
+    Ok(text)
+}
+
+async fn write_rtf(rtf: &str) -> Result<()> {
+    // pbcopy recognises RTF by its header.
+    let argv: &[&str] = if cfg!(target_os = "macos") {
+        &["pbcopy"]
+    } else if wayland() {
+        &["wl-copy", "--type", "text/rtf"]
+    } else {
+        &["xclip", "-selection", "clipboard", "-t", "text/rtf", "-i"]
+    };
+    // wl-copy and xclip stay in the background to serve the selection, so their output
+    // must not be piped or waiting on it would never finish.
+    let mut child = Command::new(argv[0])
+        .args(&argv[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| ToolError::ExecutionFailed(format!("{}: {e}", argv[0])))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(rtf.as_bytes()).await?;
+    }
+    let status = child.wait().await?;
+    if !status.success() {
+        return Err(ToolError::ExecutionFailed(format!(
+            "{} exited with {status}",
+            argv[0]
+        )));
+    }
+    Ok(())
+}
+
+fn temp_png_path() -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    std::env::temp_dir().join(format!("opencraw-clipboard-{nanos}.png"))
+}
+
+/// Plain base64 or a `data:` URL.
+fn decode_base64(content: &str) -> Result<Vec<u8>> {
+    let encoded = match content.split_once(";base64,") {
+        Some((_, data)) => data,
+        None => content,
+    };
+    base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .map_err(|e| ToolError::InvalidArguments(format!("invalid base64 image: {e}")))
+}
+
+fn encode_png(image: &ImageData) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, image.width as u32, image.height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(failed)?;
+    writer.write_image_data(&image.bytes).map_err(failed)?;
+    writer.finish().map_err(failed)?;
+    Ok(out)
+}
+
+/// Decode a PNG into the RGBA pixels the clipboard expects.
+fn decode_png(bytes: &[u8]) -> Result<ImageData<'static>> {
+    let invalid = |e: png::DecodingError| ToolError::InvalidArguments(format!("invalid PNG: {e}"));
+    let mut decoder = png::Decoder::new(Cursor::new(bytes));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(invalid)?;
+    let size = reader
+        .output_buffer_size()
+        .ok_or_else(|| ToolError::InvalidArguments("PNG is too large".to_string()))?;
+    let mut buf = vec![0; size];
+    let info = reader.next_frame(&mut buf).map_err(invalid)?;
+    buf.truncate(info.buffer_size());
+
+    let rgba: Vec<u8> = match info.color_type {
+        png::ColorType::Rgba => buf,
+        png::ColorType::Rgb => buf
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buf
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => buf.iter().flat_map(|&v| [v, v, v, 255]).collect(),
+        other => {
+            return Err(ToolError::InvalidArguments(format!(
+                "unsupported PNG color type: {other:?}"
+            )))
+        }
+    };
+    Ok(ImageData {
+        width: info.width as usize,
+        height: info.height as usize,
+        bytes: rgba.into(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn png_round_trips_through_clipboard_pixels() {
+        let image = ImageData {
+            width: 2,
+            height: 1,
+            bytes: vec![255, 0, 0, 255, 0, 0, 255, 128].into(),
+        };
+        let png = encode_png(&image).unwrap();
+        let data_url = format!(
+            "data:image/png;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(&png)
+        );
+
+        let decoded = decode_png(&decode_base64(&data_url).unwrap()).unwrap();
+        assert_eq!((decoded.width, decoded.height), (2, 1));
+        assert_eq!(decoded.bytes, image.bytes);
+        assert!(matches!(
+            decode_png(b"not a png"),
+            Err(ToolError::InvalidArguments(_))
+        ));
+    }
+}
@@ -0,0 +1,340 @@
+use crate::error::{Result, ToolError};
+use crate::traits::{require_string, Tool, ToolSpec};
+use async_trait::async_trait;
+use horizons_core::core_agents::models::RiskLevel;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+const OUTPUT_BYTES_MAX: usize = 64 * 1024;
+
+/// A language the tool knows how to run: where the snippet goes and how to run it, either
+/// with a local toolchain or inside a container image.
+struct PresetDef {
+    language: &'static str,
+    file: &'static str,
+    /// Command whose success means the local toolchain is installed.
+    probe: &'static [&'static str],
+    /// Run in the snippet's directory by `sh -c`, locally and in containers alike.
+    run: &'static str,
+    image: &'static str,
+}
+
+const PRESETS: &[PresetDef] = &[
+    PresetDef {
+        language: "python",
+        file: "main.py",
+        probe: &["python3", "--version"],
+        run: "python3 main.py",
+        image: "python:3.12-slim",
+    },
+    PresetDef {
+        language: "node",
+        file: "main.js",
+        probe: &["node", "--version"],
+        run: "node main.js",
+        image: "node:22-slim",
+    },
+    PresetDef {
+        language: "rust",
+        file: "main.rs",
+        probe: &["rustc", "--version"],
+        run: "rustc -O -o main main.rs && ./main",
+        image: "rust:1-slim",
+    },
+    PresetDef {
+        language: "go",
+        file: "main.go",
+        probe: &["go", "version"],
+        run: "GOCACHE=\"$PWD/.gocache\" go run main.go",
+        image: "golang:1.23",
+    },
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CodeRuntime {
+    /// Isolated: no network, capped memory and CPU.
+    Container { image: String },
+    /// Runs on the host with the user's permissions.
+    Local { version: String },
+}
+
+/// A language available to `code.run`, as reported in status.
+#[derive(Debug, Clone, Serialize)]
+pub struct CodePreset {
+    pub language: String,
+    pub runtime: CodeRuntime,
+    #[serde(skip)]
+    file: &'static str,
+    #[serde(skip)]
+    run: &'static str,
+}
+
+#[derive(Debug, Clone)]
+pub struct CodeRunOptions {
+    pub timeout: Duration,
+    /// Use a container runtime when one is installed, else fall back to local toolchains.
+    pub prefer_containers: bool,
+    /// `docker` or `podman`.
+    pub container_cli: String,
+    /// Replaces the default image per language.
+    pub images: HashMap<String, String>,
+}
+
+pub struct CodeRunTool {
+    presets: Vec<CodePreset>,
+    timeout: Duration,
+    container_cli: String,
+}
+
+impl CodeRunTool {
+    /// Probe for a container runtime and local toolchains and keep the languages that can
+    /// actually run here.
+    pub async fn discover(opts: CodeRunOptions) -> Self {
+        let containers = opts.prefer_containers
+            && probe(&[opts.container_cli.as_str(), "version"])
+                .await
+                .is_some();
+
+        let mut presets = Vec::new();
+        for def in PRESETS {
+            let runtime = if containers {
+                let image = opts
+                    .images
+                    .get(def.language)
+                    .cloned()
+                    .unwrap_or_else(|| def.image.to_string());
+                Some(CodeRuntime::Container { image })
+            } else {
+                probe(def.probe)
+                    .await
+                    .map(|version| CodeRuntime::Local { version })
+            };
+            if let Some(runtime) = runtime {
+                presets.push(CodePreset {
+                    language: def.language.to_string(),
+                    runtime,
+                    file: def.file,
+                    run: def.run,
+                });
+            }
+        }
+
+        Self {
+            presets,
+            timeout: opts.timeout,
+            container_cli: opts.container_cli,
+        }
+    }
+
+    pub fn presets(&self) -> &[CodePreset] {
+        &self.presets
+    }
+
+    async fn run(
+        &self,
+        preset: &CodePreset,
+        dir: &Path,
+        cancel: &CancellationToken,
+    ) -> Result<serde_json::Value> {
+        let (mut cmd, container_name) = match &preset.runtime {
+            CodeRuntime::Local { .. } => {
+                let mut cmd = Command::new("/bin/sh");
+                cmd.arg("-c").arg(preset.run).current_dir(dir);
+                (cmd, None)
+            }
+            CodeRuntime::Container { image } => {
+                let name = format!("opencraw-code-{}", unique_suffix());
+                let mut cmd = Command::new(&self.container_cli);
+                cmd.args(["run", "--rm", "--name", &name])
+                    .args(["--network", "none", "--memory", "512m", "--cpus", "1"])
+                    .args(["--pids-limit", "256"])
+                    .arg("-v")
+                    .arg(format!("{}:/work", dir.display()))
+                    .args(["-w", "/work", image, "sh", "-c", preset.run]);
+                (cmd, Some(name))
+            }
+        };
+        cmd.stdin(Stdio::null()).kill_on_drop(true);
+
+        let finished = tokio::select! {
+            out = tokio::time::timeout(self.timeout, cmd.output()) => Some(out),
+            _ = cancel.cancelled() => None,
+        };
+        let output = match finished {
+            Some(Ok(out)) => out.map_err(|e| ToolError::ExecutionFailed(e.to_string()))?,
+            stopped => {
+                // Killing the CLI doesn't stop the container.
+                if let Some(name) = container_name {
+                    let _ = Command::new(&self.container_cli)
+                        .args(["rm", "-f", &name])
+                        .output()
+                        .await;
+                }
+                if stopped.is_none() {
+                    return Err(ToolError::Cancelled);
+                }
+                return Err(ToolError::ExecutionFailed(format!(
+                    "{} snippet timed out after {}s",
+                    preset.language,
+                    self.timeout.as_secs()
+                )));
+            }
+        };
+
+        Ok(serde_json::json!({
+            "language": preset.language,
+            "runtime": preset.runtime,
+            "stdout": truncated(&output.stdout),
+            "stderr": truncated(&output.stderr),
+            "exit_code": output.status.code().unwrap_or(-1),
+        }))
+    }
+}
+
+#[async_trait]
+impl Tool for CodeRunTool {
+    fn spec(&self) -> ToolSpec {
+        let languages: Vec<&str> = self.presets.iter().map(|p| p.language.as_str()).collect();
+        ToolSpec {
+            name: "code.run".to_string(),
+            description: format!(
+                "Run a self-contained code snippet and return stdout, stderr and exit code. The snippet is the whole program (a main file). Languages: {}.",
+                languages.join(", ")
+            ),
+            parameters_schema: serde_json::json!({
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "language": { "type": "string", "enum": languages },
+                    "code": { "type": "string" }
+                },
+                "required": ["language", "code"]
+            }),
+            risk_level: RiskLevel::High,
+        }
+    }
+
+    #[tracing::instrument(level = "info", skip_all)]
+    async fn execute(
+        &self,
+        arguments: serde_json::Value,
+        cancel: &CancellationToken,
+    ) -> Result<serde_json::Value> {
+        let language = require_string(&arguments, "language")?;
+        let code = require_string(&arguments, "code")?;
+        let preset = self
+            .presets
+            .iter()
+            .find(|p| p.language == language)
+            .ok_or_else(|| {
+                ToolError::InvalidArguments(format!("language not available here: {language}"))
+            })?;
+
+        let dir = std::env::temp_dir().join(format!("opencraw-code-{}", unique_suffix()));
+        tokio::fs::create_dir_all(&dir).await?;
+        tokio::fs::write(dir.join(preset.file), code).await?;
+        let result = self.run(preset, &dir, cancel).await;
+        if let Err(e) = tokio::fs::remove_dir_all(&dir).await {
+            tracing::debug!(%e, dir = %dir.display(), "failed to remove snippet dir");
+        }
+        result
+    }
+}
+
+/// First line of the command's output if it exits successfully.
+async fn probe(argv: &[&str]) -> Option<String> {
+    let (program, args) = argv.split_first()?;
+    let mut cmd = Command::new(program);
+    cmd.args(args).stdin(Stdio::null()).kill_on_drop(true);
+    let output = tokio::time::timeout(PROBE_TIMEOUT, cmd.output())
+        .await
+        .ok()?
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = if output.stdout.is_empty() {
+        &output.stderr
+    } else {
+        &output.stdout
+    };
+    Some(
+        String::from_utf8_lossy(text)
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+    )
+}
+
+fn truncated(bytes: &[u8]) -> String {
+    let mut text = String::from_utf8_lossy(&bytes[..bytes.len().min(OUTPUT_BYTES_MAX)]).to_string();
+    if bytes.len() > OUTPUT_BYTES_MAX {
+        text.push_str(&format!(
+            "\n[truncated {} bytes]",
+            bytes.len() - OUTPUT_BYTES_MAX
+        ));
+    }
+    text
+}
+
+fn unique_suffix() -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    format!(
+        "{}-{}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn runs_python_snippet_with_local_toolchain() {
+        let tool = CodeRunTool::discover(CodeRunOptions {
+            timeout: Duration::from_secs(20),
+            prefer_containers: false,
+            container_cli: "docker".to_string(),
+            images: HashMap::new(),
+        })
+        .await;
+        // Only meaningful where python3 is installed.
+        if !tool.presets().iter().any(|p| p.language == "python") {
+            return;
+        }
+
+        let out = tool
+            .execute(
+                serde_json::json!({
+                    "language": "python",
+                    "code": "import sys\nprint(6 * 7)\nsys.exit(3)"
+                }),
+                &CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(out["stdout"], "42\n");
+        assert_eq!(out["exit_code"], 3);
+        assert_eq!(out["runtime"]["kind"], "local");
+
+        let err = tool
+            .execute(
+                serde_json::json!({ "language": "cobol", "code": "" }),
+                &CancellationToken::new(),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ToolError::InvalidArguments(_)));
+    }
+}
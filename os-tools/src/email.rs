@@ -0,0 +1,1393 @@
+//! Gmail over the REST API, for triage: find threads, read one, draft a reply, and file
+//! threads away.
+//!
+//! The tool never sends. `draft_reply` leaves a draft in the thread for the owner to
+//! review and send from Gmail, so triaging needs no send approval. `summarize_thread`
+//! returns the thread cut down to what a summary needs (quoted text stripped, long
+//! messages clipped) along with the questions still waiting on the owner.
+//!
+//! Mailbox changes (labels, archiving, read state, stars) are each switched on through
+//! `EmailActions` and apply to whole threads, given by id or by a search query, so
+//! "archive everything from this newsletter" is one call.
+//!
+//! Attachments use the channel attachment shape (`name`, `content_type`, `url`):
+//! `download_attachment` saves one under the download directory and returns it that way,
+//! and `draft_reply` attaches local files given as paths or in that shape, so a file
+//! from another tool or a download can go straight into a reply. Local files must sit
+//! inside the filesystem tool's allowed roots or the download directory.
+
+use crate::error::{Result, ToolError};
+use crate::filesystem::{canonical_roots, ensure_inside};
+use crate::traits::{optional_string, require_string, until_cancelled, Tool, ToolSpec};
+use async_trait::async_trait;
+use base64::Engine as _;
+use horizons_core::core_agents::models::RiskLevel;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+const API_BASE: &str = "https://gmail.googleapis.com/gmail/v1/users/me";
+const TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_LIMIT: u64 = 10;
+const LIMIT_MAX: u64 = 50;
+/// Messages of a thread returned by `summarize_thread`; earlier ones are counted.
+const THREAD_MESSAGES_MAX: usize = 25;
+const MESSAGE_CHARS_MAX: usize = 2000;
+/// Most threads one change applies to when picked by query.
+const BULK_MAX: u64 = 100;
+/// Gmail's own limit on a message's attachments.
+const ATTACHMENT_MAX_BYTES: u64 = 25 * 1024 * 1024;
+/// Always allowed, and listed in this order ahead of the mailbox changes.
+const READ_ACTIONS: &[&str] = &[
+    "search",
+    "summarize_thread",
+    "list_labels",
+    "list_attachments",
+];
+/// Always allowed too, but they write: a draft in the mailbox or a file on disk.
+const FILE_ACTIONS: &[&str] = &["draft_reply", "download_attachment"];
+const CHANGE_ACTIONS: &[&str] = &[
+    "label",
+    "unlabel",
+    "move",
+    "archive",
+    "mark_read",
+    "mark_unread",
+    "star",
+    "unstar",
+];
+
+/// Which mailbox changes the tool may make. Reading and drafting are always allowed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EmailActions {
+    /// `label` and `unlabel` with existing labels.
+    pub labels: bool,
+    /// `archive`, and `move` (out of the inbox into a label) when `labels` is on too.
+    pub archive: bool,
+    /// `mark_read` and `mark_unread`.
+    pub mark_read: bool,
+    /// `star` and `unstar`.
+    pub star: bool,
+}
+
+impl EmailActions {
+    /// Whether `action` may run; `None` for an action that doesn't exist.
+    fn allows(&self, action: &str) -> Option<bool> {
+        match action {
+            action if READ_ACTIONS.contains(&action) || FILE_ACTIONS.contains(&action) => {
+                Some(true)
+            }
+            "label" | "unlabel" => Some(self.labels),
+            "move" => Some(self.labels && self.archive),
+            "archive" => Some(self.archive),
+            "mark_read" | "mark_unread" => Some(self.mark_read),
+            "star" | "unstar" => Some(self.star),
+            _ => None,
+        }
+    }
+
+    /// Searching, reading and listing, which write nothing anywhere.
+    pub fn is_read_only(action: &str) -> bool {
+        READ_ACTIONS.contains(&action)
+    }
+}
+
+pub struct EmailTool {
+    client: reqwest::Client,
+    client_id: String,
+    client_secret: String,
+    refresh_token: String,
+    /// Access token and when it stops working.
+    token: Mutex<Option<(String, Instant)>>,
+    /// The account's own address, to tell the owner's messages from everyone else's.
+    address: Mutex<Option<String>>,
+    actions: EmailActions,
+    download_dir: PathBuf,
+    /// Canonical directories, besides `download_dir`, that drafts may attach files from.
+    attachment_roots: Vec<PathBuf>,
+    /// Largest attachment downloaded, and largest total attached to one draft.
+    attachment_max_bytes: u64,
+}
+
+impl EmailTool {
+    pub fn new(
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+        refresh_token: impl Into<String>,
+    ) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+            refresh_token: refresh_token.into(),
+            token: Mutex::new(None),
+            address: Mutex::new(None),
+            actions: EmailActions::default(),
+            download_dir: std::env::temp_dir().join("opencraw-email"),
+            attachment_roots: Vec::new(),
+            attachment_max_bytes: ATTACHMENT_MAX_BYTES,
+        }
+    }
+
+    pub fn with_actions(mut self, actions: EmailActions) -> Self {
+        self.actions = actions;
+        self
+    }
+
+    /// Save downloads under `dir`, and refuse attachments over `max_bytes`.
+    pub fn with_attachments(mut self, dir: PathBuf, max_bytes: u64) -> Self {
+        self.download_dir = dir;
+        self.attachment_max_bytes = max_bytes;
+        self
+    }
+
+    /// Let drafts attach local files from these directories (the filesystem tool's
+    /// allowed roots). Without it only downloaded attachments can be attached.
+    pub fn with_attachment_roots(mut self, roots: Vec<PathBuf>) -> Result<Self> {
+        self.attachment_roots = canonical_roots(&roots)?;
+        Ok(self)
+    }
+
+    /// Where local attachments may come from: the allowed roots, plus the download
+    /// directory once something has been saved there.
+    async fn attachment_sources(&self) -> Vec<PathBuf> {
+        let mut roots = self.attachment_roots.clone();
+        if let Ok(dir) = tokio::fs::canonicalize(&self.download_dir).await {
+            roots.push(dir);
+        }
+        roots
+    }
+
+    async fn access_token(&self) -> Result<String> {
+        let mut token = self.token.lock().await;
+        if let Some((value, expires)) = token.as_ref() {
+            if Instant::now() < *expires {
+                return Ok(value.clone());
+            }
+        }
+        let resp = self
+            .client
+            .post(TOKEN_URL)
+            .form(&[
+                ("grant_type", "refresh_token"),
+                ("client_id", self.client_id.as_str()),
+                ("client_secret", self.client_secret.as_str()),
+                ("refresh_token", self.refresh_token.as_str()),
+            ])
+            .send()
+            .await
+            .map_err(|e| ToolError::ExecutionFailed(format!("gmail token refresh failed: {e}")))?;
+        if !resp.status().is_success() {
+            return Err(ToolError::Unauthorized(format!(
+                "google rejected the gmail refresh token ({})",
+                resp.status()
+            )));
+        }
+        let body: serde_json::Value = resp.json().await.map_err(|e| {
+            ToolError::ExecutionFailed(format!("gmail token refresh sent invalid JSON: {e}"))
+        })?;
+        let value = body["access_token"]
+            .as_str()
+            .ok_or_else(|| ToolError::Unauthorized("no access_token from google".to_string()))?
+            .to_string();
+        // Refresh a minute early rather than race the expiry.
+        let lifetime = body["expires_in"]
+            .as_u64()
+            .unwrap_or(3600)
+            .saturating_sub(60);
+        *token = Some((
+            value.clone(),
+            Instant::now() + Duration::from_secs(lifetime),
+        ));
+        Ok(value)
+    }
+
+    async fn request(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        query: &[(&str, String)],
+        body: Option<serde_json::Value>,
+    ) -> Result<serde_json::Value> {
+        let token = self.access_token().await?;
+        let mut req = self
+            .client
+            .request(method, format!("{API_BASE}{path}"))
+            .bearer_auth(token)
+            .query(query);
+        if let Some(body) = body {
+            req = req.json(&body);
+        }
+        let resp = req
+            .send()
+            .await
+            .map_err(|e| ToolError::ExecutionFailed(format!("gmail request failed: {e}")))?;
+        let status = resp.status();
+        let text = resp.text().await.unwrap_or_default();
+        if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+            return Err(ToolError::Unauthorized(format!(
+                "gmail refused the request ({status}); the refresh token needs the gmail.compose scope and gmail.readonly, or gmail.modify for changes"
+            )));
+        }
+        if !status.is_success() {
+            return Err(ToolError::ExecutionFailed(format!(
+                "gmail returned {status}: {}",
+                text.trim()
+            )));
+        }
+        serde_json::from_str(&text)
+            .map_err(|e| ToolError::ExecutionFailed(format!("gmail sent invalid JSON: {e}")))
+    }
+
+    async fn own_address(&self) -> Result<String> {
+        let mut address = self.address.lock().await;
+        if let Some(address) = address.as_ref() {
+            return Ok(address.clone());
+        }
+        let profile = self
+            .request(reqwest::Method::GET, "/profile", &[], None)
+            .await?;
+        let value = profile["emailAddress"]
+            .as_str()
+            .ok_or_else(|| ToolError::ExecutionFailed("gmail profile has no address".to_string()))?
+            .to_ascii_lowercase();
+        *address = Some(value.clone());
+        Ok(value)
+    }
+
+    async fn thread(&self, arguments: &serde_json::Value) -> Result<serde_json::Value> {
+        let id = thread_id(require_string(arguments, "thread_id")?)?;
+        self.request(
+            reqwest::Method::GET,
+            &format!("/threads/{id}"),
+            &[("format", "full".to_string())],
+            None,
+        )
+        .await
+    }
+
+    async fn search(&self, arguments: &serde_json::Value) -> Result<serde_json::Value> {
+        let limit = arguments
+            .get("limit")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(DEFAULT_LIMIT)
+            .clamp(1, LIMIT_MAX);
+        let query = optional_string(arguments, "query")?
+            .filter(|q| !q.trim().is_empty())
+            .unwrap_or_else(|| "in:inbox".to_string());
+        let page = self
+            .request(
+                reqwest::Method::GET,
+                "/threads",
+                &[("q", query), ("maxResults", limit.to_string())],
+                None,
+            )
+            .await?;
+        let mut threads = Vec::new();
+        for id in page["threads"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|t| t["id"].as_str())
+        {
+            let thread = self
+                .request(
+                    reqwest::Method::GET,
+                    &format!("/threads/{id}"),
+                    &[
+                        ("format", "metadata".to_string()),
+                        ("metadataHeaders", "From".to_string()),
+                        ("metadataHeaders", "Subject".to_string()),
+                        ("metadataHeaders", "Date".to_string()),
+                    ],
+                    None,
+                )
+                .await?;
+            threads.push(thread_listing(&thread));
+        }
+        Ok(serde_json::json!({ "threads": threads }))
+    }
+
+    async fn summarize_thread(&self, arguments: &serde_json::Value) -> Result<serde_json::Value> {
+        let thread = self.thread(arguments).await?;
+        let me = self.own_address().await?;
+        Ok(thread_summary(&thread, &me))
+    }
+
+    async fn draft_reply(&self, arguments: &serde_json::Value) -> Result<serde_json::Value> {
+        let body = require_string(arguments, "body")?;
+        if body.trim().is_empty() {
+            return Err(ToolError::InvalidArguments("body is empty".to_string()));
+        }
+        let reply_all = arguments
+            .get("reply_all")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        let roots = self.attachment_sources().await;
+        let mut files = Vec::new();
+        for source in arguments
+            .get("attachments")
+            .and_then(|v| v.as_array())
+            .into_iter()
+            .flatten()
+        {
+            files.push(load_attachment(source, &roots).await?);
+        }
+        let total: usize = files.iter().map(|f| f.bytes.len()).sum();
+        if total as u64 > self.attachment_max_bytes {
+            return Err(ToolError::InvalidArguments(format!(
+                "attachments total {total} bytes, over the {} byte limit",
+                self.attachment_max_bytes
+            )));
+        }
+        let thread = self.thread(arguments).await?;
+        let me = self.own_address().await?;
+        let mut draft = reply_draft(&thread, &me, &body, reply_all)?;
+        draft.attachments = files;
+        let raw = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(draft.raw());
+        let created = self
+            .request(
+                reqwest::Method::POST,
+                "/drafts",
+                &[],
+                Some(serde_json::json!({
+                    "message": { "raw": raw, "threadId": thread["id"] }
+                })),
+            )
+            .await?;
+        Ok(serde_json::json!({
+            "draft_id": created["id"],
+            "thread_id": thread["id"],
+            "to": draft.to,
+            "cc": draft.cc,
+            "subject": draft.subject,
+            "attachments": draft.attachments.iter().map(|f| &f.name).collect::<Vec<_>>(),
+            "note": "Saved as a draft in Gmail; nothing was sent.",
+        }))
+    }
+
+    async fn list_attachments(&self, arguments: &serde_json::Value) -> Result<serde_json::Value> {
+        let thread = self.thread(arguments).await?;
+        let messages: Vec<serde_json::Value> = messages(&thread)
+            .iter()
+            .filter_map(|m| {
+                let files = attachment_parts(&m["payload"]);
+                (!files.is_empty()).then(|| {
+                    serde_json::json!({
+                        "message_id": m["id"],
+                        "from": header(m, "From"),
+                        "date": header(m, "Date"),
+                        "attachments": files.iter().map(|f| f.describe()).collect::<Vec<_>>(),
+                    })
+                })
+            })
+            .collect();
+        Ok(serde_json::json!({ "thread_id": thread["id"], "messages": messages }))
+    }
+
+    async fn download_attachment(
+        &self,
+        arguments: &serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        let message_id = thread_id(require_string(arguments, "message_id")?)?;
+        let name = require_string(arguments, "name")?;
+        // Gmail issues a new attachment id on every fetch, so files are named by the
+        // message they're on and their file name.
+        let message = self
+            .request(
+                reqwest::Method::GET,
+                &format!("/messages/{message_id}"),
+                &[("format", "full".to_string())],
+                None,
+            )
+            .await?;
+        let parts = attachment_parts(&message["payload"]);
+        let part = parts.iter().find(|p| p.name == name).ok_or_else(|| {
+            let names: Vec<&str> = parts.iter().map(|p| p.name.as_str()).collect();
+            ToolError::InvalidArguments(format!(
+                "no attachment named {name:?} on that message (have: {})",
+                names.join(", ")
+            ))
+        })?;
+        if part.size_bytes > self.attachment_max_bytes {
+            return Err(ToolError::InvalidArguments(format!(
+                "{name} is {} bytes, over the {} byte limit",
+                part.size_bytes, self.attachment_max_bytes
+            )));
+        }
+        let body = self
+            .request(
+                reqwest::Method::GET,
+                &format!("/messages/{message_id}/attachments/{}", part.attachment_id),
+                &[],
+                None,
+            )
+            .await?;
+        let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(
+                body["data"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .trim_end_matches('='),
+            )
+            .map_err(|e| {
+                ToolError::ExecutionFailed(format!("gmail sent bad attachment data: {e}"))
+            })?;
+        if bytes.len() as u64 > self.attachment_max_bytes {
+            return Err(ToolError::ExecutionFailed(format!(
+                "{name} is over the {} byte limit",
+                self.attachment_max_bytes
+            )));
+        }
+        let dir = self.download_dir.join(&message_id);
+        tokio::fs::create_dir_all(&dir).await?;
+        let path = dir.join(safe_file_name(&name));
+        tokio::fs::write(&path, &bytes).await?;
+        Ok(serde_json::json!({
+            "path": path,
+            "size_bytes": bytes.len(),
+            // Same shape as a channel attachment, so it can be sent or attached as is.
+            "attachment": {
+                "name": name,
+                "content_type": part.content_type,
+                "url": format!("file://{}", path.display()),
+            },
+        }))
+    }
+
+    async fn labels(&self) -> Result<Vec<serde_json::Value>> {
+        let page = self
+            .request(reqwest::Method::GET, "/labels", &[], None)
+            .await?;
+        Ok(page["labels"].as_array().cloned().unwrap_or_default())
+    }
+
+    async fn list_labels(&self) -> Result<serde_json::Value> {
+        let labels: Vec<serde_json::Value> = self
+            .labels()
+            .await?
+            .iter()
+            .map(|label| {
+                serde_json::json!({
+                    "id": label["id"],
+                    "name": label["name"],
+                    "type": label["type"],
+                })
+            })
+            .collect();
+        Ok(serde_json::json!({ "labels": labels }))
+    }
+
+    /// The threads a change applies to: `thread_ids`, `thread_id`, or up to `BULK_MAX`
+    /// matching `query`. Also returns whether the query matched more than that.
+    async fn targets(&self, arguments: &serde_json::Value) -> Result<(Vec<String>, bool)> {
+        if let Some(ids) = arguments.get("thread_ids").and_then(|v| v.as_array()) {
+            let ids = ids
+                .iter()
+                .map(|id| thread_id(id.as_str().unwrap_or_default().to_string()))
+                .collect::<Result<Vec<_>>>()?;
+            return Ok((ids, false));
+        }
+        if let Some(id) = optional_string(arguments, "thread_id")? {
+            return Ok((vec![thread_id(id)?], false));
+        }
+        let Some(query) = optional_string(arguments, "query")?.filter(|q| !q.trim().is_empty())
+        else {
+            return Err(ToolError::InvalidArguments(
+                "name the threads with thread_ids, thread_id or query".to_string(),
+            ));
+        };
+        let page = self
+            .request(
+                reqwest::Method::GET,
+                "/threads",
+                &[("q", query), ("maxResults", BULK_MAX.to_string())],
+                None,
+            )
+            .await?;
+        let ids = page["threads"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|t| t["id"].as_str().map(str::to_string))
+            .collect();
+        Ok((ids, page["nextPageToken"].is_string()))
+    }
+
+    async fn modify(
+        &self,
+        action: &str,
+        arguments: &serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        let (add, remove): (Vec<String>, Vec<String>) = match action {
+            "archive" => (vec![], vec!["INBOX".to_string()]),
+            "mark_read" => (vec![], vec!["UNREAD".to_string()]),
+            "mark_unread" => (vec!["UNREAD".to_string()], vec![]),
+            "star" => (vec!["STARRED".to_string()], vec![]),
+            "unstar" => (vec![], vec!["STARRED".to_string()]),
+            _ => {
+                let names: Vec<String> = arguments
+                    .get("labels")
+                    .and_then(|v| v.as_array())
+                    .map(|names| {
+                        names
+                            .iter()
+                            .filter_map(|n| n.as_str())
+                            .map(str::to_string)
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                if names.is_empty() || (action == "move" && names.len() != 1) {
+                    return Err(ToolError::InvalidArguments(format!(
+                        "{action} needs labels{}",
+                        if action == "move" {
+                            " with one label"
+                        } else {
+                            ""
+                        }
+                    )));
+                }
+                let ids = resolve_labels(&self.labels().await?, &names)?;
+                match action {
+                    "label" => (ids, vec![]),
+                    "unlabel" => (vec![], ids),
+                    _ => (ids, vec!["INBOX".to_string()]),
+                }
+            }
+        };
+        let (threads, more) = self.targets(arguments).await?;
+        for (done, id) in threads.iter().enumerate() {
+            self.request(
+                reqwest::Method::POST,
+                &format!("/threads/{id}/modify"),
+                &[],
+                Some(serde_json::json!({ "addLabelIds": add, "removeLabelIds": remove })),
+            )
+            .await
+            .map_err(|e| {
+                ToolError::ExecutionFailed(format!(
+                    "{action} stopped after {done} of {} threads: {e}",
+                    threads.len()
+                ))
+            })?;
+        }
+        let mut out = serde_json::json!({
+            "action": action,
+            "threads": threads.len(),
+            "thread_ids": threads,
+        });
+        if more {
+            out["note"] = format!(
+                "Only the first {BULK_MAX} matching threads were changed; run it again for the rest."
+            )
+            .into();
+        }
+        Ok(out)
+    }
+}
+
+#[async_trait]
+impl Tool for EmailTool {
+    fn spec(&self) -> ToolSpec {
+        let changes: Vec<&str> = CHANGE_ACTIONS
+            .iter()
+            .copied()
+            .filter(|a| self.actions.allows(a) == Some(true))
+            .collect();
+        let mut description = "The owner's Gmail, for triage; it can't send. search: threads matching a Gmail query (\"is:unread in:inbox\", \"from:alice newer_than:7d\"), with subject, sender and snippet. summarize_thread: a thread's messages without quoted text, plus the questions others asked since the owner last replied; write the summary from it. draft_reply: save a reply to a thread as a Gmail draft for the owner to review and send, optionally with attachments (local paths or attachment objects). list_labels: the mailbox's labels. list_attachments: the files on a thread's messages. download_attachment: save one (by message_id and name) locally and return it as an attachment.".to_string();
+        if !changes.is_empty() {
+            description.push_str(&format!(
+                " {}: change whole threads, named by thread_ids, thread_id or a query (up to {BULK_MAX} threads); move takes one label and removes the thread from the inbox.",
+                changes.join(", ")
+            ));
+        }
+        let actions: Vec<&str> = READ_ACTIONS
+            .iter()
+            .chain(FILE_ACTIONS)
+            .copied()
+            .chain(changes)
+            .collect();
+        ToolSpec {
+            name: "email".to_string(),
+            description,
+            parameters_schema: serde_json::json!({
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "action": { "type": "string", "enum": actions },
+                    "query": { "type": "string", "description": "Gmail search query; in:inbox when omitted for search" },
+                    "limit": { "type": "integer", "minimum": 1, "maximum": LIMIT_MAX },
+                    "thread_id": { "type": "string" },
+                    "thread_ids": { "type": "array", "items": { "type": "string" } },
+                    "labels": { "type": "array", "items": { "type": "string" }, "description": "Label names, for label, unlabel and move" },
+                    "body": { "type": "string", "description": "Plain-text reply, without the quoted thread" },
+                    "reply_all": { "type": "boolean", "description": "Also address everyone else on the last message" },
+                    "attachments": {
+                        "type": "array",
+                        "description": "Files for draft_reply: paths, or { name, content_type, url } with a file:// or data: url",
+                        "items": { "type": ["string", "object"] }
+                    },
+                    "message_id": { "type": "string" },
+                    "name": { "type": "string", "description": "Attachment file name, for download_attachment" }
+                },
+                "required": ["action"]
+            }),
+            // Drafts and downloads are always on; the app lowers plain reads to low
+            // and raises drafts with attachments to high.
+            risk_level: RiskLevel::Medium,
+        }
+    }
+
+    #[tracing::instrument(level = "info", skip_all)]
+    async fn execute(
+        &self,
+        arguments: serde_json::Value,
+        cancel: &CancellationToken,
+    ) -> Result<serde_json::Value> {
+        let action = require_string(&arguments, "action")?;
+        match self.actions.allows(&action) {
+            None => {
+                return Err(ToolError::InvalidArguments(format!(
+                    "unknown action: {action}"
+                )))
+            }
+            Some(false) => {
+                return Err(ToolError::Unauthorized(format!(
+                    "{action} is turned off for this mailbox"
+                )))
+            }
+            Some(true) => {}
+        }
+        match action.as_str() {
+            "search" => until_cancelled(cancel, self.search(&arguments)).await,
+            "summarize_thread" => until_cancelled(cancel, self.summarize_thread(&arguments)).await,
+            "draft_reply" => until_cancelled(cancel, self.draft_reply(&arguments)).await,
+            "list_labels" => until_cancelled(cancel, self.list_labels()).await,
+            "list_attachments" => until_cancelled(cancel, self.list_attachments(&arguments)).await,
+            "download_attachment" => {
+                until_cancelled(cancel, self.download_attachment(&arguments)).await
+            }
+            other => until_cancelled(cancel, self.modify(other, &arguments)).await,
+        }
+    }
+}
+
+fn thread_id(id: String) -> Result<String> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(ToolError::InvalidArguments(format!(
+            "invalid thread id: {id:?}"
+        )));
+    }
+    Ok(id)
+}
+
+/// Label ids for `names`, matched by name (case-insensitively) or id.
+fn resolve_labels(labels: &[serde_json::Value], names: &[String]) -> Result<Vec<String>> {
+    names
+        .iter()
+        .map(|name| {
+            labels
+                .iter()
+                .find(|l| {
+                    l["id"].as_str() == Some(name.trim())
+                        || l["name"]
+                            .as_str()
+                            .is_some_and(|n| n.eq_ignore_ascii_case(name.trim()))
+                })
+                .and_then(|l| l["id"].as_str())
+                .map(str::to_string)
+                .ok_or_else(|| {
+                    let user: Vec<&str> = labels
+                        .iter()
+                        .filter(|l| l["type"].as_str() == Some("user"))
+                        .filter_map(|l| l["name"].as_str())
+                        .collect();
+                    ToolError::InvalidArguments(format!(
+                        "no label named {name:?} (have: {})",
+                        user.join(", ")
+                    ))
+                })
+        })
+        .collect()
+}
+
+fn messages(thread: &serde_json::Value) -> &[serde_json::Value] {
+    thread["messages"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
+fn header<'a>(message: &'a serde_json::Value, name: &str) -> Option<&'a str> {
+    message["payload"]["headers"]
+        .as_array()?
+        .iter()
+        .find(|h| {
+            h["name"]
+                .as_str()
+                .is_some_and(|n| n.eq_ignore_ascii_case(name))
+        })
+        .and_then(|h| h["value"].as_str())
+}
+
+/// The addresses in a header like `"Doe, Jane" <jane@x.com>, bob@y.com`, lowercased.
+fn addresses(value: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in value.chars().chain(std::iter::once(',')) {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                current.push(c);
+            }
+            ',' if !quoted => {
+                let entry = current.trim();
+                let address = match (entry.rfind('<'), entry.rfind('>')) {
+                    (Some(start), Some(end)) if start < end => &entry[start + 1..end],
+                    _ => entry,
+                };
+                if address.contains('@') {
+                    out.push(address.trim().to_ascii_lowercase());
+                }
+                current.clear();
+            }
+            _ => current.push(c),
+        }
+    }
+    out
+}
+
+fn from_address(message: &serde_json::Value) -> Option<String> {
+    header(message, "From").and_then(|from| addresses(from).into_iter().next())
+}
+
+fn thread_listing(thread: &serde_json::Value) -> serde_json::Value {
+    let all = messages(thread);
+    let (first, last) = (all.first(), all.last());
+    serde_json::json!({
+        "thread_id": thread["id"],
+        "subject": first.and_then(|m| header(m, "Subject")),
+        "from": last.and_then(|m| header(m, "From")),
+        "date": last.and_then(|m| header(m, "Date")),
+        "messages": all.len(),
+        "unread": all.iter().any(|m| labelled(m, "UNREAD")),
+        "snippet": last.map(|m| m["snippet"].clone()),
+    })
+}
+
+fn labelled(message: &serde_json::Value, label: &str) -> bool {
+    message["labelIds"]
+        .as_array()
+        .is_some_and(|labels| labels.iter().any(|l| l.as_str() == Some(label)))
+}
+
+fn thread_summary(thread: &serde_json::Value, me: &str) -> serde_json::Value {
+    let all = messages(thread);
+    let mine = |m: &serde_json::Value| from_address(m).as_deref() == Some(me);
+    let mut participants: Vec<String> = Vec::new();
+    for message in all {
+        for name in ["From", "To", "Cc"] {
+            for address in header(message, name).map(addresses).unwrap_or_default() {
+                if address != me && !participants.contains(&address) {
+                    participants.push(address);
+                }
+            }
+        }
+    }
+    // Questions count as open when nobody has answered them from this account yet.
+    let unanswered = all.iter().rposition(mine).map_or(0, |i| i + 1);
+    let open_questions: Vec<serde_json::Value> = all[unanswered..]
+        .iter()
+        .flat_map(|m| {
+            let from = header(m, "From").unwrap_or_default().to_string();
+            questions(&reply_text(m))
+                .into_iter()
+                .map(move |q| serde_json::json!({ "from": from, "question": q }))
+        })
+        .collect();
+    let skipped = all.len().saturating_sub(THREAD_MESSAGES_MAX);
+    let shown: Vec<serde_json::Value> = all[skipped..]
+        .iter()
+        .map(|m| {
+            serde_json::json!({
+                "from": header(m, "From"),
+                "date": header(m, "Date"),
+                "message_id": m["id"],
+                "mine": mine(m),
+                "text": clip(&reply_text(m)),
+                "attachments": attachment_parts(&m["payload"])
+                    .iter()
+                    .map(|f| f.name.clone())
+                    .collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "thread_id": thread["id"],
+        "subject": all.first().and_then(|m| header(m, "Subject")),
+        "participants": participants,
+        "message_count": all.len(),
+        "earlier_messages_omitted": skipped,
+        "awaiting_my_reply": all.last().is_some_and(|m| !mine(m)),
+        "open_questions": open_questions,
+        "messages": shown,
+    })
+}
+
+/// The message's own text: the plain-text body (or the HTML one with tags removed),
+/// without the quoted history most replies carry.
+fn reply_text(message: &serde_json::Value) -> String {
+    let body = body_part(&message["payload"], "text/plain")
+        .or_else(|| body_part(&message["payload"], "text/html").map(|html| strip_tags(&html)))
+        .unwrap_or_else(|| message["snippet"].as_str().unwrap_or_default().to_string());
+    let mut out = Vec::new();
+    for line in body.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("-----Original Message-----")
+            || (trimmed.starts_with("On ") && trimmed.ends_with("wrote:"))
+        {
+            break;
+        }
+        if !trimmed.starts_with('>') {
+            out.push(line.trim_end());
+        }
+    }
+    out.join("\n").trim().to_string()
+}
+
+fn body_part(part: &serde_json::Value, mime_type: &str) -> Option<String> {
+    if part["mimeType"].as_str() == Some(mime_type) {
+        if let Some(data) = part["body"]["data"].as_str() {
+            let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+                .decode(data.trim_end_matches('='))
+                .ok()?;
+            return Some(String::from_utf8_lossy(&bytes).into_owned());
+        }
+    }
+    part["parts"]
+        .as_array()?
+        .iter()
+        .find_map(|p| body_part(p, mime_type))
+}
+
+fn strip_tags(html: &str) -> String {
+    let mut out = String::new();
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                out.push(' ');
+            }
+            _ if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out.replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+}
+
+fn questions(text: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut sentence = String::new();
+    for c in text.chars() {
+        match c {
+            '\n' | '.' | '!' => sentence.clear(),
+            '?' => {
+                let question = format!("{}?", sentence.trim());
+                if question.chars().filter(|c| c.is_alphanumeric()).count() > 2 {
+                    out.push(question);
+                }
+                sentence.clear();
+            }
+            _ => sentence.push(c),
+        }
+    }
+    out
+}
+
+fn clip(text: &str) -> String {
+    let mut out: String = text.chars().take(MESSAGE_CHARS_MAX).collect();
+    if out.len() < text.len() {
+        out.push('…');
+    }
+    out
+}
+
+/// A file on a message, as Gmail lists it.
+struct AttachmentPart {
+    name: String,
+    content_type: String,
+    size_bytes: u64,
+    attachment_id: String,
+}
+
+impl AttachmentPart {
+    fn describe(&self) -> serde_json::Value {
+        serde_json::json!({
+            "name": self.name,
+            "content_type": self.content_type,
+            "size_bytes": self.size_bytes,
+        })
+    }
+}
+
+fn attachment_parts(part: &serde_json::Value) -> Vec<AttachmentPart> {
+    let mut out = Vec::new();
+    if let (Some(name), Some(id)) = (
+        part["filename"].as_str().filter(|n| !n.is_empty()),
+        part["body"]["attachmentId"].as_str(),
+    ) {
+        out.push(AttachmentPart {
+            name: name.to_string(),
+            content_type: part["mimeType"]
+                .as_str()
+                .unwrap_or("application/octet-stream")
+                .to_string(),
+            size_bytes: part["body"]["size"].as_u64().unwrap_or(0),
+            attachment_id: id.to_string(),
+        });
+    }
+    for child in part["parts"].as_array().into_iter().flatten() {
+        out.extend(attachment_parts(child));
+    }
+    out
+}
+
+/// A file to attach to a draft.
+struct DraftFile {
+    name: String,
+    content_type: String,
+    bytes: Vec<u8>,
+}
+
+/// Read a file given as a path, a `file://` or `data:` url, or a channel attachment
+/// (`{ name, content_type, url }`). Paths must resolve inside `roots`.
+async fn load_attachment(source: &serde_json::Value, roots: &[PathBuf]) -> Result<DraftFile> {
+    let (url, name, content_type) = match source {
+        serde_json::Value::String(url) => (url.as_str(), None, None),
+        serde_json::Value::Object(_) => (
+            source["url"].as_str().ok_or_else(|| {
+                ToolError::InvalidArguments("attachment objects need a url".to_string())
+            })?,
+            source["name"].as_str(),
+            source["content_type"].as_str(),
+        ),
+        other => {
+            return Err(ToolError::InvalidArguments(format!(
+                "attachments must be paths or attachment objects, got {other}"
+            )))
+        }
+    };
+    if url.starts_with("http://") || url.starts_with("https://") {
+        return Err(ToolError::InvalidArguments(format!(
+            "{url} is remote; download it first and attach the local file"
+        )));
+    }
+    if let Some(data) = url.strip_prefix("data:") {
+        let (meta, payload) = data.split_once(',').ok_or_else(|| {
+            ToolError::InvalidArguments("malformed data: url attachment".to_string())
+        })?;
+        let bytes = match meta.strip_suffix(";base64") {
+            Some(_) => base64::engine::general_purpose::STANDARD
+                .decode(payload)
+                .map_err(|e| ToolError::InvalidArguments(format!("bad base64 attachment: {e}")))?,
+            None => payload.as_bytes().to_vec(),
+        };
+        let meta_type = meta.trim_end_matches(";base64");
+        return Ok(DraftFile {
+            name: name.unwrap_or("attachment").to_string(),
+            content_type: content_type
+                .or((!meta_type.is_empty()).then_some(meta_type))
+                .unwrap_or("application/octet-stream")
+                .to_string(),
+            bytes,
+        });
+    }
+    let path = Path::new(url.strip_prefix("file://").unwrap_or(url));
+    ensure_inside(path, roots).await?;
+    let bytes = tokio::fs::read(path)
+        .await
+        .map_err(|e| ToolError::InvalidArguments(format!("read {}: {e}", path.display())))?;
+    let name = name
+        .map(str::to_string)
+        .or_else(|| path.file_name().map(|n| n.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "attachment".to_string());
+    Ok(DraftFile {
+        content_type: content_type
+            .unwrap_or_else(|| guess_content_type(&name))
+            .to_string(),
+        name,
+        bytes,
+    })
+}
+
+fn guess_content_type(name: &str) -> &'static str {
+    let ext = name
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "pdf" => "application/pdf",
+        "txt" => "text/plain",
+        "csv" => "text/csv",
+        "html" | "htm" => "text/html",
+        "ics" => "text/calendar",
+        "zip" => "application/zip",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "pptx" => "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        _ => "application/octet-stream",
+    }
+}
+
+/// A download's file name, without path separators or a leading dot.
+fn safe_file_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | '\0' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    match cleaned.trim_start_matches('.') {
+        "" => "attachment".to_string(),
+        name => name.to_string(),
+    }
+}
+
+/// A header value as an RFC 2047 encoded word when it isn't plain ASCII.
+fn encoded_word(value: &str) -> String {
+    if value.is_ascii() {
+        value.to_string()
+    } else {
+        format!(
+            "=?UTF-8?B?{}?=",
+            base64::engine::general_purpose::STANDARD.encode(value)
+        )
+    }
+}
+
+struct Draft {
+    to: Vec<String>,
+    cc: Vec<String>,
+    subject: String,
+    in_reply_to: Option<String>,
+    references: Option<String>,
+    body: String,
+    attachments: Vec<DraftFile>,
+}
+
+impl Draft {
+    /// The RFC 2822 message Gmail stores as the draft.
+    fn raw(&self) -> String {
+        let mut out = format!("To: {}\r\n", self.to.join(", "));
+        if !self.cc.is_empty() {
+            out.push_str(&format!("Cc: {}\r\n", self.cc.join(", ")));
+        }
+        out.push_str(&format!("Subject: {}\r\n", encoded_word(&self.subject)));
+        if let Some(id) = &self.in_reply_to {
+            out.push_str(&format!("In-Reply-To: {id}\r\n"));
+        }
+        if let Some(references) = &self.references {
+            out.push_str(&format!("References: {references}\r\n"));
+        }
+        out.push_str("MIME-Version: 1.0\r\n");
+        let text = format!(
+            "Content-Type: text/plain; charset=\"UTF-8\"\r\nContent-Transfer-Encoding: 8bit\r\n\r\n{}",
+            self.body.replace("\r\n", "\n").replace('\n', "\r\n")
+        );
+        if self.attachments.is_empty() {
+            out.push_str(&text);
+            return out;
+        }
+        let boundary = format!(
+            "opencraw-{:x}",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos()
+        );
+        out.push_str(&format!(
+            "Content-Type: multipart/mixed; boundary=\"{boundary}\"\r\n\r\n--{boundary}\r\n{text}\r\n"
+        ));
+        for file in &self.attachments {
+            let name = encoded_word(&file.name.replace(['"', '\r', '\n'], "_"));
+            let content_type = file.content_type.replace(['\r', '\n'], "");
+            out.push_str(&format!(
+                "--{boundary}\r\nContent-Type: {content_type}; name=\"{name}\"\r\nContent-Disposition: attachment; filename=\"{name}\"\r\nContent-Transfer-Encoding: base64\r\n\r\n"
+            ));
+            let encoded = base64::engine::general_purpose::STANDARD.encode(&file.bytes);
+            for line in encoded.as_bytes().chunks(76) {
+                out.push_str(&String::from_utf8_lossy(line));
+                out.push_str("\r\n");
+            }
+        }
+        out.push_str(&format!("--{boundary}--\r\n"));
+        out
+    }
+}
+
+/// A reply to the last message someone else sent, or to the owner's own recipients when
+/// they wrote last (a follow-up).
+fn reply_draft(thread: &serde_json::Value, me: &str, body: &str, reply_all: bool) -> Result<Draft> {
+    let all = messages(thread);
+    let mine = |m: &serde_json::Value| from_address(m).as_deref() == Some(me);
+    let last = all
+        .iter()
+        .rev()
+        .find(|m| !mine(m))
+        .or_else(|| all.last())
+        .ok_or_else(|| ToolError::InvalidArguments("the thread has no messages".to_string()))?;
+    let list = |name: &str| header(last, name).map(addresses).unwrap_or_default();
+    let mut to = if mine(last) {
+        list("To")
+    } else if header(last, "Reply-To").is_some() {
+        list("Reply-To")
+    } else {
+        list("From")
+    };
+    let mut cc = Vec::new();
+    if reply_all {
+        for address in list("To").into_iter().chain(list("Cc")) {
+            if address != me && !to.contains(&address) && !cc.contains(&address) {
+                cc.push(address);
+            }
+        }
+    }
+    to.retain(|a| a != me);
+    if to.is_empty() {
+        return Err(ToolError::InvalidArguments(
+            "nobody to reply to in this thread".to_string(),
+        ));
+    }
+    let single_line = |s: &str| s.replace(['\r', '\n'], " ");
+    let subject = single_line(header(last, "Subject").unwrap_or_default().trim());
+    let subject = if subject.to_ascii_lowercase().starts_with("re:") {
+        subject
+    } else {
+        format!("Re: {subject}")
+    };
+    let in_reply_to = header(last, "Message-ID").map(single_line);
+    let references = match (header(last, "References"), in_reply_to.as_deref()) {
+        (Some(refs), Some(id)) => Some(format!("{} {id}", single_line(refs))),
+        (None, Some(id)) => Some(id.to_string()),
+        (Some(refs), None) => Some(single_line(refs)),
+        (None, None) => None,
+    };
+    Ok(Draft {
+        to,
+        cc,
+        subject,
+        in_reply_to,
+        references,
+        body: body.to_string(),
+        attachments: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(from: &str, to: &str, cc: &str, text: &str) -> serde_json::Value {
+        serde_json::json!({
+            "labelIds": ["INBOX"],
+            "snippet": "",
+            "payload": {
+                "mimeType": "multipart/alternative",
+                "headers": [
+                    { "name": "From", "value": from },
+                    { "name": "To", "value": to },
+                    { "name": "Cc", "value": cc },
+                    { "name": "Subject", "value": "Offsite venue" },
+                    { "name": "Message-ID", "value": format!("<{}@mail>", text.len()) },
+                ],
+                "parts": [{
+                    "mimeType": "text/plain",
+                    "body": { "data": base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(text) }
+                }]
+            }
+        })
+    }
+
+    fn thread() -> serde_json::Value {
+        serde_json::json!({
+            "id": "18c2f",
+            "messages": [
+                message("\"Lee, Sam\" <sam@acme.test>", "me@example.com", "", "Can we book the lake house? Budget is 5k."),
+                message("Me <me@example.com>", "sam@acme.test", "", "Yes, looks good."),
+                message(
+                    "Sam <sam@acme.test>",
+                    "me@example.com",
+                    "kim@acme.test",
+                    "Great. Do you want catering too? And is Friday ok?\n\nOn Tue, Me wrote:\n> Yes, looks good.",
+                ),
+            ]
+        })
+    }
+
+    #[test]
+    fn summary_strips_quotes_and_lists_questions_since_my_last_reply() {
+        let summary = thread_summary(&thread(), "me@example.com");
+        assert_eq!(
+            summary["participants"],
+            serde_json::json!(["sam@acme.test", "kim@acme.test"])
+        );
+        assert_eq!(summary["awaiting_my_reply"], true);
+        assert_eq!(
+            summary["open_questions"],
+            serde_json::json!([
+                { "from": "Sam <sam@acme.test>", "question": "Do you want catering too?" },
+                { "from": "Sam <sam@acme.test>", "question": "And is Friday ok?" },
+            ])
+        );
+        assert_eq!(
+            summary["messages"][2]["text"],
+            "Great. Do you want catering too? And is Friday ok?"
+        );
+        assert_eq!(summary["messages"][1]["mine"], true);
+    }
+
+    #[test]
+    fn only_searching_reading_and_listing_are_read_only() {
+        for action in READ_ACTIONS {
+            assert!(EmailActions::is_read_only(action));
+        }
+        for action in FILE_ACTIONS.iter().chain(CHANGE_ACTIONS) {
+            assert!(!EmailActions::is_read_only(action), "{action}");
+        }
+        assert_eq!(EmailActions::default().allows("draft_reply"), Some(true));
+        assert_eq!(EmailActions::default().allows("archive"), Some(false));
+    }
+
+    #[test]
+    fn drafts_reply_to_the_last_sender_and_threads_it() {
+        let draft = reply_draft(&thread(), "me@example.com", "Friday works.", true).unwrap();
+        assert_eq!(draft.to, vec!["sam@acme.test"]);
+        assert_eq!(draft.cc, vec!["kim@acme.test"]);
+        let raw = draft.raw();
+        assert!(raw.starts_with(
+            "To: sam@acme.test\r\nCc: kim@acme.test\r\nSubject: Re: Offsite venue\r\n"
+        ));
+        assert!(raw.contains("In-Reply-To: <"));
+        assert!(raw.ends_with("\r\n\r\nFriday works."));
+
+        let draft = reply_draft(&thread(), "me@example.com", "Hi", false).unwrap();
+        assert!(draft.cc.is_empty());
+    }
+
+    #[tokio::test]
+    async fn lists_attachments_and_attaches_files_to_drafts() {
+        let payload = serde_json::json!({
+            "mimeType": "multipart/mixed",
+            "parts": [
+                { "mimeType": "text/plain", "filename": "", "body": { "size": 5, "data": "aGVsbG8" } },
+                { "mimeType": "application/pdf", "filename": "invoice.pdf", "body": { "size": 48211, "attachmentId": "ANGjdJ8" } },
+            ]
+        });
+        let parts = attachment_parts(&payload);
+        assert_eq!(parts.len(), 1);
+        assert_eq!(
+            parts[0].describe(),
+            serde_json::json!({ "name": "invoice.pdf", "content_type": "application/pdf", "size_bytes": 48211 })
+        );
+        assert_eq!(safe_file_name("../../.ssh/id_rsa"), "_.._.ssh_id_rsa");
+
+        let file = load_attachment(
+            &serde_json::json!({
+                "name": "notes.txt",
+                "content_type": "text/plain",
+                "url": "data:text/plain;base64,aGk=",
+            }),
+            &[],
+        )
+        .await
+        .unwrap();
+        assert_eq!(file.bytes, b"hi");
+        let mut draft = reply_draft(&thread(), "me@example.com", "See attached.", false).unwrap();
+        draft.attachments.push(file);
+        let raw = draft.raw();
+        assert!(raw.contains("Content-Type: multipart/mixed; boundary=\"opencraw-"));
+        assert!(raw.contains("\r\n\r\nSee attached.\r\n"));
+        assert!(raw.contains(
+            "Content-Type: text/plain; name=\"notes.txt\"\r\nContent-Disposition: attachment; filename=\"notes.txt\"\r\nContent-Transfer-Encoding: base64\r\n\r\naGk=\r\n"
+        ));
+        assert!(raw.trim_end().ends_with("--"));
+        assert!(
+            load_attachment(&serde_json::json!("https://example.com/a.pdf"), &[])
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn draft_attachments_stay_inside_allowed_roots() {
+        let shared = tempfile::tempdir().unwrap();
+        std::fs::write(shared.path().join("report.txt"), "q3").unwrap();
+        let tool = EmailTool::new("id", "secret", "refresh")
+            .with_attachments(shared.path().join("downloads"), ATTACHMENT_MAX_BYTES)
+            .with_attachment_roots(vec![shared.path().to_path_buf()])
+            .unwrap();
+        let roots = tool.attachment_sources().await;
+
+        let inside = shared.path().join("report.txt");
+        let file = load_attachment(&serde_json::json!(inside.to_string_lossy()), &roots)
+            .await
+            .unwrap();
+        assert_eq!(file.bytes, b"q3");
+        for path in ["/etc/passwd", "file:///root/.ssh/id_rsa"] {
+            let err = load_attachment(&serde_json::json!(path), &roots)
+                .await
+                .err()
+                .unwrap();
+            assert!(matches!(err, ToolError::Unauthorized(_)), "{path}: {err}");
+        }
+        let err = load_attachment(
+            &serde_json::json!({ "name": "keys", "url": "file:///etc/passwd" }),
+            &[],
+        )
+        .await
+        .err()
+        .unwrap();
+        assert!(matches!(err, ToolError::Unauthorized(_)));
+    }
+
+    #[test]
+    fn changes_need_their_toggle_and_known_labels() {
+        let actions = EmailActions {
+            archive: true,
+            ..EmailActions::default()
+        };
+        assert_eq!(actions.allows("summarize_thread"), Some(true));
+        assert_eq!(actions.allows("archive"), Some(true));
+        assert_eq!(actions.allows("move"), Some(false));
+        assert_eq!(actions.allows("star"), Some(false));
+        assert_eq!(actions.allows("send"), None);
+
+        let labels = vec![
+            serde_json::json!({ "id": "INBOX", "name": "INBOX", "type": "system" }),
+            serde_json::json!({ "id": "Label_7", "name": "Newsletters", "type": "user" }),
+        ];
+        assert_eq!(
+            resolve_labels(&labels, &["newsletters".to_string(), "INBOX".to_string()]).unwrap(),
+            vec!["Label_7", "INBOX"]
+        );
+        let err = resolve_labels(&labels, &["Receipts".to_string()]).unwrap_err();
+        assert!(err.to_string().contains("(have: Newsletters)"));
+    }
+}
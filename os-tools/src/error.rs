@@ -15,6 +15,10 @@ pub enum ToolError {
 
     #[error("io error: {0}")]
     Io(String),
+
+    /// The run was stopped (`/stop`, a timeout, a cancelled task) while the tool ran.
+    #[error("cancelled")]
+    Cancelled,
 }
 
 impl From<std::io::Error> for ToolError {
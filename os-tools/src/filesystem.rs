@@ -4,11 +4,32 @@ use async_trait::async_trait;
 use horizons_core::core_agents::models::RiskLevel;
 use regex::Regex;
 use std::path::{Component, Path, PathBuf};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader};
+use tokio_util::sync::CancellationToken;
 
 pub struct FilesystemTool {
     root_dir: PathBuf,
+    /// Canonical directories every access must stay inside. Defaults to `root_dir`.
+    allowed_roots: Vec<PathBuf>,
     search_results_max: usize,
     file_bytes_max: usize,
+    page_bytes_max: usize,
+    page_lines_max: usize,
+}
+
+/// Which slice of a file `read_file` should return.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReadRange {
+    /// No paging arguments: the whole file if it fits, else the first page.
+    Whole,
+    Bytes {
+        offset: u64,
+        length: usize,
+    },
+    Lines {
+        start_line: u64,
+        line_count: usize,
+    },
 }
 
 impl FilesystemTool {
@@ -19,48 +40,220 @@ impl FilesystemTool {
                 "root_dir is required".to_string(),
             ));
         }
+        let allowed_roots =
+            vec![std::fs::canonicalize(&root_dir).unwrap_or_else(|_| root_dir.clone())];
         Ok(Self {
             root_dir,
+            allowed_roots,
             search_results_max: 200,
             file_bytes_max: 1_000_000,
+            page_bytes_max: 64 * 1024,
+            page_lines_max: 2_000,
         })
     }
 
-    fn resolve_path(&self, user_path: &str) -> Result<PathBuf> {
-        let rel = Path::new(user_path);
-        if rel.is_absolute() {
-            return Err(ToolError::Unauthorized(
-                "absolute paths are not allowed".to_string(),
-            ));
+    /// Replace the default jail (`root_dir`) with these directories. Relative paths still
+    /// resolve against `root_dir`; absolute paths are accepted when inside a root.
+    pub fn with_allowed_roots(mut self, roots: Vec<PathBuf>) -> Result<Self> {
+        if roots.is_empty() {
+            return Ok(self);
         }
+        self.allowed_roots = canonical_roots(&roots)?;
+        Ok(self)
+    }
+
+    fn resolve_path(&self, user_path: &str) -> Result<PathBuf> {
+        let requested = Path::new(user_path);
+        let mut resolved = if requested.is_absolute() {
+            PathBuf::new()
+        } else {
+            self.root_dir.clone()
+        };
 
-        for component in rel.components() {
+        for component in requested.components() {
             match component {
                 Component::ParentDir => {
                     return Err(ToolError::Unauthorized(
                         "path traversal is not allowed".to_string(),
                     ));
                 }
-                Component::CurDir | Component::Normal(_) => {}
+                Component::CurDir => {}
+                Component::Normal(c) => resolved.push(c),
+                Component::RootDir | Component::Prefix(_) if requested.is_absolute() => {
+                    resolved.push(component)
+                }
                 Component::RootDir | Component::Prefix(_) => {
                     return Err(ToolError::Unauthorized("invalid path".to_string()));
                 }
             }
         }
 
-        Ok(self.root_dir.join(rel))
+        Ok(resolved)
     }
 
-    async fn read_file(&self, path: &Path) -> Result<String> {
-        let bytes = tokio::fs::read(path).await?;
-        if bytes.len() > self.file_bytes_max {
-            return Err(ToolError::ExecutionFailed(format!(
-                "file too large: {} bytes (max {})",
-                bytes.len(),
-                self.file_bytes_max
-            )));
+    fn read_range(&self, args: &serde_json::Value) -> Result<ReadRange> {
+        let offset = optional_u64(args, "offset")?;
+        let length = optional_u64(args, "length")?;
+        let start_line = optional_u64(args, "start_line")?;
+        let line_count = optional_u64(args, "line_count")?;
+
+        if start_line.is_some() || line_count.is_some() {
+            if offset.is_some() || length.is_some() {
+                return Err(ToolError::InvalidArguments(
+                    "use either offset/length or start_line/line_count, not both".to_string(),
+                ));
+            }
+            let start_line = start_line.unwrap_or(1).max(1);
+            let line_count = line_count
+                .map(|n| n as usize)
+                .unwrap_or(200)
+                .clamp(1, self.page_lines_max);
+            return Ok(ReadRange::Lines {
+                start_line,
+                line_count,
+            });
+        }
+        if offset.is_some() || length.is_some() {
+            let length = length
+                .map(|n| n as usize)
+                .unwrap_or(self.page_bytes_max)
+                .clamp(1, self.page_bytes_max);
+            return Ok(ReadRange::Bytes {
+                offset: offset.unwrap_or(0),
+                length,
+            });
+        }
+        Ok(ReadRange::Whole)
+    }
+
+    async fn read_file(&self, path: &Path, range: ReadRange) -> Result<serde_json::Value> {
+        let total_bytes = tokio::fs::metadata(path).await?.len();
+        match range {
+            ReadRange::Whole if total_bytes as usize <= self.file_bytes_max => {
+                let bytes = tokio::fs::read(path).await?;
+                Ok(serde_json::json!({ "content": String::from_utf8_lossy(&bytes) }))
+            }
+            ReadRange::Whole => {
+                self.read_bytes(path, 0, self.page_bytes_max, total_bytes)
+                    .await
+            }
+            ReadRange::Bytes { offset, length } => {
+                self.read_bytes(path, offset, length, total_bytes).await
+            }
+            ReadRange::Lines {
+                start_line,
+                line_count,
+            } => self.read_lines(path, start_line, line_count).await,
+        }
+    }
+
+    async fn read_bytes(
+        &self,
+        path: &Path,
+        offset: u64,
+        length: usize,
+        total_bytes: u64,
+    ) -> Result<serde_json::Value> {
+        let mut file = tokio::fs::File::open(path).await?;
+        file.seek(std::io::SeekFrom::Start(offset)).await?;
+        let mut buf = Vec::with_capacity(length);
+        (&mut file)
+            .take(length as u64)
+            .read_to_end(&mut buf)
+            .await?;
+
+        // Don't split a UTF-8 sequence across pages; the next page starts at the cut. A
+        // page too short for the character it starts with gets that whole character.
+        let end = offset + buf.len() as u64;
+        if end < total_bytes {
+            match utf8_boundary(&buf) {
+                0 if !buf.is_empty() => {
+                    let rest = utf8_width(buf[0]).saturating_sub(buf.len());
+                    (&mut file).take(rest as u64).read_to_end(&mut buf).await?;
+                }
+                keep => buf.truncate(keep),
+            }
         }
-        Ok(String::from_utf8_lossy(&bytes).to_string())
+        let next_offset = offset + buf.len() as u64;
+        let eof = next_offset >= total_bytes;
+        Ok(serde_json::json!({
+            "content": String::from_utf8_lossy(&buf),
+            "offset": offset,
+            "bytes_read": buf.len(),
+            "total_bytes": total_bytes,
+            "eof": eof,
+            "next_cursor": if eof { serde_json::Value::Null } else {
+                serde_json::json!({ "offset": next_offset, "length": length })
+            },
+        }))
+    }
+
+    async fn read_lines(
+        &self,
+        path: &Path,
+        start_line: u64,
+        line_count: usize,
+    ) -> Result<serde_json::Value> {
+        let file = tokio::fs::File::open(path).await?;
+        let mut reader = BufReader::new(file);
+        let mut line = Vec::new();
+        let mut line_no = 0u64;
+        // Byte offset of the start of `line`.
+        let mut line_offset = 0u64;
+        let mut content = String::new();
+        let mut lines_read = 0usize;
+        let mut eof = false;
+        // Where the rest of a line longer than a page starts.
+        let mut cut_at = None;
+
+        loop {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line).await?;
+            if read == 0 {
+                eof = true;
+                break;
+            }
+            line_no += 1;
+            if line_no < start_line {
+                line_offset += read as u64;
+                continue;
+            }
+            if lines_read >= line_count || content.len() + line.len() > self.page_bytes_max {
+                if lines_read == 0 {
+                    // The first line alone is over a page: return its start and carry on
+                    // from there by byte offset, or this page would be empty forever.
+                    let keep = utf8_boundary(&line[..self.page_bytes_max]);
+                    content.push_str(&String::from_utf8_lossy(&line[..keep]));
+                    cut_at = Some(line_offset + keep as u64);
+                }
+                // This line belongs to the next page.
+                line_no -= 1;
+                break;
+            }
+            content.push_str(&String::from_utf8_lossy(&line));
+            lines_read += 1;
+            line_offset += read as u64;
+        }
+
+        let next_line = line_no + 1;
+        let next_cursor = match cut_at {
+            Some(offset) => {
+                serde_json::json!({ "offset": offset, "length": self.page_bytes_max })
+            }
+            None if eof => serde_json::Value::Null,
+            None => serde_json::json!({ "start_line": next_line, "line_count": line_count }),
+        };
+        let mut out = serde_json::json!({
+            "content": content,
+            "start_line": start_line,
+            "lines_read": lines_read,
+            "eof": eof,
+            "next_cursor": next_cursor,
+        });
+        if cut_at.is_some() {
+            out["truncated_line"] = serde_json::json!(next_line);
+        }
+        Ok(out)
     }
 
     async fn write_file(&self, path: &Path, content: &str) -> Result<()> {
@@ -74,7 +267,17 @@ impl FilesystemTool {
         if let Some(parent) = path.parent() {
             tokio::fs::create_dir_all(parent).await?;
         }
-        tokio::fs::write(path, content).await?;
+        // A link swapped in after the check isn't followed, and the file is checked again
+        // once it exists, before anything is truncated or written.
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create(true);
+        #[cfg(unix)]
+        options.custom_flags(libc::O_NOFOLLOW);
+        let mut file = options.open(path).await?;
+        ensure_inside(path, &self.allowed_roots).await?;
+        file.set_len(0).await?;
+        file.write_all(content.as_bytes()).await?;
+        file.flush().await?;
         Ok(())
     }
 
@@ -128,9 +331,8 @@ impl FilesystemTool {
 
                 let name = p.file_name().and_then(|s| s.to_str()).unwrap_or("");
                 if regex.is_match(name) {
-                    if let Ok(rel) = p.strip_prefix(&self.root_dir) {
-                        out.push(rel.to_string_lossy().to_string());
-                    }
+                    let shown = p.strip_prefix(&self.root_dir).unwrap_or(&p);
+                    out.push(shown.to_string_lossy().to_string());
                     if out.len() >= self.search_results_max {
                         return Ok(out);
                     }
@@ -147,7 +349,7 @@ impl Tool for FilesystemTool {
     fn spec(&self) -> ToolSpec {
         ToolSpec {
             name: "filesystem".to_string(),
-            description: "Read and write files within a configured root directory.".to_string(),
+            description: "Read and write files within the configured root directories. Relative paths resolve against the root directory. Large files are read in pages: pass the returned next_cursor fields back to continue. A line longer than a page comes back cut (truncated_line), with a byte-offset cursor to the rest of it.".to_string(),
             parameters_schema: serde_json::json!({
                 "type": "object",
                 "additionalProperties": false,
@@ -155,7 +357,11 @@ impl Tool for FilesystemTool {
                     "action": { "type": "string", "enum": ["read_file", "write_file", "list_dir", "search_files"] },
                     "path": { "type": "string" },
                     "content": { "type": "string" },
-                    "pattern": { "type": "string" }
+                    "pattern": { "type": "string" },
+                    "offset": { "type": "integer", "minimum": 0, "description": "read_file: byte offset to start at" },
+                    "length": { "type": "integer", "minimum": 1, "description": "read_file: max bytes to return (<= 65536)" },
+                    "start_line": { "type": "integer", "minimum": 1, "description": "read_file: first line to return (1-based)" },
+                    "line_count": { "type": "integer", "minimum": 1, "description": "read_file: max lines to return (<= 2000)" }
                 },
                 "required": ["action", "path"]
             }),
@@ -164,15 +370,20 @@ impl Tool for FilesystemTool {
     }
 
     #[tracing::instrument(level = "info", skip_all)]
-    async fn execute(&self, arguments: serde_json::Value) -> Result<serde_json::Value> {
+    async fn execute(
+        &self,
+        arguments: serde_json::Value,
+        _cancel: &CancellationToken,
+    ) -> Result<serde_json::Value> {
         let action = require_string(&arguments, "action")?;
         let path = require_string(&arguments, "path")?;
         let resolved = self.resolve_path(&path)?;
+        ensure_inside(&resolved, &self.allowed_roots).await?;
 
         match action.as_str() {
             "read_file" => {
-                let content = self.read_file(&resolved).await?;
-                Ok(serde_json::json!({ "content": content }))
+                let range = self.read_range(&arguments)?;
+                self.read_file(&resolved, range).await
             }
             "write_file" => {
                 let content = require_string(&arguments, "content")?;
@@ -196,6 +407,84 @@ impl Tool for FilesystemTool {
     }
 }
 
+/// Canonicalize allowed roots, failing on any that don't exist.
+pub(crate) fn canonical_roots(roots: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    roots
+        .iter()
+        .map(|r| {
+            std::fs::canonicalize(r).map_err(|e| {
+                ToolError::InvalidArguments(format!("allowed root {}: {e}", r.display()))
+            })
+        })
+        .collect()
+}
+
+/// Reject paths that land outside `roots` (canonical) once symlinks are resolved. For a
+/// path that doesn't exist yet (a write), its nearest existing ancestor is checked, and
+/// none of the missing components may be a dangling symlink, which a write would follow.
+pub(crate) async fn ensure_inside(path: &Path, roots: &[PathBuf]) -> Result<()> {
+    let outside =
+        || ToolError::Unauthorized(format!("{} is outside the allowed roots", path.display()));
+    let mut existing = path;
+    let mut missing = Vec::new();
+    let canonical = loop {
+        match tokio::fs::canonicalize(existing).await {
+            Ok(p) => break p,
+            Err(_) if tokio::fs::symlink_metadata(existing).await.is_ok() => {
+                return Err(outside());
+            }
+            Err(_) => match (existing.parent(), existing.file_name()) {
+                (Some(parent), Some(name)) => {
+                    missing.push(name);
+                    existing = parent;
+                }
+                _ => break PathBuf::new(),
+            },
+        }
+    };
+    let full = missing.iter().rev().fold(canonical, |p, name| p.join(name));
+    if full.as_os_str().is_empty() || !roots.iter().any(|r| full.starts_with(r)) {
+        return Err(outside());
+    }
+    Ok(())
+}
+
+fn optional_u64(args: &serde_json::Value, key: &str) -> Result<Option<u64>> {
+    match args.get(key) {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(v) => v.as_u64().map(Some).ok_or_else(|| {
+            ToolError::InvalidArguments(format!("key {key} must be a non-negative integer"))
+        }),
+    }
+}
+
+/// Length of the longest prefix of `buf` that doesn't end inside a UTF-8 sequence.
+fn utf8_boundary(buf: &[u8]) -> usize {
+    let tail_start = buf.len().saturating_sub(3);
+    for i in (tail_start..buf.len()).rev() {
+        let b = buf[i];
+        if b & 0b1100_0000 == 0b1000_0000 {
+            continue; // continuation byte
+        }
+        return if i + utf8_width(b) <= buf.len() {
+            buf.len()
+        } else {
+            i
+        };
+    }
+    buf.len()
+}
+
+/// Length of the UTF-8 sequence that starts with `lead`.
+fn utf8_width(lead: u8) -> usize {
+    match lead {
+        b if b < 0x80 => 1,
+        b if b >= 0xF0 => 4,
+        b if b >= 0xE0 => 3,
+        _ => 2,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -205,12 +494,218 @@ mod tests {
         let tmp = tempfile::tempdir().unwrap();
         let tool = FilesystemTool::new(tmp.path()).unwrap();
         let err = tool
-            .execute(serde_json::json!({
-                "action": "read_file",
-                "path": "../secrets.txt"
-            }))
+            .execute(
+                serde_json::json!({
+                    "action": "read_file",
+                    "path": "../secrets.txt"
+                }),
+                &CancellationToken::new(),
+            )
             .await
             .unwrap_err();
         assert!(err.to_string().contains("traversal"));
     }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn filesystem_rejects_paths_outside_allowed_roots() {
+        let tmp = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(outside.path().join("secret.txt"), "x").unwrap();
+        let shared = tmp.path().join("shared");
+        std::fs::create_dir(&shared).unwrap();
+        std::fs::write(shared.join("notes.txt"), "hello").unwrap();
+        std::os::unix::fs::symlink(outside.path(), shared.join("escape")).unwrap();
+        let tool = FilesystemTool::new(tmp.path())
+            .unwrap()
+            .with_allowed_roots(vec![shared.clone()])
+            .unwrap();
+
+        let out = tool
+            .execute(
+                serde_json::json!({
+                    "action": "read_file",
+                    "path": shared.join("notes.txt").to_string_lossy()
+                }),
+                &CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(out["content"], "hello");
+
+        for path in [
+            outside.path().join("secret.txt"),
+            shared.join("escape/secret.txt"),
+            shared.join("escape/new.txt"),
+        ] {
+            let err = tool
+                .execute(
+                    serde_json::json!({
+                        "action": "write_file",
+                        "path": path.to_string_lossy(),
+                        "content": "y"
+                    }),
+                    &CancellationToken::new(),
+                )
+                .await
+                .unwrap_err();
+            assert!(matches!(err, ToolError::Unauthorized(_)), "{path:?}: {err}");
+        }
+        let err = tool
+            .execute(
+                serde_json::json!({ "action": "list_dir", "path": "." }),
+                &CancellationToken::new(),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ToolError::Unauthorized(_)));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn writes_dont_follow_dangling_symlinks_out_of_the_root() {
+        let tmp = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let target = outside.path().join("cron");
+        std::os::unix::fs::symlink(&target, tmp.path().join("link")).unwrap();
+        std::os::unix::fs::symlink(outside.path().join("dir"), tmp.path().join("dirlink")).unwrap();
+        let tool = FilesystemTool::new(tmp.path()).unwrap();
+
+        for path in ["link", "dirlink/x"] {
+            let err = tool
+                .execute(
+                    serde_json::json!({ "action": "write_file", "path": path, "content": "y" }),
+                    &CancellationToken::new(),
+                )
+                .await
+                .unwrap_err();
+            assert!(matches!(err, ToolError::Unauthorized(_)), "{path}: {err}");
+        }
+        assert!(!target.exists());
+        assert!(!outside.path().join("dir").exists());
+
+        tool.execute(
+            serde_json::json!({ "action": "write_file", "path": "new/ok.txt", "content": "y" }),
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(tmp.path().join("new/ok.txt")).unwrap(),
+            "y"
+        );
+    }
+
+    #[tokio::test]
+    async fn read_file_pages_through_large_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let tool = FilesystemTool::new(tmp.path()).unwrap();
+        let body: String = (0..100).map(|i| format!("line {i} é\n")).collect();
+        std::fs::write(tmp.path().join("big.log"), &body).unwrap();
+
+        let mut offset = 0u64;
+        let mut pages = 0;
+        let mut collected = String::new();
+        loop {
+            let out = tool
+                .execute(
+                    serde_json::json!({
+                        "action": "read_file",
+                        "path": "big.log",
+                        "offset": offset,
+                        "length": 97
+                    }),
+                    &CancellationToken::new(),
+                )
+                .await
+                .unwrap();
+            collected.push_str(out["content"].as_str().unwrap());
+            pages += 1;
+            if out["eof"].as_bool().unwrap() {
+                assert!(out["next_cursor"].is_null());
+                break;
+            }
+            offset = out["next_cursor"]["offset"].as_u64().unwrap();
+        }
+        assert!(pages > 1);
+        assert_eq!(collected, body);
+    }
+
+    #[tokio::test]
+    async fn short_pages_and_long_lines_still_make_progress() {
+        let tmp = tempfile::tempdir().unwrap();
+        let tool = FilesystemTool::new(tmp.path()).unwrap();
+        let read = |args: serde_json::Value| {
+            let tool = &tool;
+            async move { tool.execute(args, &CancellationToken::new()).await.unwrap() }
+        };
+
+        // Pages shorter than a character still return that character.
+        std::fs::write(tmp.path().join("emoji.txt"), "é🙂x").unwrap();
+        let out = read(serde_json::json!({
+            "action": "read_file", "path": "emoji.txt", "offset": 2, "length": 1
+        }))
+        .await;
+        assert_eq!(out["content"], "🙂");
+        assert_eq!(out["next_cursor"]["offset"], 6);
+
+        // A line over a page comes back cut, with a byte cursor to the rest of it.
+        let long = "é".repeat(40 * 1024);
+        std::fs::write(tmp.path().join("one.json"), format!("head\n{long}\ntail\n")).unwrap();
+        let out = read(serde_json::json!({
+            "action": "read_file", "path": "one.json", "start_line": 2
+        }))
+        .await;
+        assert_eq!(out["lines_read"], 0);
+        assert_eq!(out["truncated_line"], 2);
+        let first = out["content"].as_str().unwrap().to_string();
+        assert_eq!(first.len(), 64 * 1024);
+        let offset = out["next_cursor"]["offset"].as_u64().unwrap();
+        assert_eq!(offset, 5 + first.len() as u64);
+        let out = read(serde_json::json!({
+            "action": "read_file", "path": "one.json", "offset": offset
+        }))
+        .await;
+        assert_eq!(
+            format!("{first}{}", out["content"].as_str().unwrap()),
+            format!("{long}\ntail\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn read_file_returns_line_ranges() {
+        let tmp = tempfile::tempdir().unwrap();
+        let tool = FilesystemTool::new(tmp.path()).unwrap();
+        std::fs::write(tmp.path().join("a.txt"), "one\ntwo\nthree\nfour\n").unwrap();
+
+        let out = tool
+            .execute(
+                serde_json::json!({
+                    "action": "read_file",
+                    "path": "a.txt",
+                    "start_line": 2,
+                    "line_count": 2
+                }),
+                &CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(out["content"], "two\nthree\n");
+        assert_eq!(out["eof"], false);
+        assert_eq!(out["next_cursor"]["start_line"], 4);
+
+        let out = tool
+            .execute(
+                serde_json::json!({
+                    "action": "read_file",
+                    "path": "a.txt",
+                    "start_line": 4
+                }),
+                &CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(out["content"], "four\n");
+        assert_eq!(out["eof"], true);
+    }
 }
@@ -3,16 +3,31 @@
 //! Tools are invoked by the assistant agent, gated by Horizons CoreAgents policies.
 //! See: specifications/openshell/implementation_v0_1_0.md
 
+#[cfg(target_os = "macos")]
+mod apple;
 mod browser;
 mod clipboard;
+mod code_run;
+mod email;
 mod error;
 mod filesystem;
+mod network_policy;
 mod shell;
+mod shell_session;
+mod todoist;
 mod traits;
 
+#[cfg(target_os = "macos")]
+pub use apple::{AppleNotesTool, AppleRemindersTool};
 pub use browser::BrowserTool;
 pub use clipboard::ClipboardTool;
+pub use code_run::{CodePreset, CodeRunOptions, CodeRunTool, CodeRuntime};
+pub use email::{EmailActions, EmailTool};
 pub use error::{Result, ToolError};
 pub use filesystem::FilesystemTool;
-pub use shell::ShellTool;
-pub use traits::{to_llm_tool_def, Tool, ToolSpec};
+pub use network_policy::NetworkPolicy;
+pub use shell::{ShellPolicy, ShellTool};
+pub use shell_session::SHELL_OWNER;
+pub use todoist::TodoistTool;
+pub use tokio_util::sync::CancellationToken;
+pub use traits::{to_llm_tool_def, until_cancelled, Tool, ToolSpec};
@@ -0,0 +1,251 @@
+use crate::error::{Result, ToolError};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use url::Url;
+
+/// Which URLs a network-facing tool may reach and which responses it may read.
+///
+/// Deny patterns win over allow patterns; an empty allowlist allows any public host.
+/// Private, loopback, link-local and CGNAT (Tailscale) addresses are blocked unless
+/// `allow_private_networks` is set, checked against every address the host resolves to so
+/// a public name pointing at the LAN is caught too. Requests go through `client`, which
+/// checks the addresses again as it connects, so the name can't be re-pointed in between.
+#[derive(Debug, Clone, Default)]
+pub struct NetworkPolicy {
+    /// Host patterns: `example.com`, or `*.example.com` for it and its subdomains.
+    pub allow_hosts: Vec<String>,
+    pub deny_hosts: Vec<String>,
+    pub allow_private_networks: bool,
+    /// MIME types, with `type/*` wildcards. Empty allows any.
+    pub allowed_content_types: Vec<String>,
+}
+
+impl NetworkPolicy {
+    pub async fn check_url(&self, raw: &str) -> Result<Url> {
+        let url = Url::parse(raw)
+            .map_err(|e| ToolError::InvalidArguments(format!("invalid url {raw}: {e}")))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(violation(format!("scheme {} is not allowed", url.scheme())));
+        }
+        let host = url
+            .host_str()
+            .ok_or_else(|| violation(format!("{raw} has no host")))?
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_ascii_lowercase();
+
+        if self.deny_hosts.iter().any(|p| host_matches(p, &host)) {
+            return Err(violation(format!("{host} is on the denylist")));
+        }
+        if !self.allow_hosts.is_empty() && !self.allow_hosts.iter().any(|p| host_matches(p, &host))
+        {
+            return Err(violation(format!("{host} is not on the allowlist")));
+        }
+        if self.allow_private_networks {
+            return Ok(url);
+        }
+
+        let addrs: Vec<IpAddr> = match host.parse::<IpAddr>() {
+            Ok(ip) => vec![ip],
+            Err(_) => {
+                let port = url.port_or_known_default().unwrap_or(443);
+                tokio::net::lookup_host((host.as_str(), port))
+                    .await
+                    .map_err(|e| ToolError::ExecutionFailed(format!("resolve {host}: {e}")))?
+                    .map(|a| a.ip())
+                    .collect()
+            }
+        };
+        if let Some(ip) = addrs.iter().find(|ip| is_internal(ip)) {
+            return Err(violation(format!(
+                "{host} resolves to internal address {ip}"
+            )));
+        }
+        Ok(url)
+    }
+
+    /// A client for fetching under this policy. It follows no redirects, so callers check
+    /// each hop with `check_url`, and it only connects to addresses the policy allows,
+    /// from the lookup it makes as it connects rather than the earlier one `check_url` made.
+    pub fn client(&self, timeout: Duration) -> reqwest::Result<reqwest::Client> {
+        reqwest::Client::builder()
+            .timeout(timeout)
+            .redirect(reqwest::redirect::Policy::none())
+            .dns_resolver(Arc::new(PolicyResolver {
+                allow_private_networks: self.allow_private_networks,
+            }))
+            .build()
+    }
+
+    pub fn check_content_type(&self, content_type: &str) -> Result<()> {
+        if self.allowed_content_types.is_empty() {
+            return Ok(());
+        }
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        let allowed = self.allowed_content_types.iter().any(|p| {
+            let p = p.to_ascii_lowercase();
+            match p.strip_suffix("/*") {
+                Some(major) => mime.split('/').next() == Some(major),
+                None => p == mime,
+            }
+        });
+        if allowed {
+            Ok(())
+        } else {
+            Err(violation(format!("content type {mime} is not allowed")))
+        }
+    }
+}
+
+/// Resolves hosts for `NetworkPolicy::client`, refusing names with an internal address.
+struct PolicyResolver {
+    allow_private_networks: bool,
+}
+
+impl reqwest::dns::Resolve for PolicyResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let allow_private_networks = self.allow_private_networks;
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            // The connector fills in the port.
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            if !allow_private_networks {
+                if let Some(addr) = addrs.iter().find(|a| is_internal(&a.ip())) {
+                    return Err(violation(format!(
+                        "{host} resolves to internal address {}",
+                        addr.ip()
+                    ))
+                    .into());
+                }
+            }
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+fn violation(detail: String) -> ToolError {
+    ToolError::Unauthorized(format!("network policy: {detail}"))
+}
+
+fn host_matches(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.trim().to_ascii_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host == domain || host.ends_with(&format!(".{domain}")),
+        None => host == pattern,
+    }
+}
+
+fn is_internal(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => is_internal_v4(v4),
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => is_internal_v4(&v4),
+            None => is_internal_v6(v6),
+        },
+    }
+}
+
+fn is_internal_v4(ip: &Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        // 100.64.0.0/10: carrier-grade NAT, also used by Tailscale.
+        || (a == 100 && (64..128).contains(&b))
+        || a == 0
+}
+
+fn is_internal_v6(ip: &Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // fc00::/7 unique local (including Tailscale's fd7a:115c:a1e0::/48).
+        || (first & 0xfe00) == 0xfc00
+        // fe80::/10 link-local.
+        || (first & 0xffc0) == 0xfe80
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn blocks_internal_addresses_and_listed_hosts() {
+        let policy = NetworkPolicy {
+            deny_hosts: vec!["*.tracker.example".to_string()],
+            allowed_content_types: vec!["text/html".to_string(), "image/*".to_string()],
+            ..NetworkPolicy::default()
+        };
+
+        for url in [
+            "http://127.0.0.1:8080/admin",
+            "http://192.168.1.1/",
+            "http://100.100.100.100/",
+            "http://[::1]/",
+            "http://[fd7a:115c:a1e0::1]/",
+            "http://[::ffff:10.0.0.1]/",
+            "https://ads.tracker.example/pixel",
+            "file:///etc/passwd",
+        ] {
+            let err = policy.check_url(url).await.unwrap_err();
+            assert!(matches!(err, ToolError::Unauthorized(_)), "{url}: {err}");
+        }
+        assert!(policy.check_url("http://93.184.215.14/").await.is_ok());
+
+        let allowlisted = NetworkPolicy {
+            allow_hosts: vec!["docs.rs".to_string()],
+            ..NetworkPolicy::default()
+        };
+        assert!(allowlisted.check_url("https://crates.io/").await.is_err());
+
+        assert!(policy
+            .check_content_type("text/html; charset=utf-8")
+            .is_ok());
+        assert!(policy.check_content_type("image/png").is_ok());
+        assert!(policy
+            .check_content_type("application/octet-stream")
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn client_checks_addresses_again_when_it_connects() {
+        use reqwest::dns::Resolve;
+
+        // What a rebinding name looks like at connect time, after check_url passed it.
+        let resolver = PolicyResolver {
+            allow_private_networks: false,
+        };
+        let err = resolver
+            .resolve("localhost".parse().unwrap())
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("internal address"), "{err}");
+
+        let resolver = PolicyResolver {
+            allow_private_networks: true,
+        };
+        let addrs: Vec<SocketAddr> = resolver
+            .resolve("localhost".parse().unwrap())
+            .await
+            .unwrap()
+            .collect();
+        assert!(addrs.iter().all(|a| a.ip().is_loopback()));
+
+        let client = NetworkPolicy::default()
+            .client(Duration::from_secs(5))
+            .unwrap();
+        assert!(client.get("http://localhost:9/").send().await.is_err());
+    }
+}
@@ -1,59 +1,300 @@
 use crate::error::{Result, ToolError};
+use crate::shell_session::ShellSessions;
 use crate::traits::{optional_string, require_string, Tool, ToolSpec};
 use async_trait::async_trait;
 use horizons_core::core_agents::models::RiskLevel;
+use std::collections::VecDeque;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt};
 use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+
+/// Longest a single command may run, whatever the caller asks for.
+const EXEC_TIMEOUT_MAX: Duration = Duration::from_secs(600);
+/// How long to keep reading output after the command exits, in case a background child
+/// still holds the pipe open.
+const DRAIN_GRACE: Duration = Duration::from_secs(1);
+
+/// Limits applied to every command the shell tool runs.
+#[derive(Debug, Clone)]
+pub struct ShellPolicy {
+    /// Default run time; a call may ask for less or more, up to 10 minutes.
+    pub timeout: Duration,
+    /// Per stream (stdout, stderr, session output). The start and end are kept and the
+    /// middle dropped, so both the command's first lines and its final error survive.
+    pub max_output_bytes: usize,
+}
+
+impl Default for ShellPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            max_output_bytes: 64 * 1024,
+        }
+    }
+}
 
 pub struct ShellTool {
-    timeout: std::time::Duration,
+    policy: ShellPolicy,
+    sessions: Arc<ShellSessions>,
 }
 
 impl ShellTool {
     pub fn new(timeout: std::time::Duration) -> Self {
-        Self { timeout }
+        Self {
+            policy: ShellPolicy {
+                timeout,
+                ..ShellPolicy::default()
+            },
+            sessions: ShellSessions::new(Duration::from_secs(30 * 60)),
+        }
+    }
+
+    pub fn with_policy(mut self, policy: ShellPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Close persistent sessions after this long without a command.
+    pub fn with_session_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.sessions = ShellSessions::new(idle_timeout);
+        self
+    }
+
+    fn timeout_for(&self, arguments: &serde_json::Value) -> Duration {
+        arguments
+            .get("timeout_seconds")
+            .and_then(|v| v.as_u64())
+            .map(Duration::from_secs)
+            .unwrap_or(self.policy.timeout)
+            .min(EXEC_TIMEOUT_MAX)
+    }
+
+    /// A timed-out command is killed and reported with whatever output it produced, not
+    /// as an error, so the model can see how far it got. A cancelled one is just killed.
+    async fn exec_once(
+        &self,
+        arguments: &serde_json::Value,
+        cancel: &CancellationToken,
+    ) -> Result<serde_json::Value> {
+        let command = require_string(arguments, "command")?;
+        let working_directory = optional_string(arguments, "working_directory")?;
+        let timeout = self.timeout_for(arguments);
+
+        let mut cmd = Command::new("/bin/sh");
+        cmd.arg("-lc")
+            .arg(command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(dir) = working_directory {
+            cmd.current_dir(dir);
+        }
+
+        let started = Instant::now();
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| ToolError::ExecutionFailed(e.to_string()))?;
+        let stdout = Arc::new(Mutex::new(CappedOutput::new(self.policy.max_output_bytes)));
+        let stderr = Arc::new(Mutex::new(CappedOutput::new(self.policy.max_output_bytes)));
+        let mut drains = [
+            tokio::spawn(drain(child.stdout.take(), stdout.clone())),
+            tokio::spawn(drain(child.stderr.take(), stderr.clone())),
+        ];
+
+        let waited = tokio::select! {
+            status = tokio::time::timeout(timeout, child.wait()) => Some(status),
+            _ = cancel.cancelled() => None,
+        };
+        let exit_code = match waited {
+            None => {
+                let _ = child.kill().await;
+                for drain in &drains {
+                    drain.abort();
+                }
+                return Err(ToolError::Cancelled);
+            }
+            Some(Ok(status)) => {
+                let status = status.map_err(|e| ToolError::ExecutionFailed(e.to_string()))?;
+                let _ = tokio::time::timeout(DRAIN_GRACE, async {
+                    for drain in drains.iter_mut() {
+                        let _ = drain.await;
+                    }
+                })
+                .await;
+                Some(status.code().unwrap_or(-1))
+            }
+            Some(Err(_)) => {
+                let _ = child.kill().await;
+                None
+            }
+        };
+        for drain in &drains {
+            drain.abort();
+        }
+
+        let (stdout, stdout_dropped) = take_output(&stdout);
+        let (stderr, stderr_dropped) = take_output(&stderr);
+        Ok(serde_json::json!({
+            "stdout": stdout,
+            "stderr": stderr,
+            "exit_code": exit_code,
+            "timed_out": exit_code.is_none(),
+            "duration_ms": started.elapsed().as_millis() as u64,
+            "truncated": stdout_dropped + stderr_dropped > 0,
+            "stdout_bytes_dropped": stdout_dropped,
+            "stderr_bytes_dropped": stderr_dropped,
+        }))
+    }
+}
+
+/// Keeps the first and last half of `max` bytes of a stream and counts what falls between.
+struct CappedOutput {
+    head: Vec<u8>,
+    tail: VecDeque<u8>,
+    head_max: usize,
+    tail_max: usize,
+    dropped: usize,
+}
+
+impl CappedOutput {
+    fn new(max: usize) -> Self {
+        Self {
+            head: Vec::new(),
+            tail: VecDeque::new(),
+            head_max: max / 2,
+            tail_max: max - max / 2,
+            dropped: 0,
+        }
+    }
+
+    fn push(&mut self, chunk: &[u8]) {
+        let room = self.head_max - self.head.len();
+        let (head, rest) = chunk.split_at(room.min(chunk.len()));
+        self.head.extend_from_slice(head);
+        self.tail.extend(rest);
+        if self.tail.len() > self.tail_max {
+            let excess = self.tail.len() - self.tail_max;
+            self.tail.drain(..excess);
+            self.dropped += excess;
+        }
+    }
+
+    fn finish(&self) -> (String, usize) {
+        let head = String::from_utf8_lossy(&self.head);
+        let tail: Vec<u8> = self.tail.iter().copied().collect();
+        let tail = String::from_utf8_lossy(&tail);
+        if self.dropped == 0 {
+            (format!("{head}{tail}"), 0)
+        } else {
+            (
+                format!("{head}\n[... {} bytes omitted ...]\n{tail}", self.dropped),
+                self.dropped,
+            )
+        }
+    }
+}
+
+async fn drain(stream: Option<impl AsyncRead + Unpin>, out: Arc<Mutex<CappedOutput>>) {
+    let Some(mut stream) = stream else {
+        return;
+    };
+    let mut chunk = [0u8; 8192];
+    loop {
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => {
+                if let Ok(mut out) = out.lock() {
+                    out.push(&chunk[..n]);
+                }
+            }
+        }
     }
 }
 
+fn take_output(out: &Mutex<CappedOutput>) -> (String, usize) {
+    out.lock().map(|o| o.finish()).unwrap_or_default()
+}
+
 #[async_trait]
 impl Tool for ShellTool {
     fn spec(&self) -> ToolSpec {
         ToolSpec {
             name: "shell.execute".to_string(),
-            description: "Execute a shell command on the host machine.".to_string(),
+            description: "Execute a shell command on the host machine. By default each call is a fresh shell. For multi-step work (builds, virtualenvs), open a named session with action=session_open and run commands in it with action=session_exec: cwd and environment carry over between calls. Close it with session_close when done.".to_string(),
             parameters_schema: serde_json::json!({
                 "type": "object",
                 "additionalProperties": false,
                 "properties": {
+                    "action": {
+                        "type": "string",
+                        "enum": ["exec", "session_open", "session_exec", "session_list", "session_close"],
+                        "description": "Defaults to exec (one-off command)."
+                    },
                     "command": { "type": "string" },
-                    "working_directory": { "type": "string" }
-                },
-                "required": ["command"]
+                    "working_directory": { "type": "string", "description": "exec / session_open: directory to start in" },
+                    "session": { "type": "string", "description": "Session name for the session_* actions" },
+                    "timeout_seconds": { "type": "integer", "minimum": 1, "maximum": 600, "description": "exec / session_exec: max run time" }
+                }
             }),
             risk_level: RiskLevel::High,
         }
     }
 
     #[tracing::instrument(level = "info", skip_all)]
-    async fn execute(&self, arguments: serde_json::Value) -> Result<serde_json::Value> {
-        let command = require_string(&arguments, "command")?;
-        let working_directory = optional_string(&arguments, "working_directory")?;
-
-        let mut cmd = Command::new("/bin/sh");
-        cmd.arg("-lc").arg(command);
-        if let Some(dir) = working_directory {
-            cmd.current_dir(dir);
+    async fn execute(
+        &self,
+        arguments: serde_json::Value,
+        cancel: &CancellationToken,
+    ) -> Result<serde_json::Value> {
+        let action = optional_string(&arguments, "action")?.unwrap_or_else(|| "exec".to_string());
+        match action.as_str() {
+            "exec" => self.exec_once(&arguments, cancel).await,
+            "session_open" => {
+                let name = require_string(&arguments, "session")?;
+                let working_directory = optional_string(&arguments, "working_directory")?;
+                self.sessions
+                    .open(&name, working_directory.as_deref())
+                    .await?;
+                Ok(serde_json::json!({ "status": "ok", "session": name }))
+            }
+            "session_exec" => {
+                let name = require_string(&arguments, "session")?;
+                let command = require_string(&arguments, "command")?;
+                let timeout = self.timeout_for(&arguments);
+                let started = Instant::now();
+                let out = self
+                    .sessions
+                    .exec(
+                        &name,
+                        &command,
+                        timeout,
+                        self.policy.max_output_bytes,
+                        cancel,
+                    )
+                    .await?;
+                Ok(serde_json::json!({
+                    "session": name,
+                    "output": out.output,
+                    "exit_code": out.exit_code,
+                    "duration_ms": started.elapsed().as_millis() as u64,
+                    "truncated": out.bytes_dropped > 0,
+                    "output_bytes_dropped": out.bytes_dropped,
+                }))
+            }
+            "session_list" => Ok(serde_json::json!({ "sessions": self.sessions.list().await })),
+            "session_close" => {
+                let name = require_string(&arguments, "session")?;
+                let closed = self.sessions.close(&name).await;
+                Ok(serde_json::json!({ "status": if closed { "ok" } else { "not_found" } }))
+            }
+            other => Err(ToolError::InvalidArguments(format!(
+                "unknown action: {other}"
+            ))),
         }
-
-        let output = tokio::time::timeout(self.timeout, cmd.output())
-            .await
-            .map_err(|_| ToolError::ExecutionFailed("shell command timed out".to_string()))?
-            .map_err(|e| ToolError::ExecutionFailed(e.to_string()))?;
-
-        Ok(serde_json::json!({
-            "stdout": String::from_utf8_lossy(&output.stdout).to_string(),
-            "stderr": String::from_utf8_lossy(&output.stderr).to_string(),
-            "exit_code": output.status.code().unwrap_or(-1),
-        }))
     }
 }
 
@@ -65,10 +306,63 @@ mod tests {
     async fn shell_exec_echo_works() {
         let tool = ShellTool::new(std::time::Duration::from_secs(5));
         let out = tool
-            .execute(serde_json::json!({ "command": "echo hello" }))
+            .execute(
+                serde_json::json!({ "command": "echo hello" }),
+                &CancellationToken::new(),
+            )
             .await
             .unwrap();
         assert_eq!(out["exit_code"].as_i64().unwrap(), 0);
         assert!(out["stdout"].as_str().unwrap().contains("hello"));
     }
+
+    #[tokio::test]
+    async fn shell_exec_truncates_output_and_reports_timeout() {
+        let tool = ShellTool::new(Duration::from_secs(5)).with_policy(ShellPolicy {
+            timeout: Duration::from_secs(5),
+            max_output_bytes: 100,
+        });
+        let out = tool
+            .execute(
+                serde_json::json!({ "command": "seq 1 1000; echo done >&2" }),
+                &CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+        let stdout = out["stdout"].as_str().unwrap();
+        assert!(stdout.starts_with("1\n2\n"));
+        assert!(stdout.ends_with("999\n1000\n"));
+        assert_eq!(out["truncated"], true);
+        assert_eq!(out["stdout_bytes_dropped"], 3893 - 100);
+        assert_eq!(out["stderr"], "done\n");
+        assert_eq!(out["exit_code"], 0);
+
+        let out = tool
+            .execute(
+                serde_json::json!({ "command": "echo started; sleep 30", "timeout_seconds": 1 }),
+                &CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(out["timed_out"], true);
+        assert!(out["exit_code"].is_null());
+        assert_eq!(out["stdout"], "started\n");
+    }
+
+    #[tokio::test]
+    async fn shell_exec_is_killed_when_cancelled() {
+        let tool = ShellTool::new(Duration::from_secs(30));
+        let cancel = CancellationToken::new();
+        let trigger = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            trigger.cancel();
+        });
+        let started = Instant::now();
+        let out = tool
+            .execute(serde_json::json!({ "command": "sleep 30" }), &cancel)
+            .await;
+        assert!(matches!(out, Err(ToolError::Cancelled)));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
 }
@@ -0,0 +1,527 @@
+//! Named shell sessions that outlive a single tool call.
+//!
+//! Each session is a shell on a PTY, so `cd`, exported variables and an activated
+//! virtualenv carry over between commands, and programs that want a terminal get one.
+//! Commands are written to the shell followed by a `printf` of a per-command marker and
+//! `$?`; output is read up to that marker. After a command is interrupted, whatever it
+//! still prints is read off up to a fresh marker before the next command runs.
+//!
+//! Sessions belong to the `SHELL_OWNER` of the call that opened them, so two
+//! conversations can both have a `build` session and neither can reach the other's.
+
+use crate::error::{Result, ToolError};
+use portable_pty::{native_pty_system, Child, CommandBuilder, MasterPty, PtySize};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Mutex};
+use tokio_util::sync::CancellationToken;
+
+const MARKER_PREFIX: &str = "__OPENCRAW_DONE_";
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long an interrupted command gets to wind down before the session is given up on.
+const RESYNC_TIMEOUT: Duration = Duration::from_secs(5);
+const SESSIONS_MAX: usize = 8;
+
+tokio::task_local! {
+    /// Who the current call's sessions belong to, e.g. the app's `channel:sender`.
+    /// Calls outside a scope share the unnamed owner.
+    pub static SHELL_OWNER: String;
+}
+
+fn owner() -> String {
+    SHELL_OWNER.try_with(Clone::clone).unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ShellSessionInfo {
+    pub name: String,
+    pub pid: Option<u32>,
+    pub age_seconds: u64,
+    pub idle_seconds: u64,
+}
+
+pub struct ExecOutput {
+    pub output: String,
+    pub exit_code: i64,
+    /// Bytes cut from the start of the output to fit the limit.
+    pub bytes_dropped: usize,
+}
+
+struct Session {
+    writer: Box<dyn Write + Send>,
+    output: mpsc::UnboundedReceiver<Vec<u8>>,
+    child: Box<dyn Child + Send + Sync>,
+    // Dropping the master closes the PTY; keep it for the session's lifetime.
+    _master: Box<dyn MasterPty + Send>,
+    created_at: Instant,
+    last_used: Instant,
+    /// The last command was interrupted and may still be printing.
+    interrupted: bool,
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+    }
+}
+
+/// A session's owner and name.
+type SessionKey = (String, String);
+
+pub struct ShellSessions {
+    sessions: Mutex<HashMap<SessionKey, Arc<Mutex<Session>>>>,
+    idle_timeout: Duration,
+    next_marker: AtomicU64,
+}
+
+impl ShellSessions {
+    /// Sessions unused for `idle_timeout` are closed by a background sweep.
+    pub fn new(idle_timeout: Duration) -> Arc<Self> {
+        let this = Arc::new(Self {
+            sessions: Mutex::new(HashMap::new()),
+            idle_timeout,
+            next_marker: AtomicU64::new(0),
+        });
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            handle.spawn(reap_idle(Arc::downgrade(&this)));
+        }
+        this
+    }
+
+    pub async fn open(&self, name: &str, working_directory: Option<&str>) -> Result<()> {
+        let key = (owner(), name.to_string());
+        let mut sessions = self.sessions.lock().await;
+        if sessions.contains_key(&key) {
+            return Err(ToolError::InvalidArguments(format!(
+                "shell session already open: {name}"
+            )));
+        }
+        if sessions.len() >= SESSIONS_MAX {
+            return Err(ToolError::ExecutionFailed(format!(
+                "too many shell sessions open (max {SESSIONS_MAX}); close one first"
+            )));
+        }
+
+        let mut session = spawn_shell(working_directory)?;
+        // Turn off echo and prompts so only command output comes back.
+        let ready = self
+            .run(
+                &mut session,
+                "stty -echo 2>/dev/null; PS1=''; PS2=''; export TERM=dumb",
+                STARTUP_TIMEOUT,
+                usize::MAX,
+                &CancellationToken::new(),
+            )
+            .await;
+        if let Err(e) = ready {
+            return Err(ToolError::ExecutionFailed(format!(
+                "shell session did not start: {e}"
+            )));
+        }
+        sessions.insert(key, Arc::new(Mutex::new(session)));
+        Ok(())
+    }
+
+    /// Output beyond `max_output_bytes` is cut from the start, keeping the most recent.
+    /// A timed-out or cancelled command is interrupted; the session stays open.
+    pub async fn exec(
+        &self,
+        name: &str,
+        command: &str,
+        timeout: Duration,
+        max_output_bytes: usize,
+        cancel: &CancellationToken,
+    ) -> Result<ExecOutput> {
+        let session = self
+            .sessions
+            .lock()
+            .await
+            .get(&(owner(), name.to_string()))
+            .cloned()
+            .ok_or_else(|| {
+                ToolError::InvalidArguments(format!("no shell session named {name}; open it first"))
+            })?;
+        let mut session = session.lock().await;
+        session.last_used = Instant::now();
+        let out = self
+            .run(&mut session, command, timeout, max_output_bytes, cancel)
+            .await;
+        session.last_used = Instant::now();
+        out
+    }
+
+    pub async fn list(&self) -> Vec<ShellSessionInfo> {
+        let owner = owner();
+        let sessions: Vec<(String, Arc<Mutex<Session>>)> = self
+            .sessions
+            .lock()
+            .await
+            .iter()
+            .filter(|((o, _), _)| *o == owner)
+            .map(|((_, name), v)| (name.clone(), v.clone()))
+            .collect();
+        let mut out = Vec::new();
+        for (name, session) in sessions {
+            // A session busy with a command is reported as not idle.
+            let (pid, age, idle) = match session.try_lock() {
+                Ok(s) => (
+                    s.child.process_id(),
+                    s.created_at.elapsed(),
+                    s.last_used.elapsed(),
+                ),
+                Err(_) => (None, Duration::ZERO, Duration::ZERO),
+            };
+            out.push(ShellSessionInfo {
+                name,
+                pid,
+                age_seconds: age.as_secs(),
+                idle_seconds: idle.as_secs(),
+            });
+        }
+        out.sort_by(|a, b| a.name.cmp(&b.name));
+        out
+    }
+
+    pub async fn close(&self, name: &str) -> bool {
+        self.sessions
+            .lock()
+            .await
+            .remove(&(owner(), name.to_string()))
+            .is_some()
+    }
+
+    async fn run(
+        &self,
+        session: &mut Session,
+        command: &str,
+        timeout: Duration,
+        max_output_bytes: usize,
+        cancel: &CancellationToken,
+    ) -> Result<ExecOutput> {
+        if session.interrupted {
+            self.resync(session).await?;
+        }
+        let marker = self.send(session, command)?;
+
+        let mut buf: Vec<u8> = Vec::new();
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            if let Some((output, exit_code)) = parse_until_marker(&buf, &marker) {
+                let (output, bytes_dropped) = clean_output(output.as_bytes(), max_output_bytes);
+                return Ok(ExecOutput {
+                    output,
+                    exit_code,
+                    bytes_dropped,
+                });
+            }
+            let received = tokio::select! {
+                received = tokio::time::timeout_at(deadline, session.output.recv()) => received,
+                _ = cancel.cancelled() => {
+                    interrupt(session);
+                    return Err(ToolError::Cancelled);
+                }
+            };
+            match received {
+                Ok(Some(chunk)) => buf.extend_from_slice(&chunk),
+                Ok(None) => {
+                    return Err(ToolError::ExecutionFailed(
+                        "shell session exited".to_string(),
+                    ))
+                }
+                Err(_) => {
+                    // Interrupt the command; the session stays usable.
+                    interrupt(session);
+                    return Err(ToolError::ExecutionFailed(format!(
+                        "command timed out after {}s; output so far:\n{}",
+                        timeout.as_secs(),
+                        clean_output(&buf, max_output_bytes).0
+                    )));
+                }
+            }
+        }
+    }
+
+    /// Write `command` and the `printf` of a fresh marker after it; returns the marker.
+    fn send(&self, session: &mut Session, command: &str) -> Result<String> {
+        let marker = format!(
+            "{MARKER_PREFIX}{}__",
+            self.next_marker.fetch_add(1, Ordering::Relaxed)
+        );
+        let script = format!("{command}\nprintf '\\n{marker}:%s\\n' \"$?\"\n");
+        session
+            .writer
+            .write_all(script.as_bytes())
+            .and_then(|_| session.writer.flush())?;
+        Ok(marker)
+    }
+
+    /// Read off what an interrupted command printed after it was stopped, so it isn't
+    /// taken for the next command's output.
+    async fn resync(&self, session: &mut Session) -> Result<()> {
+        let marker = self.send(session, ":")?;
+        let deadline = tokio::time::Instant::now() + RESYNC_TIMEOUT;
+        let mut buf: Vec<u8> = Vec::new();
+        while parse_until_marker(&buf, &marker).is_none() {
+            match tokio::time::timeout_at(deadline, session.output.recv()).await {
+                Ok(Some(chunk)) => buf.extend_from_slice(&chunk),
+                Ok(None) => {
+                    return Err(ToolError::ExecutionFailed(
+                        "shell session exited".to_string(),
+                    ))
+                }
+                Err(_) => {
+                    return Err(ToolError::ExecutionFailed(
+                        "shell session is still busy with an interrupted command; close it and open a new one"
+                            .to_string(),
+                    ))
+                }
+            }
+        }
+        session.interrupted = false;
+        Ok(())
+    }
+
+    async fn close_idle(&self) {
+        let mut sessions = self.sessions.lock().await;
+        sessions.retain(|(_, name), session| match session.try_lock() {
+            Ok(s) if s.last_used.elapsed() >= self.idle_timeout => {
+                tracing::info!(session = %name, "closing idle shell session");
+                false
+            }
+            _ => true,
+        });
+    }
+}
+
+/// Send Ctrl-C to the running command.
+fn interrupt(session: &mut Session) {
+    let _ = session.writer.write_all(b"\x03");
+    let _ = session.writer.flush();
+    session.interrupted = true;
+}
+
+async fn reap_idle(sessions: Weak<ShellSessions>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(30));
+    loop {
+        interval.tick().await;
+        let Some(sessions) = sessions.upgrade() else {
+            return;
+        };
+        sessions.close_idle().await;
+    }
+}
+
+fn spawn_shell(working_directory: Option<&str>) -> Result<Session> {
+    let pty = native_pty_system()
+        .openpty(PtySize {
+            rows: 50,
+            cols: 200,
+            pixel_width: 0,
+            pixel_height: 0,
+        })
+        .map_err(|e| ToolError::ExecutionFailed(format!("openpty: {e}")))?;
+
+    let shell = if std::path::Path::new("/bin/bash").exists() {
+        "/bin/bash"
+    } else {
+        "/bin/sh"
+    };
+    let mut cmd = CommandBuilder::new(shell);
+    if shell.ends_with("bash") {
+        cmd.args(["--noprofile", "--norc"]);
+    }
+    match working_directory {
+        Some(dir) => cmd.cwd(dir),
+        None => {
+            if let Ok(dir) = std::env::current_dir() {
+                cmd.cwd(dir);
+            }
+        }
+    }
+
+    let child = pty
+        .slave
+        .spawn_command(cmd)
+        .map_err(|e| ToolError::ExecutionFailed(format!("spawn shell: {e}")))?;
+    let mut reader = pty
+        .master
+        .try_clone_reader()
+        .map_err(|e| ToolError::ExecutionFailed(e.to_string()))?;
+    let writer = pty
+        .master
+        .take_writer()
+        .map_err(|e| ToolError::ExecutionFailed(e.to_string()))?;
+
+    // PTY reads block, so they get their own thread.
+    let (tx, rx) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        let mut chunk = [0u8; 8192];
+        loop {
+            match reader.read(&mut chunk) {
+                Ok(0) | Err(_) => return,
+                Ok(n) => {
+                    if tx.send(chunk[..n].to_vec()).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+
+    Ok(Session {
+        writer,
+        output: rx,
+        child,
+        _master: pty.master,
+        created_at: Instant::now(),
+        last_used: Instant::now(),
+        interrupted: false,
+    })
+}
+
+/// Output before `marker` and the exit code after it, once the marker line is complete.
+/// The echoed `printf` line also contains the marker, but followed by `%s`, not digits.
+fn parse_until_marker(buf: &[u8], marker: &str) -> Option<(String, i64)> {
+    let text = String::from_utf8_lossy(buf).replace('\r', "");
+    let needle = format!("{marker}:");
+    let mut from = 0;
+    while let Some(pos) = text[from..].find(&needle) {
+        let start = from + pos;
+        let rest = &text[start + needle.len()..];
+        if let Some(end) = rest.find('\n') {
+            if let Ok(code) = rest[..end].trim().parse::<i64>() {
+                let before = text[..start].strip_suffix('\n').unwrap_or(&text[..start]);
+                return Some((before.to_string(), code));
+            }
+        }
+        from = start + needle.len();
+    }
+    None
+}
+
+/// Drop markers left by interrupted commands and keep the tail of long output. Returns the
+/// text and how many bytes were cut.
+fn clean_output(buf: &[u8], max_bytes: usize) -> (String, usize) {
+    let text = String::from_utf8_lossy(buf).replace('\r', "");
+    let text: String = text
+        .lines()
+        .filter(|l| !l.starts_with(MARKER_PREFIX))
+        .collect::<Vec<_>>()
+        .join("\n");
+    if text.len() <= max_bytes {
+        return (text, 0);
+    }
+    let mut cut = text.len() - max_bytes;
+    while !text.is_char_boundary(cut) {
+        cut += 1;
+    }
+    (
+        format!("[{cut} earlier bytes omitted]\n{}", &text[cut..]),
+        cut,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn session_keeps_cwd_and_env_between_commands() {
+        let tmp = tempfile::tempdir().unwrap();
+        let sessions = ShellSessions::new(Duration::from_secs(600));
+        sessions
+            .open("build", Some(tmp.path().to_str().unwrap()))
+            .await
+            .unwrap();
+
+        let t = Duration::from_secs(10);
+        let max = 64 * 1024;
+        sessions
+            .exec(
+                "build",
+                "mkdir sub && cd sub && export GREETING=hi",
+                t,
+                max,
+                &CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+        let out = sessions
+            .exec(
+                "build",
+                "echo \"$GREETING from $(basename \"$PWD\")\"",
+                t,
+                max,
+                &CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(out.output, "hi from sub");
+        assert_eq!(out.exit_code, 0);
+
+        let out = sessions
+            .exec("build", "false", t, max, &CancellationToken::new())
+            .await
+            .unwrap();
+        assert_eq!(out.exit_code, 1);
+
+        let out = sessions
+            .exec("build", "seq 1 100", t, 8, &CancellationToken::new())
+            .await
+            .unwrap();
+        assert_eq!(out.output.lines().last(), Some("100"));
+        assert!(out.bytes_dropped > 0);
+
+        assert_eq!(sessions.list().await.len(), 1);
+        assert!(sessions.close("build").await);
+        assert!(sessions
+            .exec("build", "true", t, max, &CancellationToken::new())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn sessions_are_per_owner_and_resync_after_an_interrupt() {
+        let sessions = ShellSessions::new(Duration::from_secs(600));
+        let max = 64 * 1024;
+        let exec = |owner: &str, command: &str, timeout: Duration| {
+            let sessions = sessions.clone();
+            let command = command.to_string();
+            SHELL_OWNER.scope(owner.to_string(), async move {
+                sessions
+                    .exec("build", &command, timeout, max, &CancellationToken::new())
+                    .await
+            })
+        };
+        SHELL_OWNER
+            .scope("telegram:1".to_string(), sessions.open("build", None))
+            .await
+            .unwrap();
+
+        // Another conversation can't see or use it, but can have its own.
+        let t = Duration::from_secs(10);
+        assert!(exec("telegram:2", "echo hi", t).await.is_err());
+        assert!(sessions.list().await.is_empty());
+        SHELL_OWNER
+            .scope("telegram:2".to_string(), sessions.open("build", None))
+            .await
+            .unwrap();
+        assert_eq!(
+            exec("telegram:2", "echo two", t).await.unwrap().output,
+            "two"
+        );
+
+        // What the interrupted command prints on its way out isn't the next one's output.
+        let slow = "(trap 'sleep 0.2; echo late; exit 1' INT; sleep 5)";
+        assert!(exec("telegram:1", slow, Duration::from_millis(300))
+            .await
+            .is_err());
+        let out = exec("telegram:1", "echo after", t).await.unwrap();
+        assert_eq!(out.output, "after");
+        assert_eq!(out.exit_code, 0);
+    }
+}
@@ -0,0 +1,377 @@
+//! Todoist tasks over the REST API.
+//!
+//! A lighter alternative to a full issue tracker for personal to-dos. Projects are named
+//! by the model and resolved to ids here, and priorities use the app's numbering (1 is
+//! most urgent) rather than the API's reversed one.
+
+use crate::error::{Result, ToolError};
+use crate::traits::{optional_string, require_string, until_cancelled, Tool, ToolSpec};
+use async_trait::async_trait;
+use horizons_core::core_agents::models::RiskLevel;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+const API_BASE: &str = "https://api.todoist.com/api/v1";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_LIMIT: u64 = 50;
+const LIMIT_MAX: u64 = 200;
+
+pub struct TodoistTool {
+    client: reqwest::Client,
+    api_token: String,
+}
+
+impl TodoistTool {
+    pub fn new(api_token: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            api_token: api_token.into(),
+        }
+    }
+
+    async fn request(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        query: &[(&str, String)],
+        body: Option<serde_json::Value>,
+    ) -> Result<serde_json::Value> {
+        let mut req = self
+            .client
+            .request(method, format!("{API_BASE}{path}"))
+            .bearer_auth(&self.api_token)
+            .query(query);
+        if let Some(body) = body {
+            req = req.json(&body);
+        }
+        let resp = req
+            .send()
+            .await
+            .map_err(|e| ToolError::ExecutionFailed(format!("todoist request failed: {e}")))?;
+        let status = resp.status();
+        let text = resp.text().await.unwrap_or_default();
+        if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+            return Err(ToolError::Unauthorized(
+                "todoist rejected the API token".to_string(),
+            ));
+        }
+        if !status.is_success() {
+            return Err(ToolError::ExecutionFailed(format!(
+                "todoist returned {status}: {}",
+                text.trim()
+            )));
+        }
+        if text.trim().is_empty() {
+            return Ok(serde_json::Value::Null);
+        }
+        serde_json::from_str(&text)
+            .map_err(|e| ToolError::ExecutionFailed(format!("todoist sent invalid JSON: {e}")))
+    }
+
+    /// Every project as `(id, name)`; the API pages with `next_cursor`.
+    async fn projects(&self) -> Result<Vec<(String, String)>> {
+        let mut out = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut query = vec![("limit", LIMIT_MAX.to_string())];
+            if let Some(cursor) = &cursor {
+                query.push(("cursor", cursor.clone()));
+            }
+            let page = self
+                .request(reqwest::Method::GET, "/projects", &query, None)
+                .await?;
+            for project in results(&page) {
+                if let (Some(id), Some(name)) = (
+                    project.get("id").and_then(|v| v.as_str()),
+                    project.get("name").and_then(|v| v.as_str()),
+                ) {
+                    out.push((id.to_string(), name.to_string()));
+                }
+            }
+            cursor = page
+                .get("next_cursor")
+                .and_then(|v| v.as_str())
+                .map(str::to_string);
+            if cursor.is_none() {
+                return Ok(out);
+            }
+        }
+    }
+
+    async fn list(&self, arguments: &serde_json::Value) -> Result<serde_json::Value> {
+        let limit = arguments
+            .get("limit")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(DEFAULT_LIMIT)
+            .clamp(1, LIMIT_MAX);
+        let filter = optional_string(arguments, "filter")?.filter(|f| !f.trim().is_empty());
+        let project = optional_string(arguments, "project")?.filter(|p| !p.trim().is_empty());
+        if filter.is_some() && project.is_some() {
+            return Err(ToolError::InvalidArguments(
+                "use filter or project, not both (filters can say #Project)".to_string(),
+            ));
+        }
+        let projects = self.projects().await?;
+
+        let mut query = vec![("limit", limit.to_string())];
+        let path = match (&filter, &project) {
+            (Some(filter), _) => {
+                query.push(("query", filter.clone()));
+                "/tasks/filter"
+            }
+            (None, Some(project)) => {
+                query.push(("project_id", project_id(&projects, project)?));
+                "/tasks"
+            }
+            (None, None) => "/tasks",
+        };
+        let page = self
+            .request(reqwest::Method::GET, path, &query, None)
+            .await?;
+        let tasks: Vec<serde_json::Value> = results(&page)
+            .iter()
+            .take(limit as usize)
+            .map(|task| summarize(task, &projects))
+            .collect();
+        Ok(serde_json::json!({ "tasks": tasks }))
+    }
+
+    async fn add(&self, arguments: &serde_json::Value) -> Result<serde_json::Value> {
+        let content = require_string(arguments, "content")?;
+        if content.trim().is_empty() {
+            return Err(ToolError::InvalidArguments("content is empty".to_string()));
+        }
+        let mut body = serde_json::json!({ "content": content });
+        if let Some(description) = optional_string(arguments, "description")? {
+            body["description"] = description.into();
+        }
+        if let Some(due) = optional_string(arguments, "due")?.filter(|d| !d.trim().is_empty()) {
+            let (key, value) = due_field(&due);
+            body[key] = value.into();
+        }
+        if let Some(priority) = arguments.get("priority").and_then(|v| v.as_u64()) {
+            body["priority"] = api_priority(priority)?.into();
+        }
+        if let Some(labels) = arguments.get("labels").and_then(|v| v.as_array()) {
+            body["labels"] = labels
+                .iter()
+                .filter_map(|l| l.as_str())
+                .collect::<Vec<_>>()
+                .into();
+        }
+        let projects = match optional_string(arguments, "project")?.filter(|p| !p.trim().is_empty())
+        {
+            Some(project) => {
+                let projects = self.projects().await?;
+                body["project_id"] = project_id(&projects, &project)?.into();
+                projects
+            }
+            None => Vec::new(),
+        };
+        let task = self
+            .request(reqwest::Method::POST, "/tasks", &[], Some(body))
+            .await?;
+        Ok(serde_json::json!({ "created": summarize(&task, &projects) }))
+    }
+
+    async fn complete(&self, arguments: &serde_json::Value) -> Result<serde_json::Value> {
+        let id = require_string(arguments, "id")?;
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(ToolError::InvalidArguments(format!(
+                "invalid task id: {id:?}"
+            )));
+        }
+        self.request(
+            reqwest::Method::POST,
+            &format!("/tasks/{id}/close"),
+            &[],
+            None,
+        )
+        .await?;
+        Ok(serde_json::json!({ "completed": id }))
+    }
+}
+
+#[async_trait]
+impl Tool for TodoistTool {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "todoist".to_string(),
+            description: "The owner's Todoist tasks. list: open tasks, optionally in one project or matching a Todoist filter (\"today | overdue\", \"#Work & p1\"). add: a task with optional project, due (a date, an ISO 8601 time, or words like \"tomorrow 9am\" or \"every friday\"), priority, labels and description. complete: close a task by id.".to_string(),
+            parameters_schema: serde_json::json!({
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "action": { "type": "string", "enum": ["list", "add", "complete"] },
+                    "filter": { "type": "string", "description": "Todoist filter query" },
+                    "project": { "type": "string", "description": "Project name; the inbox when omitted for add" },
+                    "limit": { "type": "integer", "minimum": 1, "maximum": LIMIT_MAX },
+                    "content": { "type": "string", "description": "Task title" },
+                    "description": { "type": "string" },
+                    "due": { "type": "string" },
+                    "priority": { "type": "integer", "minimum": 1, "maximum": 4, "description": "1 is most urgent, 4 is none" },
+                    "labels": { "type": "array", "items": { "type": "string" } },
+                    "id": { "type": "string" }
+                },
+                "required": ["action"]
+            }),
+            risk_level: RiskLevel::Medium,
+        }
+    }
+
+    #[tracing::instrument(level = "info", skip_all)]
+    async fn execute(
+        &self,
+        arguments: serde_json::Value,
+        cancel: &CancellationToken,
+    ) -> Result<serde_json::Value> {
+        let action = require_string(&arguments, "action")?;
+        match action.as_str() {
+            "list" => until_cancelled(cancel, self.list(&arguments)).await,
+            "add" => until_cancelled(cancel, self.add(&arguments)).await,
+            "complete" => until_cancelled(cancel, self.complete(&arguments)).await,
+            other => Err(ToolError::InvalidArguments(format!(
+                "unknown action: {other}"
+            ))),
+        }
+    }
+}
+
+/// Paged endpoints wrap items in `results`; older ones return a bare array.
+fn results(page: &serde_json::Value) -> &[serde_json::Value] {
+    page.get("results")
+        .and_then(|v| v.as_array())
+        .or_else(|| page.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
+fn project_id(projects: &[(String, String)], name: &str) -> Result<String> {
+    projects
+        .iter()
+        .find(|(_, n)| n.eq_ignore_ascii_case(name.trim()))
+        .map(|(id, _)| id.clone())
+        .ok_or_else(|| {
+            let names: Vec<&str> = projects.iter().map(|(_, n)| n.as_str()).collect();
+            ToolError::InvalidArguments(format!(
+                "no todoist project named {name:?} (have: {})",
+                names.join(", ")
+            ))
+        })
+}
+
+/// `2026-03-01` is a date, `2026-03-01T09:00...` a time, anything else is Todoist's
+/// natural language (which also handles recurrence).
+fn due_field(due: &str) -> (&'static str, String) {
+    let due = due.trim();
+    let is_date = |s: &str| {
+        s.len() == 10
+            && s.char_indices().all(|(i, c)| match i {
+                4 | 7 => c == '-',
+                _ => c.is_ascii_digit(),
+            })
+    };
+    if is_date(due) {
+        ("due_date", due.to_string())
+    } else if due.len() > 10 && is_date(&due[..10]) && due[10..].starts_with('T') {
+        ("due_datetime", due.to_string())
+    } else {
+        ("due_string", due.to_string())
+    }
+}
+
+/// The apps show p1 as most urgent; the API stores it as 4.
+fn api_priority(priority: u64) -> Result<u64> {
+    match priority {
+        1..=4 => Ok(5 - priority),
+        other => Err(ToolError::InvalidArguments(format!(
+            "priority must be 1 (urgent) to 4 (none), got {other}"
+        ))),
+    }
+}
+
+fn summarize(task: &serde_json::Value, projects: &[(String, String)]) -> serde_json::Value {
+    let project_id = task.get("project_id").and_then(|v| v.as_str());
+    let project = project_id.and_then(|id| {
+        projects
+            .iter()
+            .find(|(pid, _)| pid == id)
+            .map(|(_, name)| name.clone())
+    });
+    let due = task.get("due").filter(|d| !d.is_null()).map(|due| {
+        serde_json::json!({
+            "date": due.get("date"),
+            "text": due.get("string"),
+            "recurring": due.get("is_recurring").and_then(|v| v.as_bool()).unwrap_or(false),
+        })
+    });
+    let mut out = serde_json::json!({
+        "id": task.get("id"),
+        "content": task.get("content"),
+        "project": project.map(serde_json::Value::from).or_else(|| project_id.map(Into::into)),
+        "due": due,
+        "priority": task
+            .get("priority")
+            .and_then(|v| v.as_u64())
+            .filter(|p| (1..=4).contains(p))
+            .map(|p| 5 - p),
+        "labels": task.get("labels").cloned().unwrap_or_else(|| serde_json::json!([])),
+    });
+    if let Some(description) = task
+        .get("description")
+        .and_then(|v| v.as_str())
+        .filter(|d| !d.is_empty())
+    {
+        out["description"] = description.into();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_due_dates_priorities_and_tasks() {
+        assert_eq!(
+            due_field("2026-03-01"),
+            ("due_date", "2026-03-01".to_string())
+        );
+        assert_eq!(due_field("2026-03-01T09:00:00Z").0, "due_datetime");
+        assert_eq!(due_field("every friday 9am").0, "due_string");
+        assert_eq!(due_field("2026-3-1").0, "due_string");
+
+        assert_eq!(api_priority(1).unwrap(), 4);
+        assert_eq!(api_priority(4).unwrap(), 1);
+        assert!(api_priority(0).is_err());
+
+        let page = serde_json::json!({
+            "results": [{
+                "id": "6X7rM8997g3RQmvh",
+                "content": "Renew passport",
+                "description": "",
+                "project_id": "6Jf8VQXxpwv56VQ7",
+                "priority": 4,
+                "labels": ["errands"],
+                "due": { "date": "2026-03-01", "string": "Mar 1", "is_recurring": false }
+            }],
+            "next_cursor": null
+        });
+        let projects = vec![("6Jf8VQXxpwv56VQ7".to_string(), "Personal".to_string())];
+        let task = summarize(&results(&page)[0], &projects);
+        assert_eq!(
+            task,
+            serde_json::json!({
+                "id": "6X7rM8997g3RQmvh",
+                "content": "Renew passport",
+                "project": "Personal",
+                "due": { "date": "2026-03-01", "text": "Mar 1", "recurring": false },
+                "priority": 1,
+                "labels": ["errands"],
+            })
+        );
+    }
+}
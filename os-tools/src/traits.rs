@@ -1,6 +1,8 @@
 use crate::error::{Result, ToolError};
 use async_trait::async_trait;
 use horizons_core::core_agents::models::RiskLevel;
+use std::future::Future;
+use tokio_util::sync::CancellationToken;
 
 pub struct ToolSpec {
     pub name: String,
@@ -12,7 +14,27 @@ pub struct ToolSpec {
 #[async_trait]
 pub trait Tool: Send + Sync {
     fn spec(&self) -> ToolSpec;
-    async fn execute(&self, arguments: serde_json::Value) -> Result<serde_json::Value>;
+
+    /// Run the tool. When `cancel` fires the tool should stop its work (kill child
+    /// processes, drop requests) and return `ToolError::Cancelled` promptly; the caller
+    /// only waits a short grace period before dropping the call.
+    async fn execute(
+        &self,
+        arguments: serde_json::Value,
+        cancel: &CancellationToken,
+    ) -> Result<serde_json::Value>;
+}
+
+/// Await `work` unless `cancel` fires first, in which case `work` is dropped. Child
+/// processes inside it need `kill_on_drop` for that to stop them.
+pub async fn until_cancelled<T>(
+    cancel: &CancellationToken,
+    work: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    tokio::select! {
+        out = work => out,
+        _ = cancel.cancelled() => Err(ToolError::Cancelled),
+    }
 }
 
 pub fn to_llm_tool_def(tool: &dyn Tool) -> os_llm::ToolDefinition {